@@ -0,0 +1,79 @@
+//! Runs a ROM without any frontend and prints its screen as text, the
+//! smallest host the emulator core can have.
+//!
+//! ```text
+//! cargo run -p msx --example headless -- roms/cbios_main_msx1.rom 300
+//! ```
+//!
+//! The ROM, or a zip holding it, goes in slot 0 and RAM in slot 3, as for
+//! the BIOS. Space is held down during the second half of the frames.
+
+use std::{env, fs, process};
+
+use msx::{
+    archive,
+    frame::{FrameBuffer, SCREEN_HEIGHT, SCREEN_WIDTH},
+    keyboard::key_position,
+    slot::{RamSlot, RomSlot, SlotType},
+    vdp_timing::FRAME_T_STATES,
+    Msx,
+};
+
+// screen pixels per character of the text
+const CELL_WIDTH: usize = 4;
+const CELL_HEIGHT: usize = 8;
+
+fn main() -> anyhow::Result<()> {
+    let mut args = env::args().skip(1);
+    let Some(path) = args.next() else {
+        eprintln!("Usage: headless <rom> [frames]");
+        process::exit(1);
+    };
+    let frames: u32 = match args.next() {
+        Some(frames) => frames.parse()?,
+        None => 120,
+    };
+
+    let rom = archive::extract(&fs::read(&path)?, None)?;
+    let mut msx = Msx::new(&[
+        SlotType::Rom(RomSlot::new(&rom, 0x0000, 0x10000)),
+        SlotType::Empty,
+        SlotType::Empty,
+        SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+    ]);
+
+    let (row, bit) = key_position("Space").unwrap();
+    let mut frame = FrameBuffer::default();
+    for n in 0..frames {
+        msx.set_key(row, bit, n >= frames / 2);
+        if msx.run_cycles(FRAME_T_STATES as u32).reason != msx::StopReason::Budget {
+            println!("Stopped at frame {}, PC {:04X}", n, msx.pc());
+            break;
+        }
+    }
+
+    msx.render(&mut frame);
+    print!("{}", screen_text(&frame));
+    Ok(())
+}
+
+// the screen with a character for each cell, `#` when a pixel of the cell
+// isn't of the backdrop color
+fn screen_text(frame: &FrameBuffer) -> String {
+    let pixel = |x: usize, y: usize| {
+        let offset = (y * SCREEN_WIDTH + x) * 4;
+        &frame.rgba[offset..offset + 4]
+    };
+    let backdrop = pixel(0, 0);
+
+    let mut text = String::new();
+    for cell_y in (0..SCREEN_HEIGHT).step_by(CELL_HEIGHT) {
+        for cell_x in (0..SCREEN_WIDTH).step_by(CELL_WIDTH) {
+            let drawn = (cell_y..cell_y + CELL_HEIGHT)
+                .any(|y| (cell_x..cell_x + CELL_WIDTH).any(|x| pixel(x, y) != backdrop));
+            text.push(if drawn { '#' } else { ' ' });
+        }
+        text.push('\n');
+    }
+    text
+}
@@ -0,0 +1,362 @@
+//! The 8-bit arithmetic and logic of the Z80, and the flags it sets.
+//!
+//! Every instruction doing ADD, ADC, SUB, SBC, AND, OR, XOR, CP, INC or DEC
+//! goes through these functions, whatever its operand, so that the flags
+//! can't differ between the forms of an instruction.
+
+use crate::cpu::Flag;
+
+const S: u8 = Flag::S as u8;
+const Z: u8 = Flag::Z as u8;
+const H: u8 = Flag::H as u8;
+const P: u8 = Flag::P as u8;
+const N: u8 = Flag::N as u8;
+const C: u8 = Flag::C as u8;
+
+/// The flags set by the operations. The undocumented bits 5 and 3 are left
+/// as they were.
+pub const FLAGS: u8 = S | Z | H | P | N | C;
+
+/// The flags set by INC and DEC, which leave the carry as it was.
+pub const INC_DEC_FLAGS: u8 = FLAGS & !C;
+
+/// The result of an operation and the flags it sets.
+pub type Output = (u8, u8);
+
+// S and Z of a result
+fn sign_zero(result: u8) -> u8 {
+    (result & S) | if result == 0 { Z } else { 0 }
+}
+
+// P of the logical operations
+fn parity(result: u8) -> u8 {
+    if result.count_ones() & 1 == 0 {
+        P
+    } else {
+        0
+    }
+}
+
+/// ADD and ADC: A plus the value and the carry.
+pub fn add(a: u8, value: u8, carry: bool) -> Output {
+    let wide = a as u16 + value as u16 + carry as u16;
+    let result = wide as u8;
+    // the operands have the same sign and the result hasn't
+    let overflow = if !(a ^ value) & (a ^ result) & 0x80 != 0 {
+        P
+    } else {
+        0
+    };
+    let carry = if wide > 0xFF { C } else { 0 };
+    (
+        result,
+        sign_zero(result) | ((a ^ value ^ result) & H) | overflow | carry,
+    )
+}
+
+/// SUB and SBC: A minus the value and the carry.
+pub fn sub(a: u8, value: u8, carry: bool) -> Output {
+    let wide = (a as u16)
+        .wrapping_sub(value as u16)
+        .wrapping_sub(carry as u16);
+    let result = wide as u8;
+    // the operands have different signs and the result has the sign of the
+    // value
+    let overflow = if (a ^ value) & (a ^ result) & 0x80 != 0 {
+        P
+    } else {
+        0
+    };
+    // borrowing wraps the result around
+    let carry = if wide > 0xFF { C } else { 0 };
+    (
+        result,
+        sign_zero(result) | ((a ^ value ^ result) & H) | overflow | N | carry,
+    )
+}
+
+/// CP: the flags of subtracting the value from A, which is left as is.
+pub fn cp(a: u8, value: u8) -> u8 {
+    sub(a, value, false).1
+}
+
+pub fn and(a: u8, value: u8) -> Output {
+    let result = a & value;
+    (result, sign_zero(result) | H | parity(result))
+}
+
+pub fn or(a: u8, value: u8) -> Output {
+    let result = a | value;
+    (result, sign_zero(result) | parity(result))
+}
+
+pub fn xor(a: u8, value: u8) -> Output {
+    let result = a ^ value;
+    (result, sign_zero(result) | parity(result))
+}
+
+/// INC, whose flags are [`INC_DEC_FLAGS`].
+pub fn inc(value: u8) -> Output {
+    let result = value.wrapping_add(1);
+    let half = if result & 0x0F == 0 { H } else { 0 };
+    let overflow = if result == 0x80 { P } else { 0 };
+    (result, sign_zero(result) | half | overflow)
+}
+
+/// DEC, whose flags are [`INC_DEC_FLAGS`].
+pub fn dec(value: u8) -> Output {
+    let result = value.wrapping_sub(1);
+    let half = if value & 0x0F == 0 { H } else { 0 };
+    let overflow = if value == 0x80 { P } else { 0 };
+    (result, sign_zero(result) | half | overflow | N)
+}
+
+/// ADD HL, IX or IY and a register pair, which only sets these flags, H
+/// being the carry from bit 11.
+pub const ADD16_FLAGS: u8 = H | N | C;
+
+/// The result of a 16-bit operation and the flags it sets.
+pub type Output16 = (u16, u8);
+
+/// 16-bit ADD, whose flags are [`ADD16_FLAGS`].
+pub fn add16(a: u16, value: u16) -> Output16 {
+    let wide = a as u32 + value as u32;
+    let half = if (a ^ value ^ wide as u16) & 0x1000 != 0 {
+        H
+    } else {
+        0
+    };
+    let carry = if wide > 0xFFFF { C } else { 0 };
+    (wide as u16, half | carry)
+}
+
+/// ADC HL, which unlike ADD sets all the flags from the 16-bit result.
+pub fn adc16(a: u16, value: u16, carry: bool) -> Output16 {
+    wide_flags(a, value, a as u32 + value as u32 + carry as u32, 0)
+}
+
+/// SBC HL: HL minus the register pair and the carry.
+pub fn sbc16(a: u16, value: u16, carry: bool) -> Output16 {
+    let wide = (a as u32)
+        .wrapping_sub(value as u32)
+        .wrapping_sub(carry as u32);
+    wide_flags(a, value, wide, N)
+}
+
+// the flags of ADC and SBC HL: those of ADC and SBC A on the high bytes
+fn wide_flags(a: u16, value: u16, wide: u32, subtract: u8) -> Output16 {
+    let result = wide as u16;
+    let [high, value_high, result_high] = [a, value, result].map(|n| (n >> 8) as u8);
+    let same_sign = (high ^ value_high) & 0x80 == 0;
+    // adding operands of the same sign, or subtracting ones of different
+    // signs, gives a result of the other sign
+    let overflow = if same_sign == (subtract == 0) && (high ^ result_high) & 0x80 != 0 {
+        P
+    } else {
+        0
+    };
+    let sign_zero = (result_high & S) | if result == 0 { Z } else { 0 };
+    let carry = if wide > 0xFFFF { C } else { 0 };
+    let half = (high ^ value_high ^ result_high) & H;
+    (result, sign_zero | half | overflow | subtract | carry)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the flags computed one by one from their definitions, on wider
+    // integers, to check the bit tricks above
+    #[derive(Default)]
+    struct Reference {
+        result: i32,
+        half: bool,
+        overflow: bool,
+        parity: bool,
+        subtract: bool,
+        carry: bool,
+    }
+
+    impl Reference {
+        fn arithmetic(a: u8, value: u8, carry: bool, subtract: bool) -> Self {
+            let (a, value, c) = (a as i32, value as i32, carry as i32);
+            let signed = |n: i32| n as u8 as i8 as i32;
+            let (result, half, signed_result, carry) = if subtract {
+                (
+                    a - value - c,
+                    (a & 0x0F) - (value & 0x0F) - c < 0,
+                    signed(a) - signed(value) - c,
+                    a - value - c < 0,
+                )
+            } else {
+                (
+                    a + value + c,
+                    (a & 0x0F) + (value & 0x0F) + c > 0x0F,
+                    signed(a) + signed(value) + c,
+                    a + value + c > 0xFF,
+                )
+            };
+            Self {
+                result: result & 0xFF,
+                half,
+                overflow: !(-128..=127).contains(&signed_result),
+                subtract,
+                carry,
+                ..Default::default()
+            }
+        }
+
+        fn logic(result: u8, half: bool) -> Self {
+            Self {
+                result: result as i32,
+                half,
+                parity: (0..8).filter(|bit| result & (1 << bit) != 0).count() % 2 == 0,
+                ..Default::default()
+            }
+        }
+
+        fn output(&self) -> Output {
+            let result = self.result as u8;
+            let flags = [
+                (result >= 0x80, S),
+                (result == 0, Z),
+                (self.half, H),
+                (self.overflow || self.parity, P),
+                (self.subtract, N),
+                (self.carry, C),
+            ]
+            .iter()
+            .filter(|(set, _)| *set)
+            .fold(0, |flags, (_, flag)| flags | flag);
+            (result, flags)
+        }
+    }
+
+    fn operands() -> impl Iterator<Item = (u8, u8)> {
+        (0..=255).flat_map(|a| (0..=255).map(move |value| (a, value)))
+    }
+
+    #[test]
+    fn test_add() {
+        for (a, value) in operands() {
+            for carry in [false, true] {
+                assert_eq!(
+                    add(a, value, carry),
+                    Reference::arithmetic(a, value, carry, false).output(),
+                    "ADC {a:#04X}, {value:#04X}, carry {carry}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_sub() {
+        for (a, value) in operands() {
+            for carry in [false, true] {
+                assert_eq!(
+                    sub(a, value, carry),
+                    Reference::arithmetic(a, value, carry, true).output(),
+                    "SBC {a:#04X}, {value:#04X}, carry {carry}"
+                );
+            }
+            assert_eq!(cp(a, value), sub(a, value, false).1);
+        }
+    }
+
+    #[test]
+    fn test_logic() {
+        for (a, value) in operands() {
+            assert_eq!(and(a, value), Reference::logic(a & value, true).output());
+            assert_eq!(or(a, value), Reference::logic(a | value, false).output());
+            assert_eq!(xor(a, value), Reference::logic(a ^ value, false).output());
+        }
+    }
+
+    #[test]
+    fn test_inc_dec() {
+        for value in 0..=255 {
+            // the same as adding or subtracting 1, but for the carry
+            let (result, flags) = add(value, 1, false);
+            assert_eq!(inc(value), (result, flags & INC_DEC_FLAGS));
+            let (result, flags) = sub(value, 1, false);
+            assert_eq!(dec(value), (result, flags & INC_DEC_FLAGS));
+        }
+        assert_eq!(inc(0x7F), (0x80, S | H | P));
+        assert_eq!(dec(0x00), (0xFF, S | H | N));
+    }
+
+    // values around the bits the flags come from
+    const WORDS: [u16; 12] = [
+        0x0000, 0x0001, 0x0FFF, 0x1000, 0x1234, 0x7FFF, 0x8000, 0x8001, 0x8FFF, 0xF000, 0xFFFE,
+        0xFFFF,
+    ];
+
+    fn reference16(a: u16, value: u16, carry: bool, subtract: bool) -> Output16 {
+        let (a, value, c) = (a as i32, value as i32, carry as i32);
+        let signed = |n: i32| n as u16 as i16 as i32;
+        let (result, half, signed_result, carry) = if subtract {
+            (
+                a - value - c,
+                (a & 0x0FFF) - (value & 0x0FFF) - c < 0,
+                signed(a) - signed(value) - c,
+                a - value - c < 0,
+            )
+        } else {
+            (
+                a + value + c,
+                (a & 0x0FFF) + (value & 0x0FFF) + c > 0x0FFF,
+                signed(a) + signed(value) + c,
+                a + value + c > 0xFFFF,
+            )
+        };
+        let result = result as u16;
+        let flags = [
+            (result >= 0x8000, S),
+            (result == 0, Z),
+            (half, H),
+            (!(-0x8000..=0x7FFF).contains(&signed_result), P),
+            (subtract, N),
+            (carry, C),
+        ]
+        .iter()
+        .filter(|(set, _)| *set)
+        .fold(0, |flags, (_, flag)| flags | flag);
+        (result, flags)
+    }
+
+    #[test]
+    fn test_16_bit() {
+        for a in WORDS {
+            for value in WORDS {
+                let (result, flags) = reference16(a, value, false, false);
+                assert_eq!(add16(a, value), (result, flags & ADD16_FLAGS));
+                for carry in [false, true] {
+                    assert_eq!(
+                        adc16(a, value, carry),
+                        reference16(a, value, carry, false),
+                        "ADC HL {a:#06X}, {value:#06X}, carry {carry}"
+                    );
+                    assert_eq!(
+                        sbc16(a, value, carry),
+                        reference16(a, value, carry, true),
+                        "SBC HL {a:#06X}, {value:#06X}, carry {carry}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_16_bit_flag_table() {
+        // ADD HL,DE carrying from bit 11 and 15, with S, Z and P left alone
+        assert_eq!(add16(0x0FFF, 0x0001), (0x1000, H));
+        assert_eq!(add16(0xFFFF, 0x0001), (0x0000, H | C));
+        // ADC HL sets them: 0x7FFF + 1 overflows, 0xFFFF + 0 + 1 is zero
+        assert_eq!(adc16(0x7FFF, 0x0000, true), (0x8000, S | H | P));
+        assert_eq!(adc16(0xFFFF, 0x0000, true), (0x0000, Z | H | C));
+        // SBC HL: 0x8000 - 1 overflows, 0 - 0 - 1 borrows
+        assert_eq!(sbc16(0x8000, 0x0001, false), (0x7FFF, H | P | N));
+        assert_eq!(sbc16(0x0000, 0x0000, true), (0xFFFF, S | H | N | C));
+        assert_eq!(sbc16(0x1234, 0x1234, false), (0x0000, Z | N));
+    }
+}
@@ -0,0 +1,141 @@
+use std::io::{Cursor, Read};
+#[cfg(feature = "std-fs")]
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail};
+use flate2::read::GzDecoder;
+use zip::ZipArchive;
+
+// extensions of the images we pick from archives
+const IMAGE_EXTENSIONS: [&str; 5] = ["rom", "dsk", "mx1", "mx2", "bin"];
+
+/// Reads an image file that may be compressed. Entries inside a zip file can
+/// be selected with `archive.zip#entry.rom`.
+#[cfg(feature = "std-fs")]
+pub fn read_file(path: &Path) -> anyhow::Result<Vec<u8>> {
+    let (path, entry) = split_entry(path);
+    let data = std::fs::read(&path).map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+    extract(&data, entry.as_deref())
+}
+
+/// Returns the uncompressed image: gzip data is inflated, zip files have
+/// either the named entry or their only ROM/disk image extracted and any
+/// other data is returned as is.
+pub fn extract(data: &[u8], entry: Option<&str>) -> anyhow::Result<Vec<u8>> {
+    if data.starts_with(&[0x1F, 0x8B]) {
+        let mut buffer = Vec::new();
+        GzDecoder::new(data).read_to_end(&mut buffer)?;
+        return Ok(buffer);
+    }
+
+    if data.starts_with(b"PK\x03\x04") {
+        return extract_zip(data, entry);
+    }
+
+    if let Some(entry) = entry {
+        bail!("Can't extract {}: not an archive", entry);
+    }
+
+    Ok(data.to_vec())
+}
+
+fn extract_zip(data: &[u8], entry: Option<&str>) -> anyhow::Result<Vec<u8>> {
+    let mut zip = ZipArchive::new(Cursor::new(data))?;
+    let names: Vec<String> = zip.file_names().map(|n| n.to_string()).collect();
+
+    let name = match entry {
+        Some(entry) => names
+            .iter()
+            .find(|n| *n == entry || file_name(n).eq_ignore_ascii_case(entry))
+            .ok_or_else(|| anyhow!("{} not found in archive", entry))?
+            .clone(),
+        None => {
+            let images: Vec<&String> = names.iter().filter(|n| is_image(n)).collect();
+            match images.as_slice() {
+                [name] => (*name).clone(),
+                [] => bail!("No ROM or disk image found in archive"),
+                _ => bail!(
+                    "Archive has more than one image, pick one with archive.zip#name: {}",
+                    images
+                        .iter()
+                        .map(|s| s.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            }
+        }
+    };
+
+    let mut file = zip.by_name(&name)?;
+    let mut buffer = Vec::new();
+    file.read_to_end(&mut buffer)?;
+    Ok(buffer)
+}
+
+#[cfg(feature = "std-fs")]
+fn split_entry(path: &Path) -> (PathBuf, Option<String>) {
+    if path.exists() {
+        return (path.to_path_buf(), None);
+    }
+
+    let s = path.to_string_lossy();
+    match s.rsplit_once('#') {
+        Some((archive, entry)) => (PathBuf::from(archive), Some(entry.to_string())),
+        None => (path.to_path_buf(), None),
+    }
+}
+
+fn file_name(name: &str) -> &str {
+    name.rsplit('/').next().unwrap_or(name)
+}
+
+fn is_image(name: &str) -> bool {
+    let ext = name.rsplit_once('.').map(|(_, ext)| ext).unwrap_or("");
+    IMAGE_EXTENSIONS
+        .iter()
+        .any(|candidate| ext.eq_ignore_ascii_case(candidate))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::{write::GzEncoder, Compression};
+    use zip::{write::FileOptions, ZipWriter};
+
+    use super::*;
+
+    fn zip_with(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, data) in entries {
+            zip.start_file(*name, FileOptions::default()).unwrap();
+            zip.write_all(data).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn test_extract_gzip() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&[1, 2, 3]).unwrap();
+        let data = encoder.finish().unwrap();
+
+        assert_eq!(extract(&data, None).unwrap(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_extract_zip() {
+        let data = zip_with(&[("readme.txt", b"hi"), ("GAME.ROM", &[1, 2])]);
+        assert_eq!(extract(&data, None).unwrap(), vec![1, 2]);
+
+        let data = zip_with(&[("a/one.rom", &[1]), ("two.rom", &[2])]);
+        assert!(extract(&data, None).is_err());
+        assert_eq!(extract(&data, Some("one.rom")).unwrap(), vec![1]);
+        assert_eq!(extract(&data, Some("two.rom")).unwrap(), vec![2]);
+    }
+
+    #[test]
+    fn test_plain_data() {
+        assert_eq!(extract(&[0xF3, 0xC3], None).unwrap(), vec![0xF3, 0xC3]);
+    }
+}
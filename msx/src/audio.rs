@@ -0,0 +1,160 @@
+//! The sound of the machine as samples for the host to play.
+//!
+//! Only the key click is mixed for now: bit 7 of PPI port C, a 1-bit DAC
+//! the BIOS toggles for the click of the keys and that some programs use
+//! for all their sound. The PSG isn't synthesized yet.
+
+use std::collections::VecDeque;
+
+use crate::sound::PSG_CLOCK;
+
+/// Clock of the Z80, the T-states of the bus per second.
+pub const CPU_CLOCK: f64 = 2.0 * PSG_CLOCK as f64;
+
+// level changes kept when the host doesn't take the samples, about 2 frames
+// of a program toggling the bit as fast as it can
+const MAX_EDGES: usize = 4096;
+
+/// Mixes the sound sources into samples between 0 and 1, from the changes
+/// the bus records at its T-states.
+#[derive(Debug, Clone)]
+pub struct Mixer {
+    /// master volume, from 0 to 1
+    pub volume: f32,
+    /// volume of the key click, from 0 to 1, before the master volume
+    pub key_click_volume: f32,
+    // key click level at `position`, and its changes after it
+    key_click: bool,
+    edges: VecDeque<(u64, bool)>,
+    // T-state the samples were made up to, with the fraction of the one
+    // the next sample starts in
+    position: f64,
+}
+
+impl Default for Mixer {
+    fn default() -> Self {
+        Self {
+            volume: 1.0,
+            key_click_volume: 1.0,
+            key_click: false,
+            edges: VecDeque::new(),
+            position: 0.0,
+        }
+    }
+}
+
+impl Mixer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the key click level at a T-state of the bus.
+    pub fn set_key_click(&mut self, clock: u64, level: bool) {
+        let last = self
+            .edges
+            .back()
+            .map_or(self.key_click, |(_, level)| *level);
+        if level == last {
+            return;
+        }
+        if self.edges.len() == MAX_EDGES {
+            if let Some((_, level)) = self.edges.pop_front() {
+                self.key_click = level;
+            }
+        }
+        self.edges.push_back((clock, level));
+    }
+
+    /// The samples from the last call up to the T-state `clock`, at the
+    /// sample rate in Hz. Each sample is the part of its time the key click
+    /// was high, which filters out what the sample rate can't play.
+    pub fn samples(&mut self, clock: u64, sample_rate: f32) -> Vec<f32> {
+        let clock = clock as f64;
+        // the machine was reset or a state loaded
+        if clock < self.position {
+            self.position = clock;
+            if let Some((_, level)) = self.edges.back() {
+                self.key_click = *level;
+            }
+            self.edges.clear();
+        }
+
+        let step = CPU_CLOCK / sample_rate as f64;
+        let gain = self.volume * self.key_click_volume;
+        let mut samples = Vec::with_capacity(((clock - self.position) / step) as usize);
+        while self.position + step <= clock {
+            let end = self.position + step;
+            let mut start = self.position;
+            let mut high = 0.0;
+            while let Some(&(at, level)) = self.edges.front() {
+                let at = (at as f64).max(start);
+                if at >= end {
+                    break;
+                }
+                if self.key_click {
+                    high += at - start;
+                }
+                start = at;
+                self.key_click = level;
+                self.edges.pop_front();
+            }
+            if self.key_click {
+                high += end - start;
+            }
+            samples.push((high / step) as f32 * gain);
+            self.position = end;
+        }
+        samples
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a sample rate of 1/5 of the CPU clock, so a sample is 5 T-states
+    const RATE: f32 = 715_909.0;
+
+    #[test]
+    fn test_key_click() {
+        let mut mixer = Mixer::new();
+        assert_eq!(mixer.samples(12, RATE), [0.0, 0.0]);
+
+        // high from 12 to 17, then from 21 on
+        mixer.set_key_click(12, true);
+        mixer.set_key_click(15, true);
+        mixer.set_key_click(17, false);
+        mixer.set_key_click(21, true);
+        let samples = mixer.samples(30, RATE);
+        assert_eq!(samples.len(), 4);
+        let expected = [0.6, 0.4, 0.8, 1.0];
+        for (sample, expected) in samples.iter().zip(expected) {
+            assert!((sample - expected).abs() < 1e-4, "{:?}", samples);
+        }
+
+        mixer.volume = 0.5;
+        mixer.key_click_volume = 0.5;
+        assert_eq!(mixer.samples(35, RATE), [0.25]);
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut mixer = Mixer::new();
+        mixer.set_key_click(5, true);
+        mixer.samples(50, RATE);
+
+        // after a reset the clock starts over, the level is kept
+        mixer.set_key_click(3, false);
+        assert!(mixer.samples(0, RATE).is_empty());
+        assert_eq!(mixer.samples(5, RATE), [0.0]);
+    }
+
+    #[test]
+    fn test_edges_limit() {
+        let mut mixer = Mixer::new();
+        for clock in 0..2 * MAX_EDGES as u64 {
+            mixer.set_key_click(clock, clock & 1 == 0);
+        }
+        assert_eq!(mixer.edges.len(), MAX_EDGES);
+    }
+}
@@ -0,0 +1,314 @@
+use std::collections::VecDeque;
+
+use anyhow::{anyhow, bail};
+
+use crate::{
+    keyboard::{key_position, InputProfile},
+    Msx,
+};
+
+/// Frames a key is held down, long enough for the BIOS keyboard scan.
+pub const HOLD_FRAMES: u32 = 2;
+/// Frames between releasing a key and pressing the next one.
+pub const RELEASE_FRAMES: u32 = 2;
+
+const SHIFT: &str = "ShiftLeft";
+const CONTROL: &str = "ControlLeft";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyAction {
+    /// presses the keys (row, bit) together, then releases them
+    Press(Vec<(usize, u8)>),
+    /// waits for a number of frames
+    Wait(u32),
+}
+
+/// Types text into the PPI keyboard matrix, one key every few frames.
+///
+/// Besides plain characters, the text can have `\n` (or a line break) for
+/// ENTER, `\t` for TAB, `{WAIT n}` to wait for n frames and special keys by
+/// name, e.g. `{ESC}`, `{F1}`, `{UP}` or `{CTRL+C}`. Use `\{` and `\\` for
+/// literal braces and backslashes.
+///
+/// With the joystick [`InputProfile`] the cursor keys and space move joystick
+/// port 1 instead.
+#[derive(Debug, Clone, Default)]
+pub struct Autotyper {
+    pub profile: InputProfile,
+    actions: VecDeque<KeyAction>,
+    pressed: Option<Vec<(usize, u8)>>,
+    wait: u32,
+}
+
+impl Autotyper {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queues the text after the keys that are still pending.
+    pub fn queue(&mut self, text: &str) -> anyhow::Result<()> {
+        let actions = parse(text)?;
+        self.actions.extend(actions);
+        Ok(())
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.actions.is_empty() && self.pressed.is_none() && self.wait == 0
+    }
+
+    /// Number of actions that haven't started yet.
+    pub fn pending(&self) -> usize {
+        self.actions.len()
+    }
+
+    /// Drops the pending keys, releasing the ones being held.
+    pub fn clear(&mut self, msx: &mut Msx) {
+        self.actions.clear();
+        self.wait = 0;
+        if let Some(keys) = self.pressed.take() {
+            press(msx, self.profile, &keys, false);
+        }
+    }
+
+    /// Advances the typing by one frame.
+    pub fn frame(&mut self, msx: &mut Msx) {
+        if self.wait > 0 {
+            self.wait -= 1;
+            return;
+        }
+
+        if let Some(keys) = self.pressed.take() {
+            press(msx, self.profile, &keys, false);
+            self.wait = RELEASE_FRAMES;
+            return;
+        }
+
+        match self.actions.pop_front() {
+            Some(KeyAction::Press(keys)) => {
+                press(msx, self.profile, &keys, true);
+                self.pressed = Some(keys);
+                self.wait = HOLD_FRAMES;
+            }
+            Some(KeyAction::Wait(frames)) => self.wait = frames,
+            None => {}
+        }
+    }
+}
+
+fn press(msx: &mut Msx, profile: InputProfile, keys: &[(usize, u8)], pressed: bool) {
+    for &(row, bit) in keys {
+        match profile.joystick_line((row, bit)) {
+            Some(line) => msx.set_joystick(line, pressed),
+            None => msx.set_key(row, bit, pressed),
+        }
+    }
+}
+
+/// Parses text into key actions, see [`Autotyper`].
+pub fn parse(text: &str) -> anyhow::Result<Vec<KeyAction>> {
+    let mut actions = Vec::new();
+    let mut chars = text.chars();
+
+    while let Some(ch) = chars.next() {
+        let keys = match ch {
+            '\\' => match chars.next() {
+                Some('n') => keys(&["Enter"])?,
+                Some('t') => keys(&["Tab"])?,
+                Some(ch) => char_keys(ch)?,
+                None => bail!("Unfinished escape at the end of the text"),
+            },
+            '{' => {
+                let name: String = chars.by_ref().take_while(|c| *c != '}').collect();
+                match special(&name)? {
+                    KeyAction::Press(keys) => keys,
+                    wait => {
+                        actions.push(wait);
+                        continue;
+                    }
+                }
+            }
+            // CR LF line breaks only press ENTER once
+            '\r' => continue,
+            ch => char_keys(ch)?,
+        };
+        actions.push(KeyAction::Press(keys));
+    }
+
+    Ok(actions)
+}
+
+fn keys(codes: &[&str]) -> anyhow::Result<Vec<(usize, u8)>> {
+    codes
+        .iter()
+        .map(|code| key_position(code).ok_or_else(|| anyhow!("Unknown key: {}", code)))
+        .collect()
+}
+
+fn special(name: &str) -> anyhow::Result<KeyAction> {
+    let upper = name.trim().to_uppercase();
+
+    if let Some(frames) = upper.strip_prefix("WAIT") {
+        let frames = frames
+            .trim()
+            .parse()
+            .map_err(|_| anyhow!("Invalid wait: {{{}}}", name))?;
+        return Ok(KeyAction::Wait(frames));
+    }
+
+    let (modifiers, key) = match upper.rsplit_once('+') {
+        Some((modifiers, key)) => (modifiers, key),
+        None => ("", upper.as_str()),
+    };
+
+    let mut codes = Vec::new();
+    for modifier in modifiers.split('+').filter(|m| !m.is_empty()) {
+        codes.push(match modifier {
+            "SHIFT" => SHIFT,
+            "CTRL" => CONTROL,
+            "GRAPH" => "AltLeft",
+            "CODE" => "AltRight",
+            _ => bail!("Unknown modifier: {}", modifier),
+        });
+    }
+
+    let code = match key {
+        "ENTER" | "RETURN" => "Enter",
+        "ESC" => "Escape",
+        "TAB" => "Tab",
+        "BS" => "Backspace",
+        "SPACE" => "Space",
+        "HOME" => "Home",
+        "INS" => "Insert",
+        "DEL" => "Delete",
+        "STOP" => "Pause",
+        "SELECT" => "End",
+        "CAPS" => "CapsLock",
+        "UP" => "ArrowUp",
+        "DOWN" => "ArrowDown",
+        "LEFT" => "ArrowLeft",
+        "RIGHT" => "ArrowRight",
+        "F1" => "F1",
+        "F2" => "F2",
+        "F3" => "F3",
+        "F4" => "F4",
+        "F5" => "F5",
+        key if key.len() == 1 => {
+            let mut keys = keys(&codes)?;
+            keys.extend(char_keys(key.chars().next().unwrap().to_ascii_lowercase())?);
+            return Ok(KeyAction::Press(keys));
+        }
+        _ => bail!("Unknown key: {{{}}}", name),
+    };
+    codes.push(code);
+
+    Ok(KeyAction::Press(keys(&codes)?))
+}
+
+/// Keys to press for a character on the international MSX keyboard.
+pub fn char_keys(ch: char) -> anyhow::Result<Vec<(usize, u8)>> {
+    let (code, shift) = match ch {
+        'a'..='z' | 'A'..='Z' => (
+            format!("Key{}", ch.to_ascii_uppercase()),
+            ch.is_ascii_uppercase(),
+        ),
+        '0'..='9' => (format!("Digit{}", ch), false),
+        _ => {
+            let (code, shift) = match ch {
+                ')' => ("Digit0", true),
+                '!' => ("Digit1", true),
+                '@' => ("Digit2", true),
+                '#' => ("Digit3", true),
+                '$' => ("Digit4", true),
+                '%' => ("Digit5", true),
+                '^' => ("Digit6", true),
+                '&' => ("Digit7", true),
+                '*' => ("Digit8", true),
+                '(' => ("Digit9", true),
+                '-' => ("Minus", false),
+                '_' => ("Minus", true),
+                '=' => ("Equal", false),
+                '+' => ("Equal", true),
+                '\\' => ("Backslash", false),
+                '|' => ("Backslash", true),
+                '[' => ("BracketLeft", false),
+                '{' => ("BracketLeft", true),
+                ']' => ("BracketRight", false),
+                '}' => ("BracketRight", true),
+                ';' => ("Semicolon", false),
+                ':' => ("Semicolon", true),
+                '\'' => ("Quote", false),
+                '"' => ("Quote", true),
+                '`' => ("Backquote", false),
+                '~' => ("Backquote", true),
+                ',' => ("Comma", false),
+                '<' => ("Comma", true),
+                '.' => ("Period", false),
+                '>' => ("Period", true),
+                '/' => ("Slash", false),
+                '?' => ("Slash", true),
+                ' ' => ("Space", false),
+                '\n' => ("Enter", false),
+                '\t' => ("Tab", false),
+                _ => bail!("Can't type {:?}", ch),
+            };
+            (code.to_string(), shift)
+        }
+    };
+
+    if shift {
+        keys(&[SHIFT, &code])
+    } else {
+        keys(&[&code])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let actions = parse("aB\\n{WAIT 10}{ctrl+stop}").unwrap();
+        assert_eq!(
+            actions,
+            vec![
+                KeyAction::Press(vec![(2, 6)]),
+                KeyAction::Press(vec![(6, 0), (2, 7)]),
+                KeyAction::Press(vec![(7, 7)]),
+                KeyAction::Wait(10),
+                KeyAction::Press(vec![(6, 1), (7, 4)]),
+            ]
+        );
+
+        assert!(parse("{NOPE}").is_err());
+        assert!(parse("é").is_err());
+    }
+
+    #[test]
+    fn test_joystick_profile() {
+        use crate::slot::{RamSlot, SlotType};
+
+        let mut msx = Msx::new(&[
+            SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Empty,
+        ]);
+        let mut autotyper = Autotyper::new();
+        autotyper.profile = InputProfile::Joystick;
+        autotyper.queue("{LEFT}a").unwrap();
+
+        // the cursor key moves the joystick, the others type as usual
+        autotyper.frame(&mut msx);
+        assert_eq!(msx.bus.read().unwrap().psg.joystick(), 0x04);
+        assert_eq!(msx.bus.read().unwrap().ppi.keyboard_matrix()[8], 0xFF);
+        autotyper.frame(&mut msx);
+        autotyper.frame(&mut msx);
+        autotyper.frame(&mut msx);
+        assert_eq!(msx.bus.read().unwrap().psg.joystick(), 0x00);
+        for _ in 0..3 {
+            autotyper.frame(&mut msx);
+        }
+        assert_eq!(msx.bus.read().unwrap().ppi.keyboard_matrix()[2], 0xBF);
+    }
+}
@@ -0,0 +1,271 @@
+use std::{collections::VecDeque, fmt};
+
+use serde::Serialize;
+
+/// Switches kept by default, the oldest being dropped first.
+pub const DEFAULT_BANK_LOG_SIZE: usize = 1024;
+
+/// What a mapper was written to switch a bank: a memory address, like the
+/// bank registers of a MegaRAM or of a ROM mapper, or an I/O port, like the
+/// segment registers of a memory mapper.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BankRegister {
+    Address(u16),
+    Port(u8),
+}
+
+impl fmt::Display for BankRegister {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BankRegister::Address(address) => write!(f, "#{:04X}", address),
+            BankRegister::Port(port) => write!(f, "port #{:02X}", port),
+        }
+    }
+}
+
+/// A write that selected another bank in a window of a mapper.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct BankSwitch {
+    pub slot: u8,
+    pub register: BankRegister,
+    /// index of the window in [`Slot::banks`](crate::slot::Slot::banks)
+    pub window: u8,
+    pub old: u8,
+    pub new: u8,
+    /// address of the instruction writing the register
+    pub pc: u16,
+    /// frame the switch happened in, counted since the log was enabled
+    pub frame: u64,
+}
+
+impl fmt::Display for BankSwitch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "#{:04X}  slot {} {} window {}: {} -> {}  frame {}",
+            self.pc, self.slot, self.register, self.window, self.old, self.new, self.frame
+        )
+    }
+}
+
+/// The most recent bank switches of the mappers in the slots. The bus
+/// records them while the machine tells it the PC of the instruction being
+/// executed and when a frame starts, like the [`IoLog`](crate::io_log::IoLog).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BankLog {
+    switches: VecDeque<BankSwitch>,
+    size: usize,
+    pc: u16,
+    frame: u64,
+}
+
+impl BankLog {
+    pub fn new(size: usize) -> Self {
+        Self {
+            switches: VecDeque::with_capacity(size),
+            size,
+            pc: 0,
+            frame: 0,
+        }
+    }
+
+    /// Sets the PC of the instruction about to be executed.
+    pub fn set_pc(&mut self, pc: u16) {
+        self.pc = pc;
+    }
+
+    pub fn next_frame(&mut self) {
+        self.frame += 1;
+    }
+
+    /// Records the windows whose banks differ after a write to a register
+    /// of the mapper in a slot.
+    pub fn record(&mut self, slot: u8, register: BankRegister, before: &[u8], after: &[u8]) {
+        for (window, (&old, &new)) in before.iter().zip(after).enumerate() {
+            if old == new {
+                continue;
+            }
+            if self.switches.len() == self.size {
+                self.switches.pop_front();
+            }
+            self.switches.push_back(BankSwitch {
+                slot,
+                register,
+                window: window as u8,
+                old,
+                new,
+                pc: self.pc,
+                frame: self.frame,
+            });
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.switches.clear();
+    }
+
+    /// The switches, oldest first.
+    pub fn switches(&self) -> impl DoubleEndedIterator<Item = &BankSwitch> {
+        self.switches.iter()
+    }
+}
+
+impl Default for BankLog {
+    fn default() -> Self {
+        Self::new(DEFAULT_BANK_LOG_SIZE)
+    }
+}
+
+/// The banks a mapper in a slot has selected.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MapperBanks {
+    pub slot: u8,
+    pub name: String,
+    /// by window, see [`Slot::banks`](crate::slot::Slot::banks)
+    pub banks: Vec<u8>,
+}
+
+impl fmt::Display for MapperBanks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "slot {} {}:", self.slot, self.name)?;
+        for bank in &self.banks {
+            write!(f, " {}", bank)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ram_cartridge::{MegaRam, MemoryMapper},
+        slot::{RamSlot, SlotType},
+        Msx,
+    };
+
+    #[test]
+    fn test_drops_oldest() {
+        let mut log = BankLog::new(2);
+        log.set_pc(0x4010);
+        log.record(
+            1,
+            BankRegister::Address(0x6000),
+            &[0, 1, 2, 3],
+            &[0, 5, 2, 3],
+        );
+        log.next_frame();
+        log.record(2, BankRegister::Port(0xFE), &[3, 2, 1, 0], &[3, 2, 4, 0]);
+        // the same bank selected again isn't a switch
+        log.record(
+            1,
+            BankRegister::Address(0x6000),
+            &[0, 5, 2, 3],
+            &[0, 5, 2, 3],
+        );
+        log.record(
+            1,
+            BankRegister::Address(0x8000),
+            &[0, 5, 2, 3],
+            &[0, 5, 7, 3],
+        );
+
+        let switches: Vec<_> = log.switches().copied().collect();
+        assert_eq!(switches.len(), 2);
+        assert_eq!(
+            switches[0].to_string(),
+            "#4010  slot 2 port #FE window 2: 1 -> 4  frame 1"
+        );
+        assert_eq!(switches[1].register, BankRegister::Address(0x8000));
+    }
+
+    #[test]
+    fn test_machine_log() {
+        // LD A,0xF4 / OUT (0xA8),A / LD A,5 / LD (0x6000),A / LD A,4 / OUT (0xFE),A
+        let mut rom = vec![
+            0x3E, 0xF4, 0xD3, 0xA8, 0x3E, 0x05, 0x32, 0x00, 0x60, 0x3E, 0x04, 0xD3, 0xFE,
+        ];
+        rom.resize(0x4000, 0);
+        let mut msx = Msx::new(&[
+            SlotType::Empty,
+            SlotType::MegaRam(MegaRam::new(0x20000)),
+            SlotType::MemoryMapper(MemoryMapper::new(0x20000)),
+            SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+        ]);
+        msx.load_rom(0, &rom);
+        msx.enable_bank_log();
+        // page 1 selects slot 1, pages 2 and 3 the RAM
+        for _ in 0..6 {
+            msx.step();
+        }
+
+        let switches = msx.bank_switches();
+        assert_eq!(
+            switches,
+            vec![
+                BankSwitch {
+                    slot: 1,
+                    register: BankRegister::Address(0x6000),
+                    window: 1,
+                    old: 1,
+                    new: 5,
+                    pc: 0x0006,
+                    frame: 0,
+                },
+                BankSwitch {
+                    slot: 2,
+                    register: BankRegister::Port(0xFE),
+                    window: 2,
+                    old: 1,
+                    new: 4,
+                    pc: 0x000B,
+                    frame: 0,
+                },
+            ]
+        );
+        assert_eq!(
+            msx.mapper_banks()
+                .iter()
+                .map(|mapper| mapper.to_string())
+                .collect::<Vec<_>>(),
+            ["slot 1 MegaRAM: 0 5 2 3", "slot 2 Memory mapper: 3 2 4 0"]
+        );
+    }
+
+    #[test]
+    fn test_ldir_switches() {
+        // LD A,0xF4 / OUT (0xA8),A / LD HL,0x0100 / LD DE,0x6000 / LD BC,2 /
+        // LDIR, copying the banks 5 and 6 into the register of window 1
+        let mut rom = vec![
+            0x3E, 0xF4, 0xD3, 0xA8, 0x21, 0x00, 0x01, 0x11, 0x00, 0x60, 0x01, 0x02, 0x00, 0xED,
+            0xB0,
+        ];
+        rom.resize(0x4000, 0);
+        rom[0x0100..0x0102].copy_from_slice(&[5, 6]);
+        let mut msx = Msx::new(&[
+            SlotType::Empty,
+            SlotType::MegaRam(MegaRam::new(0x20000)),
+            SlotType::Empty,
+            SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+        ]);
+        msx.load_rom(0, &rom);
+        msx.enable_bank_log();
+        for _ in 0..7 {
+            msx.step();
+        }
+
+        let switches: Vec<_> = msx
+            .bank_switches()
+            .iter()
+            .map(|switch| (switch.register, switch.old, switch.new, switch.pc))
+            .collect();
+        assert_eq!(
+            switches,
+            [
+                (BankRegister::Address(0x6000), 1, 5, 0x000D),
+                (BankRegister::Address(0x6001), 5, 6, 0x000D),
+            ]
+        );
+    }
+}
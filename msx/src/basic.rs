@@ -0,0 +1,115 @@
+use anyhow::bail;
+
+// system variables pointing to the BASIC program text, the simple and array
+// variables and the end of the variables storage
+pub const TXTTAB: u16 = 0xF676;
+pub const VARTAB: u16 = 0xF6C2;
+pub const ARYTAB: u16 = 0xF6C4;
+pub const STREND: u16 = 0xF6C6;
+
+/// Address programs are saved from on a standard machine
+pub const DEFAULT_TXTTAB: u16 = 0x8001;
+
+// BIOS keyboard buffer and its put/get pointers
+pub const KEYBUF: u16 = 0xFBF0;
+pub const KEYBUF_SIZE: u16 = 40;
+pub const PUTPNT: u16 = 0xF3F8;
+pub const GETPNT: u16 = 0xF3FA;
+
+/// A BASIC program, either as saved by `SAVE "FILE"` (tokenized) or as an
+/// ASCII listing (`SAVE "FILE",A`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BasicProgram {
+    /// tokenized program text, without the leading 0xFF marker
+    Tokenized(Vec<u8>),
+    /// listing with MSX line endings, typed in line by line
+    Ascii(Vec<u8>),
+}
+
+impl BasicProgram {
+    pub fn parse(data: &[u8]) -> anyhow::Result<Self> {
+        if let Some(program) = data.strip_prefix(&[0xFF]) {
+            // the program ends with a null link to the next line
+            if program.len() < 2 {
+                bail!("Tokenized BASIC program is truncated");
+            }
+            return Ok(Self::Tokenized(program.to_vec()));
+        }
+
+        // listings are usually CR LF terminated and may have a trailing EOF
+        let mut listing = Vec::with_capacity(data.len());
+        for byte in data {
+            match byte {
+                b'\n' if listing.last() == Some(&b'\r') => {}
+                b'\n' => listing.push(b'\r'),
+                0x1A => break,
+                byte => listing.push(*byte),
+            }
+        }
+        if !listing.is_empty() && listing.last() != Some(&b'\r') {
+            listing.push(b'\r');
+        }
+
+        Ok(Self::Ascii(listing))
+    }
+}
+
+/// Rewrites the links between the lines of a tokenized program saved from
+/// `DEFAULT_TXTTAB` so it can be placed at `start`.
+pub fn relocate(program: &[u8], start: u16) -> Vec<u8> {
+    let mut program = program.to_vec();
+    let mut offset = 0usize;
+
+    while offset + 1 < program.len() {
+        let link = u16::from_le_bytes([program[offset], program[offset + 1]]);
+        if link == 0 {
+            break;
+        }
+
+        let next = link.wrapping_sub(DEFAULT_TXTTAB) as usize;
+        let [lo, hi] = start.wrapping_add(next as u16).to_le_bytes();
+        program[offset] = lo;
+        program[offset + 1] = hi;
+
+        if next <= offset {
+            break;
+        }
+        offset = next;
+    }
+
+    program
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let data = b"10 PRINT \"HI\"\r\n20 GOTO 10\n\x1A\x1A";
+        assert_eq!(
+            BasicProgram::parse(data).unwrap(),
+            BasicProgram::Ascii(b"10 PRINT \"HI\"\r20 GOTO 10\r".to_vec())
+        );
+
+        let data = [0xFF, 0x09, 0x80, 0x0A, 0x00, 0x91, 0x00, 0x00, 0x00];
+        assert_eq!(
+            BasicProgram::parse(&data).unwrap(),
+            BasicProgram::Tokenized(data[1..].to_vec())
+        );
+        assert!(BasicProgram::parse(&[0xFF]).is_err());
+    }
+
+    #[test]
+    fn test_relocate() {
+        // 10 END / 20 END
+        let program = [
+            0x07, 0x80, 0x0A, 0x00, 0x81, 0x00, 0x0D, 0x80, 0x14, 0x00, 0x81, 0x00, 0x00, 0x00,
+        ];
+        let relocated = relocate(&program, 0x9001);
+        assert_eq!(&relocated[0..2], &[0x07, 0x90]);
+        assert_eq!(&relocated[6..8], &[0x0D, 0x90]);
+        assert_eq!(&relocated[2..6], &program[2..6]);
+        assert_eq!(&relocated[12..], &[0x00, 0x00]);
+    }
+}
@@ -0,0 +1,67 @@
+use anyhow::bail;
+
+/// MSX BASIC BLOAD binary: a 0xFE byte followed by the start, end and
+/// execution addresses and the bytes from start to end (inclusive).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BinFile {
+    pub start: u16,
+    pub end: u16,
+    pub exec: u16,
+    pub data: Vec<u8>,
+}
+
+impl BinFile {
+    pub fn parse(data: &[u8]) -> anyhow::Result<Self> {
+        if data.len() < 7 || data[0] != 0xFE {
+            bail!("Not a BLOAD file: missing 0xFE header");
+        }
+
+        let word = |offset: usize| u16::from_le_bytes([data[offset], data[offset + 1]]);
+        let (start, end, exec) = (word(1), word(3), word(5));
+        if end < start {
+            bail!(
+                "Invalid BLOAD header: end {:#06X} is before start {:#06X}",
+                end,
+                start
+            );
+        }
+
+        let len = (end - start) as usize + 1;
+        let payload = &data[7..];
+        if payload.len() < len {
+            bail!(
+                "BLOAD file is truncated: expected {} bytes, got {}",
+                len,
+                payload.len()
+            );
+        }
+
+        Ok(Self {
+            start,
+            end,
+            exec,
+            data: payload[..len].to_vec(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let data = [
+            0xFE, 0x00, 0xC0, 0x02, 0xC0, 0x01, 0xC0, 0xC9, 0x3E, 0x01, 0x1A,
+        ];
+        let bin = BinFile::parse(&data).unwrap();
+        assert_eq!(bin.start, 0xC000);
+        assert_eq!(bin.end, 0xC002);
+        assert_eq!(bin.exec, 0xC001);
+        // trailing bytes (usually padding) are ignored
+        assert_eq!(bin.data, vec![0xC9, 0x3E, 0x01]);
+
+        assert!(BinFile::parse(&data[..8]).is_err());
+        assert!(BinFile::parse(&[0x00; 8]).is_err());
+    }
+}
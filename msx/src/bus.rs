@@ -1,11 +1,31 @@
-use std::fmt;
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::atomic::{AtomicU32, Ordering},
+};
 
+use anyhow::bail;
 use derivative::Derivative;
 use serde::{Deserialize, Serialize};
 use tracing::error;
 
-use super::{ppi::Ppi, sound::AY38910, vdp::TMS9918};
-use crate::slot::{RamSlot, RomSlot, SlotType};
+use super::{
+    audio::Mixer,
+    bank_log::{BankLog, BankRegister},
+    cas::CasRecorder,
+    debug_device::DebugDevice,
+    input_log::InputLog,
+    interrupt_stats::InterruptStats,
+    io_log::{IoDirection, IoLog},
+    ppi::Ppi,
+    sound::AY38910,
+    tape::Tape,
+    vdp::TMS9918,
+};
+use crate::{
+    device::Device,
+    slot::{RamSlot, RomSlot, Slot, SlotType},
+};
 
 #[derive(Debug, Copy, Clone, Serialize, Deserialize, Eq, PartialEq)]
 pub struct MemorySegment {
@@ -25,6 +45,28 @@ impl fmt::Display for MemorySegment {
     }
 }
 
+// an address read from an empty slot, set through the shared reference
+// memory is read with; 0 when there is none, the address plus one otherwise
+#[derive(Debug, Default)]
+struct EmptySlotRead(AtomicU32);
+
+impl EmptySlotRead {
+    fn set(&self, address: u16) {
+        self.0.store(address as u32 + 1, Ordering::Relaxed);
+    }
+
+    fn take(&mut self) -> Option<u16> {
+        let read = std::mem::take(self.0.get_mut());
+        read.checked_sub(1).map(|address| address as u16)
+    }
+}
+
+impl Clone for EmptySlotRead {
+    fn clone(&self) -> Self {
+        Self(AtomicU32::new(self.0.load(Ordering::Relaxed)))
+    }
+}
+
 #[derive(Derivative, Clone, Serialize, Deserialize)]
 #[derivative(Debug, PartialEq)]
 pub struct Bus {
@@ -34,11 +76,88 @@ pub struct Bus {
     pub vdp: TMS9918,
     pub psg: AY38910,
     pub ppi: Ppi,
+    /// emulator-only, enabled by the host, so not part of the saved state
+    #[serde(skip)]
+    #[derivative(PartialEq = "ignore")]
+    pub debug_device: Option<DebugDevice>,
+    /// recent port accesses, enabled by the host like the debug device
+    #[serde(skip)]
+    #[derivative(PartialEq = "ignore")]
+    pub io_log: Option<IoLog>,
+    /// recent bank switches of the mappers, enabled by the host like the
+    /// I/O log
+    #[serde(skip)]
+    #[derivative(PartialEq = "ignore")]
+    pub bank_log: Option<BankLog>,
+    /// recent presses and releases of the keyboard and joystick, enabled by
+    /// the host like the I/O log
+    #[serde(skip)]
+    #[derivative(PartialEq = "ignore")]
+    pub input_log: Option<InputLog>,
+    /// how the VDP interrupts went since the machine started or a state was
+    /// loaded
+    #[serde(skip)]
+    #[derivative(PartialEq = "ignore")]
+    pub interrupt_stats: InterruptStats,
+    /// sound samples for the host, enabled by it like the debug device
+    #[serde(skip)]
+    #[derivative(PartialEq = "ignore")]
+    pub mixer: Option<Mixer>,
+    /// cassette in the recorder, inserted by the host like the debug device
+    #[serde(skip)]
+    #[derivative(PartialEq = "ignore")]
+    pub tape: Option<Tape>,
+    /// blocks saved on the cassette output, recorded when the host asks
+    #[serde(skip)]
+    #[derivative(PartialEq = "ignore")]
+    pub tape_recorder: Option<CasRecorder>,
 
     vdp_io_clock: u8,
-    slots: [SlotType; 4],
+    pub(crate) slots: [SlotType; 4],
+
+    /// T-states run since the machine started, the time base of the devices
+    clock: u64,
+    // T-state of the next device event, when the devices are synced; 0 syncs
+    // them on the next tick, e.g. after loading a state
+    #[serde(skip)]
+    #[derivative(PartialEq = "ignore")]
+    next_event: u64,
+    // whether a device holds the interrupt line, as of the last sync
+    #[serde(skip)]
+    #[derivative(PartialEq = "ignore")]
+    irq: bool,
+    // whether a device pulsed the NMI line, until the CPU is told
+    #[serde(skip)]
+    #[derivative(PartialEq = "ignore")]
+    nmi: bool,
 
     wrote_to_ppi: bool,
+    // whether the primary slot register was written since last asked
+    #[serde(skip)]
+    #[derivative(PartialEq = "ignore")]
+    wrote_slot_register: bool,
+    // the address last read from an empty slot since last asked
+    #[serde(skip)]
+    #[derivative(PartialEq = "ignore")]
+    empty_slot_read: EmptySlotRead,
+    // whether the VRAM was read or written through port 0x98 since last asked
+    #[serde(skip)]
+    #[derivative(PartialEq = "ignore")]
+    accessed_vram: bool,
+    // the slot of the BIOS the machine was built with, the first with a ROM
+    // from 0x0000; kept when a state is loaded, like the host devices
+    #[serde(skip)]
+    #[derivative(PartialEq = "ignore")]
+    pub(crate) bios_slot: Option<u8>,
+
+    // devices attached besides the built-in ones, and the ports they decode;
+    // kept when a state is loaded, like the host devices
+    #[serde(skip)]
+    #[derivative(PartialEq = "ignore")]
+    pub(crate) devices: Vec<Box<dyn Device>>,
+    #[serde(skip)]
+    #[derivative(PartialEq = "ignore")]
+    pub(crate) ports: HashMap<u8, usize>,
 }
 
 impl Default for Bus {
@@ -50,6 +169,14 @@ impl Default for Bus {
             vdp: TMS9918::new(),
             psg: AY38910::new(),
             ppi: Ppi::new(),
+            debug_device: None,
+            io_log: None,
+            bank_log: None,
+            input_log: None,
+            interrupt_stats: InterruptStats::new(),
+            mixer: None,
+            tape: None,
+            tape_recorder: None,
             vdp_io_clock: 0,
             slots: [
                 SlotType::Empty,
@@ -57,7 +184,17 @@ impl Default for Bus {
                 SlotType::Empty,
                 SlotType::Empty,
             ],
+            clock: 0,
+            next_event: 0,
+            irq: false,
+            nmi: false,
             wrote_to_ppi: false,
+            wrote_slot_register: false,
+            empty_slot_read: EmptySlotRead::default(),
+            accessed_vram: false,
+            bios_slot: None,
+            devices: Vec::new(),
+            ports: HashMap::new(),
         }
     }
 }
@@ -69,6 +206,14 @@ impl Bus {
             vdp: TMS9918::new(),
             psg: AY38910::new(),
             ppi: Ppi::new(),
+            debug_device: None,
+            io_log: None,
+            bank_log: None,
+            input_log: None,
+            interrupt_stats: InterruptStats::new(),
+            mixer: None,
+            tape: None,
+            tape_recorder: None,
             vdp_io_clock: 0,
             slots: [
                 slots.get(0).unwrap().clone(),
@@ -76,7 +221,20 @@ impl Bus {
                 slots.get(2).unwrap().clone(),
                 slots.get(3).unwrap().clone(),
             ],
+            clock: 0,
+            next_event: 0,
+            irq: false,
+            nmi: false,
             wrote_to_ppi: false,
+            wrote_slot_register: false,
+            empty_slot_read: EmptySlotRead::default(),
+            accessed_vram: false,
+            bios_slot: slots
+                .iter()
+                .position(|slot| matches!(slot, SlotType::Rom(rom) if rom.base == 0x0000))
+                .map(|slot| slot as u8),
+            devices: Vec::new(),
+            ports: HashMap::new(),
         }
     }
 
@@ -88,32 +246,249 @@ impl Bus {
         self.vdp.reset();
         self.psg.reset();
         self.ppi.reset();
+        if let Some(debug_device) = &mut self.debug_device {
+            debug_device.reset();
+        }
+        for device in &mut self.devices {
+            device.reset();
+        }
+        for slot in &mut self.slots {
+            slot.reset();
+        }
+        self.clock = 0;
+        self.next_event = 0;
+        self.irq = false;
+        self.nmi = false;
+        if let Some(mixer) = &mut self.mixer {
+            mixer.set_key_click(0, self.ppi.key_click());
+        }
+        if let Some(tape) = &mut self.tape {
+            tape.set_clock(0);
+            tape.set_motor(0, self.ppi.cassette_motor());
+        }
     }
 
-    pub fn input(&mut self, port: u8) -> u8 {
+    // built-in device decoding a port
+    fn builtin_device(&mut self, port: u8) -> Option<&mut dyn Device> {
         match port {
-            0x98 | 0x99 => self.vdp.read(port),
-            0xA0 | 0xA1 => self.psg.read(port),
-            0xA8 | 0xA9 | 0xAA | 0xAB => self.ppi.read(port),
-            _ => {
-                error!("[BUS] Invalid port {:02X} read", port);
-                0xff
+            0x2E | 0x2F => self
+                .debug_device
+                .as_mut()
+                .map(|device| device as &mut dyn Device),
+            0x98 | 0x99 => Some(&mut self.vdp),
+            0xA0..=0xA2 => Some(&mut self.psg),
+            0xA8..=0xAB => Some(&mut self.ppi),
+            _ => None,
+        }
+    }
+
+    fn device(&mut self, port: u8) -> Option<&mut dyn Device> {
+        if let Some(index) = self.ports.get(&port) {
+            return Some(self.devices[*index].as_mut());
+        }
+        self.builtin_device(port)
+    }
+
+    /// Attaches a device to the given ports, which must not be decoded by
+    /// any other device. Returns the index of the device.
+    pub fn attach_device(
+        &mut self,
+        ports: impl IntoIterator<Item = u8>,
+        device: Box<dyn Device>,
+    ) -> anyhow::Result<usize> {
+        let ports: Vec<u8> = ports.into_iter().collect();
+        for port in &ports {
+            if let Some(other) = self.device(*port) {
+                bail!("Port {:#04X} is already used by the {}", port, other.name());
             }
         }
+
+        let index = self.devices.len();
+        self.devices.push(device);
+        self.ports
+            .extend(ports.into_iter().map(|port| (port, index)));
+        Ok(index)
+    }
+
+    /// Attached devices, in the order they were attached.
+    pub fn devices(&self) -> impl Iterator<Item = &dyn Device> {
+        self.devices.iter().map(|device| device.as_ref())
+    }
+
+    /// The first attached device of a type, e.g. the serial port of a link.
+    pub fn attached_mut<T: Device + 'static>(&mut self) -> Option<&mut T> {
+        self.devices
+            .iter_mut()
+            .find_map(|device| device.as_any_mut().downcast_mut::<T>())
+    }
+
+    /// Advances the clock by the T-states of an instruction, returning
+    /// whether a device requests an interrupt. The devices are only synced
+    /// when one of them has an event due, or when their ports are accessed.
+    pub fn tick(&mut self, t_states: u32) -> bool {
+        self.clock += t_states as u64;
+        if self.clock >= self.next_event {
+            self.sync();
+        }
+        self.irq
+    }
+
+    /// Switches the VDP between 50 and 60 Hz, the frame interrupts and the
+    /// frames of the debug device following from the frame being drawn.
+    pub fn set_pal(&mut self, pal: bool) {
+        self.sync();
+        self.vdp.set_pal(pal);
+        if let Some(debug_device) = &mut self.debug_device {
+            debug_device.pal = pal;
+            debug_device.origin = self.vdp.origin;
+        }
+        self.schedule();
+    }
+
+    /// T-states run since the machine started.
+    pub fn clock(&self) -> u64 {
+        self.clock
+    }
+
+    /// Catches all the devices up with the clock, e.g. before the host
+    /// inspects them.
+    pub fn sync(&mut self) {
+        let clock = self.clock;
+        self.for_each_device(|device| device.sync(clock));
+        self.schedule();
+    }
+
+    // finds the next device event and whether the interrupt line is held,
+    // after the devices changed
+    fn schedule(&mut self) {
+        let mut next_event = u64::MAX;
+        let mut irq = false;
+        let mut nmi = false;
+        self.for_each_device(|device| {
+            if let Some(event) = device.next_event() {
+                next_event = next_event.min(event);
+            }
+            irq |= device.irq();
+            nmi |= device.take_nmi();
+        });
+        self.next_event = next_event;
+        self.irq = irq;
+        self.nmi |= nmi;
+    }
+
+    /// Whether a device pulsed the NMI line since the last call.
+    pub fn take_nmi(&mut self) -> bool {
+        std::mem::take(&mut self.nmi)
+    }
+
+    fn for_each_device(&mut self, mut f: impl FnMut(&mut dyn Device)) {
+        f(&mut self.vdp);
+        f(&mut self.psg);
+        f(&mut self.ppi);
+        if let Some(debug_device) = &mut self.debug_device {
+            f(debug_device);
+        }
+        for device in &mut self.devices {
+            f(device.as_mut());
+        }
+    }
+
+    // slots decoding a port no device does, if any
+    fn slot_io(&mut self, port: u8) -> Option<impl Iterator<Item = &mut dyn Slot>> {
+        let mut slots = self
+            .slots
+            .iter_mut()
+            .filter_map(|slot| slot.as_slot_mut())
+            .filter(move |slot| slot.ports().contains(&port))
+            .peekable();
+        slots.peek()?;
+        Some(slots)
+    }
+
+    pub fn input(&mut self, port: u8) -> u8 {
+        if port == 0x98 {
+            self.accessed_vram = true;
+        }
+        let clock = self.clock;
+        // the cassette input is bit 7 of PSG port A
+        if port == 0xA2 && self.psg.selected_register() == 14 {
+            if let Some(tape) = &mut self.tape {
+                self.psg.set_cassette_input(tape.level(clock));
+            }
+        }
+        let value = match self.device(port) {
+            Some(device) => {
+                device.sync(clock);
+                let value = device.io_read(port);
+                self.schedule();
+                value
+            }
+            None => match self.slot_io(port) {
+                // the cartridges decoding the port all drive the data bus
+                Some(slots) => slots.fold(0xFF, |value, slot| value & slot.io_read(port)),
+                None => {
+                    error!("[BUS] Invalid port {:02X} read", port);
+                    0xff
+                }
+            },
+        };
+        if let Some(io_log) = &mut self.io_log {
+            io_log.record(port, IoDirection::Read, value);
+        }
+        if let Some(input_log) = &mut self.input_log {
+            match port {
+                0xA9 => input_log.read_row(self.ppi.keyboard_row()),
+                0xA2 if self.psg.selected_register() == 14 => input_log.read_joystick(),
+                _ => {}
+            }
+        }
+        value
     }
 
     pub fn output(&mut self, port: u8, data: u8) {
-        match port {
-            0x98 | 0x99 => self.vdp.write(port, data),
-            0xA0 | 0xA1 => self.psg.write(port, data),
-            0xA8 | 0xA9 | 0xAA | 0xAB => {
-                self.wrote_to_ppi = true;
-                self.ppi.write(port, data);
+        if (0xA8..=0xAB).contains(&port) {
+            self.wrote_to_ppi = true;
+        }
+        if port == 0xA8 {
+            self.wrote_slot_register = true;
+        }
+        if port == 0x98 {
+            self.accessed_vram = true;
+        }
+        if let Some(io_log) = &mut self.io_log {
+            io_log.record(port, IoDirection::Write, data);
+        }
+        let clock = self.clock;
+        match self.device(port) {
+            Some(device) => {
+                device.sync(clock);
+                device.io_write(port, data);
+                self.schedule();
             }
-            _ => {
-                error!("[BUS] Invalid port {:02X} write", port);
+            None => {
+                let banks = self.bank_log.is_some().then(|| self.banks());
+                match self.slot_io(port) {
+                    Some(slots) => slots.for_each(|slot| slot.io_write(port, data)),
+                    None => {
+                        error!("[BUS] Invalid port {:02X} write", port);
+                    }
+                }
+                if let Some(banks) = banks {
+                    self.record_bank_switches(BankRegister::Port(port), banks);
+                }
             }
         };
+        if matches!(port, 0xAA | 0xAB) {
+            if let Some(mixer) = &mut self.mixer {
+                mixer.set_key_click(clock, self.ppi.key_click());
+            }
+            if let Some(tape) = &mut self.tape {
+                tape.set_motor(clock, self.ppi.cassette_motor());
+            }
+            if let Some(recorder) = &mut self.tape_recorder {
+                recorder.output(clock, self.ppi.cassette_output(), self.ppi.cassette_motor());
+            }
+        }
     }
 
     pub fn wrote_to_ppi(&mut self) -> bool {
@@ -122,29 +497,180 @@ impl Bus {
         wrote_to_ppi
     }
 
+    /// Whether the primary slot register was written since the last call,
+    /// even with the value it held, for the slot tracing.
+    pub fn wrote_slot_register(&mut self) -> bool {
+        std::mem::take(&mut self.wrote_slot_register)
+    }
+
+    pub fn accessed_vram(&mut self) -> bool {
+        std::mem::take(&mut self.accessed_vram)
+    }
+
     pub fn read_byte(&self, addr: u16) -> u8 {
         let (slot_number, addr) = self.translate_address(addr);
-        self.slots[slot_number].read(addr)
+        let slot = &self.slots[slot_number];
+        if matches!(slot, SlotType::Empty) {
+            self.empty_slot_read.set(addr);
+        }
+        slot.read(addr)
+    }
+
+    /// The address last read from an empty slot since the last call, if
+    /// any, for the slot checks.
+    pub fn empty_slot_read(&mut self) -> Option<u16> {
+        self.empty_slot_read.take()
     }
 
     pub fn write_byte(&mut self, addr: u16, data: u8) {
         let (slot_number, addr) = self.translate_address(addr);
+        let banks = self.bank_log.is_some().then(|| self.banks());
         self.slots[slot_number].write(addr, data);
+        if let Some(banks) = banks {
+            self.record_bank_switches(BankRegister::Address(addr), banks);
+        }
+    }
+
+    /// Banks selected in the windows of the mapper in each slot, see
+    /// [`Slot::banks`].
+    pub fn banks(&self) -> [Vec<u8>; 4] {
+        [0, 1, 2, 3].map(|n| {
+            self.slots[n]
+                .as_slot()
+                .map(|slot| slot.banks())
+                .unwrap_or_default()
+        })
+    }
+
+    // logs the banks that a write to a register changed from those before
+    fn record_bank_switches(&mut self, register: BankRegister, before: [Vec<u8>; 4]) {
+        let after = self.banks();
+        if let Some(bank_log) = &mut self.bank_log {
+            for (n, (before, after)) in before.iter().zip(&after).enumerate() {
+                bank_log.record(n as u8, register, before, after);
+            }
+        }
     }
 
     pub fn write_word(&mut self, address: u16, value: u16) {
         let low_byte = (value & 0x00FF) as u8;
         let high_byte = ((value & 0xFF00) >> 8) as u8;
         self.write_byte(address, low_byte);
-        self.write_byte(address + 1, high_byte);
+        self.write_byte(address.wrapping_add(1), high_byte);
     }
 
     pub fn read_word(&self, address: u16) -> u16 {
         let low_byte = self.read_byte(address) as u16;
-        let high_byte = self.read_byte(address + 1) as u16;
+        let high_byte = self.read_byte(address.wrapping_add(1)) as u16;
         (high_byte << 8) | low_byte
     }
 
+    /// Reads the memory from the address into the buffer, wrapping at
+    /// 0xFFFF. The slot is looked up once per 16K page and RAM is copied as
+    /// a whole, other slots are read byte by byte.
+    pub fn read_block(&self, address: u16, buf: &mut [u8]) {
+        let mut address = address;
+        let mut done = 0;
+        while done < buf.len() {
+            let n = page_run(address, buf.len() - done);
+            let chunk = &mut buf[done..done + n];
+            let slot = &self.slots[self.page_slot(address)];
+            match slot {
+                SlotType::Ram(ram) if ram.bytes(address, n).is_some() => {
+                    chunk.copy_from_slice(ram.bytes(address, n).unwrap());
+                }
+                _ => {
+                    for (i, byte) in chunk.iter_mut().enumerate() {
+                        *byte = slot.read(address.wrapping_add(i as u16));
+                    }
+                }
+            }
+            address = address.wrapping_add(n as u16);
+            done += n;
+        }
+    }
+
+    /// Copies `len` bytes from `src` to `dst` the way LDIR does: byte by
+    /// byte going up, so a destination right after the source repeats its
+    /// first bytes. Runs between RAM are copied as a whole, any other slot
+    /// is read and written byte by byte, in order, to keep the side effects
+    /// of devices.
+    pub fn copy(&mut self, src: u16, dst: u16, len: usize) {
+        let (mut src, mut dst) = (src, dst);
+        let mut left = len;
+        while left > 0 {
+            let n = page_run(src, page_run(dst, left));
+            let (src_slot, dst_slot) = (self.page_slot(src), self.page_slot(dst));
+            // a destination ahead of the source within the run sees the
+            // bytes written before, which a whole copy wouldn't
+            let repeats = src_slot == dst_slot && dst > src && (dst as usize) < src as usize + n;
+            let whole = !repeats
+                && matches!(&self.slots[src_slot], SlotType::Ram(ram) if ram.bytes(src, n).is_some())
+                && matches!(&self.slots[dst_slot], SlotType::Ram(ram) if ram.bytes(dst, n).is_some());
+
+            if whole && src_slot == dst_slot {
+                // a source overlapping ahead of the destination reads the
+                // same either way
+                if let SlotType::Ram(ram) = &mut self.slots[src_slot] {
+                    let from = (src - ram.base) as usize;
+                    ram.data
+                        .copy_within(from..from + n, (dst - ram.base) as usize);
+                }
+            } else if whole {
+                let (low, high) = self.slots.split_at_mut(src_slot.max(dst_slot));
+                let (from, to) = if src_slot < dst_slot {
+                    (&low[src_slot], &mut high[0])
+                } else {
+                    (&high[0], &mut low[dst_slot])
+                };
+                if let (SlotType::Ram(from), SlotType::Ram(to)) = (from, to) {
+                    to.bytes_mut(dst, n)
+                        .unwrap()
+                        .copy_from_slice(from.bytes(src, n).unwrap());
+                }
+            } else {
+                // through read_byte and write_byte, which note the reads of
+                // empty slots and the bank switches
+                for i in 0..n as u16 {
+                    let value = self.read_byte(src.wrapping_add(i));
+                    self.write_byte(dst.wrapping_add(i), value);
+                }
+            }
+            src = src.wrapping_add(n as u16);
+            dst = dst.wrapping_add(n as u16);
+            left -= n;
+        }
+    }
+
+    /// Writes the value to the `len` bytes from the address, wrapping at
+    /// 0xFFFF. RAM is filled as a whole, other slots are written byte by
+    /// byte.
+    pub fn fill(&mut self, address: u16, len: usize, value: u8) {
+        let mut address = address;
+        let mut left = len;
+        while left > 0 {
+            let n = page_run(address, left);
+            let slot_number = self.page_slot(address);
+            match &mut self.slots[slot_number] {
+                SlotType::Ram(ram) if ram.bytes(address, n).is_some() => {
+                    ram.bytes_mut(address, n).unwrap().fill(value);
+                }
+                _ => {
+                    for i in 0..n as u16 {
+                        self.write_byte(address.wrapping_add(i), value);
+                    }
+                }
+            }
+            address = address.wrapping_add(n as u16);
+            left -= n;
+        }
+    }
+
+    // the primary slot selected for the page of the address
+    fn page_slot(&self, address: u16) -> usize {
+        ((self.ppi.primary_slot_config >> ((address >> 14) * 2)) & 0b11) as usize
+    }
+
     pub fn primary_slot_config(&self) -> u8 {
         self.ppi.primary_slot_config
     }
@@ -233,11 +759,62 @@ impl Bus {
     pub fn load_empty(&mut self, slot: u8) {
         self.slots[slot as usize] = SlotType::Empty;
     }
+
+    /// The slot of the BIOS the machine was built with, the first primary
+    /// slot holding a ROM from 0x0000.
+    pub fn bios_slot(&self) -> Option<u8> {
+        self.bios_slot
+    }
+
+    pub fn slot(&self, slot: u8) -> Option<&SlotType> {
+        self.slots.get(slot as usize)
+    }
+
+    pub fn slot_mut(&mut self, slot: u8) -> Option<&mut SlotType> {
+        self.slots.get_mut(slot as usize)
+    }
+
+    /// Replaces the contents of a slot, returning what was there before. The
+    /// memory pages are mapped on every access, so the new contents are seen
+    /// right away.
+    pub fn insert_slot(&mut self, slot: u8, slot_type: SlotType) -> anyhow::Result<SlotType> {
+        let current = self
+            .slots
+            .get_mut(slot as usize)
+            .ok_or_else(|| anyhow::anyhow!("Invalid slot: {}", slot))?;
+        Ok(std::mem::replace(current, slot_type))
+    }
+
+    /// Clears the contents of all RAM slots.
+    pub fn clear_ram(&mut self) {
+        self.slots.iter_mut().for_each(SlotType::clear_ram);
+    }
+
+    /// Loses what the batteries of the slots and devices keep through
+    /// resets, see [`Msx::power_cycle`](crate::Msx::power_cycle).
+    pub fn power_off(&mut self) {
+        for device in &mut self.devices {
+            device.power_off();
+        }
+        for slot in self.slots.iter_mut().filter_map(SlotType::as_slot_mut) {
+            slot.power_off();
+        }
+    }
+}
+
+// bytes from the address to the end of its 16K page, at most `len`
+fn page_run(address: u16, len: usize) -> usize {
+    len.min(0x4000 - (address as usize & 0x3FFF))
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::slot::{RamSlot, RomSlot};
+    use crate::{
+        ram_cartridge::RamCartridge,
+        serial::I8251,
+        slot::{ExpandedSlot, RamSlot, RomSlot},
+        vdp_timing::{DISPLAY_T_STATES, FRAME_T_STATES},
+    };
 
     use super::*;
 
@@ -342,8 +919,8 @@ mod tests {
         bus.ppi.primary_slot_config = 0b00_11_00_00;
         assert_eq!(bus.translate_address(0x0000), (0, 0x0000));
         assert_eq!(bus.translate_address(0x4000), (0, 0x4000));
-        assert_eq!(bus.translate_address(0x8000), (3, 0x0000));
-        assert_eq!(bus.translate_address(0xC000), (0, 0x8000));
+        assert_eq!(bus.translate_address(0x8000), (3, 0x8000));
+        assert_eq!(bus.translate_address(0xC000), (0, 0xC000));
 
         bus.ppi.primary_slot_config = 0b00_00_00_00;
         assert_eq!(bus.translate_address(0x0FFF), (0, 0x0FFF));
@@ -354,19 +931,471 @@ mod tests {
         bus.ppi.primary_slot_config = 0b11_11_00_00;
         assert_eq!(bus.translate_address(0x0FFF), (0, 0x0FFF));
         assert_eq!(bus.translate_address(0x4FFF), (0, 0x4FFF));
-        assert_eq!(bus.translate_address(0x8FFF), (3, 0x0FFF));
-        assert_eq!(bus.translate_address(0xCFFF), (3, 0x4FFF));
+        assert_eq!(bus.translate_address(0x8FFF), (3, 0x8FFF));
+        assert_eq!(bus.translate_address(0xCFFF), (3, 0xCFFF));
 
         bus.ppi.primary_slot_config = 0b11_11_01_00;
         assert_eq!(bus.translate_address(0x0FFF), (0, 0x0FFF));
-        assert_eq!(bus.translate_address(0x4FFF), (1, 0x0FFF));
-        assert_eq!(bus.translate_address(0x8FFF), (3, 0x0FFF));
-        assert_eq!(bus.translate_address(0xFFFF), (3, 0x7FFF));
+        assert_eq!(bus.translate_address(0x4FFF), (1, 0x4FFF));
+        assert_eq!(bus.translate_address(0x8FFF), (3, 0x8FFF));
+        assert_eq!(bus.translate_address(0xFFFF), (3, 0xFFFF));
 
         bus.ppi.primary_slot_config = 0b11_11_01_10;
         assert_eq!(bus.translate_address(0x0FFF), (2, 0x0FFF));
-        assert_eq!(bus.translate_address(0x4FFF), (1, 0x0FFF));
-        assert_eq!(bus.translate_address(0x8FFF), (3, 0x0FFF));
-        assert_eq!(bus.translate_address(0xFFFF), (3, 0x7FFF));
+        assert_eq!(bus.translate_address(0x4FFF), (1, 0x4FFF));
+        assert_eq!(bus.translate_address(0x8FFF), (3, 0x8FFF));
+        assert_eq!(bus.translate_address(0xFFFF), (3, 0xFFFF));
+    }
+
+    #[test]
+    fn test_slot_switch_through_ppi() {
+        let mut bus = Bus::new(&[
+            SlotType::Rom(RomSlot::new(&[0x11], 0x0000, 0x10000)),
+            SlotType::Rom(RomSlot::new(&[0x22], 0x0000, 0x10000)),
+            SlotType::Empty,
+            SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+        ]);
+        assert_eq!(bus.read_byte(0x4000), 0x11);
+
+        // the BIOS initialization: mode set, then RAM on page 3
+        bus.output(0xAB, 0x82);
+        bus.output(0xA8, 0b11_00_00_00);
+        assert_eq!(bus.primary_slot_config(), 0b11_00_00_00);
+        bus.write_byte(0xC000, 0x42);
+        assert_eq!(bus.read_byte(0xC000), 0x42);
+
+        // read-modify-write of page 1, as ENASLT does
+        let config = bus.input(0xA8);
+        bus.output(0xA8, (config & 0b11_11_00_11) | 0b00_00_01_00);
+        assert_eq!(bus.read_byte(0x0000), 0x11);
+        assert_eq!(bus.read_byte(0x4000), 0x22);
+        assert_eq!(bus.read_byte(0xC000), 0x42);
+
+        // bit set/reset commands only change port C
+        bus.output(0xAB, 0x0F);
+        assert_eq!(bus.primary_slot_config(), 0b11_00_01_00);
+        assert_eq!(bus.input(0xAA) & 0x80, 0x80);
+    }
+
+    #[test]
+    fn test_slot_switch_from_code() {
+        let bus = Bus::new(&[
+            SlotType::Rom(RomSlot::new(&[0x11], 0x0000, 0x10000)),
+            SlotType::Rom(RomSlot::new(&[0x22], 0x0000, 0x10000)),
+            SlotType::Empty,
+            SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+        ]);
+        let mut cpu = crate::Z80::new(std::sync::Arc::new(std::sync::RwLock::new(bus)));
+        cpu.bus.write().unwrap().ppi.primary_slot_config = 0b11_00_00_00;
+
+        // IN A, (#A8) / AND #F3 / OR #04 / OUT (#A8), A / LD A, (#4000) / HALT
+        let program = [
+            0xDB, 0xA8, 0xE6, 0xF3, 0xF6, 0x04, 0xD3, 0xA8, 0x3A, 0x00, 0x40, 0x76,
+        ];
+        for (i, byte) in program.iter().enumerate() {
+            cpu.write_byte(0xC000 + i as u16, *byte);
+        }
+        cpu.pc = 0xC000;
+        while !cpu.halted {
+            cpu.execute_cycle();
+        }
+
+        assert_eq!(cpu.a, 0x22);
+        assert_eq!(cpu.bus.read().unwrap().primary_slot_config(), 0b11_00_01_00);
+    }
+
+    #[test]
+    fn test_insert_slot() {
+        let mut bus = Bus::new(&[
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+        ]);
+        bus.ppi.primary_slot_config = 0b11_11_01_11;
+        assert_eq!(bus.read_byte(0x4000), 0xFF);
+
+        let cart = SlotType::Rom(RomSlot::new(&[0x41, 0x42], 0x0000, 0x10000));
+        let previous = bus.insert_slot(1, cart).unwrap();
+        assert_eq!(previous, SlotType::Empty);
+        assert_eq!(bus.read_byte(0x4000), 0x41);
+        assert_eq!(bus.read_byte(0x4001), 0x42);
+
+        let ejected = bus.insert_slot(1, SlotType::Empty).unwrap();
+        assert!(matches!(ejected, SlotType::Rom(_)));
+        assert_eq!(bus.read_byte(0x4000), 0xFF);
+        assert!(bus.insert_slot(4, SlotType::Empty).is_err());
+
+        bus.write_byte(0x8000, 0x12);
+        bus.clear_ram();
+        assert_eq!(bus.read_byte(0x8000), 0xFF);
+    }
+
+    #[test]
+    fn test_expanded_slot() {
+        let mut bus = Bus::new(&[
+            SlotType::Rom(RomSlot::new(&[0x11], 0x0000, 0x8000)),
+            SlotType::Empty,
+            RamCartridge::MemoryMapper(0x20000).slot(),
+            SlotType::Expanded(ExpandedSlot::new([
+                SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+                SlotType::Empty,
+                RamCartridge::MemoryMapper(0x10000).slot(),
+                SlotType::Empty,
+            ])),
+        ]);
+        bus.ppi.primary_slot_config = 0b11_11_00_00;
+        assert_eq!(bus.read_byte(0xFFFF), 0xFF);
+
+        // the mapper of secondary slot 2 on page 2
+        bus.write_byte(0xFFFF, 0b00_10_00_00);
+        assert_eq!(bus.read_byte(0xFFFF), 0b11_01_11_11);
+        bus.write_byte(0x8000, 0x42);
+        assert_eq!(bus.read_byte(0x8000), 0x42);
+        // both mappers follow the segment registers, driving the data bus
+        bus.output(0xFE, 3);
+        assert_eq!(bus.read_byte(0x8000), 0xFF);
+        assert_eq!(bus.input(0xFE), 0xFB);
+        let banks = bus.slot(3).and_then(|slot| slot.as_slot()).unwrap().banks();
+        assert_eq!(banks, [3, 2, 3, 0]);
+
+        // the register is only seen with page 3 on the expanded slot
+        bus.ppi.primary_slot_config = 0b10_11_00_00;
+        bus.write_byte(0xFFFF, 0x12);
+        assert_eq!(bus.read_byte(0xFFFF), 0x12);
+        bus.ppi.primary_slot_config = 0b11_11_00_00;
+        assert_eq!(bus.read_byte(0xFFFF), 0b11_01_11_11);
+
+        bus.reset();
+        assert_eq!(bus.read_byte(0xFFFF), 0xFF);
+    }
+
+    #[test]
+    fn test_serial_link_queues() {
+        let mut bus = Bus::new(&[
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+        ]);
+        // no RS-232 interface until one is attached
+        assert_eq!(bus.input(0x81), 0xFF);
+        assert_eq!(bus.input(0x80), 0xFF);
+        assert!(bus.attached_mut::<I8251>().is_none());
+        bus.attach_device([0x80, 0x81], Box::new(I8251::new()))
+            .unwrap();
+
+        assert_eq!(bus.input(0x81) & 0x02, 0);
+        bus.attached_mut::<I8251>().unwrap().receive(b"OK");
+        assert_eq!(bus.input(0x81) & 0x02, 0x02);
+        assert_eq!(bus.input(0x80), b'O');
+        assert_eq!(bus.input(0x80), b'K');
+        assert_eq!(bus.input(0x81) & 0x02, 0);
+
+        bus.output(0x80, b'H');
+        bus.output(0x80, b'I');
+        let serial = bus.attached_mut::<I8251>().unwrap();
+        assert_eq!(serial.take_transmitted(), b"HI");
+        assert!(serial.take_transmitted().is_empty());
+    }
+
+    // raises an interrupt from T-state 20
+    #[derive(Debug, Clone, Default)]
+    struct Counter {
+        value: u8,
+        clock: u64,
+    }
+
+    impl Device for Counter {
+        fn name(&self) -> &str {
+            "counter"
+        }
+
+        fn io_read(&mut self, port: u8) -> u8 {
+            self.value.wrapping_add(port & 1)
+        }
+
+        fn io_write(&mut self, _port: u8, value: u8) {
+            self.value = value;
+        }
+
+        fn sync(&mut self, clock: u64) {
+            self.clock = clock;
+        }
+
+        fn next_event(&self) -> Option<u64> {
+            (self.clock < 20).then_some(20)
+        }
+
+        fn irq(&self) -> bool {
+            self.clock >= 20
+        }
+
+        fn reset(&mut self) {
+            self.value = 0;
+            self.clock = 0;
+        }
+    }
+
+    // pulses the NMI line when written to
+    #[derive(Debug, Clone, Default)]
+    struct NmiButton {
+        pressed: bool,
+    }
+
+    impl Device for NmiButton {
+        fn name(&self) -> &str {
+            "NMI button"
+        }
+
+        fn io_read(&mut self, _port: u8) -> u8 {
+            0xFF
+        }
+
+        fn io_write(&mut self, _port: u8, _value: u8) {
+            self.pressed = true;
+        }
+
+        fn take_nmi(&mut self) -> bool {
+            std::mem::take(&mut self.pressed)
+        }
+    }
+
+    #[test]
+    fn test_device_nmi() {
+        let mut bus = Bus::new(&[
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+        ]);
+        bus.attach_device([0x40], Box::new(NmiButton::default()))
+            .unwrap();
+        assert!(!bus.take_nmi());
+
+        bus.output(0x40, 0);
+        assert!(bus.take_nmi());
+        // reported once per pulse
+        assert!(!bus.take_nmi());
+        bus.sync();
+        assert!(!bus.take_nmi());
+    }
+
+    #[test]
+    fn test_attach_device() {
+        let mut bus = Bus::new(&[
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+        ]);
+        assert_eq!(bus.input(0x40), 0xFF);
+
+        let index = bus
+            .attach_device(0x40..=0x41, Box::new(Counter::default()))
+            .unwrap();
+        assert_eq!(index, 0);
+        bus.output(0x40, 0x10);
+        assert_eq!(bus.input(0x40), 0x10);
+        assert_eq!(bus.input(0x41), 0x11);
+
+        assert!(!bus.tick(10));
+        assert!(bus.tick(10));
+
+        let copy = bus.clone();
+        assert_eq!(copy.devices().count(), 1);
+
+        bus.reset();
+        assert_eq!(bus.input(0x40), 0x00);
+        assert!(!bus.tick(10));
+
+        let error = bus
+            .attach_device([0x99], Box::new(Counter::default()))
+            .unwrap_err();
+        assert_eq!(error.to_string(), "Port 0x99 is already used by the VDP");
+        assert!(bus
+            .attach_device([0x41], Box::new(Counter::default()))
+            .is_err());
+    }
+
+    #[test]
+    fn test_vdp_frame_interrupt() {
+        let mut bus = Bus::new(&[
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+        ]);
+        // frame interrupt enabled
+        bus.vdp.registers[1] = 0x20;
+
+        // the beam only moves when the VDP is synced
+        assert!(!bus.tick(4));
+        assert!(!bus.tick(DISPLAY_T_STATES as u32 - 5));
+        assert_eq!(bus.vdp.line, 0);
+        bus.sync();
+        assert_eq!(bus.vdp.line, 191);
+        assert!(bus.tick(1));
+        assert!(bus.vdp.vblank);
+
+        // reading the status clears the flag and the interrupt
+        assert_eq!(bus.input(0x99) & 0x80, 0x80);
+        assert!(!bus.tick(4));
+        assert_eq!(bus.input(0x99) & 0x80, 0x00);
+
+        assert!(!bus.tick(FRAME_T_STATES as u32 - 5));
+        assert!(bus.tick(1));
+        assert_eq!(bus.vdp.frame, 2);
+    }
+
+    #[test]
+    fn test_psg_envelope_catches_up() {
+        let mut bus = Bus::new(&[
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+        ]);
+        // envelope period 1, shape 13 (attack then hold), restarted at 100
+        bus.output(0xA0, 11);
+        bus.output(0xA1, 1);
+        bus.tick(100);
+        bus.output(0xA0, 13);
+        bus.output(0xA1, 13);
+        assert_eq!(bus.psg.envelope_volume(), 0);
+
+        // the PSG isn't synced while nothing accesses it
+        bus.tick(32 * 5);
+        assert_eq!(bus.psg.envelope_step(), 0);
+        bus.sync();
+        assert_eq!(bus.psg.envelope_step(), 5);
+        assert_eq!(bus.psg.envelope_volume(), 5);
+
+        bus.tick(32 * 100);
+        bus.sync();
+        assert_eq!(bus.psg.envelope_volume(), 15);
+    }
+
+    // records the writes it gets, in order
+    #[derive(Debug, Clone, PartialEq, Default)]
+    struct WriteLog {
+        writes: Vec<(u16, u8)>,
+    }
+
+    impl crate::slot::Slot for WriteLog {
+        fn name(&self) -> &str {
+            "Write log"
+        }
+
+        fn read(&self, address: u16) -> u8 {
+            address as u8
+        }
+
+        fn write(&mut self, address: u16, value: u8) {
+            self.writes.push((address, value));
+        }
+
+        fn size(&self) -> u32 {
+            0x10000
+        }
+    }
+
+    fn write_log(bus: &Bus, slot: usize) -> Vec<(u16, u8)> {
+        match &bus.slots[slot] {
+            SlotType::Device(device) => {
+                let log = device.as_any().downcast_ref::<WriteLog>().unwrap();
+                log.writes.clone()
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn block_bus() -> Bus {
+        let mut rom = vec![0; 0x8000];
+        for (i, byte) in rom.iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+        let mut bus = Bus::new(&[
+            SlotType::Rom(RomSlot::new(&rom, 0x0000, 0x8000)),
+            SlotType::Device(Box::<WriteLog>::default()),
+            SlotType::Empty,
+            SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+        ]);
+        // ROM, device, RAM, RAM
+        bus.ppi.primary_slot_config = 0b11_11_01_00;
+        bus
+    }
+
+    #[test]
+    fn test_read_block() {
+        let mut bus = block_bus();
+        bus.write_byte(0x8000, 0xAA);
+        bus.write_byte(0xFFFF, 0xBB);
+
+        // across all the pages and wrapping at the end of the memory
+        let mut block = vec![0; 0x10000];
+        bus.read_block(0x3FF0, &mut block);
+        let bytes: Vec<u8> = (0..0x10000u32)
+            .map(|i| bus.read_byte(0x3FF0u16.wrapping_add(i as u16)))
+            .collect();
+        assert_eq!(block, bytes);
+        assert_eq!(block[0x4010], 0xAA);
+        assert_eq!(block[0xC00F], 0xBB);
+    }
+
+    #[test]
+    fn test_copy() {
+        let mut bus = block_bus();
+
+        // ROM into the RAM, across the page boundary
+        bus.copy(0x3FFE, 0x8000, 4);
+        let mut block = [0; 4];
+        bus.read_block(0x8000, &mut block);
+        assert_eq!(block, [0xFE, 0xFF, 0x00, 0x01]);
+
+        // a destination right after the source repeats it, as LDIR does
+        bus.copy(0x8000, 0x8001, 3);
+        bus.read_block(0x8000, &mut block);
+        assert_eq!(block, [0xFE; 4]);
+
+        // one before the source shifts it
+        bus.fill(0x9000, 4, 0);
+        bus.write_byte(0x9003, 0x33);
+        bus.copy(0x9001, 0x9000, 3);
+        bus.read_block(0x9000, &mut block);
+        assert_eq!(block, [0x00, 0x00, 0x33, 0x33]);
+
+        // writes to devices and ROM go byte by byte
+        bus.copy(0x8000, 0x7FFF, 2);
+        assert_eq!(write_log(&bus, 1), vec![(0x7FFF, 0xFE)]);
+        assert_eq!(bus.read_byte(0x0000), 0x00);
+        bus.copy(0x0000, 0x0001, 2);
+        assert_eq!(bus.read_byte(0x0001), 0x01);
+
+        // between the RAM of two slots, both ways
+        *bus.slot_mut(2).unwrap() = SlotType::Ram(RamSlot::new(0x0000, 0x10000));
+        bus.ppi.primary_slot_config = 0b11_11_10_00;
+        bus.copy(0x8000, 0x4000, 4);
+        bus.read_block(0x4000, &mut block);
+        assert_eq!(block, [0xFE; 4]);
+        bus.write_byte(0x4001, 0x11);
+        bus.copy(0x4000, 0x8010, 2);
+        bus.read_block(0x8010, &mut block);
+        assert_eq!(block, [0xFE, 0x11, 0xFF, 0xFF]);
+    }
+
+    #[test]
+    fn test_fill() {
+        let mut bus = block_bus();
+        bus.fill(0x7FFE, 4, 0x55);
+        assert_eq!(write_log(&bus, 1), vec![(0x7FFE, 0x55), (0x7FFF, 0x55)]);
+        let mut block = [0; 3];
+        bus.read_block(0x7FFF, &mut block);
+        assert_eq!(block, [0xFF, 0x55, 0x55]);
+
+        // wraps to the ROM, which ignores it
+        bus.fill(0xFFFF, 2, 0x66);
+        assert_eq!(bus.read_byte(0xFFFF), 0x66);
+        assert_eq!(bus.read_byte(0x0000), 0x00);
     }
 }
@@ -0,0 +1,289 @@
+use std::fmt;
+#[cfg(feature = "std-fs")]
+use std::path::Path;
+
+use anyhow::bail;
+
+/// Marks the start of a block in a CAS image, at an offset multiple of 8.
+/// On the tape, a block starts after a header tone instead.
+pub const BLOCK_HEADER: [u8; 8] = [0x1F, 0xA6, 0xDE, 0xBA, 0xCC, 0x13, 0x7D, 0x74];
+
+// bytes repeated 10 times at the start of the blocks naming a file: binary
+// (BSAVE), tokenized BASIC (CSAVE) and ASCII (SAVE "CAS:")
+const FILE_TYPES: [u8; 3] = [0xD0, 0xD3, 0xEA];
+
+// 1s in a row after which a 0 starts a block rather than a byte: far more
+// than the 8 bits and 2 stop bits of a 0xFF, far less than a header tone
+pub(crate) const HEADER_ONES: usize = 20;
+
+// half cycles of the header tone the recorder locks on
+const LOCK_HALVES: usize = 16;
+
+/// A CAS image, the bytes of the blocks of a tape without the tones: the
+/// format the tape dumps of the MSX are shared in.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Cas {
+    pub blocks: Vec<Vec<u8>>,
+}
+
+impl Cas {
+    pub fn parse(data: &[u8]) -> anyhow::Result<Self> {
+        if !data.starts_with(&BLOCK_HEADER) {
+            bail!("Not a CAS file: missing block header");
+        }
+        let mut blocks: Vec<Vec<u8>> = Vec::new();
+        let mut offset = 0;
+        while offset < data.len() {
+            if offset % 8 == 0 && data[offset..].starts_with(&BLOCK_HEADER) {
+                blocks.push(Vec::new());
+                offset += BLOCK_HEADER.len();
+                continue;
+            }
+            blocks.last_mut().unwrap().push(data[offset]);
+            offset += 1;
+        }
+        Ok(Self { blocks })
+    }
+
+    /// The image, each block padded with zeros up to the next header.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        for block in &self.blocks {
+            data.resize(data.len().next_multiple_of(8), 0);
+            data.extend(BLOCK_HEADER);
+            data.extend(block);
+        }
+        data
+    }
+
+    #[cfg(feature = "std-fs")]
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        Self::parse(&std::fs::read(path)?)
+    }
+
+    #[cfg(feature = "std-fs")]
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        Ok(std::fs::write(path, self.to_bytes())?)
+    }
+
+    /// Bytes in the blocks.
+    pub fn len(&self) -> usize {
+        self.blocks.iter().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+}
+
+impl fmt::Display for Cas {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} blocks, {} bytes", self.blocks.len(), self.len())
+    }
+}
+
+/// Whether a block names a file, written after a long header tone rather
+/// than a short one.
+pub fn is_file_header(block: &[u8]) -> bool {
+    block.len() >= 10
+        && FILE_TYPES.contains(&block[0])
+        && block[..10].iter().all(|b| *b == block[0])
+}
+
+/// Turns the cassette output, bit 5 of PPI port C, back into the bytes the
+/// BIOS saved, as the blocks of a CAS image. The half cycles of the output
+/// are measured against the clock of the bus while the motor runs; the
+/// recorder locks on those of each header tone, the 1s, so that it reads
+/// any baud rate.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CasRecorder {
+    cas: Cas,
+    level: bool,
+    // T-state of the last edge of the output while the motor ran
+    edge: Option<u64>,
+    // T-states of a half cycle of the header tone, once locked on it
+    short: Option<f64>,
+    // last half cycles seen while locking
+    halves: Vec<u64>,
+    lows: usize,
+    highs: usize,
+    // 1s in a row outside of a byte
+    ones: usize,
+    // bits read of the byte being read, and their value
+    byte: Option<(u8, u8)>,
+}
+
+impl CasRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The blocks recorded.
+    pub fn cas(&self) -> &Cas {
+        &self.cas
+    }
+
+    /// Takes the level of the output and the motor after a write to port
+    /// C at the T-state `clock`.
+    pub fn output(&mut self, clock: u64, level: bool, motor: bool) {
+        if !motor {
+            // a block ends with the motor; the next one has its own tone
+            self.edge = None;
+            self.unlock();
+            self.level = level;
+            return;
+        }
+        if level == self.level {
+            return;
+        }
+        self.level = level;
+        if let Some(edge) = self.edge {
+            self.half(clock - edge);
+        }
+        self.edge = Some(clock);
+    }
+
+    fn unlock(&mut self) {
+        self.short = None;
+        self.halves.clear();
+        (self.lows, self.highs, self.ones) = (0, 0, 0);
+        self.byte = None;
+    }
+
+    // a half cycle of `t_states`
+    fn half(&mut self, t_states: u64) {
+        let Some(short) = self.short else {
+            self.halves.push(t_states);
+            if self.halves.len() > LOCK_HALVES {
+                self.halves.remove(0);
+            }
+            let mean = self.halves.iter().sum::<u64>() as f64 / self.halves.len() as f64;
+            let steady = self
+                .halves
+                .iter()
+                .all(|half| (*half as f64 - mean).abs() < mean * 0.2);
+            if self.halves.len() == LOCK_HALVES && steady {
+                self.short = Some(mean);
+                // the tone locked on is part of the header
+                self.ones = LOCK_HALVES / 4;
+            }
+            return;
+        };
+        if t_states as f64 > short * 4.0 {
+            // too long for either tone: the output stopped mid-block
+            self.unlock();
+            return;
+        }
+        // the same halving of the cycles of a 1 as the tape demodulation
+        let high = (t_states as f64) < short * 1.5;
+        let (count, needed) = if high {
+            (self.highs, self.lows) = (self.highs + 1, 0);
+            (self.highs, 4)
+        } else {
+            (self.lows, self.highs) = (self.lows + 1, 0);
+            (self.lows, 2)
+        };
+        if count == needed {
+            (self.lows, self.highs) = (0, 0);
+            self.bit(high);
+        }
+    }
+
+    fn bit(&mut self, value: bool) {
+        match &mut self.byte {
+            Some((bits, byte)) => {
+                *byte |= (value as u8) << *bits;
+                *bits += 1;
+                if *bits == 8 {
+                    let byte = *byte;
+                    self.byte = None;
+                    if let Some(block) = self.cas.blocks.last_mut() {
+                        block.push(byte);
+                    }
+                }
+            }
+            None if value => self.ones += 1,
+            // a start bit, of a block after a header tone
+            None => {
+                if self.ones >= HEADER_ONES {
+                    self.cas.blocks.push(Vec::new());
+                }
+                self.ones = 0;
+                self.byte = Some((0, 0));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        slot::{RamSlot, SlotType},
+        tape::Tape,
+        Msx,
+    };
+
+    fn cas() -> Cas {
+        Cas {
+            blocks: vec![
+                [[0xD3; 10].as_slice(), b"HELLO"].concat(),
+                vec![0x00, 0xFF, 0x12, 0x80, 0x00, 0x00, 0x00],
+            ],
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let data = cas().to_bytes();
+        assert_eq!(data.len(), 8 + 15 + 1 + 8 + 7);
+        assert_eq!(data[24..32], BLOCK_HEADER);
+        // the padding before the second header is read back with the first
+        // block, as everywhere
+        let parsed = Cas::parse(&data).unwrap();
+        assert_eq!(
+            parsed.blocks[0],
+            [cas().blocks[0].as_slice(), &[0]].concat()
+        );
+        assert_eq!(parsed.blocks[1], cas().blocks[1]);
+        assert!(is_file_header(&parsed.blocks[0]));
+        assert!(!is_file_header(&parsed.blocks[1]));
+        assert!(Cas::parse(b"RIFF").is_err());
+    }
+
+    // plays a CAS image on the cassette input and copies it, the way the
+    // BIOS would, to the cassette output being recorded
+    #[test]
+    fn test_record() {
+        for baud in [1200, 2400] {
+            let mut msx = Msx::new(&[
+                SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+                SlotType::Empty,
+                SlotType::Empty,
+                SlotType::Empty,
+            ]);
+            let tape = Tape::from_cas("test.cas", &cas().to_bytes(), Some(baud)).unwrap();
+            let length = (tape.status().length * 3_579_545.0) as u64;
+            msx.insert_tape(tape);
+            msx.start_tape_recording();
+
+            let mut bus = msx.bus.write().unwrap();
+            bus.output(0xA0, 14);
+            // motor on, then the input copied to the output every 50
+            // T-states
+            bus.output(0xAB, 0x08);
+            while bus.clock() < length {
+                bus.tick(50);
+                let level = bus.input(0xA2) & 0x80 != 0;
+                bus.output(0xAB, 0x0A | level as u8);
+            }
+            bus.output(0xAB, 0x09);
+            drop(bus);
+
+            let recorded = msx.tape_recording().unwrap();
+            assert_eq!(recorded.blocks.len(), 2, "{} baud", baud);
+            assert_eq!(recorded.blocks[0][..15], cas().blocks[0]);
+            assert_eq!(recorded.blocks[1], cas().blocks[1]);
+        }
+    }
+}
@@ -0,0 +1,56 @@
+//! C-BIOS, a free MSX BIOS (<https://cbios.sourceforge.net>), bundled so that
+//! cartridges boot without the BIOS of a real machine, which can't be
+//! redistributed. Its BSD license allows shipping it with the emulator.
+
+use crate::slot::{RomSlot, SlotType};
+
+/// The MSX1 C-BIOS of `roms/`, the main ROM followed by the logo.
+pub const ROM: &[u8] = include_bytes!("../../roms/cbios_msx1.rom");
+
+/// What software C-BIOS can't run, told with the information of its ROM.
+pub const LIMITATIONS: &str = "C-BIOS starts cartridges only: it has no BASIC, so BASIC \
+programs, tapes and cartridges that run BASIC (those with a TEXT address) or call its routines \
+don't work, and there is no disk ROM for MSX-DOS. Its font, keyboard layout and the \
+frequency it reports are those of the international version, whatever the preset.";
+
+/// C-BIOS in a slot, from 0x0000 like the BIOS of a real machine.
+pub fn slot() -> SlotType {
+    SlotType::Rom(RomSlot::new(ROM, 0x0000, 0x10000))
+}
+
+/// Whether a ROM is a C-BIOS, of any version or region, by the name it
+/// shows when booting.
+pub fn is_cbios(rom: &[u8]) -> bool {
+    rom.windows(6).any(|window| window == b"C-BIOS")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{preset::Preset, vdp_timing::FRAME_T_STATES, Msx};
+
+    #[test]
+    fn test_is_cbios() {
+        assert!(is_cbios(ROM));
+        assert!(!is_cbios(&[0xFF; 0x4000]));
+    }
+
+    #[test]
+    fn test_boots_cartridge() {
+        // INIT at 0x4010: LD A,0x42 / LD (0xE000),A / JR $
+        let mut rom = vec![0xFF; 0x4000];
+        rom[..4].copy_from_slice(&[b'A', b'B', 0x10, 0x40]);
+        rom[0x10..0x17].copy_from_slice(&[0x3E, 0x42, 0x32, 0x00, 0xE0, 0x18, 0xFE]);
+        let preset = Preset::find("msx1-jp").unwrap();
+        let mut msx = Msx::new(&preset.cbios_slots(SlotType::Rom(RomSlot::cartridge(&rom))));
+
+        for _ in 0..300 {
+            msx.run_cycles(FRAME_T_STATES as u32);
+            if msx.pc() == 0x4015 {
+                break;
+            }
+        }
+        assert_eq!(msx.pc(), 0x4015);
+        assert_eq!(msx.cpu.read_byte(0xE000), 0x42);
+    }
+}
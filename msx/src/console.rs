@@ -0,0 +1,182 @@
+//! The text printed through the BIOS CHPUT routine, as plain text for the
+//! host terminal.
+
+use crate::Z80;
+
+/// H.CHPU, which CHPUT calls with the character in A, in the MSX BIOS and
+/// in C-BIOS alike. Watching it rather than the CHPUT entry at 0x00A2 also
+/// catches the BASIC output, which calls the routine directly.
+pub const H_CHPU: u16 = 0xFDA4;
+
+const ESC: u8 = 0x1B;
+// the next character is a graphic one, offset by 0x40
+const GRAPHIC: u8 = 0x01;
+
+// characters of output kept to look for texts in
+const RECENT_SIZE: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum Escape {
+    #[default]
+    None,
+    /// after ESC, the next character tells how long the sequence is
+    Start,
+    /// characters of the sequence left to skip
+    Skip(u8),
+    /// after GRAPHIC
+    Graphic,
+}
+
+/// Turns the characters sent to CHPUT into text: the printable ASCII as
+/// is, line feeds and tabs, and anything else outside ASCII as a dot. The
+/// other control characters and the escape sequences moving the cursor or
+/// erasing are dropped.
+#[derive(Debug, Clone, Default)]
+pub struct Console {
+    escape: Escape,
+    // the last characters of the text
+    recent: String,
+}
+
+impl Console {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Must be called before the CPU executes an instruction, returns the
+    /// text to print when it enters H.CHPU.
+    pub fn before_step(&mut self, cpu: &Z80) -> Option<char> {
+        if cpu.pc != H_CHPU {
+            return None;
+        }
+        self.put(cpu.a)
+    }
+
+    /// The text for a character sent to CHPUT, if any.
+    pub fn put(&mut self, ch: u8) -> Option<char> {
+        let text = self.text(ch)?;
+        if self.recent.len() >= RECENT_SIZE {
+            self.recent.drain(..RECENT_SIZE / 2);
+        }
+        self.recent.push(text);
+        Some(text)
+    }
+
+    /// Whether the text printed so far ends with this one, which can be up
+    /// to 128 characters long. Checked after each character, this tells
+    /// when a program prints something, e.g. `Ok` or `PASSED`.
+    pub fn printed(&self, text: &str) -> bool {
+        !text.is_empty() && self.recent.ends_with(text)
+    }
+
+    fn text(&mut self, ch: u8) -> Option<char> {
+        match self.escape {
+            Escape::Start => {
+                // ESC Y row column and ESC x/y n have arguments
+                self.escape = match ch {
+                    b'Y' => Escape::Skip(2),
+                    b'x' | b'y' => Escape::Skip(1),
+                    _ => Escape::None,
+                };
+                return None;
+            }
+            Escape::Skip(n) => {
+                self.escape = if n > 1 {
+                    Escape::Skip(n - 1)
+                } else {
+                    Escape::None
+                };
+                return None;
+            }
+            Escape::Graphic => {
+                self.escape = Escape::None;
+                return Some('.');
+            }
+            Escape::None => {}
+        }
+
+        match ch {
+            ESC => {
+                self.escape = Escape::Start;
+                None
+            }
+            GRAPHIC => {
+                self.escape = Escape::Graphic;
+                None
+            }
+            b'\n' => Some('\n'),
+            b'\t' => Some('\t'),
+            0x20..=0x7E => Some(ch as char),
+            0x00..=0x1F | 0x7F => None,
+            _ => Some('.'),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, RwLock};
+
+    use super::*;
+    use crate::{
+        bus::Bus,
+        slot::{RamSlot, SlotType},
+    };
+
+    fn text(bytes: &[u8]) -> String {
+        let mut console = Console::new();
+        bytes.iter().filter_map(|&ch| console.put(ch)).collect()
+    }
+
+    #[test]
+    fn test_text() {
+        assert_eq!(text(b"Ok\r\n"), "Ok\n");
+        assert_eq!(text(b"A\tB\x07\x08C"), "A\tBC");
+        assert_eq!(text(&[b'a', 0x85, b'b']), "a.b");
+    }
+
+    #[test]
+    fn test_escape_sequences() {
+        // clear screen, cursor home and up, then locate to row 5 column 10
+        assert_eq!(text(b"\x0C\x1BH\x1BAx"), "x");
+        assert_eq!(text(b"\x1BY%*Hi"), "Hi");
+        assert_eq!(text(b"\x1Bx5ok\x1By4"), "ok");
+        // a graphic character takes two bytes
+        assert_eq!(text(&[GRAPHIC, 0x41, b'!']), ".!");
+    }
+
+    #[test]
+    fn test_printed() {
+        let mut console = Console::new();
+        for &ch in b"\x0CReady\r\n" {
+            console.put(ch);
+        }
+        assert!(console.printed("Ready\n"));
+        assert!(!console.printed("Ready"));
+        assert!(!console.printed(""));
+
+        // only the end of a long output is kept
+        for _ in 0..1000 {
+            console.put(b'.');
+        }
+        console.put(b'!');
+        assert!(console.printed(&format!("{}!", ".".repeat(100))));
+    }
+
+    #[test]
+    fn test_before_step() {
+        let bus = Bus::new(&[
+            SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Empty,
+        ]);
+        let mut cpu = Z80::new(Arc::new(RwLock::new(bus)));
+        let mut console = Console::new();
+        cpu.a = b'K';
+        cpu.pc = 0x00A2;
+        assert_eq!(console.before_step(&cpu), None);
+        cpu.pc = H_CHPU;
+        assert_eq!(console.before_step(&cpu), Some('K'));
+    }
+}
@@ -5,9 +5,9 @@ use std::{
 
 use derivative::Derivative;
 use serde::{Deserialize, Serialize};
-use tracing::{error, info, trace};
+use tracing::{info, trace};
 
-use super::bus::Bus;
+use super::{alu, bus::Bus};
 
 // static constexpr byte S_FLAG = 0x80;
 // static constexpr byte Z_FLAG = 0x40;
@@ -69,9 +69,20 @@ pub struct Z80 {
     // Interrupt mode
     pub im: u8,
     interrupt_request: bool,
+    // the NMI is taken before the next instruction, whatever IFF1 is
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    nmi_request: bool,
+    // interrupts aren't accepted right after EI, only after the next
+    // instruction, so that EI / RET can't be interrupted
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    ei_delay: bool,
 
     // Halted?
     pub halted: bool,
+    /// counts the T-states of the Z80 datasheet, without the wait state the
+    /// MSX inserts in each M1 cycle
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub stock_timing: bool,
 
     // Debug options
     pub max_cycles: Option<u64>,
@@ -133,7 +144,10 @@ impl Z80 {
             iff2: false,
             im: 0,
             interrupt_request: false,
+            nmi_request: false,
+            ei_delay: false,
             halted: false,
+            stock_timing: false,
             max_cycles: None,
             track_flags: false,
             cycles: 0,
@@ -166,6 +180,8 @@ impl Z80 {
         self.iff2 = false;
         self.im = 0;
         self.interrupt_request = false;
+        self.nmi_request = false;
+        self.ei_delay = false;
         self.halted = false;
         self.max_cycles = None;
         self.track_flags = false;
@@ -184,20 +200,28 @@ impl Z80 {
         self.interrupt_request = true;
     }
 
+    /// Whether the next cycle takes the requested maskable interrupt rather
+    /// than running an instruction.
+    pub fn takes_interrupt(&self) -> bool {
+        self.interrupt_request && self.iff1 && !self.ei_delay && !self.nmi_request
+    }
+
+    /// Requests a non-maskable interrupt, taken before the next instruction
+    /// even with interrupts disabled. It wakes the CPU from HALT.
+    pub fn request_nmi(&mut self) {
+        self.nmi_request = true;
+        self.halted = false;
+    }
+
     pub fn memory(&self) -> Vec<u8> {
-        let mut memory = Vec::new();
-        for pc in 0..self.read_bus().mem_size() {
-            memory.push(self.read_byte(pc as u16));
-        }
+        let bus = self.read_bus();
+        let mut memory = vec![0; bus.mem_size()];
+        bus.read_block(0, &mut memory);
         memory
     }
 
     pub fn execute_cycle(&mut self) {
         self.cycles += 1;
-        if self.halted {
-            info!("Halted");
-            return;
-        }
 
         // Check if we reached max_cycles
         if let Some(max_cycles) = self.max_cycles {
@@ -206,8 +230,21 @@ impl Z80 {
             }
         }
 
-        if self.interrupt_request && self.iff1 {
+        if self.nmi_request {
+            info!("NMI");
+            // IFF2 keeps whether interrupts were enabled, for RETN to
+            // restore them
+            self.nmi_request = false;
+            self.iff1 = false;
+            self.push(self.pc);
+            self.pc = 0x0066;
+            return;
+        }
+
+        if self.interrupt_request && self.iff1 && !self.ei_delay {
             info!("Interrupt request");
+            // the interrupt wakes the CPU from HALT, returning after it
+            self.halted = false;
             self.interrupt_request = false;
             self.iff1 = false;
             self.push(self.pc);
@@ -215,6 +252,11 @@ impl Z80 {
             return;
         }
 
+        if self.halted {
+            info!("Halted");
+            return;
+        }
+
         // Fetch and decode the next instruction
         let opcode = self.read_byte(self.pc);
         // if opcode > 0x00 {
@@ -227,6 +269,8 @@ impl Z80 {
         //     self.c,
         //     self.f
         // );
+        // EI sets it again, so only the instruction right after it is delayed
+        self.ei_delay = false;
         self.execute(opcode);
     }
 
@@ -623,6 +667,16 @@ impl Z80 {
                 self.l = self.h;
                 self.pc = self.pc.wrapping_add(1);
             }
+            0x6E => {
+                // LD L, (HL)
+                trace!("LD L, (HL)");
+                self.l = self.read_byte(self.get_hl());
+                self.pc = self.pc.wrapping_add(1);
+            }
+            0x5B | 0x6D | 0x7F => {
+                // LD E, E / LD L, L / LD A, A, which do nothing, as LD B, B
+                self.pc = self.pc.wrapping_add(1);
+            }
             0x77 => {
                 // LD (HL), A
                 // trace!("LD (HL), A -> A before = 0x{:02X}", self.a);
@@ -803,33 +857,28 @@ impl Z80 {
             0x3C => {
                 // INC A
                 trace!("INC A");
-                self.a = self.a.wrapping_add(1);
-                self.set_inc_flags(self.a);
+                self.a = self.inc(self.a);
                 self.pc = self.pc.wrapping_add(1);
             }
             0x04 => {
                 // INC B
-                self.b = self.b.wrapping_add(1);
-                self.set_inc_flags(self.b);
+                self.b = self.inc(self.b);
                 self.pc = self.pc.wrapping_add(1);
             }
             0x0C => {
                 // INC C
-                self.c = self.c.wrapping_add(1);
-                self.set_inc_flags(self.c);
+                self.c = self.inc(self.c);
                 self.pc = self.pc.wrapping_add(1);
             }
             0x14 => {
                 // INC D
-                self.d = self.d.wrapping_add(1);
-                self.set_inc_flags(self.d);
+                self.d = self.inc(self.d);
                 self.pc = self.pc.wrapping_add(1);
             }
             0x1C => {
                 // INC E
                 self.pc = self.pc.wrapping_add(1);
-                self.e = self.e.wrapping_add(1);
-                self.set_inc_flags(self.e);
+                self.e = self.inc(self.e);
             }
             0x03 => {
                 // INC BC
@@ -860,13 +909,11 @@ impl Z80 {
             0x24 => {
                 // INC H
                 self.pc = self.pc.wrapping_add(1);
-                self.h = self.h.wrapping_add(1);
-                self.set_inc_flags(self.h);
+                self.h = self.inc(self.h);
             }
             0x2C => {
                 // INC L
-                self.l = self.l.wrapping_add(1);
-                self.set_inc_flags(self.l);
+                self.l = self.inc(self.l);
                 self.pc = self.pc.wrapping_add(1);
             }
             0x34 => {
@@ -993,56 +1040,13 @@ impl Z80 {
                 self.add_a(immediate_value);
                 self.pc = self.pc.wrapping_add(2);
             }
-            0x09 => {
-                // ADD HL, BC
-                let hl = self.get_hl();
-                let bc = self.get_bc();
-                let (result, carry) = hl.overflowing_add(bc);
-
-                self.set_hl(result);
-                self.set_flag(Flag::H, (hl & 0x0FFF) + (bc & 0x0FFF) > 0x0FFF);
-                self.set_flag(Flag::C, carry);
-                self.set_flag(Flag::N, false);
-                self.pc = self.pc.wrapping_add(1);
-                trace!("ADD HL, BC");
-            }
-            0x19 => {
-                // ADD HL, DE
-                let hl = self.get_hl();
-                let de = self.get_de();
-                let (result, carry) = hl.overflowing_add(de);
-
-                self.set_hl(result);
-                self.set_flag(Flag::H, (hl & 0x0FFF) + (de & 0x0FFF) > 0x0FFF);
-                self.set_flag(Flag::C, carry);
-                self.set_flag(Flag::N, false);
-                self.pc = self.pc.wrapping_add(1);
-                trace!("ADD HL, DE");
-            }
-            0x29 => {
-                // ADD HL, HL
-                let hl = self.get_hl();
-                let (result, carry) = hl.overflowing_add(hl);
-
-                self.set_hl(result);
-                self.set_flag(Flag::H, (hl & 0x0FFF) + (hl & 0x0FFF) > 0x0FFF);
-                self.set_flag(Flag::C, carry);
-                self.set_flag(Flag::N, false);
-                self.pc = self.pc.wrapping_add(1);
-                trace!("ADD HL, HL");
-            }
-            0x39 => {
-                // ADD HL, SP
-                trace!("ADD HL, SP");
-                let hl = self.get_hl();
-                let result = hl.wrapping_add(self.sp);
-
-                self.set_flag(Flag::N, false);
-                self.set_flag(Flag::H, (hl & 0x0FFF) + (self.sp & 0x0FFF) > 0x0FFF);
-                self.set_flag(Flag::C, result < hl);
-
+            0x09 | 0x19 | 0x29 | 0x39 => {
+                // ADD HL, BC / DE / HL / SP
+                let value = self.get_register_pair_by_index(opcode >> 4);
+                let result = self.add16(self.get_hl(), value);
                 self.set_hl(result);
                 self.pc = self.pc.wrapping_add(1);
+                trace!("ADD HL, {:04X}", value);
             }
             0x8F => {
                 // ADC A, A
@@ -1053,7 +1057,7 @@ impl Z80 {
             0x88 => {
                 // ADC A, B
                 trace!("ADC A, B");
-                self.adc_a(self.a);
+                self.adc_a(self.b);
                 self.pc = self.pc.wrapping_add(1);
             }
             0x89 => {
@@ -1098,8 +1102,7 @@ impl Z80 {
                 // ADC A, n
                 let value = self.read_byte(self.pc.wrapping_add(1));
                 self.pc = self.pc.wrapping_add(2);
-                let result = self.a.wrapping_add(value);
-                self.a = result;
+                self.adc_a(value);
             }
             0x97 => {
                 // SUB A
@@ -1273,12 +1276,7 @@ impl Z80 {
             0xB7 => {
                 // OR A
                 self.pc = self.pc.wrapping_add(1);
-                self.set_flag(Flag::Z, self.a == 0);
-                self.set_flag(Flag::S, self.a & 0x80 != 0);
-                self.set_flag(Flag::H, false);
-                self.set_flag(Flag::P, parity(self.a));
-                self.set_flag(Flag::N, false);
-                self.set_flag(Flag::C, false);
+                self.or_a(self.a);
             }
             0x07 => {
                 // RLCA
@@ -1307,38 +1305,32 @@ impl Z80 {
             0xB0 => {
                 // OR B
                 self.pc = self.pc.wrapping_add(1);
-                let result = self.a | self.b;
-                self.a = result;
+                self.or_a(self.b);
             }
             0xB1 => {
                 // OR C
                 self.pc = self.pc.wrapping_add(1);
-                let result = self.a | self.c;
-                self.a = result;
+                self.or_a(self.c);
             }
             0xB2 => {
                 // OR D
                 self.pc = self.pc.wrapping_add(1);
-                let result = self.a | self.d;
-                self.a = result;
+                self.or_a(self.d);
             }
             0xB3 => {
                 // OR E
                 self.pc = self.pc.wrapping_add(1);
-                let result = self.a | self.e;
-                self.a = result;
+                self.or_a(self.e);
             }
             0xB4 => {
                 // OR H
                 self.pc = self.pc.wrapping_add(1);
-                let result = self.a | self.h;
-                self.a = result;
+                self.or_a(self.h);
             }
             0xB5 => {
                 // OR L
                 self.pc = self.pc.wrapping_add(1);
-                let result = self.a | self.l;
-                self.a = result;
+                self.or_a(self.l);
             }
             0xB6 => {
                 // OR (HL)
@@ -1407,8 +1399,7 @@ impl Z80 {
                 // XOR n
                 let value = self.read_byte(self.pc.wrapping_add(1));
                 self.pc = self.pc.wrapping_add(2);
-                let result = self.a ^ value;
-                self.a = result;
+                self.xor_a(value);
             }
             0x18 => {
                 // JR e
@@ -1419,10 +1410,43 @@ impl Z80 {
             }
             0x76 => {
                 // HALT
-                println!("HALT from 0x{:04X}", self.pc);
+                trace!("HALT from 0x{:04X}", self.pc);
                 self.pc = self.pc.wrapping_add(1);
                 self.halted = true;
             }
+            0x27 => {
+                // DAA
+                trace!("DAA");
+                let a = self.a;
+                let subtract = self.get_flag(Flag::N);
+                let half = self.get_flag(Flag::H);
+                let mut carry = self.get_flag(Flag::C);
+                let mut correction = 0;
+                if half || a & 0x0F > 0x09 {
+                    correction |= 0x06;
+                }
+                if carry || a > 0x99 {
+                    correction |= 0x60;
+                    carry = true;
+                }
+                self.a = match subtract {
+                    true => a.wrapping_sub(correction),
+                    false => a.wrapping_add(correction),
+                };
+
+                self.set_flag(Flag::S, self.a & 0x80 != 0);
+                self.set_flag(Flag::Z, self.a == 0);
+                self.set_flag(
+                    Flag::H,
+                    match subtract {
+                        true => half && a & 0x0F < 0x06,
+                        false => a & 0x0F > 0x09,
+                    },
+                );
+                self.set_flag(Flag::P, parity(self.a));
+                self.set_flag(Flag::C, carry);
+                self.pc = self.pc.wrapping_add(1);
+            }
             0x2F => {
                 // CPL
                 trace!("CPL -> 0. A = 0x{:02X}", self.a);
@@ -1480,105 +1504,12 @@ impl Z80 {
                 self.pc = self.pc.wrapping_add(1);
             }
             0xDD => {
-                trace!("CP (IX+d)");
-                self.pc = self.pc.wrapping_add(1);
-                let opcode = self.read_byte(self.pc);
-                match opcode {
-                    0xBE => {
-                        self.pc = self.pc.wrapping_add(1);
-                        let d = self.read_byte(self.pc) as i8;
-                        self.pc = self.pc.wrapping_add(1);
-                        let value = self.read_byte(self.get_ix_d(d as u8));
-                        self.cp(value);
-                        self.pc = self.pc.wrapping_add(1);
-                    }
-                    0x21 => {
-                        // LD IX, nn
-                        let low_byte = self.read_byte(self.pc);
-                        let high_byte = self.read_byte(self.pc);
-                        self.ix = u16::from_le_bytes([low_byte, high_byte]);
-                        trace!("LD IX, {:04X}", self.ix);
-                        self.pc = self.pc.wrapping_add(3);
-                    }
-                    0xE5 => {
-                        // PUSH IX
-                        self.push(self.iy);
-                        self.pc = self.pc.wrapping_add(1);
-                    }
-                    0xE1 => {
-                        // POP IX
-                        self.ix = self.pop();
-                        self.pc = self.pc.wrapping_add(1);
-                    }
-                    _ => {
-                        panic!("Unknown opcode (CP (IX+d)) 0xDD 0x{:02X}", opcode);
-                    }
-                }
+                trace!("DD prefix");
+                self.ix = self.execute_indexed(self.ix);
             }
             0xFD => {
-                trace!("CP (IY+d)");
-                self.pc = self.pc.wrapping_add(1);
-                let opcode = self.read_byte(self.pc);
-                match opcode {
-                    0xBE => {
-                        // CP (IY+d)
-                        self.pc = self.pc.wrapping_add(1);
-                        let d = self.read_byte(self.pc) as i8;
-                        self.pc = self.pc.wrapping_add(1);
-                        let value = self.read_byte(self.get_iy_d(d as u8));
-                        self.cp(value);
-                        self.pc = self.pc.wrapping_add(1);
-                    }
-                    0x22 => {
-                        // LD (nn), IY
-                        let low_addr = self.read_byte(self.pc);
-                        let high_addr = self.read_byte(self.pc);
-                        let address = u16::from_le_bytes([low_addr, high_addr]);
-                        self.write_word(address, self.iy);
-                        trace!("LD ({:04X}), IY", address);
-                        self.pc = self.pc.wrapping_add(3);
-                    }
-                    0x2A => {
-                        // LD IX, (nn)
-                        let low_addr = self.read_byte(self.pc);
-                        let high_addr = self.read_byte(self.pc);
-                        let address = u16::from_le_bytes([low_addr, high_addr]);
-                        self.ix = self.read_word(address);
-                        trace!("LD IX, {:04X}", self.ix);
-                        self.pc = self.pc.wrapping_add(3);
-                    }
-                    0x2D => {
-                        // DEC IYL
-                        let iyl = self.iy as u8;
-                        let result = iyl.wrapping_sub(1);
-
-                        self.set_flag(Flag::N, true);
-                        self.set_flag(Flag::H, (iyl & 0x0F) == 0x01);
-                        self.set_flag(Flag::Z, result == 0);
-                        self.set_flag(Flag::S, (result & 0x80) != 0);
-                        self.set_flag(Flag::P, iyl == 0x80);
-
-                        self.iy = (self.iy & 0xFF00) | (result as u16);
-                        self.pc = self.pc.wrapping_add(1);
-                    }
-                    0xE5 => {
-                        // PUSH IY
-                        self.push(self.iy);
-                        self.pc = self.pc.wrapping_add(1);
-                    }
-                    0xE1 => {
-                        // POP IY
-                        self.iy = self.pop();
-                        self.pc = self.pc.wrapping_add(1);
-                    }
-                    0xAF => {}
-                    _ => {
-                        error!(
-                            "Unknown opcode at {:04X} (CP (IY+d)) 0xFD 0x{:02X}",
-                            self.pc, opcode
-                        );
-                    }
-                }
+                trace!("FD prefix");
+                self.iy = self.execute_indexed(self.iy);
             }
             0x3F => {
                 // CCF
@@ -1610,10 +1541,7 @@ impl Z80 {
             }
             0xE3 => {
                 // EX (SP), HL
-                let hl = self.get_hl();
-                let value = self.read_word(self.sp);
-
-                self.write_word(self.sp, hl);
+                let value = self.ex_sp(self.get_hl());
                 self.set_hl(value);
 
                 self.pc = self.pc.wrapping_add(1);
@@ -1690,6 +1618,26 @@ impl Z80 {
                     self.pc = self.pc.wrapping_add(3);
                 }
             }
+            0xEC => {
+                // CALL PE, nn
+                let address = self.read_word(self.pc.wrapping_add(1));
+                if self.get_flag(Flag::P) {
+                    self.push(self.pc.wrapping_add(3));
+                    self.pc = address;
+                } else {
+                    self.pc = self.pc.wrapping_add(3);
+                }
+            }
+            0xF4 => {
+                // CALL P, nn
+                let address = self.read_word(self.pc.wrapping_add(1));
+                if !self.get_flag(Flag::S) {
+                    self.push(self.pc.wrapping_add(3));
+                    self.pc = address;
+                } else {
+                    self.pc = self.pc.wrapping_add(3);
+                }
+            }
             0xFC => {
                 trace!("CALL M, {:04X}", self.pc);
                 // CALL M, nn
@@ -1911,6 +1859,11 @@ impl Z80 {
                     self.pc = address;
                 }
             }
+            0xE9 => {
+                // JP (HL)
+                trace!("JP (HL)");
+                self.pc = self.get_hl();
+            }
             0x20 | 0x28 | 0x30 | 0x38 => {
                 trace!(
                     "Flags for JS - Z={} C={}",
@@ -1998,64 +1951,15 @@ impl Z80 {
                 let extended_opcode = self.read_byte(self.pc.wrapping_add(1));
 
                 match extended_opcode {
-                    0x00..=0x1F => {
-                        // RLC r
-                        let reg_index = extended_opcode & 0x07;
-
-                        trace!("RLC {}", reg_index);
-                        let value = self.get_register_by_index(reg_index);
-                        let carry = (value & 0x80) != 0;
-
-                        let result = (value << 1) | (carry as u8);
-                        self.set_register_by_index(reg_index, result);
-
-                        self.set_flag(Flag::S, result & 0x80 != 0);
-                        self.set_flag(Flag::Z, result == 0);
-                        self.set_flag(Flag::H, false);
-                        self.set_flag(Flag::P, result.count_ones() % 2 == 0);
-                        self.set_flag(Flag::N, false);
-                        self.set_flag(Flag::C, carry);
-
-                        self.pc = self.pc.wrapping_add(2);
-                    }
-                    0x28..=0x2F => {
-                        // SRA r
-                        let reg_index = extended_opcode & 0x07;
-
-                        trace!("SRA {}", reg_index);
-                        let value = self.get_register_by_index(reg_index);
-                        let carry = (value & 0x01) != 0;
-
-                        let result = (value >> 1) | (value & 0x80);
-                        self.set_register_by_index(reg_index, result);
-
-                        self.set_flag(Flag::S, result & 0x80 != 0);
-                        self.set_flag(Flag::Z, result == 0);
-                        self.set_flag(Flag::H, false);
-                        self.set_flag(Flag::P, result.count_ones() % 2 == 0);
-                        self.set_flag(Flag::N, false);
-                        self.set_flag(Flag::C, carry);
-
-                        self.pc = self.pc.wrapping_add(2);
-                    }
-                    0x20..=0x3F => {
-                        // SLA r
+                    0x00..=0x3F => {
+                        // RLC, RRC, RL, RR, SLA, SRA, SLL and SRL r
                         let reg_index = extended_opcode & 0x07;
 
-                        trace!("SLA {}", reg_index);
+                        trace!("shift {:02X}", extended_opcode);
                         let value = self.get_register_by_index(reg_index);
-                        let carry = (value & 0x80) != 0;
-
-                        let result = value << 1;
+                        let result = self.shift(extended_opcode >> 3, value);
                         self.set_register_by_index(reg_index, result);
 
-                        self.set_flag(Flag::S, result & 0x80 != 0);
-                        self.set_flag(Flag::Z, result == 0);
-                        self.set_flag(Flag::H, false);
-                        self.set_flag(Flag::P, result.count_ones() % 2 == 0);
-                        self.set_flag(Flag::N, false);
-                        self.set_flag(Flag::C, carry);
-
                         self.pc = self.pc.wrapping_add(2);
                     }
                     0x40..=0x7F => {
@@ -2065,14 +1969,7 @@ impl Z80 {
 
                         trace!("BIT {}, {}", bit, reg_index);
                         let value = self.get_register_by_index(reg_index);
-                        let mask = 1 << bit;
-                        let bit_value = value & mask;
-
-                        self.set_flag(Flag::S, bit_value & 0x80 != 0);
-                        self.set_flag(Flag::Z, bit_value == 0);
-                        self.set_flag(Flag::H, true);
-                        self.set_flag(Flag::P, bit_value == 0); // P/V flag is set to the inverse of the Z flag
-                        self.set_flag(Flag::N, false);
+                        self.bit(bit, value);
 
                         self.pc = self.pc.wrapping_add(2);
                     }
@@ -2157,8 +2054,13 @@ impl Z80 {
                         // re-execute it. One important aspect of this implementation is that it allows to
                         // serve interrupt request during execution of a (long) LDIR instruction.
 
-                        let value = self.read_byte(self.get_hl());
-                        self.write_byte(self.get_de(), value);
+                        // a byte per repeat, through the same path as any
+                        // other access, under a single lock of the bus
+                        {
+                            let mut bus = self.write_bus();
+                            let value = bus.read_byte(self.get_hl());
+                            bus.write_byte(self.get_de(), value);
+                        }
                         self.set_hl(self.get_hl().wrapping_add(1));
                         self.set_de(self.get_de().wrapping_add(1));
                         self.set_bc(self.get_bc().wrapping_sub(1));
@@ -2181,43 +2083,37 @@ impl Z80 {
                             self.pc = self.pc.wrapping_sub(1);
                         }
                     }
-                    0x42 => {
-                        // SBC HL, BC
-                        let hl = self.get_hl();
-                        let bc = self.get_bc();
-                        let carry = if self.get_flag(Flag::C) { 1 } else { 0 };
-
-                        let result = hl.wrapping_sub(bc).wrapping_sub(carry);
+                    0x44 => {
+                        // NEG
+                        trace!("NEG");
+                        self.alu_a(alu::sub(0, self.a, false));
+                        self.pc = self.pc.wrapping_add(1);
+                    }
+                    0x45 => {
+                        // RETN
+                        trace!("RETN");
+                        self.iff1 = self.iff2;
+                        self.pc = self.pop();
+                    }
+                    0x42 | 0x52 | 0x62 | 0x72 => {
+                        // SBC HL, BC / DE / HL / SP
+                        let value = self.get_register_pair_by_index(extended_opcode >> 4);
+                        let carry = self.get_flag(Flag::C);
+                        let (result, flags) = alu::sbc16(self.get_hl(), value, carry);
                         self.set_hl(result);
-
-                        self.set_flag(Flag::S, result & 0x8000 != 0);
-                        self.set_flag(Flag::Z, result == 0);
-                        self.set_flag(Flag::H, (hl & 0xFFF) < (bc & 0xFFF) + carry);
-                        self.set_flag(Flag::P, (hl & 0x7FFF) < (bc & 0x7FFF) + carry);
-                        self.set_flag(Flag::N, true);
-                        self.set_flag(Flag::C, hl < bc + carry);
-
+                        self.set_flags(alu::FLAGS, flags);
                         self.pc = self.pc.wrapping_add(1);
-                        trace!("SBC HL, BC");
+                        trace!("SBC HL, {:04X}", value);
                     }
-                    0x52 => {
-                        // SBC HL, DE
-                        let hl = self.get_hl();
-                        let de = self.get_de();
-                        let carry = if self.get_flag(Flag::C) { 1 } else { 0 };
-
-                        let result = hl.wrapping_sub(de).wrapping_sub(carry);
+                    0x4A | 0x5A | 0x6A | 0x7A => {
+                        // ADC HL, BC / DE / HL / SP
+                        let value = self.get_register_pair_by_index(extended_opcode >> 4);
+                        let carry = self.get_flag(Flag::C);
+                        let (result, flags) = alu::adc16(self.get_hl(), value, carry);
                         self.set_hl(result);
-
-                        self.set_flag(Flag::S, result & 0x8000 != 0);
-                        self.set_flag(Flag::Z, result == 0);
-                        self.set_flag(Flag::H, (hl & 0xFFF) < (de & 0xFFF) + carry);
-                        self.set_flag(Flag::P, (hl & 0x7FFF) < (de & 0x7FFF) + carry);
-                        self.set_flag(Flag::N, true);
-                        self.set_flag(Flag::C, hl < de + carry);
-
+                        self.set_flags(alu::FLAGS, flags);
                         self.pc = self.pc.wrapping_add(1);
-                        trace!("SBC HL, DE");
+                        trace!("ADC HL, {:04X}", value);
                     }
                     0x56 => {
                         // IM 1
@@ -2261,7 +2157,9 @@ impl Z80 {
 
                         self.set_hl(self.get_hl().wrapping_add(1));
                         self.b = self.b.wrapping_sub(1);
-                        self.set_flag(Flag::P, self.b != 0);
+                        self.set_flag(Flag::S, self.b & 0x80 != 0);
+                        self.set_flag(Flag::Z, self.b == 0);
+                        self.set_flag(Flag::N, true);
                         self.pc = self.pc.wrapping_add(1);
                         trace!("OUTI");
                     }
@@ -2302,27 +2200,23 @@ impl Z80 {
                         self.pc = self.pc.wrapping_add(1);
                         trace!("IN (C), E");
                     }
-                    0x53 => {
-                        // LD (nn), DE
+                    0x43 | 0x53 | 0x63 | 0x73 => {
+                        // LD (nn), BC / DE / HL / SP
                         let address = self.read_word(self.pc.wrapping_add(1));
-                        let value = self.get_de();
+                        let value = self.get_register_pair_by_index(extended_opcode >> 4);
                         self.write_word(address, value);
                         self.pc = self.pc.wrapping_add(3);
-                        trace!("LD (nn), DE");
+                        trace!("LD ({:04X}), {:04X}", address, value);
                     }
-                    0x5B => {
-                        // LD DE, (nn)
+                    0x4B | 0x5B | 0x6B | 0x7B => {
+                        // LD BC / DE / HL / SP, (nn)
                         let address = self.read_word(self.pc.wrapping_add(1));
                         let value = self.read_word(address);
-                        self.set_de(value);
+                        self.set_register_pair_by_index(extended_opcode >> 4, value);
                         self.pc = self.pc.wrapping_add(3);
-                        trace!("LD DE, (nn)");
+                        trace!("LD {:04X}, ({:04X})", value, address);
                     }
                     // Add extended opcodes handling here
-                    // 0x4A => self.sbc_hl(RegisterPair::BC),
-                    // 0x5A => self.sbc_hl(RegisterPair::DE),
-                    // 0x6A => self.sbc_hl(RegisterPair::HL),
-                    // 0x7A => self.sbc_hl(RegisterPair::SP),
                     // ... (other opcodes)
                     _ => {
                         self.report_unknown(
@@ -2339,16 +2233,15 @@ impl Z80 {
                 trace!("EI");
                 self.pc = self.pc.wrapping_add(1);
                 self.iff1 = true;
+                self.iff2 = true;
+                self.ei_delay = true;
             }
             // DI
             0xF3 => {
                 trace!("DI");
                 self.pc = self.pc.wrapping_add(1);
                 self.iff1 = false;
-            }
-
-            _ => {
-                self.report_unknown("Unhandled opcode", opcode);
+                self.iff2 = false;
             }
         }
 
@@ -2391,139 +2284,317 @@ impl Z80 {
         panic!("{} at {:04X}: {:02X}", message, self.pc, opcode);
     }
 
-    fn add_a(&mut self, value: u8) {
-        let a = self.a;
-        let result = a.wrapping_add(value);
+    // the instruction after a DD or FD prefix, with `index`, IX or IY, in
+    // place of HL; returns the new value of the index register
+    fn execute_indexed(&mut self, mut index: u16) -> u16 {
+        self.pc = self.pc.wrapping_add(1);
+        let opcode = self.read_byte(self.pc);
+        // (IX+d) or (IY+d), with the displacement right after the opcode
+        let displaced = |cpu: &Self| {
+            let d = cpu.read_byte(cpu.pc.wrapping_add(1)) as i8 as u16;
+            index.wrapping_add(d)
+        };
 
-        self.set_flag(Flag::S, result & 0x80 != 0);
-        self.set_flag(Flag::Z, result == 0);
-        self.set_flag(Flag::H, (a & 0x0F) + (value & 0x0F) > 0x0F);
-        self.set_flag(Flag::P, ((a ^ result) & !(a ^ value)) & 0x80 != 0);
-        self.set_flag(Flag::N, false);
-        self.set_flag(Flag::C, (a as u16) + (value as u16) > 0xFF);
+        match opcode {
+            0xDD | 0xFD | 0xED => {
+                // a prefix followed by another one is ignored, it runs as a
+                // NOP and the next prefix starts a new instruction
+            }
+            0x09 | 0x19 | 0x29 | 0x39 => {
+                // ADD IX, BC / DE / IX / SP
+                let value = match opcode {
+                    0x29 => index,
+                    _ => self.get_register_pair_by_index(opcode >> 4),
+                };
+                index = self.add16(index, value);
+                self.pc = self.pc.wrapping_add(1);
+            }
+            0x21 => {
+                // LD IX, nn
+                index = self.read_word(self.pc.wrapping_add(1));
+                self.pc = self.pc.wrapping_add(3);
+            }
+            0x22 => {
+                // LD (nn), IX
+                let address = self.read_word(self.pc.wrapping_add(1));
+                self.write_word(address, index);
+                self.pc = self.pc.wrapping_add(3);
+            }
+            0x2A => {
+                // LD IX, (nn)
+                let address = self.read_word(self.pc.wrapping_add(1));
+                index = self.read_word(address);
+                self.pc = self.pc.wrapping_add(3);
+            }
+            0x23 => {
+                // INC IX
+                index = index.wrapping_add(1);
+                self.pc = self.pc.wrapping_add(1);
+            }
+            0x2B => {
+                // DEC IX
+                index = index.wrapping_sub(1);
+                self.pc = self.pc.wrapping_add(1);
+            }
+            0x24 | 0x25 | 0x2C | 0x2D => {
+                // INC / DEC IXH / IXL
+                let reg_index = opcode >> 3;
+                let value = self.indexed_register(index, reg_index);
+                let result = match opcode & 0x01 {
+                    0 => self.inc(value),
+                    _ => self.dec(value),
+                };
+                index = self.set_indexed_register(index, reg_index, result);
+                self.pc = self.pc.wrapping_add(1);
+            }
+            0x26 | 0x2E => {
+                // LD IXH / IXL, n
+                let value = self.read_byte(self.pc.wrapping_add(1));
+                index = self.set_indexed_register(index, opcode >> 3, value);
+                self.pc = self.pc.wrapping_add(2);
+            }
+            0x34 | 0x35 => {
+                // INC / DEC (IX+d)
+                let address = displaced(self);
+                let value = self.read_byte(address);
+                let result = match opcode {
+                    0x34 => self.inc(value),
+                    _ => self.dec(value),
+                };
+                self.write_byte(address, result);
+                self.pc = self.pc.wrapping_add(2);
+            }
+            0x36 => {
+                // LD (IX+d), n
+                let address = displaced(self);
+                let value = self.read_byte(self.pc.wrapping_add(2));
+                self.write_byte(address, value);
+                self.pc = self.pc.wrapping_add(3);
+            }
+            0x40..=0x7F if opcode != 0x76 => {
+                // LD r, r' with IXH / IXL for H and L, or with (IX+d) and
+                // the real H and L
+                let target = (opcode >> 3) & 0x07;
+                let source = opcode & 0x07;
+                if source == 6 {
+                    let value = self.read_byte(displaced(self));
+                    self.set_register_by_index(target, value);
+                    self.pc = self.pc.wrapping_add(2);
+                } else if target == 6 {
+                    let value = self.get_register_by_index(source);
+                    self.write_byte(displaced(self), value);
+                    self.pc = self.pc.wrapping_add(2);
+                } else {
+                    let value = self.indexed_register(index, source);
+                    index = self.set_indexed_register(index, target, value);
+                    self.pc = self.pc.wrapping_add(1);
+                }
+            }
+            0x80..=0xBF => {
+                // ADD, ADC, SUB, SBC, AND, XOR, OR and CP with IXH / IXL or
+                // (IX+d)
+                let value = match opcode & 0x07 {
+                    6 => {
+                        let value = self.read_byte(displaced(self));
+                        self.pc = self.pc.wrapping_add(1);
+                        value
+                    }
+                    source => self.indexed_register(index, source),
+                };
+                self.alu(opcode >> 3, value);
+                self.pc = self.pc.wrapping_add(1);
+            }
+            0xCB => {
+                // RLC .. SRL, BIT, RES and SET on (IX+d), the opcode coming
+                // after the displacement; the undocumented ones also copy
+                // the result to a register
+                let address = displaced(self);
+                let extended_opcode = self.read_byte(self.pc.wrapping_add(2));
+                let bit = (extended_opcode >> 3) & 0x07;
+                let value = self.read_byte(address);
+                let result = match extended_opcode & 0xC0 {
+                    0x00 => Some(self.shift(extended_opcode >> 3, value)),
+                    0x40 => {
+                        self.bit(bit, value);
+                        None
+                    }
+                    0x80 => Some(value & !(1 << bit)),
+                    _ => Some(value | (1 << bit)),
+                };
+                if let Some(result) = result {
+                    self.write_byte(address, result);
+                    let reg_index = extended_opcode & 0x07;
+                    if reg_index != 6 {
+                        self.set_register_by_index(reg_index, result);
+                    }
+                }
+                self.pc = self.pc.wrapping_add(3);
+            }
+            0xE1 => {
+                // POP IX
+                index = self.pop();
+                self.pc = self.pc.wrapping_add(1);
+            }
+            0xE3 => {
+                // EX (SP), IX
+                index = self.ex_sp(index);
+                self.pc = self.pc.wrapping_add(1);
+            }
+            0xE5 => {
+                // PUSH IX
+                self.push(index);
+                self.pc = self.pc.wrapping_add(1);
+            }
+            0xE9 => {
+                // JP (IX)
+                self.pc = index;
+            }
+            0xF9 => {
+                // LD SP, IX
+                self.sp = index;
+                self.pc = self.pc.wrapping_add(1);
+            }
+            _ => {
+                // the prefix does nothing to the other instructions, which
+                // run as they would without it
+                self.execute(opcode);
+            }
+        }
+        index
+    }
 
-        self.a = result;
+    // a register by index as the DD and FD instructions see it, with the
+    // halves of the index register for H and L
+    fn indexed_register(&mut self, index: u16, reg_index: u8) -> u8 {
+        match reg_index {
+            4 => (index >> 8) as u8,
+            5 => index as u8,
+            _ => self.get_register_by_index(reg_index),
+        }
     }
 
-    fn adc_a(&mut self, value: u8) {
-        let a = self.a;
-        let carry = if self.get_flag(Flag::C) { 1 } else { 0 };
-        let result = a.wrapping_add(value).wrapping_add(carry);
+    // sets a register as [`Z80::indexed_register`] reads it, returning the
+    // new value of the index register
+    fn set_indexed_register(&mut self, index: u16, reg_index: u8, value: u8) -> u16 {
+        match reg_index {
+            4 => (index & 0x00FF) | (value as u16) << 8,
+            5 => (index & 0xFF00) | value as u16,
+            _ => {
+                self.set_register_by_index(reg_index, value);
+                index
+            }
+        }
+    }
 
+    // ADD, ADC, SUB, SBC, AND, XOR, OR or CP by bits 5-3 of the opcode
+    fn alu(&mut self, operation: u8, value: u8) {
+        match operation & 0x07 {
+            0 => self.add_a(value),
+            1 => self.adc_a(value),
+            2 => self.sub_a(value),
+            3 => self.sbc_a(value),
+            4 => self.and_a(value),
+            5 => self.xor_a(value),
+            6 => self.or_a(value),
+            _ => self.cp(value),
+        }
+    }
+
+    // RLC, RRC, RL, RR, SLA, SRA, SLL or SRL by bits 5-3 of the CB opcode
+    fn shift(&mut self, operation: u8, value: u8) -> u8 {
+        let carry = self.get_flag(Flag::C) as u8;
+        let (result, carry) = match operation & 0x07 {
+            0 => (value.rotate_left(1), value & 0x80 != 0),
+            1 => (value.rotate_right(1), value & 0x01 != 0),
+            2 => (value << 1 | carry, value & 0x80 != 0),
+            3 => (value >> 1 | carry << 7, value & 0x01 != 0),
+            4 => (value << 1, value & 0x80 != 0),
+            5 => (value >> 1 | (value & 0x80), value & 0x01 != 0),
+            6 => (value << 1 | 0x01, value & 0x80 != 0),
+            _ => (value >> 1, value & 0x01 != 0),
+        };
+
+        self.set_flag(Flag::S, result & 0x80 != 0);
         self.set_flag(Flag::Z, result == 0);
+        self.set_flag(Flag::H, false);
+        self.set_flag(Flag::P, parity(result));
         self.set_flag(Flag::N, false);
-        self.set_flag(Flag::H, (a & 0x0F) + (value & 0x0F) + carry > 0x0F);
-        self.set_flag(Flag::C, a > 0xFF - value - carry);
+        self.set_flag(Flag::C, carry);
+        result
+    }
+
+    // BIT b, sets the flags for the bit of the value
+    fn bit(&mut self, bit: u8, value: u8) {
+        let bit_value = value & (1 << bit);
+        self.set_flag(Flag::S, bit_value & 0x80 != 0);
+        self.set_flag(Flag::Z, bit_value == 0);
+        self.set_flag(Flag::H, true);
+        self.set_flag(Flag::P, bit_value == 0); // P/V flag is set to the inverse of the Z flag
+        self.set_flag(Flag::N, false);
+    }
 
+    // A gets the result of an ALU operation
+    fn alu_a(&mut self, (result, flags): alu::Output) {
         self.a = result;
+        self.set_flags(alu::FLAGS, flags);
     }
 
-    fn sub_a(&mut self, value: u8) {
-        let a = self.a;
-        let result = a.wrapping_sub(value);
+    fn add_a(&mut self, value: u8) {
+        self.alu_a(alu::add(self.a, value, false));
+    }
 
-        self.set_flag(Flag::S, result & 0x80 != 0);
-        self.set_flag(Flag::Z, result == 0);
-        self.set_flag(Flag::H, (a & 0x0F) < (value & 0x0F));
-        self.set_flag(Flag::P, ((a ^ value) & (a ^ result)) & 0x80 != 0);
-        self.set_flag(Flag::N, true);
-        self.set_flag(Flag::C, a < value);
+    fn adc_a(&mut self, value: u8) {
+        self.alu_a(alu::add(self.a, value, self.get_flag(Flag::C)));
+    }
 
-        self.a = result;
+    fn sub_a(&mut self, value: u8) {
+        self.alu_a(alu::sub(self.a, value, false));
     }
 
     fn sbc_a(&mut self, value: u8) {
-        let carry = if self.get_flag(Flag::C) { 1 } else { 0 };
-        let a = i16::from(self.a);
-        let d = i16::from(value);
-        let wans = a - d - carry;
-        let ans = (wans & 0xff) as u8;
-
-        self.set_flag(Flag::S, ans & 0x80 != 0);
-        self.set_flag(Flag::Z, ans == 0);
-        self.set_flag(Flag::C, wans & 0x100 != 0);
-        self.set_flag(Flag::P, (self.a ^ value) & (self.a ^ ans) & 0x80 != 0);
-        self.set_flag(
-            Flag::H,
-            (self.a & 0x0F)
-                .wrapping_sub(value & 0x0F)
-                .wrapping_sub(carry as u8)
-                & 0x10
-                != 0,
-        );
-        self.set_flag(Flag::N, true);
-
-        self.a = ans;
+        self.alu_a(alu::sub(self.a, value, self.get_flag(Flag::C)));
     }
 
     fn and_a(&mut self, value: u8) {
-        self.a &= value;
-
-        self.set_flag(Flag::Z, self.a == 0);
-        self.set_flag(Flag::S, self.a & 0x80 != 0);
-        self.set_flag(Flag::H, true);
-        self.set_flag(Flag::P, parity(self.a));
-        self.set_flag(Flag::N, false);
-        self.set_flag(Flag::C, false);
+        self.alu_a(alu::and(self.a, value));
     }
 
     fn or_a(&mut self, value: u8) {
-        self.a |= value;
-
-        self.set_flag(Flag::Z, self.a == 0);
-        self.set_flag(Flag::S, self.a & 0x80 != 0);
-        self.set_flag(Flag::H, false);
-        self.set_flag(Flag::P, parity(self.a));
-        self.set_flag(Flag::N, false);
-        self.set_flag(Flag::C, false);
+        self.alu_a(alu::or(self.a, value));
     }
 
     fn xor_a(&mut self, value: u8) {
-        self.a ^= value;
-        self.set_flag(Flag::Z, self.a == 0);
-        self.set_flag(Flag::S, self.a & 0x80 != 0);
-        self.set_flag(Flag::H, false);
-        self.set_flag(Flag::P, parity(self.a));
-        self.set_flag(Flag::N, false);
-        self.set_flag(Flag::C, false);
+        self.alu_a(alu::xor(self.a, value));
     }
 
     fn cp(&mut self, value: u8) {
-        let result = self.a.wrapping_sub(value);
-        let overflow = (self.a ^ value) & (self.a ^ result) & 0x80 != 0;
-
-        self.set_flag(Flag::S, result & 0x80 != 0);
-        self.set_flag(Flag::Z, result == 0);
-        self.set_flag(Flag::H, (self.a & 0xF) < (value & 0xF));
-        // self.set_flag(Flag::P, overflow_sub(self.a, value, result));
-        self.set_flag(Flag::P, overflow);
-        self.set_flag(Flag::N, true);
-        self.set_flag(Flag::C, self.a < value);
-    }
-
-    // Helper function to set flags for INC
-    fn set_inc_flags(&mut self, value: u8) {
-        self.set_flag(Flag::S, value & 0x80 != 0);
-        self.set_flag(Flag::Z, value == 0);
-        self.set_flag(Flag::H, (value & 0x0F) == 0x00);
-        self.set_flag(Flag::P, value == 0x80);
-        self.set_flag(Flag::N, false);
+        self.set_flags(alu::FLAGS, alu::cp(self.a, value));
     }
 
-    fn dec(&mut self, value: u8) -> u8 {
-        let result = value.wrapping_sub(1);
-        let carry = (value & 0x0F) < (result & 0x0F);
-        let overflow = (value ^ result) & (value ^ 1) & 0x80 != 0;
+    // ADD HL, IX or IY and a register pair
+    fn add16(&mut self, a: u16, value: u16) -> u16 {
+        let (result, flags) = alu::add16(a, value);
+        self.set_flags(alu::ADD16_FLAGS, flags);
+        result
+    }
 
-        self.set_flag(Flag::Z, result == 0);
-        self.set_flag(Flag::S, result & 0x80 != 0);
-        self.set_flag(Flag::H, carry);
-        self.set_flag(Flag::P, overflow);
-        self.set_flag(Flag::N, true);
+    fn inc(&mut self, value: u8) -> u8 {
+        let (result, flags) = alu::inc(value);
+        self.set_flags(alu::INC_DEC_FLAGS, flags);
+        result
+    }
 
+    fn dec(&mut self, value: u8) -> u8 {
+        let (result, flags) = alu::dec(value);
+        self.set_flags(alu::INC_DEC_FLAGS, flags);
         result
     }
 
+    // sets the flags of the mask to those given
+    fn set_flags(&mut self, mask: u8, flags: u8) {
+        self.f = (self.f & !mask) | flags;
+    }
+
     pub fn set_flag(&mut self, flag: Flag, value: bool) {
         if value {
             self.f |= flag as u8;
@@ -2541,14 +2612,14 @@ impl Z80 {
     }
 
     // Function to obtain a read lock on the bus
-    fn read_bus(&self) -> std::sync::RwLockReadGuard<Bus> {
+    fn read_bus(&self) -> std::sync::RwLockReadGuard<'_, Bus> {
         self.bus
             .read()
             .expect("Couldn't obtain a read lock on the bus.")
     }
 
     // Function to obtain a write lock on the bus
-    fn write_bus(&self) -> std::sync::RwLockWriteGuard<Bus> {
+    fn write_bus(&self) -> std::sync::RwLockWriteGuard<'_, Bus> {
         self.bus
             .write()
             .expect("Couldn't obtain a write lock on the bus.")
@@ -2594,6 +2665,25 @@ impl Z80 {
         }
     }
 
+    // BC, DE, HL and SP, by bits 5-4 of the opcode
+    fn get_register_pair_by_index(&self, index: u8) -> u16 {
+        match index & 0x03 {
+            0 => self.get_bc(),
+            1 => self.get_de(),
+            2 => self.get_hl(),
+            _ => self.sp,
+        }
+    }
+
+    fn set_register_pair_by_index(&mut self, index: u8, value: u16) {
+        match index & 0x03 {
+            0 => self.set_bc(value),
+            1 => self.set_de(value),
+            2 => self.set_hl(value),
+            _ => self.sp = value,
+        }
+    }
+
     fn set_register_by_index(&mut self, index: u8, value: u8) {
         // info!(
         //     "set_register_by_index | Val = {} | PC = #{:04X}",
@@ -2736,9 +2826,7 @@ impl Z80 {
     fn inc_hl(&mut self) {
         let hl = self.get_hl();
         let value = self.read_byte(hl);
-        let result = value.wrapping_add(1);
-
-        self.set_inc_flags(result);
+        let result = self.inc(value);
 
         // info!("INC HL | PC = #{:04X}", self.pc);
         self.write_byte(hl, result);
@@ -2747,13 +2835,22 @@ impl Z80 {
     fn dec_hl(&mut self) {
         let hl = self.get_hl();
         let value = self.read_byte(hl);
-        let result = value.wrapping_sub(1);
+        let result = self.dec(value);
 
         // info!("DEC HL | PC = #{:04X}", self.pc);
         self.write_byte(hl, result);
     }
 
     // Stack operations
+
+    // EX (SP): swaps the word on top of the stack with the value, which is
+    // returned
+    fn ex_sp(&mut self, value: u16) -> u16 {
+        let top = self.read_word(self.sp);
+        self.write_word(self.sp, value);
+        top
+    }
+
     fn push(&mut self, value: u16) {
         trace!("[->SP] 0x{:04X} into sp=0x{:04X}", value, self.sp);
         self.sp = self.sp.wrapping_sub(2);
@@ -2854,6 +2951,7 @@ fn parity(value: u8) -> bool {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::slot::{RamSlot, SlotType};
 
     #[test]
     fn test_sbc_set_c_flag_1() {
@@ -2924,4 +3022,312 @@ mod tests {
         assert!(cpu.get_flag(Flag::N));
         assert!(!cpu.get_flag(Flag::C));
     }
+
+    fn cpu_with_program(program: &[u8]) -> Z80 {
+        let bus = Bus::new(&[
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+        ]);
+        let mut cpu = Z80::new(Arc::new(RwLock::new(bus)));
+        cpu.bus.write().unwrap().ppi.primary_slot_config = 0xFF;
+        for (i, byte) in program.iter().enumerate() {
+            cpu.write_byte(0xC000 + i as u16, *byte);
+        }
+        cpu.pc = 0xC000;
+        cpu.sp = 0xF000;
+        cpu
+    }
+
+    #[test]
+    fn test_ei_delay() {
+        // EI / NOP / NOP
+        let mut cpu = cpu_with_program(&[0xFB, 0x00, 0x00]);
+        cpu.request_interrupt();
+        cpu.execute_cycle();
+        assert_eq!(cpu.pc, 0xC001);
+
+        // the instruction after EI runs before the interrupt is accepted
+        cpu.execute_cycle();
+        assert_eq!(cpu.pc, 0xC002);
+        cpu.execute_cycle();
+        assert_eq!(cpu.pc, 0x0038);
+        assert_eq!(cpu.read_word(cpu.sp), 0xC002);
+    }
+
+    #[test]
+    fn test_halt_wakes_on_interrupt() {
+        // EI / HALT
+        let mut cpu = cpu_with_program(&[0xFB, 0x76]);
+        cpu.execute_cycle();
+        cpu.execute_cycle();
+        assert!(cpu.halted);
+        cpu.execute_cycle();
+        assert_eq!(cpu.pc, 0xC002);
+
+        // returning after the HALT
+        cpu.request_interrupt();
+        cpu.execute_cycle();
+        assert!(!cpu.halted);
+        assert_eq!(cpu.pc, 0x0038);
+        assert_eq!(cpu.read_word(cpu.sp), 0xC002);
+    }
+
+    #[test]
+    fn test_indexed() {
+        #[rustfmt::skip]
+        let mut cpu = cpu_with_program(&[
+            0xDD, 0x21, 0x00, 0xD0, // LD IX, D000
+            0xDD, 0x36, 0x02, 0x05, // LD (IX+2), 5
+            0xDD, 0x86, 0x02,       // ADD A, (IX+2)
+            0xDD, 0x34, 0xFF,       // INC (IX-1)
+            0xDD, 0xCB, 0x02, 0x0E, // RRC (IX+2)
+            0xDD, 0xE5,             // PUSH IX
+            0xFD, 0xE1,             // POP IY
+            0xFD, 0xE9,             // JP (IY)
+        ]);
+        cpu.a = 0x01;
+        cpu.write_byte(0xCFFF, 0x10);
+
+        cpu.execute_cycle();
+        assert_eq!(cpu.ix, 0xD000);
+        cpu.execute_cycle();
+        assert_eq!(cpu.read_byte(0xD002), 0x05);
+        cpu.execute_cycle();
+        assert_eq!(cpu.a, 0x06);
+        cpu.execute_cycle();
+        assert_eq!(cpu.read_byte(0xCFFF), 0x11);
+        cpu.execute_cycle();
+        assert_eq!(cpu.read_byte(0xD002), 0x82);
+        assert!(cpu.get_flag(Flag::C));
+        cpu.execute_cycle();
+        cpu.execute_cycle();
+        assert_eq!(cpu.iy, 0xD000);
+        assert_eq!(cpu.sp, 0xF000);
+        cpu.execute_cycle();
+        assert_eq!(cpu.pc, 0xD000);
+    }
+
+    #[test]
+    fn test_shifts() {
+        // RRC A / RL A / SRL A
+        let mut cpu = cpu_with_program(&[0xCB, 0x0F, 0xCB, 0x17, 0xCB, 0x3F]);
+        cpu.a = 0x81;
+        cpu.execute_cycle();
+        assert_eq!(cpu.a, 0xC0);
+        assert!(cpu.get_flag(Flag::C));
+        cpu.execute_cycle();
+        assert_eq!(cpu.a, 0x81);
+        assert!(cpu.get_flag(Flag::C));
+        cpu.execute_cycle();
+        assert_eq!(cpu.a, 0x40);
+        assert!(cpu.get_flag(Flag::C));
+    }
+
+    #[test]
+    fn test_daa() {
+        // ADD A, n / DAA / SUB n / DAA / DAA
+        let mut cpu = cpu_with_program(&[0xC6, 0x19, 0x27, 0xD6, 0x02, 0x27, 0x27]);
+        cpu.a = 0x29;
+        cpu.execute_cycle();
+        cpu.execute_cycle();
+        assert_eq!(cpu.a, 0x48);
+        assert!(!cpu.get_flag(Flag::C));
+        cpu.execute_cycle();
+        cpu.execute_cycle();
+        assert_eq!(cpu.a, 0x46);
+        assert!(cpu.get_flag(Flag::N));
+
+        // not BCD after a subtraction: corrected from the nibbles alone
+        cpu.a = 0x9A;
+        cpu.set_flag(Flag::H, false);
+        cpu.set_flag(Flag::C, false);
+        cpu.execute_cycle();
+        assert_eq!(cpu.a, 0x34);
+        assert!(cpu.get_flag(Flag::C));
+        assert!(!cpu.get_flag(Flag::H));
+    }
+
+    #[test]
+    fn test_jumps_and_calls() {
+        #[rustfmt::skip]
+        let mut cpu = cpu_with_program(&[
+            0x21, 0x07, 0xC0, // LD HL, C007
+            0x6E,             // LD L, (HL)
+            0xE9,             // JP (HL)
+            0x00, 0x00,
+            0x0A,             // the low byte of the JP (HL) target
+            0x00, 0x00,
+            0xEC, 0x00, 0xD0, // CALL PE, D000
+            0xF4, 0x00, 0xD1, // CALL P, D100
+        ]);
+        cpu.execute_cycle();
+        cpu.execute_cycle();
+        assert_eq!(cpu.get_hl(), 0xC00A);
+        cpu.execute_cycle();
+        assert_eq!(cpu.pc, 0xC00A);
+
+        // parity odd, not taken
+        cpu.set_flag(Flag::P, false);
+        cpu.execute_cycle();
+        assert_eq!(cpu.pc, 0xC00D);
+
+        // sign clear, taken
+        cpu.set_flag(Flag::S, false);
+        cpu.execute_cycle();
+        assert_eq!(cpu.pc, 0xD100);
+        assert_eq!(cpu.read_word(cpu.sp), 0xC010);
+    }
+
+    #[test]
+    fn test_outi_loop() {
+        // OUTI / JP NZ, C000
+        let mut cpu = cpu_with_program(&[0xED, 0xA3, 0xC2, 0x00, 0xC0]);
+        cpu.b = 2;
+        cpu.c = 0x2E;
+        cpu.set_hl(0xD000);
+        cpu.execute_cycle();
+        assert_eq!(cpu.b, 1);
+        assert!(!cpu.get_flag(Flag::Z));
+        assert!(cpu.get_flag(Flag::N));
+        cpu.execute_cycle();
+        assert_eq!(cpu.pc, 0xC000);
+
+        // Z set when B reaches 0, ending the loop
+        cpu.execute_cycle();
+        assert_eq!(cpu.b, 0);
+        assert!(cpu.get_flag(Flag::Z));
+        cpu.execute_cycle();
+        assert_eq!(cpu.pc, 0xC005);
+        assert_eq!(cpu.get_hl(), 0xD002);
+    }
+
+    #[test]
+    fn test_prefix_chain() {
+        // DD FD E5: the DD is ignored and runs as a step of its own
+        let mut cpu = cpu_with_program(&[0xDD, 0xFD, 0xE5]);
+        cpu.iy = 0x1234;
+        cpu.execute_cycle();
+        assert_eq!(cpu.pc, 0xC001);
+        assert_eq!(cpu.sp, 0xF000);
+
+        cpu.execute_cycle();
+        assert_eq!(cpu.pc, 0xC003);
+        assert_eq!(cpu.read_word(cpu.sp), 0x1234);
+    }
+
+    #[test]
+    fn test_alu_flags() {
+        // ADC A,n / OR B / XOR n / ADC A,B / DEC (HL), which all set flags
+        let mut cpu = cpu_with_program(&[0xCE, 0x01, 0xB0, 0xEE, 0x80, 0x88, 0x35]);
+        cpu.a = 0x7F;
+        cpu.f = Flag::C as u8;
+        cpu.execute_cycle();
+        assert_eq!(cpu.a, 0x81);
+        assert_eq!(cpu.f, Flag::S as u8 | Flag::H as u8 | Flag::P as u8);
+
+        cpu.b = 0x00;
+        cpu.execute_cycle();
+        assert_eq!(cpu.f, Flag::S as u8 | Flag::P as u8);
+
+        cpu.execute_cycle();
+        assert_eq!(cpu.a, 0x01);
+        assert_eq!(cpu.f, 0);
+
+        cpu.b = 0xFF;
+        cpu.execute_cycle();
+        assert_eq!(cpu.a, 0x00);
+        assert_eq!(cpu.f, Flag::Z as u8 | Flag::H as u8 | Flag::C as u8);
+
+        // the carry is kept
+        cpu.set_hl(0xD000);
+        cpu.write_byte(0xD000, 0x01);
+        cpu.execute_cycle();
+        assert_eq!(cpu.read_byte(0xD000), 0x00);
+        assert_eq!(cpu.f, Flag::Z as u8 | Flag::N as u8 | Flag::C as u8);
+    }
+
+    #[test]
+    fn test_ld_register_pair_indirect() {
+        // LD (0xD000), BC / LD SP, (0xD000) / LD (0xD002), HL / LD BC, (0xD002)
+        let mut cpu = cpu_with_program(&[
+            0xED, 0x43, 0x00, 0xD0, 0xED, 0x7B, 0x00, 0xD0, 0xED, 0x63, 0x02, 0xD0, 0xED, 0x4B,
+            0x02, 0xD0,
+        ]);
+        cpu.set_bc(0x1234);
+        cpu.set_hl(0x5678);
+        for _ in 0..4 {
+            cpu.execute_cycle();
+        }
+        assert_eq!(cpu.read_word(0xD000), 0x1234);
+        assert_eq!(cpu.sp, 0x1234);
+        assert_eq!(cpu.read_word(0xD002), 0x5678);
+        assert_eq!(cpu.get_bc(), 0x5678);
+    }
+
+    #[test]
+    fn test_neg() {
+        let mut cpu = cpu_with_program(&[0xED, 0x44, 0xED, 0x44]);
+        cpu.a = 0x01;
+        cpu.f = 0;
+        cpu.execute_cycle();
+        assert_eq!(cpu.a, 0xFF);
+        assert_eq!(
+            cpu.f,
+            Flag::S as u8 | Flag::H as u8 | Flag::N as u8 | Flag::C as u8
+        );
+
+        // 0x80 is its own negation, overflowing
+        cpu.a = 0x80;
+        cpu.execute_cycle();
+        assert_eq!(cpu.a, 0x80);
+        assert_eq!(
+            cpu.f,
+            Flag::S as u8 | Flag::P as u8 | Flag::N as u8 | Flag::C as u8
+        );
+    }
+
+    #[test]
+    fn test_16_bit_arithmetic() {
+        // ADD HL,DE / ADC HL,BC / SBC HL,HL / ADD IY,IY / EX (SP),IX
+        let mut cpu = cpu_with_program(&[0x19, 0xED, 0x4A, 0xED, 0x62, 0xFD, 0x29, 0xDD, 0xE3]);
+        let szp = Flag::S as u8 | Flag::Z as u8 | Flag::P as u8;
+
+        // ADD only touches H, N and C
+        cpu.f = szp | Flag::N as u8;
+        cpu.set_hl(0x0FFF);
+        cpu.set_de(0xF001);
+        cpu.execute_cycle();
+        assert_eq!(cpu.get_hl(), 0x0000);
+        assert_eq!(cpu.f, szp | Flag::H as u8 | Flag::C as u8);
+
+        // ADC sets S, Z and P from the result: 0x0000 + 0x7FFF + 1 overflows
+        cpu.set_bc(0x7FFF);
+        cpu.execute_cycle();
+        assert_eq!(cpu.get_hl(), 0x8000);
+        assert_eq!(cpu.f, Flag::S as u8 | Flag::H as u8 | Flag::P as u8);
+
+        cpu.f |= Flag::C as u8;
+        cpu.execute_cycle();
+        assert_eq!(cpu.get_hl(), 0xFFFF);
+        assert_eq!(
+            cpu.f,
+            Flag::S as u8 | Flag::H as u8 | Flag::N as u8 | Flag::C as u8
+        );
+
+        cpu.f = szp;
+        cpu.iy = 0x8000;
+        cpu.execute_cycle();
+        assert_eq!(cpu.iy, 0x0000);
+        assert_eq!(cpu.f, szp | Flag::C as u8);
+        assert_eq!(cpu.pc, 0xC007);
+
+        cpu.ix = 0x1234;
+        cpu.write_word(cpu.sp, 0xABCD);
+        cpu.execute_cycle();
+        assert_eq!(cpu.ix, 0xABCD);
+        assert_eq!(cpu.read_word(cpu.sp), 0x1234);
+        assert_eq!(cpu.pc, 0xC009);
+    }
 }
@@ -0,0 +1,280 @@
+use std::fmt::Write;
+
+use serde::{Deserialize, Serialize};
+
+use crate::vdp::{frames_until, FrameOrigin};
+
+/// Emulator-only debug device on ports 0x2E and 0x2F, compatible with the
+/// debugdevice of openMSX, for test ROMs and homebrew to print to the host.
+///
+/// Port 0x2E sets the mode, bits 5-4 selecting it:
+///
+/// - `00` off
+/// - `01` single byte: each byte written to 0x2F is printed on its own line,
+///   in hex (bit 0), binary (bit 1), decimal (bit 2) and ASCII (bit 3)
+/// - `10` multi byte: the bytes are printed one after the other, in hex
+///   (bits 1-0 = `00`), binary (`01`), decimal (`10`) or ASCII (`11`)
+/// - `11` breaks into the debugger, keeping the mode; this one is not in
+///   openMSX
+///
+/// Writing the mode also ends the current line, unless bit 6 is set.
+///
+/// Programs can also time themselves, which openMSX doesn't offer: reading
+/// port 0x2E latches the T-states run since the machine was reset, as of the
+/// start of the `IN`, and the frames drawn. Each read of port 0x2F then
+/// returns the next byte of the T-states (4 bytes) and the frames (2 bytes),
+/// least significant first, and 0xFF once they're all read, as openMSX does.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct DebugDevice {
+    mode: Mode,
+    // format bits of the mode
+    parameter: u8,
+    /// printed text the host didn't take yet
+    output: String,
+    break_requested: bool,
+    /// whether frames are counted at 50 Hz, like the VDP of the machine
+    pub pal: bool,
+    /// where the frames at that frequency started, like on the VDP
+    #[serde(default)]
+    pub origin: FrameOrigin,
+    // T-state clock of the bus, as of the last access
+    clock: u64,
+    // latched counters not read yet, the next byte last
+    latch: Vec<u8>,
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+enum Mode {
+    #[default]
+    Off,
+    SingleByte,
+    MultiByte,
+}
+
+impl DebugDevice {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn reset(&mut self) {
+        self.mode = Mode::Off;
+        self.parameter = 0;
+        self.latch.clear();
+    }
+
+    /// Catches up with the T-state clock of the bus.
+    pub fn sync(&mut self, clock: u64) {
+        self.clock = clock;
+    }
+
+    pub fn read(&mut self, port: u8) -> u8 {
+        if port & 0x01 == 0 {
+            let t_states = (self.clock as u32).to_le_bytes();
+            let frames = (frames_until(self.clock, self.pal, self.origin) as u16).to_le_bytes();
+            self.latch = t_states.into_iter().chain(frames).rev().collect();
+            return 0xFF;
+        }
+        self.latch.pop().unwrap_or(0xFF)
+    }
+
+    pub fn write(&mut self, port: u8, value: u8) {
+        if port & 0x01 == 0 {
+            self.set_mode(value);
+            return;
+        }
+
+        match self.mode {
+            Mode::Off => {}
+            Mode::SingleByte => {
+                if self.parameter & 0x01 != 0 {
+                    let _ = write!(self.output, "{:02X}h ", value);
+                }
+                if self.parameter & 0x02 != 0 {
+                    let _ = write!(self.output, "{:08b}b ", value);
+                }
+                if self.parameter & 0x04 != 0 {
+                    let _ = write!(self.output, "{} ", value);
+                }
+                if self.parameter & 0x08 != 0 {
+                    let _ = write!(self.output, "'{}' ", printable(value));
+                }
+                self.output.truncate(self.output.trim_end().len());
+                self.output.push('\n');
+            }
+            Mode::MultiByte => {
+                let _ = match self.parameter {
+                    0 => write!(self.output, "{:02X}h ", value),
+                    1 => write!(self.output, "{:08b}b ", value),
+                    2 => write!(self.output, "{} ", value),
+                    _ => write!(self.output, "{}", printable(value)),
+                };
+            }
+        }
+    }
+
+    fn set_mode(&mut self, value: u8) {
+        match (value >> 4) & 0x03 {
+            0 => self.mode = Mode::Off,
+            1 => {
+                self.mode = Mode::SingleByte;
+                self.parameter = value & 0x0F;
+            }
+            2 => {
+                self.mode = Mode::MultiByte;
+                self.parameter = value & 0x03;
+            }
+            _ => self.break_requested = true,
+        }
+
+        if value & 0x40 == 0 && !self.output.is_empty() && !self.output.ends_with('\n') {
+            self.output.push('\n');
+        }
+    }
+
+    /// Takes the text printed since the last call.
+    pub fn take_output(&mut self) -> String {
+        std::mem::take(&mut self.output)
+    }
+
+    /// Whether the program asked to break, without taking the request.
+    pub fn break_requested(&self) -> bool {
+        self.break_requested
+    }
+
+    /// Whether the program asked to break since the last call.
+    pub fn take_break(&mut self) -> bool {
+        std::mem::take(&mut self.break_requested)
+    }
+}
+
+// control characters print as dots, like openMSX does
+fn printable(value: u8) -> char {
+    match value {
+        b' '..=b'~' => value as char,
+        b'\n' | b'\r' | b'\t' => value as char,
+        _ => '.',
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_multi_byte_ascii() {
+        let mut device = DebugDevice::new();
+        device.write(0x2E, 0x23);
+        for byte in b"HELLO" {
+            device.write(0x2F, *byte);
+        }
+        device.write(0x2E, 0x00);
+        device.write(0x2F, b'!');
+
+        assert_eq!(device.take_output(), "HELLO\n");
+        assert_eq!(device.take_output(), "");
+    }
+
+    #[test]
+    fn test_single_byte() {
+        let mut device = DebugDevice::new();
+        device.write(0x2E, 0x1F);
+        device.write(0x2F, 0x41);
+        device.write(0x2E, 0x11);
+        device.write(0x2F, 0x07);
+
+        assert_eq!(device.take_output(), "41h 01000001b 65 'A'\n07h\n");
+    }
+
+    #[test]
+    fn test_break() {
+        let mut device = DebugDevice::new();
+        device.write(0x2E, 0x20);
+        device.write(0x2F, 0x10);
+        assert!(!device.take_break());
+
+        // the mode is kept and the hex line ended
+        device.write(0x2E, 0x30);
+        device.write(0x2F, 0x20);
+        assert!(device.take_break());
+        assert!(!device.take_break());
+        assert_eq!(device.take_output(), "10h \n20h ");
+    }
+
+    #[test]
+    fn test_counters() {
+        use crate::vdp_timing::{DISPLAY_T_STATES, FRAME_T_STATES};
+
+        let mut device = DebugDevice::new();
+        assert_eq!(device.read(0x2F), 0xFF);
+
+        // two frames and a VBlank in
+        device.sync(2 * FRAME_T_STATES + DISPLAY_T_STATES);
+        assert_eq!(device.read(0x2E), 0xFF);
+        device.sync(0);
+        let bytes: Vec<u8> = (0..7).map(|_| device.read(0x2F)).collect();
+        let t_states = (2 * FRAME_T_STATES + DISPLAY_T_STATES) as u32;
+        assert_eq!(bytes[..4], t_states.to_le_bytes());
+        assert_eq!(bytes[4..], [3, 0, 0xFF]);
+    }
+
+    #[test]
+    fn test_program_timing() {
+        use crate::{
+            slot::{RamSlot, SlotType},
+            Msx,
+        };
+
+        // nop; nop; nop; in a,(#2e); in a,(#2f)
+        let mut msx = Msx::new(&[
+            SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Empty,
+        ]);
+        for (i, byte) in [0x00, 0x00, 0x00, 0xDB, 0x2E, 0xDB, 0x2F]
+            .iter()
+            .enumerate()
+        {
+            msx.cpu.write_byte(i as u16, *byte);
+        }
+        msx.enable_debug_device();
+        for _ in 0..5 {
+            msx.step();
+        }
+        // the NOPs took 5 T-states each
+        assert_eq!(msx.cpu.a, 15);
+    }
+
+    #[test]
+    fn test_program_output() {
+        use crate::{
+            slot::{RomSlot, SlotType},
+            Msx,
+        };
+
+        // ld a,#23; out (#2e),a; ld a,'O'; out (#2f),a; ld a,'K'; out (#2f),a
+        let mut program = vec![0; 0x4000];
+        program[..12].copy_from_slice(&[
+            0x3E, 0x23, 0xD3, 0x2E, 0x3E, b'O', 0xD3, 0x2F, 0x3E, b'K', 0xD3, 0x2F,
+        ]);
+        let slots = [
+            SlotType::Rom(RomSlot::new(&program, 0x0000, 0x4000)),
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Empty,
+        ];
+
+        let mut msx = Msx::new(&slots);
+        for _ in 0..6 {
+            msx.step();
+        }
+        assert_eq!(msx.debug_device_output(), (String::new(), false));
+
+        let mut msx = Msx::new(&slots);
+        msx.enable_debug_device();
+        for _ in 0..6 {
+            msx.step();
+        }
+        assert_eq!(msx.debug_device_output(), ("OK".to_string(), false));
+    }
+}
@@ -0,0 +1,176 @@
+use std::{any::Any, fmt};
+
+use crate::{debug_device::DebugDevice, ppi::Ppi, serial::I8251, sound::AY38910, vdp::TMS9918};
+
+/// A device on the I/O bus. Besides the built-in chips, devices can be
+/// attached to free ports with [`Bus::attach_device`](crate::bus::Bus::attach_device).
+pub trait Device: DeviceClone + fmt::Debug + Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Reads one of the ports the device is attached to.
+    fn io_read(&mut self, port: u8) -> u8;
+
+    /// Writes one of the ports the device is attached to.
+    fn io_write(&mut self, port: u8, value: u8);
+
+    /// Catches the device up with the T-state `clock` of the bus. It's only
+    /// called before its ports are accessed and once its next event is due,
+    /// so the device works out what happened since the last call.
+    fn sync(&mut self, _clock: u64) {}
+
+    /// T-state at which the device does something on its own, like raising
+    /// an interrupt, if any.
+    fn next_event(&self) -> Option<u64> {
+        None
+    }
+
+    /// Whether the device holds the interrupt line.
+    fn irq(&self) -> bool {
+        false
+    }
+
+    /// Whether the device pulsed the NMI line since it was last asked. The
+    /// Z80 takes the NMI on the edge, so each pulse is reported once.
+    fn take_nmi(&mut self) -> bool {
+        false
+    }
+
+    fn reset(&mut self) {}
+
+    /// Called on a power cycle, after the reset, to lose what a battery
+    /// keeps through resets, e.g. the time and memory of a clock chip.
+    fn power_off(&mut self) {}
+}
+
+/// Lets boxed devices be cloned along with the bus, and the host reach the
+/// one it attached.
+pub trait DeviceClone {
+    fn clone_box(&self) -> Box<dyn Device>;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: Device + Clone + 'static> DeviceClone for T {
+    fn clone_box(&self) -> Box<dyn Device> {
+        Box::new(self.clone())
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl Clone for Box<dyn Device> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+impl Device for TMS9918 {
+    fn name(&self) -> &str {
+        "VDP"
+    }
+
+    fn io_read(&mut self, port: u8) -> u8 {
+        self.read(port)
+    }
+
+    fn io_write(&mut self, port: u8, value: u8) {
+        self.write(port, value)
+    }
+
+    fn sync(&mut self, clock: u64) {
+        TMS9918::sync(self, clock)
+    }
+
+    fn next_event(&self) -> Option<u64> {
+        Some(self.next_vblank())
+    }
+
+    fn irq(&self) -> bool {
+        TMS9918::irq(self)
+    }
+
+    fn reset(&mut self) {
+        TMS9918::reset(self)
+    }
+}
+
+impl Device for AY38910 {
+    fn name(&self) -> &str {
+        "PSG"
+    }
+
+    fn io_read(&mut self, port: u8) -> u8 {
+        self.read(port)
+    }
+
+    fn io_write(&mut self, port: u8, value: u8) {
+        self.write(port, value)
+    }
+
+    fn sync(&mut self, clock: u64) {
+        AY38910::sync(self, clock)
+    }
+
+    fn reset(&mut self) {
+        AY38910::reset(self)
+    }
+}
+
+impl Device for Ppi {
+    fn name(&self) -> &str {
+        "PPI"
+    }
+
+    fn io_read(&mut self, port: u8) -> u8 {
+        self.read(port)
+    }
+
+    fn io_write(&mut self, port: u8, value: u8) {
+        self.write(port, value)
+    }
+
+    fn reset(&mut self) {
+        Ppi::reset(self)
+    }
+}
+
+impl Device for DebugDevice {
+    fn name(&self) -> &str {
+        "Debug device"
+    }
+
+    fn io_read(&mut self, port: u8) -> u8 {
+        self.read(port)
+    }
+
+    fn io_write(&mut self, port: u8, value: u8) {
+        self.write(port, value)
+    }
+
+    fn sync(&mut self, clock: u64) {
+        DebugDevice::sync(self, clock)
+    }
+
+    fn reset(&mut self) {
+        DebugDevice::reset(self)
+    }
+}
+
+impl Device for I8251 {
+    fn name(&self) -> &str {
+        "Serial"
+    }
+
+    fn io_read(&mut self, port: u8) -> u8 {
+        self.read(port)
+    }
+
+    fn io_write(&mut self, port: u8, value: u8) {
+        self.write(port, value)
+    }
+
+    fn reset(&mut self) {
+        I8251::reset(self)
+    }
+}
@@ -0,0 +1,736 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+};
+
+use crate::instruction::MAX_INSTRUCTION_LEN;
+
+const R: [&str; 8] = ["B", "C", "D", "E", "H", "L", "(HL)", "A"];
+const RP: [&str; 4] = ["BC", "DE", "HL", "SP"];
+const RP2: [&str; 4] = ["BC", "DE", "HL", "AF"];
+const CC: [&str; 8] = ["NZ", "Z", "NC", "C", "PO", "PE", "P", "M"];
+const ALU: [&str; 8] = [
+    "ADD A, ", "ADC A, ", "SUB ", "SBC A, ", "AND ", "XOR ", "OR ", "CP ",
+];
+const ROT: [&str; 8] = ["RLC", "RRC", "RL", "RR", "SLA", "SRA", "SLL", "SRL"];
+const BLOCK: [[&str; 4]; 4] = [
+    ["LDI", "CPI", "INI", "OUTI"],
+    ["LDD", "CPD", "IND", "OUTD"],
+    ["LDIR", "CPIR", "INIR", "OTIR"],
+    ["LDDR", "CPDR", "INDR", "OTDR"],
+];
+
+/// Data bytes per `DB` line.
+const DB_PER_LINE: usize = 8;
+
+/// How the execution continues after an instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    /// continues with the next instruction
+    Next,
+    /// JP nn or JR e, doesn't continue
+    Jump(u16),
+    /// conditional JP, JR or DJNZ
+    Branch(u16),
+    /// CALL or RST, continues once the subroutine returns
+    Call(u16),
+    /// RET, RETI, RETN or JP (HL), where it goes isn't known
+    Return,
+}
+
+/// A decoded instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Decoded {
+    pub len: u8,
+    pub mnemonic: String,
+    pub flow: Flow,
+    /// false for undocumented encodings assemblers can't produce
+    pub valid: bool,
+}
+
+// decodes the opcodes following an optional DD/FD prefix
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    pc: u16,
+    index: Option<&'static str>,
+    index_used: bool,
+    // the instruction has an (IX+d) operand, so H and L aren't replaced
+    memory: bool,
+}
+
+impl<'a> Decoder<'a> {
+    fn displacement(&self) -> i8 {
+        self.bytes[1] as i8
+    }
+
+    // offset of the immediate operand from the opcode
+    fn immediate_offset(&self) -> usize {
+        if self.memory && self.index.is_some() {
+            2
+        } else {
+            1
+        }
+    }
+
+    fn n(&self) -> String {
+        format!("#{:02X}", self.bytes[self.immediate_offset()])
+    }
+
+    fn nn_value(&self) -> u16 {
+        u16::from_le_bytes([self.bytes[1], self.bytes[2]])
+    }
+
+    fn nn(&self) -> String {
+        format!("#{:04X}", self.nn_value())
+    }
+
+    fn r(&mut self, i: u8) -> String {
+        match (i, self.index) {
+            (6, Some(index)) => {
+                self.index_used = true;
+                format!("({}{:+})", index, self.displacement())
+            }
+            (4 | 5, Some(index)) if !self.memory => {
+                self.index_used = true;
+                format!("{}{}", index, if i == 4 { "H" } else { "L" })
+            }
+            _ => R[i as usize].to_string(),
+        }
+    }
+
+    fn hl(&mut self) -> &'static str {
+        match self.index {
+            Some(index) => {
+                self.index_used = true;
+                index
+            }
+            None => "HL",
+        }
+    }
+
+    fn rp(&mut self, p: u8) -> &'static str {
+        if p == 2 {
+            self.hl()
+        } else {
+            RP[p as usize]
+        }
+    }
+
+    fn rp2(&mut self, p: u8) -> &'static str {
+        if p == 2 {
+            self.hl()
+        } else {
+            RP2[p as usize]
+        }
+    }
+
+    // target of a relative jump, `len` being the whole instruction length
+    fn relative(&self, len: u8) -> u16 {
+        self.pc
+            .wrapping_add(len as u16)
+            .wrapping_add(self.bytes[1] as i8 as u16)
+    }
+
+    // decodes the opcode, returning the length after the prefix
+    fn decode(&mut self, prefix_len: u8) -> (u8, String, Flow) {
+        let opcode = self.bytes[0];
+        let (x, y, z) = (opcode >> 6, (opcode >> 3) & 7, opcode & 7);
+        let (p, q) = (y >> 1, y & 1);
+
+        self.memory = match x {
+            0 => (4..=6).contains(&z) && y == 6,
+            1 => (y == 6 || z == 6) && opcode != 0x76,
+            2 => z == 6,
+            _ => false,
+        };
+        let extra = if self.memory && self.index.is_some() {
+            1
+        } else {
+            0
+        };
+
+        let (len, mnemonic, flow) = match (x, z) {
+            (0, 0) => match y {
+                0 => (1, "NOP".to_string(), Flow::Next),
+                1 => (1, "EX AF, AF'".to_string(), Flow::Next),
+                2 => {
+                    let target = self.relative(prefix_len + 2);
+                    (2, format!("DJNZ #{:04X}", target), Flow::Branch(target))
+                }
+                3 => {
+                    let target = self.relative(prefix_len + 2);
+                    (2, format!("JR #{:04X}", target), Flow::Jump(target))
+                }
+                _ => {
+                    let target = self.relative(prefix_len + 2);
+                    let mnemonic = format!("JR {}, #{:04X}", CC[y as usize - 4], target);
+                    (2, mnemonic, Flow::Branch(target))
+                }
+            },
+            (0, 1) if q == 0 => (3, format!("LD {}, {}", self.rp(p), self.nn()), Flow::Next),
+            (0, 1) => {
+                let hl = self.hl();
+                (1, format!("ADD {}, {}", hl, self.rp(p)), Flow::Next)
+            }
+            (0, 2) => {
+                let mnemonic = match (p, q) {
+                    (0, 0) => "LD (BC), A".to_string(),
+                    (1, 0) => "LD (DE), A".to_string(),
+                    (2, 0) => format!("LD ({}), {}", self.nn(), self.hl()),
+                    (3, 0) => format!("LD ({}), A", self.nn()),
+                    (0, _) => "LD A, (BC)".to_string(),
+                    (1, _) => "LD A, (DE)".to_string(),
+                    (2, _) => format!("LD {}, ({})", self.hl(), self.nn()),
+                    _ => format!("LD A, ({})", self.nn()),
+                };
+                (if p >= 2 { 3 } else { 1 }, mnemonic, Flow::Next)
+            }
+            (0, 3) => {
+                let op = if q == 0 { "INC" } else { "DEC" };
+                (1, format!("{} {}", op, self.rp(p)), Flow::Next)
+            }
+            (0, 4) => (1, format!("INC {}", self.r(y)), Flow::Next),
+            (0, 5) => (1, format!("DEC {}", self.r(y)), Flow::Next),
+            (0, 6) => (2, format!("LD {}, {}", self.r(y), self.n()), Flow::Next),
+            (0, _) => {
+                let mnemonic = ["RLCA", "RRCA", "RLA", "RRA", "DAA", "CPL", "SCF", "CCF"];
+                (1, mnemonic[y as usize].to_string(), Flow::Next)
+            }
+            (1, _) if opcode == 0x76 => (1, "HALT".to_string(), Flow::Next),
+            (1, _) => (1, format!("LD {}, {}", self.r(y), self.r(z)), Flow::Next),
+            (2, _) => (1, format!("{}{}", ALU[y as usize], self.r(z)), Flow::Next),
+            (_, 0) => (1, format!("RET {}", CC[y as usize]), Flow::Next),
+            (_, 1) if q == 0 => (1, format!("POP {}", self.rp2(p)), Flow::Next),
+            (_, 1) => match p {
+                0 => (1, "RET".to_string(), Flow::Return),
+                1 => (1, "EXX".to_string(), Flow::Next),
+                2 => (1, format!("JP ({})", self.hl()), Flow::Return),
+                _ => (1, format!("LD SP, {}", self.hl()), Flow::Next),
+            },
+            (_, 2) => {
+                let target = self.nn_value();
+                let mnemonic = format!("JP {}, #{:04X}", CC[y as usize], target);
+                (3, mnemonic, Flow::Branch(target))
+            }
+            (_, 3) => match y {
+                0 => {
+                    let target = self.nn_value();
+                    (3, format!("JP #{:04X}", target), Flow::Jump(target))
+                }
+                2 => (2, format!("OUT ({}), A", self.n()), Flow::Next),
+                3 => (2, format!("IN A, ({})", self.n()), Flow::Next),
+                4 => (1, format!("EX (SP), {}", self.hl()), Flow::Next),
+                5 => (1, "EX DE, HL".to_string(), Flow::Next),
+                6 => (1, "DI".to_string(), Flow::Next),
+                // CB prefixes are decoded before getting here
+                _ => (1, "EI".to_string(), Flow::Next),
+            },
+            (_, 4) => {
+                let target = self.nn_value();
+                let mnemonic = format!("CALL {}, #{:04X}", CC[y as usize], target);
+                (3, mnemonic, Flow::Call(target))
+            }
+            (_, 5) if q == 0 => (1, format!("PUSH {}", self.rp2(p)), Flow::Next),
+            (_, 5) => {
+                let target = self.nn_value();
+                (3, format!("CALL #{:04X}", target), Flow::Call(target))
+            }
+            (_, 6) => (2, format!("{}{}", ALU[y as usize], self.n()), Flow::Next),
+            _ => {
+                let target = y as u16 * 8;
+                (1, format!("RST #{:02X}", target), Flow::Call(target))
+            }
+        };
+
+        (len + extra, mnemonic, flow)
+    }
+}
+
+fn cb(opcode: u8, operand: &str) -> String {
+    let (x, y) = (opcode >> 6, (opcode >> 3) & 7);
+    match x {
+        0 => format!("{} {}", ROT[y as usize], operand),
+        1 => format!("BIT {}, {}", y, operand),
+        2 => format!("RES {}, {}", y, operand),
+        _ => format!("SET {}, {}", y, operand),
+    }
+}
+
+fn ed(bytes: &[u8]) -> Decoded {
+    let opcode = bytes[1];
+    let (x, y, z) = (opcode >> 6, (opcode >> 3) & 7, opcode & 7);
+    let (p, q) = (y >> 1, y & 1);
+    let nn = u16::from_le_bytes([bytes[2], bytes[3]]);
+
+    let (len, mnemonic, flow) = match (x, z) {
+        (1, 0) if y != 6 => (2, format!("IN {}, (C)", R[y as usize]), Flow::Next),
+        (1, 1) if y != 6 => (2, format!("OUT (C), {}", R[y as usize]), Flow::Next),
+        (1, 2) => {
+            let op = if q == 0 { "SBC" } else { "ADC" };
+            (2, format!("{} HL, {}", op, RP[p as usize]), Flow::Next)
+        }
+        (1, 3) if q == 0 => (
+            4,
+            format!("LD (#{:04X}), {}", nn, RP[p as usize]),
+            Flow::Next,
+        ),
+        (1, 3) => (
+            4,
+            format!("LD {}, (#{:04X})", RP[p as usize], nn),
+            Flow::Next,
+        ),
+        (1, 4) if y == 0 => (2, "NEG".to_string(), Flow::Next),
+        (1, 5) if y == 0 => (2, "RETN".to_string(), Flow::Return),
+        (1, 5) if y == 1 => (2, "RETI".to_string(), Flow::Return),
+        (1, 6) if y == 0 => (2, "IM 0".to_string(), Flow::Next),
+        (1, 6) if y == 2 => (2, "IM 1".to_string(), Flow::Next),
+        (1, 6) if y == 3 => (2, "IM 2".to_string(), Flow::Next),
+        (1, 7) if y < 6 => {
+            let mnemonic = ["LD I, A", "LD R, A", "LD A, I", "LD A, R", "RRD", "RLD"];
+            (2, mnemonic[y as usize].to_string(), Flow::Next)
+        }
+        (2, 0..=3) if y >= 4 => (2, BLOCK[y as usize - 4][z as usize].to_string(), Flow::Next),
+        _ => {
+            return Decoded {
+                len: 2,
+                mnemonic: format!("DB #ED, #{:02X}", opcode),
+                flow: Flow::Next,
+                valid: false,
+            }
+        }
+    };
+
+    Decoded {
+        len,
+        mnemonic,
+        flow,
+        valid: true,
+    }
+}
+
+/// Decodes the instruction in `bytes`, located at `pc`. Undocumented
+/// encodings are decoded as well, but flagged as not valid.
+pub fn decode(bytes: [u8; MAX_INSTRUCTION_LEN], pc: u16) -> Decoded {
+    let index = match bytes[0] {
+        0xCB => {
+            return Decoded {
+                len: 2,
+                mnemonic: cb(bytes[1], R[(bytes[1] & 7) as usize]),
+                flow: Flow::Next,
+                // SLL
+                valid: bytes[1] & 0xF8 != 0x30,
+            };
+        }
+        0xED => return ed(&bytes),
+        0xDD => Some("IX"),
+        0xFD => Some("IY"),
+        _ => None,
+    };
+
+    let Some(index) = index else {
+        let mut decoder = Decoder {
+            bytes: &bytes,
+            pc,
+            index: None,
+            index_used: false,
+            memory: false,
+        };
+        let (len, mnemonic, flow) = decoder.decode(0);
+        return Decoded {
+            len,
+            mnemonic,
+            flow,
+            valid: true,
+        };
+    };
+
+    match bytes[1] {
+        0xCB => {
+            // DD CB d op, only the (IX+d) operand is documented
+            let operand = format!("({}{:+})", index, bytes[2] as i8);
+            let opcode = bytes[3];
+            Decoded {
+                len: 4,
+                mnemonic: cb(opcode, &operand),
+                flow: Flow::Next,
+                valid: opcode & 7 == 6 && opcode & 0xF8 != 0x30,
+            }
+        }
+        // another prefix follows, this one is ignored
+        0xDD | 0xED | 0xFD => Decoded {
+            len: 1,
+            mnemonic: format!("DB #{:02X}", bytes[0]),
+            flow: Flow::Next,
+            valid: false,
+        },
+        _ => {
+            let mut decoder = Decoder {
+                bytes: &bytes[1..],
+                pc,
+                index: Some(index),
+                index_used: false,
+                memory: false,
+            };
+            let (len, mnemonic, flow) = decoder.decode(1);
+            Decoded {
+                len: len + 1,
+                mnemonic,
+                flow,
+                // prefixes on instructions without HL don't change them
+                valid: decoder.index_used,
+            }
+        }
+    }
+}
+
+/// Entry points of a cartridge header at `start`, the INIT, STATEMENT and
+/// DEVICE addresses that aren't zero.
+pub fn cartridge_entries(memory: &[u8], start: u16) -> Vec<u16> {
+    let byte = |offset: u16| {
+        memory
+            .get(start.wrapping_add(offset) as usize)
+            .copied()
+            .unwrap_or(0xFF)
+    };
+    if byte(0) != b'A' || byte(1) != b'B' {
+        return Vec::new();
+    }
+
+    [2, 4, 6]
+        .iter()
+        .map(|offset| u16::from_le_bytes([byte(*offset), byte(offset + 1)]))
+        .filter(|address| *address != 0)
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum XrefKind {
+    Jump,
+    Branch,
+    Call,
+}
+
+/// Static disassembly of a memory range, following the execution flow from
+/// the entry points. Bytes that aren't reached are kept as data.
+#[derive(Debug, Clone)]
+pub struct Disassembly {
+    start: u16,
+    end: u16,
+    bytes: Vec<u8>,
+    // decoded instructions, by address
+    code: BTreeMap<u16, Decoded>,
+    // referenced address and where it is referenced from
+    xrefs: BTreeMap<u16, Vec<(u16, XrefKind)>>,
+}
+
+impl Disassembly {
+    /// Disassembles `start..=end` of the 64K `memory`. When no entry point
+    /// is given, the code is assumed to start at `start`, or at the
+    /// cartridge entry points if there is a cartridge header.
+    pub fn new(memory: &[u8], start: u16, end: u16, entries: &[u16]) -> Self {
+        let bytes = (start..=end)
+            .map(|address| memory.get(address as usize).copied().unwrap_or(0xFF))
+            .collect();
+        let mut disassembly = Self {
+            start,
+            end,
+            bytes,
+            code: BTreeMap::new(),
+            xrefs: BTreeMap::new(),
+        };
+
+        let mut pending: Vec<u16> = if !entries.is_empty() {
+            entries.to_vec()
+        } else {
+            let header = cartridge_entries(memory, start);
+            if header.is_empty() {
+                vec![start]
+            } else {
+                header
+            }
+        };
+        pending.reverse();
+
+        let mut covered = BTreeSet::new();
+        while let Some(mut pc) = pending.pop() {
+            loop {
+                if !disassembly.contains(pc) || covered.contains(&pc) {
+                    break;
+                }
+                let decoded = decode(disassembly.fetch(pc), pc);
+                let len = decoded.len as u16;
+                // would overlap another instruction or run past the end
+                if (1..len).any(|i| covered.contains(&pc.wrapping_add(i)))
+                    || !disassembly.contains(pc.wrapping_add(len - 1))
+                    || pc.checked_add(len - 1).is_none()
+                {
+                    break;
+                }
+                covered.extend((0..len).map(|i| pc + i));
+
+                let flow = decoded.flow;
+                disassembly.code.insert(pc, decoded);
+
+                let (target, kind) = match flow {
+                    Flow::Jump(target) => (Some(target), XrefKind::Jump),
+                    Flow::Branch(target) => (Some(target), XrefKind::Branch),
+                    Flow::Call(target) => (Some(target), XrefKind::Call),
+                    Flow::Next | Flow::Return => (None, XrefKind::Jump),
+                };
+                if let Some(target) = target {
+                    if disassembly.contains(target) {
+                        disassembly
+                            .xrefs
+                            .entry(target)
+                            .or_default()
+                            .push((pc, kind));
+                        pending.push(target);
+                    }
+                }
+
+                match pc.checked_add(len) {
+                    Some(next) if !matches!(flow, Flow::Jump(_) | Flow::Return) => pc = next,
+                    _ => break,
+                }
+            }
+        }
+
+        disassembly
+    }
+
+    fn contains(&self, address: u16) -> bool {
+        (self.start..=self.end).contains(&address)
+    }
+
+    fn byte(&self, address: u16) -> u8 {
+        self.bytes[(address - self.start) as usize]
+    }
+
+    fn fetch(&self, pc: u16) -> [u8; MAX_INSTRUCTION_LEN] {
+        let mut bytes = [0; MAX_INSTRUCTION_LEN];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            let address = pc.wrapping_add(i as u16);
+            *byte = if self.contains(address) {
+                self.byte(address)
+            } else {
+                0xFF
+            };
+        }
+        bytes
+    }
+
+    /// Number of bytes decoded as code.
+    pub fn code_len(&self) -> usize {
+        self.code.values().map(|decoded| decoded.len as usize).sum()
+    }
+
+    /// Label of a referenced address that starts an instruction.
+    pub fn label(&self, address: u16) -> Option<String> {
+        let xrefs = self.xrefs.get(&address)?;
+        if !self.code.contains_key(&address) {
+            return None;
+        }
+        let prefix = if xrefs.iter().any(|(_, kind)| *kind == XrefKind::Call) {
+            "sub"
+        } else {
+            "loc"
+        };
+        Some(format!("{}_{:04X}", prefix, address))
+    }
+
+    fn mnemonic(&self, decoded: &Decoded) -> String {
+        let target = match decoded.flow {
+            Flow::Jump(target) | Flow::Branch(target) | Flow::Call(target) => target,
+            Flow::Next | Flow::Return => return decoded.mnemonic.clone(),
+        };
+        match self.label(target) {
+            Some(label) => decoded
+                .mnemonic
+                .replace(&format!("#{:04X}", target), &label)
+                .replace(&format!("#{:02X}", target), &label),
+            None => decoded.mnemonic.clone(),
+        }
+    }
+
+    fn data_line(&self, output: &mut String, address: u16, len: usize) {
+        let bytes: Vec<u8> = (0..len).map(|i| self.byte(address + i as u16)).collect();
+        let values = bytes
+            .iter()
+            .map(|byte| format!("#{:02X}", byte))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let text: String = bytes
+            .iter()
+            .map(|byte| {
+                if byte.is_ascii_graphic() || *byte == b' ' {
+                    *byte as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        line(output, &format!("DB {}", values), address, &text);
+    }
+
+    /// Renders the disassembly as assembler source, with labels for the
+    /// jump and call targets and the places they are referenced from.
+    pub fn to_source(&self) -> String {
+        let mut output = String::new();
+        writeln!(
+            output,
+            "; Disassembly of #{:04X}-#{:04X}, {} bytes of code",
+            self.start,
+            self.end,
+            self.code_len()
+        )
+        .unwrap();
+        writeln!(output).unwrap();
+        writeln!(output, "{:8}ORG #{:04X}", "", self.start).unwrap();
+        writeln!(output).unwrap();
+
+        let mut address = self.start as u32;
+        while address <= self.end as u32 {
+            let pc = address as u16;
+
+            let Some(decoded) = self.code.get(&pc) else {
+                // data runs until the next instruction
+                let mut len = 0;
+                while len < DB_PER_LINE
+                    && address + (len as u32) <= self.end as u32
+                    && !self.code.contains_key(&(pc + len as u16))
+                {
+                    len += 1;
+                }
+                self.data_line(&mut output, pc, len);
+                address += len as u32;
+                continue;
+            };
+
+            if let Some(label) = self.label(pc) {
+                if pc != self.start && !output.ends_with("\n\n") {
+                    writeln!(output).unwrap();
+                }
+                let xrefs = self.xrefs[&pc]
+                    .iter()
+                    .map(|(from, kind)| {
+                        let kind = match kind {
+                            XrefKind::Jump => "j",
+                            XrefKind::Branch => "b",
+                            XrefKind::Call => "c",
+                        };
+                        format!("#{:04X}({})", from, kind)
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                writeln!(output, "{:<32}; XREF: {}", format!("{}:", label), xrefs).unwrap();
+            }
+
+            let bytes = (0..decoded.len as u16)
+                .map(|i| format!("{:02X}", self.byte(pc + i)))
+                .collect::<Vec<_>>()
+                .join(" ");
+            if decoded.valid {
+                line(&mut output, &self.mnemonic(decoded), pc, &bytes);
+            } else {
+                // undocumented, kept as bytes so it assembles the same
+                let values = (0..decoded.len as u16)
+                    .map(|i| format!("#{:02X}", self.byte(pc + i)))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let comment = format!("{}  {}", bytes, decoded.mnemonic);
+                line(&mut output, &format!("DB {}", values), pc, &comment);
+            }
+
+            if matches!(decoded.flow, Flow::Jump(_) | Flow::Return) {
+                writeln!(output).unwrap();
+            }
+            address += decoded.len as u32;
+        }
+
+        output
+    }
+}
+
+fn line(output: &mut String, statement: &str, address: u16, comment: &str) {
+    writeln!(
+        output,
+        "{:8}{:<24}; {:04X}  {}",
+        "", statement, address, comment
+    )
+    .unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_bytes(bytes: &[u8]) -> Decoded {
+        let mut buffer = [0; MAX_INSTRUCTION_LEN];
+        buffer[..bytes.len()].copy_from_slice(bytes);
+        decode(buffer, 0x4000)
+    }
+
+    #[test]
+    fn test_decode() {
+        let cases: &[(&[u8], &str, u8)] = &[
+            (&[0x00], "NOP", 1),
+            (&[0x21, 0x34, 0x12], "LD HL, #1234", 3),
+            (&[0x3A, 0x00, 0xC0], "LD A, (#C000)", 3),
+            (&[0x18, 0xFE], "JR #4000", 2),
+            (&[0x20, 0x02], "JR NZ, #4004", 2),
+            (&[0xCB, 0x7E], "BIT 7, (HL)", 2),
+            (&[0xDD, 0x7E, 0x05], "LD A, (IX+5)", 3),
+            (&[0xFD, 0x36, 0xFE, 0x10], "LD (IY-2), #10", 4),
+            (&[0xDD, 0x66, 0x01], "LD H, (IX+1)", 3),
+            (&[0xDD, 0x26, 0x01], "LD IXH, #01", 3),
+            (&[0xDD, 0xCB, 0x03, 0xC6], "SET 0, (IX+3)", 4),
+            (&[0xED, 0x53, 0x00, 0xF0], "LD (#F000), DE", 4),
+            (&[0xED, 0xB0], "LDIR", 2),
+            (&[0xD3, 0xA8], "OUT (#A8), A", 2),
+            (&[0xFF], "RST #38", 1),
+            (&[0xFD, 0xE9], "JP (IY)", 2),
+        ];
+        for (bytes, mnemonic, len) in cases {
+            let decoded = decode_bytes(bytes);
+            assert_eq!(&decoded.mnemonic, mnemonic);
+            assert_eq!(decoded.len, *len, "{}", mnemonic);
+            assert!(decoded.valid, "{}", mnemonic);
+        }
+
+        assert_eq!(decode_bytes(&[0xC3, 0x10, 0x40]).flow, Flow::Jump(0x4010));
+        assert_eq!(decode_bytes(&[0xC9]).flow, Flow::Return);
+        assert!(!decode_bytes(&[0xDD, 0x00]).valid);
+        assert!(!decode_bytes(&[0xED, 0x00]).valid);
+    }
+
+    #[test]
+    fn test_flow_analysis() {
+        let mut memory = vec![0xFF; 0x10000];
+        let program = [
+            0x41, 0x42, 0x10, 0x40, 0x00, 0x00, 0x00, 0x00, // header, INIT = #4010
+            0x48, 0x49, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // data
+            0xCD, 0x18, 0x40, // #4010 CALL #4018
+            0x28, 0xFB, // #4013 JR Z, #4010
+            0xC3, 0x10, 0x40, // #4015 JP #4010
+            0x3E, 0x01, // #4018 LD A, #01
+            0xC9, // #401A RET
+            0x48, 0x49, // data
+        ];
+        memory[0x4000..0x4000 + program.len()].copy_from_slice(&program);
+
+        let end = 0x4000 + program.len() as u16 - 1;
+        let disassembly = Disassembly::new(&memory, 0x4000, end, &[]);
+        assert_eq!(disassembly.code_len(), 11);
+        assert_eq!(disassembly.label(0x4018), Some("sub_4018".to_string()));
+        assert_eq!(disassembly.label(0x4010), Some("loc_4010".to_string()));
+        assert_eq!(disassembly.label(0x4013), None);
+
+        let source = disassembly.to_source();
+        assert!(source.contains("ORG #4000"));
+        assert!(source.contains("CALL sub_4018"));
+        assert!(source.contains("JR Z, loc_4010"));
+        assert!(source.contains("; XREF: #4013(b), #4015(j)"));
+        assert!(source.contains("DB #41, #42, #10, #40, #00, #00, #00, #00"));
+        assert!(source.contains("DB #48, #49 "));
+    }
+}
@@ -0,0 +1,159 @@
+//! Running a command at the MSX-DOS prompt, for headless runs of DOS tools.
+//!
+//! The command is typed with the [`Autotyper`](crate::autotype::Autotyper)
+//! once the prompt (`A>`) is under the cursor, and what it prints is read
+//! back from the text screen when the prompt shows up again.
+
+// BIOS cursor position, counting from 1
+pub const CSRY: u16 = 0xF3DC;
+pub const CSRX: u16 = 0xF3DD;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// DOS is still booting
+    Booting,
+    /// the command was queued but the cursor hasn't left the prompt yet
+    Typing,
+    /// the command is running
+    Running,
+}
+
+/// What the host has to do after a [`DosCommand::step`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DosStep {
+    Wait,
+    /// type these keys, in the [`Autotyper`](crate::autotype::Autotyper)
+    /// syntax
+    Type(String),
+    /// the command is done, having printed these lines
+    Finished(Vec<String>),
+}
+
+#[derive(Debug, Clone)]
+pub struct DosCommand {
+    command: String,
+    state: State,
+}
+
+impl DosCommand {
+    pub fn new(command: &str) -> Self {
+        Self {
+            command: command.to_string(),
+            state: State::Booting,
+        }
+    }
+
+    pub fn command(&self) -> &str {
+        &self.command
+    }
+
+    /// Checks the text screen, given as its rows, with the cursor at the
+    /// 1-based `(column, row)`, once per frame.
+    pub fn step(&mut self, screen: &[String], cursor: (u8, u8)) -> DosStep {
+        let at_prompt = prompt_row(screen, cursor).is_some();
+        match self.state {
+            State::Booting if at_prompt => {
+                self.state = State::Typing;
+                DosStep::Type(keys(&self.command))
+            }
+            State::Typing if !at_prompt => {
+                self.state = State::Running;
+                DosStep::Wait
+            }
+            State::Running if at_prompt => {
+                let row = prompt_row(screen, cursor).unwrap_or_default();
+                DosStep::Finished(self.output(&screen[..row]))
+            }
+            _ => DosStep::Wait,
+        }
+    }
+
+    // the lines after the one the command was typed on, or all of them if
+    // it scrolled off the screen
+    fn output(&self, above: &[String]) -> Vec<String> {
+        let start = above
+            .iter()
+            .rposition(|line| {
+                is_prompt(line.get(..2).unwrap_or_default())
+                    && self.command.starts_with(line[2..].trim_end())
+            })
+            .map_or(0, |row| row + 1);
+        above[start..].to_vec()
+    }
+}
+
+/// Whether the text is a DOS prompt, a drive letter and `>`.
+pub fn is_prompt(text: &str) -> bool {
+    matches!(text.as_bytes(), [b'A'..=b'H', b'>'])
+}
+
+// index of the cursor row if there's only a prompt before the cursor
+fn prompt_row(screen: &[String], (column, row): (u8, u8)) -> Option<usize> {
+    let row = (row as usize).checked_sub(1)?;
+    let before = screen.get(row)?.get(..(column as usize).checked_sub(1)?)?;
+    is_prompt(before).then_some(row)
+}
+
+// the keys typing the command and ENTER, with the characters the autotyper
+// treats as special escaped
+fn keys(command: &str) -> String {
+    let mut keys = command.replace('\\', "\\\\").replace('{', "\\{");
+    keys.push('\n');
+    keys
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn screen(rows: &[&str]) -> Vec<String> {
+        rows.iter().map(|row| row.to_string()).collect()
+    }
+
+    #[test]
+    fn test_prompt() {
+        assert!(is_prompt("A>"));
+        assert!(!is_prompt("Ok"));
+        assert!(!is_prompt("A>DIR"));
+
+        let rows = screen(&["MSX-DOS", "A>"]);
+        assert_eq!(prompt_row(&rows, (3, 2)), Some(1));
+        assert_eq!(prompt_row(&rows, (1, 2)), None);
+        assert_eq!(prompt_row(&rows, (3, 1)), None);
+        assert_eq!(prompt_row(&rows, (3, 30)), None);
+    }
+
+    #[test]
+    fn test_command() {
+        let mut dos = DosCommand::new("TEST {1}");
+        assert_eq!(dos.step(&screen(&["MSX-DOS"]), (8, 1)), DosStep::Wait);
+
+        // typed once at the prompt
+        let booted = screen(&["MSX-DOS", "A>"]);
+        assert_eq!(
+            dos.step(&booted, (3, 2)),
+            DosStep::Type("TEST \\{1}\n".to_string())
+        );
+        assert_eq!(dos.step(&booted, (3, 2)), DosStep::Wait);
+
+        let running = screen(&["MSX-DOS", "A>TEST {1}", ""]);
+        assert_eq!(dos.step(&running, (1, 3)), DosStep::Wait);
+
+        let done = screen(&["MSX-DOS", "A>TEST {1}", "OK", "2 TESTS", "A>"]);
+        assert_eq!(
+            dos.step(&done, (3, 5)),
+            DosStep::Finished(vec!["OK".to_string(), "2 TESTS".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_scrolled_output() {
+        let mut dos = DosCommand::new("DIR");
+        dos.step(&screen(&["A>"]), (3, 1));
+        dos.step(&screen(&["A>DIR"]), (6, 1));
+        assert_eq!(
+            dos.step(&screen(&["FILE2", "FILE3", "B>"]), (3, 3)),
+            DosStep::Finished(vec!["FILE2".to_string(), "FILE3".to_string()])
+        );
+    }
+}
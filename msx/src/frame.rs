@@ -0,0 +1,83 @@
+use crate::palette::Palette;
+
+pub const SCREEN_WIDTH: usize = 256;
+pub const SCREEN_HEIGHT: usize = 192;
+
+/// Converts pixels with VDP color codes to RGBA through a lookup table from
+/// [`Palette::rgba_lut`], writing 4 bytes per pixel.
+pub fn indexed_to_rgba(lut: &[[u8; 4]; 256], indexed: &[u8], rgba: &mut [u8]) {
+    for (pixel, out) in indexed.iter().zip(rgba.chunks_exact_mut(4)) {
+        out.copy_from_slice(&lut[*pixel as usize]);
+    }
+}
+
+/// RGBA frame ready to be copied to a canvas or texture as is.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameBuffer {
+    pub rgba: Vec<u8>,
+    palette: Palette,
+    lut: Box<[[u8; 4]; 256]>,
+    // the last frame, converted again when the palette changes
+    indexed: Vec<u8>,
+}
+
+impl Default for FrameBuffer {
+    fn default() -> Self {
+        Self::new(Palette::default())
+    }
+}
+
+impl FrameBuffer {
+    pub fn new(palette: Palette) -> Self {
+        let lut = Box::new(palette.rgba_lut());
+        Self {
+            rgba: lut[0].repeat(SCREEN_WIDTH * SCREEN_HEIGHT),
+            palette,
+            lut,
+            indexed: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+        }
+    }
+
+    /// Replaces the frame with a screen of VDP color codes.
+    pub fn update(&mut self, indexed: &[u8]) {
+        self.indexed.clear();
+        self.indexed.extend_from_slice(indexed);
+        indexed_to_rgba(&self.lut, indexed, &mut self.rgba);
+    }
+
+    pub fn palette(&self) -> &Palette {
+        &self.palette
+    }
+
+    /// Switches to another palette, redrawing the current frame with it.
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+        *self.lut = palette.rgba_lut();
+        indexed_to_rgba(&self.lut, &self.indexed, &mut self.rgba);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_indexed_to_rgba() {
+        let mut frame = FrameBuffer::default();
+        let mut screen = [1; SCREEN_WIDTH * SCREEN_HEIGHT];
+        screen[0] = 4;
+        screen[1] = 15;
+        screen[2] = 0xF4;
+        frame.update(&screen);
+
+        assert_eq!(frame.rgba.len(), SCREEN_WIDTH * SCREEN_HEIGHT * 4);
+        assert_eq!(frame.rgba[0..4], [0x54, 0x55, 0xED, 0xFF]);
+        assert_eq!(frame.rgba[4..8], [0xFF, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(frame.rgba[8..12], frame.rgba[0..4]);
+        assert_eq!(frame.rgba[frame.rgba.len() - 4..], [0x00, 0x00, 0x00, 0xFF]);
+
+        frame.set_palette(Palette::BRIGHT);
+        assert_eq!(frame.rgba[0..4], [0x24, 0x24, 0xFF, 0xFF]);
+        assert_eq!(frame.rgba[4..8], [0xFF, 0xFF, 0xFF, 0xFF]);
+    }
+}
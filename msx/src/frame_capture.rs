@@ -0,0 +1,155 @@
+//! The VDP registers at the start of every line of a frame, telling on which
+//! line a program changed them, e.g. for the split screens and scrolling of
+//! raster effects.
+
+use crate::{vdp_timing::LINE_T_STATES, Msx};
+
+/// A register written by the program between the starts of two lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegisterChange {
+    /// first line drawn with the new value
+    pub line: u16,
+    pub register: usize,
+    pub from: u8,
+    pub to: u8,
+}
+
+/// Records the registers line by line, as a program runs through the frame
+/// after the one it started in, or all at once with
+/// [`Msx::capture_frame`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FrameCapture {
+    /// the registers at the start of each line, from the top of the display
+    /// through the VBlank
+    pub lines: Vec<[u8; 8]>,
+    // T-state the frame starts at
+    start: u64,
+    lines_per_frame: usize,
+}
+
+impl FrameCapture {
+    /// Captures the next frame to start on the machine.
+    pub fn new(msx: &Msx) -> Self {
+        let bus = msx.bus.read().unwrap();
+        let frame = bus.vdp.frame_t_states();
+        Self {
+            lines: Vec::new(),
+            start: bus.vdp.next_frame(bus.clock()),
+            lines_per_frame: (frame / LINE_T_STATES) as usize,
+        }
+    }
+
+    /// Records the lines started so far with the registers as they are,
+    /// written by the instructions that ended by then; to be called after
+    /// every instruction. True once the last line started.
+    pub fn record(&mut self, msx: &Msx) -> bool {
+        let bus = msx.bus.read().unwrap();
+        while !self.done() && self.line_start(self.lines.len()) <= bus.clock() {
+            self.lines.push(bus.vdp.registers);
+        }
+        self.done()
+    }
+
+    pub fn done(&self) -> bool {
+        self.lines.len() == self.lines_per_frame
+    }
+
+    fn line_start(&self, line: usize) -> u64 {
+        self.start + line as u64 * LINE_T_STATES
+    }
+
+    /// The registers as the frame started.
+    pub fn first(&self) -> [u8; 8] {
+        self.lines.first().copied().unwrap_or_default()
+    }
+
+    /// The registers that changed during the frame, by line.
+    pub fn changes(&self) -> Vec<RegisterChange> {
+        self.lines
+            .windows(2)
+            .enumerate()
+            .flat_map(|(line, pair)| {
+                (0..8)
+                    .filter(|&register| pair[0][register] != pair[1][register])
+                    .map(move |register| RegisterChange {
+                        line: line as u16 + 1,
+                        register,
+                        from: pair[0][register],
+                        to: pair[1][register],
+                    })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        slot::{RamSlot, RomSlot, SlotType},
+        vdp_timing::FRAME_T_STATES,
+    };
+
+    #[test]
+    fn test_changes() {
+        let mut capture = FrameCapture {
+            lines: vec![[0; 8]; 4],
+            ..Default::default()
+        };
+        capture.lines[2][7] = 0xF4;
+        capture.lines[3] = [0x02, 0, 0, 0, 0, 0, 0, 0xF4];
+        assert_eq!(
+            capture.changes(),
+            [
+                RegisterChange {
+                    line: 2,
+                    register: 7,
+                    from: 0,
+                    to: 0xF4
+                },
+                RegisterChange {
+                    line: 3,
+                    register: 0,
+                    from: 0,
+                    to: 0x02
+                },
+            ]
+        );
+        assert_eq!(capture.first(), [0; 8]);
+    }
+
+    #[test]
+    fn test_capture_frame() {
+        // the second byte with bit 7 set writes the register
+        #[rustfmt::skip]
+        let rom = [
+            0x3E, 0x11, 0xD3, 0x99, // LD A,0x11 / OUT (0x99),A
+            0x3E, 0x87, 0xD3, 0x99, // LD A,0x87 / OUT (0x99),A: R#7 = 0x11
+            0x06, 0x00,             // LD B,0
+            0x10, 0xFE,             // DJNZ $, into line 16
+            0x3E, 0x22, 0xD3, 0x99, // LD A,0x22 / OUT (0x99),A
+            0x3E, 0x87, 0xD3, 0x99, // LD A,0x87 / OUT (0x99),A: R#7 = 0x22
+            0x18, 0xFE,             // JR $
+        ];
+        let mut msx = Msx::new(&[
+            SlotType::Rom(RomSlot::new(&rom, 0x0000, 0x8000)),
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+        ]);
+
+        // the machine starts at the top of a frame
+        let capture = msx.capture_frame();
+        assert_eq!(capture.lines.len(), 262);
+        assert_eq!(capture.first(), [0; 8]);
+        let changes = capture.changes();
+        assert_eq!(
+            changes.iter().map(|c| (c.line, c.to)).collect::<Vec<_>>(),
+            [(1, 0x11), (17, 0x22)]
+        );
+
+        // it stops on the last line, the next capture takes the next frame
+        msx.capture_frame();
+        assert_eq!(msx.bus.read().unwrap().clock() / FRAME_T_STATES, 1);
+    }
+}
@@ -0,0 +1,187 @@
+//! The CRC32 of each rendered frame as a stream of "<frame> <CRC32>" lines,
+//! to tell whether anything on the screen changed over thousands of frames
+//! without keeping the images: one run writes the stream, and the next one
+//! compares its frames with it.
+//!
+//! ```text
+//! 1 4FD0B0A2
+//! 2 4FD0B0A2
+//! 3 9A0C1E57
+//! ```
+
+use std::{collections::BTreeMap, fmt};
+
+use anyhow::anyhow;
+
+use crate::{frame::FrameBuffer, palette::Palette, Msx};
+
+/// The hash of a frame and of the same frame in the reference.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameHash {
+    /// counted from 1
+    pub frame: u64,
+    pub crc32: u32,
+    /// `None` without a reference or past its end
+    pub expected: Option<u32>,
+}
+
+impl FrameHash {
+    /// Whether the reference has another hash for the frame.
+    pub fn differs(&self) -> bool {
+        self.expected.is_some_and(|expected| expected != self.crc32)
+    }
+}
+
+/// The line of the frame in the stream.
+impl fmt::Display for FrameHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {:08X}", self.frame, self.crc32)
+    }
+}
+
+/// The hashes of a stream, by frame.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FrameHashes {
+    hashes: BTreeMap<u64, u32>,
+}
+
+impl FrameHashes {
+    #[cfg(feature = "std-fs")]
+    pub fn load(path: impl AsRef<std::path::Path>) -> anyhow::Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Reads the lines of a stream, skipping the blank ones.
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let mut hashes = BTreeMap::new();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let invalid = || anyhow!("Invalid frame hash on line {}: {}", n + 1, line);
+            let Some((frame, crc32)) = line.split_once(char::is_whitespace) else {
+                return Err(invalid());
+            };
+            let frame = frame.parse().map_err(|_| invalid())?;
+            let crc32 = u32::from_str_radix(crc32.trim(), 16).map_err(|_| invalid())?;
+            hashes.insert(frame, crc32);
+        }
+        Ok(Self { hashes })
+    }
+
+    pub fn get(&self, frame: u64) -> Option<u32> {
+        self.hashes.get(&frame).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.hashes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+    }
+}
+
+/// Renders and hashes the frames of a run one after the other, comparing
+/// them with a reference stream if there is one.
+#[derive(Debug, Clone)]
+pub struct FrameHasher {
+    frame: FrameBuffer,
+    reference: Option<FrameHashes>,
+    differing: u64,
+}
+
+impl FrameHasher {
+    /// Hashes the frames as drawn with `palette`, which the reference must
+    /// have been made with too.
+    pub fn new(palette: Palette, reference: Option<FrameHashes>) -> Self {
+        Self {
+            frame: FrameBuffer::new(palette),
+            reference,
+            differing: 0,
+        }
+    }
+
+    pub fn has_reference(&self) -> bool {
+        self.reference.is_some()
+    }
+
+    /// Number of frames that differed from the reference so far.
+    pub fn differing(&self) -> u64 {
+        self.differing
+    }
+
+    /// Hashes the screen as the frame the VDP last drew, numbered by
+    /// [`Msx::frames`].
+    pub fn hash(&mut self, msx: &Msx) -> FrameHash {
+        msx.render(&mut self.frame);
+        let frame = msx.frames();
+        let hash = FrameHash {
+            frame,
+            crc32: crc32fast::hash(&self.frame.rgba),
+            expected: self
+                .reference
+                .as_ref()
+                .and_then(|reference| reference.get(frame)),
+        };
+        if hash.differs() {
+            self.differing += 1;
+        }
+        hash
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::slot::{RamSlot, SlotType};
+
+    #[test]
+    fn test_parse() {
+        let hashes = FrameHashes::parse("1 4FD0B0A2\n\n2 9a0c1e57\n").unwrap();
+        assert_eq!(hashes.len(), 2);
+        assert_eq!(hashes.get(2), Some(0x9A0C1E57));
+        assert_eq!(hashes.get(3), None);
+
+        let error = FrameHashes::parse("1 4FD0B0A2\n2\n").unwrap_err();
+        assert_eq!(error.to_string(), "Invalid frame hash on line 2: 2");
+    }
+
+    #[test]
+    fn test_hash() {
+        let new_msx = || {
+            Msx::new(&[
+                SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+                SlotType::Empty,
+                SlotType::Empty,
+                SlotType::Empty,
+            ])
+        };
+        // the frames are numbered by the VDP, not by the calls
+        let mut hasher = FrameHasher::new(Palette::default(), None);
+        let mut msx = new_msx();
+        msx.step_frame();
+        let first = hasher.hash(&msx);
+        msx.step_frame();
+        let second = hasher.hash(&msx);
+        assert_eq!((first.frame, second.frame), (1, 2));
+        assert_eq!(first.crc32, second.crc32);
+        assert!(!first.differs());
+
+        // a stream read back matches, but for the frame changed in it
+        let stream = format!("{}\n{}\n", first, FrameHash { crc32: 0, ..second });
+        let reference = FrameHashes::parse(&stream).unwrap();
+        let mut hasher = FrameHasher::new(Palette::default(), Some(reference));
+        let mut msx = new_msx();
+        msx.step_frame();
+        assert!(!hasher.hash(&msx).differs());
+        msx.step_frame();
+        assert!(hasher.hash(&msx).differs());
+        // past the end of the reference
+        msx.step_frame();
+        assert!(!hasher.hash(&msx).differs());
+        assert_eq!(hasher.differing(), 1);
+    }
+}
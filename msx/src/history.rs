@@ -0,0 +1,297 @@
+use std::{collections::VecDeque, fmt};
+
+use crate::{
+    instruction::{Instruction, MAX_INSTRUCTION_LEN},
+    Z80,
+};
+
+/// Default number of instructions kept in the history.
+pub const DEFAULT_HISTORY_SIZE: usize = 100_000;
+
+const REGISTER_COUNT: usize = 11;
+
+/// Register values before an instruction was executed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Registers {
+    pub af: u16,
+    pub bc: u16,
+    pub de: u16,
+    pub hl: u16,
+    pub af_alt: u16,
+    pub bc_alt: u16,
+    pub de_alt: u16,
+    pub hl_alt: u16,
+    pub sp: u16,
+    pub ix: u16,
+    pub iy: u16,
+}
+
+impl Registers {
+    pub fn from_cpu(cpu: &Z80) -> Self {
+        let pair = |hi: u8, lo: u8| u16::from_be_bytes([hi, lo]);
+        Self {
+            af: pair(cpu.a, cpu.f),
+            bc: pair(cpu.b, cpu.c),
+            de: pair(cpu.d, cpu.e),
+            hl: pair(cpu.h, cpu.l),
+            af_alt: pair(cpu.a_alt, cpu.f_alt),
+            bc_alt: pair(cpu.b_alt, cpu.c_alt),
+            de_alt: pair(cpu.d_alt, cpu.e_alt),
+            hl_alt: pair(cpu.h_alt, cpu.l_alt),
+            sp: cpu.sp,
+            ix: cpu.ix,
+            iy: cpu.iy,
+        }
+    }
+
+    fn as_array(&self) -> [u16; REGISTER_COUNT] {
+        [
+            self.af,
+            self.bc,
+            self.de,
+            self.hl,
+            self.af_alt,
+            self.bc_alt,
+            self.de_alt,
+            self.hl_alt,
+            self.sp,
+            self.ix,
+            self.iy,
+        ]
+    }
+
+    fn set(&mut self, index: usize, value: u16) {
+        let register = match index {
+            0 => &mut self.af,
+            1 => &mut self.bc,
+            2 => &mut self.de,
+            3 => &mut self.hl,
+            4 => &mut self.af_alt,
+            5 => &mut self.bc_alt,
+            6 => &mut self.de_alt,
+            7 => &mut self.hl_alt,
+            8 => &mut self.sp,
+            9 => &mut self.ix,
+            _ => &mut self.iy,
+        };
+        *register = value;
+    }
+}
+
+// an executed instruction, with the registers that changed since the
+// previous one stored apart
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    pc: u16,
+    bytes: [u8; MAX_INSTRUCTION_LEN],
+    changed: u16,
+}
+
+/// An instruction from the history, with the registers before it ran.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryEntry {
+    pub pc: u16,
+    pub bytes: [u8; MAX_INSTRUCTION_LEN],
+    pub registers: Registers,
+}
+
+impl fmt::Display for HistoryEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let instruction = Instruction::from_bytes(self.bytes, self.pc);
+        let r = &self.registers;
+        write!(
+            f,
+            "{:04X}  {:<12}  {:<20} AF: {:04X} BC: {:04X} DE: {:04X} HL: {:04X} SP: {:04X} IX: {:04X} IY: {:04X}",
+            self.pc,
+            instruction.opcode_with_args(),
+            instruction.name(),
+            r.af,
+            r.bc,
+            r.de,
+            r.hl,
+            r.sp,
+            r.ix,
+            r.iy
+        )
+    }
+}
+
+/// Ring buffer of the last executed instructions. Only the registers that
+/// changed from one instruction to the next are stored, the full state is
+/// rebuilt from the oldest entry when the history is read.
+#[derive(Debug, Clone)]
+pub struct History {
+    capacity: usize,
+    entries: VecDeque<Entry>,
+    values: VecDeque<u16>,
+    // registers of the oldest entry and of the newest one
+    first: Registers,
+    last: Registers,
+}
+
+impl History {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: VecDeque::new(),
+            values: VecDeque::new(),
+            first: Registers::default(),
+            last: Registers::default(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.values.clear();
+    }
+
+    /// Records the instruction at the current PC, before it is executed.
+    pub fn record(&mut self, cpu: &Z80) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut bytes = [0; MAX_INSTRUCTION_LEN];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = cpu.read_byte(cpu.pc.wrapping_add(i as u16));
+        }
+
+        let registers = Registers::from_cpu(cpu);
+        let mut changed = 0;
+        if self.entries.is_empty() {
+            self.first = registers;
+        } else {
+            let previous = self.last.as_array();
+            for (i, value) in registers.as_array().iter().enumerate() {
+                if previous[i] != *value {
+                    changed |= 1 << i;
+                    self.values.push_back(*value);
+                }
+            }
+        }
+        self.last = registers;
+
+        self.entries.push_back(Entry {
+            pc: cpu.pc,
+            bytes,
+            changed,
+        });
+
+        if self.entries.len() > self.capacity {
+            self.entries.pop_front();
+            // the new oldest entry becomes the full state
+            if let Some(oldest) = self.entries.front_mut() {
+                apply(&mut self.first, oldest.changed, &mut self.values);
+                oldest.changed = 0;
+            }
+        }
+    }
+
+    /// Rebuilds the last `n` instructions, oldest first.
+    pub fn last(&self, n: usize) -> Vec<HistoryEntry> {
+        let skip = self.entries.len().saturating_sub(n);
+        let mut registers = self.first;
+        let mut values = self.values.iter().copied();
+        let mut result = Vec::with_capacity(n.min(self.entries.len()));
+
+        for (i, entry) in self.entries.iter().enumerate() {
+            for bit in 0..REGISTER_COUNT {
+                if entry.changed & (1 << bit) != 0 {
+                    registers.set(bit, values.next().unwrap_or_default());
+                }
+            }
+
+            if i >= skip {
+                result.push(HistoryEntry {
+                    pc: entry.pc,
+                    bytes: entry.bytes,
+                    registers,
+                });
+            }
+        }
+
+        result
+    }
+}
+
+// applies and drops the values of the changed registers
+fn apply(registers: &mut Registers, changed: u16, values: &mut VecDeque<u16>) {
+    for bit in 0..REGISTER_COUNT {
+        if changed & (1 << bit) != 0 {
+            registers.set(bit, values.pop_front().unwrap_or_default());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, RwLock};
+
+    use super::*;
+    use crate::{
+        bus::Bus,
+        slot::{RamSlot, SlotType},
+    };
+
+    #[test]
+    fn test_history_ring() {
+        let bus = Bus::new(&[
+            SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Empty,
+        ]);
+        let mut cpu = Z80::new(Arc::new(RwLock::new(bus)));
+        // LD A, #42
+        cpu.write_byte(0x0000, 0x3E);
+        cpu.write_byte(0x0001, 0x42);
+
+        let mut history = History::new(3);
+        for i in 0..5u8 {
+            cpu.pc = i as u16 * 2;
+            cpu.a = i;
+            if i == 3 {
+                cpu.b = 0x10;
+            }
+            history.record(&cpu);
+        }
+        assert_eq!(history.len(), 3);
+
+        let entries = history.last(10);
+        assert_eq!(entries.len(), 3);
+        assert_eq!(
+            entries.iter().map(|e| e.pc).collect::<Vec<_>>(),
+            vec![4, 6, 8]
+        );
+        assert_eq!(entries[0].registers.af >> 8, 2);
+        assert_eq!(entries[1].registers.bc, 0x10FF);
+        assert_eq!(entries[2].registers.af >> 8, 4);
+        assert_eq!(entries[2].registers.bc, 0x10FF);
+
+        let last = history.last(1);
+        assert_eq!(last, vec![entries[2].clone()]);
+
+        history.clear();
+        history.record(&cpu);
+        assert_eq!(history.last(5)[0].registers, Registers::from_cpu(&cpu));
+    }
+
+    #[test]
+    fn test_entry_display() {
+        let entry = HistoryEntry {
+            pc: 0x1000,
+            bytes: [0x3E, 0x42, 0x00, 0x00],
+            registers: Registers::default(),
+        };
+        assert!(entry.to_string().starts_with("1000  3E 42"));
+        assert!(entry.to_string().contains("LD A, #42"));
+    }
+}
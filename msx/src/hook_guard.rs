@@ -0,0 +1,233 @@
+use std::fmt;
+
+use crate::{
+    hooks::{describe_hook, hook_contents, hook_name, HOOKS_START, HOOK_SIZE},
+    Z80,
+};
+
+/// Address of the handler of the maskable interrupts in interrupt mode 1,
+/// RST 38h.
+pub const INTERRUPT_VECTOR: u16 = 0x0038;
+
+// H.KEYI and H.TIMI, the hooks the BIOS interrupt handler calls
+const INTERRUPT_HOOKS: [u16; 2] = [HOOKS_START, HOOKS_START + HOOK_SIZE];
+
+// first opcodes of the interrupt handlers programs put at 0x0038 when RAM
+// is on page 0: JP, JR, RET, EI, DI and PUSH
+const HANDLER_OPCODES: &[u8] = &[0xC3, 0x18, 0xC9, 0xFB, 0xF3, 0xC5, 0xD5, 0xE5, 0xF5];
+
+/// Where an interrupt goes, as the guard reads it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InterruptEntry {
+    /// the code at 0x0038
+    Vector,
+    /// a hook of the BIOS interrupt handler, by address
+    Hook(u16),
+}
+
+impl fmt::Display for InterruptEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InterruptEntry::Vector => write!(f, "interrupt vector at {:#06X}", INTERRUPT_VECTOR),
+            InterruptEntry::Hook(address) => {
+                write!(f, "{}", hook_name(*address).unwrap_or("hook"))
+            }
+        }
+    }
+}
+
+/// An interrupt entry left malformed while the interrupts are enabled, so
+/// that the next interrupt runs whatever it holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HookViolation {
+    pub entry: InterruptEntry,
+    /// address of the instruction that changed it, or enabled the
+    /// interrupts
+    pub pc: u16,
+    pub before: [u8; 5],
+    pub after: [u8; 5],
+}
+
+impl fmt::Display for HookViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let change = if self.before == self.after {
+            "interrupts enabled".to_string()
+        } else {
+            format!(
+                "{} -> {}",
+                describe(self.entry, &self.before),
+                describe(self.entry, &self.after)
+            )
+        };
+        write!(
+            f,
+            "Malformed {} with interrupts enabled at {:#06X}: {}",
+            self.entry, self.pc, change
+        )
+    }
+}
+
+fn describe(entry: InterruptEntry, bytes: &[u8; 5]) -> String {
+    match entry {
+        InterruptEntry::Hook(_) => describe_hook(bytes),
+        InterruptEntry::Vector => bytes[..3]
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect::<Vec<_>>()
+            .join(" "),
+    }
+}
+
+// whether an interrupt entry holds what an interrupt can run: a hook its
+// RET, a JP, a CALL or an inter-slot call through RST 30h to a valid slot
+// ID, both followed by a RET, the vector the start of a handler
+fn well_formed(entry: InterruptEntry, bytes: &[u8; 5]) -> bool {
+    match entry {
+        InterruptEntry::Hook(_) => match bytes[0] {
+            0xC9 | 0xC3 => true,
+            0xCD => bytes[3] == 0xC9,
+            // the slot ID is E000SSPP, the bits in between always 0
+            0xF7 => bytes[1] & 0x70 == 0 && bytes[4] == 0xC9,
+            _ => false,
+        },
+        InterruptEntry::Vector => HANDLER_OPCODES.contains(&bytes[0]) || bytes[..2] == [0xED, 0x4D],
+    }
+}
+
+/// Watches the interrupt vector at 0x0038 and the H.KEYI and H.TIMI hooks,
+/// reporting when an instruction changes one of them, or enables the
+/// interrupts, leaving one malformed while the interrupts are enabled: a
+/// game writing its interrupt hook a byte at a time without a DI crashes
+/// the first time an interrupt lands in between.
+#[derive(Debug, Clone, Default)]
+pub struct HookGuard {
+    // PC, interrupts enabled and contents of the entries before the
+    // instruction being executed
+    pending: Option<(u16, bool, [[u8; 5]; 3])>,
+}
+
+impl HookGuard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Must be called before the CPU executes an instruction.
+    pub fn before_step(&mut self, cpu: &Z80) {
+        self.pending = Some((cpu.pc, cpu.iff1, contents(cpu)));
+    }
+
+    /// Must be called after the CPU executed an instruction, returns the
+    /// entry it left malformed with the interrupts enabled, if any.
+    pub fn after_step(&mut self, cpu: &Z80) -> Option<HookViolation> {
+        let (pc, enabled, before) = self.pending.take()?;
+        if !cpu.iff1 {
+            return None;
+        }
+        let after = contents(cpu);
+        entries()
+            .zip(before.iter().zip(&after))
+            .find(|(entry, (before, after))| {
+                (!enabled || before != after) && !well_formed(*entry, after)
+            })
+            .map(|(entry, (before, after))| HookViolation {
+                entry,
+                pc,
+                before: *before,
+                after: *after,
+            })
+    }
+}
+
+fn entries() -> impl Iterator<Item = InterruptEntry> {
+    std::iter::once(InterruptEntry::Vector)
+        .chain(INTERRUPT_HOOKS.into_iter().map(InterruptEntry::Hook))
+}
+
+fn contents(cpu: &Z80) -> [[u8; 5]; 3] {
+    let mut contents = [[0; 5]; 3];
+    for (bytes, entry) in contents.iter_mut().zip(entries()) {
+        *bytes = match entry {
+            InterruptEntry::Vector => hook_contents(cpu, INTERRUPT_VECTOR),
+            InterruptEntry::Hook(address) => hook_contents(cpu, address),
+        };
+    }
+    contents
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, RwLock};
+
+    use super::*;
+    use crate::{
+        bus::Bus,
+        slot::{RamSlot, SlotType},
+    };
+
+    fn run(program: &[u8], steps: usize) -> Vec<HookViolation> {
+        let bus = Bus::new(&[
+            SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Empty,
+        ]);
+        let mut cpu = Z80::new(Arc::new(RwLock::new(bus)));
+        for (i, byte) in program.iter().enumerate() {
+            cpu.write_byte(0x0100 + i as u16, *byte);
+        }
+        // JP 0x0100 at the vector, H.KEYI and H.TIMI holding their RET
+        for (address, byte) in [(0x0038, 0xC3), (0x0039, 0x00), (0x003A, 0x01)] {
+            cpu.write_byte(address, byte);
+        }
+        for address in 0xFD9A..0xFDA4 {
+            cpu.write_byte(address, 0xC9);
+        }
+        cpu.pc = 0x0100;
+
+        let mut guard = HookGuard::new();
+        let mut violations = Vec::new();
+        for _ in 0..steps {
+            guard.before_step(&cpu);
+            cpu.execute_cycle();
+            violations.extend(guard.after_step(&cpu));
+        }
+        violations
+    }
+
+    #[test]
+    fn test_hook_written_with_interrupts_enabled() {
+        // EI / LD A,0xF7 / LD (0xFD9F),A / LD A,0x8B / LD (0xFDA0),A
+        let violations = run(
+            &[
+                0xFB, 0x3E, 0xF7, 0x32, 0x9F, 0xFD, 0x3E, 0x8B, 0x32, 0xA0, 0xFD,
+            ],
+            5,
+        );
+        // the first write leaves a call to an invalid slot, the second a
+        // call the guard can't tell from the right one
+        assert_eq!(violations.len(), 1);
+        assert_eq!(
+            violations[0].to_string(),
+            "Malformed H.TIMI with interrupts enabled at 0x0103: RET -> \
+             RST 30h slot #C9 #C9C9"
+        );
+    }
+
+    #[test]
+    fn test_hook_written_with_interrupts_disabled() {
+        // DI / LD A,0xF7 / LD (0xFD9F),A / LD A,0xFF / LD (0x0038),A / EI
+        let violations = run(
+            &[
+                0xF3, 0x3E, 0xF7, 0x32, 0x9F, 0xFD, 0x3E, 0xFF, 0x32, 0x38, 0x00, 0xFB,
+            ],
+            6,
+        );
+        // reported once the interrupts are enabled, the vector first
+        assert_eq!(violations.len(), 1);
+        assert_eq!(
+            violations[0].to_string(),
+            "Malformed interrupt vector at 0x0038 with interrupts enabled at 0x010B: \
+             interrupts enabled"
+        );
+    }
+}
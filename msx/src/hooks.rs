@@ -0,0 +1,268 @@
+use std::{collections::BTreeMap, fmt};
+
+use crate::Z80;
+
+/// Start of the hooks, 5 bytes each, which the BIOS and BASIC call so that
+/// extensions and programs can change what they do.
+pub const HOOKS_START: u16 = 0xFD9A;
+
+/// Last address of the hooks.
+pub const HOOKS_END: u16 = 0xFFC9;
+
+pub const HOOK_SIZE: u16 = 5;
+
+/// Bit 0 is set once the extended BIOS hooks hold valid code, by the MSX2
+/// BIOS or, on MSX1, by the first extension using them, e.g. MSX-DOS2.
+pub const HOKVLD: u16 = 0xFB20;
+
+/// The extended BIOS hooks, after the others: `EXTBIO`, through which
+/// extensions offer their services, e.g. the memory mapper routines of
+/// MSX-DOS2 and Nextor, then those called around disk accesses.
+const EXTENDED_HOOKS: [(u16, &str); 3] =
+    [(0xFFCA, "EXTBIO"), (0xFFCF, "DISINT"), (0xFFD4, "ENAINT")];
+
+const RET: u8 = 0xC9;
+const JP: u8 = 0xC3;
+// RST 30h, the inter-slot call of CALLF
+const RST_30: u8 = 0xF7;
+
+/// Names of the hooks, by address from `HOOKS_START`.
+const HOOK_NAMES: &[&str] = &[
+    "H.KEYI", "H.TIMI", "H.CHPU", "H.DSPC", "H.ERAC", "H.DSPF", "H.ERAF", "H.TOTE", "H.CHGE",
+    "H.INIP", "H.KEYC", "H.KYEA", "H.NMI", "H.PINL", "H.QINL", "H.INLI", "H.ONGO", "H.DSKO",
+    "H.SETS", "H.NAME", "H.KILL", "H.IPL", "H.COPY", "H.CMD", "H.DSKF", "H.DSKI", "H.ATTR",
+    "H.LSET", "H.RSET", "H.FIEL", "H.MKI$", "H.MKS$", "H.MKD$", "H.CVI", "H.CVS", "H.CVD",
+    "H.GETP", "H.SETF", "H.NOFO", "H.NULO", "H.NTFL", "H.MERG", "H.SAVE", "H.BINS", "H.BINL",
+    "H.FILE", "H.DGET", "H.FILO", "H.INDS", "H.RSLF", "H.SAVD", "H.LOC", "H.LOF", "H.EOF",
+    "H.FPOS", "H.BAKU", "H.PARD", "H.NODE", "H.POSD", "H.DEVN", "H.GEND", "H.RUNC", "H.CLEA",
+    "H.LOPD", "H.STKE", "H.ISFL", "H.OUTD", "H.CRDO", "H.DSKC", "H.DOGR", "H.PRGE", "H.ERRP",
+    "H.ERRF", "H.READ", "H.MAIN", "H.DIRD", "H.FINI", "H.FINE", "H.CRUN", "H.CRUS", "H.ISRE",
+    "H.NTFN", "H.NOTR", "H.SNGF", "H.NEWS", "H.GONE", "H.CHRG", "H.RETU", "H.PRTF", "H.COMP",
+    "H.FINP", "H.TRMN", "H.FRME", "H.NTPL", "H.EVAL", "H.OKNO", "H.FING", "H.ISMI", "H.WIDT",
+    "H.LIST", "H.BUFL", "H.FRQI", "H.SCNE", "H.FRET", "H.PTRG", "H.PHYD", "H.FORM", "H.ERRO",
+    "H.LPTO", "H.LPTS", "H.SCRE", "H.PLAY",
+];
+
+/// The name of the hook starting at an address.
+pub fn hook_name(address: u16) -> Option<&'static str> {
+    if let Some((_, name)) = EXTENDED_HOOKS.iter().find(|(start, _)| *start == address) {
+        return Some(name);
+    }
+    let offset = address.checked_sub(HOOKS_START)?;
+    if offset % HOOK_SIZE != 0 {
+        return None;
+    }
+    HOOK_NAMES.get((offset / HOOK_SIZE) as usize).copied()
+}
+
+/// The addresses of all the hooks, the extended BIOS ones last.
+pub fn hook_addresses() -> impl Iterator<Item = u16> {
+    (0..HOOK_NAMES.len() as u16)
+        .map(|n| HOOKS_START + n * HOOK_SIZE)
+        .chain(EXTENDED_HOOKS.map(|(address, _)| address))
+}
+
+/// Whether the hook is one of the extended BIOS hooks.
+pub fn is_extended(address: u16) -> bool {
+    EXTENDED_HOOKS.iter().any(|(start, _)| *start == address)
+}
+
+/// Whether the extended BIOS hooks hold valid code, see [`HOKVLD`]. Until
+/// then, they hold whatever the RAM had on MSX1.
+pub fn extended_hooks_valid(cpu: &Z80) -> bool {
+    cpu.read_byte(HOKVLD) & 0x01 != 0
+}
+
+/// What the first bytes of a hook do, e.g. `RET`, `JP #4123` or
+/// `RST 30h slot #8B #7A3C`.
+pub fn describe_hook(bytes: &[u8; 5]) -> String {
+    match bytes[0] {
+        RET => "RET".to_string(),
+        JP => format!("JP #{:04X}", u16::from_le_bytes([bytes[1], bytes[2]])),
+        RST_30 => format!(
+            "RST 30h slot #{:02X} #{:04X}",
+            bytes[1],
+            u16::from_le_bytes([bytes[2], bytes[3]])
+        ),
+        _ => bytes
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect::<Vec<_>>()
+            .join(" "),
+    }
+}
+
+/// Whether a hook still holds the `RET` it starts with, or was patched.
+pub fn is_patched(bytes: &[u8; 5]) -> bool {
+    bytes[0] != RET
+}
+
+/// The contents of a hook in memory.
+pub fn hook_contents(cpu: &Z80, address: u16) -> [u8; 5] {
+    std::array::from_fn(|n| cpu.read_byte(address.wrapping_add(n as u16)))
+}
+
+/// A call to a hook, reported the first time the hook is called and then
+/// whenever its contents changed since.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HookCall {
+    pub address: u16,
+    pub name: &'static str,
+    /// the address on the top of the stack, where the hook returns to
+    pub return_address: u16,
+    pub contents: [u8; 5],
+    /// the contents at the previous report
+    pub previous: Option<[u8; 5]>,
+}
+
+impl fmt::Display for HookCall {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Hook {} ({:#06X}) called, returning to {:#06X}: {}",
+            self.name,
+            self.address,
+            self.return_address,
+            describe_hook(&self.contents)
+        )?;
+        if let Some(previous) = self.previous {
+            write!(f, " (was {})", describe_hook(&previous))?;
+        }
+        // anything else was likely copied there wrong, or overwritten
+        if !matches!(self.contents[0], RET | JP | RST_30) {
+            write!(f, " - not RET, JP or RST 30h")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HookStats {
+    pub calls: u64,
+    /// contents at the last report
+    pub contents: [u8; 5],
+}
+
+/// Reports the calls to the hooks whose contents weren't seen before, e.g.
+/// when a program hooks `H.TIMI`, and counts the calls to each hook.
+#[derive(Debug, Clone, Default)]
+pub struct HookTracer {
+    hooks: BTreeMap<u16, HookStats>,
+}
+
+impl HookTracer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Must be called before the CPU executes an instruction, returns the
+    /// call to report when it enters a hook.
+    pub fn before_step(&mut self, cpu: &Z80) -> Option<HookCall> {
+        let address = cpu.pc;
+        let name = hook_name(address)?;
+        let contents = hook_contents(cpu, address);
+
+        let previous = match self.hooks.get_mut(&address) {
+            Some(stats) => {
+                stats.calls += 1;
+                if stats.contents == contents {
+                    return None;
+                }
+                Some(std::mem::replace(&mut stats.contents, contents))
+            }
+            None => {
+                self.hooks.insert(address, HookStats { calls: 1, contents });
+                None
+            }
+        };
+
+        Some(HookCall {
+            address,
+            name,
+            return_address: cpu.read_word(cpu.sp),
+            contents,
+            previous,
+        })
+    }
+
+    /// The hooks called so far, by address.
+    pub fn stats(&self) -> impl Iterator<Item = (u16, &HookStats)> {
+        self.hooks.iter().map(|(address, stats)| (*address, stats))
+    }
+
+    pub fn calls(&self, address: u16) -> u64 {
+        self.hooks.get(&address).map_or(0, |stats| stats.calls)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, RwLock};
+
+    use super::*;
+    use crate::{
+        bus::Bus,
+        slot::{RamSlot, SlotType},
+    };
+
+    fn cpu() -> Z80 {
+        let bus = Bus::new(&[
+            SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Empty,
+        ]);
+        let mut cpu = Z80::new(Arc::new(RwLock::new(bus)));
+        for address in HOOKS_START..=HOOKS_END {
+            cpu.write_byte(address, RET);
+        }
+        cpu
+    }
+
+    #[test]
+    fn test_hook_names() {
+        assert_eq!(hook_addresses().nth(HOOK_NAMES.len() - 1), Some(0xFFC5));
+        assert_eq!(hook_addresses().last(), Some(0xFFD4));
+        assert_eq!(hook_name(0xFD9F), Some("H.TIMI"));
+        assert_eq!(hook_name(0xFEDA), Some("H.STKE"));
+        assert_eq!(hook_name(0xFFA7), Some("H.PHYD"));
+        assert_eq!(hook_name(0xFFC5), Some("H.PLAY"));
+        assert_eq!(hook_name(0xFDA0), None);
+        assert_eq!(hook_name(0xFFCA), Some("EXTBIO"));
+        assert_eq!(hook_name(0xFFD4), Some("ENAINT"));
+        assert_eq!(hook_name(0xFFCB), None);
+        assert!(is_extended(0xFFCF));
+        assert!(!is_extended(0xFFC5));
+    }
+
+    #[test]
+    fn test_tracer() {
+        let mut cpu = cpu();
+        let mut tracer = HookTracer::new();
+        cpu.sp = 0xF000;
+        cpu.write_word(0xF000, 0x0C3F);
+        cpu.pc = 0xFD9F;
+
+        let call = tracer.before_step(&cpu).unwrap();
+        assert_eq!(call.name, "H.TIMI");
+        assert_eq!(call.return_address, 0x0C3F);
+        assert_eq!(call.previous, None);
+        assert_eq!(tracer.before_step(&cpu), None);
+
+        cpu.write_byte(0xFD9F, JP);
+        cpu.write_word(0xFDA0, 0x4123);
+        let call = tracer.before_step(&cpu).unwrap();
+        assert_eq!(
+            call.to_string(),
+            "Hook H.TIMI (0xFD9F) called, returning to 0x0C3F: JP #4123 (was RET)"
+        );
+        assert_eq!(tracer.calls(0xFD9F), 3);
+
+        cpu.write_byte(0xFD9F, 0x00);
+        let call = tracer.before_step(&cpu).unwrap();
+        assert!(call.to_string().ends_with("not RET, JP or RST 30h"));
+
+        cpu.pc = 0xFDA0;
+        assert_eq!(tracer.before_step(&cpu), None);
+    }
+}
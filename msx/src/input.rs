@@ -0,0 +1,207 @@
+//! Host input on its way to the keyboard matrix and the joystick: autofire
+//! on the joystick triggers and macros replaying recorded presses.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{keymap::Binding, Msx};
+
+// joystick lines of the triggers A and B
+const TRIGGERS: [u8; 2] = [4, 5];
+
+/// A press or release of a macro, `frame` frames after its start.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MacroEvent {
+    pub frame: u32,
+    pub binding: Binding,
+    pub pressed: bool,
+}
+
+/// Presses recorded with their timing, to be replayed by a host key.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Macro {
+    pub events: Vec<MacroEvent>,
+}
+
+impl Macro {
+    /// Frames the macro takes to replay.
+    pub fn frames(&self) -> u32 {
+        self.events.last().map_or(0, |event| event.frame)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Playback {
+    start: u32,
+    recorded: Macro,
+    // index of the next event to play
+    next: usize,
+}
+
+/// Sits between the host and the machine, which it drives through
+/// [`Msx::set_key`] and [`Msx::set_joystick`]. [`Controls::frame`] must be
+/// called once per frame.
+#[derive(Debug, Clone, Default)]
+pub struct Controls {
+    /// autofire of the triggers A and B, the frames the trigger stays
+    /// pressed and then released while held, 0 for none
+    pub autofire: [u32; 2],
+    frame: u32,
+    // frame each trigger was pressed on by the host, while held
+    triggers: [Option<u32>; 2],
+    recording: Option<(u32, Macro)>,
+    playing: Vec<Playback>,
+}
+
+impl Controls {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Presses or releases what a host key is bound to, recording it when
+    /// a macro is being recorded.
+    pub fn press(&mut self, msx: &mut Msx, binding: Binding, pressed: bool) {
+        if let Some((start, recording)) = &mut self.recording {
+            recording.events.push(MacroEvent {
+                frame: self.frame - *start,
+                binding,
+                pressed,
+            });
+        }
+        self.apply(msx, binding, pressed);
+    }
+
+    /// Starts recording the presses, forgetting a recording in progress.
+    pub fn start_recording(&mut self) {
+        self.recording = Some((self.frame, Macro::default()));
+    }
+
+    pub fn recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    /// Stops recording, returning the macro if anything was pressed.
+    pub fn stop_recording(&mut self) -> Option<Macro> {
+        let (_, recording) = self.recording.take()?;
+        (!recording.events.is_empty()).then_some(recording)
+    }
+
+    /// Replays a macro from the next frame, along with the macros still
+    /// playing.
+    pub fn play(&mut self, recorded: &Macro) {
+        self.playing.push(Playback {
+            start: self.frame + 1,
+            recorded: recorded.clone(),
+            next: 0,
+        });
+    }
+
+    pub fn playing(&self) -> bool {
+        !self.playing.is_empty()
+    }
+
+    /// Advances the autofire and the macros being played by a frame.
+    pub fn frame(&mut self, msx: &mut Msx) {
+        self.frame += 1;
+
+        for (trigger, pressed_at) in self.triggers.iter().enumerate() {
+            let period = self.autofire[trigger];
+            if let (Some(pressed_at), true) = (pressed_at, period > 0) {
+                let half_periods = (self.frame - pressed_at) / period;
+                msx.set_joystick(TRIGGERS[trigger], half_periods & 1 == 0);
+            }
+        }
+
+        let mut playing = std::mem::take(&mut self.playing);
+        for playback in &mut playing {
+            while let Some(event) = playback.recorded.events.get(playback.next).copied() {
+                if playback.start + event.frame > self.frame {
+                    break;
+                }
+                playback.next += 1;
+                self.apply(msx, event.binding, event.pressed);
+            }
+        }
+        playing.retain(|playback| playback.next < playback.recorded.events.len());
+        self.playing = playing;
+    }
+
+    fn apply(&mut self, msx: &mut Msx, binding: Binding, pressed: bool) {
+        match binding {
+            Binding::Key(row, bit) => msx.set_key(row, bit, pressed),
+            Binding::Joystick(line) => {
+                if let Some(trigger) = TRIGGERS.iter().position(|&t| t == line) {
+                    self.triggers[trigger] = pressed.then_some(self.frame);
+                }
+                msx.set_joystick(line, pressed);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::slot::{RamSlot, SlotType};
+
+    fn machine() -> Msx {
+        Msx::new(&[
+            SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Empty,
+        ])
+    }
+
+    fn joystick(msx: &Msx) -> u8 {
+        msx.bus.read().unwrap().psg.joystick()
+    }
+
+    #[test]
+    fn test_autofire() {
+        let mut msx = machine();
+        let mut controls = Controls::new();
+        controls.autofire = [2, 0];
+        controls.press(&mut msx, Binding::Joystick(4), true);
+        controls.press(&mut msx, Binding::Joystick(5), true);
+
+        // trigger A is pressed for 2 frames and released for 2, B is held
+        let mut lines = vec![joystick(&msx)];
+        for _ in 0..5 {
+            controls.frame(&mut msx);
+            lines.push(joystick(&msx));
+        }
+        assert_eq!(lines, [0x30, 0x30, 0x20, 0x20, 0x30, 0x30]);
+
+        controls.press(&mut msx, Binding::Joystick(4), false);
+        controls.frame(&mut msx);
+        assert_eq!(joystick(&msx), 0x20);
+    }
+
+    #[test]
+    fn test_macro() {
+        let mut msx = machine();
+        let mut controls = Controls::new();
+        assert_eq!(controls.stop_recording(), None);
+
+        controls.start_recording();
+        controls.frame(&mut msx);
+        controls.press(&mut msx, Binding::Key(8, 0), true);
+        controls.frame(&mut msx);
+        controls.frame(&mut msx);
+        controls.press(&mut msx, Binding::Key(8, 0), false);
+        let recorded = controls.stop_recording().unwrap();
+        assert_eq!(recorded.events.len(), 2);
+        assert_eq!(recorded.frames(), 3);
+
+        // replayed from the next frame with the same timing
+        controls.play(&recorded);
+        let matrix = |msx: &Msx| msx.bus.read().unwrap().ppi.keyboard_matrix()[8];
+        let mut rows = Vec::new();
+        while controls.playing() {
+            controls.frame(&mut msx);
+            rows.push(matrix(&msx));
+        }
+        assert_eq!(rows, [0xFF, 0xFE, 0xFE, 0xFF]);
+        assert!(!controls.recording());
+    }
+}
@@ -0,0 +1,207 @@
+use std::{collections::VecDeque, fmt};
+
+use serde::Serialize;
+
+use crate::{
+    keyboard::KEYBOARD_ROWS,
+    keymap::{Binding, JOYSTICK_LINES},
+};
+
+/// Events kept by default, the oldest being dropped first.
+pub const DEFAULT_INPUT_LOG_SIZE: usize = 4096;
+
+/// A key of the matrix or a joystick line pressed or released by the host,
+/// the autotyper, a macro or netplay.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct InputEvent {
+    pub input: Binding,
+    pub pressed: bool,
+    /// frame the event happened in, counted since the reset
+    pub frame: u64,
+    /// instructions run since the reset
+    pub cycle: u64,
+    /// address of the instruction about to be executed
+    pub pc: u16,
+    /// for a release, how many times the program read the row of the key,
+    /// or the joystick, while it was down; none if its press isn't logged
+    pub reads: Option<u64>,
+    // reads of the row or the joystick when the event happened
+    #[serde(skip)]
+    read_count: u64,
+}
+
+impl fmt::Display for InputEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{:04X}  ", self.pc)?;
+        match self.input {
+            Binding::Key(row, bit) => write!(f, "key {} {}", row, bit)?,
+            Binding::Joystick(line) => write!(f, "joy {}", JOYSTICK_LINES[line as usize])?,
+        }
+        write!(f, " {}", if self.pressed { "down" } else { "up" })?;
+        if let Some(reads) = self.reads {
+            write!(
+                f,
+                ", read {} time{} while down",
+                reads,
+                if reads == 1 { "" } else { "s" }
+            )?;
+        }
+        write!(f, "  frame {} cycle {}", self.frame, self.cycle)
+    }
+}
+
+/// The most recent presses and releases of the keyboard and the joystick,
+/// recorded by the machine as they are injected, with how many times the
+/// program read them while they were down: a key released before the game
+/// polled its row never reached it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputLog {
+    events: VecDeque<InputEvent>,
+    size: usize,
+    // reads of each row of the keyboard matrix through the PPI, and of the
+    // joystick port through the PSG
+    row_reads: [u64; KEYBOARD_ROWS],
+    joystick_reads: u64,
+}
+
+impl InputLog {
+    pub fn new(size: usize) -> Self {
+        Self {
+            events: VecDeque::with_capacity(size),
+            size,
+            row_reads: [0; KEYBOARD_ROWS],
+            joystick_reads: 0,
+        }
+    }
+
+    /// Counts a read of a row of the keyboard matrix.
+    pub fn read_row(&mut self, row: u8) {
+        if let Some(reads) = self.row_reads.get_mut(row as usize) {
+            *reads += 1;
+        }
+    }
+
+    /// Counts a read of the joystick port.
+    pub fn read_joystick(&mut self) {
+        self.joystick_reads += 1;
+    }
+
+    fn read_count(&self, input: Binding) -> u64 {
+        match input {
+            Binding::Key(row, _) => self.row_reads.get(row).copied().unwrap_or_default(),
+            Binding::Joystick(_) => self.joystick_reads,
+        }
+    }
+
+    /// Records a press or release at a frame, cycle and PC.
+    pub fn record(&mut self, input: Binding, pressed: bool, frame: u64, cycle: u64, pc: u16) {
+        let read_count = self.read_count(input);
+        let reads = if pressed {
+            None
+        } else {
+            self.events
+                .iter()
+                .rev()
+                .find(|event| event.input == input)
+                .filter(|event| event.pressed)
+                .map(|press| read_count - press.read_count)
+        };
+        if self.events.len() == self.size {
+            self.events.pop_front();
+        }
+        self.events.push_back(InputEvent {
+            input,
+            pressed,
+            frame,
+            cycle,
+            pc,
+            reads,
+            read_count,
+        });
+    }
+
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+
+    /// The events, oldest first.
+    pub fn events(&self) -> impl DoubleEndedIterator<Item = &InputEvent> {
+        self.events.iter()
+    }
+}
+
+impl Default for InputLog {
+    fn default() -> Self {
+        Self::new(DEFAULT_INPUT_LOG_SIZE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        slot::{RamSlot, SlotType},
+        Msx,
+    };
+
+    #[test]
+    fn test_reads_while_down() {
+        let mut log = InputLog::new(2);
+        log.record(Binding::Key(8, 0), true, 10, 1000, 0x0C3C);
+        log.read_row(8);
+        log.read_row(7);
+        log.read_row(8);
+        log.record(Binding::Key(8, 0), false, 11, 1200, 0x0C3C);
+        assert_eq!(
+            log.events().last().unwrap().to_string(),
+            "#0C3C  key 8 0 up, read 2 times while down  frame 11 cycle 1200"
+        );
+
+        // the press dropped with the oldest events can't tell
+        log.record(Binding::Key(8, 0), true, 12, 1300, 0x0C3C);
+        log.read_row(8);
+        log.record(Binding::Joystick(4), true, 12, 1300, 0x4010);
+        log.record(Binding::Joystick(4), false, 13, 1400, 0x4010);
+        log.record(Binding::Key(8, 0), false, 14, 1500, 0x0C3C);
+        let events: Vec<_> = log.events().map(|event| event.to_string()).collect();
+        assert_eq!(
+            events,
+            [
+                "#4010  joy a up, read 0 times while down  frame 13 cycle 1400",
+                "#0C3C  key 8 0 up  frame 14 cycle 1500",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_machine_log() {
+        // LD A,0x58 / OUT (0xAA),A / IN A,(0xA9) / JR $-2, polling row 8
+        let mut msx = Msx::new(&[
+            SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Empty,
+        ]);
+        for (i, byte) in [0x3E, 0x58, 0xD3, 0xAA, 0xDB, 0xA9, 0x18, 0xFC]
+            .into_iter()
+            .enumerate()
+        {
+            msx.set_memory(i as u16, byte);
+        }
+        msx.enable_input_log();
+        msx.set_key(8, 0, true);
+        // pressing it again changes nothing
+        msx.set_key(8, 0, true);
+        for _ in 0..6 {
+            msx.step();
+        }
+        msx.set_key(8, 0, false);
+        msx.set_joystick(4, true);
+
+        let events = msx.input_events();
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[1].reads, Some(2));
+        assert_eq!(events[1].cycle, 6);
+        assert_eq!(events[2].input, Binding::Joystick(4));
+    }
+}
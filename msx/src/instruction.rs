@@ -4,12 +4,21 @@ use tracing::error;
 
 use crate::Z80;
 
+/// Longest instruction, prefixes included.
+pub const MAX_INSTRUCTION_LEN: usize = 4;
+
 pub struct Instruction<'a> {
     pub opcode: u8,
-    pub cpu: &'a Z80,
+    source: Source<'a>,
     pub pc: u16,
 }
 
+// where the instruction bytes are read from
+enum Source<'a> {
+    Cpu(&'a Z80),
+    Bytes([u8; MAX_INSTRUCTION_LEN]),
+}
+
 impl<'a> Instruction<'a> {
     pub fn parse(cpu: &'a Z80) -> Self {
         Self::parse_at(cpu, cpu.pc)
@@ -17,7 +26,30 @@ impl<'a> Instruction<'a> {
 
     pub fn parse_at(cpu: &'a Z80, pc: u16) -> Self {
         let opcode = cpu.read_byte(pc);
-        Instruction { opcode, cpu, pc }
+        Instruction {
+            opcode,
+            source: Source::Cpu(cpu),
+            pc,
+        }
+    }
+
+    /// Decodes an instruction from its bytes, as they were at `pc`.
+    pub fn from_bytes(bytes: [u8; MAX_INSTRUCTION_LEN], pc: u16) -> Self {
+        Instruction {
+            opcode: bytes[0],
+            source: Source::Bytes(bytes),
+            pc,
+        }
+    }
+
+    fn read_byte(&self, address: u16) -> u8 {
+        match &self.source {
+            Source::Cpu(cpu) => cpu.read_byte(address),
+            Source::Bytes(bytes) => bytes
+                .get(address.wrapping_sub(self.pc) as usize)
+                .copied()
+                .unwrap_or(0xFF),
+        }
     }
 
     pub fn name(&self) -> String {
@@ -28,7 +60,7 @@ impl<'a> Instruction<'a> {
             let mut i = 1;
             while name.contains(&format!("${}", i)) {
                 let pc = self.pc.wrapping_add(i);
-                let arg = self.cpu.read_byte(pc);
+                let arg = self.read_byte(pc);
                 name = name.replace(&format!("${}", i), &format!("{:02X}", arg));
                 i += 1;
             }
@@ -52,7 +84,7 @@ impl<'a> Instruction<'a> {
         res.push(format!("{:02X}", self.opcode));
         for i in 1..length {
             let pc = self.pc.wrapping_add(i as u16);
-            let arg = self.cpu.read_byte(pc);
+            let arg = self.read_byte(pc);
             res.push(format!("{:02X}", arg));
         }
         res
@@ -63,7 +95,7 @@ impl<'a> Instruction<'a> {
         let mut args = String::new();
         for i in 1..length {
             let pc = self.pc.wrapping_add(i as u16);
-            let arg = self.cpu.read_byte(pc);
+            let arg = self.read_byte(pc);
             args.push_str(&format!("{:02X} ", arg));
         }
 
@@ -257,6 +289,14 @@ impl<'a> Instruction<'a> {
             0xEE => ("XOR #$1", 2),
             0x18 => ("JR #$1", 2),
             0x76 => ("HALT", 1),
+            0x27 => ("DAA", 1),
+            0x5B => ("LD E, E", 1),
+            0x6D => ("LD L, L", 1),
+            0x7F => ("LD A, A", 1),
+            0x6E => ("LD L, (HL)", 1),
+            0xE9 => ("JP (HL)", 1),
+            0xEC => ("CALL PE, #$2$1", 3),
+            0xF4 => ("CALL P, #$2$1", 3),
             0x2F => ("CPL", 1),
             0xBF => ("CP A", 1),
             0xB8 => ("CP B", 1),
@@ -268,12 +308,13 @@ impl<'a> Instruction<'a> {
             0xFE => ("CP #$1", 2),
             0xBE => ("CP (HL)", 1),
             0xDD => {
-                let opcode = self.cpu.read_byte(self.pc.wrapping_add(1));
+                let opcode = self.read_byte(self.pc.wrapping_add(1));
                 match opcode {
                     0xBE => ("CP (IX+d)", 4),
                     0x21 => ("LD IX, nn", 4),
                     0xE5 => ("PUSH IX", 2),
                     0xE1 => ("POP IX", 2),
+                    0xE9 => ("JP (IX)", 2),
                     _ => {
                         error!("Unknown opcode (CP (IX+d)) 0xDD 0x{:02X}", opcode);
                         ("Unknown", 1)
@@ -281,7 +322,7 @@ impl<'a> Instruction<'a> {
                 }
             }
             0xFD => {
-                let opcode = self.cpu.read_byte(self.pc.wrapping_add(1));
+                let opcode = self.read_byte(self.pc.wrapping_add(1));
                 match opcode {
                     0xBE => ("CP (IY+d)", 4),
                     0x22 => ("LD ($2$1), IY", 4),
@@ -289,6 +330,7 @@ impl<'a> Instruction<'a> {
                     0x2D => ("DEC IYL", 2),
                     0xE5 => ("PUSH IY", 2),
                     0xE1 => ("POP IY", 2),
+                    0xE9 => ("JP (IY)", 2),
                     0xAF => ("XOR A", 2),
                     _ => {
                         error!("Unknown opcode (CP (IY+d)) 0xFD 0x{:02X}", opcode);
@@ -343,7 +385,7 @@ impl<'a> Instruction<'a> {
             0x1F => ("RRA", 1),
             0xCB => {
                 // Read extended opcode and execute it
-                let extended_opcode = self.cpu.read_byte(self.pc.wrapping_add(1));
+                let extended_opcode = self.read_byte(self.pc.wrapping_add(1));
                 match extended_opcode {
                     0x00..=0x1F => ("RLC r", 2),
                     0x28..=0x2F => ("RR r", 2),
@@ -360,18 +402,32 @@ impl<'a> Instruction<'a> {
 
             // Extended opcodes
             0xED => {
-                let extended_opcode = self.cpu.read_byte(self.pc.wrapping_add(1));
+                let extended_opcode = self.read_byte(self.pc.wrapping_add(1));
                 match extended_opcode {
                     0xB0 => ("LDIR", 2),
                     0x42 => ("SBC HL, BC", 2),
                     0x52 => ("SBC HL, DE", 2),
+                    0x62 => ("SBC HL, HL", 2),
+                    0x72 => ("SBC HL, SP", 2),
+                    0x4A => ("ADC HL, BC", 2),
+                    0x5A => ("ADC HL, DE", 2),
+                    0x6A => ("ADC HL, HL", 2),
+                    0x7A => ("ADC HL, SP", 2),
+                    0x44 => ("NEG", 2),
+                    0x45 => ("RETN", 2),
                     0x56 => ("IM 1", 2),
                     0xA2 => ("INI", 2),
                     0xA3 => ("OUTI", 2),
                     0x51 => ("OUT (C), D", 2),
                     0x58 => ("OUT (C), E", 2),
+                    0x43 => ("LD ($2$1), BC", 4),
                     0x53 => ("LD ($2$1), DE", 4),
+                    0x63 => ("LD ($2$1), HL", 4),
+                    0x73 => ("LD ($2$1), SP", 4),
+                    0x4B => ("LD BC, ($2$1)", 4),
                     0x5B => ("LD DE, ($2$1)", 4),
+                    0x6B => ("LD HL, ($2$1)", 4),
+                    0x7B => ("LD SP, ($2$1)", 4),
                     _ => {
                         error!("Unknown opcode (ED) 0xED 0x{:02X}", extended_opcode);
                         ("Unknown", 1)
@@ -382,10 +438,6 @@ impl<'a> Instruction<'a> {
             // Interrupts
             0xFB => ("EI", 1),
             0xF3 => ("DI", 1),
-            _ => {
-                error!("Unknown opcode 0x{:02X}", self.opcode);
-                ("Unknown", 1)
-            }
         }
     }
 }
@@ -395,7 +447,7 @@ impl<'a> fmt::Display for Instruction<'a> {
         let (name, length) = self.as_def();
         let mut args = String::new();
         for i in 1..length {
-            let arg = self.cpu.read_byte(self.pc + i as u16);
+            let arg = self.read_byte(self.pc + i as u16);
             args.push_str(&format!("{:02X} ", arg));
         }
 
@@ -1,8 +1,10 @@
 use std::fmt;
 
+use serde::{Deserialize, Serialize};
+
 use crate::cpu::Flag;
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
 pub struct InternalState {
     // 8-bit registers
     pub a: u8,
@@ -51,6 +53,40 @@ impl fmt::Display for InternalState {
     }
 }
 
+/// The flags of an F register as `SZ5H3PNC`, with a `-` for each flag that
+/// is reset.
+pub fn flag_string(f: u8) -> String {
+    "SZ5H3PNC"
+        .chars()
+        .enumerate()
+        .map(|(n, name)| match f & (0x80 >> n) {
+            0 => '-',
+            _ => name,
+        })
+        .collect()
+}
+
+impl InternalState {
+    pub fn flags(&self) -> String {
+        flag_string(self.f)
+    }
+}
+
 pub trait ReportState {
     fn report_state(&mut self) -> anyhow::Result<InternalState>;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flags() {
+        let mut state = InternalState::default();
+        assert_eq!(state.flags(), "--------");
+        state.f = 0xFF;
+        assert_eq!(state.flags(), "SZ5H3PNC");
+        state.f = Flag::Z as u8 | Flag::C as u8 | 0x08;
+        assert_eq!(state.flags(), "-Z--3--C");
+    }
+}
@@ -0,0 +1,161 @@
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::vdp::TMS9918;
+
+/// Clock of the Z80 of an MSX, to tell the latencies in microseconds.
+const CPU_HZ: f64 = 3_579_545.0;
+
+/// How the frame interrupts of the VDP went: how many fired, how many the
+/// CPU took, and how long it took to, counted by the bus while the machine
+/// runs. Games dropping frames or music slowing down show up as missed
+/// interrupts or long latencies.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct InterruptStats {
+    /// frames started with the interrupt enabled in R#1
+    pub fired: u64,
+    /// interrupts the CPU took while the VDP held the line
+    pub delivered: u64,
+    /// interrupts still pending when the next frame fired, the CPU never
+    /// taking them
+    pub missed: u64,
+    /// T-states from firing to delivery, over all the delivered interrupts
+    pub latency: u64,
+    // clock at which the pending interrupt fired
+    #[serde(skip)]
+    pending_since: Option<u64>,
+    // frame counter of the VDP as of the last update, none after a state
+    // was loaded
+    #[serde(skip)]
+    frame: Option<u8>,
+}
+
+impl InterruptStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Average T-states from firing to delivery, if any was delivered.
+    pub fn average_latency(&self) -> Option<f64> {
+        (self.delivered > 0).then(|| self.latency as f64 / self.delivered as f64)
+    }
+
+    /// Counts an instruction: whether the CPU took an interrupt at `clock`,
+    /// before running it, and the frames the VDP started since, which fire
+    /// when the beam enters the VBlank.
+    pub fn update(&mut self, vdp: &TMS9918, clock: u64, taken: bool) {
+        if taken {
+            if let Some(since) = self.pending_since.take() {
+                self.delivered += 1;
+                self.latency += clock.saturating_sub(since);
+            }
+        }
+
+        let frames = self.frame.map_or(0, |frame| vdp.frame.wrapping_sub(frame));
+        self.frame = Some(vdp.frame);
+        if frames > 0 && vdp.registers[1] & 0x20 != 0 {
+            self.fired += frames as u64;
+            // frames that started while one was pending are lost with it
+            self.missed += frames as u64 - 1 + self.pending_since.is_some() as u64;
+            self.pending_since = Some(vdp.last_vblank());
+        }
+
+        // cleared by reading the status register without taking it, e.g.
+        // by polling with the interrupts disabled
+        if !vdp.irq() {
+            self.pending_since = None;
+        }
+    }
+}
+
+impl fmt::Display for InterruptStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} fired, {} delivered, {} missed",
+            self.fired, self.delivered, self.missed
+        )?;
+        if let Some(latency) = self.average_latency() {
+            write!(
+                f,
+                ", average latency {:.0} T-states ({:.1} µs)",
+                latency,
+                latency * 1_000_000.0 / CPU_HZ
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        slot::{RamSlot, SlotType},
+        vdp_timing::{DISPLAY_T_STATES, FRAME_T_STATES},
+        Msx,
+    };
+
+    #[test]
+    fn test_update() {
+        let mut vdp = TMS9918::new();
+        vdp.registers[1] = 0x20;
+        let mut stats = InterruptStats::new();
+        stats.update(&vdp, 0, false);
+
+        // fired when the first VBlank starts, taken 130 T-states later
+        let vblank = DISPLAY_T_STATES;
+        vdp.sync(vblank + 100);
+        stats.update(&vdp, vblank + 100, false);
+        stats.update(&vdp, vblank + 130, true);
+        vdp.status = 0;
+        stats.update(&vdp, vblank + 140, false);
+
+        // two frames fire while the interrupts are disabled, then one more
+        // taken 40 T-states after it fired
+        vdp.sync(vblank + 2 * FRAME_T_STATES + 50);
+        stats.update(&vdp, vblank + 2 * FRAME_T_STATES + 50, false);
+        vdp.sync(vblank + 3 * FRAME_T_STATES + 10);
+        stats.update(&vdp, vblank + 3 * FRAME_T_STATES + 10, false);
+        stats.update(&vdp, vblank + 3 * FRAME_T_STATES + 40, true);
+
+        assert_eq!((stats.fired, stats.delivered, stats.missed), (4, 2, 2));
+        assert_eq!(stats.average_latency(), Some(85.0));
+        assert_eq!(
+            stats.to_string(),
+            "4 fired, 2 delivered, 2 missed, average latency 85 T-states (23.7 µs)"
+        );
+    }
+
+    #[test]
+    fn test_machine() {
+        // IM 1 / EI / JR $, with an interrupt handler reading the status
+        // register: IN A,(0x99) / EI / RET
+        let mut msx = Msx::new(&[
+            SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Empty,
+        ]);
+        for (address, program) in [
+            (0x0000, &[0xED, 0x56, 0xFB, 0x18, 0xFE][..]),
+            (0x0038, &[0xDB, 0x99, 0xFB, 0xC9][..]),
+        ] {
+            for (i, byte) in program.iter().enumerate() {
+                msx.set_memory(address + i as u16, *byte);
+            }
+        }
+        msx.bus.write().unwrap().output(0x99, 0x20);
+        msx.bus.write().unwrap().output(0x99, 0x81);
+        msx.run_cycles(10 * FRAME_T_STATES as u32);
+
+        let stats = msx.interrupt_stats();
+        assert_eq!(stats.fired, 10, "{}", stats);
+        assert_eq!(stats.delivered, stats.fired);
+        assert_eq!(stats.missed, 0);
+        // the JR of 12 T-states, and its M1 wait state, runs when the frame
+        // starts
+        assert!(stats.average_latency().unwrap() <= 13.0, "{}", stats);
+    }
+}
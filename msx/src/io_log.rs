@@ -0,0 +1,163 @@
+use std::{collections::VecDeque, fmt};
+
+use serde::Serialize;
+
+/// Events kept by default, the oldest being dropped first.
+pub const DEFAULT_IO_LOG_SIZE: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IoDirection {
+    Read,
+    Write,
+}
+
+/// A read or write of an I/O port.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct IoEvent {
+    pub port: u8,
+    pub direction: IoDirection,
+    pub value: u8,
+    /// address of the instruction accessing the port
+    pub pc: u16,
+    /// frame the access happened in, counted since the log was enabled
+    pub frame: u64,
+}
+
+impl fmt::Display for IoEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let arrow = match self.direction {
+            IoDirection::Read => "<-",
+            IoDirection::Write => "->",
+        };
+        write!(
+            f,
+            "#{:04X}  port #{:02X} {} #{:02X}  frame {}",
+            self.pc, self.port, arrow, self.value, self.frame
+        )
+    }
+}
+
+/// The most recent I/O port accesses. The bus records them while the
+/// machine tells it the PC of the instruction being executed and when a
+/// frame starts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IoLog {
+    events: VecDeque<IoEvent>,
+    size: usize,
+    pc: u16,
+    frame: u64,
+}
+
+impl IoLog {
+    pub fn new(size: usize) -> Self {
+        Self {
+            events: VecDeque::with_capacity(size),
+            size,
+            pc: 0,
+            frame: 0,
+        }
+    }
+
+    /// Sets the PC of the instruction about to be executed.
+    pub fn set_pc(&mut self, pc: u16) {
+        self.pc = pc;
+    }
+
+    pub fn next_frame(&mut self) {
+        self.frame += 1;
+    }
+
+    pub fn record(&mut self, port: u8, direction: IoDirection, value: u8) {
+        if self.events.len() == self.size {
+            self.events.pop_front();
+        }
+        self.events.push_back(IoEvent {
+            port,
+            direction,
+            value,
+            pc: self.pc,
+            frame: self.frame,
+        });
+    }
+
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+
+    /// Removes the events, oldest first.
+    pub fn drain(&mut self) -> impl Iterator<Item = IoEvent> + '_ {
+        self.events.drain(..)
+    }
+
+    /// The events, oldest first.
+    pub fn events(&self) -> impl DoubleEndedIterator<Item = &IoEvent> {
+        self.events.iter()
+    }
+}
+
+impl Default for IoLog {
+    fn default() -> Self {
+        Self::new(DEFAULT_IO_LOG_SIZE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{slot::SlotType, Msx};
+
+    #[test]
+    fn test_drops_oldest() {
+        let mut log = IoLog::new(2);
+        log.set_pc(0x4010);
+        log.record(0x98, IoDirection::Write, 0x01);
+        log.next_frame();
+        log.record(0x99, IoDirection::Read, 0x9F);
+        log.record(0xA8, IoDirection::Write, 0xF0);
+
+        let events: Vec<_> = log.events().copied().collect();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].to_string(), "#4010  port #99 <- #9F  frame 1");
+        assert_eq!(events[1].port, 0xA8);
+    }
+
+    #[test]
+    fn test_machine_log() {
+        // LD A,0x55 / OUT (0x98),A / IN A,(0xA8)
+        let mut rom = vec![0x3E, 0x55, 0xD3, 0x98, 0xDB, 0xA8];
+        rom.resize(0x4000, 0);
+        let mut msx = Msx::new(&[
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Empty,
+        ]);
+        msx.load_rom(0, &rom);
+        msx.enable_io_log();
+        for _ in 0..3 {
+            msx.step();
+        }
+
+        let events = msx.io_events();
+        assert_eq!(
+            events,
+            vec![
+                IoEvent {
+                    port: 0x98,
+                    direction: IoDirection::Write,
+                    value: 0x55,
+                    pc: 0x0002,
+                    frame: 0,
+                },
+                IoEvent {
+                    port: 0xA8,
+                    direction: IoDirection::Read,
+                    value: events[1].value,
+                    pc: 0x0004,
+                    frame: 0,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,160 @@
+use serde::{Deserialize, Serialize};
+
+/// Number of rows in the MSX keyboard matrix.
+pub const KEYBOARD_ROWS: usize = 11;
+
+/// Keyboard matrix with no keys down.
+pub const NO_KEYS: [u8; KEYBOARD_ROWS] = [0xFF; KEYBOARD_ROWS];
+
+/// Position (row, bit) of a key in the international MSX keyboard matrix,
+/// using the key code names of the DOM `KeyboardEvent.code` property.
+pub fn key_position(code: &str) -> Option<(usize, u8)> {
+    let pos = match code {
+        "Digit0" => (0, 0),
+        "Digit1" => (0, 1),
+        "Digit2" => (0, 2),
+        "Digit3" => (0, 3),
+        "Digit4" => (0, 4),
+        "Digit5" => (0, 5),
+        "Digit6" => (0, 6),
+        "Digit7" => (0, 7),
+
+        "Digit8" => (1, 0),
+        "Digit9" => (1, 1),
+        "Minus" => (1, 2),
+        "Equal" => (1, 3),
+        "Backslash" => (1, 4),
+        "BracketLeft" => (1, 5),
+        "BracketRight" => (1, 6),
+        "Semicolon" => (1, 7),
+
+        "Quote" => (2, 0),
+        "Backquote" => (2, 1),
+        "Comma" => (2, 2),
+        "Period" => (2, 3),
+        "Slash" => (2, 4),
+        "IntlRo" => (2, 5),
+        "KeyA" => (2, 6),
+        "KeyB" => (2, 7),
+
+        "KeyC" => (3, 0),
+        "KeyD" => (3, 1),
+        "KeyE" => (3, 2),
+        "KeyF" => (3, 3),
+        "KeyG" => (3, 4),
+        "KeyH" => (3, 5),
+        "KeyI" => (3, 6),
+        "KeyJ" => (3, 7),
+
+        "KeyK" => (4, 0),
+        "KeyL" => (4, 1),
+        "KeyM" => (4, 2),
+        "KeyN" => (4, 3),
+        "KeyO" => (4, 4),
+        "KeyP" => (4, 5),
+        "KeyQ" => (4, 6),
+        "KeyR" => (4, 7),
+
+        "KeyS" => (5, 0),
+        "KeyT" => (5, 1),
+        "KeyU" => (5, 2),
+        "KeyV" => (5, 3),
+        "KeyW" => (5, 4),
+        "KeyX" => (5, 5),
+        "KeyY" => (5, 6),
+        "KeyZ" => (5, 7),
+
+        "ShiftLeft" | "ShiftRight" => (6, 0),
+        "ControlLeft" | "ControlRight" => (6, 1),
+        "AltLeft" => (6, 2),
+        "CapsLock" => (6, 3),
+        "AltRight" => (6, 4),
+        "F1" => (6, 5),
+        "F2" => (6, 6),
+        "F3" => (6, 7),
+
+        "F4" => (7, 0),
+        "F5" => (7, 1),
+        "Escape" => (7, 2),
+        "Tab" => (7, 3),
+        "Pause" => (7, 4),
+        "Backspace" => (7, 5),
+        "End" => (7, 6),
+        "Enter" | "NumpadEnter" => (7, 7),
+
+        "Space" => (8, 0),
+        "Home" => (8, 1),
+        "Insert" => (8, 2),
+        "Delete" => (8, 3),
+        "ArrowLeft" => (8, 4),
+        "ArrowUp" => (8, 5),
+        "ArrowDown" => (8, 6),
+        "ArrowRight" => (8, 7),
+
+        "NumpadMultiply" => (9, 0),
+        "NumpadAdd" => (9, 1),
+        "NumpadDivide" => (9, 2),
+        "Numpad0" => (9, 3),
+        "Numpad1" => (9, 4),
+        "Numpad2" => (9, 5),
+        "Numpad3" => (9, 6),
+        "Numpad4" => (9, 7),
+
+        "Numpad5" => (10, 0),
+        "Numpad6" => (10, 1),
+        "Numpad7" => (10, 2),
+        "Numpad8" => (10, 3),
+        "Numpad9" => (10, 4),
+        "NumpadSubtract" => (10, 5),
+        "NumpadComma" => (10, 6),
+        "NumpadDecimal" => (10, 7),
+
+        _ => return None,
+    };
+
+    Some(pos)
+}
+
+/// What the cursor keys and the space bar of the host drive, as games read
+/// either the keyboard or the joystick.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum InputProfile {
+    /// the MSX cursor keys and space bar
+    #[default]
+    Keyboard,
+    /// the directions and trigger A of joystick port 1
+    Joystick,
+}
+
+impl InputProfile {
+    pub const ALL: [InputProfile; 2] = [InputProfile::Keyboard, InputProfile::Joystick];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            InputProfile::Keyboard => "keyboard",
+            InputProfile::Joystick => "joystick",
+        }
+    }
+
+    pub fn find(name: &str) -> Option<InputProfile> {
+        Self::ALL
+            .into_iter()
+            .find(|profile| profile.name().eq_ignore_ascii_case(name))
+    }
+
+    /// The joystick line (bit of PSG port A) a key of the matrix drives
+    /// instead of itself with this profile.
+    pub fn joystick_line(self, (row, bit): (usize, u8)) -> Option<u8> {
+        if self != InputProfile::Joystick || row != 8 {
+            return None;
+        }
+        match bit {
+            5 => Some(0), // up
+            6 => Some(1), // down
+            4 => Some(2), // left
+            7 => Some(3), // right
+            0 => Some(4), // space, trigger A
+            _ => None,
+        }
+    }
+}
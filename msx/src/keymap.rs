@@ -0,0 +1,232 @@
+//! Bindings of the host keys and gamepad buttons to the MSX keyboard matrix
+//! and joystick, with overrides for some ROMs.
+//!
+//! The configuration has one binding per line, the host key first by its
+//! `KeyboardEvent.code` or gamepad button name:
+//!
+//! ```text
+//! # comments start with #
+//! ShiftRight 6 0        # a row and bit of the keyboard matrix
+//! KeyZ Space            # the MSX key at the position of a host key
+//! KeyX joy a            # joystick port 1: up, down, left, right, a or b
+//!
+//! [0123456789abcdef0123456789abcdef01234567]
+//! PadB F1               # only for the ROM with this SHA1
+//! ```
+
+use std::{collections::BTreeMap, fmt::Write};
+
+use anyhow::{anyhow, bail};
+use serde::{Deserialize, Serialize};
+
+use crate::keyboard::{key_position, KEYBOARD_ROWS};
+
+/// Names of the joystick lines, by their bit of PSG port A.
+pub const JOYSTICK_LINES: [&str; 6] = ["up", "down", "left", "right", "a", "b"];
+
+// names of the buttons of the standard gamepad layout, by their index
+const GAMEPAD_BUTTONS: [&str; 16] = [
+    "PadA",
+    "PadB",
+    "PadX",
+    "PadY",
+    "PadL",
+    "PadR",
+    "PadL2",
+    "PadR2",
+    "PadSelect",
+    "PadStart",
+    "PadL3",
+    "PadR3",
+    "PadUp",
+    "PadDown",
+    "PadLeft",
+    "PadRight",
+];
+
+/// What a host key or button presses on the MSX.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Binding {
+    /// a (row, bit) of the keyboard matrix
+    Key(usize, u8),
+    /// a line of joystick port 1, see [`JOYSTICK_LINES`]
+    Joystick(u8),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyMap {
+    /// host key codes bound to other inputs than the default ones
+    pub bindings: BTreeMap<String, Binding>,
+    /// bindings replacing those for a ROM, by its lowercase SHA1
+    pub roms: BTreeMap<String, BTreeMap<String, Binding>>,
+}
+
+impl KeyMap {
+    /// Parses the configuration described in the [module](self) docs.
+    pub fn parse(text: &str) -> anyhow::Result<KeyMap> {
+        let mut key_map = KeyMap::default();
+        let mut section = &mut key_map.bindings;
+        for (n, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(sha1) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                let sha1 = sha1.trim().to_lowercase();
+                if sha1.len() != 40 || !sha1.bytes().all(|b| b.is_ascii_hexdigit()) {
+                    bail!("Expected the SHA1 of a ROM on line {}", n + 1);
+                }
+                section = key_map.roms.entry(sha1).or_default();
+                continue;
+            }
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            let binding =
+                parse_binding(&parts[1..]).map_err(|e| anyhow!("{} on line {}", e, n + 1))?;
+            section.insert(parts[0].to_string(), binding);
+        }
+        Ok(key_map)
+    }
+
+    /// The configuration the key map was parsed from, without comments.
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        write_bindings(&mut text, &self.bindings);
+        for (sha1, bindings) in &self.roms {
+            let _ = writeln!(text, "\n[{}]", sha1);
+            write_bindings(&mut text, bindings);
+        }
+        text
+    }
+
+    /// What a host key or button is bound to, with the overrides of the ROM
+    /// with this SHA1 if any.
+    pub fn binding(&self, code: &str, rom_sha1: Option<&str>) -> Option<Binding> {
+        rom_sha1
+            .and_then(|sha1| self.roms.get(sha1))
+            .and_then(|bindings| bindings.get(code))
+            .or_else(|| self.bindings.get(code))
+            .copied()
+            .or_else(|| default_binding(code))
+    }
+}
+
+/// The binding of a host key or button without a key map: the keys at
+/// their place on the international keyboard, the gamepad directions and
+/// its first two buttons to the joystick.
+pub fn default_binding(code: &str) -> Option<Binding> {
+    if let Some((row, bit)) = key_position(code) {
+        return Some(Binding::Key(row, bit));
+    }
+    let line = match code {
+        "PadUp" => 0,
+        "PadDown" => 1,
+        "PadLeft" => 2,
+        "PadRight" => 3,
+        "PadA" => 4,
+        "PadB" => 5,
+        _ => return None,
+    };
+    Some(Binding::Joystick(line))
+}
+
+/// Name of a gamepad button in the key map, by its index in the standard
+/// layout of the browsers.
+pub fn gamepad_button(index: usize) -> String {
+    match GAMEPAD_BUTTONS.get(index) {
+        Some(name) => name.to_string(),
+        None => format!("Pad{}", index),
+    }
+}
+
+// the binding after the host key: `row bit`, an MSX key or `joy line`
+fn parse_binding(parts: &[&str]) -> anyhow::Result<Binding> {
+    match parts {
+        [joy, line] if joy.eq_ignore_ascii_case("joy") => JOYSTICK_LINES
+            .iter()
+            .position(|name| name.eq_ignore_ascii_case(line))
+            .map(|line| Binding::Joystick(line as u8))
+            .ok_or_else(|| anyhow!("Unknown joystick line {}", line)),
+        [row, bit] => match (row.parse::<usize>(), bit.parse::<u8>()) {
+            (Ok(row), Ok(bit)) if row < KEYBOARD_ROWS && bit < 8 => Ok(Binding::Key(row, bit)),
+            _ => bail!("Invalid row or bit"),
+        },
+        [code] => key_position(code)
+            .map(|(row, bit)| Binding::Key(row, bit))
+            .ok_or_else(|| anyhow!("Unknown MSX key {}", code)),
+        _ => bail!("Expected a key and a row and bit, an MSX key or joy and a line"),
+    }
+}
+
+fn write_bindings(text: &mut String, bindings: &BTreeMap<String, Binding>) {
+    for (code, binding) in bindings {
+        let _ = match binding {
+            Binding::Key(row, bit) => writeln!(text, "{} {} {}", code, row, bit),
+            Binding::Joystick(line) => {
+                writeln!(text, "{} joy {}", code, JOYSTICK_LINES[*line as usize])
+            }
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHA1: &str = "0123456789abcdef0123456789abcdef01234567";
+
+    #[test]
+    fn test_parse() {
+        let text = format!(
+            "# bindings\nShiftRight 6 0\nKeyZ Space\n\n KeyX joy A # fire\n[{}]\nPadB F1\n",
+            SHA1.to_uppercase()
+        );
+        let key_map = KeyMap::parse(&text).unwrap();
+        assert_eq!(key_map.bindings["ShiftRight"], Binding::Key(6, 0));
+        assert_eq!(key_map.bindings["KeyZ"], Binding::Key(8, 0));
+        assert_eq!(key_map.bindings["KeyX"], Binding::Joystick(4));
+        assert_eq!(key_map.roms[SHA1]["PadB"], Binding::Key(6, 5));
+
+        // the text round-trips
+        assert_eq!(KeyMap::parse(&key_map.to_text()).unwrap(), key_map);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |text| KeyMap::parse(text).unwrap_err().to_string();
+        assert_eq!(error("KeyA 11 0"), "Invalid row or bit on line 1");
+        assert_eq!(
+            error("\nKeyA joy fire"),
+            "Unknown joystick line fire on line 2"
+        );
+        assert_eq!(error("KeyA Nope"), "Unknown MSX key Nope on line 1");
+        assert_eq!(
+            error("KeyA"),
+            "Expected a key and a row and bit, an MSX key or joy and a line on line 1"
+        );
+        assert_eq!(error("[abc]"), "Expected the SHA1 of a ROM on line 1");
+    }
+
+    #[test]
+    fn test_binding() {
+        let key_map = KeyMap::parse(&format!("KeyZ Space\n[{}]\nKeyZ joy a", SHA1)).unwrap();
+        assert_eq!(key_map.binding("KeyZ", None), Some(Binding::Key(8, 0)));
+        assert_eq!(
+            key_map.binding("KeyZ", Some(SHA1)),
+            Some(Binding::Joystick(4))
+        );
+        assert_eq!(
+            key_map.binding("KeyZ", Some("other")),
+            Some(Binding::Key(8, 0))
+        );
+
+        // the defaults
+        assert_eq!(
+            key_map.binding("KeyA", Some(SHA1)),
+            Some(Binding::Key(2, 6))
+        );
+        assert_eq!(key_map.binding("PadLeft", None), Some(Binding::Joystick(2)));
+        assert_eq!(key_map.binding("PadStart", None), None);
+        assert_eq!(gamepad_button(14), "PadLeft");
+        assert_eq!(gamepad_button(16), "Pad16");
+    }
+}
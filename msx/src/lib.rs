@@ -1,17 +1,113 @@
+//! An MSX1 emulator core: a Z80, a TMS9918 VDP, an AY-3-8910 PSG and the
+//! slots they share, with nothing of a frontend. The debugger and the web
+//! app are built on it, and so can any other host.
+//!
+//! A host builds an [`Msx`] from the contents of its four primary slots,
+//! runs it, draws the screen into a [`FrameBuffer`](frame::FrameBuffer) and
+//! sets the keys of the keyboard matrix:
+//!
+//! ```
+//! use msx::{
+//!     frame::{FrameBuffer, SCREEN_HEIGHT, SCREEN_WIDTH},
+//!     keyboard::key_position,
+//!     slot::{RamSlot, RomSlot, SlotType},
+//!     vdp_timing::FRAME_T_STATES,
+//!     Msx,
+//! };
+//!
+//! // JR $, looping forever
+//! let rom = [0x18, 0xFE];
+//! let mut msx = Msx::new(&[
+//!     SlotType::Rom(RomSlot::new(&rom, 0x0000, 0x8000)),
+//!     SlotType::Empty,
+//!     SlotType::Empty,
+//!     SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+//! ]);
+//!
+//! let mut frame = FrameBuffer::default();
+//! for _ in 0..3 {
+//!     msx.run_cycles(FRAME_T_STATES as u32);
+//!     msx.render(&mut frame);
+//! }
+//! assert_eq!(frame.rgba.len(), SCREEN_WIDTH * SCREEN_HEIGHT * 4);
+//!
+//! // a pressed key reads as a reset bit of its row
+//! let (row, bit) = key_position("Space").unwrap();
+//! msx.set_key(row, bit, true);
+//! assert_eq!(msx.keyboard_matrix()[row] & (1 << bit), 0);
+//! ```
+//!
+//! `examples/headless.rs` does the same with a ROM file.
+
+pub mod alu;
+pub mod archive;
+pub mod audio;
+pub mod autotype;
+pub mod bank_log;
+pub mod basic;
+pub mod bload;
 pub mod bus;
+pub mod cas;
+#[cfg(feature = "cbios")]
+pub mod cbios;
+pub mod console;
 pub mod cpu;
+pub mod debug_device;
+pub mod device;
+pub mod disasm;
+pub mod dos;
+pub mod frame;
+pub mod frame_capture;
+pub mod frame_hash;
+pub mod history;
+pub mod hook_guard;
+pub mod hooks;
+pub mod input;
+pub mod input_log;
 pub mod instruction;
 pub mod internal_state;
+pub mod interrupt_stats;
+pub mod io_log;
+pub mod keyboard;
+pub mod keymap;
 pub mod machine;
 pub mod memory;
+pub mod netplay;
+pub mod palette;
+pub mod patch;
 pub mod ppi;
+pub mod preset;
+pub mod ram_cartridge;
+pub mod regions;
+pub mod renderer;
+pub mod romdb;
+pub mod rominfo;
+pub mod screen_reader;
+pub mod serial;
 pub mod slot;
+pub mod slot_check;
+pub mod slot_trace;
 pub mod sound;
+pub mod source_map;
+pub mod stack_guard;
+pub mod state;
+pub mod sunrise_ide;
+pub mod symbols;
+pub mod sysvars;
+pub mod t_states;
+pub mod tape;
+pub mod tile_cache;
+pub mod turbo_io;
+pub mod ui_snapshot;
 pub mod utils;
 pub mod vdp;
+pub mod vdp_timing;
+#[cfg(feature = "std-fs")]
+pub mod verify;
 
 pub use cpu::Z80;
-pub use internal_state::{InternalState, ReportState};
-pub use machine::{Msx, ProgramEntry};
+pub use internal_state::{flag_string, InternalState, ReportState};
+pub use machine::{Msx, ProgramEntry, RunExit, StopReason};
+pub use ui_snapshot::UiSnapshot;
 pub use utils::compare_slices;
 pub use vdp::TMS9918;
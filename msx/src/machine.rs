@@ -7,15 +7,96 @@ use derivative::Derivative;
 use serde::{Deserialize, Serialize};
 
 use crate::{
+    audio::Mixer,
+    bank_log::{BankLog, BankSwitch, MapperBanks},
+    basic,
+    bload::BinFile,
     bus::{Bus, MemorySegment},
+    cas::{Cas, CasRecorder},
     cpu::Z80,
+    debug_device::DebugDevice,
+    device::Device,
+    dos,
+    frame::FrameBuffer,
+    frame_capture::FrameCapture,
+    input_log::{InputEvent, InputLog},
     instruction::Instruction,
+    interrupt_stats::InterruptStats,
+    io_log::{IoEvent, IoLog},
+    keymap::Binding,
+    preset::{self, Preset},
+    renderer::Renderer,
+    serial::I8251,
     slot::SlotType,
+    sound::AY38910,
+    state::{self, MachineState},
+    t_states,
+    tape::{Tape, TapeStatus},
+    turbo_io,
+    ui_snapshot::UiSnapshot,
     utils::hexdump,
-    vdp::TMS9918,
+    vdp::{DisplayMode, TMS9918},
     InternalState, ReportState,
 };
 
+/// Number of steps that make a frame, until the VDP timing is emulated.
+pub const STEPS_PER_FRAME: u16 = 192;
+
+/// Why [`Msx::run_cycles`] returned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// the T-states asked for were run
+    Budget,
+    /// the CPU is halted for good, see [`Msx::halted`]
+    Halted,
+    /// the program asked the debug device to break
+    DebugBreak,
+}
+
+/// What a call to [`Msx::run_cycles`] ran.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RunExit {
+    /// T-states run, which go past the budget when its last instruction
+    /// doesn't fit in it
+    pub t_states: u32,
+    pub reason: StopReason,
+}
+
+/// How much of the machine [`Msx::reset_with`] reinitializes, each kind
+/// doing what the previous ones do as well.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ResetKind {
+    /// the reset button: the CPU, the chips, the slot selection and the
+    /// mapper banks, which the cartridges reset with the reset line; the
+    /// RAM is kept
+    #[default]
+    Soft,
+    /// the RAM and the memory of the mappers are filled with the power on
+    /// pattern
+    Hard,
+    /// what batteries keep through resets is lost, e.g. SRAM and clock
+    /// chips, unless it's persisted elsewhere
+    PowerCycle,
+}
+
+impl ResetKind {
+    pub const ALL: [ResetKind; 3] = [ResetKind::Soft, ResetKind::Hard, ResetKind::PowerCycle];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ResetKind::Soft => "soft",
+            ResetKind::Hard => "hard",
+            ResetKind::PowerCycle => "power",
+        }
+    }
+
+    pub fn find(name: &str) -> Option<ResetKind> {
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.name().eq_ignore_ascii_case(name))
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct ProgramEntry {
     pub address: u16,
@@ -55,7 +136,12 @@ pub struct Msx {
     pub break_on_mismatch: bool,
     pub track_flags: bool,
     pub previous_memory: Option<Vec<u8>>,
+    /// hash of the memory and the chips, see [`Msx::update_memory_hash`]
     pub memory_hash: u64,
+    /// runs the BIOS tape routines at once from the tape in the recorder,
+    /// see [`turbo_io`]
+    #[serde(skip)]
+    pub turbo_io: bool,
 }
 
 impl Default for Msx {
@@ -75,6 +161,7 @@ impl Default for Msx {
             breakpoints: Vec::new(),
             previous_memory: None,
             memory_hash: 0,
+            turbo_io: false,
             running: false,
         }
     }
@@ -118,6 +205,7 @@ impl Msx {
             breakpoints: Vec::new(),
             previous_memory: None,
             memory_hash: 0,
+            turbo_io: false,
             running: false,
         }
     }
@@ -127,6 +215,27 @@ impl Msx {
         bus.load_rom(slot, data);
     }
 
+    /// Sets the machine up as the web version does: the ROM in slot 0, RAM
+    /// in slot 3 and the other slots empty. Without a preset, the RAM is 64K
+    /// and the VDP runs at 60 Hz.
+    pub fn load_machine(&mut self, rom: &[u8], preset: Option<&Preset>) {
+        {
+            let mut bus = self.bus.write().unwrap();
+            bus.load_rom(0, rom);
+            bus.load_empty(1);
+            bus.load_empty(2);
+            match preset {
+                Some(preset) => *bus.slot_mut(3).unwrap() = preset.ram(),
+                None => bus.load_ram(3),
+            }
+            bus.vdp.pal = false;
+        }
+        self.cpu.stock_timing = false;
+        if let Some(preset) = preset {
+            preset.apply(self);
+        }
+    }
+
     pub fn load_ram(&mut self, slot: u8) {
         let mut bus = self.bus.write().unwrap();
         bus.load_ram(slot);
@@ -165,8 +274,10 @@ impl Msx {
         self.cpu.pc
     }
 
+    /// Whether the CPU is halted for good, by a HALT with interrupts
+    /// disabled. With them enabled, it only waits for the next interrupt.
     pub fn halted(&self) -> bool {
-        self.cpu.halted
+        self.cpu.halted && !self.cpu.iff1
     }
 
     pub fn set_a(&mut self, value: u8) {
@@ -209,10 +320,68 @@ impl Msx {
         self.cpu.memory()
     }
 
+    // what the A/B comparisons check besides the CPU registers, with the
+    // name of its addresses and whether they're shown in hex: the memory
+    // seen by the CPU outside of the pages of ROMs, which differ between
+    // the ROMs compared, the VRAM and the chip registers
+    fn compared_state(&self) -> [(&'static str, Vec<u8>, bool); 4] {
+        let bus = self.bus.read().unwrap();
+        let mut memory = vec![0; 0x10000];
+        for (page, memory) in memory.chunks_mut(0x4000).enumerate() {
+            let slot = (bus.primary_slot_config() >> (page * 2)) & 0x03;
+            if !matches!(bus.slot(slot), Some(SlotType::Rom(_))) {
+                bus.read_block(page as u16 * 0x4000, memory);
+            }
+        }
+        [
+            ("memory at", memory, true),
+            ("VRAM at", bus.vdp.vram.to_vec(), true),
+            ("VDP register", bus.vdp.registers.to_vec(), false),
+            ("PSG register", bus.psg.registers().to_vec(), false),
+        ]
+    }
+
+    /// Hashes the memory seen by the CPU outside of the ROMs, the VRAM and
+    /// the registers of the VDP and the PSG into `memory_hash`, for the A/B comparisons to tell
+    /// at each step whether anything diverged besides the CPU registers.
+    pub fn update_memory_hash(&mut self) -> u64 {
+        let mut hasher = crc32fast::Hasher::new();
+        for (_, data, _) in self.compared_state() {
+            hasher.update(&data);
+        }
+        self.memory_hash = hasher.finalize() as u64;
+        self.memory_hash
+    }
+
+    /// The first address of the memory, the VRAM or the chip registers
+    /// hashed by [`Msx::update_memory_hash`] that differs from the other
+    /// machine, e.g. "memory at 0xC000: 0x01, 0x02 on the other".
+    pub fn first_difference(&self, other: &Msx) -> Option<String> {
+        let states = self
+            .compared_state()
+            .into_iter()
+            .zip(other.compared_state());
+        for ((name, data, hex), (_, other_data, _)) in states {
+            let Some(index) = data.iter().zip(&other_data).position(|(a, b)| a != b) else {
+                continue;
+            };
+            let address = if hex {
+                format!("{:#06X}", index)
+            } else {
+                index.to_string()
+            };
+            return Some(format!(
+                "{} {}: {:#04X}, {:#04X} on the other",
+                name, address, data[index], other_data[index]
+            ));
+        }
+        None
+    }
+
     pub fn vram_dump(&self) -> String {
         let bus = self.bus.read().unwrap();
         let vdp = bus.vdp.clone();
-        hexdump(&vdp.vram, 0, 0x4000)
+        hexdump(&vdp.vram[..], 0, 0x4000)
     }
 
     pub fn instruction(&mut self) -> ProgramEntry {
@@ -247,6 +416,24 @@ impl Msx {
         program
     }
 
+    /// Disassembles a number of instructions from an address on.
+    pub fn disassemble(&self, start: u16, count: usize) -> Vec<ProgramEntry> {
+        let mut pc = start;
+        (0..count)
+            .map(|_| {
+                let instr = Instruction::parse_at(&self.cpu, pc);
+                let entry = ProgramEntry {
+                    address: pc,
+                    instruction: instr.name().to_string(),
+                    data: instr.opcode_with_args(),
+                    dump: None,
+                };
+                pc = pc.wrapping_add(instr.len() as u16);
+                entry
+            })
+            .collect()
+    }
+
     pub fn program(&self) -> Vec<ProgramEntry> {
         let mut program = Vec::new();
         let mut pc = self.cpu.pc;
@@ -273,21 +460,526 @@ impl Msx {
         program
     }
 
-    #[allow(unused)]
+    /// Resets the machine, see [`ResetKind`].
+    pub fn reset_with(&mut self, kind: ResetKind) {
+        match kind {
+            ResetKind::Soft => self.reset(),
+            ResetKind::Hard => self.hard_reset(),
+            ResetKind::PowerCycle => self.power_cycle(),
+        }
+    }
+
+    /// Soft reset: the CPU, the devices and the slots are reset, the RAM
+    /// is kept.
     pub fn reset(&mut self) {
         self.cpu.reset();
         let mut bus = self.bus.write().unwrap();
         bus.reset();
     }
 
+    /// Hard reset: the RAM is cleared as well.
+    pub fn hard_reset(&mut self) {
+        self.reset();
+        self.current_scanline = 0;
+        let mut bus = self.bus.write().unwrap();
+        bus.clear_ram();
+    }
+
+    /// Power cycle: the memory and clocks batteries keep are lost as well.
+    pub fn power_cycle(&mut self) {
+        self.hard_reset();
+        let mut bus = self.bus.write().unwrap();
+        bus.power_off();
+    }
+
+    /// Inserts a cartridge (or any slot contents) while running, returning
+    /// the previous contents of the slot.
+    pub fn insert_cart(&mut self, slot: u8, cart: SlotType) -> anyhow::Result<SlotType> {
+        let mut bus = self.bus.write().unwrap();
+        bus.insert_slot(slot, cart)
+    }
+
+    /// Attaches an I/O device to the given ports, see [`Bus::attach_device`].
+    pub fn attach_device(
+        &mut self,
+        ports: impl IntoIterator<Item = u8>,
+        device: Box<dyn Device>,
+    ) -> anyhow::Result<usize> {
+        let mut bus = self.bus.write().unwrap();
+        bus.attach_device(ports, device)
+    }
+
+    /// Contents of the four primary slots.
+    pub fn slots(&self) -> Vec<SlotType> {
+        let bus = self.bus.read().unwrap();
+        (0..4).filter_map(|n| bus.slot(n).cloned()).collect()
+    }
+
+    /// Removes the cartridge from a slot, leaving it empty.
+    pub fn eject_cart(&mut self, slot: u8) -> anyhow::Result<SlotType> {
+        self.insert_cart(slot, SlotType::Empty)
+    }
+
+    /// Puts a tape in the recorder, replacing any, its motor started and
+    /// stopped by the PPI from now on.
+    pub fn insert_tape(&mut self, mut tape: Tape) {
+        let mut bus = self.bus.write().unwrap();
+        let clock = bus.clock();
+        tape.set_clock(clock);
+        tape.set_motor(clock, bus.ppi.cassette_motor());
+        bus.tape = Some(tape);
+    }
+
+    /// Takes the tape out of the recorder, if any.
+    pub fn eject_tape(&mut self) -> Option<Tape> {
+        let mut bus = self.bus.write().unwrap();
+        bus.psg.set_cassette_input(false);
+        bus.tape.take()
+    }
+
+    pub fn rewind_tape(&mut self) -> anyhow::Result<()> {
+        self.with_tape(|tape| tape.rewind())
+    }
+
+    /// Demodulates the tape again at a baud rate, or at the one detected
+    /// from the recording.
+    pub fn set_tape_baud(&mut self, baud: Option<u32>) -> anyhow::Result<()> {
+        self.with_tape(|tape| tape.set_baud(baud))
+    }
+
+    /// Where the tape is, `None` without one.
+    pub fn tape_status(&self) -> Option<TapeStatus> {
+        let mut bus = self.bus.write().unwrap();
+        let clock = bus.clock();
+        let tape = bus.tape.as_mut()?;
+        tape.sync(clock);
+        Some(tape.status())
+    }
+
+    /// Starts recording the blocks saved on the cassette output, as CSAVE
+    /// and BSAVE "CAS:" do, dropping those recorded before.
+    pub fn start_tape_recording(&mut self) {
+        let mut bus = self.bus.write().unwrap();
+        bus.tape_recorder = Some(CasRecorder::new());
+    }
+
+    /// The blocks recorded so far, `None` when not recording.
+    pub fn tape_recording(&self) -> Option<Cas> {
+        let bus = self.bus.read().unwrap();
+        bus.tape_recorder
+            .as_ref()
+            .map(|recorder| recorder.cas().clone())
+    }
+
+    /// Stops recording, returning the blocks recorded.
+    pub fn stop_tape_recording(&mut self) -> Option<Cas> {
+        let mut bus = self.bus.write().unwrap();
+        bus.tape_recorder
+            .take()
+            .map(|recorder| recorder.cas().clone())
+    }
+
+    fn with_tape(&mut self, f: impl FnOnce(&mut Tape)) -> anyhow::Result<()> {
+        let mut bus = self.bus.write().unwrap();
+        let clock = bus.clock();
+        let Some(tape) = &mut bus.tape else {
+            anyhow::bail!("No tape in the recorder");
+        };
+        tape.sync(clock);
+        f(tape);
+        Ok(())
+    }
+
+    /// What a frontend shows of the machine, cheap enough to take on every
+    /// frame, see [`UiSnapshot`].
+    pub fn ui_snapshot(&self) -> UiSnapshot {
+        UiSnapshot::new(&self.cpu)
+    }
+
+    /// Draws the screen as the VDP shows it now into the frame.
+    pub fn render(&self, frame: &mut FrameBuffer) {
+        let mut bus = self.bus.write().unwrap();
+        let mut renderer = Renderer::new(&mut bus.vdp);
+        renderer.draw(0, 0, 256, 192);
+        frame.update(&renderer.screen_buffer);
+    }
+
     pub fn vdp(&self) -> TMS9918 {
         let bus = self.bus.read().unwrap();
         bus.vdp.clone()
     }
 
+    pub fn psg(&self) -> AY38910 {
+        let bus = self.bus.read().unwrap();
+        bus.psg.clone()
+    }
+
     pub fn step(&mut self) {
-        self.cpu.execute_cycle();
-        self.current_scanline = (self.current_scanline + 1) % 192;
+        self.step_t_states();
+    }
+
+    // runs an instruction, returning the T-states it took
+    fn step_t_states(&mut self) -> u32 {
+        let pc = self.cpu.pc;
+        let takes_interrupt = self.cpu.takes_interrupt();
+        let quick = self.turbo_io && !takes_interrupt;
+        let t_states = match quick.then(|| turbo_io::call(&mut self.cpu)).flatten() {
+            Some(t_states) => t_states,
+            None => {
+                let timing = t_states::timing(&self.cpu);
+                self.cpu.execute_cycle();
+                timing.taken(pc, &self.cpu)
+            }
+        };
+        self.current_scanline = (self.current_scanline + 1) % STEPS_PER_FRAME;
+
+        let mut guard = self.bus.write().unwrap();
+        let bus = &mut *guard;
+        let clock = bus.clock();
+        let irq = bus.tick(t_states);
+        let nmi = bus.take_nmi();
+        bus.interrupt_stats.update(&bus.vdp, clock, takes_interrupt);
+        if let Some(io_log) = &mut bus.io_log {
+            io_log.set_pc(self.cpu.pc);
+            if self.current_scanline == 0 {
+                io_log.next_frame();
+            }
+        }
+        if let Some(bank_log) = &mut bus.bank_log {
+            bank_log.set_pc(self.cpu.pc);
+            if self.current_scanline == 0 {
+                bank_log.next_frame();
+            }
+        }
+        drop(guard);
+        if irq {
+            self.cpu.request_interrupt();
+        }
+        if nmi {
+            self.cpu.request_nmi();
+        }
+        t_states
+    }
+
+    /// Runs instructions until they took `t_states`, counting the MSX wait
+    /// states, or until the CPU halts or the program asks the debug device to
+    /// break. The break request is left for [`Msx::debug_device_output`].
+    pub fn run_cycles(&mut self, t_states: u32) -> RunExit {
+        let mut run = 0;
+        while run < t_states {
+            if self.halted() {
+                return RunExit {
+                    t_states: run,
+                    reason: StopReason::Halted,
+                };
+            }
+
+            run += self.step_t_states();
+
+            if self.debug_break_requested() {
+                return RunExit {
+                    t_states: run,
+                    reason: StopReason::DebugBreak,
+                };
+            }
+        }
+
+        RunExit {
+            t_states: run,
+            reason: StopReason::Budget,
+        }
+    }
+
+    fn debug_break_requested(&self) -> bool {
+        let bus = self.bus.read().unwrap();
+        bus.debug_device
+            .as_ref()
+            .is_some_and(|device| device.break_requested())
+    }
+
+    /// Steps through the rest of the video frame, until the VDP counts the
+    /// next one as the beam enters its VBlank, see [`Msx::frames`], or the
+    /// CPU halts.
+    pub fn step_frame(&mut self) {
+        let frame = self.frames();
+        loop {
+            self.step();
+            if self.frames() != frame || self.halted() {
+                break;
+            }
+        }
+    }
+
+    /// Runs to the start of the next frame and through it, capturing the VDP
+    /// registers as each line starts. Stops as the last line starts,
+    /// ignoring the breakpoints.
+    pub fn capture_frame(&mut self) -> FrameCapture {
+        let mut capture = FrameCapture::new(self);
+        while !capture.record(self) {
+            self.step();
+        }
+        capture
+    }
+
+    pub fn keyboard_matrix(&self) -> [u8; 11] {
+        let bus = self.bus.read().unwrap();
+        bus.ppi.keyboard_matrix()
+    }
+
+    pub fn set_keyboard_matrix(&mut self, matrix: [u8; 11]) {
+        let mut bus = self.bus.write().unwrap();
+        if bus.input_log.is_some() {
+            let before = bus.ppi.keyboard_matrix();
+            for (row, (old, new)) in before.iter().zip(matrix).enumerate() {
+                for bit in (0..8).filter(|bit| (old ^ new) & 1 << bit != 0) {
+                    // keys are down when their bit is low
+                    self.log_input(&mut bus, Binding::Key(row, bit), new & 1 << bit == 0);
+                }
+            }
+        }
+        bus.ppi.set_keyboard_matrix(matrix);
+    }
+
+    pub fn set_key(&mut self, row: usize, bit: u8, pressed: bool) {
+        let mut bus = self.bus.write().unwrap();
+        if bus.input_log.is_some() && row < bus.ppi.keyboard_matrix().len() {
+            let down = bus.ppi.keyboard_matrix()[row] & 1 << bit == 0;
+            if down != pressed {
+                self.log_input(&mut bus, Binding::Key(row, bit), pressed);
+            }
+        }
+        bus.ppi.set_key(row, bit, pressed);
+    }
+
+    /// Presses or releases a line of joystick port 1, a bit of PSG port A.
+    pub fn set_joystick(&mut self, line: u8, pressed: bool) {
+        let mut bus = self.bus.write().unwrap();
+        let joystick = bus.psg.joystick();
+        if bus.input_log.is_some() && (joystick & 1 << line != 0) != pressed {
+            self.log_input(&mut bus, Binding::Joystick(line), pressed);
+        }
+        bus.psg.set_joystick(if pressed {
+            joystick | 1 << line
+        } else {
+            joystick & !(1 << line)
+        });
+    }
+
+    // records a change of the keyboard or joystick in the input log
+    fn log_input(&self, bus: &mut Bus, input: Binding, pressed: bool) {
+        let frame = bus.vdp.frames_until(bus.clock());
+        if let Some(input_log) = &mut bus.input_log {
+            input_log.record(input, pressed, frame, self.cpu.cycles, self.cpu.pc);
+        }
+    }
+
+    /// Triggers a non-maskable interrupt, as the NMI line of the cartridge
+    /// slots would. The CPU takes it before the next instruction.
+    pub fn nmi(&mut self) {
+        self.cpu.request_nmi();
+    }
+
+    /// Copies a BLOAD binary to its addresses, through the current slot
+    /// configuration, optionally jumping to its execution address.
+    pub fn load_bin(&mut self, bin: &BinFile, run: bool) {
+        for (i, byte) in bin.data.iter().enumerate() {
+            self.cpu.write_byte(bin.start.wrapping_add(i as u16), *byte);
+        }
+
+        if run {
+            self.cpu.pc = bin.exec;
+            self.cpu.halted = false;
+        }
+    }
+
+    fn read_word(&self, address: u16) -> u16 {
+        u16::from_le_bytes([self.get_memory(address), self.get_memory(address + 1)])
+    }
+
+    fn write_word(&mut self, address: u16, value: u16) {
+        let [lo, hi] = value.to_le_bytes();
+        self.set_memory(address, lo);
+        self.set_memory(address + 1, hi);
+    }
+
+    /// Whether the BIOS has set up the keyboard buffer pointers.
+    pub fn key_buffer_ready(&self) -> bool {
+        let buffer = basic::KEYBUF..basic::KEYBUF + basic::KEYBUF_SIZE;
+        buffer.contains(&self.read_word(basic::PUTPNT))
+            && buffer.contains(&self.read_word(basic::GETPNT))
+    }
+
+    /// Puts a character in the BIOS keyboard buffer, as if it was typed.
+    /// Returns false when the buffer is full.
+    pub fn push_key_buffer(&mut self, ch: u8) -> bool {
+        if !self.key_buffer_ready() {
+            return false;
+        }
+
+        let put = self.read_word(basic::PUTPNT);
+        let mut next = put + 1;
+        if next == basic::KEYBUF + basic::KEYBUF_SIZE {
+            next = basic::KEYBUF;
+        }
+        if next == self.read_word(basic::GETPNT) {
+            return false;
+        }
+
+        self.set_memory(put, ch);
+        self.write_word(basic::PUTPNT, next);
+        true
+    }
+
+    /// Copies a tokenized BASIC program to the program area of an
+    /// initialized BASIC, as a `LOAD` would do.
+    pub fn load_basic(&mut self, program: &[u8]) {
+        let start = self.read_word(basic::TXTTAB);
+        let program = basic::relocate(program, start);
+        for (i, byte) in program.iter().enumerate() {
+            self.set_memory(start.wrapping_add(i as u16), *byte);
+        }
+
+        let end = start.wrapping_add(program.len() as u16);
+        self.write_word(basic::VARTAB, end);
+        self.write_word(basic::ARYTAB, end);
+        self.write_word(basic::STREND, end);
+    }
+
+    /// The rows of the name table in the text modes (SCREEN 0 and 1), with
+    /// the characters outside of printable ASCII as dots. Empty in the
+    /// graphic modes.
+    pub fn screen_text(&self) -> Vec<String> {
+        let bus = self.bus.read().unwrap();
+        let columns = match bus.vdp.display_mode {
+            DisplayMode::Text1 => 40,
+            DisplayMode::Graphic1 => 32,
+            DisplayMode::Graphic2 | DisplayMode::Multicolor => return Vec::new(),
+        };
+        let (base, size) = bus.vdp.name_table_base_and_size();
+        bus.vdp.vram[base..base + size]
+            .chunks(columns)
+            .map(|row| {
+                let text: String = row
+                    .iter()
+                    .map(|&ch| match ch {
+                        0x20..=0x7E => ch as char,
+                        _ => '.',
+                    })
+                    .collect();
+                text.trim_end().to_string()
+            })
+            .collect()
+    }
+
+    /// The BIOS cursor position, as the 1-based (column, row).
+    pub fn cursor(&self) -> (u8, u8) {
+        (self.get_memory(dos::CSRX), self.get_memory(dos::CSRY))
+    }
+
+    /// Serializes the CPU and the whole bus, including slot contents but the
+    /// device slots, saved empty, in the current save state format, see
+    /// [`state::STATE_VERSION`].
+    pub fn save_state(&self) -> anyhow::Result<Vec<u8>> {
+        let mut bus = self.bus.read().unwrap().clone();
+        bus.slots.iter_mut().for_each(SlotType::remove_devices);
+        let state = MachineState {
+            cpu: self.cpu.clone(),
+            bus: Box::new(bus),
+            current_scanline: self.current_scanline,
+        };
+        state::encode(&state)
+    }
+
+    /// Restores a state produced by `save_state`, by this or an older
+    /// version.
+    pub fn load_state(&mut self, data: &[u8]) -> anyhow::Result<()> {
+        let state = state::decode(data)?;
+        let mut bus = self.bus.write().unwrap();
+        let debug_device = bus.debug_device.take();
+        let io_log = bus.io_log.take();
+        let bank_log = bus.bank_log.take();
+        let input_log = bus.input_log.take();
+        let mut mixer = bus.mixer.take();
+        let mut tape = bus.tape.take();
+        let tape_recorder = bus.tape_recorder.take();
+        let devices = std::mem::take(&mut bus.devices);
+        let ports = std::mem::take(&mut bus.ports);
+        let slots = std::mem::replace(&mut bus.slots, std::array::from_fn(|_| SlotType::Empty));
+        let bios_slot = bus.bios_slot;
+        *bus = *state.bus;
+        for (slot, previous) in bus.slots.iter_mut().zip(slots) {
+            slot.restore_devices(previous);
+        }
+        bus.bios_slot = bios_slot;
+        bus.devices = devices;
+        bus.ports = ports;
+        bus.debug_device = debug_device;
+        bus.io_log = io_log;
+        bus.bank_log = bank_log;
+        bus.input_log = input_log;
+        if let Some(mixer) = &mut mixer {
+            mixer.set_key_click(bus.clock(), bus.ppi.key_click());
+        }
+        bus.mixer = mixer;
+        // the tape stays where it is, the motor as the loaded PPI has it
+        if let Some(tape) = &mut tape {
+            tape.set_clock(bus.clock());
+            tape.set_motor(bus.clock(), bus.ppi.cassette_motor());
+        }
+        bus.tape = tape;
+        bus.tape_recorder = tape_recorder;
+        drop(bus);
+
+        let mut cpu = state.cpu;
+        cpu.bus = self.bus.clone();
+        self.cpu = cpu;
+        self.current_scanline = state.current_scanline;
+
+        Ok(())
+    }
+
+    /// CRC32 of the registers, memory and VRAM, used to detect when two
+    /// machines that should be running in sync diverge.
+    pub fn checksum(&self) -> u32 {
+        let cpu = &self.cpu;
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&[
+            cpu.a, cpu.f, cpu.b, cpu.c, cpu.d, cpu.e, cpu.h, cpu.l, cpu.a_alt, cpu.f_alt,
+            cpu.b_alt, cpu.c_alt, cpu.d_alt, cpu.e_alt, cpu.h_alt, cpu.l_alt,
+        ]);
+        hasher.update(&cpu.sp.to_le_bytes());
+        hasher.update(&cpu.pc.to_le_bytes());
+        hasher.update(&cpu.ix.to_le_bytes());
+        hasher.update(&cpu.iy.to_le_bytes());
+        hasher.update(&self.memory());
+        hasher.update(&self.vram());
+        hasher.finalize()
+    }
+
+    /// T-states run since the reset.
+    pub fn clock(&self) -> u64 {
+        self.bus.read().unwrap().clock()
+    }
+
+    /// Whether the VDP draws 50 frames a second, instead of 60.
+    pub fn pal(&self) -> bool {
+        self.bus.read().unwrap().vdp.pal
+    }
+
+    /// Switches between 50 and 60 Hz while running: the VDP, its frame
+    /// interrupts, and the frequency the ID bytes of the BIOS tell
+    /// programs, see [`Preset::apply`].
+    pub fn set_pal(&mut self, pal: bool) {
+        let mut bus = self.bus.write().unwrap();
+        bus.set_pal(pal);
+        preset::set_bios_frequency(&mut bus, pal);
+    }
+
+    /// Frames the VDP drew since the reset, counted when the beam enters
+    /// their VBlank.
+    pub fn frames(&self) -> u64 {
+        let bus = self.bus.read().unwrap();
+        bus.vdp.frames_until(bus.clock())
     }
 
     pub fn primary_slot_config(&self) -> u8 {
@@ -300,12 +992,439 @@ impl Msx {
         bus.memory_segments()
     }
 
+    /// Attaches an RS-232 interface, an i8251 on ports 0x80 and 0x81, for a
+    /// link cable. Without it nothing answers on those ports, as on machines
+    /// without one.
+    pub fn enable_serial(&mut self) -> anyhow::Result<()> {
+        let mut bus = self.bus.write().unwrap();
+        bus.attach_device([0x80, 0x81], Box::new(I8251::new()))?;
+        Ok(())
+    }
+
+    pub fn link_connected(&mut self, connected: bool) {
+        let mut bus = self.bus.write().unwrap();
+        if let Some(serial) = bus.attached_mut::<I8251>() {
+            serial.set_connected(connected);
+        }
+    }
+
+    pub fn link_receive(&mut self, data: &[u8]) {
+        let mut bus = self.bus.write().unwrap();
+        if let Some(serial) = bus.attached_mut::<I8251>() {
+            serial.receive(data);
+        }
+    }
+
+    pub fn link_transmit(&mut self) -> Vec<u8> {
+        let mut bus = self.bus.write().unwrap();
+        bus.attached_mut::<I8251>()
+            .map(I8251::take_transmitted)
+            .unwrap_or_default()
+    }
+
+    /// Enables the debug device on ports 0x2E and 0x2F, see
+    /// [`DebugDevice`]. It counts frames at the frequency of the VDP.
+    pub fn enable_debug_device(&mut self) {
+        let mut bus = self.bus.write().unwrap();
+        let mut debug_device = DebugDevice::new();
+        debug_device.pal = bus.vdp.pal;
+        debug_device.origin = bus.vdp.origin;
+        bus.debug_device = Some(debug_device);
+    }
+
+    /// Takes what the program printed on the debug device, and whether it
+    /// asked to break.
+    pub fn debug_device_output(&mut self) -> (String, bool) {
+        let mut bus = self.bus.write().unwrap();
+        match &mut bus.debug_device {
+            Some(device) => (device.take_output(), device.take_break()),
+            None => (String::new(), false),
+        }
+    }
+
+    /// Starts logging the I/O port accesses, see [`IoLog`].
+    pub fn enable_io_log(&mut self) {
+        let mut bus = self.bus.write().unwrap();
+        let mut io_log = IoLog::default();
+        io_log.set_pc(self.cpu.pc);
+        bus.io_log = Some(io_log);
+    }
+
+    /// The logged I/O port accesses, oldest first.
+    pub fn io_events(&self) -> Vec<IoEvent> {
+        let bus = self.bus.read().unwrap();
+        bus.io_log
+            .as_ref()
+            .map(|io_log| io_log.events().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// Starts logging the presses and releases of the keyboard and the
+    /// joystick, see [`InputLog`].
+    pub fn enable_input_log(&mut self) {
+        self.bus.write().unwrap().input_log = Some(InputLog::default());
+    }
+
+    /// Stops logging the input, dropping the events logged.
+    pub fn disable_input_log(&mut self) {
+        self.bus.write().unwrap().input_log = None;
+    }
+
+    pub fn input_log_enabled(&self) -> bool {
+        self.bus.read().unwrap().input_log.is_some()
+    }
+
+    /// Drops the logged input events, logging on.
+    pub fn clear_input_log(&mut self) {
+        if let Some(input_log) = &mut self.bus.write().unwrap().input_log {
+            input_log.clear();
+        }
+    }
+
+    /// The logged input events, oldest first.
+    pub fn input_events(&self) -> Vec<InputEvent> {
+        let bus = self.bus.read().unwrap();
+        bus.input_log
+            .as_ref()
+            .map(|input_log| input_log.events().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// Removes the logged I/O port accesses, oldest first, for those
+    /// following the log as the machine runs.
+    pub fn take_io_events(&mut self) -> Vec<IoEvent> {
+        let mut bus = self.bus.write().unwrap();
+        bus.io_log
+            .as_mut()
+            .map(|io_log| io_log.drain().collect())
+            .unwrap_or_default()
+    }
+
+    /// How the VDP interrupts went, see [`InterruptStats`].
+    pub fn interrupt_stats(&self) -> InterruptStats {
+        self.bus.read().unwrap().interrupt_stats
+    }
+
+    /// Starts logging the bank switches of the mappers, see [`BankLog`].
+    pub fn enable_bank_log(&mut self) {
+        let mut bus = self.bus.write().unwrap();
+        let mut bank_log = BankLog::default();
+        bank_log.set_pc(self.cpu.pc);
+        bus.bank_log = Some(bank_log);
+    }
+
+    /// Stops logging the bank switches, dropping those logged.
+    pub fn disable_bank_log(&mut self) {
+        self.bus.write().unwrap().bank_log = None;
+    }
+
+    pub fn bank_log_enabled(&self) -> bool {
+        self.bus.read().unwrap().bank_log.is_some()
+    }
+
+    /// Drops the logged bank switches, logging on.
+    pub fn clear_bank_log(&mut self) {
+        if let Some(bank_log) = &mut self.bus.write().unwrap().bank_log {
+            bank_log.clear();
+        }
+    }
+
+    /// The logged bank switches, oldest first.
+    pub fn bank_switches(&self) -> Vec<BankSwitch> {
+        let bus = self.bus.read().unwrap();
+        bus.bank_log
+            .as_ref()
+            .map(|bank_log| bank_log.switches().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// The banks selected by the mappers in the slots, those without one
+    /// left out.
+    pub fn mapper_banks(&self) -> Vec<MapperBanks> {
+        let bus = self.bus.read().unwrap();
+        (0..4)
+            .filter_map(|n| {
+                let slot = bus.slot(n)?.as_slot()?;
+                let banks = slot.banks();
+                (!banks.is_empty()).then(|| MapperBanks {
+                    slot: n,
+                    name: slot.name().to_string(),
+                    banks,
+                })
+            })
+            .collect()
+    }
+
+    /// Starts mixing the sound, see [`Mixer`].
+    pub fn enable_audio(&mut self) {
+        let mut bus = self.bus.write().unwrap();
+        let mut mixer = Mixer::new();
+        mixer.set_key_click(bus.clock(), bus.ppi.key_click());
+        bus.mixer = Some(mixer);
+    }
+
+    /// Sets the master and key click volumes, from 0 to 1.
+    pub fn set_volume(&mut self, volume: f32, key_click_volume: f32) {
+        let mut bus = self.bus.write().unwrap();
+        if let Some(mixer) = &mut bus.mixer {
+            mixer.volume = volume;
+            mixer.key_click_volume = key_click_volume;
+        }
+    }
+
+    /// The sound since the last call, at the sample rate in Hz, or nothing
+    /// if the audio isn't enabled.
+    pub fn audio_samples(&mut self, sample_rate: f32) -> Vec<f32> {
+        let mut bus = self.bus.write().unwrap();
+        let clock = bus.clock();
+        match &mut bus.mixer {
+            Some(mixer) => mixer.samples(clock, sample_rate),
+            None => Vec::new(),
+        }
+    }
+
     pub fn wrote_to_ppi(&self) -> bool {
         let mut bus = self.bus.write().unwrap();
         bus.wrote_to_ppi()
     }
 
+    /// Whether the last instructions read or wrote the VRAM, clearing it.
+    pub fn accessed_vram(&self) -> bool {
+        let mut bus = self.bus.write().unwrap();
+        bus.accessed_vram()
+    }
+
+    /// The address last read from an empty slot since the last call, if
+    /// any, see [`SlotChecker`](crate::slot_check::SlotChecker).
+    pub fn empty_slot_read(&self) -> Option<u16> {
+        let mut bus = self.bus.write().unwrap();
+        bus.empty_slot_read()
+    }
+
+    /// Whether the last instructions wrote the primary slot register,
+    /// clearing it.
+    pub fn wrote_slot_register(&self) -> bool {
+        let mut bus = self.bus.write().unwrap();
+        bus.wrote_slot_register()
+    }
+
+    /// Whether the VDP shows the screen, blanking it otherwise.
+    pub fn display_enabled(&self) -> bool {
+        let bus = self.bus.read().unwrap();
+        bus.vdp.registers[1] & 0x40 != 0
+    }
+
     // pub fn is_at_instruction(&self, opcode: u8) -> bool {
     //     self.cpu.memory()[self.cpu.pc as usize] == opcode
     // }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ram_cartridge::RamCartridge, slot::RamSlot};
+
+    fn machine(program: &[u8]) -> Msx {
+        let mut msx = Msx::new(&[
+            SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Empty,
+        ]);
+        for (i, byte) in program.iter().enumerate() {
+            msx.cpu.write_byte(i as u16, *byte);
+        }
+        msx
+    }
+
+    #[test]
+    fn test_nmi() {
+        // EI / HALT, with RETN at the NMI handler
+        let mut program = vec![0x00; 0x68];
+        program[..2].copy_from_slice(&[0xFB, 0x76]);
+        program[0x66..].copy_from_slice(&[0xED, 0x45]);
+        let mut msx = machine(&program);
+        msx.cpu.sp = 0xF000;
+        msx.step();
+        msx.step();
+        assert!(msx.cpu.halted);
+
+        // taken with interrupts enabled or not, waking the CPU
+        msx.nmi();
+        assert!(!msx.cpu.halted);
+        msx.step();
+        assert_eq!(msx.pc(), 0x0066);
+        assert!(!msx.cpu.iff1);
+        assert!(msx.cpu.iff2);
+        assert_eq!(msx.cpu.read_word(msx.cpu.sp), 0x0002);
+
+        // RETN enables the interrupts again
+        msx.step();
+        assert_eq!(msx.pc(), 0x0002);
+        assert!(msx.cpu.iff1);
+    }
+
+    #[test]
+    fn test_first_difference() {
+        let mut a = machine(&[]);
+        let mut b = machine(&[]);
+        assert_eq!(a.update_memory_hash(), b.update_memory_hash());
+        assert_eq!(a.first_difference(&b), None);
+
+        b.bus.write().unwrap().psg.write(0xA0, 7);
+        b.bus.write().unwrap().psg.write(0xA1, 0xB8);
+        assert_ne!(a.update_memory_hash(), b.update_memory_hash());
+        assert_eq!(
+            a.first_difference(&b).unwrap(),
+            "PSG register 7: 0x00, 0xB8 on the other"
+        );
+        a.set_memory(0xC000, 0x01);
+        b.set_memory(0xC000, 0x02);
+        assert_eq!(
+            a.first_difference(&b).unwrap(),
+            "memory at 0xC000: 0x01, 0x02 on the other"
+        );
+    }
+
+    #[test]
+    fn test_reset_kinds() {
+        for kind in ResetKind::ALL {
+            let mut msx = Msx::new(&[
+                SlotType::Empty,
+                SlotType::Empty,
+                RamCartridge::MemoryMapper(0x20000).slot(),
+                SlotType::Empty,
+            ]);
+            let mut bus = msx.bus.write().unwrap();
+            bus.ppi.primary_slot_config = 0xAA;
+            bus.output(0xFE, 5);
+            bus.write_byte(0x8000, 0x42);
+            drop(bus);
+
+            msx.reset_with(kind);
+            let mut bus = msx.bus.write().unwrap();
+            // the slot selection and the banks are reset on any reset
+            assert_eq!(bus.primary_slot_config(), 0x00, "{:?}", kind);
+            assert_eq!(bus.input(0xFE), 0xF9, "{:?}", kind);
+            bus.ppi.primary_slot_config = 0xAA;
+            bus.output(0xFE, 5);
+            let kept = if kind == ResetKind::Soft { 0x42 } else { 0xFF };
+            assert_eq!(bus.read_byte(0x8000), kept, "{:?}", kind);
+        }
+        assert_eq!(ResetKind::find("POWER"), Some(ResetKind::PowerCycle));
+        assert_eq!(ResetKind::find("warm"), None);
+    }
+
+    #[test]
+    fn test_screen_text() {
+        let msx = machine(&[0x00]);
+        {
+            let mut bus = msx.bus.write().unwrap();
+            bus.vdp.vram[..80].fill(b' ');
+            bus.vdp.vram[..2].copy_from_slice(b"A>");
+            bus.vdp.vram[40..43].copy_from_slice(&[b'O', b'K', 0xFF]);
+        }
+        let text = msx.screen_text();
+        assert_eq!(text.len(), 24);
+        assert_eq!(text[0], "A>");
+        assert_eq!(text[1], "OK.");
+
+        msx.bus.write().unwrap().vdp.display_mode = DisplayMode::Graphic2;
+        assert!(msx.screen_text().is_empty());
+    }
+
+    #[test]
+    fn test_force_mode() {
+        let msx = machine(&[0x00]);
+        let force = |mode| msx.bus.write().unwrap().vdp.force_mode(mode);
+
+        // M3 selects Graphic2, where the text isn't decoded
+        msx.bus.write().unwrap().vdp.registers[0] = 0x02;
+        force(None);
+        assert!(msx.screen_text().is_empty());
+
+        force(Some(DisplayMode::Text1));
+        assert_eq!(msx.screen_text().len(), 24);
+        {
+            let bus = msx.bus.read().unwrap();
+            assert_eq!(bus.vdp.registers[0], 0x02);
+            assert_eq!(bus.vdp.register_mode(), DisplayMode::Graphic2);
+            assert_eq!(bus.vdp.forced_mode(), Some(&DisplayMode::Text1));
+        }
+
+        force(None);
+        assert!(msx.screen_text().is_empty());
+        assert_eq!(DisplayMode::find("G1"), Some(DisplayMode::Graphic1));
+    }
+
+    #[test]
+    fn test_run_cycles_budget() {
+        // NOPs, 5 T-states each with the wait state
+        let mut msx = machine(&[0x00; 16]);
+        let exit = msx.run_cycles(50);
+        assert_eq!(
+            exit,
+            RunExit {
+                t_states: 50,
+                reason: StopReason::Budget,
+            }
+        );
+        assert_eq!(msx.pc(), 10);
+
+        // the last instruction runs whole, past the budget
+        assert_eq!(msx.run_cycles(3).t_states, 5);
+        assert_eq!(msx.pc(), 11);
+    }
+
+    #[test]
+    fn test_run_cycles_halt() {
+        // LD A,1 / HALT
+        let mut msx = machine(&[0x3E, 0x01, 0x76]);
+        let exit = msx.run_cycles(1000);
+        assert_eq!(
+            exit,
+            RunExit {
+                t_states: 13,
+                reason: StopReason::Halted,
+            }
+        );
+        assert_eq!(msx.run_cycles(1000).t_states, 0);
+    }
+
+    #[test]
+    fn test_run_cycles_debug_break() {
+        // LD A,0x30 / OUT (0x2E),A / NOP
+        let mut msx = machine(&[0x3E, 0x30, 0xD3, 0x2E, 0x00]);
+        msx.enable_debug_device();
+        let exit = msx.run_cycles(1000);
+        assert_eq!(
+            exit,
+            RunExit {
+                t_states: 20,
+                reason: StopReason::DebugBreak,
+            }
+        );
+        assert!(msx.debug_device_output().1);
+    }
+
+    #[test]
+    fn test_key_click_audio() {
+        // LD A,0x0F / OUT (0xAB),A / NOPs, setting bit 7 of PPI port C
+        let mut program = vec![0x00; 32];
+        program[..4].copy_from_slice(&[0x3E, 0x0F, 0xD3, 0xAB]);
+        let mut msx = machine(&program);
+        assert!(msx.audio_samples(44_100.0).is_empty());
+
+        msx.enable_audio();
+        msx.run_cycles(2000);
+        let samples = msx.audio_samples(44_100.0);
+        assert_eq!(samples.len(), 2000 * 44_100 / 3_579_545);
+        // high from the OUT on, part of the first sample
+        assert!(samples[0] > 0.5 && samples[0] < 1.0);
+        assert_eq!(samples.last(), Some(&1.0));
+
+        msx.set_volume(0.5, 1.0);
+        msx.run_cycles(2000);
+        assert_eq!(msx.audio_samples(44_100.0).last(), Some(&0.5));
+    }
+}
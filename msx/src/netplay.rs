@@ -0,0 +1,319 @@
+//! Transport agnostic, delay based netplay.
+//!
+//! Both peers run the same machine and exchange the keyboard matrix of every
+//! frame. Local input is scheduled `input_delay` frames in the future, which
+//! gives it time to reach the other side, and a frame only runs once the
+//! input of both players for it is known. Every `checksum_interval` frames the
+//! peers exchange a checksum of the machine; when they differ the host sends
+//! its full state and the guest loads it.
+use std::collections::{BTreeMap, VecDeque};
+
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::{
+    keyboard::{KEYBOARD_ROWS, NO_KEYS},
+    Msx,
+};
+
+// how many frames of input and checksums are kept around
+const HISTORY_FRAMES: u64 = 600;
+
+pub type Input = [u8; KEYBOARD_ROWS];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NetplayRole {
+    Host,
+    Guest,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NetplayMessage {
+    Input { frame: u64, input: Input },
+    Checksum { frame: u64, checksum: u32 },
+    State { frame: u64, data: Vec<u8> },
+}
+
+impl NetplayMessage {
+    pub fn to_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        Ok(serde_json::to_vec(self)?)
+    }
+
+    pub fn from_bytes(data: &[u8]) -> anyhow::Result<Self> {
+        Ok(serde_json::from_slice(data)?)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NetplaySession {
+    pub role: NetplayRole,
+    pub input_delay: u64,
+    pub checksum_interval: u64,
+    /// machine frames run for each netplay frame, i.e. how often input is sampled
+    pub frames_per_input: u32,
+
+    /// next frame to be emulated
+    pub frame: u64,
+    /// number of times the peers diverged
+    pub desyncs: u64,
+
+    /// next frame that local input will be scheduled for
+    local_frame: u64,
+    local_inputs: BTreeMap<u64, Input>,
+    remote_inputs: BTreeMap<u64, Input>,
+    local_checksums: BTreeMap<u64, u32>,
+    remote_checksums: BTreeMap<u64, u32>,
+    outbox: VecDeque<NetplayMessage>,
+}
+
+impl NetplaySession {
+    pub fn new(role: NetplayRole, input_delay: u64, checksum_interval: u64) -> Self {
+        // nobody can provide input for the first frames, they run with no keys
+        let initial: BTreeMap<u64, Input> = (0..input_delay).map(|f| (f, NO_KEYS)).collect();
+
+        Self {
+            role,
+            input_delay,
+            checksum_interval: checksum_interval.max(1),
+            frames_per_input: 1,
+            frame: 0,
+            desyncs: 0,
+            local_frame: input_delay,
+            local_inputs: initial.clone(),
+            remote_inputs: initial,
+            local_checksums: BTreeMap::new(),
+            remote_checksums: BTreeMap::new(),
+            outbox: VecDeque::new(),
+        }
+    }
+
+    /// Schedules the current local input `input_delay` frames ahead. Returns
+    /// false without scheduling anything when we are already that far ahead.
+    pub fn push_local_input(&mut self, input: Input) -> bool {
+        if self.local_frame > self.frame + self.input_delay {
+            return false;
+        }
+
+        let frame = self.local_frame;
+        self.local_inputs.insert(frame, input);
+        self.outbox
+            .push_back(NetplayMessage::Input { frame, input });
+        self.local_frame += 1;
+        true
+    }
+
+    /// True when the input of both players for the next frame is known.
+    pub fn can_advance(&self) -> bool {
+        self.local_inputs.contains_key(&self.frame) && self.remote_inputs.contains_key(&self.frame)
+    }
+
+    /// Runs the next frame with the combined input of both players.
+    pub fn advance(&mut self, msx: &mut Msx) -> bool {
+        let (Some(local), Some(remote)) = (
+            self.local_inputs.get(&self.frame),
+            self.remote_inputs.get(&self.frame),
+        ) else {
+            return false;
+        };
+
+        // keys are active low, a key is down if either player has it down
+        let mut input = NO_KEYS;
+        for (row, value) in input.iter_mut().enumerate() {
+            *value = local[row] & remote[row];
+        }
+
+        msx.set_keyboard_matrix(input);
+        for _ in 0..self.frames_per_input {
+            msx.step_frame();
+        }
+        self.frame += 1;
+
+        if self.frame % self.checksum_interval == 0 {
+            let checksum = msx.checksum();
+            self.local_checksums.insert(self.frame, checksum);
+            self.outbox.push_back(NetplayMessage::Checksum {
+                frame: self.frame,
+                checksum,
+            });
+            self.check_sync(self.frame, msx);
+        }
+
+        self.prune();
+        true
+    }
+
+    /// Handles a message received from the peer.
+    pub fn handle_message(&mut self, message: NetplayMessage, msx: &mut Msx) -> anyhow::Result<()> {
+        match message {
+            NetplayMessage::Input { frame, input } => {
+                self.remote_inputs.insert(frame, input);
+            }
+            NetplayMessage::Checksum { frame, checksum } => {
+                self.remote_checksums.insert(frame, checksum);
+                self.check_sync(frame, msx);
+            }
+            NetplayMessage::State { frame, data } => {
+                if self.role == NetplayRole::Host {
+                    warn!("[NETPLAY] Ignoring state sent by guest");
+                    return Ok(());
+                }
+
+                info!("[NETPLAY] Resyncing to host state at frame {}", frame);
+                msx.load_state(&data)?;
+                self.frame = frame;
+                self.local_checksums.retain(|&f, _| f <= frame);
+                self.remote_checksums.retain(|&f, _| f <= frame);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Takes the messages that must be sent to the peer.
+    pub fn take_outgoing(&mut self) -> Vec<NetplayMessage> {
+        self.outbox.drain(..).collect()
+    }
+
+    fn check_sync(&mut self, frame: u64, msx: &mut Msx) {
+        let (Some(local), Some(remote)) = (
+            self.local_checksums.get(&frame),
+            self.remote_checksums.get(&frame),
+        ) else {
+            return;
+        };
+
+        if local == remote {
+            return;
+        }
+
+        warn!(
+            "[NETPLAY] Desync at frame {}: local {:08X} remote {:08X}",
+            frame, local, remote
+        );
+        self.desyncs += 1;
+        self.local_checksums.remove(&frame);
+        self.remote_checksums.remove(&frame);
+
+        if self.role == NetplayRole::Host {
+            match msx.save_state() {
+                Ok(data) => self.outbox.push_back(NetplayMessage::State {
+                    frame: self.frame,
+                    data,
+                }),
+                Err(e) => warn!("[NETPLAY] Could not save state for resync: {}", e),
+            }
+        }
+    }
+
+    fn prune(&mut self) {
+        let Some(oldest) = self.frame.checked_sub(HISTORY_FRAMES) else {
+            return;
+        };
+
+        self.local_inputs.retain(|&f, _| f >= oldest);
+        self.remote_inputs.retain(|&f, _| f >= oldest);
+        self.local_checksums.retain(|&f, _| f >= oldest);
+        self.remote_checksums.retain(|&f, _| f >= oldest);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::slot::{RamSlot, RomSlot, SlotType};
+
+    fn machine() -> Msx {
+        // a program that keeps copying the keyboard row 8 into memory:
+        // loop: in a,(#a9); ld (#c000),a; inc (hl); jr loop
+        let mut program = vec![0; 0x4000];
+        program[..8].copy_from_slice(&[0xDB, 0xA9, 0x32, 0x00, 0xC0, 0x34, 0x18, 0xF8]);
+        let mut msx = Msx::new(&[
+            SlotType::Rom(RomSlot::new(&program, 0x0000, 0x4000)),
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+        ]);
+        {
+            let mut bus = msx.bus.write().unwrap();
+            bus.ppi.primary_slot_config = 0b11_11_00_00;
+            bus.ppi.write(0xAA, 0x58);
+        }
+        msx.set_hl(0xC001);
+        msx
+    }
+
+    fn exchange(from: &mut NetplaySession, to: &mut NetplaySession, msx: &mut Msx) {
+        for message in from.take_outgoing() {
+            to.handle_message(message, msx).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_lockstep_with_input_delay() {
+        let mut host_msx = machine();
+        let mut guest_msx = machine();
+        let mut host = NetplaySession::new(NetplayRole::Host, 2, 4);
+        let mut guest = NetplaySession::new(NetplayRole::Guest, 2, 4);
+
+        for frame in 0..20 {
+            let mut input = NO_KEYS;
+            if frame == 5 {
+                input[8] = 0xFE;
+            }
+
+            host.push_local_input(input);
+            guest.push_local_input(NO_KEYS);
+            exchange(&mut host, &mut guest, &mut guest_msx);
+            exchange(&mut guest, &mut host, &mut host_msx);
+
+            assert!(host.advance(&mut host_msx));
+            assert!(guest.advance(&mut guest_msx));
+        }
+
+        assert_eq!(host.frame, 20);
+        assert_eq!(guest.frame, 20);
+        assert_eq!(host_msx.checksum(), guest_msx.checksum());
+        assert_eq!(host.desyncs, 0);
+    }
+
+    #[test]
+    fn test_waits_for_remote_input() {
+        let mut msx = machine();
+        let mut session = NetplaySession::new(NetplayRole::Host, 1, 60);
+
+        session.push_local_input(NO_KEYS);
+        assert!(session.advance(&mut msx));
+        // frame 1 has local input but nothing from the peer yet
+        assert!(!session.can_advance());
+        assert!(!session.advance(&mut msx));
+        // local input can be scheduled up to the input delay ahead, no further
+        assert!(session.push_local_input(NO_KEYS));
+        assert!(!session.push_local_input(NO_KEYS));
+    }
+
+    #[test]
+    fn test_resync_on_desync() {
+        let mut host_msx = machine();
+        let mut guest_msx = machine();
+        let mut host = NetplaySession::new(NetplayRole::Host, 1, 2);
+        let mut guest = NetplaySession::new(NetplayRole::Guest, 1, 2);
+
+        guest_msx.set_memory(0xC100, 0x42);
+
+        for _ in 0..2 {
+            host.push_local_input(NO_KEYS);
+            guest.push_local_input(NO_KEYS);
+            exchange(&mut host, &mut guest, &mut guest_msx);
+            exchange(&mut guest, &mut host, &mut host_msx);
+            host.advance(&mut host_msx);
+            guest.advance(&mut guest_msx);
+        }
+
+        exchange(&mut guest, &mut host, &mut host_msx);
+        assert_eq!(host.desyncs, 1);
+
+        exchange(&mut host, &mut guest, &mut guest_msx);
+        assert_eq!(guest.frame, host.frame);
+        assert_eq!(guest_msx.checksum(), host_msx.checksum());
+    }
+}
@@ -0,0 +1,205 @@
+use std::fmt;
+
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+
+// names of the VDP color codes, as in the TMS9918 datasheet
+const COLOR_NAMES: [&str; 16] = [
+    "Transparent",
+    "Black",
+    "Medium Green",
+    "Light Green",
+    "Dark Blue",
+    "Light Blue",
+    "Dark Red",
+    "Cyan",
+    "Medium Red",
+    "Light Red",
+    "Dark Yellow",
+    "Light Yellow",
+    "Dark Green",
+    "Magenta",
+    "Gray",
+    "White",
+];
+
+/// RGB colors shown for the 16 VDP color codes. Transparent (0) is drawn as
+/// black.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Palette(pub [[u8; 3]; 16]);
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::CLASSIC
+    }
+}
+
+impl Palette {
+    /// Colors measured from the video output of a TMS9918A.
+    pub const CLASSIC: Palette = Palette([
+        [0x00, 0x00, 0x00],
+        [0x00, 0x00, 0x00],
+        [0x21, 0xC8, 0x42],
+        [0x5E, 0xDC, 0x78],
+        [0x54, 0x55, 0xED],
+        [0x7D, 0x76, 0xFC],
+        [0xD4, 0x52, 0x4D],
+        [0x42, 0xEB, 0xF5],
+        [0xFC, 0x55, 0x54],
+        [0xFF, 0x79, 0x78],
+        [0xD4, 0xC1, 0x54],
+        [0xE6, 0xCE, 0x80],
+        [0x21, 0xB0, 0x3B],
+        [0xC9, 0x5B, 0xBA],
+        [0xCC, 0xCC, 0xCC],
+        [0xFF, 0xFF, 0xFF],
+    ]);
+
+    /// More saturated colors, as the V9938 shows MSX1 screens.
+    pub const BRIGHT: Palette = Palette([
+        [0x00, 0x00, 0x00],
+        [0x00, 0x00, 0x00],
+        [0x24, 0xDB, 0x24],
+        [0x6D, 0xFF, 0x6D],
+        [0x24, 0x24, 0xFF],
+        [0x49, 0x6D, 0xFF],
+        [0xB6, 0x24, 0x24],
+        [0x49, 0xDB, 0xFF],
+        [0xFF, 0x24, 0x24],
+        [0xFF, 0x6D, 0x6D],
+        [0xDB, 0xDB, 0x24],
+        [0xDB, 0xDB, 0x92],
+        [0x24, 0x92, 0x24],
+        [0xDB, 0x49, 0xB6],
+        [0xB6, 0xB6, 0xB6],
+        [0xFF, 0xFF, 0xFF],
+    ]);
+
+    /// The classic colors by luminance, like on a black and white TV.
+    pub const GRAYSCALE: Palette = Self::CLASSIC.tinted([0xFF, 0xFF, 0xFF]);
+
+    /// The classic colors by luminance on a green phosphor monitor.
+    pub const GREEN: Palette = Self::CLASSIC.tinted([0x33, 0xFF, 0x33]);
+
+    /// The classic colors by luminance on an amber phosphor monitor.
+    pub const AMBER: Palette = Self::CLASSIC.tinted([0xFF, 0xB0, 0x00]);
+
+    pub const PRESETS: [(&'static str, Palette); 5] = [
+        ("classic", Self::CLASSIC),
+        ("bright", Self::BRIGHT),
+        ("grayscale", Self::GRAYSCALE),
+        ("green", Self::GREEN),
+        ("amber", Self::AMBER),
+    ];
+
+    pub fn preset(name: &str) -> Option<Palette> {
+        Self::PRESETS
+            .iter()
+            .find(|(preset, _)| preset.eq_ignore_ascii_case(name))
+            .map(|(_, palette)| *palette)
+    }
+
+    // the colors by luminance, as shades of the tint, which white becomes
+    const fn tinted(self, tint: [u8; 3]) -> Palette {
+        let mut colors = self.0;
+        let mut i = 0;
+        while i < 16 {
+            let [r, g, b] = colors[i];
+            let y = (r as u32 * 299 + g as u32 * 587 + b as u32 * 114) / 1000;
+            let mut channel = 0;
+            while channel < 3 {
+                colors[i][channel] = (tint[channel] as u32 * y / 0xFF) as u8;
+                channel += 1;
+            }
+            i += 1;
+        }
+        Palette(colors)
+    }
+
+    /// Parses a palette file: the 16 colors as `#RRGGBB` (the `#` is
+    /// optional), one per line, in color code order. Anything after a `;` is
+    /// a comment. This is the format the palette is displayed in.
+    pub fn parse(text: &str) -> anyhow::Result<Palette> {
+        let mut colors = Vec::new();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.split(';').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let hex = line.strip_prefix('#').unwrap_or(line);
+            if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                bail!("Line {}: invalid color {}", n + 1, line);
+            }
+            let rgb = u32::from_str_radix(hex, 16)?;
+            let [_, r, g, b] = rgb.to_be_bytes();
+            colors.push([r, g, b]);
+        }
+
+        match colors.try_into() {
+            Ok(colors) => Ok(Palette(colors)),
+            Err(colors) => bail!("A palette has 16 colors, found {}", colors.len()),
+        }
+    }
+
+    #[cfg(feature = "std-fs")]
+    pub fn load(path: impl AsRef<std::path::Path>) -> anyhow::Result<Palette> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?;
+        Self::parse(&text).map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))
+    }
+
+    /// RGBA bytes of every possible pixel value, so converting a pixel is a
+    /// single lookup without bounds checks. Only the color code in the lower
+    /// 4 bits is used.
+    pub fn rgba_lut(&self) -> [[u8; 4]; 256] {
+        let mut lut = [[0; 4]; 256];
+        for (i, rgba) in lut.iter_mut().enumerate() {
+            let [r, g, b] = self.0[i & 0x0F];
+            *rgba = [r, g, b, 0xFF];
+        }
+        lut
+    }
+}
+
+impl fmt::Display for Palette {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (n, [r, g, b]) in self.0.iter().enumerate() {
+            writeln!(
+                f,
+                "#{:02X}{:02X}{:02X} ; {:>2} {}",
+                r, g, b, n, COLOR_NAMES[n]
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        for (_, palette) in Palette::PRESETS {
+            assert_eq!(Palette::parse(&palette.to_string()).unwrap(), palette);
+        }
+
+        let mut text = "; custom\n000000\n".repeat(15);
+        text.push_str("#12abEF ; white\n");
+        let palette = Palette::parse(&text).unwrap();
+        assert_eq!(palette.0[15], [0x12, 0xAB, 0xEF]);
+        assert_eq!(palette.rgba_lut()[0xFF], [0x12, 0xAB, 0xEF, 0xFF]);
+
+        assert!(Palette::parse("000000\n").is_err());
+        assert!(Palette::parse(&"0000000\n".repeat(16)).is_err());
+        assert!(Palette::parse(&"+00000\n".repeat(16)).is_err());
+
+        assert_eq!(Palette::preset("Grayscale"), Some(Palette::GRAYSCALE));
+        assert_eq!(Palette::GRAYSCALE.0[15], [0xFF, 0xFF, 0xFF]);
+        assert_eq!(Palette::preset("amber"), Some(Palette::AMBER));
+        assert_eq!(Palette::GREEN.0[15], [0x33, 0xFF, 0x33]);
+        assert_eq!(Palette::GREEN.0[1], [0x00, 0x00, 0x00]);
+    }
+}
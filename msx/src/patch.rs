@@ -0,0 +1,310 @@
+use anyhow::{anyhow, bail};
+
+// largest ROM a BPS patch may produce, far above any MSX ROM, so that a
+// malformed patch can't make it allocate without bounds
+const MAX_TARGET_SIZE: usize = 16 * 1024 * 1024;
+
+/// Applies an IPS or BPS patch to a ROM image, detecting the format by the
+/// patch header.
+pub fn apply(rom: &[u8], patch: &[u8]) -> anyhow::Result<Vec<u8>> {
+    if patch.starts_with(b"PATCH") {
+        apply_ips(rom, patch)
+    } else if patch.starts_with(b"BPS1") {
+        apply_bps(rom, patch)
+    } else {
+        bail!("Unknown patch format, expected IPS or BPS")
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8], pos: usize) -> Self {
+        Self { data, pos }
+    }
+
+    fn bytes(&mut self, len: usize) -> anyhow::Result<&'a [u8]> {
+        let bytes = self
+            .pos
+            .checked_add(len)
+            .and_then(|end| self.data.get(self.pos..end))
+            .ok_or_else(|| anyhow!("Unexpected end of patch at {:#X}", self.pos))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> anyhow::Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn be(&mut self, len: usize) -> anyhow::Result<usize> {
+        Ok(self
+            .bytes(len)?
+            .iter()
+            .fold(0, |acc, b| (acc << 8) | *b as usize))
+    }
+
+    // BPS variable length number
+    fn number(&mut self) -> anyhow::Result<usize> {
+        let start = self.pos;
+        let overflow = || anyhow!("BPS number too large at {:#X}", start);
+        let mut data = 0usize;
+        let mut shift = 1usize;
+        loop {
+            let x = self.byte()?;
+            data = ((x & 0x7F) as usize)
+                .checked_mul(shift)
+                .and_then(|value| data.checked_add(value))
+                .ok_or_else(overflow)?;
+            if x & 0x80 != 0 {
+                return Ok(data);
+            }
+            shift = shift.checked_mul(1 << 7).ok_or_else(overflow)?;
+            data = data.checked_add(shift).ok_or_else(overflow)?;
+        }
+    }
+}
+
+fn apply_ips(rom: &[u8], patch: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut target = rom.to_vec();
+    let mut reader = Reader::new(patch, 5);
+
+    loop {
+        let offset_bytes = reader.bytes(3)?;
+        if offset_bytes == b"EOF" {
+            break;
+        }
+        let offset = offset_bytes
+            .iter()
+            .fold(0, |acc, b| (acc << 8) | *b as usize);
+
+        let size = reader.be(2)?;
+        let (size, data) = if size == 0 {
+            // RLE record
+            let size = reader.be(2)?;
+            let value = reader.byte()?;
+            (size, vec![value; size])
+        } else {
+            (size, reader.bytes(size)?.to_vec())
+        };
+
+        if target.len() < offset + size {
+            target.resize(offset + size, 0);
+        }
+        target[offset..offset + size].copy_from_slice(&data);
+    }
+
+    // optional truncation extension
+    if let Ok(len) = reader.be(3) {
+        target.truncate(len);
+    }
+
+    Ok(target)
+}
+
+fn apply_bps(rom: &[u8], patch: &[u8]) -> anyhow::Result<Vec<u8>> {
+    if patch.len() < 16 {
+        bail!("BPS patch is too short");
+    }
+
+    let footer = &patch[patch.len() - 12..];
+    let crc = |offset: usize| u32::from_le_bytes(footer[offset..offset + 4].try_into().unwrap());
+    let (source_crc, target_crc, patch_crc) = (crc(0), crc(4), crc(8));
+
+    if crc32fast::hash(&patch[..patch.len() - 4]) != patch_crc {
+        bail!("BPS patch checksum mismatch");
+    }
+    if crc32fast::hash(rom) != source_crc {
+        bail!("ROM checksum does not match the one expected by the BPS patch");
+    }
+
+    let mut reader = Reader::new(&patch[..patch.len() - 12], 4);
+    let source_size = reader.number()?;
+    let target_size = reader.number()?;
+    let metadata_size = reader.number()?;
+    reader.bytes(metadata_size)?;
+
+    if source_size != rom.len() {
+        bail!(
+            "BPS patch expects a {} bytes ROM, got {} bytes",
+            source_size,
+            rom.len()
+        );
+    }
+
+    if target_size > MAX_TARGET_SIZE {
+        bail!("BPS patch makes a {} bytes ROM, too large", target_size);
+    }
+
+    let mut target = Vec::with_capacity(target_size);
+    let mut source_relative = 0isize;
+    let mut target_relative = 0isize;
+
+    while reader.pos < reader.data.len() {
+        let data = reader.number()?;
+        let length = (data >> 2) + 1;
+        if length > MAX_TARGET_SIZE - target.len() {
+            bail!(
+                "BPS patch makes a ROM larger than {} bytes",
+                MAX_TARGET_SIZE
+            );
+        }
+
+        match data & 3 {
+            // SourceRead
+            0 => {
+                let start = target.len();
+                let bytes = start
+                    .checked_add(length)
+                    .and_then(|end| rom.get(start..end))
+                    .ok_or_else(|| anyhow!("BPS source read out of bounds"))?;
+                target.extend_from_slice(bytes);
+            }
+            // TargetRead
+            1 => target.extend_from_slice(reader.bytes(length)?),
+            // SourceCopy
+            2 => {
+                source_relative = relative(source_relative, reader.number()?)?;
+                let start = usize::try_from(source_relative)?;
+                let bytes = start
+                    .checked_add(length)
+                    .and_then(|end| rom.get(start..end))
+                    .ok_or_else(|| anyhow!("BPS source copy out of bounds"))?;
+                target.extend_from_slice(bytes);
+                // the length fits, as it was read from the ROM
+                source_relative += length as isize;
+            }
+            // TargetCopy, may overlap the bytes being written
+            _ => {
+                target_relative = relative(target_relative, reader.number()?)?;
+                for _ in 0..length {
+                    let byte = *target
+                        .get(usize::try_from(target_relative)?)
+                        .ok_or_else(|| anyhow!("BPS target copy out of bounds"))?;
+                    target.push(byte);
+                    target_relative += 1;
+                }
+            }
+        }
+    }
+
+    if target.len() != target_size || crc32fast::hash(&target) != target_crc {
+        bail!("Patched ROM checksum does not match the BPS patch");
+    }
+
+    Ok(target)
+}
+
+// moves a relative offset of the copy commands by a signed number
+fn relative(offset: isize, data: usize) -> anyhow::Result<isize> {
+    let value = (data >> 1) as isize;
+    let value = if data & 1 != 0 { -value } else { value };
+    offset
+        .checked_add(value)
+        .ok_or_else(|| anyhow!("BPS copy offset out of bounds"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ips() {
+        let mut patch = b"PATCH".to_vec();
+        // 2 bytes at 0x000001
+        patch.extend_from_slice(&[0x00, 0x00, 0x01, 0x00, 0x02, 0xAA, 0xBB]);
+        // RLE of 3 x 0x11 at 0x000006, past the end of the ROM
+        patch.extend_from_slice(&[0x00, 0x00, 0x06, 0x00, 0x00, 0x00, 0x03, 0x11]);
+        patch.extend_from_slice(b"EOF");
+
+        let rom = apply(&[0, 1, 2, 3, 4], &patch).unwrap();
+        assert_eq!(rom, vec![0, 0xAA, 0xBB, 3, 4, 0, 0x11, 0x11, 0x11]);
+    }
+
+    fn number(mut data: usize, out: &mut Vec<u8>) {
+        loop {
+            let x = (data & 0x7F) as u8;
+            data >>= 7;
+            if data == 0 {
+                out.push(0x80 | x);
+                break;
+            }
+            out.push(x);
+            data -= 1;
+        }
+    }
+
+    #[test]
+    fn test_bps() {
+        let source = [1, 2, 3, 4];
+        let target = [1, 2, 9, 1, 2, 1, 2];
+
+        let mut patch = b"BPS1".to_vec();
+        number(source.len(), &mut patch);
+        number(target.len(), &mut patch);
+        number(0, &mut patch);
+        let command = |kind: usize, length: usize| ((length - 1) << 2) | kind;
+        // SourceRead 2
+        number(command(0, 2), &mut patch);
+        // TargetRead 1 (9)
+        number(command(1, 1), &mut patch);
+        patch.push(9);
+        // SourceCopy 2 from offset 0
+        number(command(2, 2), &mut patch);
+        number(0, &mut patch);
+        // TargetCopy 2 from offset 0
+        number(command(3, 2), &mut patch);
+        number(0, &mut patch);
+        checksums(&source, &target, &mut patch);
+
+        assert_eq!(apply(&source, &patch).unwrap(), target.to_vec());
+        assert!(apply(&[1, 2, 3, 5], &patch).is_err());
+    }
+
+    fn checksums(source: &[u8], target: &[u8], patch: &mut Vec<u8>) {
+        patch.extend_from_slice(&crc32fast::hash(source).to_le_bytes());
+        patch.extend_from_slice(&crc32fast::hash(target).to_le_bytes());
+        let patch_crc = crc32fast::hash(patch);
+        patch.extend_from_slice(&patch_crc.to_le_bytes());
+    }
+
+    #[test]
+    fn test_bps_malformed() {
+        // a number without its last byte, then one too large for usize
+        assert!(Reader::new(&[0x12, 0x34], 0).number().is_err());
+        assert!(Reader::new(&[0x00; 16], 0).number().is_err());
+        let mut data = Vec::new();
+        number(usize::MAX, &mut data);
+        assert_eq!(Reader::new(&data, 0).number().unwrap(), usize::MAX);
+
+        let source = [1, 2, 3, 4];
+        let patch = |commands: &[usize]| {
+            let mut patch = b"BPS1".to_vec();
+            for n in commands {
+                number(*n, &mut patch);
+            }
+            checksums(&source, &[], &mut patch);
+            patch
+        };
+        let errors = [
+            // a 4G target, a huge metadata block
+            patch(&[4, 1 << 32, 0]),
+            patch(&[4, 4, usize::MAX]),
+            // a source read of 4G, a target copy of 4G after a target read
+            // and a source read of the largest length
+            patch(&[4, 4, 0, (1 << 32) << 2]),
+            patch(&[4, 4, 0, 1 << 2 | 1, 1, 2, ((1 << 32) << 2) | 3, 0]),
+            patch(&[4, 4, 0, (usize::MAX >> 2) << 2]),
+            // a source copy far past the ROM, then one whose offset
+            // overflows
+            patch(&[4, 4, 0, 2, (usize::MAX >> 2) << 1]),
+            patch(&[4, 4, 0, 2, 2, 2, usize::MAX & !1]),
+        ];
+        for patch in errors {
+            assert!(apply(&source, &patch).is_err());
+        }
+    }
+}
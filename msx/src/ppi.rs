@@ -2,6 +2,19 @@
 use serde::{Deserialize, Serialize};
 use tracing::info;
 
+// control word bit selecting a mode set instead of a port C bit set/reset
+const MODE_SET: u8 = 0x80;
+// control word of the 8255 after a reset, all ports as inputs
+const RESET_CONTROL: u8 = 0x9B;
+
+/// Intel 8255 PPI as wired on the MSX:
+///
+/// - port A (0xA8) is the primary slot register, two bits per page
+/// - port B (0xA9) reads the keyboard row selected by port C
+/// - port C (0xAA) selects the keyboard row (bits 0-3) and drives the
+///   cassette motor and output, the CAPS LED and the key click (bits 4-7)
+/// - the control port (0xAB) sets the mode of the ports, or sets and
+///   resets single bits of port C
 #[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
 pub struct Ppi {
     pub primary_slot_config: u8,
@@ -10,6 +23,8 @@ pub struct Ppi {
     control: u8,
 
     keyboard_row_selected: u8,
+    /// keyboard matrix, one byte per row with a bit cleared for each key down
+    keyboard: [u8; 11],
 }
 
 impl Ppi {
@@ -18,15 +33,20 @@ impl Ppi {
             primary_slot_config: 0,
             register_b: 0,
             register_c: 0x50, // Everything OFF. Motor and CapsLed = 1 means OFF
-            control: 0,
+            control: RESET_CONTROL,
 
             keyboard_row_selected: 0,
+            keyboard: [0xFF; 11],
         }
     }
 
     pub fn reset(&mut self) {
+        // the BIOS starts from slot 0 on all pages
+        self.primary_slot_config = 0;
+        self.control = RESET_CONTROL;
         self.register_c = 0x50; // Everything OFF. Motor and CapsLed = 1 means OFF
         self.keyboard_row_selected = 0;
+        self.keyboard = [0xFF; 11];
         self.update_pulse_signal();
         self.update_caps_led();
     }
@@ -39,6 +59,71 @@ impl Ppi {
         // TODO leds_socket.led_state_changed(0, (~registerC & 0x40) >> 6);
     }
 
+    pub fn keyboard_matrix(&self) -> [u8; 11] {
+        self.keyboard
+    }
+
+    pub fn set_keyboard_matrix(&mut self, matrix: [u8; 11]) {
+        self.keyboard = matrix;
+    }
+
+    pub fn set_key(&mut self, row: usize, bit: u8, pressed: bool) {
+        if pressed {
+            self.keyboard[row] &= !(1 << bit);
+        } else {
+            self.keyboard[row] |= 1 << bit;
+        }
+    }
+
+    /// Keyboard row selected by the lower bits of port C.
+    pub fn keyboard_row(&self) -> u8 {
+        self.register_c & 0x0F
+    }
+
+    /// CAPS LED state, lit when bit 6 of port C is low.
+    pub fn caps_led(&self) -> bool {
+        self.register_c & 0x40 == 0
+    }
+
+    /// Cassette motor state, running when bit 4 of port C is low.
+    pub fn cassette_motor(&self) -> bool {
+        self.register_c & 0x10 == 0
+    }
+
+    /// Cassette output level, bit 5 of port C.
+    pub fn cassette_output(&self) -> bool {
+        self.register_c & 0x20 != 0
+    }
+
+    /// Key click level, bit 7 of port C, a 1-bit DAC for the sound.
+    pub fn key_click(&self) -> bool {
+        self.register_c & 0x80 != 0
+    }
+
+    fn update_keyboard_config(&mut self) {
+        self.keyboard_row_selected = self.keyboard_row();
+        self.register_b = self
+            .keyboard
+            .get(self.keyboard_row_selected as usize)
+            .copied()
+            .unwrap_or(0xFF);
+    }
+
+    // applies a change of port C to the devices wired to its bits
+    fn set_register_c(&mut self, value: u8) {
+        let changed = self.register_c ^ value;
+        self.register_c = value;
+        if changed & 0x0F != 0 {
+            self.update_keyboard_config();
+        }
+        if changed & 0xA0 != 0 {
+            self.update_pulse_signal();
+        }
+        if changed & 0x40 != 0 {
+            self.update_caps_led();
+        }
+    }
+
     pub fn read(&mut self, port: u8) -> u8 {
         match port {
             0xA8 => {
@@ -50,6 +135,7 @@ impl Ppi {
                 self.primary_slot_config
             }
             0xA9 => {
+                self.update_keyboard_config();
                 info!(
                     "[PPI] [RD] [KeybordPort] [{:02X}] = {:02X}",
                     port, self.register_b
@@ -57,14 +143,6 @@ impl Ppi {
                 self.register_b
             }
             0xAA => {
-                // returns register and flags
-                // var mod = registerC ^ val;
-                // if (!mod) return;
-                // registerC = val;
-                // if (mod & 0x0f) updateKeyboardConfig();
-                // if (mod & 0xa0) updatePulseSignal();
-                // if (mod & 0x40) updateCapsLed();
-
                 info!(
                     "[PPI] [RD] [Register C ] [{:02X}] = {:02X}",
                     port, self.register_c
@@ -73,7 +151,7 @@ impl Ppi {
             }
             0xAB => {
                 info!("[PPI] [RD] [IgnoredPort] [{:02X}] = {:02X}", port, 0xFF);
-                // ignored output port
+                // the control word can't be read back
                 0xFF
             }
             _ => 0xFF,
@@ -93,36 +171,75 @@ impl Ppi {
             }
             0xAA => {
                 info!("[PPI] [WR] [PpiControl1] [{:02X}] = {:02X}", port, value);
-                let mode = self.register_c ^ value;
-                if mode == 0 {
-                    return;
-                }
-                self.register_c = value;
-                // var bit = (val & 0x0e) >>> 1;
-                // if ((val & 0x01) === 0) registerC &= ~(1 << bit);
-                // else registerC |= 1 << bit;
-
-                // if (bit <= 3) updateKeyboardConfig();
-                // else if (bit === 5 || bit === 7) updatePulseSignal();
-                // else if (bit === 6) updateCapsLed();
+                self.set_register_c(value);
             }
             0xAB => {
                 info!("[PPI] [WR] [PpiControl2] [{:02X}] = {:02X}", port, value);
-                let bit = (value & 0x0e) >> 1;
-                if (value & 0x01) == 0 {
-                    self.register_c &= !(1 << bit);
-                } else {
-                    self.register_c |= 1 << bit;
+                if value & MODE_SET != 0 {
+                    // a mode set clears the output latches, so the slot
+                    // register has to be written again afterwards
+                    self.control = value;
+                    self.primary_slot_config = 0;
+                    self.set_register_c(0);
+                    return;
                 }
 
-                // match bit {
-                //     0..=3 => self.update_keyboard_config(),
-                //     5 | 7 => self.update_pulse_signal(),
-                //     6 => self.update_caps_led(),
-                //     _ => (),
-                // }
+                let bit = (value & 0x0e) >> 1;
+                let register_c = if (value & 0x01) == 0 {
+                    self.register_c & !(1 << bit)
+                } else {
+                    self.register_c | 1 << bit
+                };
+                self.set_register_c(register_c);
             }
             _ => (),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_primary_slot_register() {
+        let mut ppi = Ppi::new();
+        ppi.write(0xA8, 0b11_10_01_00);
+        assert_eq!(ppi.read(0xA8), 0b11_10_01_00);
+
+        // mode set as done by the BIOS: A and C out, B in
+        ppi.write(0xAB, 0x82);
+        assert_eq!(ppi.read(0xA8), 0x00);
+        assert_eq!(ppi.read(0xAA), 0x00);
+        assert_eq!(ppi.read(0xAB), 0xFF);
+
+        ppi.write(0xA8, 0xF0);
+        ppi.reset();
+        assert_eq!(ppi.primary_slot_config, 0);
+    }
+
+    #[test]
+    fn test_keyboard_row_select() {
+        let mut ppi = Ppi::new();
+        ppi.set_key(8, 0, true);
+        ppi.set_key(2, 6, true);
+
+        // row 8 through port C, keeping the upper bits
+        ppi.write(0xAA, 0x58);
+        assert_eq!(ppi.keyboard_row(), 8);
+        assert_eq!(ppi.read(0xA9), 0xFE);
+
+        // row 2 through bit set/reset: clear bit 3, set bit 1
+        ppi.write(0xAB, 0x06);
+        ppi.write(0xAB, 0x03);
+        assert_eq!(ppi.keyboard_row(), 2);
+        assert_eq!(ppi.read(0xA9), 0xBF);
+        assert_eq!(ppi.read(0xAA), 0x52);
+
+        // CAPS LED on, bit 6 reset
+        assert!(!ppi.caps_led());
+        ppi.write(0xAB, 0x0C);
+        assert!(ppi.caps_led());
+        assert_eq!(ppi.read(0xAA), 0x12);
+    }
+}
@@ -0,0 +1,216 @@
+use std::fmt;
+
+#[cfg(feature = "cbios")]
+use crate::cbios;
+use crate::{
+    bus::Bus,
+    slot::{RamSlot, RomSlot, SlotType},
+    Msx,
+};
+
+// ID bytes of the BIOS: the character set, date format and interrupt
+// frequency, then the keyboard type in the low nibble of the second one
+const BIOS_ID: usize = 0x002B;
+// interrupt frequency bit of the first ID byte, set at 50 Hz
+const BIOS_ID_50HZ: u8 = 0x80;
+
+/// Character set, date format and keyboard of a machine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Region {
+    /// Japanese characters, dates as Y/M/D and a JIS keyboard
+    Japan,
+    /// International characters, dates as D/M/Y and a QWERTY keyboard
+    International,
+}
+
+impl Region {
+    // the first ID byte, without the frequency, and the keyboard type
+    fn bios_id(self) -> (u8, u8) {
+        match self {
+            Region::Japan => (0x00, 0x00),
+            Region::International => (0x21, 0x01),
+        }
+    }
+}
+
+/// A model of MSX1: what software can tell apart between machines, set the
+/// same way by the CLI and the web version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Preset {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub region: Region,
+    /// a TMS9929 VDP drawing 50 frames a second, instead of 60
+    pub pal: bool,
+    /// bytes of RAM, in slot 3 at the top of the memory
+    pub ram_size: u32,
+    /// whether the machine inserts a wait state in each M1 cycle, as all
+    /// MSX do, making instructions slower than the Z80 datasheet says
+    pub m1_wait: bool,
+}
+
+impl Preset {
+    pub const PRESETS: [Preset; 3] = [
+        Preset {
+            name: "msx1-jp",
+            description: "Japanese MSX1, 60 Hz with 64K of RAM",
+            region: Region::Japan,
+            pal: false,
+            ram_size: 0x10000,
+            m1_wait: true,
+        },
+        Preset {
+            name: "msx1-int",
+            description: "European MSX1, 50 Hz with 64K of RAM",
+            region: Region::International,
+            pal: true,
+            ram_size: 0x10000,
+            m1_wait: true,
+        },
+        Preset {
+            name: "msx1-16k",
+            description: "Japanese MSX1 with 16K of RAM, at 0xC000",
+            region: Region::Japan,
+            pal: false,
+            ram_size: 0x4000,
+            m1_wait: true,
+        },
+    ];
+
+    pub fn find(name: &str) -> Option<&'static Preset> {
+        Self::PRESETS
+            .iter()
+            .find(|preset| preset.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Address of the start of the RAM.
+    pub fn ram_base(&self) -> u16 {
+        (0x10000 - self.ram_size) as u16
+    }
+
+    /// The RAM slot of the machine.
+    pub fn ram(&self) -> SlotType {
+        SlotType::Ram(RamSlot::new(self.ram_base(), self.ram_size))
+    }
+
+    /// The slots of the machine: the ROM with the BIOS in slot 0, the
+    /// cartridge slots 1 and 2 empty and the RAM in slot 3.
+    pub fn slots(&self, rom: SlotType) -> [SlotType; 4] {
+        [rom, SlotType::Empty, SlotType::Empty, self.ram()]
+    }
+
+    /// The slots of the machine booting the bundled C-BIOS, with the
+    /// cartridge in slot 1.
+    #[cfg(feature = "cbios")]
+    pub fn cbios_slots(&self, cartridge: SlotType) -> [SlotType; 4] {
+        [cbios::slot(), cartridge, SlotType::Empty, self.ram()]
+    }
+
+    /// Sets what the slots don't tell: the timing of the CPU, the frequency
+    /// of the VDP, and the ID bytes of the BIOS in slot 0, which the BIOS and
+    /// programs read to know the region. A cartridge in slot 0, without a
+    /// BIOS, is left as is.
+    pub fn apply(&self, msx: &mut Msx) {
+        msx.cpu.stock_timing = !self.m1_wait;
+        let mut bus = msx.bus.write().unwrap();
+        bus.set_pal(self.pal);
+
+        let Some(rom) = bios_rom(&mut bus) else {
+            return;
+        };
+        let (id, keyboard) = self.region.bios_id();
+        rom.data[BIOS_ID] = id | if self.pal { BIOS_ID_50HZ } else { 0 };
+        rom.data[BIOS_ID + 1] = rom.data[BIOS_ID + 1] & 0xF0 | keyboard;
+    }
+}
+
+// the ROM of the BIOS in slot 0, unless slot 0 holds a cartridge
+fn bios_rom(bus: &mut Bus) -> Option<&mut RomSlot> {
+    let Some(SlotType::Rom(rom)) = bus.slot_mut(0) else {
+        return None;
+    };
+    if rom.base != 0 || rom.data.starts_with(b"AB") || rom.data.len() <= BIOS_ID + 1 {
+        return None;
+    }
+    Some(rom)
+}
+
+/// Sets the interrupt frequency bit of the ID bytes of the BIOS in slot 0,
+/// which the BIOS and programs read to tell 50 from 60 Hz, leaving the
+/// region as it is.
+pub fn set_bios_frequency(bus: &mut Bus, pal: bool) {
+    if let Some(rom) = bios_rom(bus) {
+        rom.data[BIOS_ID] = rom.data[BIOS_ID] & !BIOS_ID_50HZ | if pal { BIOS_ID_50HZ } else { 0 };
+    }
+}
+
+impl fmt::Display for Preset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.name, self.description)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        slot::RomSlot,
+        vdp_timing::{FRAME_T_STATES, PAL_FRAME_T_STATES},
+    };
+
+    fn machine(preset: &Preset, rom: &[u8]) -> Msx {
+        let mut msx = Msx::new(&preset.slots(SlotType::Rom(RomSlot::new(rom, 0x0000, 0x8000))));
+        preset.apply(&mut msx);
+        msx
+    }
+
+    #[test]
+    fn test_bios_id() {
+        let bios = [0xF3; 0x8000];
+        let msx = machine(Preset::find("MSX1-INT").unwrap(), &bios);
+        assert_eq!(msx.cpu.read_byte(0x002B), 0xA1);
+        assert_eq!(msx.cpu.read_byte(0x002C), 0xF1);
+        assert_eq!(msx.vdp().frame_t_states(), PAL_FRAME_T_STATES);
+
+        // switched at runtime, the region stays
+        let mut msx = msx;
+        msx.set_pal(false);
+        assert_eq!(msx.cpu.read_byte(0x002B), 0x21);
+        assert_eq!(msx.vdp().frame_t_states(), FRAME_T_STATES);
+
+        let msx = machine(Preset::find("msx1-jp").unwrap(), &bios);
+        assert_eq!(msx.cpu.read_byte(0x002B), 0x00);
+        assert_eq!(msx.cpu.read_byte(0x002C), 0xF0);
+        assert_eq!(msx.vdp().frame_t_states(), FRAME_T_STATES);
+
+        // a cartridge isn't patched
+        let mut cartridge = [0xF3; 0x4000];
+        cartridge[..2].copy_from_slice(b"AB");
+        let msx = machine(Preset::find("msx1-int").unwrap(), &cartridge);
+        assert_eq!(msx.cpu.read_byte(0x002B), 0xF3);
+    }
+
+    #[test]
+    fn test_16k_ram() {
+        let mut msx = machine(Preset::find("msx1-16k").unwrap(), &[0xF3]);
+        msx.bus.write().unwrap().ppi.primary_slot_config = 0xFF;
+        msx.cpu.write_byte(0xC000, 0x12);
+        msx.cpu.write_byte(0x8000, 0x34);
+        assert_eq!(msx.cpu.read_byte(0xC000), 0x12);
+        // nothing is mapped below the RAM
+        assert_eq!(msx.cpu.read_byte(0x8000), 0xFF);
+    }
+
+    #[test]
+    fn test_m1_wait() {
+        let mut msx = machine(Preset::find("msx1-jp").unwrap(), &[0x00]);
+        assert_eq!(msx.run_cycles(1).t_states, 5);
+
+        let z80 = Preset {
+            m1_wait: false,
+            ..Preset::PRESETS[0]
+        };
+        let mut msx = machine(&z80, &[0x00]);
+        assert_eq!(msx.run_cycles(1).t_states, 4);
+    }
+}
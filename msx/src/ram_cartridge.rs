@@ -0,0 +1,343 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+
+use crate::slot::{RamSlot, Slot, SlotType};
+
+const BANK_SIZE: usize = 0x2000;
+const SEGMENT_SIZE: usize = 0x4000;
+
+// port switching a MegaRAM between writing to the RAM and selecting banks
+const MEGARAM_PORT: u8 = 0x8E;
+// segment registers of the memory mappers, one per page
+const MAPPER_PORTS: [u8; 4] = [0xFC, 0xFD, 0xFE, 0xFF];
+
+/// A RAM cartridge for a cartridge slot, given as `ram[:<KB>]`,
+/// `megaram[:<KB>]` or `mapper[:<KB>]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RamCartridge {
+    /// plain RAM expansion at the top of the memory, like the main RAM: 16,
+    /// 32 or 64K, 64K by default
+    Ram(u32),
+    /// banked RAM, see [`MegaRam`]: a power of two from 64K to 2M, 256K by
+    /// default
+    MegaRam(u32),
+    /// memory mapped RAM, see [`MemoryMapper`]: a power of two from 64K to
+    /// 4M, 512K by default
+    MemoryMapper(u32),
+}
+
+impl RamCartridge {
+    /// The contents of the slot the cartridge is inserted in.
+    pub fn slot(&self) -> SlotType {
+        match *self {
+            RamCartridge::Ram(size) => SlotType::Ram(RamSlot::new((0x10000 - size) as u16, size)),
+            RamCartridge::MegaRam(size) => SlotType::MegaRam(MegaRam::new(size)),
+            RamCartridge::MemoryMapper(size) => SlotType::MemoryMapper(MemoryMapper::new(size)),
+        }
+    }
+}
+
+impl FromStr for RamCartridge {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, size) = match s.split_once(':') {
+            Some((kind, size)) => match size.trim_end_matches(['K', 'k']).parse::<u32>() {
+                Ok(size) => (kind, Some(size * 1024)),
+                Err(_) => bail!("Invalid size of the {} cartridge: {}", kind, size),
+            },
+            None => (s, None),
+        };
+
+        match kind.to_ascii_lowercase().as_str() {
+            "ram" => {
+                let size = size.unwrap_or(0x10000);
+                if ![0x4000, 0x8000, 0x10000].contains(&size) {
+                    bail!("A RAM cartridge has 16, 32 or 64K");
+                }
+                Ok(RamCartridge::Ram(size))
+            }
+            "megaram" => {
+                let size = size.unwrap_or(0x40000);
+                if !size.is_power_of_two() || !(0x10000..=0x200000).contains(&size) {
+                    bail!("A MegaRAM has a power of two from 64 to 2048K");
+                }
+                Ok(RamCartridge::MegaRam(size))
+            }
+            "mapper" => {
+                let size = size.unwrap_or(0x80000);
+                if !size.is_power_of_two() || !(0x10000..=0x400000).contains(&size) {
+                    bail!("A memory mapper has a power of two from 64 to 4096K");
+                }
+                Ok(RamCartridge::MemoryMapper(size))
+            }
+            _ => bail!(
+                "Unknown cartridge {}, expected ram[:KB], megaram[:KB] or mapper[:KB]",
+                kind
+            ),
+        }
+    }
+}
+
+impl fmt::Display for RamCartridge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RamCartridge::Ram(size) => write!(f, "{}K RAM", size / 1024),
+            RamCartridge::MegaRam(size) => write!(f, "{}K MegaRAM", size / 1024),
+            RamCartridge::MemoryMapper(size) => write!(f, "{}K memory mapper", size / 1024),
+        }
+    }
+}
+
+/// MegaRAM: RAM seen through four 8K windows at 0x4000-0xBFFF, like an
+/// ASCII8 mapper with banks that can be written. Reading port 0x8E lets the
+/// program write to the RAM; writing to it makes the writes select the bank
+/// of the window written to instead, which is the mode after a reset.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MegaRam {
+    data: Vec<u8>,
+    banks: [u8; 4],
+    writable: bool,
+}
+
+impl MegaRam {
+    pub fn new(size: u32) -> Self {
+        Self {
+            data: vec![0xFF; size as usize],
+            banks: [0, 1, 2, 3],
+            writable: false,
+        }
+    }
+
+    // window of an address, if it's in one
+    fn window(address: u16) -> Option<usize> {
+        let window = address.checked_sub(0x4000)? as usize / BANK_SIZE;
+        (window < 4).then_some(window)
+    }
+
+    fn offset(&self, address: u16) -> Option<usize> {
+        let bank = self.banks[Self::window(address)?] as usize;
+        Some(bank * BANK_SIZE + (address as usize & (BANK_SIZE - 1)))
+    }
+}
+
+impl Slot for MegaRam {
+    fn name(&self) -> &str {
+        "MegaRAM"
+    }
+
+    fn read(&self, address: u16) -> u8 {
+        self.offset(address)
+            .map_or(0xFF, |offset| self.data[offset])
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        if self.writable {
+            if let Some(offset) = self.offset(address) {
+                self.data[offset] = value;
+            }
+        } else if let Some(window) = Self::window(address) {
+            let mask = (self.data.len() / BANK_SIZE - 1) as u8;
+            self.banks[window] = value & mask;
+        }
+    }
+
+    fn size(&self) -> u32 {
+        self.data.len() as u32
+    }
+
+    fn reset(&mut self) {
+        self.banks = [0, 1, 2, 3];
+        self.writable = false;
+    }
+
+    fn clear_ram(&mut self) {
+        self.data.fill(0xFF);
+    }
+
+    fn banks(&self) -> Vec<u8> {
+        self.banks.to_vec()
+    }
+
+    fn ports(&self) -> &[u8] {
+        &[MEGARAM_PORT]
+    }
+
+    fn io_read(&mut self, _port: u8) -> u8 {
+        self.writable = true;
+        0xFF
+    }
+
+    fn io_write(&mut self, _port: u8, _value: u8) {
+        self.writable = false;
+    }
+}
+
+/// Memory mapper: RAM in 16K segments, the one seen on each page being
+/// selected by the ports 0xFC to 0xFF, as on MSX2 machines and the RAM
+/// expansions MSX-DOS2 and Nextor need. All the mappers of a machine share
+/// the ports; reading one returns the segment with the bits above the size
+/// set. A reset selects segments 3, 2, 1 and 0, like the BIOS does.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MemoryMapper {
+    data: Vec<u8>,
+    segments: [u8; 4],
+}
+
+impl MemoryMapper {
+    pub fn new(size: u32) -> Self {
+        Self {
+            data: vec![0xFF; size as usize],
+            segments: [3, 2, 1, 0],
+        }
+    }
+
+    // bits of the segment numbers the mapper decodes
+    fn mask(&self) -> u8 {
+        (self.data.len() / SEGMENT_SIZE - 1) as u8
+    }
+
+    fn offset(&self, address: u16) -> usize {
+        let segment = self.segments[address as usize / SEGMENT_SIZE] as usize;
+        segment * SEGMENT_SIZE + (address as usize & (SEGMENT_SIZE - 1))
+    }
+}
+
+impl Slot for MemoryMapper {
+    fn name(&self) -> &str {
+        "Memory mapper"
+    }
+
+    fn read(&self, address: u16) -> u8 {
+        self.data[self.offset(address)]
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        let offset = self.offset(address);
+        self.data[offset] = value;
+    }
+
+    fn size(&self) -> u32 {
+        self.data.len() as u32
+    }
+
+    fn reset(&mut self) {
+        self.segments = [3, 2, 1, 0];
+    }
+
+    fn clear_ram(&mut self) {
+        self.data.fill(0xFF);
+    }
+
+    fn banks(&self) -> Vec<u8> {
+        self.segments.to_vec()
+    }
+
+    fn ports(&self) -> &[u8] {
+        &MAPPER_PORTS
+    }
+
+    fn io_read(&mut self, port: u8) -> u8 {
+        self.segments[(port & 0x03) as usize] | !self.mask()
+    }
+
+    fn io_write(&mut self, port: u8, value: u8) {
+        self.segments[(port & 0x03) as usize] = value & self.mask();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::Bus;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            "ram".parse::<RamCartridge>().unwrap(),
+            RamCartridge::Ram(0x10000)
+        );
+        assert_eq!(
+            "RAM:16".parse::<RamCartridge>().unwrap(),
+            RamCartridge::Ram(0x4000)
+        );
+        assert_eq!(
+            "megaram:512K".parse::<RamCartridge>().unwrap(),
+            RamCartridge::MegaRam(0x80000)
+        );
+        assert_eq!(
+            "megaram".parse::<RamCartridge>().unwrap().to_string(),
+            "256K MegaRAM"
+        );
+        assert_eq!(
+            "mapper".parse::<RamCartridge>().unwrap().to_string(),
+            "512K memory mapper"
+        );
+        assert!("ram:48".parse::<RamCartridge>().is_err());
+        assert!("megaram:100".parse::<RamCartridge>().is_err());
+        assert!("rom".parse::<RamCartridge>().is_err());
+    }
+
+    #[test]
+    fn test_megaram() {
+        let mut bus = Bus::new(&[
+            SlotType::Empty,
+            RamCartridge::MegaRam(0x10000).slot(),
+            SlotType::Empty,
+            SlotType::Empty,
+        ]);
+        // slot 1 on every page
+        bus.ppi.primary_slot_config = 0x55;
+
+        // selects bank 5, then writes to it
+        bus.write_byte(0x4000, 5);
+        bus.input(0x8E);
+        bus.write_byte(0x4000, 0x42);
+        assert_eq!(bus.read_byte(0x4000), 0x42);
+
+        // bank 13 is bank 5 of the 64K
+        bus.output(0x8E, 0);
+        bus.write_byte(0xA000, 13);
+        assert_eq!(bus.read_byte(0xA000), 0x42);
+        assert_eq!(bus.read_byte(0x6000), 0xFF);
+        let banks = bus.slot(1).and_then(|slot| slot.as_slot()).unwrap().banks();
+        assert_eq!(banks, [5, 1, 2, 5]);
+
+        // nothing outside the windows
+        bus.input(0x8E);
+        bus.write_byte(0xC000, 0x12);
+        assert_eq!(bus.read_byte(0xC000), 0xFF);
+
+        bus.clear_ram();
+        assert_eq!(bus.read_byte(0xA000), 0xFF);
+    }
+
+    #[test]
+    fn test_memory_mapper() {
+        let mut bus = Bus::new(&[
+            SlotType::Empty,
+            SlotType::Empty,
+            RamCartridge::MemoryMapper(0x20000).slot(),
+            SlotType::Empty,
+        ]);
+        bus.ppi.primary_slot_config = 0xAA;
+        assert_eq!(bus.input(0xFC), 0xFB);
+
+        // segment 5 on page 2, then on page 1
+        bus.output(0xFE, 5);
+        bus.write_byte(0x8000, 0x42);
+        bus.output(0xFD, 5);
+        assert_eq!(bus.read_byte(0x4000), 0x42);
+        // 128K has 8 segments, so segment 13 is segment 5
+        bus.output(0xFC, 13);
+        assert_eq!(bus.read_byte(0x0000), 0x42);
+        assert_eq!(bus.input(0xFC), 0xFD);
+        let segments = bus.slot(2).and_then(|slot| slot.as_slot()).unwrap().banks();
+        assert_eq!(segments, [5, 5, 5, 0]);
+
+        bus.reset();
+        assert_eq!(bus.input(0xFE), 0xF9);
+    }
+}
@@ -0,0 +1,202 @@
+use anyhow::{anyhow, bail};
+use serde::Serialize;
+
+use crate::symbols::parse_address;
+
+/// A named range of memory, e.g. the system variables of the BIOS.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MemoryRegion {
+    pub start: u16,
+    /// inclusive
+    pub end: u16,
+    pub name: String,
+}
+
+impl MemoryRegion {
+    pub fn contains(&self, address: u16) -> bool {
+        (self.start..=self.end).contains(&address)
+    }
+
+    pub fn size(&self) -> u32 {
+        self.end as u32 - self.start as u32 + 1
+    }
+}
+
+/// Annotations of the memory, which label the addresses of the hexdumps and
+/// the watch list. Region files have one region per line:
+///
+/// ```text
+/// ; start end name
+/// F380 FFFF "system vars"
+/// C000 C0FF sprite buffer
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Regions {
+    // sorted by start, then by end
+    regions: Vec<MemoryRegion>,
+}
+
+impl Regions {
+    /// Adds the regions of a region file.
+    #[cfg(feature = "std-fs")]
+    pub fn load(&mut self, path: impl AsRef<std::path::Path>) -> anyhow::Result<()> {
+        self.add_text(&std::fs::read_to_string(path)?)
+    }
+
+    /// Adds the regions of the text of a region file, skipping comments and
+    /// blank lines.
+    pub fn add_text(&mut self, text: &str) -> anyhow::Result<()> {
+        for (n, line) in text.lines().enumerate() {
+            let line = line.split(';').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let mut parts = line.splitn(3, char::is_whitespace);
+            let (Some(start), Some(end), Some(name)) = (parts.next(), parts.next(), parts.next())
+            else {
+                bail!("Invalid region on line {}: {}", n + 1, line);
+            };
+            let start = parse_address(start)
+                .ok_or_else(|| anyhow!("Invalid address on line {}: {}", n + 1, start))?;
+            let end = parse_address(end)
+                .ok_or_else(|| anyhow!("Invalid address on line {}: {}", n + 1, end))?;
+            self.add(start, end, name)
+                .map_err(|e| anyhow!("{} on line {}", e, n + 1))?;
+        }
+        Ok(())
+    }
+
+    /// Adds a region, renaming the one with the same range if any. The name
+    /// may be quoted.
+    pub fn add(&mut self, start: u16, end: u16, name: &str) -> anyhow::Result<()> {
+        if end < start {
+            bail!("Region end {:#06X} before its start {:#06X}", end, start);
+        }
+        let name = name.trim();
+        let name = name
+            .strip_prefix('"')
+            .and_then(|name| name.strip_suffix('"'))
+            .unwrap_or(name);
+        if name.is_empty() {
+            bail!("Missing region name");
+        }
+
+        let region = MemoryRegion {
+            start,
+            end,
+            name: name.to_string(),
+        };
+        match self
+            .regions
+            .binary_search_by_key(&(start, end), |r| (r.start, r.end))
+        {
+            Ok(n) => self.regions[n] = region,
+            Err(n) => self.regions.insert(n, region),
+        }
+        Ok(())
+    }
+
+    /// Removes the regions starting at an address.
+    pub fn remove(&mut self, start: u16) -> Vec<MemoryRegion> {
+        let (removed, kept) = std::mem::take(&mut self.regions)
+            .into_iter()
+            .partition(|r| r.start == start);
+        self.regions = kept;
+        removed
+    }
+
+    pub fn clear(&mut self) {
+        self.regions.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.regions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.regions.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &MemoryRegion> {
+        self.regions.iter()
+    }
+
+    /// The innermost region holding an address.
+    pub fn find(&self, address: u16) -> Option<&MemoryRegion> {
+        self.regions
+            .iter()
+            .filter(|r| r.contains(address))
+            .min_by_key(|r| r.size())
+    }
+
+    /// The regions with addresses in a range.
+    pub fn overlapping(&self, start: u16, end: u16) -> impl Iterator<Item = &MemoryRegion> {
+        self.regions
+            .iter()
+            .filter(move |r| r.start <= end && r.end >= start)
+    }
+
+    /// Names for a range of a hexdump: the region holding its start, then
+    /// those starting in it.
+    pub fn labels(&self, start: u16, end: u16) -> Vec<&str> {
+        let mut labels: Vec<&str> = self
+            .find(start)
+            .map(|r| r.name.as_str())
+            .into_iter()
+            .collect();
+        for region in self.overlapping(start, end) {
+            if region.start > start && !labels.contains(&region.name.as_str()) {
+                labels.push(&region.name);
+            }
+        }
+        labels
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_text() {
+        let mut regions = Regions::default();
+        regions
+            .add_text(
+                "; BIOS work area
+F380 FFFF \"system vars\"
+0xFD9F $FDA3 H.TIMI hook
+C000 C0FF sprite buffer ; 32 sprites
+",
+            )
+            .unwrap();
+
+        assert_eq!(regions.len(), 3);
+        assert_eq!(regions.find(0xF380).unwrap().name, "system vars");
+        assert_eq!(regions.find(0xFDA0).unwrap().name, "H.TIMI hook");
+        assert_eq!(regions.find(0xC0FF).unwrap().name, "sprite buffer");
+        assert_eq!(regions.find(0xC100), None);
+
+        assert!(regions.add_text("C000 sprites").is_err());
+        assert!(regions.add_text("C0FF C000 sprites").is_err());
+    }
+
+    #[test]
+    fn test_labels() {
+        let mut regions = Regions::default();
+        regions.add(0xF380, 0xFFFF, "system vars").unwrap();
+        regions.add(0xFD9F, 0xFDA3, "H.TIMI").unwrap();
+        regions.add(0xFD9F, 0xFDA3, "\"timer hook\"").unwrap();
+
+        assert_eq!(regions.labels(0xF370, 0xF37F), Vec::<&str>::new());
+        assert_eq!(regions.labels(0xF380, 0xF38F), vec!["system vars"]);
+        assert_eq!(
+            regions.labels(0xFD90, 0xFD9F),
+            vec!["system vars", "timer hook"]
+        );
+        assert_eq!(regions.labels(0xFDA0, 0xFDAF), vec!["timer hook"]);
+
+        assert_eq!(regions.remove(0xFD9F).len(), 1);
+        assert_eq!(regions.overlapping(0x0000, 0xF380).count(), 1);
+    }
+}
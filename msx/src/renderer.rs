@@ -1,12 +1,17 @@
-use msx::{vdp::DisplayMode, TMS9918};
+use crate::{vdp::DisplayMode, TMS9918};
 
+/// Draws the screen of the VDP as color codes, to be converted to RGBA by
+/// a [`FrameBuffer`](crate::frame::FrameBuffer).
 pub struct Renderer<'a> {
     vdp: &'a TMS9918,
     pub screen_buffer: [u8; 256 * 192],
 }
 
 impl<'a> Renderer<'a> {
-    pub fn new(vdp: &'a TMS9918) -> Self {
+    pub fn new(vdp: &'a mut TMS9918) -> Self {
+        // only the patterns written since the last frame are decoded
+        vdp.update_tiles();
+        let vdp: &'a TMS9918 = vdp;
         let screen_buffer = [0; 256 * 192];
         Self { vdp, screen_buffer }
     }
@@ -33,34 +38,37 @@ impl<'a> Renderer<'a> {
         tracing::trace!("Rendering mode: {:?}", self.vdp.display_mode);
 
         for y in y0..height {
-            // renders this raster line
-            match self.vdp.display_mode {
-                DisplayMode::Text1 => {
-                    // screen 0
-                    self.render_text1(y as usize);
-                }
-                DisplayMode::Graphic1 => {
-                    // screen 1
-                    self.render_graphic1(y as usize);
-                }
-                DisplayMode::Graphic2 => { // screen 2
-                     // self.render_graphic2(y as usize);
-                }
-                // DisplayMode::Multicolor => { // screen 3
-                //     self.render_text2(y as usize, fg, bg);
-                // }
-                _ => panic!("Unsupported screen mode: {:?}", self.vdp.display_mode),
+            self.render_line(y as usize);
+        }
+    }
+
+    /// Renders a raster line of the display, 0 to 191.
+    pub fn render_line(&mut self, line: usize) {
+        match self.vdp.display_mode {
+            DisplayMode::Text1 => {
+                // screen 0
+                self.render_text1(line);
+            }
+            DisplayMode::Graphic1 => {
+                // screen 1
+                self.render_graphic1(line);
+            }
+            DisplayMode::Graphic2 => { // screen 2
+                 // self.render_graphic2(line);
+            }
+            DisplayMode::Multicolor => { // screen 3
+                 // self.render_text2(line, fg, bg);
             }
         }
     }
 
     pub fn render_text1(&mut self, line: usize) {
-        // let fg = self.vdp.registers[7] & 0xF0;
-        // let bg = self.vdp.registers[7] & 0x0F;
-        let fg = 15;
-        let bg = 4;
+        // the text color is in the high nibble of R#7, the backdrop color
+        // of the 0 pixels in the low one
+        let fg = self.vdp.registers[7] >> 4;
+        let bg = self.vdp.registers[7] & 0x0F;
 
-        let caracter_pattern_area = self.vdp.char_pattern_table();
+        let tiles = self.vdp.tiles();
         let l = (line + self.vdp.get_vertical_scroll()) & 7;
 
         // Calculate the base address of the PNT using register R#2
@@ -72,11 +80,14 @@ impl<'a> Renderer<'a> {
         for name in name_start..name_end {
             let screen_offset = pnt_base + name; // Calculate the proper offset in the VRAM
             let char_code = self.vdp.vram[screen_offset]; // Get the value directly from the VRAM array
-            let pattern = caracter_pattern_area[l + char_code as usize * 8];
-
-            for i in 0..6 {
-                let mask = 0x80 >> i;
-                self.screen_buffer[pixel_ptr + i] = if (pattern & mask) != 0 { fg } else { bg };
+            let pattern = tiles.row(char_code as usize, l);
+
+            // only the 6 leftmost pixels of a pattern are shown
+            for (pixel, bit) in self.screen_buffer[pixel_ptr..pixel_ptr + 6]
+                .iter_mut()
+                .zip(pattern)
+            {
+                *pixel = if *bit != 0 { fg } else { bg };
             }
 
             pixel_ptr += 6;
@@ -89,7 +100,7 @@ impl<'a> Renderer<'a> {
         let fg = 15;
         let bg = 4;
 
-        let caracter_pattern_area = self.vdp.char_pattern_table();
+        let tiles = self.vdp.tiles();
         let l = (line + self.vdp.get_vertical_scroll()) & 7;
 
         // Calculate the base address of the PNT using register R#2
@@ -101,14 +112,36 @@ impl<'a> Renderer<'a> {
         for name in name_start..name_end {
             let screen_offset = pnt_base + name; // Calculate the proper offset in the VRAM
             let char_code = self.vdp.vram[screen_offset]; // Get the value directly from the VRAM array
-            let pattern = caracter_pattern_area[l + char_code as usize * 8];
+            let pattern = tiles.row(char_code as usize, l);
 
-            for i in 0..8 {
-                let mask = 0x80 >> i;
-                self.screen_buffer[pixel_ptr + i] = if (pattern & mask) != 0 { fg } else { bg };
+            for (pixel, bit) in self.screen_buffer[pixel_ptr..pixel_ptr + 8]
+                .iter_mut()
+                .zip(pattern)
+            {
+                *pixel = if *bit != 0 { fg } else { bg };
             }
 
             pixel_ptr += 8;
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text1_colors() {
+        let mut vdp = TMS9918::new();
+        vdp.display_mode = DisplayMode::Text1;
+        // light yellow on dark red, with character 0 having its leftmost
+        // pixel set on every row
+        vdp.registers[7] = 0xB6;
+        let (patterns, _) = vdp.char_pattern_table_base_and_size();
+        vdp.vram[patterns..patterns + 8].fill(0x80);
+
+        let mut renderer = Renderer::new(&mut vdp);
+        renderer.render_text1(0);
+        assert_eq!(renderer.screen_buffer[..7], [11, 6, 6, 6, 6, 6, 11]);
+    }
+}
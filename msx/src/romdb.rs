@@ -0,0 +1,130 @@
+use std::{collections::HashMap, fmt};
+
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+
+const EMBEDDED_DATABASE: &str = include_str!("../data/romdb.json");
+
+/// Known metadata about a ROM image.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RomInfo {
+    pub crc32: String,
+    #[serde(default)]
+    pub sha1: Option<String>,
+    pub title: String,
+    /// recommended mapper, none for plain ROMs
+    #[serde(default)]
+    pub mapper: Option<String>,
+    /// machine the ROM requires, e.g. MSX1 or MSX2
+    #[serde(default)]
+    pub machine: Option<String>,
+}
+
+impl fmt::Display for RomInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.title)?;
+        if let Some(mapper) = &self.mapper {
+            write!(f, " ({} mapper)", mapper)?;
+        }
+        Ok(())
+    }
+}
+
+/// ROM metadata keyed by CRC32 and SHA1.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RomDatabase {
+    by_crc32: HashMap<String, RomInfo>,
+    by_sha1: HashMap<String, String>,
+}
+
+impl RomDatabase {
+    /// Database with the entries that ship with the emulator.
+    pub fn embedded() -> Self {
+        let mut db = Self::default();
+        db.add_json(EMBEDDED_DATABASE)
+            .expect("embedded ROM database is invalid");
+        db
+    }
+
+    /// Adds the entries of a JSON database file, replacing existing ones.
+    #[cfg(feature = "std-fs")]
+    pub fn load(&mut self, path: impl AsRef<std::path::Path>) -> anyhow::Result<()> {
+        self.add_json(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn add_json(&mut self, json: &str) -> anyhow::Result<()> {
+        let entries: Vec<RomInfo> = serde_json::from_str(json)?;
+        for entry in entries {
+            self.add(entry);
+        }
+        Ok(())
+    }
+
+    pub fn add(&mut self, mut info: RomInfo) {
+        info.crc32 = info.crc32.to_uppercase();
+        if let Some(sha1) = &mut info.sha1 {
+            *sha1 = sha1.to_lowercase();
+            self.by_sha1.insert(sha1.clone(), info.crc32.clone());
+        }
+        self.by_crc32.insert(info.crc32.clone(), info);
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_crc32.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_crc32.is_empty()
+    }
+
+    pub fn find_by_crc32(&self, crc32: u32) -> Option<&RomInfo> {
+        self.by_crc32.get(&format!("{:08X}", crc32))
+    }
+
+    pub fn find_by_sha1(&self, sha1: &str) -> Option<&RomInfo> {
+        self.by_sha1
+            .get(&sha1.to_lowercase())
+            .and_then(|crc32| self.by_crc32.get(crc32))
+    }
+
+    /// Looks up a ROM image by its contents, first by CRC32 and then by SHA1.
+    pub fn lookup(&self, data: &[u8]) -> Option<&RomInfo> {
+        self.find_by_crc32(crc32fast::hash(data))
+            .or_else(|| self.find_by_sha1(&sha1_hex(data)))
+    }
+}
+
+pub fn sha1_hex(data: &[u8]) -> String {
+    Sha1::digest(data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup_embedded() {
+        let db = RomDatabase::embedded();
+        let rom = include_bytes!("../../roms/cbios_logo_msx1.rom");
+
+        let info = db.lookup(rom).unwrap();
+        assert_eq!(info.title, "C-BIOS MSX1 (logo)");
+        assert_eq!(info.machine.as_deref(), Some("MSX1"));
+        assert_eq!(db.lookup(&[0; 16]), None);
+    }
+
+    #[test]
+    fn test_lookup_by_sha1() {
+        let mut db = RomDatabase::default();
+        db.add_json(
+            r#"[{"crc32": "00000000", "sha1": "ABCDEF", "title": "Game", "mapper": "Konami4"}]"#,
+        )
+        .unwrap();
+
+        let info = db.find_by_sha1("abcdef").unwrap();
+        assert_eq!(info.to_string(), "Game (Konami4 mapper)");
+    }
+}
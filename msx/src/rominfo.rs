@@ -0,0 +1,262 @@
+use std::fmt;
+
+use sha1::{Digest, Sha1};
+
+use crate::{disasm, instruction::MAX_INSTRUCTION_LEN};
+
+/// Cartridge header, the `AB` id followed by the routine addresses the BIOS
+/// looks for when booting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RomHeader {
+    /// offset of the header in the image
+    pub offset: usize,
+    pub init: u16,
+    pub statement: u16,
+    pub device: u16,
+    /// tokenized BASIC program run on boot
+    pub text: u16,
+}
+
+impl RomHeader {
+    /// Finds the header at the start of the image, or at the start of its
+    /// second 16K page for images that begin at page 0.
+    pub fn parse(rom: &[u8]) -> Option<Self> {
+        [0x0000, 0x4000].into_iter().find_map(|offset| {
+            let header = rom.get(offset..offset + 10)?;
+            if &header[0..2] != b"AB" {
+                return None;
+            }
+            let word = |i: usize| u16::from_le_bytes([header[i], header[i + 1]]);
+            Some(Self {
+                offset,
+                init: word(2),
+                statement: word(4),
+                device: word(6),
+                text: word(8),
+            })
+        })
+    }
+
+    /// Address the image is expected to start at, based on where its
+    /// routines are.
+    pub fn load_address(&self) -> u16 {
+        let first = [self.init, self.statement, self.device, self.text]
+            .into_iter()
+            .filter(|address| *address != 0)
+            .min()
+            .unwrap_or(0x4000);
+        (first & 0xC000).wrapping_sub(self.offset as u16)
+    }
+}
+
+impl fmt::Display for RomHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let vector = |address: u16| {
+            if address == 0 {
+                "-".to_string()
+            } else {
+                format!("{:#06X}", address)
+            }
+        };
+        write!(
+            f,
+            "INIT: {} STATEMENT: {} DEVICE: {} TEXT: {}",
+            vector(self.init),
+            vector(self.statement),
+            vector(self.device),
+            vector(self.text)
+        )
+    }
+}
+
+/// Cartridge memory mappers, with the names used by the ROM database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mapper {
+    /// up to 64K, without bank switching
+    Plain,
+    Konami4,
+    /// Konami with SCC sound
+    Konami5,
+    Ascii8,
+    Ascii16,
+}
+
+impl fmt::Display for Mapper {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Mapper::Plain => "Plain",
+            Mapper::Konami4 => "Konami4",
+            Mapper::Konami5 => "Konami5",
+            Mapper::Ascii8 => "ASCII8",
+            Mapper::Ascii16 => "ASCII16",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Guesses the mapper of an image by counting the `LD (nn), A` writes to
+/// the bank switching addresses of each mapper.
+pub fn detect_mapper(rom: &[u8]) -> Mapper {
+    if rom.len() <= 0x10000 {
+        return Mapper::Plain;
+    }
+
+    let candidates = [
+        Mapper::Konami4,
+        Mapper::Konami5,
+        Mapper::Ascii8,
+        Mapper::Ascii16,
+    ];
+    let mut votes = [0u32; 4];
+    for window in rom.windows(3) {
+        if window[0] != 0x32 {
+            continue;
+        }
+        match u16::from_le_bytes([window[1], window[2]]) {
+            0x4000 | 0x8000 | 0xA000 => votes[0] += 1,
+            0x5000 | 0x9000 | 0xB000 => votes[1] += 1,
+            0x6800 | 0x7800 => votes[2] += 1,
+            0x77FF => votes[3] += 1,
+            0x6000 => {
+                votes[0] += 1;
+                votes[2] += 1;
+                votes[3] += 1;
+            }
+            0x7000 => {
+                votes[1] += 1;
+                votes[2] += 1;
+                votes[3] += 1;
+            }
+            _ => {}
+        }
+    }
+    // ASCII16 games write to 0x6000 and 0x7000 as well, prefer it on a tie
+    votes[2] = votes[2].saturating_sub(1);
+
+    let mut best = 0;
+    for (i, count) in votes.iter().enumerate() {
+        if *count > votes[best] {
+            best = i;
+        }
+    }
+    candidates[best]
+}
+
+/// What is known about a cartridge image before running it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RomReport {
+    pub size: usize,
+    pub crc32: u32,
+    pub sha1: String,
+    pub header: Option<RomHeader>,
+    pub mapper: Mapper,
+    /// address the first byte of the image is mapped to
+    pub address: u16,
+}
+
+impl RomReport {
+    /// Analyzes an image, mapped at `address` or at the address its header
+    /// suggests.
+    pub fn new(rom: &[u8], address: Option<u16>) -> Self {
+        let header = RomHeader::parse(rom);
+        let address = address
+            .or_else(|| header.map(|header| header.load_address()))
+            .unwrap_or(0x4000);
+
+        Self {
+            size: rom.len(),
+            crc32: crc32fast::hash(rom),
+            sha1: format!("{:x}", Sha1::digest(rom)),
+            header,
+            mapper: detect_mapper(rom),
+            address,
+        }
+    }
+
+    /// Disassembles the first `count` instructions of the INIT routine, when
+    /// it is inside the image.
+    pub fn init_preview(&self, rom: &[u8], count: usize) -> Vec<String> {
+        let Some(header) = self.header.filter(|header| header.init != 0) else {
+            return Vec::new();
+        };
+
+        let byte = |pc: u16| {
+            let offset = pc.wrapping_sub(self.address) as usize;
+            rom.get(offset).copied()
+        };
+
+        let mut lines = Vec::new();
+        let mut pc = header.init;
+        while lines.len() < count && byte(pc).is_some() {
+            let mut bytes = [0xFF; MAX_INSTRUCTION_LEN];
+            for (i, b) in bytes.iter_mut().enumerate() {
+                *b = byte(pc.wrapping_add(i as u16)).unwrap_or(0xFF);
+            }
+            let decoded = disasm::decode(bytes, pc);
+            let hex = bytes[..decoded.len as usize]
+                .iter()
+                .map(|b| format!("{:02X}", b))
+                .collect::<Vec<_>>()
+                .join(" ");
+            lines.push(format!("{:04X}  {:<12}  {}", pc, hex, decoded.mnemonic));
+            pc = pc.wrapping_add(decoded.len as u16);
+        }
+        lines
+    }
+}
+
+impl fmt::Display for RomReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Size: {} bytes ({}K)", self.size, self.size / 1024)?;
+        writeln!(f, "CRC32: {:08X}", self.crc32)?;
+        writeln!(f, "SHA1: {}", self.sha1)?;
+        writeln!(f, "Mapper: {}", self.mapper)?;
+        writeln!(f, "Address: {:#06X}", self.address)?;
+        match &self.header {
+            Some(header) => write!(f, "Header at {:#06X}: {}", header.offset, header),
+            None => write!(f, "Header: none"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header() {
+        let mut rom = vec![0xFF; 0x4000];
+        rom[..10].copy_from_slice(&[b'A', b'B', 0x10, 0x80, 0, 0, 0, 0, 0, 0]);
+        // LD A, #01 / RET
+        rom[0x10..0x13].copy_from_slice(&[0x3E, 0x01, 0xC9]);
+
+        let report = RomReport::new(&rom, None);
+        let header = report.header.unwrap();
+        assert_eq!(header.init, 0x8010);
+        assert_eq!(report.address, 0x8000);
+        assert_eq!(report.mapper, Mapper::Plain);
+        assert_eq!(
+            report.init_preview(&rom, 2),
+            vec!["8010  3E 01         LD A, #01", "8012  C9            RET"]
+        );
+
+        assert_eq!(RomHeader::parse(&[0u8; 0x8000]), None);
+    }
+
+    #[test]
+    fn test_detect_mapper() {
+        let mut rom = vec![0u8; 0x20000];
+        for (i, address) in [0x5000u16, 0x7000, 0x9000, 0xB000].iter().enumerate() {
+            let [lo, hi] = address.to_le_bytes();
+            rom[i * 3..i * 3 + 3].copy_from_slice(&[0x32, lo, hi]);
+        }
+        assert_eq!(detect_mapper(&rom), Mapper::Konami5);
+
+        rom.fill(0);
+        for (i, address) in [0x6000u16, 0x7000, 0x77FF].iter().enumerate() {
+            let [lo, hi] = address.to_le_bytes();
+            rom[i * 3..i * 3 + 3].copy_from_slice(&[0x32, lo, hi]);
+        }
+        assert_eq!(detect_mapper(&rom), Mapper::Ascii16);
+    }
+}
@@ -0,0 +1,104 @@
+//! Follows the text screen for a screen reader or a terminal, telling the
+//! lines that changed rather than the whole screen each time.
+
+/// Frames between the updates, a few per second so that a line isn't read
+/// again for every character typed on it.
+pub const UPDATE_FRAMES: u32 = 15;
+
+/// Compares the text screen, as given by
+/// [`Msx::screen_text`](crate::Msx::screen_text), with the one it saw last.
+#[derive(Debug, Clone, Default)]
+pub struct ScreenReader {
+    lines: Vec<String>,
+}
+
+impl ScreenReader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The lines that are new or changed since the last call, top to
+    /// bottom, without the ones with no word of two letters or digits, which
+    /// are blank, decoration or the patterns of a game. After the screen scrolled, only
+    /// the lines that came in at the bottom or changed are new.
+    pub fn update(&mut self, screen: Vec<String>) -> Vec<String> {
+        if screen == self.lines {
+            return Vec::new();
+        }
+        let previous = std::mem::replace(&mut self.lines, screen);
+        let scroll = scroll(&previous, &self.lines);
+        self.lines
+            .iter()
+            .enumerate()
+            .filter(|(row, line)| has_word(line) && previous.get(row + scroll) != Some(*line))
+            .map(|(_, line)| line.clone())
+            .collect()
+    }
+}
+
+fn has_word(line: &str) -> bool {
+    line.as_bytes()
+        .windows(2)
+        .any(|pair| pair.iter().all(u8::is_ascii_alphanumeric))
+}
+
+// lines the screen scrolled up by, the fewest for which the lines that
+// aren't blank all moved, or 0
+fn scroll(previous: &[String], screen: &[String]) -> usize {
+    if previous.len() != screen.len() {
+        return 0;
+    }
+    (1..screen.len())
+        .find(|&n| {
+            let (moved, kept) = (&previous[n..], &screen[..screen.len() - n]);
+            moved == kept && moved.iter().any(|line| !line.is_empty())
+        })
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn screen(rows: &[&str]) -> Vec<String> {
+        rows.iter().map(|row| row.to_string()).collect()
+    }
+
+    #[test]
+    fn test_changes() {
+        let mut reader = ScreenReader::new();
+        assert_eq!(
+            reader.update(screen(&["MSX BASIC", "", "Ok", ""])),
+            ["MSX BASIC", "Ok"]
+        );
+        assert!(reader
+            .update(screen(&["MSX BASIC", "", "Ok", ""]))
+            .is_empty());
+        assert_eq!(
+            reader.update(screen(&["MSX BASIC", "", "Ok", "PRINT"])),
+            ["PRINT"]
+        );
+
+        // the graphic modes have no text
+        assert!(reader.update(Vec::new()).is_empty());
+        assert_eq!(reader.update(screen(&["Ok", ""])), ["Ok"]);
+
+        // nor do the patterns of SCREEN 1 games
+        assert!(reader.update(screen(&["..--..", "...Y...8"])).is_empty());
+    }
+
+    #[test]
+    fn test_scroll() {
+        let mut reader = ScreenReader::new();
+        reader.update(screen(&["10", "20", "30", "40"]));
+        assert_eq!(
+            reader.update(screen(&["30", "40", "50", "60"])),
+            ["50", "60"]
+        );
+
+        // a line that moved up with the others isn't new, even if it's
+        // the same as the one that was there
+        reader.update(screen(&["AA", "AA", "BB", ""]));
+        assert_eq!(reader.update(screen(&["AA", "BB", "", "CC"])), ["CC"]);
+    }
+}
@@ -0,0 +1,109 @@
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+use tracing::trace;
+
+// i8251 status register bits
+const STATUS_TX_READY: u8 = 0x01;
+const STATUS_RX_READY: u8 = 0x02;
+const STATUS_TX_EMPTY: u8 = 0x04;
+const STATUS_DSR: u8 = 0x80;
+
+/// Minimal i8251 USART as found on the MSX RS-232 interface (ports 0x80 and
+/// 0x81). Instead of a physical line, bytes are queued so that the host can
+/// carry them to another emulator instance (see the link cable in the runner).
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct I8251 {
+    /// bytes received from the other end, waiting to be read by the CPU
+    rx: VecDeque<u8>,
+    /// bytes written by the CPU, waiting to be sent to the other end
+    tx: VecDeque<u8>,
+
+    mode: u8,
+    command: u8,
+    expecting_mode: bool,
+    connected: bool,
+}
+
+impl I8251 {
+    pub fn new() -> Self {
+        Self {
+            rx: VecDeque::new(),
+            tx: VecDeque::new(),
+            mode: 0,
+            command: 0,
+            expecting_mode: true,
+            connected: false,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.rx.clear();
+        self.tx.clear();
+        self.mode = 0;
+        self.command = 0;
+        self.expecting_mode = true;
+    }
+
+    /// Marks the line as connected, which is reported to the CPU as DSR.
+    pub fn set_connected(&mut self, connected: bool) {
+        self.connected = connected;
+    }
+
+    /// Queues bytes coming from the other end of the link.
+    pub fn receive(&mut self, data: &[u8]) {
+        self.rx.extend(data);
+    }
+
+    /// Takes the bytes the CPU transmitted since the last call.
+    pub fn take_transmitted(&mut self) -> Vec<u8> {
+        self.tx.drain(..).collect()
+    }
+
+    pub fn status(&self) -> u8 {
+        let mut status = STATUS_TX_READY | STATUS_TX_EMPTY;
+        if !self.rx.is_empty() {
+            status |= STATUS_RX_READY;
+        }
+        if self.connected {
+            status |= STATUS_DSR;
+        }
+        status
+    }
+
+    pub fn read(&mut self, port: u8) -> u8 {
+        match port {
+            0x80 => {
+                let data = self.rx.pop_front().unwrap_or(0xFF);
+                trace!("[serial] Reading data {:02X}", data);
+                data
+            }
+            0x81 => self.status(),
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write(&mut self, port: u8, data: u8) {
+        match port {
+            0x80 => {
+                trace!("[serial] Writing data {:02X}", data);
+                self.tx.push_back(data);
+            }
+            0x81 => {
+                if self.expecting_mode {
+                    trace!("[serial] Mode {:02X}", data);
+                    self.mode = data;
+                    self.expecting_mode = false;
+                } else {
+                    trace!("[serial] Command {:02X}", data);
+                    self.command = data;
+                    // internal reset: the next write is a mode instruction again
+                    if data & 0x40 != 0 {
+                        self.expecting_mode = true;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
@@ -1,17 +1,38 @@
 use std::{
+    any::Any,
     fmt::{self, Debug},
-    fs::File,
-    io::Read,
     path::PathBuf,
 };
 
 use serde::{Deserialize, Serialize};
 
+#[cfg(feature = "std-fs")]
+use crate::{archive, patch};
+use crate::{
+    ram_cartridge::{MegaRam, MemoryMapper},
+    rominfo::RomHeader,
+    sunrise_ide::SunriseIde,
+};
+
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub enum SlotType {
     Empty,
     Ram(RamSlot),
     Rom(RomSlot),
+    /// banked RAM cartridge, see [`MegaRam`]
+    MegaRam(MegaRam),
+    /// memory mapped RAM, see [`MemoryMapper`]
+    MemoryMapper(MemoryMapper),
+    /// four secondary slots, see [`ExpandedSlot`]
+    Expanded(ExpandedSlot),
+    /// IDE interface with a hard disk, see [`SunriseIde`]
+    SunriseIde(SunriseIde),
+    /// memory mapped device with its own behavior, e.g. a mapper or a chip
+    /// with registers in memory space. Devices aren't kept in save states:
+    /// they are saved as empty slots, and stay in the slots of the machine a
+    /// state is loaded in, see [`SlotType::restore_devices`].
+    #[serde(skip)]
+    Device(Box<dyn Slot>),
 }
 
 impl fmt::Display for SlotType {
@@ -21,59 +42,235 @@ impl fmt::Display for SlotType {
             SlotType::Ram(slot) => write!(f, "RAM base={:#06X} size={:#06X}", slot.base, slot.size),
             SlotType::Rom(slot) => write!(
                 f,
-                "ROM path={:?} base={:#06X} size={:#06X}",
-                slot.rom_path, slot.base, slot.size
+                "ROM path={:?} base={:#06X} size={:#06X} crc32={:08X}",
+                slot.rom_path, slot.base, slot.size, slot.crc32
             ),
+            SlotType::MegaRam(slot) => write!(f, "{} size={:#06X}", slot.name(), slot.size()),
+            SlotType::MemoryMapper(slot) => write!(f, "{} size={:#06X}", slot.name(), slot.size()),
+            SlotType::SunriseIde(slot) => write!(
+                f,
+                "{} size={:#06X} disk={:#X}",
+                slot.name(),
+                slot.size(),
+                slot.disk().map_or(0, |disk| disk.len())
+            ),
+            SlotType::Expanded(slot) => {
+                write!(f, "Expanded (")?;
+                for (n, sub_slot) in slot.slots.iter().enumerate() {
+                    if n > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", n, sub_slot)?;
+                }
+                write!(f, ")")
+            }
+            SlotType::Device(slot) => write!(f, "{} size={:#06X}", slot.name(), slot.size()),
         }
     }
 }
 
 impl SlotType {
-    pub fn read(&self, address: u16) -> u8 {
+    pub fn as_slot(&self) -> Option<&dyn Slot> {
         match self {
-            SlotType::Empty => 0xFF,
-            SlotType::Ram(slot) => slot.read(address),
-            SlotType::Rom(slot) => slot.read(address),
+            SlotType::Empty => None,
+            SlotType::Ram(slot) => Some(slot),
+            SlotType::Rom(slot) => Some(slot),
+            SlotType::MegaRam(slot) => Some(slot),
+            SlotType::MemoryMapper(slot) => Some(slot),
+            SlotType::Expanded(slot) => Some(slot),
+            SlotType::SunriseIde(slot) => Some(slot),
+            SlotType::Device(slot) => Some(slot.as_ref()),
         }
     }
 
-    pub fn write(&mut self, address: u16, value: u8) {
+    pub fn as_slot_mut(&mut self) -> Option<&mut dyn Slot> {
         match self {
-            SlotType::Empty => {}
-            SlotType::Ram(slot) => slot.write(address, value),
-            SlotType::Rom(slot) => slot.write(address, value),
+            SlotType::Empty => None,
+            SlotType::Ram(slot) => Some(slot),
+            SlotType::Rom(slot) => Some(slot),
+            SlotType::MegaRam(slot) => Some(slot),
+            SlotType::MemoryMapper(slot) => Some(slot),
+            SlotType::Expanded(slot) => Some(slot),
+            SlotType::SunriseIde(slot) => Some(slot),
+            SlotType::Device(slot) => Some(slot.as_mut()),
+        }
+    }
+
+    pub fn read(&self, address: u16) -> u8 {
+        self.as_slot().map_or(0xFF, |slot| slot.read(address))
+    }
+
+    pub fn write(&mut self, address: u16, value: u8) {
+        if let Some(slot) = self.as_slot_mut() {
+            slot.write(address, value);
         }
     }
 
     pub fn size(&self) -> u32 {
+        self.as_slot().map_or(0, |slot| slot.size())
+    }
+
+    pub fn reset(&mut self) {
+        if let Some(slot) = self.as_slot_mut() {
+            slot.reset();
+        }
+    }
+
+    /// Clears the RAM of the slot, see [`Slot::clear_ram`].
+    pub fn clear_ram(&mut self) {
+        match self {
+            SlotType::Ram(ram) => ram.clear(),
+            slot => {
+                if let Some(slot) = slot.as_slot_mut() {
+                    slot.clear_ram();
+                }
+            }
+        }
+    }
+
+    /// Empties the device slots, this one or its secondary slots, which
+    /// can't be serialized.
+    pub fn remove_devices(&mut self) {
         match self {
-            SlotType::Empty => 0,
-            SlotType::Ram(slot) => slot.size,
-            SlotType::Rom(slot) => slot.size,
+            SlotType::Device(_) => *self = SlotType::Empty,
+            SlotType::Expanded(expanded) => {
+                expanded.slots.iter_mut().for_each(SlotType::remove_devices)
+            }
+            _ => {}
+        }
+    }
+
+    /// Puts back the devices of `slot` in the slots emptied by
+    /// [`SlotType::remove_devices`], e.g. after loading a state.
+    pub fn restore_devices(&mut self, slot: SlotType) {
+        match (self, slot) {
+            (this @ SlotType::Empty, device @ SlotType::Device(_)) => *this = device,
+            (SlotType::Expanded(this), SlotType::Expanded(expanded)) => {
+                for (this, slot) in this.slots.iter_mut().zip(expanded.slots) {
+                    this.restore_devices(slot);
+                }
+            }
+            _ => {}
         }
     }
 }
 
-pub trait Slot: Debug {
+/// Contents of a slot, addressed with the CPU addresses of the pages it is
+/// selected on. Devices with side effects on reads need interior
+/// mutability, as memory is read through a shared reference.
+pub trait Slot: SlotClone + Debug + Send + Sync {
+    fn name(&self) -> &str;
     fn read(&self, address: u16) -> u8;
     fn write(&mut self, address: u16, value: u8);
+    fn size(&self) -> u32;
+
+    /// Called when the machine is reset.
+    fn reset(&mut self) {}
+
+    /// Called on a hard reset, to clear the RAM of the slot if it has any.
+    fn clear_ram(&mut self) {}
+
+    /// Called on a power cycle, after [`Slot::clear_ram`], to lose the
+    /// memory a battery keeps through resets, e.g. SRAM, unless it's
+    /// persisted elsewhere.
+    fn power_off(&mut self) {}
+
+    /// Banks selected in the windows of a mapper, by address, for the
+    /// debuggers to show.
+    fn banks(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    /// I/O ports the slot decodes besides its memory, like the one a MegaRAM
+    /// switches modes with. Ports of the I/O devices take precedence.
+    fn ports(&self) -> &[u8] {
+        &[]
+    }
+
+    fn io_read(&mut self, _port: u8) -> u8 {
+        0xFF
+    }
+
+    fn io_write(&mut self, _port: u8, _value: u8) {}
+}
+
+/// Lets boxed slots be cloned and compared like the built-in ones.
+pub trait SlotClone {
+    fn clone_box(&self) -> Box<dyn Slot>;
+    fn as_any(&self) -> &dyn Any;
+    fn eq_slot(&self, other: &dyn Slot) -> bool;
+}
+
+impl<T: Slot + Clone + PartialEq + 'static> SlotClone for T {
+    fn clone_box(&self) -> Box<dyn Slot> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn eq_slot(&self, other: &dyn Slot) -> bool {
+        other.as_any().downcast_ref::<T>() == Some(self)
+    }
+}
+
+impl Clone for Box<dyn Slot> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+impl PartialEq for Box<dyn Slot> {
+    fn eq(&self, other: &Self) -> bool {
+        self.eq_slot(other.as_ref())
+    }
+}
+
+// paths are saved as text, so states with paths that aren't valid UTF-8 can
+// still be saved and loaded on other platforms, such as the web version
+mod text_path {
+    use std::path::PathBuf;
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        path: &Option<PathBuf>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        path.as_ref()
+            .map(|path| path.to_string_lossy())
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<PathBuf>, D::Error> {
+        Ok(Option::<String>::deserialize(deserializer)?.map(PathBuf::from))
+    }
 }
 
 #[derive(Debug, Default, Serialize, Deserialize, PartialEq, Clone)]
 pub struct RomSlot {
+    #[serde(with = "text_path")]
     pub rom_path: Option<PathBuf>,
     pub base: u16,
     pub size: u32,
     pub data: Vec<u8>,
+    /// CRC32 of the ROM image, before it was padded to the slot size
+    #[serde(default)]
+    pub crc32: u32,
 }
 
 impl RomSlot {
     pub fn new(rom: &[u8], base: u16, size: u32) -> Self {
         let mut data = vec![0xFF; size as usize];
-        data[0..rom.len()].copy_from_slice(rom);
 
-        if rom.len() < size as usize {
-            data[rom.len()..].copy_from_slice(&rom[0..(size as usize - rom.len())]);
+        // smaller ROMs are mirrored over the whole slot
+        if !rom.is_empty() {
+            for (i, byte) in data.iter_mut().enumerate() {
+                *byte = rom[i % rom.len()];
+            }
         }
 
         RomSlot {
@@ -81,28 +278,75 @@ impl RomSlot {
             size,
             data,
             rom_path: None,
+            crc32: crc32fast::hash(rom),
         }
     }
 
+    #[cfg(feature = "std-fs")]
     pub fn load(rom_path: PathBuf, base: u16, size: u32) -> anyhow::Result<Self> {
-        let mut file = File::open(&rom_path)?;
-        let mut buffer = Vec::new();
-        file.read_to_end(&mut buffer)?;
+        Self::load_patched(rom_path, &[], base, size)
+    }
 
+    /// Loads a ROM, applying the given IPS/BPS patches in order before it is
+    /// placed in the slot.
+    #[cfg(feature = "std-fs")]
+    pub fn load_patched(
+        rom_path: PathBuf,
+        patches: &[PathBuf],
+        base: u16,
+        size: u32,
+    ) -> anyhow::Result<Self> {
+        let buffer = read_patched(&rom_path, patches)?;
         let mut rom_slot = Self::new(&buffer, base, size);
         rom_slot.rom_path = Some(rom_path);
 
         Ok(rom_slot)
     }
 
-    fn translate_address(&self, address: u16) -> u16 {
-        address - self.base
+    /// A cartridge, mapped from the address its header asks for, or from
+    /// 0x4000 without one, up to the end of the memory.
+    pub fn cartridge(rom: &[u8]) -> Self {
+        let base = RomHeader::parse(rom).map_or(0x4000, |header| header.load_address());
+        Self::new(rom, base, 0x10000 - base as u32)
+    }
+
+    /// Loads a cartridge, applying the given IPS/BPS patches, see
+    /// [`RomSlot::cartridge`].
+    #[cfg(feature = "std-fs")]
+    pub fn load_cartridge(rom_path: PathBuf, patches: &[PathBuf]) -> anyhow::Result<Self> {
+        let mut rom_slot = Self::cartridge(&read_patched(&rom_path, patches)?);
+        rom_slot.rom_path = Some(rom_path);
+
+        Ok(rom_slot)
+    }
+
+    // None below the ROM, which reads as unmapped
+    fn translate_address(&self, address: u16) -> Option<u16> {
+        address.checked_sub(self.base)
     }
 }
 
+// the image of a ROM file with the patches applied in order
+#[cfg(feature = "std-fs")]
+fn read_patched(rom_path: &std::path::Path, patches: &[PathBuf]) -> anyhow::Result<Vec<u8>> {
+    let mut buffer = archive::read_file(rom_path)?;
+    for patch_path in patches {
+        let data = archive::read_file(patch_path)?;
+        buffer = patch::apply(&buffer, &data)
+            .map_err(|e| anyhow::anyhow!("{}: {}", patch_path.display(), e))?;
+    }
+    Ok(buffer)
+}
+
 impl Slot for RomSlot {
+    fn name(&self) -> &str {
+        "ROM"
+    }
+
     fn read(&self, address: u16) -> u8 {
-        let address = self.translate_address(address);
+        let Some(address) = self.translate_address(address) else {
+            return 0xFF;
+        };
         if (address as usize) >= self.data.len() {
             // tracing::warn!(
             //     "Attempt to read from out of bounds ROM address {:#06X}, returning 0xFF",
@@ -116,6 +360,10 @@ impl Slot for RomSlot {
     fn write(&mut self, address: u16, _value: u8) {
         tracing::trace!("Attempt to write to ROM address {:#06X}", address);
     }
+
+    fn size(&self) -> u32 {
+        self.size
+    }
 }
 
 #[derive(Debug, Default, Serialize, Deserialize, PartialEq, Clone)]
@@ -131,14 +379,37 @@ impl RamSlot {
         RamSlot { base, data, size }
     }
 
-    fn translate_address(&self, address: u16) -> u16 {
-        address - self.base
+    // None below the RAM, e.g. page 2 of a 16K machine, which isn't mapped
+    fn translate_address(&self, address: u16) -> Option<u16> {
+        address.checked_sub(self.base)
+    }
+
+    /// Fills the RAM with the power on pattern.
+    pub fn clear(&mut self) {
+        self.data.fill(0xFF);
+    }
+
+    /// The `len` bytes from the address, if they're all in the RAM.
+    pub fn bytes(&self, address: u16, len: usize) -> Option<&[u8]> {
+        let start = address.checked_sub(self.base)? as usize;
+        self.data.get(start..start + len)
+    }
+
+    pub fn bytes_mut(&mut self, address: u16, len: usize) -> Option<&mut [u8]> {
+        let start = address.checked_sub(self.base)? as usize;
+        self.data.get_mut(start..start + len)
     }
 }
 
 impl Slot for RamSlot {
+    fn name(&self) -> &str {
+        "RAM"
+    }
+
     fn read(&self, address: u16) -> u8 {
-        let address = self.translate_address(address);
+        let Some(address) = self.translate_address(address) else {
+            return 0xFF;
+        };
         if (address as usize) >= self.data.len() {
             tracing::warn!(
                 "Attempt to read from out of bounds RAM address {:#06X}, returning 0xFF",
@@ -150,10 +421,226 @@ impl Slot for RamSlot {
     }
 
     fn write(&mut self, address: u16, value: u8) {
-        let address = self.translate_address(address);
+        let Some(address) = self.translate_address(address) else {
+            return;
+        };
         if (address as usize) >= self.data.len() {
             return;
         }
         self.data[address as usize] = value;
     }
+
+    fn size(&self) -> u32 {
+        self.size
+    }
+}
+
+/// A primary slot expanded into four secondary slots, the one seen on each
+/// page being selected by the secondary slot register at 0xFFFF, as on MSX2
+/// machines. The register reads back complemented, which is how the BIOS
+/// finds expanded slots, and is only seen while page 3 is on the slot.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExpandedSlot {
+    slots: Vec<SlotType>,
+    register: u8,
+    // ports the secondary slots decode, all of them being asked
+    ports: Vec<u8>,
+}
+
+impl ExpandedSlot {
+    pub fn new(slots: [SlotType; 4]) -> Self {
+        let mut ports: Vec<u8> = slots
+            .iter()
+            .filter_map(SlotType::as_slot)
+            .flat_map(|slot| slot.ports().iter().copied())
+            .collect();
+        ports.sort_unstable();
+        ports.dedup();
+        Self {
+            slots: slots.into(),
+            register: 0,
+            ports,
+        }
+    }
+
+    /// The secondary slot register, two bits per page like the primary one.
+    pub fn register(&self) -> u8 {
+        self.register
+    }
+
+    pub fn slot(&self, slot: u8) -> Option<&SlotType> {
+        self.slots.get(slot as usize)
+    }
+
+    // the secondary slot selected for the page of the address
+    fn page_slot(&self, address: u16) -> usize {
+        ((self.register >> ((address >> 14) * 2)) & 0b11) as usize
+    }
+
+    // secondary slots decoding a port
+    fn slots_at(&mut self, port: u8) -> impl Iterator<Item = &mut dyn Slot> {
+        self.slots
+            .iter_mut()
+            .filter_map(SlotType::as_slot_mut)
+            .filter(move |slot| slot.ports().contains(&port))
+    }
+}
+
+impl Slot for ExpandedSlot {
+    fn name(&self) -> &str {
+        "Expanded slot"
+    }
+
+    fn read(&self, address: u16) -> u8 {
+        if address == 0xFFFF {
+            return !self.register;
+        }
+        self.slots[self.page_slot(address)].read(address)
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        if address == 0xFFFF {
+            self.register = value;
+            return;
+        }
+        let slot = self.page_slot(address);
+        self.slots[slot].write(address, value);
+    }
+
+    fn size(&self) -> u32 {
+        self.slots.iter().map(SlotType::size).sum()
+    }
+
+    fn reset(&mut self) {
+        self.register = 0;
+        self.slots.iter_mut().for_each(SlotType::reset);
+    }
+
+    fn clear_ram(&mut self) {
+        self.slots.iter_mut().for_each(SlotType::clear_ram);
+    }
+
+    fn power_off(&mut self) {
+        for slot in self.slots.iter_mut().filter_map(SlotType::as_slot_mut) {
+            slot.power_off();
+        }
+    }
+
+    fn banks(&self) -> Vec<u8> {
+        self.slots
+            .iter()
+            .filter_map(SlotType::as_slot)
+            .flat_map(|slot| slot.banks())
+            .collect()
+    }
+
+    fn ports(&self) -> &[u8] {
+        &self.ports
+    }
+
+    fn io_read(&mut self, port: u8) -> u8 {
+        // the secondary slots decoding the port all drive the data bus
+        self.slots_at(port)
+            .fold(0xFF, |value, slot| value & slot.io_read(port))
+    }
+
+    fn io_write(&mut self, port: u8, value: u8) {
+        self.slots_at(port)
+            .for_each(|slot| slot.io_write(port, value));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 8K banks switched by writing to the first byte of each bank
+    #[derive(Debug, Clone, PartialEq)]
+    struct BankedRom {
+        data: Vec<u8>,
+        banks: [u8; 4],
+    }
+
+    impl Slot for BankedRom {
+        fn name(&self) -> &str {
+            "Banked ROM"
+        }
+
+        fn read(&self, address: u16) -> u8 {
+            let page = (address.wrapping_sub(0x4000) >> 13) as usize;
+            let bank = self.banks.get(page).copied().unwrap_or(0) as usize;
+            self.data[bank * 0x2000 + (address & 0x1FFF) as usize]
+        }
+
+        fn write(&mut self, address: u16, value: u8) {
+            if address & 0x1FFF == 0 {
+                let page = (address.wrapping_sub(0x4000) >> 13) as usize;
+                if let Some(bank) = self.banks.get_mut(page) {
+                    *bank = value & 0x03;
+                }
+            }
+        }
+
+        fn size(&self) -> u32 {
+            self.data.len() as u32
+        }
+
+        fn reset(&mut self) {
+            self.banks = [0, 1, 2, 3];
+        }
+    }
+
+    #[test]
+    fn test_device_slot() {
+        let data = (0..4).flat_map(|bank| vec![bank; 0x2000]).collect();
+        let mut slot = SlotType::Device(Box::new(BankedRom {
+            data,
+            banks: [0, 1, 2, 3],
+        }));
+        assert_eq!(slot.read(0x6000), 1);
+
+        slot.write(0x6000, 3);
+        assert_eq!(slot.read(0x6000), 3);
+        assert_eq!(slot.to_string(), "Banked ROM size=0x8000");
+
+        let copy = slot.clone();
+        assert_eq!(copy, slot);
+        slot.reset();
+        assert_eq!(slot.read(0x6000), 1);
+        assert_ne!(copy, slot);
+        assert_ne!(slot, SlotType::Ram(RamSlot::new(0x0000, 0x8000)));
+    }
+
+    #[test]
+    fn test_expanded_slot() {
+        let mut slot = SlotType::Expanded(ExpandedSlot::new([
+            SlotType::Rom(RomSlot::new(&[0x11], 0x0000, 0x10000)),
+            SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+            SlotType::Empty,
+            SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+        ]));
+        // secondary slot 0 on every page after a reset
+        assert_eq!(slot.read(0x4000), 0x11);
+        assert_eq!(slot.read(0xFFFF), 0xFF);
+
+        // 1 on page 3, 3 on page 2 and 2 on page 1
+        slot.write(0xFFFF, 0b01_11_10_00);
+        assert_eq!(slot.read(0xFFFF), 0b10_00_01_11);
+        assert_eq!(slot.read(0x4000), 0xFF);
+        slot.write(0x8000, 0x22);
+        slot.write(0xC000, 0x33);
+        assert_eq!(slot.read(0x8000), 0x22);
+        assert_eq!(slot.read(0xC000), 0x33);
+        assert_eq!(slot.read(0x0000), 0x11);
+        assert_eq!(
+            slot.to_string(),
+            "Expanded (0: ROM path=None base=0x0000 size=0x10000 crc32=B8B2CF7F, \
+             1: RAM base=0x0000 size=0x10000, 2: Empty, 3: RAM base=0x0000 size=0x10000)"
+        );
+
+        slot.clear_ram();
+        assert_eq!(slot.read(0x8000), 0xFF);
+        slot.reset();
+        assert_eq!(slot.read(0xFFFF), 0xFF);
+    }
 }
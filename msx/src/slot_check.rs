@@ -0,0 +1,270 @@
+use std::{collections::BTreeMap, fmt, ops::RangeInclusive};
+
+use crate::Z80;
+
+/// RDPRIM, WRPRIM and CLPRIM, the routines the BIOS copies to its work area
+/// to reach the other slots, in RAM as they switch the pages of the ROM.
+pub const PRIMARY_SLOT_ROUTINES: RangeInclusive<u16> = 0xF380..=0xF399;
+
+/// A change of the primary slot register, through port 0xA8.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlotSwitch {
+    /// address of the instruction writing the register
+    pub pc: u16,
+    pub from: u8,
+    pub to: u8,
+    // instruction count when it happened
+    step: u64,
+}
+
+/// A read from a page whose selected slot is empty, by a program rather
+/// than by the BIOS probing the slots.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlotConflict {
+    /// address of the instruction reading
+    pub pc: u16,
+    pub address: u16,
+    pub primary_slot_config: u8,
+    /// the last slot switch, if there was one
+    pub switch: Option<SlotSwitch>,
+    /// instructions run since that switch
+    pub since_switch: u64,
+}
+
+// the slots selected on the pages 0 to 3, e.g. "0 1 3 3"
+pub(crate) struct Pages(pub u8);
+
+impl fmt::Display for Pages {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let slots: Vec<String> = (0..4)
+            .map(|page| ((self.0 >> (page * 2)) & 0b11).to_string())
+            .collect();
+        write!(f, "{} ({:#04X})", slots.join(" "), self.0)
+    }
+}
+
+impl fmt::Display for SlotConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let page = page_of(self.address);
+        // a program running into the empty slot fetches its opcodes there
+        let access = if self.address == self.pc {
+            "Fetch"
+        } else {
+            "Read"
+        };
+        write!(
+            f,
+            "{} of {:#06X} at {:#06X} from the empty slot {} on page {}, pages in slots {}",
+            access,
+            self.address,
+            self.pc,
+            slot_of(self.primary_slot_config, page),
+            page,
+            Pages(self.primary_slot_config)
+        )?;
+        if let Some(switch) = &self.switch {
+            write!(
+                f,
+                ", {} instruction{} after the switch from {} at {:#06X}",
+                self.since_switch,
+                if self.since_switch == 1 { "" } else { "s" },
+                Pages(switch.from),
+                switch.pc
+            )?;
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn page_of(address: u16) -> u8 {
+    (address >> 14) as u8
+}
+
+pub(crate) fn slot_of(primary_slot_config: u8, page: u8) -> u8 {
+    (primary_slot_config >> (page * 2)) & 0b11
+}
+
+/// Reports the reads from pages whose selected slot is empty, where a
+/// program expected its ROM or RAM: the symptom of a wrong slot passed to
+/// ENASLT or CALSLT, or of a slot switch the program didn't undo. The
+/// reads of the BIOS, in page 0 of slot 0 or in its
+/// [`PRIMARY_SLOT_ROUTINES`], which probes the slots for cartridges and
+/// RAM, are left out.
+///
+/// Each report tells the primary slot register and the last switch of it
+/// before the read. Secondary slots aren't emulated, so there is no
+/// secondary slot register to tell.
+#[derive(Debug, Clone, Default)]
+pub struct SlotChecker {
+    steps: u64,
+    // PC and primary slot register before the instruction being executed
+    pending: Option<(u16, u8)>,
+    last_switch: Option<SlotSwitch>,
+    // conflicts by the PC of the instruction, with how many times
+    conflicts: BTreeMap<u16, (SlotConflict, u64)>,
+}
+
+impl SlotChecker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Must be called before the CPU executes an instruction.
+    pub fn before_step(&mut self, cpu: &Z80, primary_slot_config: u8) {
+        self.pending = Some((cpu.pc, primary_slot_config));
+    }
+
+    /// Must be called after the CPU executed an instruction, with the
+    /// primary slot register and the address it read from an empty slot,
+    /// if any. Returns the first conflict of each instruction, the others
+    /// being only counted.
+    pub fn after_step(
+        &mut self,
+        primary_slot_config: u8,
+        empty_read: Option<u16>,
+    ) -> Option<SlotConflict> {
+        let (pc, before) = self.pending.take()?;
+        self.steps += 1;
+        if primary_slot_config != before {
+            self.last_switch = Some(SlotSwitch {
+                pc,
+                from: before,
+                to: primary_slot_config,
+                step: self.steps,
+            });
+        }
+
+        let address = empty_read?;
+        if page_of(pc) == 0 && slot_of(before, 0) == 0 || PRIMARY_SLOT_ROUTINES.contains(&pc) {
+            return None;
+        }
+
+        // the slots of the read are those before an instruction switching
+        // them, which reads its operands first
+        let conflict = SlotConflict {
+            pc,
+            address,
+            primary_slot_config: before,
+            switch: self.last_switch.filter(|switch| switch.step < self.steps),
+            since_switch: self
+                .last_switch
+                .map_or(0, |switch| self.steps - switch.step),
+        };
+        let (_, count) = self.conflicts.entry(pc).or_insert((conflict, 0));
+        *count += 1;
+        (*count == 1).then_some(conflict)
+    }
+
+    /// The first conflict of each instruction that read from an empty slot,
+    /// with how many times it did.
+    pub fn conflicts(&self) -> impl Iterator<Item = (&SlotConflict, u64)> + '_ {
+        self.conflicts
+            .values()
+            .map(|(conflict, count)| (conflict, *count))
+    }
+
+    /// Instructions checked so far.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        slot::{RamSlot, SlotType},
+        Msx,
+    };
+
+    fn run(msx: &mut Msx, checker: &mut SlotChecker, steps: usize) -> Vec<SlotConflict> {
+        let mut conflicts = Vec::new();
+        for _ in 0..steps {
+            msx.empty_slot_read();
+            checker.before_step(&msx.cpu, msx.primary_slot_config());
+            msx.step();
+            conflicts.extend(checker.after_step(msx.primary_slot_config(), msx.empty_slot_read()));
+        }
+        conflicts
+    }
+
+    #[test]
+    fn test_read_after_switch() {
+        // at 0xC000 of the RAM in slot 3:
+        // LD A,0xF3 / OUT (0xA8),A / LD A,(0x4000) / LD A,(0x8000) / JR $-6
+        let mut msx = Msx::new(&[
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+        ]);
+        let program = [
+            0x3E, 0xF3, 0xD3, 0xA8, 0x3A, 0x00, 0x40, 0x3A, 0x00, 0x80, 0x18, 0xF8,
+        ];
+        msx.bus.write().unwrap().output(0xA8, 0xFF);
+        for (i, byte) in program.iter().enumerate() {
+            msx.set_memory(0xC000 + i as u16, *byte);
+        }
+        msx.cpu.pc = 0xC000;
+        let mut checker = SlotChecker::new();
+
+        let conflicts = run(&mut msx, &mut checker, 8);
+        // each instruction is reported once, the reads of the RAM never
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(
+            conflicts[0].to_string(),
+            "Read of 0x4000 at 0xC004 from the empty slot 0 on page 1, \
+             pages in slots 3 0 3 3 (0xF3), 1 instruction after the switch \
+             from 3 3 3 3 (0xFF) at 0xC002"
+        );
+        let counts: Vec<_> = checker.conflicts().map(|(c, n)| (c.pc, n)).collect();
+        assert_eq!(counts, [(0xC004, 2)]);
+        assert_eq!(checker.steps(), 8);
+    }
+
+    #[test]
+    fn test_ldir_read() {
+        // at 0xC000 of the RAM in slot 3:
+        // LD A,0xF3 / OUT (0xA8),A / LD HL,0x4000 / LD DE,0xD000 / LD BC,3 /
+        // LDIR / JR $
+        let mut msx = Msx::new(&[
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+        ]);
+        let program = [
+            0x3E, 0xF3, 0xD3, 0xA8, 0x21, 0x00, 0x40, 0x11, 0x00, 0xD0, 0x01, 0x03, 0x00, 0xED,
+            0xB0, 0x18, 0xFE,
+        ];
+        msx.bus.write().unwrap().output(0xA8, 0xFF);
+        for (i, byte) in program.iter().enumerate() {
+            msx.set_memory(0xC000 + i as u16, *byte);
+        }
+        msx.cpu.pc = 0xC000;
+        let mut checker = SlotChecker::new();
+
+        let conflicts = run(&mut msx, &mut checker, 8);
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!((conflicts[0].pc, conflicts[0].address), (0xC00D, 0x4000));
+        // a read on each repeat
+        let counts: Vec<_> = checker.conflicts().map(|(c, n)| (c.pc, n)).collect();
+        assert_eq!(counts, [(0xC00D, 3)]);
+    }
+
+    #[test]
+    fn test_bios_probes_ignored() {
+        // LD A,0xF0 / OUT (0xA8),A / LD A,(0x4000), run from page 0 of slot 0
+        let mut rom = vec![0x3E, 0xF0, 0xD3, 0xA8, 0x3A, 0x00, 0x40];
+        rom.resize(0x4000, 0);
+        let mut msx = Msx::new(&[
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+        ]);
+        msx.load_rom(0, &rom);
+        let mut checker = SlotChecker::new();
+        assert!(run(&mut msx, &mut checker, 3).is_empty());
+    }
+}
@@ -0,0 +1,208 @@
+use std::fmt;
+
+use crate::{
+    slot::{Slot, SlotType},
+    slot_check::{page_of, slot_of, Pages},
+    Z80,
+};
+
+/// A register selecting the slots of the pages. Only the primary slot
+/// register is emulated; the secondary slot registers, at 0xFFFF of the
+/// expanded slots, are to be traced the same way once they are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SlotRegister {
+    /// port 0xA8, two bits per page
+    Primary,
+}
+
+impl fmt::Display for SlotRegister {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SlotRegister::Primary => write!(f, "primary slot register"),
+        }
+    }
+}
+
+/// A write to a slot register, with the slots of the pages before and
+/// after it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlotWrite {
+    pub register: SlotRegister,
+    /// address of the instruction writing the register
+    pub pc: u16,
+    pub from: u8,
+    pub to: u8,
+    // what each slot holds when written, e.g. "RAM" or "ROM game.rom"
+    contents: [String; 4],
+}
+
+impl SlotWrite {
+    /// Whether the write switched the page of the instruction writing it,
+    /// the next instruction being fetched from another slot.
+    pub fn switches_pc_page(&self) -> bool {
+        let page = page_of(self.pc);
+        slot_of(self.from, page) != slot_of(self.to, page)
+    }
+}
+
+impl fmt::Display for SlotWrite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Write of the {} at {:#06X}: {}",
+            self.register,
+            self.pc,
+            Pages(self.from)
+        )?;
+        if self.from == self.to {
+            write!(f, ", unchanged")?;
+        } else {
+            write!(f, " -> {}", Pages(self.to))?;
+        }
+        for page in 0..4 {
+            let start = page as u16 * 0x4000;
+            let (from, to) = (slot_of(self.from, page), slot_of(self.to, page));
+            write!(
+                f,
+                "\n  page {}  {:#06X}-{:#06X}  slot {} {}",
+                page,
+                start,
+                start + 0x3FFF,
+                from,
+                self.contents[from as usize]
+            )?;
+            if from != to {
+                write!(f, " -> slot {} {}", to, self.contents[to as usize])?;
+            }
+            if page == page_of(self.pc) {
+                write!(f, "  <- PC")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// what a slot holds, in a few words
+fn contents(slot: Option<&SlotType>) -> String {
+    match slot {
+        None | Some(SlotType::Empty) => "empty".to_string(),
+        Some(SlotType::Ram(_)) => "RAM".to_string(),
+        Some(SlotType::Rom(rom)) => match rom.rom_path.as_ref().and_then(|path| path.file_name()) {
+            Some(name) => format!("ROM {}", name.to_string_lossy()),
+            None => "ROM".to_string(),
+        },
+        Some(SlotType::MegaRam(slot)) => slot.name().to_string(),
+        Some(SlotType::MemoryMapper(slot)) => slot.name().to_string(),
+        Some(SlotType::Expanded(_)) => "expanded".to_string(),
+        Some(SlotType::SunriseIde(slot)) => slot.name().to_string(),
+        Some(SlotType::Device(device)) => device.name().to_string(),
+    }
+}
+
+/// Reports every write to the slot registers with the slots of the pages
+/// before and after it, and can break on those switching the page the
+/// program runs from: a switch of the page under the PC sends the next
+/// fetch into whatever the other slot holds, often garbage.
+#[derive(Debug, Clone, Default)]
+pub struct SlotTracer {
+    break_on_pc_page: bool,
+    // PC and primary slot register before the instruction being executed
+    pending: Option<(u16, u8)>,
+}
+
+impl SlotTracer {
+    pub fn new(break_on_pc_page: bool) -> Self {
+        Self {
+            break_on_pc_page,
+            pending: None,
+        }
+    }
+
+    /// Whether to break on the writes switching the page of the PC.
+    pub fn breaks_on_pc_page(&self) -> bool {
+        self.break_on_pc_page
+    }
+
+    /// Must be called before the CPU executes an instruction.
+    pub fn before_step(&mut self, cpu: &Z80, primary_slot_config: u8) {
+        self.pending = Some((cpu.pc, primary_slot_config));
+    }
+
+    /// Must be called after the CPU executed an instruction, with the
+    /// primary slot register, whether the instruction wrote it and the
+    /// slots of the machine. Returns the write, if any.
+    pub fn after_step(
+        &mut self,
+        primary_slot_config: u8,
+        wrote: bool,
+        slots: &[SlotType],
+    ) -> Option<SlotWrite> {
+        let (pc, before) = self.pending.take()?;
+        if !wrote {
+            return None;
+        }
+        Some(SlotWrite {
+            register: SlotRegister::Primary,
+            pc,
+            from: before,
+            to: primary_slot_config,
+            contents: std::array::from_fn(|n| contents(slots.get(n))),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{slot::RamSlot, Msx};
+
+    #[test]
+    fn test_trace_writes() {
+        // at 0xC000 of the RAM in slot 3:
+        // LD A,0xF3 / OUT (0xA8),A / OUT (0xA8),A / LD A,0x3F / OUT (0xA8),A
+        let mut msx = Msx::new(&[
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+        ]);
+        let program = [0x3E, 0xF3, 0xD3, 0xA8, 0xD3, 0xA8, 0x3E, 0x3F, 0xD3, 0xA8];
+        msx.bus.write().unwrap().output(0xA8, 0xFF);
+        for (i, byte) in program.iter().enumerate() {
+            msx.set_memory(0xC000 + i as u16, *byte);
+        }
+        msx.cpu.pc = 0xC000;
+        msx.wrote_slot_register();
+
+        let mut tracer = SlotTracer::new(true);
+        let slots = msx.slots();
+        let mut writes = Vec::new();
+        for _ in 0..5 {
+            tracer.before_step(&msx.cpu, msx.primary_slot_config());
+            msx.step();
+            writes.extend(tracer.after_step(
+                msx.primary_slot_config(),
+                msx.wrote_slot_register(),
+                &slots,
+            ));
+        }
+
+        // the write of the value the register holds is reported too
+        assert_eq!(writes.len(), 3);
+        assert_eq!(
+            writes[0].to_string(),
+            "Write of the primary slot register at 0xC002: 3 3 3 3 (0xFF) -> 3 0 3 3 (0xF3)\n  \
+             page 0  0x0000-0x3FFF  slot 3 RAM\n  \
+             page 1  0x4000-0x7FFF  slot 3 RAM -> slot 0 empty\n  \
+             page 2  0x8000-0xBFFF  slot 3 RAM\n  \
+             page 3  0xC000-0xFFFF  slot 3 RAM  <- PC"
+        );
+        assert_eq!(
+            writes[1].to_string().lines().next(),
+            Some("Write of the primary slot register at 0xC004: 3 0 3 3 (0xF3), unchanged")
+        );
+        assert!(!writes[1].switches_pc_page());
+        // the last one takes away the RAM the program runs from
+        assert!(writes[2].switches_pc_page());
+    }
+}
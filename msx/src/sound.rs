@@ -3,10 +3,45 @@
 use serde::{Deserialize, Serialize};
 use tracing::trace;
 
+// bits implemented by each register, the others always read back as zero
+const REGISTER_MASKS: [u8; 16] = [
+    0xFF, 0x0F, 0xFF, 0x0F, 0xFF, 0x0F, 0x1F, 0xFF, 0x1F, 0x1F, 0x1F, 0xFF, 0xFF, 0x0F, 0xFF, 0xFF,
+];
+
+/// Clock of the PSG on an MSX, half the one of the Z80.
+pub const PSG_CLOCK: f32 = 1_789_772.5;
+
+const NOISE_PERIOD: usize = 6;
+const ENABLE: usize = 7;
+const VOLUME_A: usize = 8;
+const ENVELOPE_PERIOD: usize = 11;
+const ENVELOPE_SHAPE: usize = 13;
+const PORT_A: usize = 14;
+const PORT_B: usize = 15;
+// direction bits of the I/O ports in the enable register, set for output
+const PORT_A_OUTPUT: u8 = 0x40;
+const PORT_B_OUTPUT: u8 = 0x80;
+
+// bit 6 of port A is the keyboard layout, set on international machines
+const KEYBOARD_LAYOUT: u8 = 0x40;
+// bit 6 of port B selects the joystick port read on port A, set for port 2
+const JOYSTICK_PORT_2: u8 = 0x40;
+
 #[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
 pub struct AY38910 {
     registers: [u8; 16],
     selected_register: u8,
+    /// lines of joystick port 1 pulled low (up, down, left, right, trigger
+    /// A and B), read on port A when port 1 is selected; port 2 is empty
+    #[serde(default)]
+    joystick: u8,
+    /// level of the cassette input, bit 7 of port A
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    cassette_input: bool,
+    /// T-state of the bus the PSG was last synced at
+    clock: u64,
+    /// T-state the envelope was restarted at, by writing its shape
+    envelope_start: u64,
 }
 
 impl AY38910 {
@@ -14,13 +49,18 @@ impl AY38910 {
         Self {
             registers: [0; 16],
             selected_register: 0,
-            // ... (Initialize other fields)
+            joystick: 0,
+            cassette_input: false,
+            clock: 0,
+            envelope_start: 0,
         }
     }
 
     pub fn reset(&mut self) {
         self.registers = [0; 16];
         self.selected_register = 0;
+        self.clock = 0;
+        self.envelope_start = 0;
         // ... (Reset other fields)
     }
 
@@ -29,14 +69,150 @@ impl AY38910 {
         todo!()
     }
 
+    /// Sets the lines of joystick port 1 that are active, bits 0-5 of
+    /// port A.
+    pub fn set_joystick(&mut self, pressed: u8) {
+        self.joystick = pressed & 0x3F;
+    }
+
+    pub fn joystick(&self) -> u8 {
+        self.joystick
+    }
+
+    /// Sets the level of the cassette input, bit 7 of port A.
+    pub fn set_cassette_input(&mut self, level: bool) {
+        self.cassette_input = level;
+    }
+
+    /// Register the data port reads and writes.
+    pub fn selected_register(&self) -> u8 {
+        self.selected_register
+    }
+
+    /// Reads the data port (0xA2). The address and write ports (0xA0 and
+    /// 0xA1) aren't readable and float high.
     pub fn read(&mut self, port: u8) -> u8 {
         match port {
-            0xA0 => self.selected_register,
-            0xA1 => self.registers[self.selected_register as usize],
-            _ => 0,
+            0xA2 => self.read_register(self.selected_register as usize),
+            _ => 0xFF,
+        }
+    }
+
+    fn read_register(&mut self, register: usize) -> u8 {
+        let enable = self.registers[ENABLE];
+        match register {
+            // ports in input mode read the pins, the value written to them
+            // is only seen once they are switched to output
+            PORT_A if enable & PORT_A_OUTPUT == 0 => {
+                let pressed = match self.registers[PORT_B] & JOYSTICK_PORT_2 {
+                    0 => self.joystick,
+                    _ => 0,
+                };
+                !pressed & 0x3F | KEYBOARD_LAYOUT | (self.cassette_input as u8) << 7
+            }
+            PORT_B if enable & PORT_B_OUTPUT == 0 => 0xFF,
+            register => self.registers[register],
         }
     }
 
+    pub fn registers(&self) -> [u8; 16] {
+        self.registers
+    }
+
+    /// Tone, noise and volume settings of the channels A, B and C.
+    pub fn channels(&self) -> [Channel; 3] {
+        let enable = self.registers[ENABLE];
+        std::array::from_fn(|n| {
+            let volume = self.registers[VOLUME_A + n];
+            Channel {
+                period: u16::from_le_bytes([self.registers[n * 2], self.registers[n * 2 + 1]]),
+                tone: enable & (1 << n) == 0,
+                noise: enable & (8 << n) == 0,
+                volume: volume & 0x0F,
+                envelope: volume & 0x10 != 0,
+            }
+        })
+    }
+
+    pub fn noise_period(&self) -> u8 {
+        self.registers[NOISE_PERIOD]
+    }
+
+    /// Frequency the noise generator changes at, in Hz.
+    pub fn noise_frequency(&self) -> f32 {
+        PSG_CLOCK / (16.0 * self.noise_period().max(1) as f32)
+    }
+
+    pub fn envelope_period(&self) -> u16 {
+        u16::from_le_bytes([
+            self.registers[ENVELOPE_PERIOD],
+            self.registers[ENVELOPE_PERIOD + 1],
+        ])
+    }
+
+    pub fn envelope_shape(&self) -> u8 {
+        self.registers[ENVELOPE_SHAPE]
+    }
+
+    /// Catches up with the T-state `clock` of the bus. Nothing of the PSG
+    /// happens on its own, the envelope is worked out from the time.
+    pub fn sync(&mut self, clock: u64) {
+        self.clock = clock;
+    }
+
+    /// Steps the envelope took since its shape was written, as of the last
+    /// sync. A step is 16 PSG clocks times the period, 32 T-states.
+    pub fn envelope_step(&self) -> u32 {
+        let step = 32 * self.envelope_period().max(1) as u64;
+        (self.clock.saturating_sub(self.envelope_start) / step).min(u32::MAX as u64) as u32
+    }
+
+    /// Volume of the channels following the envelope, as of the last sync.
+    pub fn envelope_volume(&self) -> u8 {
+        envelope_level(self.envelope_shape(), self.envelope_step())
+    }
+
+    /// Samples of the output of the three channels mixed, between 0 and 1,
+    /// as the current registers would sound from the start of the envelope.
+    pub fn waveform(&self, samples: usize, sample_rate: f32) -> Vec<f32> {
+        let channels = self.channels();
+        let envelope_step = 16.0 * self.envelope_period().max(1) as f32 / PSG_CLOCK;
+        let noise_step = 1.0 / self.noise_frequency();
+        // 17-bit LFSR, as the noise generator of the AY-3-8910
+        let mut lfsr: u32 = 1;
+        let mut noise_time = 0.0;
+
+        (0..samples)
+            .map(|n| {
+                let time = n as f32 / sample_rate;
+                while noise_time <= time {
+                    lfsr = (lfsr >> 1) | (((lfsr ^ (lfsr >> 3)) & 1) << 16);
+                    noise_time += noise_step;
+                }
+                let noise = lfsr & 1 != 0;
+                let envelope = envelope_level(self.envelope_shape(), (time / envelope_step) as u32);
+
+                let output: f32 = channels
+                    .iter()
+                    .map(|channel| {
+                        let tone = (time * channel.frequency() * 2.0) as u32 & 1 == 0;
+                        let on = (tone || !channel.tone) && (noise || !channel.noise);
+                        let level = match channel.envelope {
+                            true => envelope,
+                            false => channel.volume,
+                        };
+                        if on {
+                            amplitude(level)
+                        } else {
+                            0.0
+                        }
+                    })
+                    .sum();
+                output / 3.0
+            })
+            .collect()
+    }
+
     pub fn write(&mut self, port: u8, data: u8) {
         match port {
             0xA0 => {
@@ -49,10 +225,211 @@ impl AY38910 {
                     data,
                     self.selected_register
                 );
-                self.registers[self.selected_register as usize] = data;
-                // ... (Update the internal state of the PSG based on the new register value)
+                let register = self.selected_register as usize;
+                self.registers[register] = data & REGISTER_MASKS[register];
+                if register == ENVELOPE_SHAPE {
+                    self.envelope_start = self.clock;
+                }
             }
             _ => {}
         }
     }
 }
+
+/// A tone channel of the PSG, decoded from its registers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Channel {
+    /// 12-bit tone period, in units of 16 PSG clocks
+    pub period: u16,
+    /// whether the tone and the noise are mixed in
+    pub tone: bool,
+    pub noise: bool,
+    pub volume: u8,
+    /// whether the volume follows the envelope instead
+    pub envelope: bool,
+}
+
+impl Channel {
+    /// Tone frequency in Hz.
+    pub fn frequency(&self) -> f32 {
+        PSG_CLOCK / (16.0 * self.period.max(1) as f32)
+    }
+
+    /// Name of the closest note to the tone, e.g. `A4`.
+    pub fn note(&self) -> String {
+        note_name(self.frequency())
+    }
+
+    /// Whether the channel is heard: mixed in and not silent.
+    pub fn audible(&self) -> bool {
+        (self.tone || self.noise) && (self.envelope || self.volume > 0)
+    }
+}
+
+const NOTE_NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
+/// Name of the closest note to a frequency, with A4 at 440 Hz.
+pub fn note_name(frequency: f32) -> String {
+    let midi = (69.0 + 12.0 * (frequency / 440.0).log2()).round() as i32;
+    format!(
+        "{}{}",
+        NOTE_NAMES[midi.rem_euclid(12) as usize],
+        midi.div_euclid(12) - 1
+    )
+}
+
+/// The envelope shape of register 13 drawn with slashes, e.g. `\\/\\/` for
+/// shape 14.
+pub fn envelope_shape_name(shape: u8) -> &'static str {
+    match shape & 0x0F {
+        0..=3 | 9 => "\\___",
+        4..=7 | 15 => "/___",
+        8 => "\\\\\\\\",
+        10 => "\\/\\/",
+        11 => "\\\u{AF}\u{AF}\u{AF}",
+        12 => "////",
+        13 => "/\u{AF}\u{AF}\u{AF}",
+        _ => "/\\/\\",
+    }
+}
+
+// volume of the envelope after a number of its 16-step ramps
+fn envelope_level(shape: u8, step: u32) -> u8 {
+    let attack = shape & 0x04 != 0;
+    let cycle = step / 16;
+    let position = (step % 16) as u8;
+    let ramp = |up: bool| if up { position } else { 15 - position };
+
+    // shapes 0-7 hold at 0 after the first ramp
+    if shape & 0x08 == 0 || shape & 0x01 != 0 {
+        if cycle == 0 {
+            return ramp(attack);
+        }
+        let alternate = shape & 0x08 != 0 && shape & 0x02 != 0;
+        return match shape & 0x08 != 0 && attack != alternate {
+            true => 15,
+            false => 0,
+        };
+    }
+    match shape & 0x02 != 0 {
+        true => ramp(attack == (cycle & 1 == 0)),
+        false => ramp(attack),
+    }
+}
+
+// output of a volume, 3 dB a step
+fn amplitude(level: u8) -> f32 {
+    match level {
+        0 => 0.0,
+        level => 2f32.powf((level as f32 - 15.0) / 2.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_register(psg: &mut AY38910, register: u8, value: u8) {
+        psg.write(0xA0, register);
+        psg.write(0xA1, value);
+    }
+
+    fn read_register(psg: &mut AY38910, register: u8) -> u8 {
+        psg.write(0xA0, register);
+        psg.read(0xA2)
+    }
+
+    #[test]
+    fn test_register_masks() {
+        let mut psg = AY38910::new();
+        write_register(&mut psg, 1, 0xFF);
+        write_register(&mut psg, 6, 0xFF);
+        write_register(&mut psg, 8, 0xFF);
+        write_register(&mut psg, 11, 0xFF);
+        assert_eq!(read_register(&mut psg, 1), 0x0F);
+        assert_eq!(read_register(&mut psg, 6), 0x1F);
+        assert_eq!(read_register(&mut psg, 8), 0x1F);
+        assert_eq!(read_register(&mut psg, 11), 0xFF);
+
+        assert_eq!(psg.read(0xA0), 0xFF);
+        assert_eq!(psg.read(0xA1), 0xFF);
+    }
+
+    #[test]
+    fn test_port_direction() {
+        let mut psg = AY38910::new();
+        psg.set_joystick(0x11);
+        write_register(&mut psg, 14, 0x00);
+        write_register(&mut psg, 15, 0x0F);
+
+        // both ports are inputs after a reset
+        assert_eq!(read_register(&mut psg, 14), 0x6E);
+        assert_eq!(read_register(&mut psg, 15), 0xFF);
+
+        write_register(&mut psg, 7, 0x80);
+        assert_eq!(read_register(&mut psg, 14), 0x6E);
+        assert_eq!(read_register(&mut psg, 15), 0x0F);
+
+        write_register(&mut psg, 7, 0x40);
+        assert_eq!(read_register(&mut psg, 14), 0x00);
+        assert_eq!(read_register(&mut psg, 15), 0xFF);
+    }
+
+    #[test]
+    fn test_joystick_port() {
+        let mut psg = AY38910::new();
+        psg.set_joystick(0x11);
+        write_register(&mut psg, 7, 0x80);
+        assert_eq!(read_register(&mut psg, 14), 0x6E);
+
+        // nothing is plugged in port 2
+        write_register(&mut psg, 15, 0x40);
+        assert_eq!(read_register(&mut psg, 14), 0x7F);
+    }
+
+    #[test]
+    fn test_channels() {
+        let mut psg = AY38910::new();
+        // channel A at 440 Hz, B with the envelope and noise only
+        write_register(&mut psg, 0, 0xFE);
+        write_register(&mut psg, 3, 0x01);
+        write_register(&mut psg, 7, 0b1010_1010);
+        write_register(&mut psg, 8, 0x0F);
+        write_register(&mut psg, 9, 0x10);
+        write_register(&mut psg, 13, 14);
+
+        let [a, b, c] = psg.channels();
+        assert_eq!(a.period, 0x00FE);
+        assert!((a.frequency() - 440.4).abs() < 0.1);
+        assert_eq!(a.note(), "A4");
+        assert!(a.tone && !a.noise && a.audible());
+        assert!(!b.tone && b.noise && b.envelope && b.audible());
+        assert!(c.tone && !c.audible());
+        assert_eq!(envelope_shape_name(psg.envelope_shape()), "/\\/\\");
+
+        let waveform = psg.waveform(100, 44100.0);
+        assert_eq!(waveform.len(), 100);
+        assert!(waveform.iter().all(|sample| (0.0..=1.0).contains(sample)));
+        assert!(waveform.iter().any(|sample| *sample > 0.3));
+    }
+
+    #[test]
+    fn test_envelope_level() {
+        // \___
+        assert_eq!(envelope_level(0, 0), 15);
+        assert_eq!(envelope_level(0, 15), 0);
+        assert_eq!(envelope_level(0, 40), 0);
+        // /\/\
+        assert_eq!(envelope_level(14, 15), 15);
+        assert_eq!(envelope_level(14, 16), 15);
+        assert_eq!(envelope_level(14, 31), 0);
+        // /¯¯¯ and \¯¯¯
+        assert_eq!(envelope_level(13, 40), 15);
+        assert_eq!(envelope_level(11, 40), 15);
+        // \\\\
+        assert_eq!(envelope_level(8, 16), 15);
+        assert_eq!(note_name(261.6), "C4");
+    }
+}
@@ -0,0 +1,403 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::anyhow;
+
+/// A line of one of the source files of a `SourceMap`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SourceLine {
+    /// index of the file in the map
+    pub file: usize,
+    /// starting at 1
+    pub line: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct SourceFile {
+    path: String,
+    lines: BTreeMap<usize, String>,
+}
+
+/// Maps addresses to the source lines that assembled them and back, from
+/// the listings of sjasmplus (`--lst`) and z88dk (`-l`), or from the source
+/// level debugging files of sjasmplus (`--sld`).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+    by_address: BTreeMap<u16, SourceLine>,
+    by_line: BTreeMap<SourceLine, u16>,
+}
+
+impl SourceMap {
+    /// Adds a listing or SLD file, whose source paths are relative to it.
+    /// The sources named by SLD files are read, listings hold their text.
+    #[cfg(feature = "std-fs")]
+    pub fn load(&mut self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)?;
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+        if text.starts_with(SLD_HEADER) {
+            let first = self.files.len();
+            self.add_sld(&text, dir)?;
+            for file in &mut self.files[first..] {
+                if let Ok(source) = std::fs::read_to_string(&file.path) {
+                    file.lines = (1..).zip(source.lines().map(String::from)).collect();
+                }
+            }
+            Ok(())
+        } else {
+            // z88dk writes a listing per source file, next to it
+            let source = path.with_extension("asm");
+            let source = source.file_name().unwrap_or_default().to_string_lossy();
+            self.add_listing(&text, &source, dir)
+        }
+    }
+
+    /// Adds a listing, where lines look like:
+    ///
+    /// ```text
+    /// 12+   4010 3E 01        start:  ld a, 1
+    /// ```
+    ///
+    /// `file` names the source until a `# file opened:` line of sjasmplus
+    /// names another one.
+    pub fn add_listing(&mut self, text: &str, file: &str, dir: &Path) -> anyhow::Result<()> {
+        let mut stack = vec![self.file_id(dir, file)];
+        for line in text.lines() {
+            if let Some(name) = line.strip_prefix("# file opened:") {
+                stack.push(self.file_id(dir, name.trim()));
+                continue;
+            }
+            if line.starts_with("# file closed:") {
+                if stack.len() > 1 {
+                    stack.pop();
+                }
+                continue;
+            }
+
+            let Some(entry) = parse_listing_line(line) else {
+                continue;
+            };
+            let file = *stack.last().unwrap();
+            let at = SourceLine {
+                file,
+                line: entry.line,
+            };
+            self.files[file]
+                .lines
+                .entry(entry.line)
+                .or_insert_with(|| entry.source.to_string());
+            if let (Some(address), true) = (entry.address, entry.code) {
+                self.add(at, address);
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds the instructions (`T` entries) of an SLD file, where lines look
+    /// like `main.asm|12||0|-1|16400|T|`.
+    pub fn add_sld(&mut self, text: &str, dir: &Path) -> anyhow::Result<()> {
+        for (n, line) in text.lines().enumerate() {
+            if line.starts_with('|') || line.trim().is_empty() {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split('|').collect();
+            if fields.len() < 7 {
+                return Err(anyhow!("Invalid SLD line {}: {}", n + 1, line));
+            }
+            if fields[6] != "T" {
+                continue;
+            }
+
+            // the line may be followed by the columns, as `12:3:8`
+            let number = fields[1].split(':').next().unwrap_or_default().parse();
+            let address = fields[5].parse::<i64>();
+            let (Ok(number), Ok(address)) = (number, address) else {
+                return Err(anyhow!("Invalid SLD line {}: {}", n + 1, line));
+            };
+            let file = self.file_id(dir, fields[0]);
+            self.add(SourceLine { file, line: number }, (address & 0xFFFF) as u16);
+        }
+        Ok(())
+    }
+
+    fn file_id(&mut self, dir: &Path, name: &str) -> usize {
+        let path = dir.join(name).to_string_lossy().to_string();
+        match self.files.iter().position(|f| f.path == path) {
+            Some(id) => id,
+            None => {
+                self.files.push(SourceFile {
+                    path,
+                    lines: BTreeMap::new(),
+                });
+                self.files.len() - 1
+            }
+        }
+    }
+
+    fn add(&mut self, at: SourceLine, address: u16) {
+        // lines assembled more than once, like in macros, keep the first
+        self.by_address.entry(address).or_insert(at);
+        self.by_line.entry(at).or_insert(address);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_address.is_empty()
+    }
+
+    /// The line that assembled the instruction at an address.
+    pub fn location(&self, address: u16) -> Option<SourceLine> {
+        self.by_address.get(&address).copied()
+    }
+
+    /// The address of the code of a line, if it has any.
+    pub fn address(&self, at: SourceLine) -> Option<u16> {
+        self.by_line.get(&at).copied()
+    }
+
+    /// The first line with code at or after a line of the same file, and
+    /// its address, e.g. where a breakpoint on a comment goes.
+    pub fn code_at_or_after(&self, at: SourceLine) -> Option<(SourceLine, u16)> {
+        self.by_line
+            .range(at..)
+            .next()
+            .filter(|(line, _)| line.file == at.file)
+            .map(|(line, address)| (*line, *address))
+    }
+
+    pub fn path(&self, file: usize) -> &str {
+        &self.files[file].path
+    }
+
+    /// The file a path names: the same path, one ending with the other, or
+    /// else the only file with that name.
+    pub fn find_file(&self, path: &str) -> Option<usize> {
+        let path = Path::new(path);
+        if let Some(id) = self.files.iter().position(|f| Path::new(&f.path) == path) {
+            return Some(id);
+        }
+        if let Some(id) = self.files.iter().position(|f| {
+            let file = PathBuf::from(&f.path);
+            file.ends_with(path) || path.ends_with(&file)
+        }) {
+            return Some(id);
+        }
+
+        let name = path.file_name()?;
+        let mut named = self
+            .files
+            .iter()
+            .enumerate()
+            .filter(|(_, f)| Path::new(&f.path).file_name() == Some(name));
+        match (named.next(), named.next()) {
+            (Some((id, _)), None) => Some(id),
+            _ => None,
+        }
+    }
+
+    /// The text of a source line, when the listing or the file had it.
+    pub fn text(&self, at: SourceLine) -> Option<&str> {
+        self.files
+            .get(at.file)?
+            .lines
+            .get(&at.line)
+            .map(String::as_str)
+    }
+}
+
+#[cfg(feature = "std-fs")]
+const SLD_HEADER: &str = "|SLD.data.version|";
+
+struct ListingLine<'a> {
+    line: usize,
+    address: Option<u16>,
+    code: bool,
+    source: &'a str,
+}
+
+// `<line>[+~>] <address> <bytes>  <source>`, where the bytes are separated
+// by single spaces and the source follows a tab or more spaces
+fn parse_listing_line(text: &str) -> Option<ListingLine<'_>> {
+    let text = text.trim_start();
+    let digits = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    let line = text[..digits].parse().ok()?;
+    let rest = text[digits..].trim_start_matches(['+', '~', '>']);
+    if !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+
+    let rest = rest.trim_start();
+    let token = rest.split_whitespace().next().unwrap_or_default();
+    // devices may prefix the page, as `01:4010`
+    let hex = token.rsplit(':').next().unwrap_or_default();
+    let address = match hex.len() == 4 {
+        true => u16::from_str_radix(hex, 16).ok(),
+        false => None,
+    };
+    let Some(address) = address else {
+        return Some(ListingLine {
+            line,
+            address: None,
+            code: false,
+            source: rest,
+        });
+    };
+
+    let mut rest = rest[token.len()..].trim_start();
+    let mut code = false;
+    while is_byte(rest) {
+        code = true;
+        rest = &rest[2..];
+        let next = rest.strip_prefix(' ').unwrap_or_default();
+        if !is_byte(next) {
+            break;
+        }
+        rest = next;
+    }
+
+    Some(ListingLine {
+        line,
+        address: Some(address),
+        code,
+        source: rest.trim(),
+    })
+}
+
+// two hex digits ending a token
+fn is_byte(s: &str) -> bool {
+    let bytes = s.as_bytes();
+    bytes.len() >= 2
+        && bytes[..2].iter().all(u8::is_ascii_hexdigit)
+        && bytes.get(2).map_or(true, |b| b.is_ascii_whitespace())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LISTING: &str = "# file opened: main.asm
+1     0000              ; demo
+2     0000                      org 0x4000
+3     4000 41 42 10 40          db \"AB\", 0x10, 0x40
+4     4004              start:
+5     4004 3E 01                ld a, 1
+6     4006                      include \"inc.asm\"
+# file opened: inc.asm
+1+    4006 CD 0D 40     loop:   call wait
+2+    4009 18 FB                jr loop
+# file closed: inc.asm
+7     400B C9           wait:   ret
+8     400C DB 99                in a, (0x99)
+9     400E 00 00 00 00          db 0, 0, 0, 0
+";
+
+    #[test]
+    fn test_add_listing() {
+        let mut map = SourceMap::default();
+        map.add_listing(LISTING, "main.lst", Path::new("demo"))
+            .unwrap();
+
+        let main = map.find_file("demo/main.asm").unwrap();
+        let inc = map.find_file("/home/user/demo/inc.asm").unwrap();
+        assert_eq!(map.path(main), "demo/main.asm");
+
+        assert_eq!(
+            map.location(0x4000),
+            Some(SourceLine {
+                file: main,
+                line: 3
+            })
+        );
+        assert_eq!(
+            map.location(0x4004),
+            Some(SourceLine {
+                file: main,
+                line: 5
+            })
+        );
+        assert_eq!(
+            map.location(0x4009),
+            Some(SourceLine { file: inc, line: 2 })
+        );
+        assert_eq!(
+            map.location(0x400B),
+            Some(SourceLine {
+                file: main,
+                line: 7
+            })
+        );
+        assert_eq!(map.location(0x4005), None);
+
+        let start = SourceLine {
+            file: main,
+            line: 4,
+        };
+        assert_eq!(map.address(start), None);
+        assert_eq!(
+            map.code_at_or_after(start),
+            Some((
+                SourceLine {
+                    file: main,
+                    line: 5
+                },
+                0x4004
+            ))
+        );
+        assert_eq!(map.text(start), Some("start:"));
+        assert_eq!(
+            map.text(SourceLine {
+                file: main,
+                line: 8
+            }),
+            Some("in a, (0x99)")
+        );
+        assert_eq!(
+            map.text(SourceLine {
+                file: main,
+                line: 9
+            }),
+            Some("db 0, 0, 0, 0")
+        );
+    }
+
+    #[test]
+    fn test_add_z88dk_listing() {
+        let mut map = SourceMap::default();
+        map.add_listing(
+            "1                          SECTION code\n\
+             2     0000  3E 01              ld a,1\n\
+             3     0002  C9                 ret\n",
+            "game.asm",
+            Path::new(""),
+        )
+        .unwrap();
+
+        let file = map.find_file("game.asm").unwrap();
+        assert_eq!(map.location(0x0002), Some(SourceLine { file, line: 3 }));
+        assert_eq!(map.text(SourceLine { file, line: 2 }), Some("ld a,1"));
+    }
+
+    #[test]
+    fn test_add_sld() {
+        let mut map = SourceMap::default();
+        map.add_sld(
+            "|SLD.data.version|1\n\
+             main.asm|5||0|-1|16388|T|\n\
+             main.asm|5||0|-1|16388|L|,start,\n\
+             main.asm|7:9:12||0|-1|16395|T|\n",
+            Path::new(""),
+        )
+        .unwrap();
+
+        let file = map.find_file("main.asm").unwrap();
+        assert_eq!(map.location(0x4004), Some(SourceLine { file, line: 5 }));
+        assert_eq!(map.address(SourceLine { file, line: 7 }), Some(0x400B));
+        assert!(map.add_sld("main.asm|x", Path::new("")).is_err());
+    }
+}
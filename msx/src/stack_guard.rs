@@ -0,0 +1,190 @@
+use std::fmt;
+
+use crate::Z80;
+
+// CALL nn, CALL cc,nn and RST p
+fn is_call(opcode: u8) -> bool {
+    matches!(
+        opcode,
+        0xCD | 0xC4 | 0xCC | 0xD4 | 0xDC | 0xE4 | 0xEC | 0xF4 | 0xFC
+    ) || opcode & 0xC7 == 0xC7
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Frame {
+    call_pc: u16,
+    slot: u16,
+    return_address: u16,
+    // primary slot the stack page was mapped to when the address was pushed
+    primary_slot: u8,
+}
+
+/// A return address that was overwritten before its RET.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StackViolation {
+    /// address of the CALL that pushed the return address
+    pub call_pc: u16,
+    /// address of the instruction that overwrote it
+    pub pc: u16,
+    /// stack address holding the return address
+    pub slot: u16,
+    pub expected: u16,
+    pub found: u16,
+}
+
+impl fmt::Display for StackViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Return address of CALL at {:#06X} overwritten at {:#06X}: ({:#06X}) = {:#06X}, expected {:#06X}",
+            self.call_pc, self.pc, self.slot, self.found, self.expected
+        )
+    }
+}
+
+/// Watches the return addresses pushed by CALL and RST instructions and
+/// reports when one is overwritten while it is still on the stack.
+#[derive(Debug, Clone, Default)]
+pub struct StackGuard {
+    frames: Vec<Frame>,
+    // PC and SP before the instruction being executed, when it is a call
+    pending: Option<(u16, u16)>,
+    pc: u16,
+}
+
+impl StackGuard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of return addresses being watched.
+    pub fn depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+        self.pending = None;
+    }
+
+    /// Must be called before the CPU executes an instruction.
+    pub fn before_step(&mut self, cpu: &Z80) {
+        self.pc = cpu.pc;
+        self.pending = is_call(cpu.read_byte(cpu.pc)).then_some((cpu.pc, cpu.sp));
+    }
+
+    /// Must be called after the CPU executed an instruction, returns the
+    /// return address that was overwritten by it, if any.
+    pub fn after_step(&mut self, cpu: &Z80) -> Option<StackViolation> {
+        // return addresses above the stack pointer were popped
+        let sp = cpu.sp;
+        self.frames.retain(|frame| frame.slot >= sp);
+
+        if let Some((call_pc, previous_sp)) = self.pending.take() {
+            // conditional calls that weren't taken don't push anything
+            if sp == previous_sp.wrapping_sub(2) {
+                self.frames.push(Frame {
+                    call_pc,
+                    slot: sp,
+                    return_address: read_word(cpu, sp),
+                    primary_slot: primary_slot(cpu, sp),
+                });
+            }
+        }
+
+        // while another slot is mapped on the stack page, as the BIOS does
+        // when switching slots, the return address isn't visible
+        let index = self.frames.iter().position(|frame| {
+            primary_slot(cpu, frame.slot) == frame.primary_slot
+                && read_word(cpu, frame.slot) != frame.return_address
+        })?;
+        let frame = self.frames.remove(index);
+
+        Some(StackViolation {
+            call_pc: frame.call_pc,
+            pc: self.pc,
+            slot: frame.slot,
+            expected: frame.return_address,
+            found: read_word(cpu, frame.slot),
+        })
+    }
+}
+
+fn primary_slot(cpu: &Z80, address: u16) -> u8 {
+    let config = cpu.bus.read().unwrap().primary_slot_config();
+    (config >> ((address >> 14) * 2)) & 0x03
+}
+
+fn read_word(cpu: &Z80, address: u16) -> u16 {
+    u16::from_le_bytes([
+        cpu.read_byte(address),
+        cpu.read_byte(address.wrapping_add(1)),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, RwLock};
+
+    use super::*;
+    use crate::{
+        bus::Bus,
+        slot::{RamSlot, SlotType},
+    };
+
+    fn run(program: &[u8], steps: usize) -> (Z80, Option<StackViolation>, usize) {
+        let bus = Bus::new(&[
+            SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Empty,
+        ]);
+        let mut cpu = Z80::new(Arc::new(RwLock::new(bus)));
+        for (i, byte) in program.iter().enumerate() {
+            cpu.write_byte(i as u16, *byte);
+        }
+        cpu.sp = 0xF000;
+
+        let mut guard = StackGuard::new();
+        let mut violation = None;
+        for _ in 0..steps {
+            guard.before_step(&cpu);
+            cpu.execute_cycle();
+            if let Some(v) = guard.after_step(&cpu) {
+                violation = Some(v);
+                break;
+            }
+        }
+        let depth = guard.depth();
+        (cpu, violation, depth)
+    }
+
+    #[test]
+    fn test_overwritten_return_address() {
+        // CALL 0x0010 / HALT ... 0x0010: LD HL, 0x1234 / LD (0xEFFE), HL / RET
+        let mut program = vec![0xCD, 0x10, 0x00, 0x76];
+        program.resize(0x10, 0);
+        program.extend_from_slice(&[0x21, 0x34, 0x12, 0x22, 0xFE, 0xEF, 0xC9]);
+
+        let (_, violation, _) = run(&program, 3);
+        let violation = violation.unwrap();
+        assert_eq!(violation.call_pc, 0x0000);
+        assert_eq!(violation.pc, 0x0013);
+        assert_eq!(violation.slot, 0xEFFE);
+        assert_eq!(violation.expected, 0x0003);
+        assert_eq!(violation.found, 0x1234);
+    }
+
+    #[test]
+    fn test_balanced_calls() {
+        // CALL 0x0010 / HALT ... 0x0010: PUSH HL / POP HL / RET
+        let mut program = vec![0xCD, 0x10, 0x00, 0x76];
+        program.resize(0x10, 0);
+        program.extend_from_slice(&[0xE5, 0xE1, 0xC9]);
+
+        let (cpu, violation, depth) = run(&program, 4);
+        assert_eq!(violation, None);
+        assert_eq!(cpu.pc, 0x0003);
+        assert_eq!(depth, 0);
+    }
+}
@@ -0,0 +1,208 @@
+use anyhow::{anyhow, bail};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{bus::Bus, cpu::Z80};
+
+/// Identifies save states among other JSON files.
+pub const STATE_FORMAT: &str = "rustmsx-state";
+
+/// Version of the save state schema written by this build. It must be
+/// bumped, with a migration added to `MIGRATIONS`, whenever a change to the
+/// serialized structs would stop older states from loading or change their
+/// meaning.
+///
+/// - 1: the machine state without an envelope
+/// - 2: the machine state in an envelope with the format and version
+/// - 3: the T-state clocks of the bus, the VDP and the PSG
+/// - 4: MegaRAM and memory mapper cartridges in the slots
+/// - 5: expanded slots and Sunrise IDE interfaces
+/// - 6: no serial port in the bus, the host attaching one for a link
+pub const STATE_VERSION: u32 = 6;
+
+// MIGRATIONS[n] upgrades a state from version n + 1 to n + 2
+const MIGRATIONS: [fn(Value) -> anyhow::Result<Value>; 5] =
+    [v1_to_v2, v2_to_v3, v3_to_v4, v4_to_v5, v5_to_v6];
+
+/// Complete machine state, as saved to a file or exchanged to resynchronize
+/// netplay peers.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct MachineState {
+    pub cpu: Z80,
+    pub bus: Box<Bus>,
+    pub current_scanline: u16,
+}
+
+#[derive(Serialize)]
+struct Envelope<'a> {
+    format: &'a str,
+    version: u32,
+    machine: &'a MachineState,
+}
+
+pub(crate) fn encode(state: &MachineState) -> anyhow::Result<Vec<u8>> {
+    Ok(serde_json::to_vec(&Envelope {
+        format: STATE_FORMAT,
+        version: STATE_VERSION,
+        machine: state,
+    })?)
+}
+
+pub(crate) fn decode(data: &[u8]) -> anyhow::Result<MachineState> {
+    let mut value: Value = serde_json::from_slice(data)?;
+    let version = version_of(&value)?;
+    if version > STATE_VERSION {
+        bail!(
+            "Save state version {} is newer than the supported version {}",
+            version,
+            STATE_VERSION
+        );
+    }
+
+    for migration in &MIGRATIONS[version as usize - 1..] {
+        value = migration(value)?;
+    }
+
+    let machine = value
+        .get_mut("machine")
+        .map(Value::take)
+        .ok_or_else(|| anyhow!("Save state has no machine"))?;
+    Ok(serde_json::from_value(machine)?)
+}
+
+/// Schema version of a save state.
+pub fn version(data: &[u8]) -> anyhow::Result<u32> {
+    version_of(&serde_json::from_slice(data)?)
+}
+
+fn version_of(value: &Value) -> anyhow::Result<u32> {
+    let Some(object) = value.as_object() else {
+        bail!("Not a save state");
+    };
+
+    match object.get("format").and_then(Value::as_str) {
+        Some(STATE_FORMAT) => {
+            let version = object
+                .get("version")
+                .and_then(Value::as_u64)
+                .ok_or_else(|| anyhow!("Save state has no version"))?;
+            if version == 0 {
+                bail!("Invalid save state version 0");
+            }
+            Ok(version as u32)
+        }
+        Some(format) => bail!("Unknown save state format: {}", format),
+        // states saved before the envelope was introduced
+        None if object.contains_key("cpu") && object.contains_key("bus") => Ok(1),
+        None => bail!("Not a save state"),
+    }
+}
+
+fn v1_to_v2(value: Value) -> anyhow::Result<Value> {
+    Ok(json!({
+        "format": STATE_FORMAT,
+        "version": 2,
+        "machine": value,
+    }))
+}
+
+// the clocks start over from the state, which didn't keep the time
+fn v2_to_v3(mut value: Value) -> anyhow::Result<Value> {
+    let bus = value
+        .pointer_mut("/machine/bus")
+        .and_then(Value::as_object_mut)
+        .ok_or_else(|| anyhow!("Save state has no bus"))?;
+    bus.insert("clock".to_string(), json!(0));
+    for (device, fields) in [
+        ("vdp", &["clock"][..]),
+        ("psg", &["clock", "envelope_start"]),
+    ] {
+        let device = bus
+            .get_mut(device)
+            .and_then(Value::as_object_mut)
+            .ok_or_else(|| anyhow!("Save state has no {}", device))?;
+        for field in fields {
+            device.insert(field.to_string(), json!(0));
+        }
+    }
+    value["version"] = json!(3);
+    Ok(value)
+}
+
+// older versions couldn't save the RAM cartridges, so their slots are kept
+// as they are
+fn v3_to_v4(mut value: Value) -> anyhow::Result<Value> {
+    value["version"] = json!(4);
+    Ok(value)
+}
+
+// older versions had no expanded slots or IDE interfaces, so their slots
+// are kept as they are
+fn v4_to_v5(mut value: Value) -> anyhow::Result<Value> {
+    value["version"] = json!(5);
+    Ok(value)
+}
+
+// the serial port is attached by the host with the link, as a host device
+// not kept in states, so its queues and mode are dropped
+fn v5_to_v6(mut value: Value) -> anyhow::Result<Value> {
+    let bus = value
+        .pointer_mut("/machine/bus")
+        .and_then(Value::as_object_mut)
+        .ok_or_else(|| anyhow!("Save state has no bus"))?;
+    bus.remove("serial");
+    value["version"] = json!(6);
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use flate2::read::GzDecoder;
+    #[cfg(target_arch = "wasm32")]
+    use wasm_bindgen_test::wasm_bindgen_test as test;
+
+    use super::*;
+
+    // embedded so the tests also run in the browser, without a filesystem
+    const FIXTURES: [&[u8]; 6] = [
+        include_bytes!("../tests/fixtures/state_v1.json.gz"),
+        include_bytes!("../tests/fixtures/state_v2.json.gz"),
+        include_bytes!("../tests/fixtures/state_v3.json.gz"),
+        include_bytes!("../tests/fixtures/state_v4.json.gz"),
+        include_bytes!("../tests/fixtures/state_v5.json.gz"),
+        include_bytes!("../tests/fixtures/state_v6.json.gz"),
+    ];
+
+    fn fixture(version: u32) -> Vec<u8> {
+        let mut state = Vec::new();
+        GzDecoder::new(FIXTURES[version as usize - 1])
+            .read_to_end(&mut state)
+            .unwrap();
+        state
+    }
+
+    // states are exchanged between the CLI and the web version, so both must
+    // read and write the exact same bytes
+    #[test]
+    fn test_round_trip() {
+        let current = fixture(STATE_VERSION);
+        for version in 1..=STATE_VERSION {
+            let machine = decode(&fixture(version)).unwrap();
+            assert_eq!(encode(&machine).unwrap(), current, "version {}", version);
+        }
+    }
+
+    #[test]
+    fn test_version() {
+        assert_eq!(version(br#"{"cpu": {}, "bus": {}}"#).unwrap(), 1);
+        assert_eq!(
+            version(br#"{"format": "rustmsx-state", "version": 7}"#).unwrap(),
+            7
+        );
+        assert!(version(br#"{"format": "other", "version": 1}"#).is_err());
+        assert!(version(b"[]").is_err());
+        assert!(version(br#"{"format": "rustmsx-state", "version": 0}"#).is_err());
+    }
+}
@@ -0,0 +1,558 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use serde::{Deserialize, Serialize};
+
+use crate::slot::Slot;
+
+const BANK_SIZE: usize = 0x4000;
+const SECTOR_SIZE: usize = 512;
+
+// status register
+const DRDY: u8 = 0x40;
+const DSC: u8 = 0x10;
+const DRQ: u8 = 0x08;
+const ERR: u8 = 0x01;
+// error register
+const IDNF: u8 = 0x10;
+const ABRT: u8 = 0x04;
+// device control register, resetting the devices while set
+const SRST: u8 = 0x04;
+// device register
+const LBA: u8 = 0x40;
+const SLAVE: u8 = 0x10;
+
+// geometry reported for CHS addressing, that of most IDE disks
+const HEADS: usize = 16;
+const SECTORS_PER_TRACK: usize = 63;
+
+// what the registers read without a device
+const NO_DEVICE: u8 = 0x7F;
+
+/// Sunrise IDE interface: a 128K flash ROM, usually holding Nextor, seen
+/// through 16K banks on page 1, and the registers of an IDE bus with a hard
+/// disk image as its master device.
+///
+/// Writing to 0x4104 selects the bank in bits 7-3, in reverse order, and
+/// maps the IDE registers over the ROM with bit 0: the data register from
+/// 0x7C00 to 0x7DFF, read and written a word at a time through a latch,
+/// and the others from 0x7E00. Writes to the disk are kept in memory.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SunriseIde {
+    rom: Vec<u8>,
+    control: u8,
+    soft_reset: bool,
+    slave_selected: bool,
+    // high byte of the data word last read, returned at the odd addresses
+    read_latch: ReadRegister,
+    // low byte of the data word being written, written at the odd addresses
+    write_latch: u8,
+    disk: Option<AtaDisk>,
+}
+
+impl SunriseIde {
+    /// The interface with its flash holding `rom` and `disk`, a raw image
+    /// of whole sectors, as the master device.
+    pub fn new(rom: &[u8], disk: Option<Vec<u8>>) -> Self {
+        let banks = rom.len().div_ceil(BANK_SIZE).max(1);
+        let mut flash = vec![0xFF; banks * BANK_SIZE];
+        flash[..rom.len()].copy_from_slice(rom);
+        Self {
+            rom: flash,
+            // as the flash comes up, with the registers on
+            control: 0xFF,
+            soft_reset: false,
+            slave_selected: false,
+            read_latch: ReadRegister::default(),
+            write_latch: 0,
+            disk: disk.map(AtaDisk::new),
+        }
+    }
+
+    #[cfg(feature = "std-fs")]
+    pub fn load(
+        rom_path: &std::path::Path,
+        disk_path: Option<&std::path::Path>,
+    ) -> anyhow::Result<Self> {
+        let rom = crate::archive::read_file(rom_path)?;
+        let disk = disk_path.map(std::fs::read).transpose()?;
+        Ok(Self::new(&rom, disk))
+    }
+
+    /// The disk image with what was written to it.
+    pub fn disk(&self) -> Option<&[u8]> {
+        self.disk.as_ref().map(|disk| disk.image.as_slice())
+    }
+
+    fn bank(&self) -> usize {
+        (self.control & 0xF8).reverse_bits() as usize % (self.rom.len() / BANK_SIZE)
+    }
+
+    fn registers_enabled(&self) -> bool {
+        self.control & 0x01 != 0
+    }
+
+    // the selected device, if there's one
+    fn device(&self) -> Option<&AtaDisk> {
+        self.disk.as_ref().filter(|_| !self.slave_selected)
+    }
+
+    fn device_mut(&mut self) -> Option<&mut AtaDisk> {
+        self.disk.as_mut().filter(|_| !self.slave_selected)
+    }
+
+    fn read_data(&self) -> u16 {
+        self.device()
+            .map_or(u16::from_le_bytes([NO_DEVICE; 2]), AtaDisk::read_data)
+    }
+
+    fn write_data(&mut self, value: u16) {
+        if let Some(device) = self.device_mut() {
+            device.write_data(value);
+        }
+    }
+
+    fn read_register(&self, register: u16) -> u8 {
+        // the alternate status doesn't acknowledge interrupts, which the
+        // interface doesn't have
+        let register = if register == 14 { 7 } else { register };
+        match register {
+            _ if self.soft_reset => {
+                if register == 7 {
+                    0xFF
+                } else {
+                    NO_DEVICE
+                }
+            }
+            0 => self.read_data() as u8,
+            6 => {
+                let device = self.device().map_or(NO_DEVICE, |device| device.device);
+                device & !SLAVE | if self.slave_selected { SLAVE } else { 0 }
+            }
+            _ => self
+                .device()
+                .map_or(NO_DEVICE, |device| device.read_register(register)),
+        }
+    }
+
+    fn write_register(&mut self, register: u16, value: u8) {
+        if self.soft_reset {
+            if register == 14 && value & SRST == 0 {
+                self.soft_reset = false;
+            }
+            return;
+        }
+        match register {
+            0 => self.write_data(u16::from_le_bytes([value, value])),
+            14 if value & SRST != 0 => {
+                self.soft_reset = true;
+                if let Some(disk) = &mut self.disk {
+                    disk.reset();
+                }
+            }
+            _ => {
+                if register == 6 {
+                    self.slave_selected = value & SLAVE != 0;
+                }
+                if let Some(device) = self.device_mut() {
+                    device.write_register(register, value);
+                }
+            }
+        }
+    }
+}
+
+impl Slot for SunriseIde {
+    fn name(&self) -> &str {
+        "Sunrise IDE"
+    }
+
+    fn read(&self, address: u16) -> u8 {
+        if self.registers_enabled() && address & 0x3E00 == 0x3C00 {
+            if address & 1 == 0 {
+                let [low, high] = self.read_data().to_le_bytes();
+                self.read_latch.set(high as u32);
+                return low;
+            }
+            return self.read_latch.get() as u8;
+        }
+        if self.registers_enabled() && address & 0x3F00 == 0x3E00 {
+            return self.read_register(address & 0x0F);
+        }
+        match address {
+            0x4000..=0x7FFF => self.rom[self.bank() * BANK_SIZE + (address as usize & 0x3FFF)],
+            _ => 0xFF,
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        if address & 0xBF04 == 0x0104 {
+            self.control = value;
+        } else if self.registers_enabled() && address & 0x3E00 == 0x3C00 {
+            if address & 1 == 0 {
+                self.write_latch = value;
+            } else {
+                self.write_data(u16::from_le_bytes([self.write_latch, value]));
+            }
+        } else if self.registers_enabled() && address & 0x3F00 == 0x3E00 {
+            self.write_register(address & 0x0F, value);
+        }
+        // the flash isn't programmed, its commands are ignored
+    }
+
+    fn size(&self) -> u32 {
+        self.rom.len() as u32
+    }
+
+    fn reset(&mut self) {
+        self.soft_reset = false;
+        self.slave_selected = false;
+        if let Some(disk) = &mut self.disk {
+            disk.reset();
+        }
+    }
+
+    fn banks(&self) -> Vec<u8> {
+        vec![self.bank() as u8]
+    }
+}
+
+// a register changed by reads, which go through a shared reference
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ReadRegister(AtomicU32);
+
+impl ReadRegister {
+    fn get(&self) -> u32 {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    fn set(&self, value: u32) {
+        self.0.store(value, Ordering::Relaxed);
+    }
+}
+
+impl Clone for ReadRegister {
+    fn clone(&self) -> Self {
+        Self(AtomicU32::new(self.get()))
+    }
+}
+
+impl PartialEq for ReadRegister {
+    fn eq(&self, other: &Self) -> bool {
+        self.get() == other.get()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+enum Transfer {
+    None,
+    /// the IDENTIFY DEVICE data being read
+    Identify,
+    /// sectors being read from the image, from the offset
+    Read {
+        offset: usize,
+        len: usize,
+    },
+    /// sectors being written to the image, from the offset
+    Write {
+        offset: usize,
+        len: usize,
+    },
+}
+
+// a hard disk answering the ATA commands Nextor uses, with PIO transfers
+// that take no time
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct AtaDisk {
+    image: Vec<u8>,
+    error: u8,
+    feature: u8,
+    sector_count: u8,
+    // LBA bits 0-23, or the sector and the cylinder
+    address: [u8; 3],
+    device: u8,
+    status: u8,
+    transfer: Transfer,
+    // bytes of the transfer done so far
+    position: ReadRegister,
+}
+
+impl AtaDisk {
+    fn new(mut image: Vec<u8>) -> Self {
+        image.resize(image.len().next_multiple_of(SECTOR_SIZE), 0);
+        let mut disk = Self {
+            image,
+            error: 0,
+            feature: 0,
+            sector_count: 0,
+            address: [0; 3],
+            device: 0,
+            status: 0,
+            transfer: Transfer::None,
+            position: ReadRegister::default(),
+        };
+        disk.reset();
+        disk
+    }
+
+    // the signature of a hard disk, after it passed its diagnostics
+    fn reset(&mut self) {
+        self.error = 0x01;
+        self.sector_count = 1;
+        self.address = [1, 0, 0];
+        self.device = 0;
+        self.status = DRDY | DSC;
+        self.transfer = Transfer::None;
+    }
+
+    fn sectors(&self) -> usize {
+        self.image.len() / SECTOR_SIZE
+    }
+
+    fn transfer_len(&self) -> usize {
+        match self.transfer {
+            Transfer::None => 0,
+            Transfer::Identify => SECTOR_SIZE,
+            Transfer::Read { len, .. } | Transfer::Write { len, .. } => len,
+        }
+    }
+
+    fn transferring(&self) -> bool {
+        (self.position.get() as usize) < self.transfer_len()
+    }
+
+    fn read_register(&self, register: u16) -> u8 {
+        match register {
+            1 => self.error,
+            2 => self.sector_count,
+            3..=5 => self.address[register as usize - 3],
+            6 => self.device,
+            7 => self.status | if self.transferring() { DRQ } else { 0 },
+            _ => NO_DEVICE,
+        }
+    }
+
+    fn write_register(&mut self, register: u16, value: u8) {
+        match register {
+            1 => self.feature = value,
+            2 => self.sector_count = value,
+            3..=5 => self.address[register as usize - 3] = value,
+            6 => self.device = value,
+            7 => self.execute(value),
+            _ => {}
+        }
+    }
+
+    fn read_data(&self) -> u16 {
+        let position = self.position.get() as usize;
+        let offset = match self.transfer {
+            Transfer::Read { offset, .. } if self.transferring() => offset + position,
+            Transfer::Identify if self.transferring() => {
+                self.position.set(position as u32 + 2);
+                return self.identify()[position / 2];
+            }
+            _ => return u16::from_le_bytes([NO_DEVICE; 2]),
+        };
+        self.position.set(position as u32 + 2);
+        u16::from_le_bytes([self.image[offset], self.image[offset + 1]])
+    }
+
+    fn write_data(&mut self, value: u16) {
+        if let Transfer::Write { offset, .. } = self.transfer {
+            if self.transferring() {
+                let offset = offset + self.position.get() as usize;
+                self.image[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+                self.position.set(self.position.get() + 2);
+            }
+        }
+    }
+
+    // the first sector of the command, if it's on the disk
+    fn sector(&self) -> Option<usize> {
+        let [low, mid, high] = self.address.map(usize::from);
+        if self.device & LBA != 0 {
+            return Some(((self.device as usize & 0x0F) << 24) | (high << 16) | (mid << 8) | low);
+        }
+        let cylinder = (high << 8) | mid;
+        let head = self.device as usize & 0x0F;
+        let sector = low.checked_sub(1)?;
+        Some((cylinder * HEADS + head) * SECTORS_PER_TRACK + sector)
+    }
+
+    fn execute(&mut self, command: u8) {
+        self.status = DRDY | DSC;
+        self.error = 0;
+        self.transfer = Transfer::None;
+        self.position.set(0);
+        match command {
+            // IDENTIFY DEVICE
+            0xEC => self.transfer = Transfer::Identify,
+            // READ SECTORS and WRITE SECTORS, with or without retries
+            0x20 | 0x21 | 0x30 | 0x31 => {
+                // 0 is 256 sectors
+                let sectors = (self.sector_count as usize).wrapping_sub(1) % 256 + 1;
+                let Some(sector) = self
+                    .sector()
+                    .filter(|sector| sector + sectors <= self.sectors())
+                else {
+                    self.status |= ERR;
+                    self.error = IDNF;
+                    return;
+                };
+                let (offset, len) = (sector * SECTOR_SIZE, sectors * SECTOR_SIZE);
+                self.transfer = if command < 0x30 {
+                    Transfer::Read { offset, len }
+                } else {
+                    Transfer::Write { offset, len }
+                };
+            }
+            // EXECUTE DEVICE DIAGNOSTIC
+            0x90 => self.reset(),
+            // INITIALIZE DEVICE PARAMETERS, SET MULTIPLE MODE, FLUSH CACHE
+            // and SET FEATURES, accepted without changing anything
+            0x91 | 0xC6 | 0xE7 | 0xEF => {}
+            _ => {
+                self.status |= ERR;
+                self.error = ABRT;
+            }
+        }
+    }
+
+    // the words of the IDENTIFY DEVICE data
+    fn identify(&self) -> [u16; 256] {
+        let sectors = self.sectors().min(0x0FFF_FFFF);
+        let cylinders = (sectors / (HEADS * SECTORS_PER_TRACK)).min(16383);
+        let chs_sectors = cylinders * HEADS * SECTORS_PER_TRACK;
+
+        let mut words = [0; 256];
+        // a fixed disk
+        words[0] = 0x0040;
+        words[1] = cylinders as u16;
+        words[3] = HEADS as u16;
+        words[6] = SECTORS_PER_TRACK as u16;
+        identify_text(&mut words[10..20], "RUSTMSX");
+        identify_text(&mut words[23..27], "1.0");
+        identify_text(&mut words[27..47], "RUSTMSX HARD DISK");
+        // LBA supported
+        words[49] = 0x0200;
+        // the current geometry is valid
+        words[53] = 0x0001;
+        words[54] = cylinders as u16;
+        words[55] = HEADS as u16;
+        words[56] = SECTORS_PER_TRACK as u16;
+        words[57] = chs_sectors as u16;
+        words[58] = (chs_sectors >> 16) as u16;
+        words[60] = sectors as u16;
+        words[61] = (sectors >> 16) as u16;
+        words
+    }
+}
+
+// ATA strings have two characters in each word, the first in the high byte,
+// padded with spaces
+fn identify_text(words: &mut [u16], text: &str) {
+    let mut bytes = text.bytes().chain(std::iter::repeat(b' '));
+    for word in words {
+        let (first, second) = (bytes.next().unwrap(), bytes.next().unwrap());
+        *word = u16::from_be_bytes([first, second]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ide() -> SunriseIde {
+        let rom: Vec<u8> = (0..8).flat_map(|bank| vec![bank; BANK_SIZE]).collect();
+        let disk = (0..4u8)
+            .flat_map(|sector| vec![sector; SECTOR_SIZE])
+            .collect();
+        SunriseIde::new(&rom, Some(disk))
+    }
+
+    fn read_sector(ide: &SunriseIde) -> Vec<u8> {
+        (0..SECTOR_SIZE as u16)
+            .map(|n| ide.read(0x7C00 + n))
+            .collect()
+    }
+
+    #[test]
+    fn test_banks() {
+        let mut ide = ide();
+        // the registers hide the end of the last bank
+        assert_eq!(ide.read(0x4000), 7);
+        assert_eq!(ide.read(0x7E07), DRDY | DSC);
+
+        // bank 1 is bit 7, bank 4 bit 5
+        ide.write(0x4104, 0x80);
+        assert_eq!(ide.read(0x4000), 1);
+        assert_eq!(ide.read(0x7E07), 1);
+        ide.write(0x4104, 0x20);
+        assert_eq!(ide.banks(), [4]);
+        assert_eq!(ide.read(0x8000), 0xFF);
+    }
+
+    #[test]
+    fn test_read_and_write() {
+        let mut ide = ide();
+        ide.write(0x4104, 0x01);
+
+        // sectors 2 and 3 by LBA
+        ide.write(0x7E02, 2);
+        ide.write(0x7E03, 2);
+        ide.write(0x7E04, 0);
+        ide.write(0x7E05, 0);
+        ide.write(0x7E06, 0xE0);
+        ide.write(0x7E07, 0x20);
+        assert_eq!(ide.read(0x7E07), DRDY | DSC | DRQ);
+        assert_eq!(read_sector(&ide), [2; SECTOR_SIZE]);
+        assert_eq!(read_sector(&ide), [3; SECTOR_SIZE]);
+        assert_eq!(ide.read(0x7E07), DRDY | DSC);
+
+        // sector 1 by CHS, the first sector of the first track
+        ide.write(0x7E02, 1);
+        ide.write(0x7E03, 2);
+        ide.write(0x7E06, 0xA0);
+        ide.write(0x7E07, 0x30);
+        for n in 0..SECTOR_SIZE as u16 {
+            ide.write(0x7C00 + n, n as u8);
+        }
+        assert_eq!(ide.read(0x7E0E), DRDY | DSC);
+        let disk = ide.disk().unwrap();
+        assert_eq!(disk[SECTOR_SIZE..SECTOR_SIZE + 3], [0, 1, 2]);
+
+        // past the end of the disk
+        ide.write(0x7E02, 2);
+        ide.write(0x7E03, 3);
+        ide.write(0x7E06, 0xE0);
+        ide.write(0x7E07, 0x20);
+        assert_eq!(ide.read(0x7E07), DRDY | DSC | ERR);
+        assert_eq!(ide.read(0x7E01), IDNF);
+    }
+
+    #[test]
+    fn test_identify() {
+        let mut ide = ide();
+        ide.write(0x7E06, 0xA0);
+        ide.write(0x7E07, 0xEC);
+        let data = read_sector(&ide);
+        // 4 sectors, and the model with its characters swapped in each word
+        assert_eq!(data[120..124], [4, 0, 0, 0]);
+        assert_eq!(&data[54..62], b"URTSSM X");
+
+        // nothing answers as the slave
+        ide.write(0x7E06, 0xB0);
+        assert_eq!(ide.read(0x7E07), NO_DEVICE);
+        assert_eq!(ide.read(0x7E06), NO_DEVICE);
+
+        // nothing answers while resetting, then the signature of a disk
+        ide.write(0x7E06, 0xA0);
+        ide.write(0x7E0E, SRST);
+        assert_eq!(ide.read(0x7E07), 0xFF);
+        ide.write(0x7E0E, 0);
+        assert_eq!(ide.read(0x7E02), 1);
+        assert_eq!(ide.read(0x7E03), 1);
+        assert_eq!(ide.read(0x7E01), 0x01);
+        ide.write(0x7E07, 0x00);
+        assert_eq!(ide.read(0x7E01), ABRT);
+    }
+}
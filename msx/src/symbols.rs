@@ -0,0 +1,178 @@
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::anyhow;
+
+/// Labels of a program and their addresses, read from the symbol files
+/// assemblers write, e.g. `sjasmplus --sym` or `glass`:
+///
+/// ```text
+/// START:  EQU 0x4010
+/// loop    equ 4018h
+/// VBLANK = $FD9F
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Symbols {
+    by_name: HashMap<String, u16>,
+    by_address: BTreeMap<u16, String>,
+}
+
+impl Symbols {
+    /// Adds the labels of a symbol file.
+    #[cfg(feature = "std-fs")]
+    pub fn load(&mut self, path: impl AsRef<std::path::Path>) -> anyhow::Result<()> {
+        self.add_text(&std::fs::read_to_string(path)?)
+    }
+
+    /// Adds the labels of the text of a symbol file, skipping comments and
+    /// blank lines.
+    pub fn add_text(&mut self, text: &str) -> anyhow::Result<()> {
+        for (n, line) in text.lines().enumerate() {
+            let line = line.split(';').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (name, value) = split_definition(line)
+                .ok_or_else(|| anyhow!("Invalid symbol on line {}: {}", n + 1, line))?;
+            let address = parse_address(value)
+                .ok_or_else(|| anyhow!("Invalid address on line {}: {}", n + 1, value))?;
+            self.add(name, address);
+        }
+        Ok(())
+    }
+
+    pub fn add(&mut self, name: &str, address: u16) {
+        self.by_name.insert(name.to_string(), address);
+        // the first label of an address names it
+        self.by_address
+            .entry(address)
+            .or_insert_with(|| name.to_string());
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_name.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_name.is_empty()
+    }
+
+    /// The address of a label, matched without case when there's no exact
+    /// match.
+    pub fn address(&self, name: &str) -> Option<u16> {
+        self.by_name.get(name).copied().or_else(|| {
+            self.by_name
+                .iter()
+                .find(|(label, _)| label.eq_ignore_ascii_case(name))
+                .map(|(_, address)| *address)
+        })
+    }
+
+    /// The label at an address.
+    pub fn label(&self, address: u16) -> Option<&str> {
+        self.by_address.get(&address).map(String::as_str)
+    }
+
+    /// The closest label at or before an address, and how far past it the
+    /// address is.
+    pub fn locate(&self, address: u16) -> Option<(&str, u16)> {
+        self.by_address
+            .range(..=address)
+            .next_back()
+            .map(|(start, label)| (label.as_str(), address - start))
+    }
+
+    /// An address as `label+offset`, or in hex when no label precedes it.
+    pub fn describe(&self, address: u16) -> String {
+        match self.locate(address) {
+            Some((label, 0)) => label.to_string(),
+            Some((label, offset)) => format!("{}+{}", label, offset),
+            None => format!("#{:04X}", address),
+        }
+    }
+
+    /// An address given as a label or as hex, like the prompt takes them.
+    pub fn resolve(&self, s: &str) -> Option<u16> {
+        self.address(s).or_else(|| parse_address(s))
+    }
+}
+
+// `name: equ value`, `name equ value` or `name = value`
+fn split_definition(line: &str) -> Option<(&str, &str)> {
+    let (name, rest) = match line.split_once('=') {
+        Some((name, value)) => (name, value),
+        None => {
+            let (name, rest) = line.split_once(char::is_whitespace)?;
+            let rest = rest.trim_start();
+            let keyword = rest.get(..3)?;
+            if !keyword.eq_ignore_ascii_case("equ") {
+                return None;
+            }
+            (name, &rest[3..])
+        }
+    };
+
+    let name = name.trim().trim_end_matches(':');
+    let value = rest.trim();
+    if name.is_empty() || name.contains(char::is_whitespace) || value.is_empty() {
+        return None;
+    }
+    Some((name, value))
+}
+
+// hex as `0x1234`, `$1234`, `#1234`, `1234h` or bare, like the prompt;
+// assemblers may write 32 bit values, of which the low 16 bits are kept
+pub(crate) fn parse_address(s: &str) -> Option<u16> {
+    let hex = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .or_else(|| s.strip_prefix('$'))
+        .or_else(|| s.strip_prefix('#'))
+        .or_else(|| s.strip_suffix('h'))
+        .or_else(|| s.strip_suffix('H'))
+        .unwrap_or(s);
+    u32::from_str_radix(hex, 16)
+        .ok()
+        .map(|value| (value & 0xFFFF) as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_text() {
+        let mut symbols = Symbols::default();
+        symbols
+            .add_text(
+                "; sjasmplus
+START: EQU 0x00004010
+loop    equ 4018h
+VBLANK = $FD9F
+init: equ #4010 ; same address as START
+",
+            )
+            .unwrap();
+
+        assert_eq!(symbols.len(), 4);
+        assert_eq!(symbols.address("START"), Some(0x4010));
+        assert_eq!(symbols.address("Loop"), Some(0x4018));
+        assert_eq!(symbols.address("VBLANK"), Some(0xFD9F));
+        assert_eq!(symbols.label(0x4010), Some("START"));
+        assert!(symbols.add_text("START 0x4010").is_err());
+    }
+
+    #[test]
+    fn test_describe() {
+        let mut symbols = Symbols::default();
+        symbols.add("START", 0x4010);
+        symbols.add("loop", 0x4018);
+
+        assert_eq!(symbols.describe(0x4010), "START");
+        assert_eq!(symbols.describe(0x4013), "START+3");
+        assert_eq!(symbols.describe(0x401A), "loop+2");
+        assert_eq!(symbols.describe(0x0038), "#0038");
+        assert_eq!(symbols.resolve("loop"), Some(0x4018));
+        assert_eq!(symbols.resolve("F3F8"), Some(0xF3F8));
+    }
+}
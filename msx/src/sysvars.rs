@@ -0,0 +1,208 @@
+use crate::{
+    hooks::{describe_hook, hook_contents},
+    Z80,
+};
+
+/// How the bytes of a system variable are shown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Byte,
+    Word,
+    Bytes(u8),
+    /// 5 bytes called by the BIOS, `RET` until a program or an extension
+    /// hooks them with a `JP` or an inter-slot call
+    Hook,
+    /// `EXPTBL`, bit 7 of each of the 4 bytes telling whether the primary
+    /// slot is expanded
+    Expanded,
+}
+
+/// A variable of the work area of the BIOS, from `#F380` up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SystemVariable {
+    pub name: &'static str,
+    pub address: u16,
+    pub kind: Kind,
+    pub description: &'static str,
+}
+
+const fn var(
+    name: &'static str,
+    address: u16,
+    kind: Kind,
+    description: &'static str,
+) -> SystemVariable {
+    SystemVariable {
+        name,
+        address,
+        kind,
+        description,
+    }
+}
+
+/// The well-known system variables of MSX1, by address.
+#[rustfmt::skip]
+pub const SYSTEM_VARIABLES: &[SystemVariable] = &[
+    var("LINL40", 0xF3AE, Kind::Byte, "width of SCREEN 0"),
+    var("LINL32", 0xF3AF, Kind::Byte, "width of SCREEN 1"),
+    var("LINLEN", 0xF3B0, Kind::Byte, "width of the current text screen"),
+    var("CRTCNT", 0xF3B1, Kind::Byte, "lines of the text screen"),
+    var("TXTNAM", 0xF3B3, Kind::Word, "SCREEN 0 name table"),
+    var("TXTCGP", 0xF3B7, Kind::Word, "SCREEN 0 pattern table"),
+    var("T32NAM", 0xF3BD, Kind::Word, "SCREEN 1 name table"),
+    var("T32COL", 0xF3BF, Kind::Word, "SCREEN 1 color table"),
+    var("T32CGP", 0xF3C1, Kind::Word, "SCREEN 1 pattern table"),
+    var("T32ATR", 0xF3C3, Kind::Word, "SCREEN 1 sprite attribute table"),
+    var("T32PAT", 0xF3C5, Kind::Word, "SCREEN 1 sprite pattern table"),
+    var("GRPNAM", 0xF3C7, Kind::Word, "SCREEN 2 name table"),
+    var("GRPCOL", 0xF3C9, Kind::Word, "SCREEN 2 color table"),
+    var("GRPCGP", 0xF3CB, Kind::Word, "SCREEN 2 pattern table"),
+    var("GRPATR", 0xF3CD, Kind::Word, "SCREEN 2 sprite attribute table"),
+    var("GRPPAT", 0xF3CF, Kind::Word, "SCREEN 2 sprite pattern table"),
+    var("CLIKSW", 0xF3DB, Kind::Byte, "key click, 0 when off"),
+    var("CSRY", 0xF3DC, Kind::Byte, "cursor row"),
+    var("CSRX", 0xF3DD, Kind::Byte, "cursor column"),
+    var("CNSDFG", 0xF3DE, Kind::Byte, "function keys shown, 0 when hidden"),
+    var("RG0SAV", 0xF3DF, Kind::Bytes(8), "VDP registers 0-7 as last written"),
+    var("STATFL", 0xF3E7, Kind::Byte, "VDP status as last read"),
+    var("FORCLR", 0xF3E9, Kind::Byte, "foreground color"),
+    var("BAKCLR", 0xF3EA, Kind::Byte, "background color"),
+    var("BDRCLR", 0xF3EB, Kind::Byte, "border color"),
+    var("PUTPNT", 0xF3F8, Kind::Word, "where the next key goes in KEYBUF"),
+    var("GETPNT", 0xF3FA, Kind::Word, "where the next key comes from in KEYBUF"),
+    var("MEMSIZ", 0xF672, Kind::Word, "top of the memory used by BASIC"),
+    var("STKTOP", 0xF674, Kind::Word, "top of the stack of BASIC"),
+    var("TXTTAB", 0xF676, Kind::Word, "start of the BASIC program"),
+    var("VARTAB", 0xF6C2, Kind::Word, "start of the BASIC variables"),
+    var("ARYTAB", 0xF6C4, Kind::Word, "start of the BASIC arrays"),
+    var("STREND", 0xF6C6, Kind::Word, "end of the BASIC arrays"),
+    var("HOKVLD", 0xFB20, Kind::Byte, "bit 0 set when EXTBIO holds valid code"),
+    var("NEWKEY", 0xFBE5, Kind::Bytes(11), "keyboard matrix rows, 0 bits pressed"),
+    var("KEYBUF", 0xFBF0, Kind::Bytes(8), "start of the keyboard buffer"),
+    var("BOTTOM", 0xFC48, Kind::Word, "lowest RAM address"),
+    var("HIMEM", 0xFC4A, Kind::Word, "highest free RAM address"),
+    var("INTFLG", 0xFC9B, Kind::Byte, "3 when CTRL+STOP was pressed"),
+    var("JIFFY", 0xFC9E, Kind::Word, "counter incremented each interrupt"),
+    var("CSRSW", 0xFCA9, Kind::Byte, "cursor shown, 0 when hidden"),
+    var("CAPST", 0xFCAB, Kind::Byte, "CAPS lock, 0 when off"),
+    var("SCRMOD", 0xFCAF, Kind::Byte, "screen mode"),
+    var("OLDSCR", 0xFCB0, Kind::Byte, "last text screen mode"),
+    var("EXPTBL", 0xFCC1, Kind::Expanded, "expanded primary slots"),
+    var("SLTTBL", 0xFCC5, Kind::Bytes(4), "secondary slot registers"),
+    var("H.KEYI", 0xFD9A, Kind::Hook, "start of the interrupt handler"),
+    var("H.TIMI", 0xFD9F, Kind::Hook, "VDP interrupt"),
+    var("H.CHPU", 0xFDA4, Kind::Hook, "CHPUT, printing a character"),
+    var("H.DSPC", 0xFDA9, Kind::Hook, "showing the cursor"),
+    var("H.NMI", 0xFDD6, Kind::Hook, "NMI"),
+    var("H.STKE", 0xFEDA, Kind::Hook, "before BASIC starts, to run a ROM"),
+    var("H.PHYD", 0xFFA7, Kind::Hook, "PHYDIO, disk access"),
+    var("H.FORM", 0xFFAC, Kind::Hook, "FORMAT, disk formatting"),
+    var("EXTBIO", 0xFFCA, Kind::Hook, "extended BIOS"),
+    var("DISINT", 0xFFCF, Kind::Hook, "before the interrupts are disabled for a disk access"),
+    var("ENAINT", 0xFFD4, Kind::Hook, "after the interrupts are enabled again"),
+];
+
+impl SystemVariable {
+    pub fn size(&self) -> u16 {
+        match self.kind {
+            Kind::Byte => 1,
+            Kind::Word => 2,
+            Kind::Bytes(n) => n as u16,
+            Kind::Hook => 5,
+            Kind::Expanded => 4,
+        }
+    }
+
+    pub fn bytes(&self, cpu: &Z80) -> Vec<u8> {
+        (0..self.size())
+            .map(|n| cpu.read_byte(self.address.wrapping_add(n)))
+            .collect()
+    }
+
+    /// The current value, decoded, e.g. `#0F (15)` for a byte or
+    /// `RST 30h slot #8B #7A3C` for a hook.
+    pub fn value(&self, cpu: &Z80) -> String {
+        let bytes = self.bytes(cpu);
+        let hex = bytes
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect::<Vec<_>>()
+            .join(" ");
+        match self.kind {
+            Kind::Byte => format!("#{:02X} ({})", bytes[0], bytes[0]),
+            Kind::Word => format!("#{:04X}", u16::from_le_bytes([bytes[0], bytes[1]])),
+            Kind::Bytes(_) => hex,
+            Kind::Hook => describe_hook(&hook_contents(cpu, self.address)),
+            Kind::Expanded => {
+                let expanded = (0..4)
+                    .filter(|slot| bytes[*slot] & 0x80 != 0)
+                    .map(|slot| slot.to_string())
+                    .collect::<Vec<_>>();
+                match expanded.is_empty() {
+                    true => format!("{} (none)", hex),
+                    false => format!("{} (slots {})", hex, expanded.join(", ")),
+                }
+            }
+        }
+    }
+}
+
+/// The system variables whose names contain a text, without case, or all of
+/// them without one.
+pub fn matching(filter: Option<&str>) -> impl Iterator<Item = &'static SystemVariable> + '_ {
+    SYSTEM_VARIABLES.iter().filter(move |var| match filter {
+        Some(filter) => var.name.to_lowercase().contains(&filter.to_lowercase()),
+        None => true,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bus::Bus,
+        slot::{RamSlot, SlotType},
+    };
+    use std::sync::{Arc, RwLock};
+
+    #[test]
+    fn test_addresses_sorted() {
+        for pair in SYSTEM_VARIABLES.windows(2) {
+            assert!(
+                pair[0].address + pair[0].size() <= pair[1].address,
+                "{} overlaps {}",
+                pair[0].name,
+                pair[1].name
+            );
+        }
+    }
+
+    #[test]
+    fn test_value() {
+        let bus = Bus::new(&[
+            SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Empty,
+        ]);
+        let mut cpu = Z80::new(Arc::new(RwLock::new(bus)));
+        let find = |name| matching(Some(name)).next().unwrap();
+
+        cpu.write_byte(0xF3E9, 15);
+        cpu.write_word(0xFC9E, 0x1234);
+        for (n, byte) in [0xF7, 0x8B, 0x3C, 0x7A, 0xC9].iter().enumerate() {
+            cpu.write_byte(0xFD9F + n as u16, *byte);
+        }
+        cpu.write_byte(0xFDA4, 0xC9);
+        for (n, byte) in [0x00, 0x00, 0x00, 0x80].iter().enumerate() {
+            cpu.write_byte(0xFCC1 + n as u16, *byte);
+        }
+
+        assert_eq!(find("FORCLR").value(&cpu), "#0F (15)");
+        assert_eq!(find("jiffy").value(&cpu), "#1234");
+        assert_eq!(find("H.TIMI").value(&cpu), "RST 30h slot #8B #7A3C");
+        assert_eq!(find("H.CHPU").value(&cpu), "RET");
+        assert_eq!(find("EXPTBL").value(&cpu), "00 00 00 80 (slots 3)");
+        assert_eq!(matching(Some("grp")).count(), 5);
+    }
+}
@@ -0,0 +1,238 @@
+use crate::Z80;
+
+// T-states of the unprefixed instructions, with conditional jumps, calls and
+// returns not taken
+#[rustfmt::skip]
+const MAIN: [u8; 256] = [
+    4, 10,  7,  6,  4,  4,  7,  4,  4, 11,  7,  6,  4,  4,  7,  4,
+    8, 10,  7,  6,  4,  4,  7,  4, 12, 11,  7,  6,  4,  4,  7,  4,
+    7, 10, 16,  6,  4,  4,  7,  4,  7, 11, 16,  6,  4,  4,  7,  4,
+    7, 10, 13,  6, 11, 11, 10,  4,  7, 11, 13,  6,  4,  4,  7,  4,
+    4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4,
+    4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4,
+    4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4,
+    7,  7,  7,  7,  7,  7,  4,  7,  4,  4,  4,  4,  4,  4,  7,  4,
+    4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4,
+    4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4,
+    4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4,
+    4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4,
+    5, 10, 10, 10, 10, 11,  7, 11,  5, 10, 10,  0, 10, 17,  7, 11,
+    5, 10, 10, 11, 10, 11,  7, 11,  5,  4, 10, 11, 10,  0,  7, 11,
+    5, 10, 10, 19, 10, 11,  7, 11,  5,  4, 10,  4, 10,  0,  7, 11,
+    5, 10, 10,  4, 10, 11,  7, 11,  5,  6, 10,  4, 10,  0,  7, 11,
+];
+
+/// T-states an instruction takes on an MSX: those of the Z80 plus the wait
+/// state the MSX inserts in each opcode fetch (M1 cycle), unless the CPU
+/// counts them as a stock Z80.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timing {
+    pub t_states: u32,
+    /// whether it's a repeating block instruction like LDIR or OTIR, which
+    /// takes 5 more T-states when it repeats
+    pub repeats: bool,
+}
+
+impl Timing {
+    /// T-states the instruction took, given the PC it started at and the CPU
+    /// after it ran: block instructions rewind PC while they repeat.
+    pub fn taken(&self, pc: u16, cpu: &Z80) -> u32 {
+        let repeated = self.repeats && cpu.pc == pc;
+        self.t_states + if repeated { 5 } else { 0 }
+    }
+}
+
+/// The timing of the instruction at PC, with the conditions evaluated on
+/// the current flags. The CPU accepting an interrupt isn't counted.
+pub fn timing(cpu: &Z80) -> Timing {
+    let wait = if cpu.stock_timing { 0 } else { 1 };
+    if cpu.halted {
+        // HALT keeps fetching NOPs
+        return Timing {
+            t_states: 4 + wait,
+            repeats: false,
+        };
+    }
+
+    let byte = |n: u16| cpu.read_byte(cpu.pc.wrapping_add(n));
+    let opcode = byte(0);
+    let (t_states, m1, repeats) = match opcode {
+        0xCB => (cb(byte(1)), 2, false),
+        0xED => {
+            let (t_states, repeats) = ed(byte(1));
+            (t_states, 2, repeats)
+        }
+        0xDD | 0xFD => match byte(1) {
+            // the DDCB/FDCB opcode comes after the displacement
+            0xCB => (4 + indexed_cb(byte(3)), 2, false),
+            // a prefix followed by another one is a NOP
+            0xDD | 0xED | 0xFD => (4, 1, false),
+            next => (4 + indexed(next, cpu.f, cpu.b), 2, false),
+        },
+        _ => (main(opcode, cpu.f, cpu.b), 1, false),
+    };
+
+    Timing {
+        t_states: t_states + m1 * wait,
+        repeats,
+    }
+}
+
+// whether the condition of bits 5-3 of the opcode holds
+fn condition(opcode: u8, f: u8) -> bool {
+    let flag = match (opcode >> 4) & 0x03 {
+        0 => f & 0x40, // Z
+        1 => f & 0x01, // C
+        2 => f & 0x04, // P/V
+        _ => f & 0x80, // S
+    } != 0;
+    // NZ, NC, PO and P are the odd ones out
+    flag == (opcode & 0x08 != 0)
+}
+
+fn main(opcode: u8, f: u8, b: u8) -> u32 {
+    let base = MAIN[opcode as usize] as u32;
+    let taken = match opcode {
+        // DJNZ
+        0x10 => b != 1,
+        // JR cc
+        0x20 | 0x28 | 0x30 | 0x38 => condition(opcode.wrapping_sub(0x20), f),
+        // RET cc
+        op if op & 0xC7 == 0xC0 => condition(op, f),
+        // CALL cc
+        op if op & 0xC7 == 0xC4 => condition(op, f),
+        _ => false,
+    };
+    if !taken {
+        return base;
+    }
+    base + match opcode {
+        0x10 | 0x20 | 0x28 | 0x30 | 0x38 => 5,
+        op if op & 0xC7 == 0xC0 => 6,
+        _ => 7,
+    }
+}
+
+fn cb(opcode: u8) -> u32 {
+    match (opcode & 0x07 == 0x06, opcode & 0xC0) {
+        (false, _) => 8,
+        // BIT n,(HL)
+        (true, 0x40) => 12,
+        (true, _) => 15,
+    }
+}
+
+fn ed(opcode: u8) -> (u32, bool) {
+    match opcode {
+        0x40..=0x7F => {
+            let t_states = match opcode & 0x07 {
+                0 | 1 => 12,
+                2 => 15,
+                3 => 20,
+                4 | 6 => 8,
+                5 => 14,
+                // LD I,A and alike, RRD and RLD
+                _ => match (opcode >> 3) & 0x07 {
+                    0..=3 => 9,
+                    4 | 5 => 18,
+                    _ => 8,
+                },
+            };
+            (t_states, false)
+        }
+        // LDI, CPI, INI, OUTI and their decrementing and repeating forms
+        0xA0..=0xA3 | 0xA8..=0xAB => (16, false),
+        0xB0..=0xB3 | 0xB8..=0xBB => (16, true),
+        _ => (8, false),
+    }
+}
+
+// T-states after the prefix of the DD and FD instructions
+fn indexed(opcode: u8, f: u8, b: u8) -> u32 {
+    let memory = match opcode {
+        0x34 | 0x35 => return 19,
+        0x36 => return 15,
+        0x76 => false,
+        0x40..=0x7F => opcode & 0x07 == 0x06 || opcode & 0x38 == 0x30,
+        0x80..=0xBF => opcode & 0x07 == 0x06,
+        _ => false,
+    };
+    match memory {
+        // the displacement adds 8 to the (HL) form
+        true => 15,
+        false => main(opcode, f, b),
+    }
+}
+
+fn indexed_cb(opcode: u8) -> u32 {
+    match opcode & 0xC0 {
+        0x40 => 16,
+        _ => 19,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, RwLock};
+
+    use super::*;
+    use crate::{
+        bus::Bus,
+        slot::{RamSlot, SlotType},
+    };
+
+    fn t_states(code: &[u8], f: u8) -> u32 {
+        cpu_timing(code, f, false)
+    }
+
+    fn cpu_timing(code: &[u8], f: u8, stock_timing: bool) -> u32 {
+        let bus = Bus::new(&[
+            SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Empty,
+        ]);
+        let mut cpu = Z80::new(Arc::new(RwLock::new(bus)));
+        for (n, byte) in code.iter().enumerate() {
+            cpu.write_byte(0x4000 + n as u16, *byte);
+        }
+        cpu.pc = 0x4000;
+        cpu.f = f;
+        cpu.b = 2;
+        cpu.stock_timing = stock_timing;
+        timing(&cpu).t_states
+    }
+
+    #[test]
+    fn test_timing() {
+        // NOP, OUT (n),A and LD A,(nn)
+        assert_eq!(t_states(&[0x00], 0), 5);
+        assert_eq!(t_states(&[0xD3, 0x98], 0), 12);
+        assert_eq!(t_states(&[0x3A, 0x00, 0xC0], 0), 14);
+        // JP NZ,nn, JR NZ taken and not, DJNZ taken
+        assert_eq!(t_states(&[0xC2, 0x00, 0x40], 0), 11);
+        assert_eq!(t_states(&[0x20, 0xFE], 0), 13);
+        assert_eq!(t_states(&[0x20, 0xFE], 0x40), 8);
+        assert_eq!(t_states(&[0x10, 0xFE], 0), 14);
+        // RET Z and CALL C taken
+        assert_eq!(t_states(&[0xC8], 0x40), 12);
+        assert_eq!(t_states(&[0xDC, 0x00, 0x40], 0x01), 18);
+        // OUTI, BIT 0,(HL), LD A,(IX+1), INC (IX+1), SET 0,(IX+1)
+        assert_eq!(t_states(&[0xED, 0xA3], 0), 18);
+        assert_eq!(t_states(&[0xCB, 0x46], 0), 14);
+        assert_eq!(t_states(&[0xDD, 0x7E, 0x01], 0), 21);
+        assert_eq!(t_states(&[0xDD, 0x34, 0x01], 0), 25);
+        assert_eq!(t_states(&[0xDD, 0xCB, 0x01, 0xC6], 0), 25);
+        // PUSH IX
+        assert_eq!(t_states(&[0xDD, 0xE5], 0), 17);
+    }
+
+    #[test]
+    fn test_stock_timing() {
+        // NOP, LD A,(IX+1) and OTIR, without the wait states of their fetches
+        assert_eq!(cpu_timing(&[0x00], 0, true), 4);
+        assert_eq!(cpu_timing(&[0xDD, 0x7E, 0x01], 0, true), 19);
+        assert_eq!(cpu_timing(&[0xED, 0xB3], 0, true), 16);
+        assert_eq!(cpu_timing(&[0xED, 0xB3], 0, false), 18);
+    }
+}
@@ -0,0 +1,678 @@
+use std::fmt;
+#[cfg(feature = "std-fs")]
+use std::path::Path;
+
+use anyhow::bail;
+
+use crate::cas::{self, Cas};
+
+// T-states a second of the 3.58 MHz Z80, the time base of the tape
+const CPU_HZ: f64 = 3_579_545.0;
+
+/// Baud rates the BIOS saves at: 1200, and 2400 after `SCREEN ,,,2`.
+pub const BAUD_RATES: [u32; 2] = [1200, 2400];
+
+// silence and header tone before the blocks of a CAS image, in seconds: the
+// long ones before those naming a file, the short ones before the others
+const LONG_SILENCE: f64 = 2.0;
+const SHORT_SILENCE: f64 = 1.0;
+const LONG_HEADER: f64 = 2000.0 / 1200.0;
+const SHORT_HEADER: f64 = 500.0 / 1200.0;
+
+// part of the peak a sample must reach past zero to flip the level, so
+// that the noise around the crossings doesn't
+const HYSTERESIS: f32 = 0.1;
+
+/// A recording mixed down to mono, its samples from -1.0 to 1.0.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Wav {
+    pub sample_rate: u32,
+    pub samples: Vec<f32>,
+}
+
+impl Wav {
+    /// Parses a PCM WAV file of 8, 16, 24 or 32 bits a sample, mixing its
+    /// channels down.
+    pub fn parse(data: &[u8]) -> anyhow::Result<Self> {
+        if data.len() < 12 || &data[0..4] != b"RIFF" || &data[8..12] != b"WAVE" {
+            bail!("Not a WAV file: missing RIFF/WAVE header");
+        }
+
+        let mut format = None;
+        let mut samples = None;
+        let mut offset = 12;
+        while offset + 8 <= data.len() {
+            let size =
+                u32::from_le_bytes(data[offset + 4..offset + 8].try_into().unwrap()) as usize;
+            let body = &data[offset + 8..(offset + 8).saturating_add(size).min(data.len())];
+            match &data[offset..offset + 4] {
+                b"fmt " => format = Some(body),
+                b"data" => samples = Some(body),
+                _ => {}
+            }
+            // chunks are padded to an even size
+            offset = offset.saturating_add(8 + size + size % 2);
+        }
+        let Some(format) = format.filter(|format| format.len() >= 16) else {
+            bail!("Invalid WAV file: missing format chunk");
+        };
+        let Some(samples) = samples else {
+            bail!("Invalid WAV file: missing data chunk");
+        };
+
+        let word = |offset: usize| u16::from_le_bytes([format[offset], format[offset + 1]]);
+        let (encoding, channels, bits) = (word(0), word(2) as usize, word(14) as usize);
+        let sample_rate = u32::from_le_bytes(format[4..8].try_into().unwrap());
+        // PCM, or WAVE_FORMAT_EXTENSIBLE, which PCM files of more than 16
+        // bits or 2 channels use
+        if encoding != 1 && encoding != 0xFFFE {
+            bail!("Unsupported WAV encoding {:#06X}, only PCM is", encoding);
+        }
+        if !matches!(bits, 8 | 16 | 24 | 32) || channels == 0 || sample_rate == 0 {
+            bail!(
+                "Unsupported WAV format: {} channels of {} bits at {} Hz",
+                channels,
+                bits,
+                sample_rate
+            );
+        }
+
+        let width = bits / 8;
+        let samples = samples
+            .chunks_exact(width * channels)
+            .map(|frame| frame.chunks_exact(width).map(pcm).sum::<f32>() / channels as f32)
+            .collect();
+        Ok(Self {
+            sample_rate,
+            samples,
+        })
+    }
+
+    /// Seconds the recording lasts.
+    pub fn duration(&self) -> f64 {
+        self.samples.len() as f64 / self.sample_rate as f64
+    }
+}
+
+// a PCM sample as -1.0 to 1.0, unsigned at 8 bits and signed above
+fn pcm(bytes: &[u8]) -> f32 {
+    let n = bytes.len();
+    if n == 1 {
+        return (bytes[0] as f32 - 128.0) / 128.0;
+    }
+    // sign-extended from the most significant byte, the last one
+    let value = bytes[..n - 1]
+        .iter()
+        .rev()
+        .fold(bytes[n - 1] as i8 as i32, |value, &byte| {
+            value << 8 | byte as i32
+        });
+    value as f32 / (1u64 << (n * 8 - 1)) as f32
+}
+
+// times, in seconds, the recording crosses zero, once its DC offset is
+// removed
+fn crossings(wav: &Wav) -> Vec<f64> {
+    // a high-pass filter at about 30 Hz, far below the tones
+    let alpha = (200.0 / wav.sample_rate as f32).min(1.0);
+    let mut offset = 0.0;
+    let filtered: Vec<f32> = wav
+        .samples
+        .iter()
+        .map(|sample| {
+            offset += (sample - offset) * alpha;
+            sample - offset
+        })
+        .collect();
+    let peak = filtered
+        .iter()
+        .fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+    let threshold = peak * HYSTERESIS;
+
+    let mut crossings = Vec::new();
+    let mut high = None;
+    for (n, &sample) in filtered.iter().enumerate() {
+        let level = match sample {
+            sample if sample > threshold => true,
+            sample if sample < -threshold => false,
+            _ => continue,
+        };
+        if high.is_some_and(|high| high != level) {
+            crossings.push(n as f64 / wav.sample_rate as f64);
+        }
+        high = Some(level);
+    }
+    crossings
+}
+
+// the baud rate of a recording, told by the half cycles of its high tone:
+// the headers, and most of the data, are 1s
+fn detect_baud(crossings: &[f64]) -> u32 {
+    let mut halves: Vec<f64> = crossings.windows(2).map(|pair| pair[1] - pair[0]).collect();
+    if halves.is_empty() {
+        return BAUD_RATES[0];
+    }
+    halves.sort_by(f64::total_cmp);
+    let median = halves[halves.len() / 2];
+    // half cycles of 208 µs at 1200 baud, of 104 µs at 2400
+    if median < 1.0 / (4.0 * 1800.0) {
+        2400
+    } else {
+        1200
+    }
+}
+
+/// Bits demodulated from a stretch of a recording, and the silence before
+/// it.
+#[derive(Debug, Clone, PartialEq)]
+struct Run {
+    silence: f64,
+    bits: Vec<bool>,
+}
+
+// Demodulates the FSK of the MSX tapes: a 0 is a cycle of a tone at the
+// baud rate, a 1 two cycles of a tone at twice it. Half cycles too long for
+// either are silence, ending a run of bits.
+fn demodulate(crossings: &[f64], baud: u32) -> Vec<Run> {
+    let bit = 1.0 / baud as f64;
+    // between the half cycles of the two tones, a quarter and half a bit
+    let short = bit * 3.0 / 8.0;
+
+    let mut runs: Vec<Run> = Vec::new();
+    let mut push = |start: f64, end: &mut f64, value: bool, until: f64| {
+        match runs.last_mut() {
+            Some(run) if start - *end <= bit => run.bits.push(value),
+            _ => runs.push(Run {
+                silence: start - *end,
+                bits: vec![value],
+            }),
+        }
+        *end = until;
+    };
+    let mut end = 0.0;
+    let mut start = 0.0;
+    let (mut lows, mut highs) = (0, 0);
+    for pair in crossings.windows(2) {
+        let half = pair[1] - pair[0];
+        let high = half < short;
+        // the half cycle next to silence has no crossing on that side, so
+        // a 1 short of it at the edge of a run is one
+        if highs == 3 && !high {
+            push(start, &mut end, true, pair[0]);
+        }
+        if half > bit {
+            (lows, highs) = (0, 0);
+            continue;
+        }
+        let (count, needed) = if high {
+            (highs, lows) = (highs + 1, 0);
+            (highs, 4)
+        } else {
+            (lows, highs) = (lows + 1, 0);
+            (lows, 2)
+        };
+        if count == 1 {
+            start = pair[0];
+        }
+        if count == needed {
+            (lows, highs) = (0, 0);
+            push(start, &mut end, high, pair[1]);
+        }
+    }
+    if highs == 3 {
+        push(start, &mut end, true, crossings[crossings.len() - 1]);
+    }
+    runs
+}
+
+// a byte as the BIOS frames it: a 0, the bits from the lowest, two 1s
+fn frame(byte: u8) -> impl Iterator<Item = bool> {
+    std::iter::once(false)
+        .chain((0..8).map(move |n| byte & 1 << n != 0))
+        .chain([true, true])
+}
+
+// the blocks of a CAS image as the BIOS saves them at the baud rate, each
+// after silence and a header tone
+fn modulate(cas: &Cas, baud: u32) -> Vec<Run> {
+    cas.blocks
+        .iter()
+        .map(|block| {
+            let (silence, header) = match cas::is_file_header(block) {
+                true => (LONG_SILENCE, LONG_HEADER),
+                false => (SHORT_SILENCE, SHORT_HEADER),
+            };
+            let ones = (header * baud as f64) as usize;
+            Run {
+                silence,
+                bits: std::iter::repeat(true)
+                    .take(ones)
+                    .chain(block.iter().flat_map(|byte| frame(*byte)))
+                    .collect(),
+            }
+        })
+        .collect()
+}
+
+/// Where and how a tape is played, for `tape status`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TapeStatus {
+    pub name: String,
+    /// seconds played
+    pub position: f64,
+    /// seconds of the whole tape
+    pub length: f64,
+    pub baud: u32,
+    /// whether the baud rate was told from the recording
+    pub detected: bool,
+    /// bits demodulated from the recording
+    pub bits: usize,
+    pub motor: bool,
+}
+
+// seconds as minutes, e.g. 2:05.3
+fn minutes(seconds: f64) -> String {
+    format!("{}:{:04.1}", (seconds / 60.0) as u64, seconds % 60.0)
+}
+
+impl fmt::Display for TapeStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} of {} ({:.0}%), {} baud{}, {} bits, motor {}",
+            self.name,
+            minutes(self.position),
+            minutes(self.length),
+            if self.length > 0.0 {
+                (self.position / self.length * 100.0).min(100.0)
+            } else {
+                0.0
+            },
+            self.baud,
+            if self.detected { " (detected)" } else { "" },
+            self.bits,
+            if self.motor { "on" } else { "off" }
+        )
+    }
+}
+
+// a byte on the tape, from its start bit to its last bit, in T-states
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Frame {
+    start: u64,
+    end: u64,
+    byte: u8,
+    // whether it's the first of a block, after a header tone
+    first: bool,
+}
+
+// what a tape is made from
+#[derive(Debug, Clone, PartialEq)]
+enum Source {
+    Wav {
+        // zero crossings of the recording, in seconds, to demodulate it
+        // again at another baud rate
+        crossings: Vec<f64>,
+        // seconds the recording lasts
+        duration: f64,
+        detected_baud: u32,
+    },
+    Cas(Cas),
+}
+
+/// A cassette in the recorder, made from a recording or a CAS image: its
+/// bits are played as a clean square wave on the cassette input, bit 7 of
+/// PSG port A, while the motor, bit 4 of PPI port C, runs. Replaying those
+/// of a recording at the nominal baud rate, rather than the raw recording,
+/// leaves the noise and the wow of the cassette out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tape {
+    name: String,
+    source: Source,
+    // baud rate set by the user, the detected one, or 1200 for a CAS image,
+    // otherwise
+    baud: Option<u32>,
+    bits: usize,
+    // the bytes framed by the bits, for the quick loading
+    frames: Vec<Frame>,
+    // T-states into the tape the square wave toggles at, low at the start
+    edges: Vec<u64>,
+    // T-states of the whole tape
+    length: u64,
+    // T-states played
+    position: u64,
+    motor: bool,
+    // T-state of the bus the position was last worked out at
+    clock: u64,
+}
+
+impl Tape {
+    /// Makes a tape from a WAV recording, demodulated at a baud rate, or at
+    /// the one it was saved at if none is given.
+    pub fn from_wav(name: &str, data: &[u8], baud: Option<u32>) -> anyhow::Result<Self> {
+        let wav = Wav::parse(data)?;
+        let crossings = crossings(&wav);
+        let source = Source::Wav {
+            detected_baud: detect_baud(&crossings),
+            crossings,
+            duration: wav.duration(),
+        };
+        Ok(Self::new(name, source, baud))
+    }
+
+    /// Makes a tape from a CAS image, played at a baud rate, or at 1200.
+    pub fn from_cas(name: &str, data: &[u8], baud: Option<u32>) -> anyhow::Result<Self> {
+        Ok(Self::new(name, Source::Cas(Cas::parse(data)?), baud))
+    }
+
+    fn new(name: &str, source: Source, baud: Option<u32>) -> Self {
+        let mut tape = Self {
+            name: name.to_string(),
+            source,
+            baud,
+            bits: 0,
+            frames: Vec::new(),
+            edges: Vec::new(),
+            length: 0,
+            position: 0,
+            motor: false,
+            clock: 0,
+        };
+        tape.decode();
+        tape
+    }
+
+    /// Loads a WAV recording or a CAS image, told apart by their contents.
+    #[cfg(feature = "std-fs")]
+    pub fn load(path: &Path, baud: Option<u32>) -> anyhow::Result<Self> {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let data = std::fs::read(path)?;
+        match data.starts_with(&cas::BLOCK_HEADER) {
+            true => Self::from_cas(&name, &data, baud),
+            false => Self::from_wav(&name, &data, baud),
+        }
+    }
+
+    // demodulates the recording, or modulates the image, at the baud rate
+    // and lays the bits out as the edges of the square wave
+    fn decode(&mut self) {
+        let baud = self.baud();
+        let (runs, duration) = match &self.source {
+            Source::Wav {
+                crossings,
+                duration,
+                ..
+            } => (demodulate(crossings, baud), *duration),
+            Source::Cas(cas) => (modulate(cas, baud), 0.0),
+        };
+        let bit = CPU_HZ / baud as f64;
+        let mut time = 0.0;
+        let mut end = 0.0;
+        self.edges.clear();
+        self.frames.clear();
+        self.bits = 0;
+        for run in &runs {
+            time += run.silence * CPU_HZ;
+            // 1s in a row outside of a byte, a run starting a block
+            let mut ones = cas::HEADER_ONES;
+            let mut frame: Option<Frame> = None;
+            let mut bits = 0;
+            for &value in &run.bits {
+                let start = time.round() as u64;
+                let halves = if value { 4 } else { 2 };
+                for _ in 0..halves {
+                    time += bit / halves as f64;
+                    self.edges.push(time.round() as u64);
+                }
+                match &mut frame {
+                    Some(byte) => {
+                        byte.byte |= (value as u8) << bits;
+                        bits += 1;
+                        if bits == 8 {
+                            byte.end = time.round() as u64;
+                            self.frames.push(*byte);
+                            frame = None;
+                            ones = 0;
+                        }
+                    }
+                    None if value => ones += 1,
+                    None => {
+                        frame = Some(Frame {
+                            start,
+                            end: start,
+                            byte: 0,
+                            first: ones >= cas::HEADER_ONES,
+                        });
+                        bits = 0;
+                        ones = 0;
+                    }
+                }
+            }
+            end += run.silence + run.bits.len() as f64 / baud as f64;
+            self.bits += run.bits.len();
+        }
+        // with the silence after the last bit
+        self.length = (time + (duration - end).max(0.0) * CPU_HZ) as u64;
+        self.position = self.position.min(self.length);
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The baud rate the tape is played at.
+    pub fn baud(&self) -> u32 {
+        match (&self.source, self.baud) {
+            (_, Some(baud)) => baud,
+            (Source::Wav { detected_baud, .. }, None) => *detected_baud,
+            (Source::Cas(_), None) => BAUD_RATES[0],
+        }
+    }
+
+    /// Plays the tape at a baud rate, or at its own, keeping the position.
+    pub fn set_baud(&mut self, baud: Option<u32>) {
+        self.baud = baud;
+        self.decode();
+    }
+
+    pub fn rewind(&mut self) {
+        self.position = 0;
+    }
+
+    /// Whether the motor runs.
+    pub fn motor(&self) -> bool {
+        self.motor
+    }
+
+    /// Moves the tape to the T-state `clock` of the bus, if the motor runs.
+    pub fn sync(&mut self, clock: u64) {
+        if self.motor {
+            self.position += clock.saturating_sub(self.clock);
+        }
+        self.clock = clock;
+    }
+
+    /// Sets the T-state of the bus the tape follows without moving it, for
+    /// when the clock of the bus goes back, on a reset or a loaded state.
+    pub fn set_clock(&mut self, clock: u64) {
+        self.clock = clock;
+    }
+
+    /// Starts or stops the motor at the T-state `clock`.
+    pub fn set_motor(&mut self, clock: u64, motor: bool) {
+        self.sync(clock);
+        self.motor = motor;
+    }
+
+    /// The level of the cassette input at the T-state `clock`.
+    pub fn level(&mut self, clock: u64) -> bool {
+        self.sync(clock);
+        self.edges.partition_point(|&edge| edge <= self.position) % 2 == 1
+    }
+
+    /// Moves the tape to the first byte of the next block, as the BIOS
+    /// TAPION routine finds it after the header tone. False past the last
+    /// one.
+    pub fn seek_block(&mut self, clock: u64) -> bool {
+        self.sync(clock);
+        let next = self.frames[self.next_frame()..]
+            .iter()
+            .find(|frame| frame.first);
+        if let Some(frame) = next {
+            self.position = frame.start;
+        }
+        next.is_some()
+    }
+
+    /// Reads the next byte and moves the tape past it, as the BIOS TAPIN
+    /// routine does. `None` past the last one.
+    pub fn read_byte(&mut self, clock: u64) -> Option<u8> {
+        self.sync(clock);
+        let frame = *self.frames.get(self.next_frame())?;
+        self.position = frame.end;
+        Some(frame.byte)
+    }
+
+    // index of the first frame not started at the position
+    fn next_frame(&self) -> usize {
+        self.frames
+            .partition_point(|frame| frame.start < self.position)
+    }
+
+    pub fn status(&self) -> TapeStatus {
+        TapeStatus {
+            name: self.name.clone(),
+            position: self.position as f64 / CPU_HZ,
+            length: self.length as f64 / CPU_HZ,
+            baud: self.baud(),
+            detected: self.baud.is_none() && matches!(self.source, Source::Wav { .. }),
+            bits: self.bits,
+            motor: self.motor,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        slot::{RamSlot, SlotType},
+        Msx,
+    };
+
+    const RATE: u32 = 44100;
+
+    // a 16-bit mono WAV of the bits, with a DC offset and silence around
+    fn wav(bits: &[bool], baud: u32) -> Vec<u8> {
+        let mut samples = vec![0.0; RATE as usize / 10];
+        let mut phase = 0.0f64;
+        let length = bits.len() * RATE as usize / baud as usize;
+        for n in 0..length {
+            let bit = bits[n * baud as usize / RATE as usize];
+            let frequency = if bit { 2 * baud } else { baud } as f64;
+            phase += frequency / RATE as f64;
+            samples.push((phase * std::f64::consts::TAU).sin() * 0.5);
+        }
+        samples.extend(vec![0.0; RATE as usize / 10]);
+
+        let data: Vec<u8> = samples
+            .iter()
+            .flat_map(|sample| (((sample + 0.1) * 32767.0) as i16).to_le_bytes())
+            .collect();
+        let mut wav = b"RIFF".to_vec();
+        wav.extend((36 + data.len() as u32).to_le_bytes());
+        wav.extend(b"WAVEfmt ");
+        wav.extend(16u32.to_le_bytes());
+        wav.extend(1u16.to_le_bytes());
+        wav.extend(1u16.to_le_bytes());
+        wav.extend(RATE.to_le_bytes());
+        wav.extend((RATE * 2).to_le_bytes());
+        wav.extend(2u16.to_le_bytes());
+        wav.extend(16u16.to_le_bytes());
+        wav.extend(b"data");
+        wav.extend((data.len() as u32).to_le_bytes());
+        wav.extend(data);
+        wav
+    }
+
+    fn recording() -> Vec<bool> {
+        let mut bits = vec![true; 200];
+        bits.extend(frame(0x5A));
+        bits
+    }
+
+    #[test]
+    fn test_demodulate() {
+        for baud in BAUD_RATES {
+            let tape = Tape::from_wav("test.wav", &wav(&recording(), baud), None).unwrap();
+            assert_eq!(tape.baud(), baud);
+            let Source::Wav { crossings, .. } = &tape.source else {
+                unreachable!()
+            };
+            let runs = demodulate(crossings, baud);
+            assert_eq!(runs.len(), 1);
+            assert!((runs[0].silence - 0.1).abs() < 0.001);
+            // with the 1s next to the silence, a half cycle short
+            let bits = &runs[0].bits;
+            assert_eq!(bits.len(), 211);
+            assert_eq!(bits[bits.len() - 11..], frame(0x5A).collect::<Vec<_>>());
+        }
+        assert!(Tape::from_wav("test.wav", b"RIFF", None).is_err());
+    }
+
+    #[test]
+    fn test_play() {
+        let mut tape = Tape::from_wav("test.wav", &wav(&recording(), 1200), None).unwrap();
+        let status = tape.status();
+        assert!((status.length - 0.2 - 211.0 / 1200.0).abs() < 0.01);
+        assert!(!status.motor);
+
+        // nothing moves while the motor is off
+        let silence = (0.1 * CPU_HZ) as u64;
+        assert!(!tape.level(silence * 2));
+        assert_eq!(tape.status().position, 0.0);
+
+        // the 1s of the header toggle the input every quarter of a bit
+        let start = silence * 2;
+        tape.set_motor(start, true);
+        let quarter = (CPU_HZ / 4800.0) as u64;
+        let first = tape.edges[0];
+        let levels: Vec<bool> = (0..4)
+            .map(|n| tape.level(start + first + quarter / 2 + n * quarter))
+            .collect();
+        assert_eq!(levels, [true, false, true, false]);
+
+        tape.set_baud(Some(2400));
+        assert_eq!(tape.baud(), 2400);
+        assert!(tape.status().position > 0.1);
+        tape.set_baud(None);
+        tape.rewind();
+        let status = tape.status().to_string();
+        assert!(status.starts_with("test.wav: 0:00.0 of 0:00.4 (0%), 1200 baud (detected), 211"));
+        assert!(status.ends_with(" bits, motor on"));
+    }
+
+    #[test]
+    fn test_cassette_input() {
+        let mut msx = Msx::new(&[
+            SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Empty,
+        ]);
+        let tape = Tape::from_wav("test.wav", &wav(&recording(), 1200), None).unwrap();
+        let first = tape.edges[0];
+        msx.insert_tape(tape);
+
+        let mut bus = msx.bus.write().unwrap();
+        bus.output(0xA0, 14);
+        // the motor is started by clearing bit 4 of port C
+        bus.output(0xAB, 0x08);
+        let mut levels = vec![bus.input(0xA2) & 0x80];
+        for t_states in [first + 100, CPU_HZ as u64 / 4800] {
+            bus.tick(t_states as u32);
+            levels.push(bus.input(0xA2) & 0x80);
+        }
+        assert_eq!(levels, [0x00, 0x80, 0x00]);
+    }
+}
@@ -0,0 +1,114 @@
+/// Character patterns decoded to one byte per pixel, 1 where the pattern bit
+/// is set. Renderers read whole rows from here instead of unpacking the
+/// pattern table from VRAM on every scanline.
+///
+/// VRAM writes mark the tile they land on as dirty and [`TileCache::update`]
+/// decodes only those, so a frame where the patterns didn't change costs
+/// nothing. The cache isn't saved, an empty cache is decoded in full.
+#[derive(Debug, Clone, Default)]
+pub struct TileCache {
+    // VRAM address of the pattern table the tiles were decoded from
+    base: usize,
+    tiles: Vec<[u8; 64]>,
+    dirty: Vec<bool>,
+    // tiles decoded by the last update
+    decoded: usize,
+}
+
+impl TileCache {
+    /// Marks the tile holding a VRAM address as changed.
+    pub fn invalidate(&mut self, address: usize) {
+        if let Some(offset) = address.checked_sub(self.base) {
+            if let Some(dirty) = self.dirty.get_mut(offset / 8) {
+                *dirty = true;
+            }
+        }
+    }
+
+    /// Drops all the tiles, e.g. after VRAM was replaced as a whole.
+    pub fn invalidate_all(&mut self) {
+        self.tiles.clear();
+        self.dirty.clear();
+    }
+
+    /// Decodes the changed tiles of the `count` patterns at `base`. Moving the
+    /// table decodes it again in full.
+    pub fn update(&mut self, vram: &[u8], base: usize, count: usize) {
+        if base != self.base || count != self.tiles.len() {
+            self.base = base;
+            self.tiles = vec![[0; 64]; count];
+            self.dirty = vec![true; count];
+        }
+
+        self.decoded = 0;
+        for (n, (tile, dirty)) in self.tiles.iter_mut().zip(&mut self.dirty).enumerate() {
+            if !*dirty {
+                continue;
+            }
+            for (row, pixels) in tile.chunks_exact_mut(8).enumerate() {
+                let pattern = vram.get(base + n * 8 + row).copied().unwrap_or(0);
+                for (bit, pixel) in pixels.iter_mut().enumerate() {
+                    *pixel = (pattern >> (7 - bit)) & 1;
+                }
+            }
+            *dirty = false;
+            self.decoded += 1;
+        }
+    }
+
+    /// The 8 pixels of a row of a tile, or blank pixels for tiles outside of
+    /// the table.
+    pub fn row(&self, tile: usize, row: usize) -> &[u8] {
+        match self.tiles.get(tile) {
+            Some(tile) => &tile[(row & 7) * 8..][..8],
+            None => &[0; 8],
+        }
+    }
+
+    /// Number of tiles decoded by the last update.
+    pub fn decoded(&self) -> usize {
+        self.decoded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::TMS9918;
+
+    fn write_vram(vdp: &mut TMS9918, address: u16, data: &[u8]) {
+        let [lo, hi] = address.to_le_bytes();
+        vdp.write(0x99, lo);
+        // bit 6 of the second byte sets the address for writing
+        vdp.write(0x99, hi | 0x40);
+        for byte in data {
+            vdp.write(0x98, *byte);
+        }
+    }
+
+    #[test]
+    fn test_decodes_changed_tiles() {
+        // Text1 patterns are at 0x0800
+        let mut vdp = TMS9918::new();
+        vdp.update_tiles();
+        assert_eq!(vdp.tiles().decoded(), 256);
+        vdp.update_tiles();
+        assert_eq!(vdp.tiles().decoded(), 0);
+
+        // second row of the pattern of 'A'
+        write_vram(&mut vdp, 0x0800 + 0x41 * 8 + 1, &[0b1010_0001]);
+        vdp.update_tiles();
+        assert_eq!(vdp.tiles().decoded(), 1);
+        assert_eq!(vdp.tiles().row(0x41, 1), &[1, 0, 1, 0, 0, 0, 0, 1]);
+        assert_eq!(vdp.tiles().row(0x41, 0), &[0; 8]);
+
+        // the name table isn't part of the cache
+        write_vram(&mut vdp, 0x0000, &[0x41; 40]);
+        vdp.update_tiles();
+        assert_eq!(vdp.tiles().decoded(), 0);
+
+        vdp.reset();
+        vdp.update_tiles();
+        assert_eq!(vdp.tiles().decoded(), 256);
+        assert_eq!(vdp.tiles().row(0x41, 1), &[0; 8]);
+    }
+}
@@ -0,0 +1,145 @@
+//! Quick loading: the BIOS routines reading the tape return at once with
+//! the bytes of the tape in the recorder, instead of timing its bits. The
+//! tape only moves through the reads, so turning it off in the middle of a
+//! file carries on from there at the real speed. The disk routines are to
+//! be handled the same way once disks are emulated.
+
+use crate::cpu::{Flag, Z80};
+
+/// BIOS entry reading the header tone of a block: carry set when none is
+/// found.
+pub const TAPION: u16 = 0x00E1;
+/// BIOS entry reading a byte into A: carry set on a read error.
+pub const TAPIN: u16 = 0x00E4;
+
+// T-states of the RET the routines end with, with the M1 wait state
+const RET_T_STATES: u32 = 11;
+
+/// Runs the BIOS routine the CPU is about to call, if it's one handled and
+/// the BIOS the machine was built with is in page 0, returning the T-states
+/// it took. `None` lets the CPU run the BIOS code, e.g. without a tape in
+/// the recorder.
+pub(crate) fn call(cpu: &mut Z80) -> Option<u32> {
+    if cpu.pc != TAPION && cpu.pc != TAPIN {
+        return None;
+    }
+    let bus_lock = cpu.bus.clone();
+    let mut bus = bus_lock.write().unwrap();
+    // a ROM in page 0 other than the BIOS may have anything at the entries;
+    // without expanded slots, the primary slot register alone selects it
+    let page_0 = bus.primary_slot_config() & 0x03;
+    if bus.bios_slot() != Some(page_0) {
+        return None;
+    }
+    let clock = bus.clock();
+    let tape = bus.tape.as_mut()?;
+    let read = match cpu.pc {
+        TAPION => tape.seek_block(clock),
+        _ => tape.read_byte(clock).map(|byte| cpu.a = byte).is_some(),
+    };
+    cpu.set_flag(Flag::C, !read);
+    cpu.pc = bus.read_word(cpu.sp);
+    cpu.sp = cpu.sp.wrapping_add(2);
+    Some(RET_T_STATES)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cas::Cas,
+        slot::{RamSlot, RomSlot, SlotType},
+        tape::Tape,
+        Msx,
+    };
+
+    #[test]
+    fn test_quick_load() {
+        let program = [
+            0x3E, 0xC0, // LD A,0xC0
+            0xD3, 0xA8, // OUT (0xA8),A: RAM in page 3
+            0x31, 0x00, 0xF0, // LD SP,0xF000
+            0xCD, 0xE1, 0x00, // CALL TAPION
+            0x38, 0xFE, // JR C,$
+            0xCD, 0xE4, 0x00, // CALL TAPIN
+            0x32, 0x00, 0xC0, // LD (0xC000),A
+            0xCD, 0xE1, 0x00, // CALL TAPION
+            0xCD, 0xE4, 0x00, // CALL TAPIN
+            0x32, 0x01, 0xC0, // LD (0xC001),A
+            0xCD, 0xE1, 0x00, // CALL TAPION, past the last block
+            0x9F, // SBC A,A
+            0x32, 0x02, 0xC0, // LD (0xC002),A
+            0x76, // HALT
+        ];
+        let mut rom = vec![0; 0x8000];
+        rom[..program.len()].copy_from_slice(&program);
+        // the routines never return when run, without anything to read
+        for entry in [TAPION, TAPIN] {
+            rom[entry as usize..entry as usize + 2].copy_from_slice(&[0x18, 0xFE]);
+        }
+        let mut msx = Msx::new(&[
+            SlotType::Rom(RomSlot::new(&rom, 0x0000, 0x8000)),
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+        ]);
+        let cas = Cas {
+            blocks: vec![[[0xD3; 10].as_slice(), b"HELLO"].concat(), vec![0x42, 0x43]],
+        };
+        msx.insert_tape(Tape::from_cas("test.cas", &cas.to_bytes(), None).unwrap());
+        msx.turbo_io = true;
+
+        for _ in 0..100 {
+            if msx.halted() {
+                break;
+            }
+            msx.step();
+        }
+        assert!(msx.halted());
+        assert_eq!(
+            [0xC000, 0xC001, 0xC002].map(|address| msx.cpu.read_byte(address)),
+            [0xD3, 0x42, 0xFF]
+        );
+    }
+
+    #[test]
+    fn test_quick_load_bios_only() {
+        let program = [
+            0x3E, 0xC1, // LD A,0xC1
+            0xD3, 0xA8, // OUT (0xA8),A: slot 1 in page 0, RAM in page 3
+            0x31, 0x00, 0xF0, // LD SP,0xF000
+            0xCD, 0xE1, 0x00, // CALL TAPION
+            0x9F, // SBC A,A
+            0x32, 0x00, 0xC0, // LD (0xC000),A
+            0x76, // HALT
+        ];
+        let mut bios = vec![0; 0x8000];
+        bios[..program.len()].copy_from_slice(&program);
+        bios[TAPION as usize..TAPION as usize + 2].copy_from_slice(&[0x18, 0xFE]);
+        // another ROM from 0x0000, the same up to its TAPION, returning no
+        // tone found
+        let mut other = bios.clone();
+        other[TAPION as usize..TAPION as usize + 2].copy_from_slice(&[0x37, 0xC9]);
+        let mut msx = Msx::new(&[
+            SlotType::Rom(RomSlot::new(&bios, 0x0000, 0x8000)),
+            SlotType::Rom(RomSlot::new(&other, 0x0000, 0x8000)),
+            SlotType::Empty,
+            SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+        ]);
+        let cas = Cas {
+            blocks: vec![[[0xD3; 10].as_slice(), b"HELLO"].concat()],
+        };
+        msx.insert_tape(Tape::from_cas("test.cas", &cas.to_bytes(), None).unwrap());
+        msx.turbo_io = true;
+
+        for _ in 0..100 {
+            if msx.halted() {
+                break;
+            }
+            msx.step();
+        }
+        // the routine of the ROM in page 0 ran, not the quick one
+        assert!(msx.halted());
+        assert_eq!(msx.cpu.read_byte(0xC000), 0xFF);
+    }
+}
@@ -0,0 +1,144 @@
+use crate::{
+    history::Registers,
+    instruction::{Instruction, MAX_INSTRUCTION_LEN},
+    internal_state::flag_string,
+    ProgramEntry, Z80,
+};
+
+/// Instructions disassembled from the PC in a [`UiSnapshot`].
+pub const PROGRAM_WINDOW: usize = 32;
+
+/// A primary slot in a [`UiSnapshot`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SlotSnapshot {
+    pub name: String,
+    /// bytes of ROM, RAM or device memory, 0 when empty
+    pub size: u32,
+    /// banks selected in the windows of a mapper, see
+    /// [`Slot::banks`](crate::slot::Slot::banks)
+    pub banks: Vec<u8>,
+}
+
+/// What a frontend shows of the machine on each frame, taken under a
+/// single lock of the bus. It owns its data, so it can be handed to another
+/// thread, and it's small enough to be taken on every frame, unlike a clone
+/// of the CPU and VDP.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UiSnapshot {
+    pub pc: u16,
+    pub registers: Registers,
+    /// the F register as `SZ5H3PNC`, with `-` for the flags reset
+    pub flags: String,
+    pub iff1: bool,
+    pub im: u8,
+    pub halted: bool,
+    /// disassembly of the instructions from the PC
+    pub program: Vec<ProgramEntry>,
+    pub vdp_registers: [u8; 8],
+    pub vdp_status: u8,
+    /// VBlanks the VDP went through, wrapping, which tells whether the
+    /// frontend's frame buffer must be drawn again
+    pub frame: u8,
+    /// contents of each primary slot
+    pub slots: [SlotSnapshot; 4],
+    /// the primary slot selected for each 16K page
+    pub pages: [u8; 4],
+}
+
+impl UiSnapshot {
+    pub fn new(cpu: &Z80) -> Self {
+        let bus = cpu.bus.read().unwrap();
+        let mut bytes = [0; PROGRAM_WINDOW * MAX_INSTRUCTION_LEN];
+        bus.read_block(cpu.pc, &mut bytes);
+
+        let mut program = Vec::with_capacity(PROGRAM_WINDOW);
+        let mut offset = 0;
+        while program.len() < PROGRAM_WINDOW {
+            let Some(pc) = cpu.pc.checked_add(offset as u16) else {
+                break;
+            };
+            let mut instruction_bytes = [0; MAX_INSTRUCTION_LEN];
+            instruction_bytes.copy_from_slice(&bytes[offset..offset + MAX_INSTRUCTION_LEN]);
+            let instr = Instruction::from_bytes(instruction_bytes, pc);
+            program.push(ProgramEntry {
+                address: pc,
+                instruction: instr.name(),
+                data: instr.opcode_with_args(),
+                dump: None,
+            });
+            offset += instr.len().max(1) as usize;
+        }
+
+        let slot_config = bus.primary_slot_config();
+        Self {
+            pc: cpu.pc,
+            registers: Registers::from_cpu(cpu),
+            flags: flag_string(cpu.f),
+            iff1: cpu.iff1,
+            im: cpu.im,
+            halted: cpu.halted,
+            program,
+            vdp_registers: bus.vdp.registers,
+            vdp_status: bus.vdp.status,
+            frame: bus.vdp.frame,
+            slots: [0, 1, 2, 3].map(|n| match bus.slot(n).and_then(|slot| slot.as_slot()) {
+                Some(slot) => SlotSnapshot {
+                    name: slot.name().to_string(),
+                    size: slot.size(),
+                    banks: slot.banks(),
+                },
+                None => SlotSnapshot {
+                    name: "Empty".to_string(),
+                    ..Default::default()
+                },
+            }),
+            pages: [0, 1, 2, 3].map(|page| (slot_config >> (page * 2)) & 0b11),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ram_cartridge::RamCartridge,
+        slot::{RamSlot, SlotType},
+        Msx,
+    };
+
+    #[test]
+    fn test_ui_snapshot() {
+        let mut msx = Msx::new(&[
+            SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+            RamCartridge::MegaRam(0x10000).slot(),
+            SlotType::Empty,
+            SlotType::Empty,
+        ]);
+        // LD A,0x42 / LD BC,0x1234 / NOP / HALT
+        for (i, byte) in [0x3E, 0x42, 0x01, 0x34, 0x12, 0x00, 0x76]
+            .iter()
+            .enumerate()
+        {
+            msx.cpu.write_byte(i as u16, *byte);
+        }
+        msx.step();
+        msx.bus.write().unwrap().vdp.registers[1] = 0x60;
+
+        let snapshot = msx.ui_snapshot();
+        assert_eq!(snapshot.pc, 0x0002);
+        assert_eq!(snapshot.registers.af >> 8, 0x42);
+        assert_eq!(snapshot.flags, flag_string(msx.cpu.f));
+        assert_eq!(snapshot.vdp_registers[1], 0x60);
+        let names: Vec<&str> = snapshot.slots.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["RAM", "MegaRAM", "Empty", "Empty"]);
+        assert_eq!(snapshot.slots[0].size, 0x10000);
+        assert_eq!(snapshot.slots[1].banks, [0, 1, 2, 3]);
+        assert!(snapshot.slots[0].banks.is_empty());
+        assert_eq!(snapshot.pages, [0; 4]);
+
+        // the same disassembly as the program listing
+        assert_eq!(snapshot.program.len(), PROGRAM_WINDOW);
+        assert_eq!(snapshot.program[..], msx.program()[..PROGRAM_WINDOW]);
+        assert_eq!(snapshot.program[0].instruction, "LD BC, #1234");
+    }
+}
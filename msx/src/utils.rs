@@ -6,7 +6,10 @@ pub fn hexdump(buffer: &[u8], start: u16, end: u16) -> String {
     while addr < end {
         let mut line = format!("{:04x}: ", addr);
         let mut chars = String::new();
-        for _ in 0..16 {
+        for n in 0..16 {
+            if n == 8 {
+                line.push(' ');
+            }
             if addr <= end {
                 let byte = buffer[addr as usize];
                 line.push_str(&format!("{:02x} ", byte));
@@ -21,7 +24,8 @@ pub fn hexdump(buffer: &[u8], start: u16, end: u16) -> String {
             }
         }
 
-        let dump_line = format!("{:>54} {}\n", line, chars);
+        // padded so the characters of a short last line stay in their column
+        let dump_line = format!("{:<55} {}\n", line, chars);
         str.push_str(&dump_line);
 
         if addr == 0 {
@@ -57,4 +61,15 @@ mod tests {
         println!("{:?}", compare_slices(&a, &b));
         println!("{:?}", compare_slices(&a, &c));
     }
+
+    #[test]
+    fn test_hexdump_columns() {
+        let buffer: Vec<u8> = (0..=0x17).collect();
+        let dump = hexdump(&buffer, 0, 0x17);
+        let lines: Vec<&str> = dump.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("0000: 00 01 02 03 04 05 06 07  08 09"));
+        assert!(lines[1].starts_with("0010: 10 11 12 13 14 15 16 17 "));
+        assert_eq!(lines[0].find("..."), lines[1].find("..."));
+    }
 }
@@ -1,9 +1,68 @@
 #![allow(dead_code)]
 
-use serde::{Deserialize, Serialize};
-use serde_big_array::BigArray;
+use derivative::Derivative;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use tracing::{error, info};
 
+use crate::{
+    renderer::Renderer,
+    tile_cache::TileCache,
+    vdp_timing::{self, DISPLAY_T_STATES, LINE_T_STATES},
+};
+
+// The VRAM and screen buffer live on the heap: moving them around by value
+// while (de)serializing a whole machine overflows the stack in debug builds
+// and on wasm.
+mod boxed_array {
+    use super::*;
+
+    pub fn serialize<S: Serializer, const N: usize>(
+        data: &[u8; N],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(data.iter())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, const N: usize>(
+        deserializer: D,
+    ) -> Result<Box<[u8; N]>, D::Error> {
+        let data = Vec::<u8>::deserialize(deserializer)?;
+        let len = data.len();
+        data.into_boxed_slice()
+            .try_into()
+            .map_err(|_| serde::de::Error::invalid_length(len, &format!("{} bytes", N).as_str()))
+    }
+}
+
+// how many times the beam entered the VBlank up to a T-state, with frames of
+// `frame` T-states
+fn vblanks_until(clock: u64, frame: u64) -> u64 {
+    (clock + frame - DISPLAY_T_STATES) / frame
+}
+
+/// Where the frames at the current frequency of the VDP started, which
+/// changes when it is switched between 50 and 60 Hz while running.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FrameOrigin {
+    /// T-state the first frame at the frequency started at
+    pub clock: u64,
+    /// frames drawn before, at the other frequencies
+    pub frames: u64,
+}
+
+impl FrameOrigin {
+    fn is_start(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Frames drawn from the start up to the T-state `clock`, each counted when
+/// the beam enters its VBlank, at 50 Hz if `pal` since `origin`.
+pub fn frames_until(clock: u64, pal: bool, origin: FrameOrigin) -> u64 {
+    let frame = vdp_timing::frame_t_states(pal);
+    origin.frames + vblanks_until(clock.saturating_sub(origin.clock), frame)
+}
+
 #[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
 pub struct Sprite {
     pub x: u8,
@@ -21,34 +80,78 @@ pub enum DisplayMode {
     Multicolor, // screen 3 - 256x192 16-color
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+impl DisplayMode {
+    pub const ALL: [DisplayMode; 4] = [
+        DisplayMode::Text1,
+        DisplayMode::Graphic1,
+        DisplayMode::Graphic2,
+        DisplayMode::Multicolor,
+    ];
+
+    /// Short name, as taken by the debugger.
+    pub fn name(&self) -> &'static str {
+        match self {
+            DisplayMode::Text1 => "text1",
+            DisplayMode::Graphic1 => "g1",
+            DisplayMode::Graphic2 => "g2",
+            DisplayMode::Multicolor => "mc",
+        }
+    }
+
+    pub fn find(name: &str) -> Option<DisplayMode> {
+        Self::ALL
+            .into_iter()
+            .find(|mode| mode.name().eq_ignore_ascii_case(name))
+    }
+}
+
+#[derive(Derivative, Clone, Serialize, Deserialize)]
+#[derivative(Debug, PartialEq)]
 pub struct TMS9918 {
-    #[serde(with = "BigArray")]
-    pub vram: [u8; 0x4000],
+    #[serde(with = "boxed_array")]
+    pub vram: Box<[u8; 0x4000]>,
     pub data_pre_read: u8, // read-ahead value
     pub registers: [u8; 8],
     pub status: u8,
     pub address: u16,
     pub first_write: Option<u8>,
-    #[serde(with = "BigArray")]
-    pub screen_buffer: [u8; 256 * 192],
+    /// color codes of the lines drawn by [`TMS9918::run_scanlines`]
+    #[serde(with = "boxed_array")]
+    pub screen_buffer: Box<[u8; 256 * 192]>,
     pub sprites: [Sprite; 8],
     pub frame: u8,
-    pub line: u8,
+    /// line the beam is on, 192 and up being the VBlank
+    pub line: u16,
     pub vblank: bool,
     pub display_mode: DisplayMode,
+    /// a TMS9929, drawing 50 frames a second instead of 60
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pal: bool,
+    /// where the frames at the frequency started, see [`TMS9918::set_pal`]
+    #[serde(default, skip_serializing_if = "FrameOrigin::is_start")]
+    pub origin: FrameOrigin,
+    /// T-state of the bus the beam position was last worked out at
+    clock: u64,
+    #[serde(skip)]
+    #[derivative(Debug = "ignore", PartialEq = "ignore")]
+    tiles: TileCache,
+    /// display mode used whatever the registers select, to tell whether a
+    /// blank screen has its data decoded in the wrong mode
+    #[serde(skip)]
+    #[derivative(PartialEq = "ignore")]
+    forced_mode: Option<DisplayMode>,
 }
 
 impl Default for TMS9918 {
     fn default() -> Self {
         Self {
-            vram: [0; 0x4000],
+            vram: Box::new([0; 0x4000]),
             data_pre_read: 0,
             registers: [0; 8],
             status: 0,
             address: 0,
             first_write: None,
-            screen_buffer: [0; 256 * 192],
+            screen_buffer: Box::new([0; 256 * 192]),
             sprites: [Sprite {
                 x: 0,
                 y: 0,
@@ -60,6 +163,11 @@ impl Default for TMS9918 {
             line: 0,
             vblank: false,
             display_mode: DisplayMode::Text1,
+            pal: false,
+            origin: FrameOrigin::default(),
+            clock: 0,
+            tiles: TileCache::default(),
+            forced_mode: None,
         }
     }
 }
@@ -69,14 +177,67 @@ impl TMS9918 {
         Self::default()
     }
 
+    /// A VDP with the VRAM starting with `vram` and the registers set, the
+    /// beam at the top of the display, to render a fixture without a
+    /// machine around it.
+    ///
+    /// # Panics
+    ///
+    /// If `vram` is larger than the 16K of VRAM.
+    pub fn from_fixture(vram: &[u8], registers: [u8; 8]) -> Self {
+        let mut vdp = Self::new();
+        vdp.vram[..vram.len()].copy_from_slice(vram);
+        vdp.tiles.invalidate_all();
+        // as written by a program, so that the display mode follows
+        for (register, value) in registers.into_iter().enumerate() {
+            vdp.write_register(0x80 | register as u8, value);
+        }
+        vdp.update_mode();
+        vdp
+    }
+
+    /// Moves the beam down `n` lines, drawing the lines of the display it
+    /// leaves into the screen buffer and setting the frame flag as it
+    /// enters the VBlank, like running the CPU for as long would. The VRAM
+    /// and registers stay as they are meanwhile.
+    pub fn run_scanlines(&mut self, n: u32) {
+        let mut lines = Vec::new();
+        for _ in 0..n {
+            if !self.vblank && !lines.contains(&self.line) {
+                lines.push(self.line);
+            }
+            self.sync(self.clock + LINE_T_STATES);
+        }
+        if lines.is_empty() {
+            return;
+        }
+
+        let mut renderer = Renderer::new(self);
+        for &line in &lines {
+            renderer.render_line(line as usize);
+        }
+        let drawn = renderer.screen_buffer;
+        for line in lines {
+            let pixels = line as usize * 256..(line as usize + 1) * 256;
+            self.screen_buffer[pixels.clone()].copy_from_slice(&drawn[pixels]);
+        }
+    }
+
+    /// The color codes of a line of the display drawn by
+    /// [`TMS9918::run_scanlines`].
+    pub fn scanline(&self, line: usize) -> &[u8] {
+        &self.screen_buffer[line * 256..(line + 1) * 256]
+    }
+
     pub fn reset(&mut self) {
-        self.vram = [0; 0x4000];
+        *self.vram = [0; 0x4000];
+        self.tiles.invalidate_all();
         self.data_pre_read = 0;
         self.registers = [0; 8];
         self.status = 0;
         self.address = 0;
         self.first_write = None;
-        self.screen_buffer = [0; 256 * 192];
+        *self.screen_buffer = [0; 256 * 192];
         self.sprites = [Sprite {
             x: 0,
             y: 0,
@@ -87,6 +248,88 @@ impl TMS9918 {
         self.frame = 0;
         self.line = 0;
         self.vblank = false;
+        self.clock = 0;
+        self.origin = FrameOrigin::default();
+    }
+
+    /// Moves the beam to the T-state `clock`, setting the frame flag of the
+    /// status register if it entered the VBlank since the last sync.
+    pub fn sync(&mut self, clock: u64) {
+        let frames = self.frames_until(clock) - self.frames_until(self.clock);
+        if frames > 0 {
+            self.status |= 0x80;
+            self.frame = self.frame.wrapping_add(frames as u8);
+        }
+        self.clock = clock;
+        let position = self.frame_position(clock);
+        self.line = (position / LINE_T_STATES) as u16;
+        self.vblank = position >= DISPLAY_T_STATES;
+    }
+
+    /// T-states from the start of the frame drawn at `clock`.
+    pub fn frame_position(&self, clock: u64) -> u64 {
+        clock.saturating_sub(self.origin.clock) % self.frame_t_states()
+    }
+
+    /// Frames drawn up to the T-state `clock`, see [`frames_until`].
+    pub fn frames_until(&self, clock: u64) -> u64 {
+        frames_until(clock, self.pal, self.origin)
+    }
+
+    /// T-state the first frame starting at `clock` or later starts at.
+    pub fn next_frame(&self, clock: u64) -> u64 {
+        let frame = self.frame_t_states();
+        let start = clock.max(self.origin.clock);
+        start + (frame - self.frame_position(start)) % frame
+    }
+
+    /// T-state at which the beam enters the next VBlank.
+    pub fn next_vblank(&self) -> u64 {
+        let frame = self.frame_t_states();
+        let vblank = self.clock - self.frame_position(self.clock) + DISPLAY_T_STATES;
+        match self.clock < vblank {
+            true => vblank,
+            false => vblank + frame,
+        }
+    }
+
+    /// T-state at which the beam last entered the VBlank, as of the last
+    /// sync.
+    pub fn last_vblank(&self) -> u64 {
+        let frame = self.frame_t_states();
+        let vblank = self.clock - self.frame_position(self.clock) + DISPLAY_T_STATES;
+        match self.clock >= vblank {
+            true => vblank,
+            false => vblank.saturating_sub(frame),
+        }
+    }
+
+    /// T-states of a frame, at 60 Hz or at 50 Hz on a TMS9929.
+    pub fn frame_t_states(&self) -> u64 {
+        vdp_timing::frame_t_states(self.pal)
+    }
+
+    /// Switches between 50 and 60 Hz, as of the last sync, the way setting
+    /// the NT bit of R#9 does on the V9938: the frame being drawn goes on
+    /// at the new length, and the frames drawn before keep their count.
+    pub fn set_pal(&mut self, pal: bool) {
+        if pal == self.pal {
+            return;
+        }
+        let position = self.frame_position(self.clock);
+        let start = self.clock - position;
+        self.origin = FrameOrigin {
+            clock: start,
+            frames: self.frames_until(start),
+        };
+        self.pal = pal;
+        self.sync(self.clock);
+    }
+
+    /// Whether the frame interrupt is raised: the frame flag is set and
+    /// enabled by bit 5 of R#1. Reading the status register clears it.
+    pub fn irq(&self) -> bool {
+        self.status & 0x80 != 0 && self.registers[1] & 0x20 != 0
     }
 
     pub fn name_table_base_and_size(&self) -> (usize, usize) {
@@ -123,6 +366,11 @@ impl TMS9918 {
 
     // Character Pattern Table Base Address = register 2 * 0x400
     pub fn char_pattern_table(&self) -> &[u8] {
+        let (base_address, size) = self.char_pattern_table_base_and_size();
+        &self.vram[base_address..(base_address + size)]
+    }
+
+    pub fn char_pattern_table_base_and_size(&self) -> (usize, usize) {
         let base_address = match self.display_mode {
             DisplayMode::Text1 => 0x0800,
             DisplayMode::Graphic1 => 0x0000,
@@ -137,7 +385,18 @@ impl TMS9918 {
             DisplayMode::Multicolor => 1536,
         };
 
-        &self.vram[base_address..(base_address + size)]
+        (base_address, size)
+    }
+
+    /// Decodes the character patterns written since the last call.
+    pub fn update_tiles(&mut self) {
+        let (base_address, size) = self.char_pattern_table_base_and_size();
+        self.tiles.update(&self.vram[..], base_address, size / 8);
+    }
+
+    /// Decoded character patterns, as of the last [`TMS9918::update_tiles`].
+    pub fn tiles(&self) -> &TileCache {
+        &self.tiles
     }
 
     pub fn color_table(&self) -> &[u8] {
@@ -190,6 +449,7 @@ impl TMS9918 {
         // }
 
         self.vram[self.address as usize] = data;
+        self.tiles.invalidate(self.address as usize);
         self.data_pre_read = data;
         self.address = (self.address + 1) & 0x3FFF;
         self.first_write = None;
@@ -211,12 +471,22 @@ impl TMS9918 {
         res
     }
 
-    fn update_mode(&mut self) {
-        // Get the Mx bits from registers R#0 and R#1
-        let mx_bits = ((self.registers[0] & 0x0E) >> 1) | ((self.registers[1] & 0x18) << 2);
+    /// Draws in a display mode whatever the registers select, or in the
+    /// one they select again with `None`. The registers are left as they
+    /// are.
+    pub fn force_mode(&mut self, mode: Option<DisplayMode>) {
+        self.forced_mode = mode;
+        self.update_mode();
+    }
 
-        // Determine the display mode based on the Mx bits
-        self.display_mode = match mx_bits {
+    pub fn forced_mode(&self) -> Option<&DisplayMode> {
+        self.forced_mode.as_ref()
+    }
+
+    /// The display mode selected by the Mx bits of R#0 and R#1.
+    pub fn register_mode(&self) -> DisplayMode {
+        let mx_bits = ((self.registers[0] & 0x0E) >> 1) | ((self.registers[1] & 0x18) << 2);
+        match mx_bits {
             0x00 => DisplayMode::Graphic1,
             0x01 => DisplayMode::Graphic2,
             0x08 => DisplayMode::Text1,
@@ -225,13 +495,16 @@ impl TMS9918 {
                 tracing::warn!("[VDP] Unsupported display mode: {:04b}", mx_bits);
                 DisplayMode::Text1 // Default to Text 1 for unsupported modes
             }
+        }
+    }
+
+    fn update_mode(&mut self) {
+        self.display_mode = match &self.forced_mode {
+            Some(mode) => mode.clone(),
+            None => self.register_mode(),
         };
 
-        tracing::info!(
-            "[VDP] Display mode is now: {:?} ({:04b})",
-            self.display_mode,
-            mx_bits
-        );
+        tracing::info!("[VDP] Display mode is now: {:?}", self.display_mode);
         // Update the VDP's state based on the new display mode
         // (e.g., update the layout, pattern, or color tables, or change the rendering method)
     }
@@ -249,7 +522,7 @@ impl TMS9918 {
         // Handle register-specific functionality
         match reg {
             0 => {
-                if modified & 0x10 == 0 {
+                if modified & 0x10 != 0 {
                     // Clear FH bit immediately when IE becomes 0? Not as per https://www.mail-archive.com/msx@stack.nl/msg13886.html
                     // We clear it only at the beginning of the next line if IE === 0
                     // Laydock2 has glitches on WebMSX with Turbo and also on a real Expert3 at 10MHz
@@ -260,7 +533,7 @@ impl TMS9918 {
                         latched_value, data
                     );
                 }
-                if modified & 0x0e == 0 {
+                if modified & 0x0e != 0 {
                     info!(
                         "[VDP] Updating mode... | Latched Value: 0x{:02X} | Data: 0x{:02X}",
                         latched_value, data
@@ -280,7 +553,7 @@ impl TMS9918 {
                     );
                     // TODO self.update_irq();
                 }
-                if modified & 0x40 == 0 {
+                if modified & 0x40 != 0 {
                     // BL
                     info!(
                         "[VDP] Disable frame interrupt | Latched Value: 0x{:02X} | Data: 0x{:02X}",
@@ -289,7 +562,7 @@ impl TMS9918 {
                     // IE1: Frame interrupt enable
                     // WebMSX blanking_change_pending = true
                 }
-                if modified & 0x18 == 0 {
+                if modified & 0x18 != 0 {
                     // Mx
                     info!(
                         "[VDP] Update mode | Latched Value: 0x{:02X} | Data: 0x{:02X}",
@@ -297,10 +570,10 @@ impl TMS9918 {
                     );
                     self.update_mode();
                 }
-                if modified & 0x04 == 0 { //CDR  (Undocumented, changes reg 13 timing to lines instead of frames)
+                if modified & 0x04 != 0 { //CDR  (Undocumented, changes reg 13 timing to lines instead of frames)
                      // TODO WebMSX updateBlinking();
                 }
-                if modified & 0x03 == 0 {
+                if modified & 0x03 != 0 {
                     info!(
                         "[VDP] Update sprites | Latched Value: 0x{:02X} | Data: 0x{:02X}",
                         latched_value, data
@@ -417,7 +690,7 @@ impl TMS9918 {
                 data,
                 data & 0x80
             );
-            if data & 0x80 == 0 {
+            if data & 0x80 != 0 {
                 info!(
                     "[VDP] Write Register: {:02X} <- Latched Value: {:02X}",
                     data, latched_value,
@@ -461,7 +734,7 @@ impl TMS9918 {
                 // }
 
                 // VRAM Address Pointer middle (A13-A8). Finish VRAM Address Pointer setting
-                self.address = (self.address & 0x7000)
+                self.address = (self.address & 0xC000)
                     | (((data & 0x3f) as u16) << 8)
                     | (latched_value as u16);
 
@@ -510,3 +783,107 @@ impl TMS9918 {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vdp_timing::{FRAME_T_STATES, PAL_FRAME_T_STATES};
+
+    // SCREEN 1 with the name table at 0x1800 and the patterns at 0x0000:
+    // character 1, a diagonal line, in the third column of the sixth row
+    fn graphic1() -> TMS9918 {
+        let mut vram = vec![0; 0x2000];
+        for row in 0..8 {
+            vram[8 + row] = 0x80 >> row;
+        }
+        vram[0x1800 + 5 * 32 + 2] = 1;
+        TMS9918::from_fixture(&vram, [0x00, 0x40, 0x06, 0x80, 0x00, 0x36, 0x07, 0x04])
+    }
+
+    #[test]
+    fn test_run_scanlines() {
+        let mut vdp = graphic1();
+        assert_eq!(vdp.display_mode, DisplayMode::Graphic1);
+
+        vdp.run_scanlines(43);
+        assert_eq!(vdp.line, 43);
+        // line 42 is the third of the sixth row of characters
+        let line = vdp.scanline(42);
+        assert_eq!(line[16..24], [4, 4, 15, 4, 4, 4, 4, 4]);
+        assert!(line[..16]
+            .iter()
+            .chain(&line[24..])
+            .all(|&pixel| pixel == 4));
+        // not drawn yet
+        assert!(vdp.scanline(43).iter().all(|&pixel| pixel == 0));
+        assert_eq!(vdp.status & 0x80, 0);
+    }
+
+    #[test]
+    fn test_run_frame() {
+        let mut vdp = graphic1();
+        let lines = (FRAME_T_STATES / LINE_T_STATES) as u32;
+        vdp.run_scanlines(192);
+        assert!(vdp.vblank);
+        assert_eq!((vdp.frame, vdp.status & 0x80), (1, 0x80));
+
+        // through the VBlank to the same line of the next frame
+        vdp.run_scanlines(lines);
+        assert_eq!((vdp.frame, vdp.line), (2, 192));
+        assert_eq!(vdp.scanline(47)[23], 15);
+    }
+
+    #[test]
+    fn test_set_pal() {
+        let mut vdp = TMS9918::new();
+        let start = 2 * FRAME_T_STATES;
+        vdp.sync(start + 100 * LINE_T_STATES);
+
+        // the frame goes on at 313 lines, the frames before still counted
+        vdp.set_pal(true);
+        assert_eq!((vdp.frames_until(vdp.clock), vdp.line), (2, 100));
+        assert_eq!(vdp.next_vblank(), start + DISPLAY_T_STATES);
+        assert_eq!(vdp.next_frame(vdp.clock), start + PAL_FRAME_T_STATES);
+        vdp.sync(start + PAL_FRAME_T_STATES);
+        assert_eq!((vdp.frames_until(vdp.clock), vdp.line), (3, 0));
+
+        // switched back past the last line of a frame at 60 Hz, the next
+        // frame starts right away, without a VBlank of its own
+        let start = start + PAL_FRAME_T_STATES;
+        vdp.sync(start + 280 * LINE_T_STATES);
+        vdp.set_pal(false);
+        assert_eq!((vdp.frames_until(vdp.clock), vdp.line), (4, 18));
+        assert_eq!(vdp.next_vblank(), start + FRAME_T_STATES + DISPLAY_T_STATES);
+        vdp.sync(vdp.next_vblank());
+        assert_eq!(vdp.frames_until(vdp.clock), 5);
+    }
+
+    #[test]
+    fn test_write_register() {
+        // the second byte with bit 7 set writes the register
+        let mut vdp = TMS9918::new();
+        vdp.write(0x99, 0xF4);
+        vdp.write(0x99, 0x87);
+        assert_eq!(vdp.registers[7], 0xF4);
+
+        // and with it clear, sets the VRAM address
+        vdp.write(0x99, 0x11);
+        vdp.write(0x99, 0x07);
+        assert_eq!(vdp.registers[7], 0xF4);
+    }
+
+    #[test]
+    fn test_set_address() {
+        let mut vdp = TMS9918::new();
+        vdp.write(0x99, 0xFF);
+        vdp.write(0x99, 0x7F);
+        assert_eq!(vdp.address, 0x3FFF);
+
+        // the second byte sets all of A13-A8, not keeping A14-A12
+        vdp.write(0x99, 0x34);
+        vdp.write(0x99, 0x42);
+        assert_eq!(vdp.address, 0x0234);
+        vdp.write(0x98, 0xAB);
+        assert_eq!(vdp.vram[0x0234], 0xAB);
+    }
+}
@@ -0,0 +1,249 @@
+use std::{collections::BTreeMap, fmt};
+
+use crate::{bus::Bus, Z80};
+
+/// T-states the TMS9918 needs between VRAM accesses while it draws the
+/// screen, on a 3.58 MHz Z80 with the M1 wait state of the MSX.
+pub const MIN_ACCESS_GAP: u64 = 29;
+
+/// T-states of a line drawn by the VDP, border and retrace included.
+pub const LINE_T_STATES: u64 = 228;
+
+/// T-states of a 60 Hz frame: 262 lines.
+pub const FRAME_T_STATES: u64 = 262 * LINE_T_STATES;
+
+/// T-states of a 50 Hz frame, drawn by the TMS9929 of European machines: 313
+/// lines.
+pub const PAL_FRAME_T_STATES: u64 = 313 * LINE_T_STATES;
+
+/// T-states of a frame, at 60 Hz or at 50 Hz on a TMS9929.
+pub fn frame_t_states(pal: bool) -> u64 {
+    match pal {
+        true => PAL_FRAME_T_STATES,
+        false => FRAME_T_STATES,
+    }
+}
+
+/// Frames drawn a second, 50 on a TMS9929 and 60 otherwise.
+pub fn frequency(pal: bool) -> u32 {
+    match pal {
+        true => 50,
+        false => 60,
+    }
+}
+
+/// T-states of a frame spent drawing the 192 lines of the display, VBlank
+/// being the rest.
+pub const DISPLAY_T_STATES: u64 = 192 * LINE_T_STATES;
+
+/// Two accesses to the VRAM, through port 0x98, closer than the VDP allows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VdpTimingViolation {
+    /// address of the instruction accessing the VRAM too soon
+    pub pc: u16,
+    /// address of the instruction of the access before
+    pub previous_pc: u16,
+    pub gap: u64,
+}
+
+impl fmt::Display for VdpTimingViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "VRAM access at {:#06X} {} T-states after the one at {:#06X}, the TMS9918 needs {} outside VBlank",
+            self.pc, self.gap, self.previous_pc, MIN_ACCESS_GAP
+        )
+    }
+}
+
+/// Times the VRAM accesses of the executed instructions by the clock of the
+/// bus and reports the ones too close to the previous one, which work here
+/// but show garbage on a real VDP.
+///
+/// Accesses are timed at the end of their instruction. They are only
+/// restricted with the display enabled, while the VDP draws the display part
+/// of its frame, at 50 or 60 Hz, see [`frame_t_states`].
+#[derive(Debug, Clone, Default)]
+pub struct VdpTimingChecker {
+    // clock of the bus when the checker started and after the last step
+    start: Option<u64>,
+    clock: u64,
+    // PC of the instruction being executed
+    pending: Option<u16>,
+    // clock and PC of the last VRAM access
+    last_access: Option<(u64, u16)>,
+    // violations by the PC of the instruction
+    violations: BTreeMap<u16, u64>,
+}
+
+impl VdpTimingChecker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// T-states run since the checker started.
+    pub fn clock(&self) -> u64 {
+        self.clock - self.start.unwrap_or(self.clock)
+    }
+
+    /// Must be called before the CPU executes an instruction.
+    pub fn before_step(&mut self, cpu: &Z80) {
+        self.start
+            .get_or_insert_with(|| cpu.bus.read().unwrap().clock());
+        self.pending = Some(cpu.pc);
+    }
+
+    /// Must be called after the CPU executed an instruction, with the bus
+    /// telling whether it accessed the VRAM. Returns the first violation of
+    /// each instruction, the others being only counted.
+    pub fn after_step(&mut self, bus: &mut Bus) -> Option<VdpTimingViolation> {
+        let pc = self.pending.take()?;
+        self.clock = bus.clock();
+        if !bus.accessed_vram() {
+            return None;
+        }
+
+        let (previous_clock, previous_pc) = self.last_access.replace((self.clock, pc))?;
+        let gap = self.clock - previous_clock;
+        let display_enabled = bus.vdp.registers[1] & 0x40 != 0;
+        let in_display = bus.vdp.frame_position(self.clock) < DISPLAY_T_STATES;
+        if gap >= MIN_ACCESS_GAP || !display_enabled || !in_display {
+            return None;
+        }
+
+        let count = self.violations.entry(pc).or_default();
+        *count += 1;
+        (*count == 1).then_some(VdpTimingViolation {
+            pc,
+            previous_pc,
+            gap,
+        })
+    }
+
+    /// The addresses of the instructions that accessed the VRAM too soon,
+    /// with how many times they did.
+    pub fn violations(&self) -> impl Iterator<Item = (u16, u64)> + '_ {
+        self.violations.iter().map(|(pc, count)| (*pc, *count))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, RwLock};
+
+    use super::*;
+    use crate::{
+        slot::{RamSlot, SlotType},
+        t_states::timing,
+    };
+
+    // a bus with the display enabled, set up further by `setup`
+    fn run_on(
+        setup: impl FnOnce(&mut Bus),
+        program: &[u8],
+        steps: usize,
+    ) -> (Vec<VdpTimingViolation>, VdpTimingChecker) {
+        let mut bus = Bus::new(&[
+            SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Empty,
+        ]);
+        bus.vdp.registers[1] |= 0x40;
+        setup(&mut bus);
+        let mut cpu = Z80::new(Arc::new(RwLock::new(bus)));
+        for (i, byte) in program.iter().enumerate() {
+            cpu.write_byte(i as u16, *byte);
+        }
+
+        let mut checker = VdpTimingChecker::new();
+        let mut violations = Vec::new();
+        for _ in 0..steps {
+            checker.before_step(&cpu);
+            let (pc, timing) = (cpu.pc, timing(&cpu));
+            cpu.execute_cycle();
+            let mut bus = cpu.bus.write().unwrap();
+            bus.tick(timing.taken(pc, &cpu));
+            violations.extend(checker.after_step(&mut bus));
+        }
+        (violations, checker)
+    }
+
+    fn run(program: &[u8], steps: usize) -> (Vec<VdpTimingViolation>, VdpTimingChecker) {
+        run_on(|_| {}, program, steps)
+    }
+
+    #[test]
+    fn test_back_to_back() {
+        // LD HL,0x0100 / LD BC,0x0398 / OUTI / OUTI / OUTI, 18 T-states apart
+        let program = [
+            0x21, 0x00, 0x01, 0x01, 0x98, 0x03, 0xED, 0xA3, 0xED, 0xA3, 0xED, 0xA3,
+        ];
+        let (violations, checker) = run(&program, 5);
+        assert_eq!(
+            violations,
+            vec![
+                VdpTimingViolation {
+                    pc: 0x0008,
+                    previous_pc: 0x0006,
+                    gap: 18,
+                },
+                VdpTimingViolation {
+                    pc: 0x000A,
+                    previous_pc: 0x0008,
+                    gap: 18,
+                }
+            ]
+        );
+        assert_eq!(
+            checker.violations().collect::<Vec<_>>(),
+            vec![(0x0008, 1), (0x000A, 1)]
+        );
+
+        // OUT (0x98),A twice in a row
+        let (violations, _) = run(&[0xD3, 0x98, 0xD3, 0x98], 2);
+        assert_eq!(
+            violations[0].to_string(),
+            "VRAM access at 0x0002 12 T-states after the one at 0x0000, the TMS9918 needs 29 outside VBlank"
+        );
+    }
+
+    #[test]
+    fn test_outi_loop() {
+        // LD HL,0x0100 / LD BC,0x0898 / loop: OUTI / JP loop, 29 T-states per
+        // byte
+        let program = [
+            0x21, 0x00, 0x01, 0x01, 0x98, 0x08, 0xED, 0xA3, 0xC3, 0x06, 0x00,
+        ];
+        let (violations, checker) = run(&program, 18);
+        assert_eq!(violations, vec![]);
+        assert_eq!(checker.clock(), 11 + 11 + 8 * 29);
+    }
+
+    #[test]
+    fn test_frame_position() {
+        // OUT (0x98),A twice in a row
+        let program = [0xD3, 0x98, 0xD3, 0x98];
+
+        // at the start of a 60 Hz frame
+        let (violations, _) = run_on(|bus| _ = bus.tick(FRAME_T_STATES as u32), &program, 2);
+        assert_eq!(violations.len(), 1);
+
+        // in the VBlank of a 50 Hz frame
+        let (violations, _) = run_on(
+            |bus| {
+                bus.set_pal(true);
+                bus.tick(FRAME_T_STATES as u32);
+            },
+            &program,
+            2,
+        );
+        assert_eq!(violations, vec![]);
+
+        // the checker started in a VBlank, mid-frame
+        let (violations, checker) =
+            run_on(|bus| _ = bus.tick(DISPLAY_T_STATES as u32), &program, 2);
+        assert_eq!(violations, vec![]);
+        assert_eq!(checker.clock(), 2 * 12);
+    }
+}
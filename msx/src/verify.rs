@@ -0,0 +1,692 @@
+//! The accuracy suite to run before submitting CPU and VDP changes, all of
+//! it with `rustmsx verify`: the ZEXDOC instruction exerciser, the JSON
+//! single-step tests of every opcode, the ROM pack of recorded machine states
+//! and a BIOS boot. Each suite gives a [`Score`].
+
+use std::{
+    env, fs,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Context};
+use serde::Deserialize;
+
+use crate::{
+    slot::{RamSlot, RomSlot, SlotType},
+    vdp_timing::FRAME_T_STATES,
+    Msx, StopReason,
+};
+
+/// ZEXDOC, the exerciser of the documented flags by Frank Cringle, as a CP/M
+/// program.
+pub const ZEXDOC: &[u8] = include_bytes!("../tests/fixtures/zexdoc.com");
+
+/// Instructions ZEXDOC runs, with room to spare, past which it is stuck.
+const ZEXDOC_STEPS: u64 = 10_000_000_000;
+
+/// The ROM pack of `tests/fixtures`, see [`RomPack`].
+pub const ROM_PACK: &str = include_str!("../tests/fixtures/rom_pack.json");
+
+/// Frames to run a BIOS, 10 seconds at 60Hz, which is enough for the memory
+/// test of a real BIOS.
+pub const BIOS_BOOT_FRAMES: u32 = 600;
+
+/// How a suite did.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Score {
+    pub passed: usize,
+    pub total: usize,
+    /// what failed, a line each
+    pub failures: Vec<String>,
+    /// what didn't run and why, e.g. the test files weren't found
+    pub skipped: Option<String>,
+}
+
+impl Score {
+    /// A suite that didn't run at all.
+    pub fn skipped(reason: impl Into<String>) -> Self {
+        Self {
+            skipped: Some(reason.into()),
+            ..Self::default()
+        }
+    }
+
+    /// Whether nothing failed, skipped suites included.
+    pub fn is_ok(&self) -> bool {
+        self.failures.is_empty()
+    }
+
+    fn pass(&mut self) {
+        self.passed += 1;
+        self.total += 1;
+    }
+
+    fn fail(&mut self, failure: String) {
+        self.total += 1;
+        self.failures.push(failure);
+    }
+}
+
+impl From<anyhow::Result<()>> for Score {
+    fn from(result: anyhow::Result<()>) -> Self {
+        let mut score = Score::default();
+        match result {
+            Ok(()) => score.pass(),
+            Err(e) => score.fail(format!("{:#}", e)),
+        }
+        score
+    }
+}
+
+// runs a suite, taking a panic of the emulator, e.g. on an opcode it doesn't
+// know, as an error
+fn guarded<T>(run: impl FnOnce() -> anyhow::Result<T>) -> anyhow::Result<T> {
+    panic::catch_unwind(AssertUnwindSafe(run)).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<String>()
+            .map(String::as_str)
+            .or_else(|| payload.downcast_ref::<&str>().copied())
+            .unwrap_or("unknown panic");
+        Err(anyhow!("panicked: {}", message))
+    })
+}
+
+// a Z80 with 64K of RAM and nothing else, in slot 0 as after a reset
+fn ram_machine() -> Msx {
+    Msx::new(&[
+        SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+        SlotType::Empty,
+        SlotType::Empty,
+        SlotType::Empty,
+    ])
+}
+
+/// Runs a CP/M program like ZEXDOC on a Z80 with 64K of RAM, from 0x0100
+/// until it returns to CP/M at 0x0000, passing what it prints to `output`.
+/// Only the BDOS calls printing a character (2) and a string (9) are there,
+/// which is all the exercisers use.
+pub fn run_cpm(program: &[u8], max_steps: u64, mut output: impl FnMut(&str)) -> anyhow::Result<()> {
+    let mut msx = ram_machine();
+    for (address, byte) in (0x0100..).zip(program) {
+        msx.set_memory(address, *byte);
+    }
+    // a RET at the BDOS entry, the call being taken before it runs, followed
+    // by the top of the memory, which programs read from the JP there
+    msx.set_memory(0x0005, 0xC9);
+    msx.set_memory(0x0006, 0x00);
+    msx.set_memory(0x0007, 0xF0);
+    msx.cpu.pc = 0x0100;
+    msx.cpu.sp = 0xF000;
+
+    for _ in 0..max_steps {
+        match msx.pc() {
+            0x0000 => return Ok(()),
+            0x0005 => output(&bdos_output(&msx)?),
+            _ => {}
+        }
+        msx.step();
+        if msx.halted() {
+            bail!("halted at {:#06X}", msx.pc());
+        }
+    }
+    bail!("still running after {} instructions", max_steps)
+}
+
+// what a BDOS console call prints
+fn bdos_output(msx: &Msx) -> anyhow::Result<String> {
+    let cpu = &msx.cpu;
+    match cpu.c {
+        0x02 => Ok((cpu.e as char).to_string()),
+        0x09 => {
+            let mut address = u16::from_be_bytes([cpu.d, cpu.e]);
+            let mut text = String::new();
+            loop {
+                match msx.get_memory(address) {
+                    b'$' => return Ok(text),
+                    ch => text.push(ch as char),
+                }
+                address = address.wrapping_add(1);
+            }
+        }
+        function => bail!("unsupported BDOS call {:#04X}", function),
+    }
+}
+
+/// Runs [`ZEXDOC`], scoring the test of each group of instructions as it
+/// prints it, and passing what it prints to `progress`. It runs billions of
+/// instructions, tens of minutes in a release build.
+pub fn zexdoc(mut progress: impl FnMut(&str)) -> Score {
+    let mut score = Score::default();
+    let mut line = String::new();
+    let result = guarded(|| {
+        run_cpm(ZEXDOC, ZEXDOC_STEPS, |text| {
+            progress(text);
+            for ch in text.chars() {
+                match ch {
+                    '\n' => {
+                        score_zexdoc_line(&mut score, line.trim());
+                        line.clear();
+                    }
+                    '\r' => {}
+                    ch => line.push(ch),
+                }
+            }
+        })
+    });
+    if let Err(e) = result {
+        score.fail(format!("{:#}", e));
+    }
+    score
+}
+
+// "<group>....  OK" or "<group>....  ERROR **** crc expected:... found:..."
+fn score_zexdoc_line(score: &mut Score, line: &str) {
+    if line.ends_with("OK") {
+        score.pass();
+    } else if line.contains("ERROR") {
+        score.fail(line.to_string());
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct SingleStepTest {
+    name: String,
+    initial: SingleStepState,
+    #[serde(rename = "final")]
+    expected: SingleStepState,
+}
+
+// what the tests set before and check after; the internal registers (R, WZ,
+// Q) aren't emulated
+#[derive(Debug, Deserialize)]
+struct SingleStepState {
+    pc: u16,
+    sp: u16,
+    a: u8,
+    f: u8,
+    b: u8,
+    c: u8,
+    d: u8,
+    e: u8,
+    h: u8,
+    l: u8,
+    ix: u16,
+    iy: u16,
+    af_: u16,
+    bc_: u16,
+    de_: u16,
+    hl_: u16,
+    im: u8,
+    iff1: u8,
+    iff2: u8,
+    ram: Vec<(u16, u8)>,
+}
+
+impl SingleStepState {
+    fn set(&self, msx: &mut Msx) {
+        let cpu = &mut msx.cpu;
+        cpu.pc = self.pc;
+        cpu.sp = self.sp;
+        (cpu.a, cpu.f) = (self.a, self.f);
+        (cpu.b, cpu.c) = (self.b, self.c);
+        (cpu.d, cpu.e) = (self.d, self.e);
+        (cpu.h, cpu.l) = (self.h, self.l);
+        cpu.ix = self.ix;
+        cpu.iy = self.iy;
+        [cpu.a_alt, cpu.f_alt] = self.af_.to_be_bytes();
+        [cpu.b_alt, cpu.c_alt] = self.bc_.to_be_bytes();
+        [cpu.d_alt, cpu.e_alt] = self.de_.to_be_bytes();
+        [cpu.h_alt, cpu.l_alt] = self.hl_.to_be_bytes();
+        cpu.im = self.im;
+        cpu.iff1 = self.iff1 != 0;
+        cpu.iff2 = self.iff2 != 0;
+        cpu.halted = false;
+        for &(address, value) in &self.ram {
+            msx.set_memory(address, value);
+        }
+    }
+
+    // what differs in the machine, as "<register> <actual>, expected <value>"
+    fn mismatches(&self, msx: &Msx) -> Vec<String> {
+        let cpu = &msx.cpu;
+        let pair = |high: u8, low: u8| u16::from_be_bytes([high, low]);
+        let registers = [
+            ("PC", cpu.pc, self.pc),
+            ("SP", cpu.sp, self.sp),
+            ("A", cpu.a as u16, self.a as u16),
+            ("F", cpu.f as u16, self.f as u16),
+            ("BC", pair(cpu.b, cpu.c), pair(self.b, self.c)),
+            ("DE", pair(cpu.d, cpu.e), pair(self.d, self.e)),
+            ("HL", pair(cpu.h, cpu.l), pair(self.h, self.l)),
+            ("IX", cpu.ix, self.ix),
+            ("IY", cpu.iy, self.iy),
+            ("AF'", pair(cpu.a_alt, cpu.f_alt), self.af_),
+            ("BC'", pair(cpu.b_alt, cpu.c_alt), self.bc_),
+            ("DE'", pair(cpu.d_alt, cpu.e_alt), self.de_),
+            ("HL'", pair(cpu.h_alt, cpu.l_alt), self.hl_),
+            ("IM", cpu.im as u16, self.im as u16),
+            ("IFF1", cpu.iff1 as u16, self.iff1 as u16),
+            ("IFF2", cpu.iff2 as u16, self.iff2 as u16),
+        ];
+        let memory = self.ram.iter().map(|&(address, value)| {
+            let name = format!("({:04X})", address);
+            (name, msx.get_memory(address) as u16, value as u16)
+        });
+        registers
+            .into_iter()
+            .map(|(name, actual, expected)| (name.to_string(), actual, expected))
+            .chain(memory)
+            .filter(|(_, actual, expected)| actual != expected)
+            .map(|(name, actual, expected)| {
+                format!("{} {:X}, expected {:X}", name, actual, expected)
+            })
+            .collect()
+    }
+}
+
+/// Runs the JSON single-step tests of a directory, a file of tests per
+/// opcode like `ed 4a.json`, as published by SingleStepTests
+/// (<https://github.com/SingleStepTests/z80>). The I/O and the cycles of the
+/// tests aren't checked.
+pub fn single_step(dir: &Path) -> anyhow::Result<Score> {
+    let mut files = fs::read_dir(dir)
+        .with_context(|| format!("reading {}", dir.display()))?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<anyhow::Result<Vec<PathBuf>>>()?;
+    files.retain(|path| {
+        path.extension()
+            .is_some_and(|extension| extension == "json")
+    });
+    files.sort();
+    if files.is_empty() {
+        return Ok(Score::skipped(format!(
+            "no JSON tests in {}",
+            dir.display()
+        )));
+    }
+
+    let mut score = Score::default();
+    for path in files {
+        let json = fs::read_to_string(&path)?;
+        let name = path.file_stem().unwrap_or_default().to_string_lossy();
+        single_step_file(&name, &json, &mut score)
+            .with_context(|| format!("reading {}", path.display()))?;
+    }
+    Ok(score)
+}
+
+/// Runs the tests of a file of single-step tests, see [`single_step`],
+/// adding a failure to the score for the file when any of them fails.
+pub fn single_step_file(name: &str, json: &str, score: &mut Score) -> anyhow::Result<()> {
+    let tests: Vec<SingleStepTest> = serde_json::from_str(json)?;
+    let mut msx = ram_machine();
+    let mut passed = 0;
+    let mut first_failure = None;
+    for test in &tests {
+        let result = guarded(|| {
+            test.initial.set(&mut msx);
+            msx.step();
+            Ok(test.expected.mismatches(&msx))
+        });
+        match result {
+            Ok(mismatches) if mismatches.is_empty() => passed += 1,
+            Ok(mismatches) => {
+                first_failure.get_or_insert(format!("{}: {}", test.name, mismatches.join(", ")));
+            }
+            Err(e) => {
+                // the emulator can't run the opcode, and won't do better in
+                // the other tests of the file
+                first_failure.get_or_insert(format!("{}: {:#}", test.name, e));
+                break;
+            }
+        }
+
+        // a test writing to the PPI may have switched the RAM out
+        if msx.primary_slot_config() != 0 {
+            msx = ram_machine();
+        }
+    }
+
+    score.passed += passed;
+    score.total += tests.len();
+    if let Some(first_failure) = first_failure {
+        score.failures.push(format!(
+            "{}: {}/{} failed, first {}",
+            name,
+            tests.len() - passed,
+            tests.len(),
+            first_failure
+        ));
+    }
+    Ok(())
+}
+
+/// Machine states recorded after running known ROMs, from [`ROM_PACK`],
+/// compared with the states they reach again. Most of the ROMs are not
+/// redistributable, so they are looked for in a directory given to
+/// [`RomPack::run`] and skipped when missing.
+#[derive(Debug, Deserialize)]
+pub struct RomPack {
+    pub cases: Vec<RomPackCase>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RomPackCase {
+    pub name: String,
+    pub slots: Vec<SlotDefinition>,
+    pub steps: u64,
+    pub vram_crc32: Option<String>,
+    pub ram_crc32: Option<String>,
+    pub primary_slot_config: Option<String>,
+    pub vdp_registers: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum SlotDefinition {
+    Empty,
+    Ram {
+        base: String,
+        size: String,
+    },
+    Rom {
+        file: String,
+        base: String,
+        size: String,
+    },
+}
+
+/// The state a case of the ROM pack reached, formatted as recorded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RomPackResult {
+    pub vram_crc32: String,
+    pub ram_crc32: String,
+    pub primary_slot_config: String,
+    pub vdp_registers: Vec<String>,
+}
+
+fn parse_hex(s: &str) -> anyhow::Result<u32> {
+    let digits = s.strip_prefix("0x").unwrap_or(s);
+    Ok(u32::from_str_radix(digits, 16)?)
+}
+
+fn crc32(data: &[u8]) -> String {
+    format!("{:08X}", crc32fast::hash(data))
+}
+
+/// Finds a ROM of the pack first in `roms` and then relative to the
+/// workspace root, where the bundled C-BIOS images live.
+fn find_rom(roms: Option<&Path>, file: &str) -> Option<PathBuf> {
+    let workspace = Path::new(env!("CARGO_MANIFEST_DIR")).join("..");
+    let rom = roms
+        .into_iter()
+        .chain([workspace.as_path()])
+        .map(|dir| dir.join(file))
+        .find(|path| path.exists());
+    rom
+}
+
+impl RomPack {
+    /// The pack of [`ROM_PACK`].
+    pub fn bundled() -> anyhow::Result<Self> {
+        Ok(serde_json::from_str(ROM_PACK)?)
+    }
+
+    /// Runs every case whose ROMs are found, in `roms` or in the workspace.
+    pub fn run(&self, roms: Option<&Path>) -> Score {
+        let mut score = Score::default();
+        let mut missing = Vec::new();
+        for case in &self.cases {
+            match guarded(|| case.run(roms)) {
+                Ok(Some(result)) => {
+                    let mismatches = case.mismatches(&result);
+                    if mismatches.is_empty() {
+                        score.pass();
+                    } else {
+                        score.fail(format!("[{}] {}", case.name, mismatches.join(", ")));
+                    }
+                }
+                Ok(None) => missing.push(case.name.as_str()),
+                Err(e) => score.fail(format!("[{}] {:#}", case.name, e)),
+            }
+        }
+        if !missing.is_empty() {
+            score.skipped = Some(format!("ROMs not found for {}", missing.join(", ")));
+        }
+        score
+    }
+}
+
+impl RomPackCase {
+    /// Runs the case, or `None` when one of its ROMs isn't found.
+    pub fn run(&self, roms: Option<&Path>) -> anyhow::Result<Option<RomPackResult>> {
+        let Some(slots) = self.slots(roms)? else {
+            return Ok(None);
+        };
+
+        let mut msx = Msx::new(&slots);
+        for _ in 0..self.steps {
+            msx.step();
+            if msx.halted() {
+                break;
+            }
+        }
+
+        let vdp = msx.vdp();
+        Ok(Some(RomPackResult {
+            vram_crc32: crc32(&msx.vram()),
+            ram_crc32: crc32(&msx.memory()),
+            primary_slot_config: format!("0x{:02X}", msx.primary_slot_config()),
+            vdp_registers: vdp
+                .registers
+                .iter()
+                .map(|r| format!("0x{:02X}", r))
+                .collect(),
+        }))
+    }
+
+    fn slots(&self, roms: Option<&Path>) -> anyhow::Result<Option<Vec<SlotType>>> {
+        if self.slots.len() != 4 {
+            bail!("expected 4 slots, got {}", self.slots.len());
+        }
+
+        let mut slots = Vec::new();
+        for slot in &self.slots {
+            let slot = match slot {
+                SlotDefinition::Empty => SlotType::Empty,
+                SlotDefinition::Ram { base, size } => {
+                    SlotType::Ram(RamSlot::new(parse_hex(base)? as u16, parse_hex(size)?))
+                }
+                SlotDefinition::Rom { file, base, size } => {
+                    let Some(path) = find_rom(roms, file) else {
+                        return Ok(None);
+                    };
+                    SlotType::Rom(RomSlot::load(
+                        path,
+                        parse_hex(base)? as u16,
+                        parse_hex(size)?,
+                    )?)
+                }
+            };
+            slots.push(slot);
+        }
+
+        Ok(Some(slots))
+    }
+
+    /// What differs from the recorded state, leaving out what wasn't
+    /// recorded.
+    pub fn mismatches(&self, result: &RomPackResult) -> Vec<String> {
+        let mut mismatches = Vec::new();
+        check(
+            &mut mismatches,
+            "VRAM CRC32",
+            &self.vram_crc32,
+            &result.vram_crc32,
+        );
+        check(
+            &mut mismatches,
+            "RAM CRC32",
+            &self.ram_crc32,
+            &result.ram_crc32,
+        );
+        check(
+            &mut mismatches,
+            "primary slot config",
+            &self.primary_slot_config,
+            &result.primary_slot_config,
+        );
+        check(
+            &mut mismatches,
+            "VDP registers",
+            &self.vdp_registers,
+            &result.vdp_registers,
+        );
+        mismatches
+    }
+}
+
+fn check<T: PartialEq + std::fmt::Debug>(
+    mismatches: &mut Vec<String>,
+    what: &str,
+    expected: &Option<T>,
+    actual: &T,
+) {
+    if let Some(expected) = expected {
+        if expected != actual {
+            mismatches.push(format!(
+                "{} mismatch: expected {:?}, got {:?}",
+                what, expected, actual
+            ));
+        }
+    }
+}
+
+/// Boots a BIOS in slot 0, with RAM in slot 3, and looks for `text` on the
+/// screen after `frames` frames, which only shows when the CPU, the VDP, the
+/// PPI and the slots all do their part. C-BIOS shows its version banner, a
+/// real BIOS the "Ok" of the BASIC prompt.
+pub fn bios_boot(bios: &[u8], text: &str, frames: u32) -> anyhow::Result<()> {
+    guarded(|| {
+        let mut msx = Msx::new(&[
+            SlotType::Rom(RomSlot::new(bios, 0x0000, 0x10000)),
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+        ]);
+        for _ in 0..frames {
+            if msx.run_cycles(FRAME_T_STATES as u32).reason == StopReason::Halted {
+                bail!("halted at {:#06X}", msx.pc());
+            }
+        }
+
+        // the text screen as the name table has it
+        let screen = msx.screen_text();
+        if !screen.iter().any(|line| line.contains(text)) {
+            bail!(
+                "{:?} isn't on the screen after {} frames:\n{}",
+                text,
+                frames,
+                screen.join("\n")
+            );
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_cpm() {
+        // LD C,9 / LD DE,0x0120 / CALL 5 / LD C,2 / LD E,'!' / CALL 5 / JP 0
+        let mut program = vec![
+            0x0E, 0x09, 0x11, 0x20, 0x01, 0xCD, 0x05, 0x00, 0x0E, 0x02, 0x1E, b'!', 0xCD, 0x05,
+            0x00, 0xC3, 0x00, 0x00,
+        ];
+        program.resize(0x20, 0);
+        program.extend(b"Hello$");
+
+        let mut output = String::new();
+        run_cpm(&program, 1000, |text| output.push_str(text)).unwrap();
+        assert_eq!(output, "Hello!");
+
+        // JR $
+        let error = run_cpm(&[0x18, 0xFE], 1000, |_| {}).unwrap_err();
+        assert_eq!(error.to_string(), "still running after 1000 instructions");
+    }
+
+    #[test]
+    fn test_score_zexdoc_line() {
+        let mut score = Score::default();
+        score_zexdoc_line(&mut score, "Z80doc instruction exerciser");
+        score_zexdoc_line(&mut score, "add hl,<bc,de,hl,sp>..........  OK");
+        score_zexdoc_line(
+            &mut score,
+            "aluop a,nn....................  ERROR **** crc expected:48799360 found:12345678",
+        );
+        assert_eq!((score.passed, score.total), (1, 2));
+        assert!(score.failures[0].starts_with("aluop a,nn"));
+    }
+
+    // a single-step test of INC A, from A to A + 1 with the flags of `f`
+    fn inc_a(a: u8, f: u8) -> String {
+        let state = |pc: u16, a: u8, f: u8| {
+            format!(
+                r#"{{"pc": {pc}, "sp": 0, "a": {a}, "f": {f}, "b": 0, "c": 0, "d": 0, "e": 0,
+                "h": 0, "l": 0, "i": 0, "r": 0, "ix": 0, "iy": 0, "af_": 0, "bc_": 0,
+                "de_": 0, "hl_": 0, "im": 1, "iff1": 0, "iff2": 0, "ram": [[4096, 60]]}}"#
+            )
+        };
+        format!(
+            r#"{{"name": "3c {a:02x}", "initial": {}, "final": {}, "cycles": []}}"#,
+            state(4096, a, 0),
+            state(4097, a.wrapping_add(1), f)
+        )
+    }
+
+    #[test]
+    fn test_single_step_file() {
+        let mut score = Score::default();
+        let json = format!("[{}, {}]", inc_a(0x01, 0x00), inc_a(0x7F, 0x94));
+        single_step_file("3c", &json, &mut score).unwrap();
+        assert_eq!(
+            score,
+            Score {
+                passed: 2,
+                total: 2,
+                ..Score::default()
+            }
+        );
+
+        // the zero flag isn't set going from 1 to 2
+        let json = format!("[{}, {}]", inc_a(0x01, 0x40), inc_a(0x02, 0x00));
+        single_step_file("3c", &json, &mut score).unwrap();
+        assert_eq!((score.passed, score.total), (3, 4));
+        assert_eq!(
+            score.failures,
+            ["3c: 1/2 failed, first 3c 01: F 0, expected 40"]
+        );
+    }
+
+    #[test]
+    fn test_rom_pack_mismatches() {
+        let pack = RomPack::bundled().unwrap();
+        let case = &pack.cases[0];
+        let result = RomPackResult {
+            vram_crc32: case.vram_crc32.clone().unwrap(),
+            ram_crc32: "00000000".to_string(),
+            primary_slot_config: case.primary_slot_config.clone().unwrap(),
+            vdp_registers: Vec::new(),
+        };
+        assert_eq!(
+            case.mismatches(&result),
+            [format!(
+                "RAM CRC32 mismatch: expected {:?}, got \"00000000\"",
+                case.ram_crc32.as_ref().unwrap()
+            )]
+        );
+    }
+}
@@ -0,0 +1,41 @@
+// Boots a whole MSX1 BIOS and looks for its text on the screen, which only
+// shows when the CPU, the VDP, the PPI and the slots all do their part. It is
+// slow and the BIOS of a real machine is not redistributable, so it is ignored
+// by default and boots the bundled C-BIOS unless given another one:
+//
+//   RUSTMSX_TEST_BIOS=/path/to/bios.rom cargo test -p msx --test bios_boot_tests -- --ignored
+//
+// A BIOS given that way should reach the "Ok" of the BASIC prompt, while
+// C-BIOS, which has no BASIC, shows its version banner. Set
+// RUSTMSX_TEST_BIOS_TEXT to look for other text and RUSTMSX_TEST_BIOS_FRAMES
+// to run another number of frames.
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use msx::verify::{bios_boot, BIOS_BOOT_FRAMES};
+
+/// The BIOS to boot and the text it shows once booted.
+fn bios() -> (PathBuf, String) {
+    match env::var("RUSTMSX_TEST_BIOS") {
+        Ok(path) => (PathBuf::from(path), "Ok".to_string()),
+        Err(_) => (
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("../roms/cbios_msx1.rom"),
+            "C-BIOS".to_string(),
+        ),
+    }
+}
+
+#[test]
+#[ignore]
+fn test_bios_boot() -> anyhow::Result<()> {
+    let (path, text) = bios();
+    let text = env::var("RUSTMSX_TEST_BIOS_TEXT").unwrap_or(text);
+    let frames = match env::var("RUSTMSX_TEST_BIOS_FRAMES") {
+        Ok(frames) => frames.parse()?,
+        Err(_) => BIOS_BOOT_FRAMES,
+    };
+
+    bios_boot(&fs::read(path)?, &text, frames)
+}
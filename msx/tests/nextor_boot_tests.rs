@@ -0,0 +1,77 @@
+// Boots Nextor from a hard disk image up to the MSX-DOS prompt, which needs
+// the slot expansion, the memory mapper, the Sunrise IDE interface and the
+// extended BIOS hooks to work together. It needs the BIOS of a real machine,
+// since C-BIOS can't boot a disk, the Nextor ROM for the Sunrise IDE and a
+// disk image with NEXTOR.SYS and COMMAND2.COM on it, none of which is in the
+// tree, so it is ignored by default and skipped without them:
+//
+//   RUSTMSX_TEST_BIOS=/path/to/bios.rom \
+//   RUSTMSX_TEST_NEXTOR=/path/to/Nextor-2.1.SunriseIDE.ROM \
+//   RUSTMSX_TEST_DISK=/path/to/disk.img \
+//   cargo test -p msx --test nextor_boot_tests -- --ignored
+//
+// The machine is laid out like an MSX2: the BIOS in slot 0, the interface in
+// slot 1, and slot 3 expanded with the RAM in 3-0 and a mapper in 3-2.
+use std::{env, path::PathBuf};
+
+use anyhow::bail;
+use msx::{
+    dos::{DosCommand, DosStep},
+    ram_cartridge::RamCartridge,
+    slot::{ExpandedSlot, RamSlot, RomSlot, SlotType},
+    sunrise_ide::SunriseIde,
+    Msx,
+};
+
+// up to 30 seconds at 60 Hz, checked every few frames
+const BOOT_FRAMES: u32 = 1800;
+const CHECK_FRAMES: u32 = 10;
+
+fn path(var: &str) -> Option<PathBuf> {
+    env::var(var).ok().map(PathBuf::from)
+}
+
+#[test]
+#[ignore]
+fn test_nextor_boot() -> anyhow::Result<()> {
+    let (Some(bios), Some(nextor), Some(disk)) = (
+        path("RUSTMSX_TEST_BIOS"),
+        path("RUSTMSX_TEST_NEXTOR"),
+        path("RUSTMSX_TEST_DISK"),
+    ) else {
+        println!("skipped: RUSTMSX_TEST_BIOS, RUSTMSX_TEST_NEXTOR or RUSTMSX_TEST_DISK not set");
+        return Ok(());
+    };
+
+    let mut msx = Msx::new(&[
+        SlotType::Rom(RomSlot::load(bios, 0x0000, 0x8000)?),
+        SlotType::SunriseIde(SunriseIde::load(&nextor, Some(&disk))?),
+        SlotType::Empty,
+        SlotType::Expanded(ExpandedSlot::new([
+            SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+            SlotType::Empty,
+            RamCartridge::MemoryMapper(0x80000).slot(),
+            SlotType::Empty,
+        ])),
+    ]);
+
+    // at the prompt, the command would be typed
+    let mut prompt = DosCommand::new("");
+    for frame in 1..=BOOT_FRAMES {
+        msx.step_frame();
+        if msx.halted() {
+            bail!("halted at {:#06X}", msx.pc());
+        }
+        if frame % CHECK_FRAMES == 0 {
+            if let DosStep::Type(_) = prompt.step(&msx.screen_text(), msx.cursor()) {
+                return Ok(());
+            }
+        }
+    }
+
+    bail!(
+        "no MSX-DOS prompt after {} frames:\n{}",
+        BOOT_FRAMES,
+        msx.screen_text().join("\n")
+    )
+}
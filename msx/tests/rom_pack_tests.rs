@@ -0,0 +1,55 @@
+// Acceptance tests that run known test ROMs and compare the final machine state
+// against recorded checksums. They are slow and most ROMs are not redistributable,
+// so they are ignored by default:
+//
+//   RUSTMSX_TEST_ROMS=/path/to/roms cargo test -p msx --test rom_pack_tests -- --ignored
+//
+// Set RUSTMSX_BLESS=1 to print the actual checksums of every case instead of
+// asserting, which is how new entries in tests/fixtures/rom_pack.json are recorded.
+// The vdp-test and ppi-psg-test cases run the small ROMs of tests/fixtures,
+// listed with their bytes in the .src files next to them.
+// `rustmsx verify` runs the same pack along with the rest of the accuracy suite.
+use std::{env, path::PathBuf};
+
+use anyhow::anyhow;
+use msx::verify::RomPack;
+
+#[test]
+#[ignore]
+fn test_rom_pack() -> anyhow::Result<()> {
+    let pack = RomPack::bundled()?;
+    let roms = env::var("RUSTMSX_TEST_ROMS").ok().map(PathBuf::from);
+    let bless = env::var("RUSTMSX_BLESS").is_ok();
+
+    let mut failures = Vec::new();
+    for case in &pack.cases {
+        let Some(result) = case.run(roms.as_deref())? else {
+            println!("[{}] skipped: ROM not found", case.name);
+            continue;
+        };
+
+        if bless {
+            println!(
+                "[{}] vram_crc32: {} ram_crc32: {} primary_slot_config: {} vdp_registers: {:?}",
+                case.name,
+                result.vram_crc32,
+                result.ram_crc32,
+                result.primary_slot_config,
+                result.vdp_registers
+            );
+            continue;
+        }
+
+        failures.extend(
+            case.mismatches(&result)
+                .into_iter()
+                .map(|mismatch| format!("[{}] {}", case.name, mismatch)),
+        );
+    }
+
+    if !failures.is_empty() {
+        return Err(anyhow!("ROM pack failures:\n{}", failures.join("\n")));
+    }
+
+    Ok(())
+}
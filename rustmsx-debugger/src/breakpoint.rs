@@ -0,0 +1,400 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::{anyhow, bail};
+
+use msx::Z80;
+
+use crate::watch::parse_address;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    A,
+    F,
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+    BC,
+    DE,
+    HL,
+    SP,
+    IX,
+    IY,
+    /// memory byte pointed by HL
+    HLAddress,
+    /// memory byte at a fixed address
+    Memory(u16),
+}
+
+impl Operand {
+    /// Current value of the register or memory byte.
+    pub fn value(&self, cpu: &Z80) -> u16 {
+        match self {
+            Operand::A => cpu.a as u16,
+            Operand::F => cpu.f as u16,
+            Operand::B => cpu.b as u16,
+            Operand::C => cpu.c as u16,
+            Operand::D => cpu.d as u16,
+            Operand::E => cpu.e as u16,
+            Operand::H => cpu.h as u16,
+            Operand::L => cpu.l as u16,
+            Operand::BC => cpu.get_bc(),
+            Operand::DE => cpu.get_de(),
+            Operand::HL => cpu.get_hl(),
+            Operand::SP => cpu.sp,
+            Operand::IX => cpu.ix,
+            Operand::IY => cpu.iy,
+            Operand::HLAddress => cpu.read_byte(cpu.get_hl()) as u16,
+            Operand::Memory(address) => cpu.read_byte(*address) as u16,
+        }
+    }
+
+    /// Whether the operand is a 16-bit register.
+    pub fn is_word(&self) -> bool {
+        matches!(
+            self,
+            Operand::BC | Operand::DE | Operand::HL | Operand::SP | Operand::IX | Operand::IY
+        )
+    }
+}
+
+impl FromStr for Operand {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let operand = match s.to_lowercase().as_str() {
+            "a" => Operand::A,
+            "f" => Operand::F,
+            "b" => Operand::B,
+            "c" => Operand::C,
+            "d" => Operand::D,
+            "e" => Operand::E,
+            "h" => Operand::H,
+            "l" => Operand::L,
+            "bc" => Operand::BC,
+            "de" => Operand::DE,
+            "hl" => Operand::HL,
+            "sp" => Operand::SP,
+            "ix" => Operand::IX,
+            "iy" => Operand::IY,
+            "(hl)" => Operand::HLAddress,
+            s => match s.strip_prefix('(').and_then(|s| s.strip_suffix(')')) {
+                Some(address) => Operand::Memory(parse_address(address)?),
+                None => bail!("Invalid operand: {}", s),
+            },
+        };
+        Ok(operand)
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::HLAddress => write!(f, "(hl)"),
+            Operand::Memory(address) => write!(f, "({:#06X})", address),
+            operand => write!(f, "{}", format!("{:?}", operand).to_lowercase()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl CompareOp {
+    // two character operators first, so `<=` isn't taken for `<`
+    const ALL: [(&'static str, CompareOp); 6] = [
+        ("==", CompareOp::Eq),
+        ("!=", CompareOp::Ne),
+        ("<=", CompareOp::Le),
+        (">=", CompareOp::Ge),
+        ("<", CompareOp::Lt),
+        (">", CompareOp::Gt),
+    ];
+
+    fn symbol(&self) -> &'static str {
+        CompareOp::ALL
+            .iter()
+            .find(|(_, op)| op == self)
+            .map(|(symbol, _)| *symbol)
+            .unwrap()
+    }
+}
+
+/// Condition on a register or memory value, e.g. `a == 10` or
+/// `(F3F8) != 0`, values being in hex.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Condition {
+    pub operand: Operand,
+    pub op: CompareOp,
+    pub value: u16,
+}
+
+impl Condition {
+    pub fn eval(&self, cpu: &Z80) -> bool {
+        let current = self.operand.value(cpu);
+        match self.op {
+            CompareOp::Eq => current == self.value,
+            CompareOp::Ne => current != self.value,
+            CompareOp::Lt => current < self.value,
+            CompareOp::Le => current <= self.value,
+            CompareOp::Gt => current > self.value,
+            CompareOp::Ge => current >= self.value,
+        }
+    }
+}
+
+impl FromStr for Condition {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (operand, op, value) = CompareOp::ALL
+            .iter()
+            .find_map(|(symbol, op)| {
+                s.split_once(symbol)
+                    .map(|(operand, value)| (operand, *op, value))
+            })
+            .ok_or_else(|| anyhow!("Invalid condition, expected <operand> <op> <value>: {}", s))?;
+
+        Ok(Self {
+            operand: operand.trim().parse()?,
+            op,
+            value: parse_value(value.trim())?,
+        })
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {:#X}", self.operand, self.op.symbol(), self.value)
+    }
+}
+
+/// Parses values in hex like the addresses of the other commands, with or
+/// without a 0x, $ or # prefix.
+fn parse_value(s: &str) -> anyhow::Result<u16> {
+    parse_address(s).map_err(|_| anyhow!("Invalid value: {}", s))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub id: usize,
+    pub address: u16,
+    pub enabled: bool,
+    pub condition: Option<Condition>,
+    /// times the breakpoint was reached with its condition met
+    pub hits: u64,
+    /// number of upcoming hits that won't stop the execution
+    pub ignore_count: u64,
+    /// removed once it stops the execution
+    pub temporary: bool,
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "#{} {:#06X} {} hits: {}",
+            self.id,
+            self.address,
+            if self.enabled { "enabled" } else { "disabled" },
+            self.hits
+        )?;
+        if self.ignore_count > 0 {
+            write!(f, " ignore: {}", self.ignore_count)?;
+        }
+        if self.temporary {
+            write!(f, " temporary")?;
+        }
+        if let Some(condition) = &self.condition {
+            write!(f, " if {}", condition)?;
+        }
+        Ok(())
+    }
+}
+
+/// Breakpoints identified by an id, so they can be disabled or changed
+/// without being removed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Breakpoints {
+    entries: Vec<Breakpoint>,
+    next_id: usize,
+}
+
+impl Breakpoints {
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+            next_id: 1,
+        }
+    }
+
+    /// Adds a breakpoint, returning its id.
+    pub fn add(&mut self, address: u16, condition: Option<Condition>) -> usize {
+        self.insert(address, condition, false)
+    }
+
+    /// Adds a breakpoint that is removed after its first hit.
+    pub fn add_temporary(&mut self, address: u16) -> usize {
+        self.insert(address, None, true)
+    }
+
+    fn insert(&mut self, address: u16, condition: Option<Condition>, temporary: bool) -> usize {
+        let id = self.next_id.max(1);
+        self.next_id = id + 1;
+        self.entries.push(Breakpoint {
+            id,
+            address,
+            enabled: true,
+            condition,
+            hits: 0,
+            ignore_count: 0,
+            temporary,
+        });
+        id
+    }
+
+    pub fn get_mut(&mut self, id: usize) -> anyhow::Result<&mut Breakpoint> {
+        self.entries
+            .iter_mut()
+            .find(|bp| bp.id == id)
+            .ok_or_else(|| anyhow!("No breakpoint #{}", id))
+    }
+
+    pub fn remove(&mut self, id: usize) -> anyhow::Result<Breakpoint> {
+        let index = self
+            .entries
+            .iter()
+            .position(|bp| bp.id == id)
+            .ok_or_else(|| anyhow!("No breakpoint #{}", id))?;
+        Ok(self.entries.remove(index))
+    }
+
+    /// Removes all the breakpoints at an address.
+    pub fn remove_address(&mut self, address: u16) {
+        self.entries.retain(|bp| bp.address != address);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Breakpoint> {
+        self.entries.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Checks the breakpoints at the current PC, counting the hits, and
+    /// returns the one that stops the execution, if any. Temporary
+    /// breakpoints are removed when they stop.
+    pub fn check(&mut self, cpu: &Z80) -> Option<Breakpoint> {
+        let mut stop = None;
+
+        for (index, bp) in self.entries.iter_mut().enumerate() {
+            if !bp.enabled || bp.address != cpu.pc {
+                continue;
+            }
+            if let Some(condition) = &bp.condition {
+                if !condition.eval(cpu) {
+                    continue;
+                }
+            }
+
+            bp.hits += 1;
+            if bp.ignore_count > 0 {
+                bp.ignore_count -= 1;
+                continue;
+            }
+            stop.get_or_insert(index);
+        }
+
+        let index = stop?;
+        if self.entries[index].temporary {
+            Some(self.entries.remove(index))
+        } else {
+            Some(self.entries[index].clone())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, RwLock};
+
+    use msx::{
+        bus::Bus,
+        slot::{RamSlot, SlotType},
+    };
+
+    use super::*;
+
+    fn cpu() -> Z80 {
+        let bus = Bus::new(&[
+            SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Empty,
+        ]);
+        Z80::new(Arc::new(RwLock::new(bus)))
+    }
+
+    #[test]
+    fn test_condition() {
+        let mut cpu = cpu();
+        cpu.a = 0x10;
+        cpu.set_hl(0xC000);
+        cpu.write_byte(0xC000, 0x42);
+
+        assert!("a == 0x10".parse::<Condition>().unwrap().eval(&cpu));
+        assert!("a == 10".parse::<Condition>().unwrap().eval(&cpu));
+        assert!("a<=#10".parse::<Condition>().unwrap().eval(&cpu));
+        assert!(!"a < $10".parse::<Condition>().unwrap().eval(&cpu));
+        assert!("(hl) == 0x42".parse::<Condition>().unwrap().eval(&cpu));
+        assert!("(0xC000) != 0".parse::<Condition>().unwrap().eval(&cpu));
+        assert!("(C000) == 42".parse::<Condition>().unwrap().eval(&cpu));
+        assert!("a == 1G".parse::<Condition>().is_err());
+        assert!("q == 1".parse::<Condition>().is_err());
+        assert!("a".parse::<Condition>().is_err());
+    }
+
+    #[test]
+    fn test_check() {
+        let mut cpu = cpu();
+        let mut breakpoints = Breakpoints::new();
+        let id = breakpoints.add(0x0000, None);
+        let conditional = breakpoints.add(0x0000, Some("a == 1".parse().unwrap()));
+
+        breakpoints.get_mut(id).unwrap().ignore_count = 1;
+        assert_eq!(breakpoints.check(&cpu), None);
+        assert_eq!(breakpoints.check(&cpu).map(|bp| bp.id), Some(id));
+
+        breakpoints.get_mut(id).unwrap().enabled = false;
+        assert_eq!(breakpoints.check(&cpu), None);
+        cpu.a = 1;
+        assert_eq!(breakpoints.check(&cpu).map(|bp| bp.id), Some(conditional));
+
+        assert_eq!(breakpoints.get_mut(id).unwrap().hits, 2);
+        breakpoints.remove(id).unwrap();
+        assert!(breakpoints.remove(id).is_err());
+    }
+
+    #[test]
+    fn test_temporary() {
+        let mut cpu = cpu();
+        let mut breakpoints = Breakpoints::new();
+        let id = breakpoints.add_temporary(0x0010);
+
+        assert_eq!(breakpoints.check(&cpu), None);
+        cpu.pc = 0x0010;
+        assert_eq!(breakpoints.check(&cpu).map(|bp| bp.id), Some(id));
+        assert!(breakpoints.is_empty());
+        assert_eq!(breakpoints.check(&cpu), None);
+    }
+}
@@ -0,0 +1,1279 @@
+//! The commands of the debugger as typed on the prompt of the CLI or on the
+//! console of the web app, and the help that lists them.
+
+use std::{fmt::Write, path::PathBuf};
+
+use anyhow::{anyhow, bail};
+use msx::{keyboard::InputProfile, machine::ResetKind, vdp::DisplayMode};
+
+use crate::{breakpoint::Condition, timer::TimerClock, watch::WatchExpr};
+
+// instructions `disasm` shows without a count
+const DISASM_COUNT: usize = 16;
+
+/// Levels of the `log` command, from the quietest.
+pub const LOG_LEVELS: &[&str] = &["off", "error", "warn", "info", "debug", "trace"];
+
+/// A command of the prompt, as listed by `help`. Keep in sync with the
+/// `Command` variants and with `CommandLine::parse`.
+pub struct CommandHelp {
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    pub usage: &'static str,
+    pub description: &'static str,
+}
+
+impl CommandHelp {
+    pub fn names(&self) -> impl Iterator<Item = &'static str> {
+        std::iter::once(self.name).chain(self.aliases.iter().copied())
+    }
+}
+
+const fn command(
+    name: &'static str,
+    aliases: &'static [&'static str],
+    usage: &'static str,
+    description: &'static str,
+) -> CommandHelp {
+    CommandHelp {
+        name,
+        aliases,
+        usage,
+        description,
+    }
+}
+
+pub const COMMANDS: &[CommandHelp] = &[
+    command(
+        "help",
+        &["h", "?"],
+        "help [command]",
+        "lists the commands, or shows how to use one",
+    ),
+    command("quit", &["q"], "quit", "quits the emulator"),
+    command(
+        "reset",
+        &[],
+        "reset [soft|hard|power]",
+        "resets keeping the RAM, which a hard reset clears, and a power cycle also loses what batteries keep",
+    ),
+    command(
+        "nmi",
+        &[],
+        "nmi",
+        "triggers a non-maskable interrupt, taken before the next instruction",
+    ),
+    command(
+        "insert",
+        &[],
+        "insert <slot> <file>",
+        "inserts a ROM cartridge into a slot while running",
+    ),
+    command(
+        "eject",
+        &[],
+        "eject <slot>",
+        "ejects the cartridge from a slot",
+    ),
+    command(
+        "step",
+        &["n"],
+        "step [count]",
+        "steps one instruction on all emulators",
+    ),
+    command(
+        "frame",
+        &["f"],
+        "frame [count]",
+        "advances a number of video frames",
+    ),
+    command(
+        "slow",
+        &[],
+        "slow [factor|off]",
+        "runs at 1/factor of the normal speed, or at full speed when off",
+    ),
+    command(
+        "cont",
+        &["c"],
+        "cont",
+        "continues execution on all emulators",
+    ),
+    command(
+        "until",
+        &["u"],
+        "until <addr>",
+        "continues until the address is reached, without adding a breakpoint",
+    ),
+    command(
+        "dump",
+        &["d"],
+        "dump [--json]",
+        "dumps the current state of all emulators",
+    ),
+    command(
+        "list",
+        &["l"],
+        "list [asm]",
+        "lists the source lines around the program counter, or the disassembly",
+    ),
+    command(
+        "log",
+        &[],
+        "log [<device> [off|error|warn|info|debug|trace]]",
+        "lists the execution log, or shows or sets how much a device logs: cpu, vdp, ppi, psg, bus, slot, memory, serial or netplay",
+    ),
+    command(
+        "history",
+        &["hist"],
+        "history [count]",
+        "prints the last instructions executed, with the registers before each",
+    ),
+    command(
+        "status",
+        &["st"],
+        "status [--json]",
+        "shows the breakpoints, slots and memory layout",
+    ),
+    command(
+        "break",
+        &["bp"],
+        "break [<addr> [if <condition>]]\n       \
+         break list|enable <id>|disable <id>|delete <id>\n       \
+         break ignore <id> <count>|cond <id> [condition]",
+        "adds a breakpoint address, or lists and changes the existing breakpoints by id",
+    ),
+    command(
+        "tbreak",
+        &["tbp"],
+        "tbreak <addr>",
+        "adds a breakpoint that is removed after its first hit",
+    ),
+    command(
+        "removebreak",
+        &["rbp"],
+        "removebreak <addr>",
+        "removes a breakpoint address",
+    ),
+    command(
+        "stackguard",
+        &["sg"],
+        "stackguard on|off",
+        "breaks when a return address is overwritten before its RET",
+    ),
+    command(
+        "hookguard",
+        &["hg"],
+        "hookguard on|off",
+        "breaks when the interrupt vector, H.KEYI or H.TIMI is left malformed with interrupts enabled",
+    ),
+    command(
+        "hooks",
+        &[],
+        "hooks [on|off]",
+        "lists the patched BIOS hooks, or reports the calls to the hooks",
+    ),
+    command(
+        "breaktext",
+        &["bt"],
+        "breaktext [<text>|clear]",
+        "breaks when the text is printed through CHPUT, or lists the texts",
+    ),
+    command(
+        "console",
+        &[],
+        "console on|off",
+        "prints the text the BIOS CHPUT routine outputs",
+    ),
+    command(
+        "screen",
+        &[],
+        "screen [--follow [off]]",
+        "prints the text screen, or its lines as they change while running",
+    ),
+    command(
+        "vdptiming",
+        &["vt"],
+        "vdptiming [on|off]",
+        "lists the VRAM accesses too close for a real VDP, or reports them",
+    ),
+    command(
+        "slotcheck",
+        &["sc"],
+        "slotcheck [on|off]",
+        "lists the reads of programs from empty slots, or reports them",
+    ),
+    command(
+        "slottrace",
+        &[],
+        "slottrace on|break|off",
+        "prints the writes to the slot register with the pages before and after, break also breaking when the page of the PC switches",
+    ),
+    command(
+        "vdp",
+        &[],
+        "vdp capture-frame | mode [text1|g1|g2|mc|auto]",
+        "lists on which lines of a frame the VDP registers changed, or forces a display mode",
+    ),
+    command(
+        "freq",
+        &[],
+        "freq [50|60]",
+        "shows the frequency of the VDP, or switches it with the frame interrupts and the BIOS ID bytes",
+    ),
+    command(
+        "tape",
+        &[],
+        "tape [status] | insert <file> | eject | rewind | baud [auto|<n>] | record | save <file.cas>",
+        "shows where the tape is, loads a WAV recording or a CAS image into the cassette recorder, sets the baud rate it is played at, or records what is saved to tape into a CAS image",
+    ),
+    command(
+        "turboio",
+        &[],
+        "turboio [on|off]",
+        "shows whether the BIOS tape routines read the tape at once instead of at its baud rate, or turns it on or off",
+    ),
+    command(
+        "mapper",
+        &[],
+        "mapper state | log [on|off|clear]",
+        "shows the banks the mappers selected, or lists the bank switches while logging them",
+    ),
+    command(
+        "at",
+        &[],
+        "at [frame|cycle [+]<n> do <command>] | delete <id> | clear",
+        "runs a command when the machine reaches a frame or cycle, or lists those queued",
+    ),
+    command(
+        "mem",
+        &["m"],
+        "mem <addr> [value]",
+        "gets or sets the value of a memory address",
+    ),
+    command(
+        "set",
+        &["s"],
+        "set a|b|c|hl|(hl)",
+        "sets the value of a register",
+    ),
+    command(
+        "watch",
+        &["w"],
+        "watch [reg|addr|start-end]",
+        "adds a register, address or range to the watch list, or shows the watched values",
+    ),
+    command(
+        "unwatch",
+        &["uw"],
+        "unwatch <reg|addr|start-end>",
+        "removes a register, address or range from the watch list",
+    ),
+    command(
+        "view",
+        &["v"],
+        "view <addr>",
+        "shows the memory at an address, which the TUI memory pane follows",
+    ),
+    command(
+        "sysvars",
+        &["sv"],
+        "sysvars [name]",
+        "shows the system variables of the BIOS, or those whose names contain a text",
+    ),
+    command(
+        "region",
+        &["rg"],
+        "region [add <start> <end> <name>|remove <start>|load <file>|clear]",
+        "lists the named memory regions, or adds, removes or loads them",
+    ),
+    command(
+        "memdump",
+        &["md"],
+        "memdump [msx|openmsx|b|diff] [--json]",
+        "dumps the contents of the memory",
+    ),
+    command(
+        "vramdump",
+        &["vdpdump", "vd"],
+        "vramdump [msx|openmsx|b|diff]",
+        "dumps vram contents",
+    ),
+    command("send", &[], "send <command>", "sends a command to openMSX"),
+    command(
+        "loadbin",
+        &["lb"],
+        "loadbin <file> [run]",
+        "loads a BLOAD binary into memory, optionally jumping to it",
+    ),
+    command(
+        "loadbas",
+        &["lbas"],
+        "loadbas <file>",
+        "loads a BASIC program (tokenized or ASCII) and runs it",
+    ),
+    command(
+        "type",
+        &[],
+        "type [text]",
+        "types text on the keyboard, or clears the pending keys without text",
+    ),
+    command(
+        "input",
+        &[],
+        "input [keyboard|joystick] | log [on|off|clear|save <file>]",
+        "sets whether the typed cursor keys and space drive the keyboard or joystick 1, or lists the presses while logging them",
+    ),
+    command(
+        "disasm",
+        &[],
+        "disasm [<addr> [count]]\n       \
+         disasm export <start> <end> <file>",
+        "disassembles from the PC or an address, or writes a labelled disassembly to a file",
+    ),
+    command(
+        "rominfo",
+        &["ri"],
+        "rominfo [slot|file]",
+        "analyzes the cartridge in a slot, or a ROM file",
+    ),
+    command(
+        "savestate",
+        &["save"],
+        "savestate <file>",
+        "saves the machine state to a file",
+    ),
+    command(
+        "loadstate",
+        &["load"],
+        "loadstate <file>",
+        "restores the machine state from a file",
+    ),
+    command(
+        "palette",
+        &["pal"],
+        "palette [name|file]",
+        "shows the palette, or switches to a preset or a palette file",
+    ),
+];
+
+/// Finds a command by its name or one of its aliases.
+pub fn find(name: &str) -> Option<&'static CommandHelp> {
+    COMMANDS.iter().find(|c| c.names().any(|n| n == name))
+}
+
+/// The command whose name is the closest to a mistyped one, if any is close
+/// enough to be what was meant.
+pub fn closest(name: &str) -> Option<&'static str> {
+    COMMANDS
+        .iter()
+        .flat_map(|c| c.names().map(move |n| (c.name, distance(name, n))))
+        .filter(|(_, d)| *d <= 2 && *d < name.len())
+        .min_by_key(|(_, d)| *d)
+        .map(|(name, _)| name)
+}
+
+// Levenshtein distance
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = if ca == *cb {
+                diagonal
+            } else {
+                1 + diagonal.min(above).min(row[j])
+            };
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
+
+/// The list of the commands, or the usage of one, as `help` prints them.
+pub fn help(command: Option<&str>) -> String {
+    let mut text = String::new();
+    match command {
+        Some(name) => match find(name) {
+            Some(help) => {
+                writeln!(text, "Usage: {}", help.usage).unwrap();
+                if !help.aliases.is_empty() {
+                    writeln!(text, "Aliases: {}", help.aliases.join(", ")).unwrap();
+                }
+                writeln!(text, "{}", help.description).unwrap();
+            }
+            None => match closest(name) {
+                Some(suggestion) => writeln!(
+                    text,
+                    "Unknown command {}, did you mean {}?",
+                    name, suggestion
+                )
+                .unwrap(),
+                None => writeln!(text, "Unknown command {}", name).unwrap(),
+            },
+        },
+        None => {
+            for help in COMMANDS {
+                writeln!(text, "{:<12} {}", help.name, help.description).unwrap();
+            }
+            writeln!(text, "\nType help <command> for its usage.").unwrap();
+        }
+    }
+    text.push('\n');
+    text
+}
+
+#[derive(Debug)]
+pub enum SetTarget {
+    A,
+    B,
+    C,
+    HL,
+    HLAddress,
+}
+
+#[derive(Debug)]
+pub enum DumpTarget {
+    Msx,
+    OpenMsx,
+    Compare,
+    Diff,
+}
+
+#[derive(Debug)]
+pub enum Command {
+    /// lists the commands, or shows the usage of one
+    Help(Option<String>),
+
+    /// quits the emulator
+    Quit,
+
+    /// resets the machine, see [`ResetKind`]
+    Reset(ResetKind),
+
+    /// triggers a non-maskable interrupt
+    Nmi,
+
+    /// inserts a ROM cartridge into a slot while running
+    Insert(u8, PathBuf),
+
+    /// ejects the cartridge from a slot
+    Eject(u8),
+
+    /// shows or changes the tape in the cassette recorder
+    Tape(TapeCommand),
+
+    /// shows whether the BIOS tape routines run at once, or turns that on
+    /// or off
+    TurboIo(Option<bool>),
+
+    /// steps one instruction on all emulators
+    Step(u32),
+
+    /// advances a number of video frames
+    Frame(u32),
+
+    /// runs at 1/factor of the normal speed, or at full speed when off
+    Slow(Option<f64>),
+
+    /// continues execution on all emulators
+    Continue,
+
+    /// dumps the current state of all emulators, as JSON with --json
+    Dump(bool),
+
+    /// lists the source lines around the current program counter, or the
+    /// disassembly when asked or without a listing
+    List(bool),
+
+    /// lists the execution log
+    Log,
+
+    /// shows or sets the level a device logs at
+    LogLevel(String, Option<String>),
+
+    /// prints the last instructions executed, with the registers before each
+    History(usize),
+
+    /// breaks when a return address is overwritten before its RET
+    StackGuard(bool),
+
+    /// breaks when the interrupt vector or hooks are left malformed with
+    /// the interrupts enabled
+    HookGuard(bool),
+
+    /// reports the calls to the BIOS hooks when their contents change, or
+    /// lists the patched and called hooks
+    Hooks(Option<bool>),
+
+    /// mirrors the text printed through the BIOS CHPUT routine to stdout
+    Console(bool),
+
+    /// breaks when the text is printed through CHPUT, lists the texts
+    /// without one or removes them all with `clear`
+    BreakText(String),
+
+    /// reports the VRAM accesses closer than the TMS9918 allows, or lists
+    /// where they happened
+    VdpTiming(Option<bool>),
+
+    /// reports the reads of programs from pages whose selected slot is
+    /// empty, or lists where they happened
+    SlotCheck(Option<bool>),
+
+    /// prints the writes to the slot registers, and whether to break on
+    /// those switching the page of the PC
+    SlotTrace(bool, bool),
+
+    /// runs through the next frame, capturing the VDP registers at the
+    /// start of each line, and lists on which lines they changed
+    VdpCaptureFrame,
+
+    /// shows the display mode, or draws in one whatever the registers
+    /// select, `Some(None)` going back to theirs
+    VdpMode(Option<Option<DisplayMode>>),
+
+    /// shows the frequency of the VDP, or switches it, `Some(true)` being
+    /// 50 Hz
+    Frequency(Option<bool>),
+
+    /// shows the banks of the mappers or lists and logs their switches
+    Mapper(MapperCommand),
+
+    /// queues a command for a frame or cycle, or lists and removes those
+    /// queued
+    At(TimerCommand),
+
+    /// prints the text screen, or starts or stops printing its lines as
+    /// they change
+    Screen(Option<bool>),
+
+    /// Status
+    Status(bool),
+
+    /// adds a breakpoint address, optionally with a condition
+    AddBreakpoint(u16, Option<Condition>),
+
+    /// adds a breakpoint that is removed after its first hit
+    TempBreakpoint(u16),
+
+    /// continues until the address is reached, without adding a breakpoint
+    Until(u16),
+
+    /// lists and changes the existing breakpoints by id
+    Breakpoint(BreakpointCommand),
+
+    /// removes a breakpoint address
+    RemoveBreakpoint(u16),
+
+    /// gets the value of a memory address
+    MemGet(u16),
+
+    /// sets the value of a memory address
+    MemSet(u16, u8),
+
+    /// dumps vram contents
+    VramDump(DumpTarget),
+
+    /// dumps the contents of the memory
+    MemDump(DumpTarget, bool),
+
+    /// sets the value of a register
+    Set(SetTarget),
+
+    /// sends a command to openMSX
+    Send(Vec<String>),
+
+    /// loads a BLOAD binary into memory, optionally jumping to it
+    LoadBin(PathBuf, bool),
+
+    /// loads a BASIC program (tokenized or ASCII) and runs it
+    LoadBasic(PathBuf),
+
+    /// types text on the keyboard, or clears the pending keys without text
+    Type(String),
+
+    /// sets what the cursor keys and space drive, or shows it
+    Input(Option<InputProfile>),
+
+    /// lists and logs the presses and releases of the keyboard and
+    /// joystick, or saves them
+    InputLog(InputLogCommand),
+
+    /// disassembles a number of instructions from an address, or from the
+    /// program counter
+    Disasm(Option<u16>, usize),
+
+    /// writes a labelled disassembly of a memory range to a file
+    DisasmExport(u16, u16, PathBuf),
+
+    /// analyzes the cartridge in a slot, or a ROM file
+    RomInfo(Option<String>),
+
+    /// saves the machine state to a file
+    SaveState(PathBuf),
+
+    /// restores the machine state from a file
+    LoadState(PathBuf),
+
+    /// shows the palette, or switches to a preset or a palette file
+    Palette(Option<String>),
+
+    /// adds a register, address or range to the watch list, or shows the
+    /// watched values
+    Watch(Option<WatchExpr>),
+
+    /// removes an expression from the watch list
+    Unwatch(WatchExpr),
+
+    /// shows the memory at an address, which the TUI memory pane follows
+    View(u16),
+
+    /// lists and changes the named memory regions
+    Region(RegionCommand),
+
+    /// shows the system variables of the BIOS, or those whose names contain
+    /// a text
+    SysVars(Option<String>),
+}
+
+#[derive(Debug)]
+pub enum BreakpointCommand {
+    List,
+    Enable(usize, bool),
+    Ignore(usize, u64),
+    Condition(usize, Option<Condition>),
+    Delete(usize),
+}
+
+#[derive(Debug)]
+pub enum MapperCommand {
+    State,
+    /// lists the bank switches logged, or starts or stops logging them
+    Log(Option<bool>),
+    ClearLog,
+}
+
+#[derive(Debug)]
+pub enum InputLogCommand {
+    /// lists the input events logged, or starts or stops logging them
+    Log(Option<bool>),
+    ClearLog,
+    /// writes the input events logged to a JSON file
+    Save(PathBuf),
+}
+
+#[derive(Debug)]
+pub enum TapeCommand {
+    Status,
+    /// loads a WAV recording or a CAS image, replacing the tape
+    Insert(PathBuf),
+    Eject,
+    Rewind,
+    /// plays the tape at a baud rate, `None` for its own
+    Baud(Option<u32>),
+    /// starts recording the cassette output, dropping what was recorded
+    Record,
+    /// writes what was recorded to a CAS image
+    Save(PathBuf),
+}
+
+#[derive(Debug)]
+pub enum TimerCommand {
+    List,
+    /// the command to run when the clock reaches a count, counted from now
+    /// if relative
+    Add {
+        clock: TimerClock,
+        at: u64,
+        relative: bool,
+        command: String,
+    },
+    Delete(usize),
+    Clear,
+}
+
+#[derive(Debug)]
+pub enum RegionCommand {
+    List,
+    Add(u16, u16, String),
+    Remove(u16),
+    Load(PathBuf),
+    Clear,
+}
+
+#[derive(Debug)]
+pub struct CommandLine {
+    pub command: Command,
+    /// the words after those the command takes
+    pub args: Vec<String>,
+}
+
+impl CommandLine {
+    fn parse_target(target: Option<&str>) -> anyhow::Result<DumpTarget> {
+        match target {
+            Some("msx") => Ok(DumpTarget::Msx),
+            Some("openmsx") => Ok(DumpTarget::OpenMsx),
+            Some("b") => Ok(DumpTarget::Compare),
+            None | Some("diff") => Ok(DumpTarget::Diff),
+            _ => bail!("Invalid target. Use openmsx, msx, b or diff."),
+        }
+    }
+
+    pub fn parse(line: &str) -> anyhow::Result<Self> {
+        let mut parts = line.split_whitespace();
+
+        let command = match parts.next() {
+            Some("help") | Some("h") | Some("?") => Command::Help(parts.next().map(String::from)),
+            Some("quit") | Some("q") => Command::Quit,
+            Some("step") | Some("n") => {
+                let n = match parts.next() {
+                    Some(n) => n.parse()?,
+                    None => 1,
+                };
+                Command::Step(n)
+            }
+            Some("frame") | Some("f") => {
+                let n = match parts.next() {
+                    Some(n) => n.parse()?,
+                    None => 1,
+                };
+                Command::Frame(n)
+            }
+            Some("slow") => match parts.next() {
+                None | Some("off") => Command::Slow(None),
+                Some(factor) => {
+                    let factor: f64 = factor.parse()?;
+                    if factor <= 0.0 {
+                        bail!("The slow factor must be positive");
+                    }
+                    Command::Slow(Some(factor))
+                }
+            },
+            Some("cont") | Some("c") => Command::Continue,
+            Some("reset") => match parts.next() {
+                None => Command::Reset(ResetKind::Soft),
+                Some(kind) => match ResetKind::find(kind) {
+                    Some(kind) => Command::Reset(kind),
+                    None => bail!("Usage: reset [soft|hard|power]"),
+                },
+            },
+            Some("nmi") => Command::Nmi,
+            Some("insert") => {
+                let (Some(slot), Some(file)) = (parts.next(), parts.next()) else {
+                    bail!("Usage: insert <slot> <file>");
+                };
+                Command::Insert(slot.parse()?, PathBuf::from(file))
+            }
+            Some("eject") => {
+                let Some(slot) = parts.next() else {
+                    bail!("Usage: eject <slot>");
+                };
+                Command::Eject(slot.parse()?)
+            }
+            Some("tape") => match parts.next() {
+                None | Some("status") => Command::Tape(TapeCommand::Status),
+                Some("insert") => {
+                    let Some(file) = parts.next() else {
+                        bail!("Usage: tape insert <file>");
+                    };
+                    Command::Tape(TapeCommand::Insert(PathBuf::from(file)))
+                }
+                Some("eject") => Command::Tape(TapeCommand::Eject),
+                Some("record") => Command::Tape(TapeCommand::Record),
+                Some("save") => {
+                    let Some(file) = parts.next() else {
+                        bail!("Usage: tape save <file.cas>");
+                    };
+                    Command::Tape(TapeCommand::Save(PathBuf::from(file)))
+                }
+                Some("rewind") => Command::Tape(TapeCommand::Rewind),
+                Some("baud") => match parts.next() {
+                    None | Some("auto") => Command::Tape(TapeCommand::Baud(None)),
+                    Some(baud) => match baud.parse() {
+                        Ok(baud) if baud > 0 => Command::Tape(TapeCommand::Baud(Some(baud))),
+                        _ => bail!("Usage: tape baud [auto|<n>]"),
+                    },
+                },
+                _ => bail!(
+                    "Usage: tape [status] | insert <file> | eject | rewind | baud [auto|<n>] | record | save <file.cas>"
+                ),
+            },
+            Some("list") | Some("l") => match parts.next() {
+                None => Command::List(false),
+                Some("asm") => Command::List(true),
+                Some(_) => bail!("Usage: list [asm]"),
+            },
+            Some("status") | Some("st") => Command::Status(json_flag(parts.by_ref())?.1),
+            Some("set") | Some("s") => {
+                let target = match parts.next() {
+                    Some("a") => SetTarget::A,
+                    Some("b") => SetTarget::B,
+                    Some("c") => SetTarget::C,
+                    Some("hl") => SetTarget::HL,
+                    Some("(hl)") => SetTarget::HLAddress,
+                    _ => bail!("Usage: set a|b|c|hl|(hl) <value>"),
+                };
+
+                Command::Set(target)
+            }
+            Some("dump") | Some("d") => Command::Dump(json_flag(parts.by_ref())?.1),
+            Some("mem") | Some("m") => {
+                let Some(addr) = parts.next() else {
+                    bail!("Usage: mem <addr> [value]");
+                };
+                let addr = u16::from_str_radix(addr, 16)?;
+
+                match parts.next() {
+                    Some(p) => {
+                        let value = u8::from_str_radix(p, 16)?;
+                        Command::MemSet(addr, value)
+                    }
+                    None => Command::MemGet(addr),
+                }
+            }
+            Some("break") | Some("bp") => match parts.next() {
+                None | Some("list") => Command::Breakpoint(BreakpointCommand::List),
+                Some(sub @ ("enable" | "disable")) => {
+                    let id = parse_id(parts.next())?;
+                    Command::Breakpoint(BreakpointCommand::Enable(id, sub == "enable"))
+                }
+                Some("ignore") => {
+                    let id = parse_id(parts.next())?;
+                    let Some(count) = parts.next() else {
+                        bail!("Usage: bp ignore <id> <count>");
+                    };
+                    Command::Breakpoint(BreakpointCommand::Ignore(id, count.parse()?))
+                }
+                Some("cond") => {
+                    let id = parse_id(parts.next())?;
+                    let condition = parts.by_ref().collect::<Vec<_>>().join(" ");
+                    let condition = match condition.is_empty() {
+                        true => None,
+                        false => Some(condition.parse()?),
+                    };
+                    Command::Breakpoint(BreakpointCommand::Condition(id, condition))
+                }
+                Some("delete") | Some("del") => {
+                    Command::Breakpoint(BreakpointCommand::Delete(parse_id(parts.next())?))
+                }
+                Some(addr) => {
+                    let addr = u16::from_str_radix(addr, 16)?;
+                    let condition = match parts.next() {
+                        Some("if") => Some(parts.by_ref().collect::<Vec<_>>().join(" ").parse()?),
+                        Some(_) => bail!("Usage: bp <addr> [if <condition>]"),
+                        None => None,
+                    };
+                    Command::AddBreakpoint(addr, condition)
+                }
+            },
+            Some("tbreak") | Some("tbp") => {
+                let Some(addr) = parts.next() else {
+                    bail!("Usage: tbreak <addr>");
+                };
+                Command::TempBreakpoint(u16::from_str_radix(addr, 16)?)
+            }
+            Some("until") | Some("u") => {
+                let Some(addr) = parts.next() else {
+                    bail!("Usage: until <addr>");
+                };
+                Command::Until(u16::from_str_radix(addr, 16)?)
+            }
+            Some("removebreak") | Some("rbp") => {
+                let Some(addr) = parts.next() else {
+                    bail!("Usage: removebreak <addr>");
+                };
+                Command::RemoveBreakpoint(u16::from_str_radix(addr, 16)?)
+            }
+            Some("send") => {
+                let mut args = Vec::new();
+
+                for arg in parts.by_ref() {
+                    args.push(arg.to_string());
+                }
+
+                Command::Send(args)
+            }
+            Some("sysvars") | Some("sv") => Command::SysVars(parts.next().map(String::from)),
+            Some("region") | Some("rg") => match parts.next() {
+                None | Some("list") => Command::Region(RegionCommand::List),
+                Some("add") => {
+                    let (Some(start), Some(end)) = (parts.next(), parts.next()) else {
+                        bail!("Usage: region add <start> <end> <name>");
+                    };
+                    let name = parts.by_ref().collect::<Vec<_>>().join(" ");
+                    Command::Region(RegionCommand::Add(
+                        u16::from_str_radix(start, 16)?,
+                        u16::from_str_radix(end, 16)?,
+                        name,
+                    ))
+                }
+                Some("remove") | Some("rm") => {
+                    let Some(start) = parts.next() else {
+                        bail!("Usage: region remove <start>");
+                    };
+                    Command::Region(RegionCommand::Remove(u16::from_str_radix(start, 16)?))
+                }
+                Some("load") => {
+                    let Some(file) = parts.next() else {
+                        bail!("Usage: region load <file>");
+                    };
+                    Command::Region(RegionCommand::Load(PathBuf::from(file)))
+                }
+                Some("clear") => Command::Region(RegionCommand::Clear),
+                Some(_) => bail!(
+                    "Usage: region [add <start> <end> <name>|remove <start>|load <file>|clear]"
+                ),
+            },
+            Some("memdump") | Some("md") => {
+                let (target, json) = json_flag(parts.by_ref())?;
+                Command::MemDump(CommandLine::parse_target(target)?, json)
+            }
+            Some("vramdump") | Some("vdpdump") | Some("vd") => {
+                Command::VramDump(CommandLine::parse_target(parts.next())?)
+            }
+            Some("log") => match (parts.next(), parts.next()) {
+                (None, _) => Command::Log,
+                (Some(device), None) => Command::LogLevel(device.to_string(), None),
+                (Some(device), Some(level)) if LOG_LEVELS.contains(&level) => {
+                    Command::LogLevel(device.to_string(), Some(level.to_string()))
+                }
+                _ => bail!("Usage: log [<device> [off|error|warn|info|debug|trace]]"),
+            },
+            Some("stackguard") | Some("sg") => match parts.next() {
+                Some("on") => Command::StackGuard(true),
+                Some("off") => Command::StackGuard(false),
+                _ => bail!("Usage: stackguard on|off"),
+            },
+            Some("hookguard") | Some("hg") => match parts.next() {
+                Some("on") => Command::HookGuard(true),
+                Some("off") => Command::HookGuard(false),
+                _ => bail!("Usage: hookguard on|off"),
+            },
+            Some("hooks") => match parts.next() {
+                None => Command::Hooks(None),
+                Some("on") => Command::Hooks(Some(true)),
+                Some("off") => Command::Hooks(Some(false)),
+                _ => bail!("Usage: hooks [on|off]"),
+            },
+            Some("console") => match parts.next() {
+                Some("on") => Command::Console(true),
+                Some("off") => Command::Console(false),
+                _ => bail!("Usage: console on|off"),
+            },
+            Some("vdptiming") | Some("vt") => match parts.next() {
+                None => Command::VdpTiming(None),
+                Some("on") => Command::VdpTiming(Some(true)),
+                Some("off") => Command::VdpTiming(Some(false)),
+                _ => bail!("Usage: vdptiming [on|off]"),
+            },
+            Some("slotcheck") | Some("sc") => match parts.next() {
+                None => Command::SlotCheck(None),
+                Some("on") => Command::SlotCheck(Some(true)),
+                Some("off") => Command::SlotCheck(Some(false)),
+                _ => bail!("Usage: slotcheck [on|off]"),
+            },
+            Some("slottrace") => match parts.next() {
+                Some("on") => Command::SlotTrace(true, false),
+                Some("break") => Command::SlotTrace(true, true),
+                Some("off") => Command::SlotTrace(false, false),
+                _ => bail!("Usage: slottrace on|break|off"),
+            },
+            Some("vdp") => match parts.next() {
+                Some("capture-frame") => Command::VdpCaptureFrame,
+                Some("mode") => match parts.next() {
+                    None => Command::VdpMode(None),
+                    Some("auto") => Command::VdpMode(Some(None)),
+                    Some(name) => match DisplayMode::find(name) {
+                        Some(mode) => Command::VdpMode(Some(Some(mode))),
+                        None => bail!("Usage: vdp mode [text1|g1|g2|mc|auto]"),
+                    },
+                },
+                _ => bail!("Usage: vdp capture-frame | mode [text1|g1|g2|mc|auto]"),
+            },
+            Some("turboio") => match parts.next() {
+                None => Command::TurboIo(None),
+                Some("on") => Command::TurboIo(Some(true)),
+                Some("off") => Command::TurboIo(Some(false)),
+                _ => bail!("Usage: turboio [on|off]"),
+            },
+            Some("freq") => match parts.next() {
+                None => Command::Frequency(None),
+                Some("50") => Command::Frequency(Some(true)),
+                Some("60") => Command::Frequency(Some(false)),
+                _ => bail!("Usage: freq [50|60]"),
+            },
+            Some("mapper") => match (parts.next(), parts.next()) {
+                (Some("state"), None) => Command::Mapper(MapperCommand::State),
+                (Some("log"), None) => Command::Mapper(MapperCommand::Log(None)),
+                (Some("log"), Some("on")) => Command::Mapper(MapperCommand::Log(Some(true))),
+                (Some("log"), Some("off")) => Command::Mapper(MapperCommand::Log(Some(false))),
+                (Some("log"), Some("clear")) => Command::Mapper(MapperCommand::ClearLog),
+                _ => bail!("Usage: mapper state | log [on|off|clear]"),
+            },
+            Some("input") => match (parts.next(), parts.next()) {
+                (None, _) => Command::Input(None),
+                (Some("log"), None) => Command::InputLog(InputLogCommand::Log(None)),
+                (Some("log"), Some("on")) => Command::InputLog(InputLogCommand::Log(Some(true))),
+                (Some("log"), Some("off")) => Command::InputLog(InputLogCommand::Log(Some(false))),
+                (Some("log"), Some("clear")) => Command::InputLog(InputLogCommand::ClearLog),
+                (Some("log"), Some("save")) => {
+                    let Some(file) = parts.next() else {
+                        bail!("Usage: input log save <file>");
+                    };
+                    Command::InputLog(InputLogCommand::Save(PathBuf::from(file)))
+                }
+                (Some(name), None) => match InputProfile::find(name) {
+                    Some(profile) => Command::Input(Some(profile)),
+                    None => {
+                        bail!("Usage: input [keyboard|joystick] | log [on|off|clear|save <file>]")
+                    }
+                },
+                _ => bail!("Usage: input [keyboard|joystick] | log [on|off|clear|save <file>]"),
+            },
+            Some("screen") => match (parts.next(), parts.next()) {
+                (None, _) => Command::Screen(None),
+                (Some("--follow"), None | Some("on")) => Command::Screen(Some(true)),
+                (Some("--follow"), Some("off")) => Command::Screen(Some(false)),
+                _ => bail!("Usage: screen [--follow [off]]"),
+            },
+            Some("history") | Some("hist") => {
+                let n = match parts.next() {
+                    Some(n) => n.parse()?,
+                    None => 20,
+                };
+                Command::History(n)
+            }
+            Some("loadbin") | Some("lb") => {
+                let Some(file) = parts.next() else {
+                    bail!("Usage: loadbin <file> [run]");
+                };
+                let run = matches!(parts.next(), Some("run"));
+                Command::LoadBin(PathBuf::from(file), run)
+            }
+            Some("disasm") => match parts.next() {
+                Some("export") => {
+                    let (Some(start), Some(end), Some(file)) =
+                        (parts.next(), parts.next(), parts.next())
+                    else {
+                        bail!("Usage: disasm export <start> <end> <file>");
+                    };
+                    let start = u16::from_str_radix(start, 16)?;
+                    let end = u16::from_str_radix(end, 16)?;
+                    if end < start {
+                        bail!("End address must not be before the start address");
+                    }
+                    Command::DisasmExport(start, end, PathBuf::from(file))
+                }
+                start => {
+                    let start = start.map(|s| u16::from_str_radix(s, 16)).transpose()?;
+                    let count = match parts.next() {
+                        Some(count) => count.parse()?,
+                        None => DISASM_COUNT,
+                    };
+                    Command::Disasm(start, count)
+                }
+            },
+            Some("savestate") | Some("save") => {
+                let Some(file) = parts.next() else {
+                    bail!("Usage: savestate <file>");
+                };
+                Command::SaveState(PathBuf::from(file))
+            }
+            Some("loadstate") | Some("load") => {
+                let Some(file) = parts.next() else {
+                    bail!("Usage: loadstate <file>");
+                };
+                Command::LoadState(PathBuf::from(file))
+            }
+            Some("rominfo") | Some("ri") => Command::RomInfo(parts.next().map(String::from)),
+            Some("palette") | Some("pal") => Command::Palette(parts.next().map(String::from)),
+            Some("watch") | Some("w") => match parts.next() {
+                Some(expression) => Command::Watch(Some(expression.parse()?)),
+                None => Command::Watch(None),
+            },
+            Some("unwatch") | Some("uw") => {
+                let Some(expression) = parts.next() else {
+                    bail!("Usage: unwatch <reg|addr|start-end>");
+                };
+                Command::Unwatch(expression.parse()?)
+            }
+            Some("view") | Some("v") => {
+                let Some(addr) = parts.next() else {
+                    bail!("Usage: view <addr>");
+                };
+                Command::View(u16::from_str_radix(addr, 16)?)
+            }
+            Some("loadbas") | Some("lbas") => {
+                let Some(file) = parts.next() else {
+                    bail!("Usage: loadbas <file>");
+                };
+                Command::LoadBasic(PathBuf::from(file))
+            }
+            Some("breaktext") | Some("bt") => {
+                // the text is everything after the command, optionally quoted
+                let text = line.trim_start();
+                let text = text[text.find(char::is_whitespace).unwrap_or(text.len())..].trim();
+                let text = text
+                    .strip_prefix('"')
+                    .and_then(|t| t.strip_suffix('"'))
+                    .unwrap_or(text);
+                return Ok(Self {
+                    command: Command::BreakText(text.to_string()),
+                    args: Vec::new(),
+                });
+            }
+            Some("at") => {
+                let command = Command::At(parse_timer(line)?);
+                return Ok(Self {
+                    command,
+                    args: Vec::new(),
+                });
+            }
+            Some("type") => {
+                // the text is everything after the command, optionally quoted
+                let text = line.trim_start()["type".len()..].trim();
+                let text = text
+                    .strip_prefix('"')
+                    .and_then(|t| t.strip_suffix('"'))
+                    .unwrap_or(text);
+                let command = Command::Type(text.to_string());
+                return Ok(Self {
+                    command,
+                    args: Vec::new(),
+                });
+            }
+            Some(name) => match closest(name) {
+                Some(suggestion) => {
+                    bail!("Invalid command: {}, did you mean {}?", name, suggestion)
+                }
+                None => bail!("Invalid command: {}, type help for the commands", name),
+            },
+            None => bail!("Type help for the commands"),
+        };
+
+        let args = parts.map(|s| s.to_string()).collect();
+
+        Ok(Self { command, args })
+    }
+}
+
+// splits the --json flag from the only other, optional, argument of a command
+fn json_flag<'a>(parts: impl Iterator<Item = &'a str>) -> anyhow::Result<(Option<&'a str>, bool)> {
+    let mut json = false;
+    let mut arg = None;
+    for part in parts {
+        match part {
+            "--json" => json = true,
+            _ if arg.is_none() => arg = Some(part),
+            _ => bail!("Unexpected argument: {}", part),
+        }
+    }
+    Ok((arg, json))
+}
+
+// `at frame|cycle [+]<n> do <command>`, keeping the command as typed for
+// when it runs, and the listing and removal of the timers
+fn parse_timer(line: &str) -> anyhow::Result<TimerCommand> {
+    const USAGE: &str = "Usage: at [frame|cycle [+]<n> do <command>] | delete <id> | clear";
+    let (head, command) = match line.split_once(" do ") {
+        Some((head, command)) => (head, Some(command.trim())),
+        None => (line, None),
+    };
+    let mut parts = head.split_whitespace().skip(1);
+    let timer = match (parts.next(), parts.next(), command) {
+        (None | Some("list"), None, None) => TimerCommand::List,
+        (Some("delete"), id, None) => TimerCommand::Delete(parse_id(id)?),
+        (Some("clear"), None, None) => TimerCommand::Clear,
+        (Some(clock @ ("frame" | "cycle")), Some(at), Some(command)) if !command.is_empty() => {
+            // checked now rather than when it runs, when nobody may be
+            // watching
+            CommandLine::parse(command)?;
+            let clock = if clock == "frame" {
+                TimerClock::Frame
+            } else {
+                TimerClock::Cycle
+            };
+            let (at, relative) = match at.strip_prefix('+') {
+                Some(at) => (at, true),
+                None => (at, false),
+            };
+            TimerCommand::Add {
+                clock,
+                at: at.parse().map_err(|_| anyhow!(USAGE))?,
+                relative,
+                command: command.to_string(),
+            }
+        }
+        _ => bail!(USAGE),
+    };
+    if parts.next().is_some() {
+        bail!(USAGE);
+    }
+    Ok(timer)
+}
+
+fn parse_id(s: Option<&str>) -> anyhow::Result<usize> {
+    let id = s.ok_or_else(|| anyhow!("Missing breakpoint id"))?;
+    Ok(id.trim_start_matches('#').parse()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_errors() {
+        for (line, error) in [
+            ("mem", "Usage: mem <addr> [value]"),
+            ("set x", "Usage: set a|b|c|hl|(hl) <value>"),
+            ("mapper log all", "Usage: mapper state | log [on|off|clear]"),
+            ("input log save", "Usage: input log save <file>"),
+            ("reset warm", "Usage: reset [soft|hard|power]"),
+            (
+                "log vdp loud",
+                "Usage: log [<device> [off|error|warn|info|debug|trace]]",
+            ),
+            (
+                "at frame 600 type RUN",
+                "Usage: at [frame|cycle [+]<n> do <command>] | delete <id> | clear",
+            ),
+            (
+                "at cycle 10 do stpe",
+                "Invalid command: stpe, did you mean step?",
+            ),
+            ("stpe", "Invalid command: stpe, did you mean step?"),
+            ("", "Type help for the commands"),
+        ] {
+            assert_eq!(CommandLine::parse(line).unwrap_err().to_string(), error);
+        }
+        assert!(matches!(
+            CommandLine::parse("disasm").unwrap().command,
+            Command::Disasm(None, DISASM_COUNT)
+        ));
+        assert!(matches!(
+            CommandLine::parse("disasm c000 4").unwrap().command,
+            Command::Disasm(Some(0xC000), 4)
+        ));
+        assert!(matches!(
+            CommandLine::parse("reset").unwrap().command,
+            Command::Reset(ResetKind::Soft)
+        ));
+        assert!(matches!(
+            CommandLine::parse("reset power").unwrap().command,
+            Command::Reset(ResetKind::PowerCycle)
+        ));
+        assert!(matches!(
+            CommandLine::parse("at frame +600 do type \"RUN\\n\"").unwrap().command,
+            Command::At(TimerCommand::Add {
+                clock: TimerClock::Frame,
+                at: 600,
+                relative: true,
+                ref command,
+            }) if command == "type \"RUN\\n\""
+        ));
+    }
+}
@@ -0,0 +1,456 @@
+//! The debugger of the CLI, its DAP server and TUI, and of the web app:
+//! breakpoints, watch expressions, the steps over more than an instruction
+//! and the commands of the prompt, so that every frontend does the same.
+//!
+//! [`CommandLine::parse`](command::CommandLine::parse) turns a line into a
+//! [`Command`] and a [`Debugger`] runs those that need nothing but the
+//! machine, returning what they print. The others, e.g. the ones reading
+//! files or talking to openMSX, go back to the frontend, which runs them or
+//! tells they aren't available there.
+
+use std::fmt::Write;
+
+use msx::{vdp_timing, Msx};
+
+pub mod breakpoint;
+pub mod command;
+pub mod stepping;
+pub mod timer;
+pub mod watch;
+
+use breakpoint::Breakpoints;
+use command::{
+    help, BreakpointCommand, Command, InputLogCommand, MapperCommand, TapeCommand, TimerCommand,
+};
+use stepping::Stepping;
+use timer::Timers;
+use watch::WatchList;
+
+/// The state of the debugger every frontend shares, and the commands that
+/// run on the machine alone.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Debugger {
+    pub breakpoints: Breakpoints,
+    pub watches: WatchList,
+    pub stepping: Stepping,
+    pub timers: Timers,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Self {
+            breakpoints: Breakpoints::new(),
+            watches: WatchList::new(),
+            stepping: Stepping::new(),
+            timers: Timers::new(),
+        }
+    }
+
+    /// Runs a command on the machine, returning what it prints, or gives
+    /// the command back when the frontend has to run it.
+    ///
+    /// Frontends that do more at each instruction than the machine, e.g.
+    /// comparing it with another, step and run frames themselves.
+    pub fn execute(&mut self, msx: &mut Msx, command: Command) -> Result<String, Command> {
+        let mut output = String::new();
+        match command {
+            Command::Help(command) => output = help(command.as_deref()),
+            Command::Step(n) => {
+                for _ in 0..n {
+                    msx.step();
+                }
+                writeln!(output, "{}", msx.disassemble(msx.pc(), 1)[0]).unwrap();
+            }
+            Command::Frame(n) => {
+                for _ in 0..n {
+                    msx.step_frame();
+                }
+                writeln!(output, "{}", msx.disassemble(msx.pc(), 1)[0]).unwrap();
+            }
+            Command::Disasm(start, count) => {
+                for entry in msx.disassemble(start.unwrap_or(msx.pc()), count) {
+                    writeln!(output, "{}", entry).unwrap();
+                }
+                output.push('\n');
+            }
+            Command::MemGet(addr) => {
+                writeln!(output, "{:#06X}: {:#04X}", addr, msx.get_memory(addr)).unwrap();
+            }
+            Command::MemSet(addr, value) => msx.set_memory(addr, value),
+            Command::AddBreakpoint(addr, condition) => {
+                let id = self.breakpoints.add(addr, condition);
+                writeln!(output, "Breakpoint #{} at {:#06X}\n", id, addr).unwrap();
+            }
+            Command::TempBreakpoint(addr) => {
+                let id = self.breakpoints.add_temporary(addr);
+                writeln!(output, "Temporary breakpoint #{} at {:#06X}\n", id, addr).unwrap();
+            }
+            Command::RemoveBreakpoint(addr) => self.breakpoints.remove_address(addr),
+            Command::Watch(Some(expression)) => self.watches.add(expression, &msx.cpu),
+            Command::Unwatch(expression) => {
+                if !self.watches.remove(&expression) {
+                    writeln!(output, "{} isn't watched\n", expression).unwrap();
+                }
+            }
+            Command::Breakpoint(command) => {
+                let res = match command {
+                    BreakpointCommand::List => {
+                        output = self.list_breakpoints();
+                        Ok(())
+                    }
+                    BreakpointCommand::Enable(id, enabled) => {
+                        self.breakpoints.get_mut(id).map(|bp| bp.enabled = enabled)
+                    }
+                    BreakpointCommand::Ignore(id, count) => self
+                        .breakpoints
+                        .get_mut(id)
+                        .map(|bp| bp.ignore_count = count),
+                    BreakpointCommand::Condition(id, condition) => self
+                        .breakpoints
+                        .get_mut(id)
+                        .map(|bp| bp.condition = condition),
+                    BreakpointCommand::Delete(id) => self.breakpoints.remove(id).map(|_| ()),
+                };
+                if let Err(e) = res {
+                    writeln!(output, "Error: {}", e).unwrap();
+                }
+                output.push('\n');
+            }
+            Command::Mapper(MapperCommand::State) => output = self.mapper_state(msx),
+            Command::Mapper(MapperCommand::Log(Some(enabled))) => {
+                // turning it on again keeps what was logged
+                match enabled {
+                    true if !msx.bank_log_enabled() => msx.enable_bank_log(),
+                    true => {}
+                    false => msx.disable_bank_log(),
+                }
+                let state = if enabled { "on" } else { "off" };
+                writeln!(output, "Mapper log {}\n", state).unwrap();
+            }
+            Command::Mapper(MapperCommand::Log(None)) => output = self.mapper_log(msx),
+            Command::Mapper(MapperCommand::ClearLog) => msx.clear_bank_log(),
+            Command::InputLog(InputLogCommand::Log(Some(enabled))) => {
+                // turning it on again keeps what was logged
+                match enabled {
+                    true if !msx.input_log_enabled() => msx.enable_input_log(),
+                    true => {}
+                    false => msx.disable_input_log(),
+                }
+                let state = if enabled { "on" } else { "off" };
+                writeln!(output, "Input log {}\n", state).unwrap();
+            }
+            Command::InputLog(InputLogCommand::Log(None)) => output = self.input_log(msx),
+            Command::InputLog(InputLogCommand::ClearLog) => msx.clear_input_log(),
+            Command::Frequency(pal) => {
+                if let Some(pal) = pal {
+                    msx.set_pal(pal);
+                }
+                let pal = msx.pal();
+                writeln!(
+                    output,
+                    "VDP at {} Hz, {} lines a frame\n",
+                    vdp_timing::frequency(pal),
+                    vdp_timing::frame_t_states(pal) / vdp_timing::LINE_T_STATES
+                )
+                .unwrap();
+            }
+            Command::Tape(TapeCommand::Status) => output = self.tape_status(msx),
+            Command::Tape(TapeCommand::Eject) => match msx.eject_tape() {
+                Some(tape) => writeln!(output, "Ejected {}\n", tape.name()).unwrap(),
+                None => writeln!(output, "No tape in the recorder\n").unwrap(),
+            },
+            Command::Tape(TapeCommand::Rewind) => match msx.rewind_tape() {
+                Ok(()) => output = self.tape_status(msx),
+                Err(e) => writeln!(output, "Error: {}\n", e).unwrap(),
+            },
+            Command::TurboIo(enabled) => {
+                if let Some(enabled) = enabled {
+                    msx.turbo_io = enabled;
+                }
+                let state = if msx.turbo_io { "on" } else { "off" };
+                writeln!(output, "Turbo I/O {}\n", state).unwrap();
+            }
+            Command::Tape(TapeCommand::Record) => {
+                msx.start_tape_recording();
+                writeln!(output, "Recording the cassette output\n").unwrap();
+            }
+            Command::Tape(TapeCommand::Baud(baud)) => match msx.set_tape_baud(baud) {
+                Ok(()) => output = self.tape_status(msx),
+                Err(e) => writeln!(output, "Error: {}\n", e).unwrap(),
+            },
+            Command::At(TimerCommand::List) => output = self.list_timers(msx),
+            Command::At(TimerCommand::Add {
+                clock,
+                at,
+                relative,
+                command,
+            }) => {
+                let at = if relative { clock.now(msx) + at } else { at };
+                let id = self.timers.add(clock, at, &command);
+                writeln!(output, "Timer #{} at {} {}\n", id, clock, at).unwrap();
+            }
+            Command::At(TimerCommand::Delete(id)) => {
+                if let Err(e) = self.timers.remove(id) {
+                    writeln!(output, "Error: {}\n", e).unwrap();
+                }
+            }
+            Command::At(TimerCommand::Clear) => self.timers.clear(),
+            command => return Err(command),
+        }
+        Ok(output)
+    }
+
+    /// The banks each mapper selected, a line per slot.
+    pub fn mapper_state(&self, msx: &Msx) -> String {
+        let mappers = msx.mapper_banks();
+        if mappers.is_empty() {
+            return "No mappers in the slots.\n\n".to_string();
+        }
+        let mut output: String = mappers
+            .iter()
+            .map(|mapper| format!("{}\n", mapper))
+            .collect();
+        output.push('\n');
+        output
+    }
+
+    /// Where the tape in the recorder is, and what was recorded.
+    pub fn tape_status(&self, msx: &Msx) -> String {
+        let mut output = match msx.tape_status() {
+            Some(status) => format!("{}\n", status),
+            None => "No tape in the recorder\n".to_string(),
+        };
+        if let Some(cas) = msx.tape_recording() {
+            writeln!(output, "Recording: {}", cas).unwrap();
+        }
+        output.push('\n');
+        output
+    }
+
+    /// The logged bank switches, oldest first.
+    pub fn mapper_log(&self, msx: &Msx) -> String {
+        if !msx.bank_log_enabled() {
+            return "The mapper log is off, turn it on with mapper log on.\n\n".to_string();
+        }
+        let switches = msx.bank_switches();
+        if switches.is_empty() {
+            return "No bank switches.\n\n".to_string();
+        }
+        let mut output: String = switches
+            .iter()
+            .map(|switch| format!("{}\n", switch))
+            .collect();
+        output.push('\n');
+        output
+    }
+
+    /// The logged presses and releases, oldest first.
+    pub fn input_log(&self, msx: &Msx) -> String {
+        if !msx.input_log_enabled() {
+            return "The input log is off, turn it on with input log on.\n\n".to_string();
+        }
+        let events = msx.input_events();
+        if events.is_empty() {
+            return "No input events.\n\n".to_string();
+        }
+        let mut output: String = events.iter().map(|event| format!("{}\n", event)).collect();
+        output.push('\n');
+        output
+    }
+
+    /// The queued timers, with the frame and cycle the machine is at.
+    pub fn list_timers(&self, msx: &Msx) -> String {
+        let mut output = format!("At frame {}, cycle {}.\n", msx.frames(), msx.cpu.cycles);
+        if self.timers.is_empty() {
+            output.push_str("No timers.\n");
+        }
+        for timer in self.timers.iter() {
+            writeln!(output, "{}", timer).unwrap();
+        }
+        output.push('\n');
+        output
+    }
+
+    /// The breakpoints, one per line.
+    pub fn list_breakpoints(&self) -> String {
+        if self.breakpoints.is_empty() {
+            return "No breakpoints.\n".to_string();
+        }
+        self.breakpoints
+            .iter()
+            .map(|bp| format!("{}\n", bp))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use msx::{
+        ram_cartridge::MegaRam,
+        slot::{RamSlot, SlotType},
+    };
+
+    use super::*;
+    use crate::command::CommandLine;
+
+    fn run(debugger: &mut Debugger, msx: &mut Msx, line: &str) -> String {
+        let command = CommandLine::parse(line).unwrap().command;
+        match debugger.execute(msx, command) {
+            Ok(output) => output,
+            Err(_) => panic!("{} wasn't run", line),
+        }
+    }
+
+    #[test]
+    fn test_execute() {
+        // LD A,1 / INC A / JR $-1
+        let mut msx = Msx::new(&[
+            SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Empty,
+        ]);
+        for (addr, value) in [0x3E, 0x01, 0x3C, 0x18, 0xFD].into_iter().enumerate() {
+            msx.set_memory(addr as u16, value);
+        }
+        let mut debugger = Debugger::new();
+
+        assert!(run(&mut debugger, &mut msx, "step 2").starts_with("0003  18 FD"));
+        assert_eq!(msx.cpu.a, 2);
+
+        run(&mut debugger, &mut msx, "mem c000 5a");
+        assert_eq!(run(&mut debugger, &mut msx, "m c000"), "0xC000: 0x5A\n");
+
+        let disasm = run(&mut debugger, &mut msx, "disasm 0 2");
+        assert_eq!(disasm.lines().count(), 3);
+        assert!(disasm.lines().nth(1).unwrap().contains("INC A"));
+
+        assert_eq!(
+            run(&mut debugger, &mut msx, "bp 2 if a == 3"),
+            "Breakpoint #1 at 0x0002\n\n"
+        );
+        run(&mut debugger, &mut msx, "bp disable 1");
+        assert_eq!(
+            run(&mut debugger, &mut msx, "bp"),
+            "#1 0x0002 disabled hits: 0 if a == 0x3\n\n"
+        );
+        assert_eq!(
+            run(&mut debugger, &mut msx, "bp delete 7"),
+            "Error: No breakpoint #7\n\n"
+        );
+
+        // the frontend runs the rest
+        let command = CommandLine::parse("cont").unwrap().command;
+        assert!(matches!(
+            debugger.execute(&mut msx, command),
+            Err(Command::Continue)
+        ));
+    }
+
+    #[test]
+    fn test_mapper() {
+        let mut msx = Msx::new(&[
+            SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+            SlotType::MegaRam(MegaRam::new(0x20000)),
+            SlotType::Empty,
+            SlotType::Empty,
+        ]);
+        let mut debugger = Debugger::new();
+        assert_eq!(
+            run(&mut debugger, &mut msx, "mapper log"),
+            "The mapper log is off, turn it on with mapper log on.\n\n"
+        );
+        run(&mut debugger, &mut msx, "mapper log on");
+        assert_eq!(
+            run(&mut debugger, &mut msx, "mapper log"),
+            "No bank switches.\n\n"
+        );
+
+        // page 1 from the MegaRAM, whose second window switches to bank 9
+        msx.set_memory(0x0000, 0xD3);
+        msx.set_memory(0x0001, 0xA8);
+        msx.set_memory(0x0002, 0x32);
+        msx.set_memory(0x0003, 0x00);
+        msx.set_memory(0x0004, 0x60);
+        msx.cpu.a = 0x04;
+        run(&mut debugger, &mut msx, "step");
+        msx.cpu.a = 0x09;
+        run(&mut debugger, &mut msx, "step");
+
+        assert_eq!(
+            run(&mut debugger, &mut msx, "mapper state"),
+            "slot 1 MegaRAM: 0 9 2 3\n\n"
+        );
+        assert_eq!(
+            run(&mut debugger, &mut msx, "mapper log"),
+            "#0002  slot 1 #6000 window 1: 1 -> 9  frame 0\n\n"
+        );
+        run(&mut debugger, &mut msx, "mapper log clear");
+        assert_eq!(
+            run(&mut debugger, &mut msx, "mapper log"),
+            "No bank switches.\n\n"
+        );
+    }
+
+    #[test]
+    fn test_timers() {
+        let mut msx = Msx::new(&[
+            SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Empty,
+        ]);
+        let mut debugger = Debugger::new();
+        run(&mut debugger, &mut msx, "step 3");
+        assert_eq!(
+            run(&mut debugger, &mut msx, "at cycle +10 do mem c000 1"),
+            "Timer #1 at cycle 13\n\n"
+        );
+        run(&mut debugger, &mut msx, "at frame 600 do type \"RUN\\n\"");
+        assert_eq!(
+            run(&mut debugger, &mut msx, "at"),
+            "At frame 0, cycle 3.\n\
+             #1 at cycle 13 do mem c000 1\n\
+             #2 at frame 600 do type \"RUN\\n\"\n\n"
+        );
+        run(&mut debugger, &mut msx, "at delete 1");
+        assert_eq!(
+            run(&mut debugger, &mut msx, "at delete 1"),
+            "Error: No timer #1\n\n"
+        );
+        run(&mut debugger, &mut msx, "at clear");
+        assert!(debugger.timers.is_empty());
+    }
+
+    #[test]
+    fn test_input_log() {
+        let mut msx = Msx::new(&[
+            SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Empty,
+        ]);
+        let mut debugger = Debugger::new();
+        assert_eq!(
+            run(&mut debugger, &mut msx, "input log"),
+            "The input log is off, turn it on with input log on.\n\n"
+        );
+        run(&mut debugger, &mut msx, "input log on");
+        // NOP / NOP
+        msx.set_memory(0x0000, 0x00);
+        msx.set_memory(0x0001, 0x00);
+        msx.set_key(8, 0, true);
+        run(&mut debugger, &mut msx, "step 2");
+        msx.set_key(8, 0, false);
+        assert_eq!(
+            run(&mut debugger, &mut msx, "input log"),
+            "#0000  key 8 0 down  frame 0 cycle 0\n\
+             #0002  key 8 0 up, read 0 times while down  frame 0 cycle 2\n\n"
+        );
+        run(&mut debugger, &mut msx, "input log clear");
+        assert_eq!(
+            run(&mut debugger, &mut msx, "input log"),
+            "No input events.\n\n"
+        );
+    }
+}
@@ -0,0 +1,267 @@
+//! The steps that run more than one instruction: to an address, out of the
+//! routine, or by source line over or into the calls.
+//!
+//! The frontend asks [`Stepping::before`] about the instruction about to
+//! run and passes the answer to [`Stepping::after`] once it ran, which
+//! tells whether the step is over.
+
+use msx::{
+    source_map::{SourceLine, SourceMap},
+    Msx,
+};
+
+/// Where a step in progress stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepStop {
+    /// the address run to was reached
+    Reached(u16),
+    /// the routine stepped out of returned to this address
+    Returned(u16),
+    /// the PC is at the start of another source line
+    Line,
+}
+
+/// What the instruction about to run means to the step in progress.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Pending {
+    // a RET, taken or not
+    returning: bool,
+    // return address and SP of a call stepped over
+    call: Option<(u16, u16)>,
+}
+
+/// A step by source line, running until the PC is at the start of another
+/// line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct LineStep {
+    from: SourceLine,
+    sp: u16,
+    /// stops in the calls the line makes, rather than stepping over them
+    into: bool,
+    // return address and SP of the call being stepped over
+    call: Option<(u16, u16)>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stepping {
+    until: Option<u16>,
+    // SP of the routine stepped out of
+    step_out: Option<u16>,
+    line_step: Option<LineStep>,
+}
+
+impl Stepping {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether a step is in progress.
+    pub fn is_active(&self) -> bool {
+        self.until.is_some() || self.step_out.is_some() || self.line_step.is_some()
+    }
+
+    /// Ends the step in progress, as any stop does.
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Runs until the address is reached, without adding a breakpoint.
+    pub fn run_until(&mut self, address: u16) {
+        self.until = Some(address);
+    }
+
+    /// Runs until the routine at the PC returns.
+    pub fn step_out(&mut self, msx: &Msx) {
+        self.step_out = Some(msx.cpu.sp);
+    }
+
+    /// Runs over the instruction at the PC, returning `false` when it
+    /// doesn't come back to the next one and a single step does the same.
+    pub fn step_over(&mut self, msx: &Msx) -> bool {
+        if !at_call(msx) {
+            return false;
+        }
+        self.until = Some(msx.disassemble(msx.pc(), 2)[1].address);
+        true
+    }
+
+    /// Runs until the PC is on another line than `from`, the line at the
+    /// PC, stopping in the calls it makes `into` them.
+    pub fn step_line(&mut self, msx: &Msx, from: SourceLine, into: bool) {
+        self.line_step = Some(LineStep {
+            from,
+            sp: msx.cpu.sp,
+            into,
+            call: None,
+        });
+    }
+
+    /// Looks at the instruction about to run, for [`Stepping::after`].
+    pub fn before(&self, msx: &Msx) -> Pending {
+        let stepping = self.step_out.is_some() || self.line_step.is_some();
+        let call = match &self.line_step {
+            Some(step) if !step.into && step.call.is_none() && at_call(msx) => {
+                Some((msx.disassemble(msx.pc(), 2)[1].address, msx.cpu.sp))
+            }
+            _ => None,
+        };
+        Pending {
+            returning: stepping && at_return(msx),
+            call,
+        }
+    }
+
+    /// Whether the step is over after the instruction `pending` was told
+    /// about ran, ending it.
+    pub fn after(
+        &mut self,
+        msx: &Msx,
+        source_map: &SourceMap,
+        pending: Pending,
+    ) -> Option<StepStop> {
+        let pc = msx.pc();
+        if self.until == Some(pc) {
+            self.until = None;
+            return Some(StepStop::Reached(pc));
+        }
+        if let Some(sp) = self.step_out {
+            if pending.returning && msx.cpu.sp > sp {
+                self.step_out = None;
+                return Some(StepStop::Returned(pc));
+            }
+        }
+        self.line_step_done(msx, source_map, pending)
+            .then_some(StepStop::Line)
+    }
+
+    // whether a step by source line is over, after an instruction that made
+    // a call or that was a RET
+    fn line_step_done(&mut self, msx: &Msx, source_map: &SourceMap, pending: Pending) -> bool {
+        let Some(step) = &mut self.line_step else {
+            return false;
+        };
+        let pc = msx.pc();
+        let sp = msx.cpu.sp;
+
+        if let Some(call) = pending.call {
+            // a call not taken goes on to the next line right away
+            if pc != call.0 {
+                step.call = Some(call);
+                return false;
+            }
+        }
+        if let Some(call) = step.call {
+            if (pc, sp) != call {
+                return false;
+            }
+            step.call = None;
+        }
+
+        // returning from the line's routine stops in the caller, even
+        // without source
+        let done = match source_map.location(pc) {
+            Some(line) => line != step.from,
+            None => pending.returning && sp > step.sp,
+        };
+        if done {
+            self.line_step = None;
+        }
+        done
+    }
+}
+
+/// Whether the instruction at PC comes back to the next one, a CALL, a RST
+/// or a repeating block instruction like LDIR.
+pub fn at_call(msx: &Msx) -> bool {
+    let pc = msx.pc();
+    match msx.cpu.read_byte(pc) {
+        0xCD | 0xC4 | 0xCC | 0xD4 | 0xDC | 0xE4 | 0xEC | 0xF4 | 0xFC => true,
+        0xC7 | 0xCF | 0xD7 | 0xDF | 0xE7 | 0xEF | 0xF7 | 0xFF => true,
+        0xED => matches!(
+            msx.cpu.read_byte(pc.wrapping_add(1)),
+            0xB0..=0xB3 | 0xB8..=0xBB
+        ),
+        _ => false,
+    }
+}
+
+/// Whether the instruction at PC is a RET, RETI or RETN, taken or not.
+pub fn at_return(msx: &Msx) -> bool {
+    let pc = msx.pc();
+    match msx.cpu.read_byte(pc) {
+        0xC9 | 0xC0 | 0xC8 | 0xD0 | 0xD8 | 0xE0 | 0xE8 | 0xF0 | 0xF8 => true,
+        0xED => matches!(
+            msx.cpu.read_byte(pc.wrapping_add(1)),
+            0x45 | 0x4D | 0x55 | 0x5D | 0x65 | 0x6D | 0x75 | 0x7D
+        ),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use msx::slot::{RamSlot, SlotType};
+
+    use super::*;
+
+    // CALL 0010 / NOP at 0, and at 0010 INC A / RET
+    fn msx() -> Msx {
+        let mut msx = Msx::new(&[
+            SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Empty,
+        ]);
+        for (address, value) in [
+            (0, 0xCD),
+            (1, 0x10),
+            (2, 0x00),
+            (3, 0x00),
+            (0x10, 0x3C),
+            (0x11, 0xC9),
+        ] {
+            msx.set_memory(address, value);
+        }
+        msx.cpu.sp = 0xF000;
+        msx
+    }
+
+    // steps until the step is over, at most a few instructions
+    fn run(stepping: &mut Stepping, msx: &mut Msx) -> Option<StepStop> {
+        let source_map = SourceMap::default();
+        for _ in 0..10 {
+            let pending = stepping.before(msx);
+            msx.step();
+            if let Some(stop) = stepping.after(msx, &source_map, pending) {
+                return Some(stop);
+            }
+        }
+        None
+    }
+
+    #[test]
+    fn test_step_over() {
+        let mut msx = msx();
+        let a = msx.cpu.a;
+        let mut stepping = Stepping::new();
+        assert!(stepping.step_over(&msx));
+        assert_eq!(run(&mut stepping, &mut msx), Some(StepStop::Reached(3)));
+        assert_eq!(msx.cpu.a, a.wrapping_add(1));
+        assert!(!stepping.is_active());
+
+        // not at a call
+        assert!(!stepping.step_over(&msx));
+    }
+
+    #[test]
+    fn test_step_out() {
+        let mut msx = msx();
+        msx.step();
+        assert_eq!(msx.pc(), 0x10);
+
+        let mut stepping = Stepping::new();
+        stepping.step_out(&msx);
+        assert_eq!(run(&mut stepping, &mut msx), Some(StepStop::Returned(3)));
+        assert!(!stepping.is_active());
+    }
+}
@@ -0,0 +1,163 @@
+use std::fmt;
+
+use anyhow::anyhow;
+
+use msx::Msx;
+
+/// What a timer counts to go off: the frames the VDP drew or the
+/// instructions the CPU ran, both in emulated time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TimerClock {
+    Frame,
+    Cycle,
+}
+
+impl TimerClock {
+    /// Current count of the clock on the machine.
+    pub fn now(&self, msx: &Msx) -> u64 {
+        match self {
+            TimerClock::Frame => msx.frames(),
+            TimerClock::Cycle => msx.cpu.cycles,
+        }
+    }
+}
+
+impl fmt::Display for TimerClock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimerClock::Frame => write!(f, "frame"),
+            TimerClock::Cycle => write!(f, "cycle"),
+        }
+    }
+}
+
+/// A command of the prompt to run once the machine reaches a frame or a
+/// cycle.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Timer {
+    pub id: usize,
+    pub clock: TimerClock,
+    pub at: u64,
+    pub command: String,
+}
+
+impl fmt::Display for Timer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "#{} at {} {} do {}",
+            self.id, self.clock, self.at, self.command
+        )
+    }
+}
+
+/// Commands queued for later in emulated time, e.g. typing once BASIC is
+/// up, which the frontends run as if typed on the prompt when their frame
+/// or cycle comes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Timers {
+    entries: Vec<Timer>,
+    next_id: usize,
+}
+
+impl Timers {
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+            next_id: 1,
+        }
+    }
+
+    /// Queues a command, returning the id of its timer.
+    pub fn add(&mut self, clock: TimerClock, at: u64, command: &str) -> usize {
+        // ids start at 1 for a default instance too
+        let id = self.next_id.max(1);
+        self.next_id = id + 1;
+        self.entries.push(Timer {
+            id,
+            clock,
+            at,
+            command: command.to_string(),
+        });
+        id
+    }
+
+    pub fn remove(&mut self, id: usize) -> anyhow::Result<Timer> {
+        let index = self
+            .entries
+            .iter()
+            .position(|timer| timer.id == id)
+            .ok_or_else(|| anyhow!("No timer #{}", id))?;
+        Ok(self.entries.remove(index))
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Timer> {
+        self.entries.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Removes and returns the timers the machine reached, in the order
+    /// they were due, those due together in the order they were added.
+    pub fn due(&mut self, msx: &Msx) -> Vec<Timer> {
+        if self.entries.is_empty() {
+            return Vec::new();
+        }
+        let frame = TimerClock::Frame.now(msx);
+        let cycle = TimerClock::Cycle.now(msx);
+        let (mut due, pending): (Vec<_>, Vec<_>) =
+            self.entries.drain(..).partition(|timer| match timer.clock {
+                TimerClock::Frame => timer.at <= frame,
+                TimerClock::Cycle => timer.at <= cycle,
+            });
+        self.entries = pending;
+        due.sort_by_key(|timer| (timer.clock, timer.at));
+        due
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use msx::slot::{RamSlot, SlotType};
+
+    use super::*;
+
+    #[test]
+    fn test_due() {
+        // JR $
+        let mut msx = Msx::new(&[
+            SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Empty,
+        ]);
+        msx.set_memory(0x0000, 0x18);
+        msx.set_memory(0x0001, 0xFE);
+        let mut timers = Timers::new();
+        timers.add(TimerClock::Frame, 2, "type RUN");
+        timers.add(TimerClock::Cycle, 10, "bp 100");
+        let last = timers.add(TimerClock::Cycle, 5, "mem c000 1");
+        assert!(timers.due(&msx).is_empty());
+
+        for _ in 0..10 {
+            msx.step();
+        }
+        let due: Vec<_> = timers.due(&msx).into_iter().map(|t| t.id).collect();
+        assert_eq!(due, [last, 2]);
+        assert_eq!(timers.iter().count(), 1);
+
+        while msx.frames() < 2 {
+            msx.step();
+        }
+        let due = timers.due(&msx);
+        assert_eq!(due[0].to_string(), "#1 at frame 2 do type RUN");
+        assert!(timers.is_empty());
+        assert_eq!(timers.remove(1).unwrap_err().to_string(), "No timer #1");
+    }
+}
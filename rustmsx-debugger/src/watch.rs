@@ -0,0 +1,265 @@
+//! Watch expressions, shown with their values by the debuggers, which tell
+//! the values that changed since they last looked.
+//!
+//! An expression is a register or `(hl)`, as in the breakpoint conditions,
+//! an address in hex for the byte and the word there, or a range of
+//! addresses like `c000-c00f`. The registers come first, so the address
+//! `c` is written `000c`.
+
+use std::{fmt, str::FromStr};
+
+use anyhow::{anyhow, bail};
+
+use msx::Z80;
+
+use crate::breakpoint::Operand;
+
+// most bytes a range shows
+const MAX_RANGE: u16 = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchExpr {
+    /// a register, or the byte at (HL) or at an address
+    Register(Operand),
+    /// the byte and the word at an address
+    Address(u16),
+    /// the bytes from the first address through the last
+    Range(u16, u16),
+}
+
+impl WatchExpr {
+    /// The current values: the register, the byte and the word at the
+    /// address, or the bytes of the range.
+    pub fn read(&self, cpu: &Z80) -> Vec<u16> {
+        match *self {
+            WatchExpr::Register(operand) => vec![operand.value(cpu)],
+            WatchExpr::Address(address) => {
+                vec![cpu.read_byte(address) as u16, cpu.read_word(address)]
+            }
+            WatchExpr::Range(start, end) => (start..=end)
+                .map(|address| cpu.read_byte(address) as u16)
+                .collect(),
+        }
+    }
+
+    /// The values in hex, as wide as what they were read from.
+    pub fn format(&self, values: &[u16]) -> Vec<String> {
+        values
+            .iter()
+            .enumerate()
+            .map(|(n, value)| match (self, n) {
+                (WatchExpr::Register(operand), _) if operand.is_word() => {
+                    format!("#{:04X}", value)
+                }
+                (WatchExpr::Address(_), 1) => format!("#{:04X}", value),
+                (WatchExpr::Range(..), _) => format!("{:02X}", value),
+                _ => format!("#{:02X}", value),
+            })
+            .collect()
+    }
+
+    /// The first address of a memory expression.
+    pub fn address(&self) -> Option<u16> {
+        match *self {
+            WatchExpr::Register(Operand::Memory(address)) => Some(address),
+            WatchExpr::Register(_) => None,
+            WatchExpr::Address(address) | WatchExpr::Range(address, _) => Some(address),
+        }
+    }
+}
+
+impl FromStr for WatchExpr {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Ok(operand) = s.parse::<Operand>() {
+            return Ok(WatchExpr::Register(operand));
+        }
+        match s.split_once('-') {
+            Some((start, end)) => {
+                let (start, end) = (parse_address(start)?, parse_address(end)?);
+                if end < start || end - start >= MAX_RANGE {
+                    bail!("A range has from 1 to {} bytes", MAX_RANGE);
+                }
+                Ok(WatchExpr::Range(start, end))
+            }
+            None => Ok(WatchExpr::Address(parse_address(s)?)),
+        }
+    }
+}
+
+impl fmt::Display for WatchExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WatchExpr::Register(operand) => write!(f, "{}", operand),
+            WatchExpr::Address(address) => write!(f, "{:04X}", address),
+            WatchExpr::Range(start, end) => write!(f, "{:04X}-{:04X}", start, end),
+        }
+    }
+}
+
+// an address in hex, with or without a prefix
+pub(crate) fn parse_address(s: &str) -> anyhow::Result<u16> {
+    let s = s.trim();
+    let hex = ["0x", "0X", "#", "$"]
+        .iter()
+        .find_map(|prefix| s.strip_prefix(prefix))
+        .unwrap_or(s);
+    u16::from_str_radix(hex, 16).map_err(|_| anyhow!("Invalid register or address: {}", s))
+}
+
+/// An expression with its values as of the last [`WatchList::update`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Watch {
+    pub expression: WatchExpr,
+    pub values: Vec<u16>,
+    /// which of the values changed at the last update
+    pub changed: Vec<bool>,
+}
+
+impl Watch {
+    /// The values in hex, see [`WatchExpr::format`].
+    pub fn formatted(&self) -> Vec<String> {
+        self.expression.format(&self.values)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WatchList {
+    watches: Vec<Watch>,
+}
+
+impl WatchList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an expression, reading its values, unless it's watched already.
+    pub fn add(&mut self, expression: WatchExpr, cpu: &Z80) {
+        if self.watches.iter().any(|w| w.expression == expression) {
+            return;
+        }
+        let values = expression.read(cpu);
+        self.watches.push(Watch {
+            expression,
+            changed: vec![false; values.len()],
+            values,
+        });
+    }
+
+    /// Removes an expression, returning whether it was watched.
+    pub fn remove(&mut self, expression: &WatchExpr) -> bool {
+        let len = self.watches.len();
+        self.watches.retain(|w| w.expression != *expression);
+        self.watches.len() != len
+    }
+
+    /// Reads the values again, marking those that changed since the last
+    /// update.
+    pub fn update(&mut self, cpu: &Z80) {
+        for watch in &mut self.watches {
+            let values = watch.expression.read(cpu);
+            watch.changed = values
+                .iter()
+                .zip(&watch.values)
+                .map(|(value, previous)| value != previous)
+                .collect();
+            watch.values = values;
+        }
+    }
+
+    pub fn watches(&self) -> &[Watch] {
+        &self.watches
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.watches.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use msx::{
+        slot::{RamSlot, SlotType},
+        Msx,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            "HL".parse::<WatchExpr>().unwrap(),
+            WatchExpr::Register(Operand::HL)
+        );
+        assert_eq!(
+            "(hl)".parse::<WatchExpr>().unwrap(),
+            WatchExpr::Register(Operand::HLAddress)
+        );
+        assert_eq!(
+            "c000".parse::<WatchExpr>().unwrap(),
+            WatchExpr::Address(0xC000)
+        );
+        assert_eq!(
+            "0xC000-#c00f".parse::<WatchExpr>().unwrap(),
+            WatchExpr::Range(0xC000, 0xC00F)
+        );
+        assert_eq!(
+            "c00f-c000".parse::<WatchExpr>().unwrap_err().to_string(),
+            "A range has from 1 to 256 bytes"
+        );
+        assert_eq!(
+            "xyz".parse::<WatchExpr>().unwrap_err().to_string(),
+            "Invalid register or address: xyz"
+        );
+
+        // the text parses back
+        for text in ["bc", "(0xF3F8)", "C000", "C000-C003"] {
+            let expression = text.parse::<WatchExpr>().unwrap();
+            assert_eq!(
+                expression.to_string().parse::<WatchExpr>().unwrap(),
+                expression
+            );
+        }
+    }
+
+    #[test]
+    fn test_watch_list() {
+        let mut msx = Msx::new(&[
+            SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Empty,
+        ]);
+        msx.cpu.write_word(0xC000, 0x1234);
+        msx.cpu.set_hl(0xABCD);
+
+        let mut watches = WatchList::new();
+        for text in ["hl", "c000", "c000-c002", "c000"] {
+            watches.add(text.parse().unwrap(), &msx.cpu);
+        }
+        assert_eq!(watches.watches().len(), 3);
+        let formatted: Vec<Vec<String>> = watches.watches().iter().map(Watch::formatted).collect();
+        assert_eq!(
+            formatted,
+            [vec!["#ABCD"], vec!["#34", "#1234"], vec!["34", "12", "FF"],]
+        );
+
+        msx.cpu.write_byte(0xC001, 0x56);
+        watches.update(&msx.cpu);
+        let changed: Vec<&[bool]> = watches.watches().iter().map(|w| &w.changed[..]).collect();
+        assert_eq!(
+            changed,
+            [&[false][..], &[false, true], &[false, true, false]]
+        );
+
+        // changes are since the last update
+        watches.update(&msx.cpu);
+        assert!(watches.watches().iter().all(|w| !w.changed.contains(&true)));
+
+        assert!(watches.remove(&WatchExpr::Address(0xC000)));
+        assert!(!watches.remove(&WatchExpr::Address(0xC000)));
+        assert_eq!(watches.watches().len(), 2);
+    }
+}
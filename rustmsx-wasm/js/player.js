@@ -0,0 +1,43 @@
+// Defines the <rust-msx> element. Each element gets a player made by the
+// wasm module, which keeps running the machine until it's freed.
+
+export function defineElement(create) {
+  if (customElements.get("rust-msx")) {
+    return;
+  }
+
+  customElements.define(
+    "rust-msx",
+    class extends HTMLElement {
+      static get observedAttributes() {
+        return ["src", "size", "preset"];
+      }
+
+      connectedCallback() {
+        if (!this.shadowRoot) {
+          const canvas = document.createElement("canvas");
+          canvas.width = 256;
+          canvas.height = 192;
+          canvas.tabIndex = 0;
+          const style = document.createElement("style");
+          style.textContent =
+            ":host { display: inline-block; } canvas { display: block; image-rendering: pixelated; background: #000; }";
+          this.attachShadow({ mode: "open" }).append(style, canvas);
+        }
+        this.player = create(this, this.shadowRoot.querySelector("canvas"));
+      }
+
+      disconnectedCallback() {
+        this.player.free();
+        this.player = null;
+      }
+
+      attributeChangedCallback(name) {
+        // the player reads the initial attributes itself
+        if (this.player) {
+          this.player.attributeChanged(name);
+        }
+      }
+    }
+  );
+}
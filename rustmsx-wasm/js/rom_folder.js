@@ -0,0 +1,83 @@
+// Remembers a local ROM folder with the File System Access API. The handle
+// is kept in IndexedDB, the only storage that can hold it, so that the
+// browser only asks to confirm the access on later visits.
+
+const DB_NAME = "rustmsx";
+const STORE = "handles";
+const KEY = "rom-folder";
+const EXTENSIONS = [".rom", ".zip", ".gz"];
+
+function openDb() {
+  return new Promise((resolve, reject) => {
+    const request = indexedDB.open(DB_NAME, 1);
+    request.onupgradeneeded = () => request.result.createObjectStore(STORE);
+    request.onsuccess = () => resolve(request.result);
+    request.onerror = () => reject(request.error);
+  });
+}
+
+async function withStore(mode, action) {
+  const db = await openDb();
+  return new Promise((resolve, reject) => {
+    const request = action(db.transaction(STORE, mode).objectStore(STORE));
+    request.onsuccess = () => resolve(request.result);
+    request.onerror = () => reject(request.error);
+  });
+}
+
+async function savedFolder() {
+  return (await withStore("readonly", (store) => store.get(KEY))) || null;
+}
+
+async function listRoms(folder) {
+  const names = [];
+  for await (const [name, handle] of folder.entries()) {
+    const lower = name.toLowerCase();
+    if (handle.kind === "file" && EXTENSIONS.some((ext) => lower.endsWith(ext))) {
+      names.push(name);
+    }
+  }
+  return names.sort((a, b) => a.localeCompare(b));
+}
+
+export function supported() {
+  return "showDirectoryPicker" in window;
+}
+
+export async function savedFolderName() {
+  const folder = await savedFolder();
+  return folder ? folder.name : null;
+}
+
+// Asks for a folder and remembers it, returning the ROMs in it.
+export async function pickFolder() {
+  const folder = await window.showDirectoryPicker({ id: KEY, mode: "read" });
+  await withStore("readwrite", (store) => store.put(folder, KEY));
+  return listRoms(folder);
+}
+
+// The ROMs of the remembered folder, asking to access it again when the
+// browser forgot the permission. Must be called from a user gesture.
+export async function listSavedFolder() {
+  const folder = await savedFolder();
+  if (!folder) {
+    return [];
+  }
+  if ((await folder.requestPermission({ mode: "read" })) !== "granted") {
+    throw new Error("Access to the ROM folder was denied");
+  }
+  return listRoms(folder);
+}
+
+export async function readRom(name) {
+  const folder = await savedFolder();
+  if (!folder) {
+    throw new Error("No ROM folder");
+  }
+  const file = await (await folder.getFileHandle(name)).getFile();
+  return new Uint8Array(await file.arrayBuffer());
+}
+
+export async function forgetFolder() {
+  await withStore("readwrite", (store) => store.delete(KEY));
+}
@@ -0,0 +1,6 @@
+// The script embedding pages load: it starts the player module next to it,
+// which defines the <rust-msx> element.
+
+import init from "./player.js";
+
+init(new URL("player_bg.wasm", import.meta.url));
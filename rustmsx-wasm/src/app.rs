@@ -1,18 +1,58 @@
-use std::rc::Rc;
+use std::{cell::Cell, rc::Rc};
 
-use gloo::timers::callback::Interval;
+use gloo::{events::EventListener, timers::callback::Interval, utils::document};
+use rustmsx_wasm::fetch::fetch_rom;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::spawn_local;
+use web_sys::{HtmlInputElement, HtmlTextAreaElement, KeyboardEvent};
 use yew::prelude::*;
 use yewdux::prelude::*;
 
 use crate::{
-    layout::{Memory, Navbar, Program, Registers, Screen, Vdp},
+    layout::{
+        Console, IoLog, Machine, Memory, Navbar, Netplay, Program, Psg, Registers, Screen,
+        SystemVariables, Vdp, Watches,
+    },
+    link, perf,
+    settings::{self, Settings},
     store::{self, ComputerState, ExecutionState},
 };
 
+/// Forwards key presses on the page to the MSX keyboard matrix or joystick,
+/// with the key map of the settings, except when typing on a text field.
+fn key_listener(dispatch: Dispatch<ComputerState>, event_type: &'static str) -> EventListener {
+    let pressed = event_type == "keydown";
+    EventListener::new(&document(), event_type, move |event| {
+        let event = event.dyn_ref::<KeyboardEvent>().unwrap();
+        if let Some(target) = event.target() {
+            if target.has_type::<HtmlInputElement>() || target.has_type::<HtmlTextAreaElement>() {
+                return;
+            }
+        }
+
+        let code = event.code();
+        let settings = Dispatch::<Settings>::new().get();
+        let state = dispatch.get();
+        let handled = state.unbound_macro.is_some()
+            || settings.macros.contains_key(&code)
+            || settings
+                .key_map
+                .binding(&code, state.rom_sha1.as_deref())
+                .is_some();
+        if handled {
+            event.prevent_default();
+            dispatch.apply(store::Msg::HostKey(code, pressed));
+        }
+    })
+}
+
 pub struct App {
     interval: Option<Interval>,
+    _key_listeners: [EventListener; 2],
     state: Rc<ComputerState>,
     dispatch: Dispatch<ComputerState>,
+    // when the last view started, to time the page updates
+    view_start: Cell<f64>,
 }
 
 pub enum Msg {
@@ -27,10 +67,40 @@ impl Component for App {
         let on_change = ctx.link().callback(Msg::State);
         let dispatch = Dispatch::<ComputerState>::subscribe(on_change);
 
+        let settings = Dispatch::<Settings>::new().get();
+        dispatch.apply(store::Msg::Palette(settings.palette));
+        // a link to a state or a ROM wins over the ROM of the last visit
+        if let Some(state) = link::state_fragment() {
+            match state {
+                Ok(state) => dispatch.apply(store::Msg::LoadState(state)),
+                Err(e) => dispatch.apply(store::Msg::Error(e)),
+            }
+        } else if let Some(url) = link::rom_url() {
+            let dispatch = dispatch.clone();
+            spawn_local(async move {
+                match fetch_rom(&url).await {
+                    Ok(rom) => {
+                        dispatch.apply(store::Msg::LoadRom(rom));
+                        dispatch.apply(store::Msg::Toggle);
+                    }
+                    Err(e) => dispatch.apply(store::Msg::Error(e)),
+                }
+            });
+        } else if settings.autoload_rom {
+            if let Some(rom) = settings::load_rom() {
+                dispatch.apply(store::Msg::LoadRom(rom));
+            }
+        }
+
         Self {
             interval: None,
+            _key_listeners: [
+                key_listener(dispatch.clone(), "keydown"),
+                key_listener(dispatch.clone(), "keyup"),
+            ],
             state: dispatch.get(),
             dispatch,
+            view_start: Cell::new(0.0),
         }
     }
 
@@ -60,28 +130,42 @@ impl Component for App {
         }
     }
 
+    fn rendered(&mut self, _ctx: &Context<Self>, _first_render: bool) {
+        let ui_ms = perf::now() - self.view_start.get();
+        self.state.perf.borrow_mut().record_ui(ui_ms);
+    }
+
     fn view(&self, _ctx: &Context<Self>) -> Html {
+        self.view_start.set(perf::now());
         let msx = self.state.msx.borrow();
-        let program = msx.program();
+        let snapshot = Rc::new(msx.ui_snapshot());
         let vram = msx.vram();
         let ram = msx.ram();
         let cpu = msx.cpu.clone();
-        let vdp = msx.vdp();
+        let io_events = msx.io_events();
+        let psg = msx.psg();
 
         html! {
             <div id="root">
                 <div class="container">
                     <Navbar />
                     <div class="main">
-                        <Program data={program} pc={cpu.pc} />
+                        <Program data={snapshot.program.clone()} pc={snapshot.pc} />
                         <div class="status">
-                            <Registers cpu={msx.cpu.clone()} vdp={vdp} />
+                            <Registers snapshot={snapshot.clone()} />
 
                             <Screen />
+                            <Netplay />
 
                             <div class="split">
                                 <Memory data={ram} />
                                 <Vdp data={vram} />
+                                <SystemVariables cpu={cpu} />
+                                <Watches />
+                                <Console />
+                                <IoLog events={io_events} />
+                                <Psg psg={psg} />
+                                <Machine snapshot={snapshot} />
                             </div>
                         </div>
                     </div>
@@ -0,0 +1,78 @@
+//! Plays the sound of the machine through the Web Audio API, queueing a
+//! buffer with the samples of each tick.
+
+use wasm_bindgen::JsValue;
+use web_sys::AudioContext;
+
+// how far ahead of the browser the samples are queued, in seconds, about
+// three ticks so that a late one doesn't run the queue dry
+const LATENCY: f64 = 0.05;
+
+// queued past this, e.g. when the machine runs faster than a real one, the
+// samples are dropped rather than lagging further behind
+const MAX_LATENCY: f64 = 0.25;
+
+#[derive(Debug)]
+pub struct AudioOutput {
+    context: AudioContext,
+    // context time the queued samples play until, 0 when nothing was
+    // queued since the start or a pause
+    end: f64,
+    /// times the queue ran dry while running
+    pub underruns: u64,
+}
+
+impl AudioOutput {
+    /// Must be created on a user gesture, browsers keep the audio
+    /// suspended otherwise.
+    pub fn new() -> Result<Self, JsValue> {
+        Ok(Self {
+            context: AudioContext::new()?,
+            end: 0.0,
+            underruns: 0,
+        })
+    }
+
+    pub fn sample_rate(&self) -> f32 {
+        self.context.sample_rate()
+    }
+
+    /// Resumes an audio context the browser suspended, on a user gesture.
+    pub fn resume(&self) {
+        if let Err(e) = self.context.resume() {
+            tracing::error!("[AUDIO] Error resuming: {:?}", e);
+        }
+    }
+
+    /// Forgets the queue, so that the time paused isn't an underrun.
+    pub fn pause(&mut self) {
+        self.end = 0.0;
+    }
+
+    /// Queues the samples after the ones queued before.
+    pub fn play(&mut self, samples: &[f32]) -> Result<(), JsValue> {
+        if samples.is_empty() {
+            return Ok(());
+        }
+        let now = self.context.current_time();
+        if self.end < now {
+            if self.end > 0.0 {
+                self.underruns += 1;
+            }
+            self.end = now + LATENCY;
+        } else if self.end > now + MAX_LATENCY {
+            return Ok(());
+        }
+
+        let buffer =
+            self.context
+                .create_buffer(1, samples.len() as u32, self.context.sample_rate())?;
+        buffer.copy_to_channel(samples, 0)?;
+        let source = self.context.create_buffer_source()?;
+        source.set_buffer(Some(&buffer));
+        source.connect_with_audio_node(&self.context.destination())?;
+        source.start_with_when(self.end)?;
+        self.end += buffer.duration();
+        Ok(())
+    }
+}
@@ -0,0 +1,211 @@
+//! `<rust-msx>`, a custom element that plays a ROM on any page, without the
+//! debugger around it. It's built apart from the app with
+//! `trunk build --filehash false player.html`, and embedded with a single
+//! script tag pointing to the `rust-msx.js` of the build:
+//!
+//! ```html
+//! <script type="module" src="https://example.com/rustmsx/rust-msx.js"></script>
+//! <rust-msx src="game.rom" autoplay size="3"></rust-msx>
+//! ```
+//!
+//! - `src`: URL of the ROM, the server must allow CORS when it's on another
+//!   origin
+//! - `autoplay`: runs the ROM as soon as it's loaded, otherwise a click on
+//!   the screen does
+//! - `size`: scale of the screen pixels, 2 by default
+//! - `preset`: machine the ROM runs on, e.g. `msx1-jp`, see
+//!   [`Preset::PRESETS`]
+//!
+//! The keyboard is read while the screen has the focus.
+
+use std::{cell::RefCell, rc::Rc};
+
+use gloo::{console, events::EventListener, timers::callback::Interval};
+use msx::{
+    archive,
+    frame::{FrameBuffer, SCREEN_HEIGHT, SCREEN_WIDTH},
+    keyboard::key_position,
+    preset::Preset,
+    Msx,
+};
+use rustmsx_wasm::{fetch::fetch_rom, STEPS_PER_TICK};
+use wasm_bindgen::{prelude::*, Clamped};
+use wasm_bindgen_futures::spawn_local;
+use web_sys::{CanvasRenderingContext2d, HtmlCanvasElement, HtmlElement, ImageData, KeyboardEvent};
+
+const DEFAULT_SIZE: u32 = 2;
+
+#[wasm_bindgen(module = "/js/player.js")]
+extern "C" {
+    #[wasm_bindgen(js_name = defineElement)]
+    fn define_element(create: &Closure<dyn Fn(HtmlElement, HtmlCanvasElement) -> Player>);
+}
+
+struct Machine {
+    msx: Msx,
+    frame: FrameBuffer,
+    context: CanvasRenderingContext2d,
+    running: bool,
+}
+
+impl Machine {
+    fn load_rom(&mut self, data: &[u8], preset: Option<&Preset>) -> Result<(), String> {
+        let data = archive::extract(data, None).map_err(|e| e.to_string())?;
+        self.msx = Msx::default();
+        self.msx.load_machine(&data, preset);
+        Ok(())
+    }
+
+    fn tick(&mut self) {
+        if !self.running {
+            return;
+        }
+        for _ in 0..STEPS_PER_TICK {
+            self.msx.step();
+        }
+        self.draw();
+    }
+
+    fn draw(&mut self) {
+        self.msx.render(&mut self.frame);
+
+        // the frame is already RGBA, it's copied to the canvas as is
+        let data = ImageData::new_with_u8_clamped_array_and_sh(
+            Clamped(&self.frame.rgba),
+            SCREEN_WIDTH as u32,
+            SCREEN_HEIGHT as u32,
+        )
+        .unwrap();
+        self.context.put_image_data(&data, 0.0, 0.0).unwrap();
+    }
+}
+
+/// The machine behind a `<rust-msx>` element, freed by the element when it's
+/// removed from the page.
+#[wasm_bindgen]
+pub struct Player {
+    element: HtmlElement,
+    canvas: HtmlCanvasElement,
+    machine: Rc<RefCell<Machine>>,
+    _interval: Interval,
+    _listeners: [EventListener; 3],
+}
+
+impl Player {
+    fn new(element: HtmlElement, canvas: HtmlCanvasElement) -> Self {
+        let context = canvas
+            .get_context("2d")
+            .unwrap()
+            .unwrap()
+            .unchecked_into::<CanvasRenderingContext2d>();
+        let machine = Rc::new(RefCell::new(Machine {
+            msx: Msx::default(),
+            frame: FrameBuffer::default(),
+            context,
+            running: false,
+        }));
+
+        let m = machine.clone();
+        let interval = Interval::new(1000 / 60, move || m.borrow_mut().tick());
+
+        let (m, c) = (machine.clone(), canvas.clone());
+        let click = EventListener::new(&canvas, "click", move |_| {
+            let _ = c.focus();
+            let mut machine = m.borrow_mut();
+            machine.running = !machine.running;
+        });
+
+        let player = Self {
+            _listeners: [
+                click,
+                key_listener(&canvas, machine.clone(), "keydown"),
+                key_listener(&canvas, machine.clone(), "keyup"),
+            ],
+            element,
+            canvas,
+            machine,
+            _interval: interval,
+        };
+        player.resize();
+        player.load();
+        player
+    }
+
+    fn resize(&self) {
+        let size = self
+            .element
+            .get_attribute("size")
+            .and_then(|size| size.parse().ok())
+            .unwrap_or(DEFAULT_SIZE);
+        let style = format!(
+            "width: {}px; height: {}px",
+            SCREEN_WIDTH as u32 * size,
+            SCREEN_HEIGHT as u32 * size
+        );
+        let _ = self.canvas.set_attribute("style", &style);
+    }
+
+    fn load(&self) {
+        let Some(url) = self.element.get_attribute("src") else {
+            return;
+        };
+        let autoplay = self.element.has_attribute("autoplay");
+        let preset = match self.element.get_attribute("preset") {
+            Some(name) => match Preset::find(&name) {
+                Some(preset) => Some(preset),
+                None => {
+                    console::error!(format!("<rust-msx>: unknown preset {}", name));
+                    return;
+                }
+            },
+            None => None,
+        };
+        let machine = self.machine.clone();
+        spawn_local(async move {
+            let res = match fetch_rom(&url).await {
+                Ok(data) => machine.borrow_mut().load_rom(&data, preset),
+                Err(e) => Err(e),
+            };
+            match res {
+                Ok(()) => machine.borrow_mut().running = autoplay,
+                Err(e) => console::error!(format!("<rust-msx>: {}", e)),
+            }
+        });
+    }
+}
+
+#[wasm_bindgen]
+impl Player {
+    /// Called by the element when one of its observed attributes changes.
+    #[wasm_bindgen(js_name = attributeChanged)]
+    pub fn attribute_changed(&self, name: &str) {
+        match name {
+            "src" | "preset" => self.load(),
+            "size" => self.resize(),
+            _ => {}
+        }
+    }
+}
+
+/// Forwards the keys pressed on the screen to the MSX keyboard matrix.
+fn key_listener(
+    canvas: &HtmlCanvasElement,
+    machine: Rc<RefCell<Machine>>,
+    event_type: &'static str,
+) -> EventListener {
+    let pressed = event_type == "keydown";
+    EventListener::new(canvas, event_type, move |event| {
+        let event = event.dyn_ref::<KeyboardEvent>().unwrap();
+        if let Some((row, bit)) = key_position(&event.code()) {
+            event.prevent_default();
+            machine.borrow_mut().msx.set_key(row, bit, pressed);
+        }
+    })
+}
+
+fn main() {
+    let create = Closure::<dyn Fn(HtmlElement, HtmlCanvasElement) -> Player>::new(Player::new);
+    define_element(&create);
+    // the element creates players for as long as the page lives
+    create.forget();
+}
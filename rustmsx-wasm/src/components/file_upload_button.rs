@@ -12,6 +12,9 @@ pub struct FileUploadButton {
 #[derive(Properties, Clone, PartialEq)]
 pub struct Props {
     pub on_upload: Callback<Vec<u8>>,
+    /// file types offered by the file picker
+    #[prop_or(AttrValue::Static(".rom,.zip,.gz"))]
+    pub accept: AttrValue,
     pub children: Children,
 }
 
@@ -51,6 +54,7 @@ impl Component for FileUploadButton {
     fn view(&self, ctx: &Context<Self>) -> Html {
         let on_open_rom = {
             let link = ctx.link().clone();
+            let accept = ctx.props().accept.clone();
             Callback::from(move |_| {
                 let link = link.clone();
                 let on_change_closure = Closure::wrap(Box::new(move |event: Event| {
@@ -76,9 +80,8 @@ impl Component for FileUploadButton {
                     .create_element("input")
                     .unwrap();
                 input.set_attribute("type", "file").unwrap();
-                input.set_attribute("accept", ".rom").unwrap();
+                input.set_attribute("accept", &accept).unwrap();
                 input.set_attribute("style", "display: none").unwrap();
-                input.set_attribute("id", "file-input").unwrap();
                 input
                     .add_event_listener_with_callback(
                         "change",
@@ -94,12 +97,6 @@ impl Component for FileUploadButton {
                     .unwrap()
                     .append_child(&input)
                     .unwrap();
-                let input = web_sys::window()
-                    .unwrap()
-                    .document()
-                    .unwrap()
-                    .get_element_by_id("file-input")
-                    .unwrap();
                 input.dyn_ref::<HtmlInputElement>().unwrap().click();
             })
         };
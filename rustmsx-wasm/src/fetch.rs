@@ -0,0 +1,22 @@
+use gloo::net::http::Request;
+
+/// Downloads a ROM, which the server must allow with CORS when it's on
+/// another origin.
+pub async fn fetch_rom(url: &str) -> Result<Vec<u8>, String> {
+    let response = Request::get(url)
+        .send()
+        .await
+        .map_err(|e| format!("Couldn't fetch {}: {}", url, e))?;
+    if !response.ok() {
+        return Err(format!(
+            "Couldn't fetch {}: {} {}",
+            url,
+            response.status(),
+            response.status_text()
+        ));
+    }
+    response
+        .binary()
+        .await
+        .map_err(|e| format!("Couldn't fetch {}: {}", url, e))
+}
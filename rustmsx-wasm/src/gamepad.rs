@@ -0,0 +1,49 @@
+//! Reads the gamepads through the Gamepad API, which has no events for the
+//! buttons, so they are polled on every tick.
+
+use std::collections::BTreeSet;
+
+use msx::keymap::gamepad_button;
+use wasm_bindgen::JsCast;
+use web_sys::{Gamepad, GamepadButton};
+
+// how far the stick is tilted before it counts as a direction
+const STICK_THRESHOLD: f64 = 0.5;
+
+/// Names of the buttons held down on any gamepad, as in the key map. The
+/// left stick reads as the directional pad.
+pub fn pressed_buttons() -> BTreeSet<String> {
+    let mut pressed = BTreeSet::new();
+    let Some(gamepads) = web_sys::window().and_then(|w| w.navigator().get_gamepads().ok()) else {
+        return pressed;
+    };
+
+    for gamepad in gamepads.iter().filter_map(|g| g.dyn_into::<Gamepad>().ok()) {
+        if !gamepad.connected() {
+            continue;
+        }
+        for (index, button) in gamepad.buttons().iter().enumerate() {
+            if button
+                .dyn_into::<GamepadButton>()
+                .is_ok_and(|button| button.pressed())
+            {
+                pressed.insert(gamepad_button(index));
+            }
+        }
+
+        let axes = gamepad.axes();
+        let axis = |n| axes.get(n).as_f64().unwrap_or_default();
+        let (x, y) = (axis(0), axis(1));
+        for (tilted, name) in [
+            (y < -STICK_THRESHOLD, "PadUp"),
+            (y > STICK_THRESHOLD, "PadDown"),
+            (x < -STICK_THRESHOLD, "PadLeft"),
+            (x > STICK_THRESHOLD, "PadRight"),
+        ] {
+            if tilted {
+                pressed.insert(name.to_string());
+            }
+        }
+    }
+    pressed
+}
@@ -0,0 +1,57 @@
+use wasm_bindgen::JsCast;
+use web_sys::{HtmlElement, HtmlInputElement};
+use yew::prelude::*;
+use yewdux::prelude::*;
+
+use crate::store::{ComputerState, Msg};
+
+#[function_component]
+pub fn Console() -> Html {
+    let (state, dispatch) = use_store::<ComputerState>();
+    let input = use_state(String::new);
+    let output = use_node_ref();
+
+    // keeps the last lines in view
+    {
+        let output = output.clone();
+        use_effect_with_deps(
+            move |_| {
+                if let Some(output) = output.cast::<HtmlElement>() {
+                    output.set_scroll_top(output.scroll_height());
+                }
+            },
+            state.console.len(),
+        );
+    }
+
+    let handle_input = {
+        let input = input.clone();
+        Callback::from(move |e: InputEvent| {
+            let target = e.target().unwrap();
+            input.set(target.unchecked_into::<HtmlInputElement>().value());
+        })
+    };
+
+    let handle_submit = {
+        let input = input.clone();
+        Callback::from(move |e: SubmitEvent| {
+            e.prevent_default();
+            if !input.trim().is_empty() {
+                dispatch.apply(Msg::Command(input.trim().to_string()));
+                input.set(String::new());
+            }
+        })
+    };
+
+    html! {
+        <div class="console">
+            <div class="console__output" ref={output}>
+                { for state.console.iter().map(|line| html! { <div>{ line }</div> }) }
+            </div>
+            <form class="console__form" onsubmit={handle_submit}>
+                <input type="text" placeholder="step, bp c000, mem c000, disasm or help" value={(*input).clone()} oninput={handle_input} />
+                <button type="submit">{ "Run" }</button>
+            </form>
+        </div>
+    }
+}
@@ -0,0 +1,63 @@
+use std::collections::BTreeSet;
+
+use msx::io_log::{IoDirection, IoEvent};
+use yew::prelude::*;
+
+// rows shown, the newest first
+const MAX_ROWS: usize = 200;
+
+#[derive(Properties, Clone, PartialEq)]
+pub struct Props {
+    pub events: Vec<IoEvent>,
+}
+
+#[function_component]
+pub fn IoLog(props: &Props) -> Html {
+    // ports whose accesses are filtered out
+    let hidden = use_state(BTreeSet::<u8>::new);
+    let ports: BTreeSet<u8> = props.events.iter().map(|event| event.port).collect();
+
+    let toggle = |port: u8| {
+        let hidden = hidden.clone();
+        Callback::from(move |_| {
+            let mut ports = (*hidden).clone();
+            if !ports.remove(&port) {
+                ports.insert(port);
+            }
+            hidden.set(ports);
+        })
+    };
+
+    html! {
+        <div class="io-log">
+            <div class="io-log__ports">
+                { for ports.iter().map(|port| {
+                    let class = classes!(
+                        "io-log__port",
+                        hidden.contains(port).then_some("io-log__port--hidden")
+                    );
+                    html! {
+                        <button class={class} onclick={toggle(*port)}>
+                            { format!("{:02X}", port) }
+                        </button>
+                    }
+                }) }
+            </div>
+            { for props.events.iter().rev()
+                .filter(|event| !hidden.contains(&event.port))
+                .take(MAX_ROWS)
+                .map(|event| html! {
+                    <div class="io-log__event">
+                        <div class="io-log__frame">{ event.frame }</div>
+                        <div class="io-log__pc">{ format!("{:04X}", event.pc) }</div>
+                        <div>{ match event.direction {
+                            IoDirection::Read => "IN ",
+                            IoDirection::Write => "OUT",
+                        } }</div>
+                        <div class="io-log__port-number">{ format!("{:02X}", event.port) }</div>
+                        <div>{ format!("{:02X}", event.value) }</div>
+                    </div>
+                }) }
+        </div>
+    }
+}
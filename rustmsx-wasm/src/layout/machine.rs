@@ -0,0 +1,47 @@
+use std::rc::Rc;
+
+use msx::UiSnapshot;
+use yew::prelude::*;
+
+#[derive(Properties, Clone, PartialEq)]
+pub struct Props {
+    pub snapshot: Rc<UiSnapshot>,
+}
+
+#[function_component]
+pub fn Machine(props: &Props) -> Html {
+    let snapshot = &props.snapshot;
+    let slot_config = (0..4).fold(0, |config, page| {
+        config | snapshot.pages[page] << (page * 2)
+    });
+
+    html! {
+        <div class="machine">
+            <div class="machine__config">{ format!("Primary slots (port A8) {:08b}", slot_config) }</div>
+            { for snapshot.slots.iter().enumerate().map(|(n, slot)| {
+                let pages: Vec<String> = (0..4)
+                    .filter(|&page| snapshot.pages[page] as usize == n)
+                    .map(|page| format!("{:04X}", page * 0x4000))
+                    .collect();
+                let banks: Vec<String> = slot.banks.iter().map(|bank| bank.to_string()).collect();
+                let class = classes!("machine__slot", pages.is_empty().then_some("machine__slot--hidden"));
+                html! {
+                    <div class={class}>
+                        <div class="machine__name">{ format!("Slot {} {}", n, slot.name) }</div>
+                        <div class="machine__size">{ match slot.size {
+                            0 => String::new(),
+                            size => format!("{}K", size / 1024),
+                        } }</div>
+                        <div class="machine__pages">{ match pages.is_empty() {
+                            true => "not selected".to_string(),
+                            false => format!("pages at {}", pages.join(" ")),
+                        } }</div>
+                        if !banks.is_empty() {
+                            <div>{ format!("banks {}", banks.join(" ")) }</div>
+                        }
+                    </div>
+                }
+            }) }
+        </div>
+    }
+}
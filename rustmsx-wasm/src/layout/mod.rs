@@ -1,15 +1,35 @@
+mod console;
+mod io_log;
+mod machine;
 mod memory;
 mod navbar;
+mod netplay;
+mod perf_hud;
 mod program;
+mod psg;
 mod registers;
-mod renderer;
+mod rom_browser;
 mod screen;
+mod settings;
+mod sysvars;
+mod touch_controls;
 mod vdp;
+mod watches;
 
+pub use console::Console;
+pub use io_log::IoLog;
+pub use machine::Machine;
 pub use memory::Memory;
 pub use navbar::Navbar;
+pub use netplay::Netplay;
+pub use perf_hud::PerfHud;
 pub use program::Program;
+pub use psg::Psg;
 pub use registers::Registers;
-pub use renderer::Renderer;
+pub use rom_browser::RomBrowser;
 pub use screen::Screen;
+pub use settings::SettingsDialog;
+pub use sysvars::SystemVariables;
+pub use touch_controls::TouchControls;
 pub use vdp::Vdp;
+pub use watches::Watches;
@@ -1,21 +1,83 @@
+use wasm_bindgen::JsCast;
+use web_sys::{HtmlInputElement, HtmlSelectElement};
 use yew::prelude::*;
 use yewdux::prelude::*;
 
+use msx::{keyboard::InputProfile, machine::ResetKind};
+
 use crate::{
     components::FileUploadButton,
+    layout::{RomBrowser, SettingsDialog},
+    rom_folder,
+    settings::Settings,
     store::{ComputerState, Msg},
 };
 
 #[function_component]
 pub fn Navbar() -> Html {
     let (state, dispatch) = use_store::<ComputerState>();
+    let (settings, settings_dispatch) = use_store::<Settings>();
+    let settings_open = use_state(|| false);
+    let rom_browser_open = use_state(|| false);
 
     let d = dispatch.clone();
     let on_rom_upload = Callback::from(move |rom: Vec<u8>| d.apply(Msg::LoadRom(rom)));
 
+    let d = dispatch.clone();
+    let on_state_upload = Callback::from(move |data: Vec<u8>| d.apply(Msg::LoadState(data)));
+
+    let d = dispatch.clone();
+    let handle_save_state_click = Callback::from(move |_| d.apply(Msg::SaveState));
+
+    let d = dispatch.clone();
+    let handle_share_click = Callback::from(move |_| d.apply(Msg::ShareState));
+
+    let d = dispatch.clone();
+    let handle_copy_text_click = Callback::from(move |_| d.apply(Msg::CopyScreenText));
+
+    let d = dispatch.clone();
+    let handle_reset_click = move |kind: ResetKind| {
+        let d = d.clone();
+        Callback::from(move |_| d.apply(Msg::Reset(kind)))
+    };
+
     let d = dispatch.clone();
     let handle_step_click = Callback::from(move |_| d.apply(Msg::Step));
 
+    let d = dispatch.clone();
+    let handle_frame_click = Callback::from(move |_| d.apply(Msg::Frame));
+
+    let d = dispatch.clone();
+    let handle_slow_change = Callback::from(move |e: Event| {
+        let select = e.target().unwrap().unchecked_into::<HtmlSelectElement>();
+        d.apply(Msg::Slow(select.value().parse().unwrap_or(1)));
+    });
+
+    let handle_input_change = settings_dispatch.reduce_mut_callback_with(|settings, e: Event| {
+        let select = e.target().unwrap().unchecked_into::<HtmlSelectElement>();
+        if let Some(profile) = InputProfile::find(&select.value()) {
+            settings.input_profile = profile;
+        }
+    });
+
+    let open = settings_open.clone();
+    let handle_settings_click = Callback::from(move |_| open.set(true));
+
+    let open = settings_open.clone();
+    let on_settings_close = Callback::from(move |_| open.set(false));
+
+    let open = rom_browser_open.clone();
+    let handle_rom_folder_click = Callback::from(move |_| open.set(true));
+
+    let open = rom_browser_open.clone();
+    let on_rom_browser_close = Callback::from(move |_| open.set(false));
+
+    let d = dispatch.clone();
+    let handle_hud_click = Callback::from(move |_| d.apply(Msg::ToggleHud));
+
+    let d = dispatch.clone();
+    let handle_record_click = Callback::from(move |_| d.apply(Msg::RecordMacro));
+
     let d = dispatch;
     let handle_run_click = Callback::from(move |_| d.apply(Msg::Toggle));
 
@@ -25,20 +87,104 @@ pub fn Navbar() -> Html {
         crate::store::ExecutionState::Paused => "Run",
     };
 
+    let loaded = match (&state.error, &state.rom_info) {
+        (Some(error), _) => format!("Error: {}", error),
+        (None, Some(info)) => format!("Loaded: {}", info),
+        (None, None) => String::new(),
+    };
+
     html! {
         <div class="navbar">
             <div class="navbar__item">
                 <FileUploadButton on_upload={on_rom_upload}>{ "Open ROM" }</FileUploadButton>
             </div>
+            if rom_folder::supported() {
+                <div class="navbar__item">
+                    <button onclick={handle_rom_folder_click}>{ "ROM Folder" }</button>
+                </div>
+            }
+            <div class="navbar__item">
+                <button onclick={handle_save_state_click}>{ "Save State" }</button>
+            </div>
+            <div class="navbar__item">
+                <FileUploadButton on_upload={on_state_upload} accept=".state,.json">{ "Load State" }</FileUploadButton>
+            </div>
+            <div class="navbar__item">
+                <button onclick={handle_share_click} title="Link restoring the current state">{ "Share" }</button>
+            </div>
+            <div class="navbar__item">
+                <button onclick={handle_copy_text_click} title="Copies the text of the screen in the text modes, e.g. for bug reports">{ "Copy Text" }</button>
+            </div>
             <div class="navbar__item">
                 <button>{ "Refresh" }</button>
             </div>
+            <div class="navbar__item">
+                <button onclick={handle_reset_click(ResetKind::Soft)} title="Like the reset button, the RAM is kept">{ "Reset" }</button>
+                <button onclick={handle_reset_click(ResetKind::Hard)} title="Resets and clears the RAM">{ "Hard Reset" }</button>
+                <button onclick={handle_reset_click(ResetKind::PowerCycle)} title="Resets, clears the RAM and loses what batteries keep">{ "Power Cycle" }</button>
+            </div>
             <div class="navbar__item">
                 <button onclick={handle_step_click}>{ "Step" }</button>
             </div>
+            <div class="navbar__item">
+                <button onclick={handle_frame_click}>{ "Frame" }</button>
+            </div>
             <div class="navbar__item">
                 <button onclick={handle_run_click}>{ label }</button>
             </div>
+            <div class="navbar__item">
+                <select onchange={handle_slow_change}>
+                    { for [1, 2, 4, 8, 16].iter().map(|factor| html! {
+                        <option value={factor.to_string()} selected={state.slow.max(1) == *factor}>
+                            { if *factor == 1 { "Full speed".to_string() } else { format!("1/{} speed", factor) } }
+                        </option>
+                    }) }
+                </select>
+            </div>
+            <div class="navbar__item" title="What the cursor keys and space drive">
+                <select onchange={handle_input_change}>
+                    { for InputProfile::ALL.iter().map(|profile| html! {
+                        <option value={profile.name()} selected={settings.input_profile == *profile}>
+                            { match profile {
+                                InputProfile::Keyboard => "Cursor keys",
+                                InputProfile::Joystick => "Joystick 1",
+                            } }
+                        </option>
+                    }) }
+                </select>
+            </div>
+            <div class="navbar__item">
+                <button onclick={handle_settings_click}>{ "Settings" }</button>
+            </div>
+            <div class="navbar__item" title="Records the keys pressed, to replay them with the next key pressed after stopping">
+                <button onclick={handle_record_click}>
+                    { if state.input.borrow().recording() {
+                        "Stop Recording"
+                    } else if state.unbound_macro.is_some() {
+                        "Press the Macro Key"
+                    } else {
+                        "Record Macro"
+                    } }
+                </button>
+            </div>
+            <div class="navbar__item">
+                <button onclick={handle_hud_click}>{ if state.hud { "Hide HUD" } else { "HUD" } }</button>
+            </div>
+            <div class="navbar__item">{ loaded }</div>
+            if let Some(link) = &state.share_link {
+                <div class="navbar__item">
+                    <input class="navbar__link" type="text" readonly=true value={link.clone()}
+                        onfocus={Callback::from(|e: FocusEvent| {
+                            e.target().unwrap().unchecked_into::<HtmlInputElement>().select();
+                        })} />
+                </div>
+            }
+            if *settings_open {
+                <SettingsDialog on_close={on_settings_close} />
+            }
+            if *rom_browser_open {
+                <RomBrowser on_close={on_rom_browser_close} />
+            }
         </div>
     }
 }
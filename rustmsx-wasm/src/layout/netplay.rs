@@ -0,0 +1,145 @@
+use msx::netplay::NetplayRole;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::spawn_local;
+use web_sys::HtmlTextAreaElement;
+use yew::prelude::*;
+use yewdux::prelude::*;
+
+use crate::{
+    netplay::Peer,
+    store::{ComputerState, Msg},
+};
+
+#[function_component]
+pub fn Netplay() -> Html {
+    let (state, dispatch) = use_store::<ComputerState>();
+    let peer = use_state(|| None::<Peer>);
+    let local_sdp = use_state(String::new);
+    let remote_sdp = use_state(String::new);
+    let status = use_state(String::new);
+
+    if let Some(netplay) = state.netplay.borrow().as_ref() {
+        let session = &netplay.session;
+        let d = dispatch;
+        let p = peer;
+        let handle_stop_click = Callback::from(move |_| {
+            d.apply(Msg::NetplayStop);
+            p.set(None);
+        });
+
+        return html! {
+            <div class="netplay">
+                <div>{ format!("Netplay ({:?}) frame: {} delay: {} desyncs: {}",
+                    session.role, session.frame, session.input_delay, session.desyncs) }</div>
+                <button onclick={handle_stop_click}>{ "Disconnect" }</button>
+            </div>
+        };
+    }
+
+    let on_open = {
+        let d = dispatch;
+        Callback::from(move |peer: Peer| d.apply(Msg::NetplayStart(peer)))
+    };
+
+    let on_remote_input = {
+        let remote_sdp = remote_sdp.clone();
+        Callback::from(move |e: InputEvent| {
+            let target = e.target().unwrap();
+            remote_sdp.set(target.unchecked_into::<HtmlTextAreaElement>().value());
+        })
+    };
+
+    let handle_host_click = {
+        let (peer, local_sdp, status, on_open) = (
+            peer.clone(),
+            local_sdp.clone(),
+            status.clone(),
+            on_open.clone(),
+        );
+        Callback::from(move |_| {
+            let new_peer = match Peer::new(NetplayRole::Host, on_open.clone()) {
+                Ok(new_peer) => new_peer,
+                Err(e) => return status.set(format!("Error: {:?}", e)),
+            };
+            peer.set(Some(new_peer.clone()));
+            status.set("Gathering candidates...".to_string());
+
+            let (local_sdp, status) = (local_sdp.clone(), status.clone());
+            spawn_local(async move {
+                match new_peer.create_offer().await {
+                    Ok(offer) => {
+                        local_sdp.set(offer);
+                        status.set("Send the offer to the guest and paste the answer".to_string());
+                    }
+                    Err(e) => status.set(format!("Error: {:?}", e)),
+                }
+            });
+        })
+    };
+
+    let handle_join_click = {
+        let (peer, local_sdp, remote_sdp, status) = (
+            peer.clone(),
+            local_sdp.clone(),
+            remote_sdp.clone(),
+            status.clone(),
+        );
+        Callback::from(move |_| {
+            let new_peer = match Peer::new(NetplayRole::Guest, on_open.clone()) {
+                Ok(new_peer) => new_peer,
+                Err(e) => return status.set(format!("Error: {:?}", e)),
+            };
+            peer.set(Some(new_peer.clone()));
+            status.set("Gathering candidates...".to_string());
+
+            let (local_sdp, status, offer) =
+                (local_sdp.clone(), status.clone(), (*remote_sdp).clone());
+            spawn_local(async move {
+                match new_peer.accept_offer(&offer).await {
+                    Ok(answer) => {
+                        local_sdp.set(answer);
+                        status.set("Send the answer to the host".to_string());
+                    }
+                    Err(e) => status.set(format!("Error: {:?}", e)),
+                }
+            });
+        })
+    };
+
+    let handle_connect_click = {
+        let (peer, remote_sdp, status) = (peer.clone(), remote_sdp.clone(), status.clone());
+        Callback::from(move |_| {
+            let Some(peer) = (*peer).clone() else {
+                return;
+            };
+
+            let (status, answer) = (status.clone(), (*remote_sdp).clone());
+            spawn_local(async move {
+                match peer.accept_answer(&answer).await {
+                    Ok(_) => status.set("Connecting...".to_string()),
+                    Err(e) => status.set(format!("Error: {:?}", e)),
+                }
+            });
+        })
+    };
+
+    let is_host = peer
+        .as_ref()
+        .map(|p| p.role == NetplayRole::Host)
+        .unwrap_or(false);
+
+    html! {
+        <div class="netplay">
+            <div class="netplay__actions">
+                <button onclick={handle_host_click}>{ "Host" }</button>
+                <button onclick={handle_join_click}>{ "Join with offer" }</button>
+                if is_host {
+                    <button onclick={handle_connect_click}>{ "Connect with answer" }</button>
+                }
+            </div>
+            <textarea readonly=true placeholder="Local offer/answer" value={(*local_sdp).clone()} />
+            <textarea placeholder="Paste the remote offer/answer" value={(*remote_sdp).clone()} oninput={on_remote_input} />
+            <div>{ (*status).clone() }</div>
+        </div>
+    }
+}
@@ -0,0 +1,27 @@
+use yew::prelude::*;
+
+use crate::perf::PerfStats;
+
+#[derive(Properties, Clone, PartialEq)]
+pub struct Props {
+    pub stats: PerfStats,
+}
+
+#[function_component]
+pub fn PerfHud(props: &Props) -> Html {
+    let stats = &props.stats;
+    let underruns = match stats.audio_underruns {
+        Some(underruns) => underruns.to_string(),
+        None => "no audio".to_string(),
+    };
+
+    html! {
+        <div class="perf-hud">
+            <div>{ format!("Speed {:.0}%", stats.speed) }</div>
+            <div>{ format!("CPU {:.1} ms  render {:.1} ms  UI {:.1} ms",
+                stats.cpu_ms, stats.render_ms, stats.ui_ms) }</div>
+            <div>{ format!("Dropped frames {}", stats.dropped_frames) }</div>
+            <div>{ format!("Audio underruns {}", underruns) }</div>
+        </div>
+    }
+}
@@ -0,0 +1,55 @@
+use msx::sound::{envelope_shape_name, AY38910};
+use yew::prelude::*;
+
+// samples drawn by the oscilloscope, about 6ms
+const SCOPE_SAMPLES: usize = 256;
+const SCOPE_SAMPLE_RATE: f32 = 44100.0;
+const SCOPE_HEIGHT: f32 = 64.0;
+
+#[derive(Properties, Clone, PartialEq)]
+pub struct Props {
+    pub psg: AY38910,
+}
+
+#[function_component]
+pub fn Psg(props: &Props) -> Html {
+    let psg = &props.psg;
+    let points = psg
+        .waveform(SCOPE_SAMPLES, SCOPE_SAMPLE_RATE)
+        .iter()
+        .enumerate()
+        .map(|(x, sample)| format!("{},{:.1}", x, SCOPE_HEIGHT * (1.0 - sample)))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    html! {
+        <div class="psg">
+            { for psg.channels().iter().zip(["A", "B", "C"]).map(|(channel, name)| {
+                let class = classes!("psg__channel", (!channel.audible()).then_some("psg__channel--muted"));
+                html! {
+                    <div class={class}>
+                        <div class="psg__name">{ name }</div>
+                        <div class="psg__frequency">{ format!("{:.1} Hz", channel.frequency()) }</div>
+                        <div class="psg__note">{ channel.note() }</div>
+                        <div>{ match channel.envelope {
+                            true => "vol env".to_string(),
+                            false => format!("vol {}", channel.volume),
+                        } }</div>
+                        <div>{ if channel.tone { "tone" } else { "" } }</div>
+                        <div>{ if channel.noise { "noise" } else { "" } }</div>
+                    </div>
+                }
+            }) }
+            <div class="psg__setting">
+                { format!("Noise period {} ({:.0} Hz)", psg.noise_period(), psg.noise_frequency()) }
+            </div>
+            <div class="psg__setting">
+                { format!("Envelope period {} shape {} {}",
+                    psg.envelope_period(), psg.envelope_shape(), envelope_shape_name(psg.envelope_shape())) }
+            </div>
+            <svg class="psg__scope" viewBox={format!("0 0 {} {}", SCOPE_SAMPLES, SCOPE_HEIGHT)} preserveAspectRatio="none">
+                <polyline points={points} />
+            </svg>
+        </div>
+    }
+}
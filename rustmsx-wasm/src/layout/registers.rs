@@ -1,73 +1,44 @@
-use msx::{TMS9918, Z80};
+use std::rc::Rc;
+
+use msx::UiSnapshot;
 use yew::prelude::*;
 
 #[derive(Properties, Clone, PartialEq)]
 pub struct Props {
-    pub cpu: Z80,
-    pub vdp: TMS9918,
+    pub snapshot: Rc<UiSnapshot>,
+}
+
+fn register(name: &'static str, value: String) -> Html {
+    html! {
+        <div class="register">
+            <div class="register__name">{ name }</div>
+            <div class="register__value">{ value }</div>
+        </div>
+    }
 }
 
 #[function_component]
 pub fn Registers(props: &Props) -> Html {
-    let cpu = &props.cpu;
+    let snapshot = &props.snapshot;
+    let registers = &snapshot.registers;
     html! {
         <div class="registers">
-            <div class="register">
-                <div class="register__name">{ "PC" }</div>
-                <div class="register__value">{ format!("{:04X}", cpu.pc) }</div>
-            </div>
-            <div class="register">
-                <div class="register__name">{ "A" }</div>
-                <div class="register__value">{ format!("{:02X}", cpu.a ) }</div>
-            </div>
-            <div class="register">
-                <div class="register__name">{ "B" }</div>
-                <div class="register__value">{ format!("{:02X}", cpu.b ) }</div>
-            </div>
-            <div class="register">
-                <div class="register__name">{ "C" }</div>
-                <div class="register__value">{ format!("{:02X}", cpu.c ) }</div>
-            </div>
-            <div class="register">
-                <div class="register__name">{ "D" }</div>
-                <div class="register__value">{ format!("{:02X}", cpu.d ) }</div>
-            </div>
-            <div class="register">
-                <div class="register__name">{ "E" }</div>
-                <div class="register__value">{ format!("{:02X}", cpu.d ) }</div>
-            </div>
-            <div class="register">
-                <div class="register__name">{ "F" }</div>
-                <div class="register__value">{ format!("{:02X}", cpu.f ) }</div>
-            </div>
-            <div class="register">
-                <div class="register__name">{ "SP" }</div>
-                <div class="register__value">{ format!("{:04X}", cpu.sp ) }</div>
-            </div>
-            <div class="register">
-                <div class="register__name">{ "HL" }</div>
-                <div class="register__value">{ format!("{:04X}", cpu.get_hl() ) }</div>
-            </div>
-            <div class="register">
-                <div class="register__name">{ "AF" }</div>
-                <div class="register__value">{ format!("{:04X}", cpu.get_af() ) }</div>
-            </div>
-            <div class="register">
-                <div class="register__name">{ "BC" }</div>
-                <div class="register__value">{ format!("{:04X}", cpu.get_bc() ) }</div>
-            </div>
-            <div class="register">
-                <div class="register__name">{ "VDP0" }</div>
-                <div class="register__value">{ format!("{:08b}", props.vdp.registers[0] ) }</div>
-            </div>
-            <div class="register">
-                <div class="register__name">{ "VDP1" }</div>
-                <div class="register__value">{ format!("{:08b}", props.vdp.registers[1] ) }</div>
-            </div>
-            <div class="register">
-                <div class="register__name">{ "VDP2" }</div>
-                <div class="register__value">{ format!("{:08b}", props.vdp.registers[2] ) }</div>
-            </div>
+            { register("PC", format!("{:04X}", snapshot.pc)) }
+            { register("A", format!("{:02X}", registers.af >> 8)) }
+            { register("B", format!("{:02X}", registers.bc >> 8)) }
+            { register("C", format!("{:02X}", registers.bc & 0xFF)) }
+            { register("D", format!("{:02X}", registers.de >> 8)) }
+            { register("E", format!("{:02X}", registers.de & 0xFF)) }
+            { register("F", format!("{:02X}", registers.af & 0xFF)) }
+            { register("Flags", snapshot.flags.clone()) }
+            { register("SP", format!("{:04X}", registers.sp)) }
+            { register("HL", format!("{:04X}", registers.hl)) }
+            { register("AF", format!("{:04X}", registers.af)) }
+            { register("BC", format!("{:04X}", registers.bc)) }
+            { register("VDP0", format!("{:08b}", snapshot.vdp_registers[0])) }
+            { register("VDP1", format!("{:08b}", snapshot.vdp_registers[1])) }
+            { register("VDP2", format!("{:08b}", snapshot.vdp_registers[2])) }
+            { register("Pages", snapshot.pages.map(|slot| slot.to_string()).join(" ")) }
         </div>
     }
 }
@@ -0,0 +1,143 @@
+use std::future::Future;
+
+use wasm_bindgen_futures::spawn_local;
+use yew::prelude::*;
+use yewdux::prelude::*;
+
+use crate::{
+    rom_folder,
+    store::{ComputerState, Msg},
+};
+
+#[derive(Properties, Clone, PartialEq)]
+pub struct Props {
+    pub on_close: Callback<()>,
+}
+
+// lists the ROMs returned by a folder operation, or shows why it failed
+fn list(
+    roms: UseStateHandle<Vec<String>>,
+    status: UseStateHandle<String>,
+    operation: impl Future<Output = Result<Vec<String>, String>> + 'static,
+) {
+    spawn_local(async move {
+        match operation.await {
+            Ok(names) => {
+                status.set(match names.is_empty() {
+                    true => "No ROMs in the folder".to_string(),
+                    false => String::new(),
+                });
+                roms.set(names);
+            }
+            Err(e) => status.set(e),
+        }
+    });
+}
+
+/// Opens the ROMs of a local folder, remembered between visits.
+#[function_component]
+pub fn RomBrowser(props: &Props) -> Html {
+    let folder = use_state(|| None::<String>);
+    let roms = use_state(Vec::<String>::new);
+    let status = use_state(String::new);
+
+    {
+        let folder = folder.clone();
+        use_effect_with_deps(
+            move |_| {
+                spawn_local(async move {
+                    folder.set(rom_folder::saved_folder_name().await);
+                });
+            },
+            (),
+        );
+    }
+
+    let handle_pick_click = {
+        let (folder, roms, status) = (folder.clone(), roms.clone(), status.clone());
+        Callback::from(move |_| {
+            let folder = folder.clone();
+            list(roms.clone(), status.clone(), async move {
+                let names = rom_folder::pick_folder().await?;
+                folder.set(rom_folder::saved_folder_name().await);
+                Ok(names)
+            });
+        })
+    };
+
+    let handle_open_click = {
+        let (roms, status) = (roms.clone(), status.clone());
+        Callback::from(move |_| {
+            list(
+                roms.clone(),
+                status.clone(),
+                rom_folder::list_saved_folder(),
+            );
+        })
+    };
+
+    let handle_forget_click = {
+        let (folder, roms, status) = (folder.clone(), roms.clone(), status.clone());
+        Callback::from(move |_| {
+            let (folder, roms, status) = (folder.clone(), roms.clone(), status.clone());
+            spawn_local(async move {
+                match rom_folder::forget_folder().await {
+                    Ok(()) => {
+                        folder.set(None);
+                        roms.set(Vec::new());
+                    }
+                    Err(e) => status.set(e),
+                }
+            });
+        })
+    };
+
+    let open_rom = |name: &String| {
+        let (name, status, on_close) = (name.clone(), status.clone(), props.on_close.clone());
+        Callback::from(move |_| {
+            let (name, status, on_close) = (name.clone(), status.clone(), on_close.clone());
+            spawn_local(async move {
+                match rom_folder::read_rom(&name).await {
+                    Ok(data) => {
+                        Dispatch::<ComputerState>::new().apply(Msg::LoadRom(data));
+                        on_close.emit(());
+                    }
+                    Err(e) => status.set(e),
+                }
+            });
+        })
+    };
+
+    let on_close = props.on_close.clone();
+    let handle_close_click = Callback::from(move |_| on_close.emit(()));
+
+    html! {
+        <div class="dialog">
+            <div class="dialog__content">
+                <h2>{ "ROM Folder" }</h2>
+                if !rom_folder::supported() {
+                    <div>{ "This browser can't open folders, use Open ROM instead." }</div>
+                } else {
+                    <div class="dialog__buttons">
+                        if let Some(name) = &*folder {
+                            <button onclick={handle_open_click}>{ format!("Open {}", name) }</button>
+                            <button onclick={handle_forget_click}>{ "Forget" }</button>
+                        }
+                        <button onclick={handle_pick_click}>{ "Choose Folder" }</button>
+                    </div>
+                    <div class="rom-browser">
+                        { for roms.iter().map(|name| html! {
+                            <div class="rom-browser__rom" onclick={open_rom(name)}>{ name }</div>
+                        }) }
+                    </div>
+                    if !status.is_empty() {
+                        <div class="dialog__error">{ &*status }</div>
+                    }
+                }
+                <div class="dialog__buttons">
+                    <button onclick={handle_close_click}>{ "Close" }</button>
+                </div>
+            </div>
+        </div>
+    }
+}
@@ -1,21 +1,28 @@
 use std::rc::Rc;
 
+use msx::frame::{FrameBuffer, SCREEN_HEIGHT, SCREEN_WIDTH};
 use wasm_bindgen::{Clamped, JsCast};
 use web_sys::{CanvasRenderingContext2d, HtmlCanvasElement, ImageData};
 use yew::prelude::*;
 use yewdux::prelude::*;
 
-use crate::store::ComputerState;
+use crate::{
+    layout::{PerfHud, TouchControls},
+    settings::Settings,
+    store::ComputerState,
+};
 
 pub enum Msg {
     State(Rc<ComputerState>),
+    Settings(Rc<Settings>),
 }
 
-#[allow(unused)]
 pub struct Screen {
     canvas_ref: NodeRef,
     state: Rc<ComputerState>,
-    dispatch: Dispatch<ComputerState>,
+    _dispatch: Dispatch<ComputerState>,
+    settings: Rc<Settings>,
+    _settings_dispatch: Dispatch<Settings>,
 }
 
 impl Component for Screen {
@@ -25,65 +32,64 @@ impl Component for Screen {
     fn create(ctx: &Context<Self>) -> Self {
         let on_change = ctx.link().callback(Msg::State);
         let dispatch = Dispatch::<ComputerState>::subscribe(on_change);
+        let settings_dispatch = Dispatch::<Settings>::subscribe(ctx.link().callback(Msg::Settings));
 
         Self {
             canvas_ref: NodeRef::default(),
             state: dispatch.get(),
-            dispatch,
+            _dispatch: dispatch,
+            settings: settings_dispatch.get(),
+            _settings_dispatch: settings_dispatch,
         }
     }
 
     fn update(&mut self, _ctx: &Context<Self>, msg: Self::Message) -> bool {
         match msg {
             Msg::State(state) => {
-                self.update_screen(state.screen_buffer.clone());
+                self.update_screen(&state.frame);
+                self.state = state;
+            }
+            Msg::Settings(settings) => {
+                self.settings = settings;
             }
         }
         true
     }
 
     fn view(&self, _ctx: &Context<Self>) -> Html {
+        let style = format!(
+            "width: {}px; height: {}px",
+            SCREEN_WIDTH as u32 * self.settings.scale,
+            SCREEN_HEIGHT as u32 * self.settings.scale
+        );
         html! {
             <div class="screen">
-                <canvas id="screen" ref={&self.canvas_ref} width="256" height="192"></canvas>
+                <div class="screen__canvas">
+                    <canvas id="screen" ref={&self.canvas_ref} width="256" height="192" {style}></canvas>
+                    if self.state.hud {
+                        <PerfHud stats={self.state.perf.borrow().clone()} />
+                    }
+                    if self.settings.touch_controls.shown() {
+                        <TouchControls />
+                    }
+                </div>
+                <div class="sr-only" aria-live="polite">{ &self.state.screen_text }</div>
             </div>
         }
     }
 }
 
 impl Screen {
-    fn update_screen(&mut self, screen_buffer: Vec<u8>) {
-        if screen_buffer.len() < 256 * 192 {
-            return;
-        }
-
+    fn update_screen(&mut self, frame: &FrameBuffer) {
         let canvas: HtmlCanvasElement = self.canvas_ref.cast().unwrap();
         let ctx = canvas.get_context("2d").unwrap().unwrap();
         let ctx = ctx.dyn_into::<CanvasRenderingContext2d>().unwrap();
 
-        let palette: [u32; 16] = [
-            0x000000, 0x0000AA, 0x00AA00, 0x00AAAA, 0xAA0000, 0xAA00AA, 0xAA5500, 0xAAAAAA,
-            0x555555, 0x5555FF, 0x55FF55, 0x55FFFF, 0xFF5555, 0xFF55FF, 0xFFFF55, 0xFFFFFF,
-        ];
-
-        let width = 256;
-        let height = 192;
-
-        let mut data = Vec::new();
-        for y in 0..height {
-            for x in 0..width {
-                let color_offset = y * width + x;
-                let color = screen_buffer[color_offset];
-                let mut color_bytes = palette[color as usize].to_le_bytes();
-                color_bytes[3] = 255;
-                data.extend_from_slice(&color_bytes);
-            }
-        }
-
+        // the frame is already RGBA, it's copied to the canvas as is
         let data = ImageData::new_with_u8_clamped_array_and_sh(
-            Clamped(&data),
-            width as u32,
-            height as u32,
+            Clamped(&frame.rgba),
+            SCREEN_WIDTH as u32,
+            SCREEN_HEIGHT as u32,
         )
         .unwrap();
 
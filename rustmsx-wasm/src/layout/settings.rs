@@ -0,0 +1,236 @@
+use msx::{keymap::KeyMap, palette::Palette, preset::Preset};
+use wasm_bindgen::JsCast;
+use web_sys::{HtmlInputElement, HtmlSelectElement, HtmlTextAreaElement};
+use yew::prelude::*;
+use yewdux::prelude::*;
+
+use crate::{
+    components::FileUploadButton,
+    settings::{self, Settings, TouchMode, SCALES},
+    store::{ComputerState, Msg},
+};
+
+// autofire choices, 0 being off
+const AUTOFIRE_FRAMES: [u32; 5] = [0, 1, 2, 3, 4];
+
+#[derive(Properties, Clone, PartialEq)]
+pub struct Props {
+    pub on_close: Callback<()>,
+}
+
+#[function_component]
+pub fn SettingsDialog(props: &Props) -> Html {
+    let (settings, settings_dispatch) = use_store::<Settings>();
+    let dispatch = Dispatch::<ComputerState>::new();
+    let rom_sha1 = use_selector(|state: &ComputerState| state.rom_sha1.clone());
+    let key_map_error = use_state(|| None::<String>);
+
+    let d = dispatch.clone();
+    let handle_palette_change = Callback::from(move |e: Event| {
+        let select = e.target().unwrap().unchecked_into::<HtmlSelectElement>();
+        if let Some(palette) = Palette::preset(&select.value()) {
+            d.apply(Msg::Palette(palette));
+        }
+    });
+
+    let d = dispatch.clone();
+    let on_palette_upload = Callback::from(move |data: Vec<u8>| d.apply(Msg::LoadPalette(data)));
+
+    let handle_scale_change = settings_dispatch.reduce_mut_callback_with(|settings, e: Event| {
+        let select = e.target().unwrap().unchecked_into::<HtmlSelectElement>();
+        settings.scale = select.value().parse().unwrap_or(settings.scale);
+    });
+
+    let handle_preset_change = settings_dispatch.reduce_mut_callback_with(|settings, e: Event| {
+        let select = e.target().unwrap().unchecked_into::<HtmlSelectElement>();
+        settings.preset = Preset::find(&select.value()).map(|preset| preset.name.to_string());
+    });
+
+    let handle_volume_change = settings_dispatch.reduce_mut_callback_with(|settings, e: Event| {
+        let input = e.target().unwrap().unchecked_into::<HtmlInputElement>();
+        settings.volume = input.value().parse().unwrap_or(settings.volume);
+    });
+
+    let handle_key_click_volume_change =
+        settings_dispatch.reduce_mut_callback_with(|settings, e: Event| {
+            let input = e.target().unwrap().unchecked_into::<HtmlInputElement>();
+            settings.key_click_volume = input.value().parse().unwrap_or(settings.key_click_volume);
+        });
+
+    let handle_autofire_change = |trigger: usize| {
+        settings_dispatch.reduce_mut_callback_with(move |settings, e: Event| {
+            let select = e.target().unwrap().unchecked_into::<HtmlSelectElement>();
+            settings.autofire[trigger] = select.value().parse().unwrap_or_default();
+        })
+    };
+    let handle_autofire_a_change = handle_autofire_change(0);
+    let handle_autofire_b_change = handle_autofire_change(1);
+
+    let handle_touch_controls_change =
+        settings_dispatch.reduce_mut_callback_with(|settings, e: Event| {
+            let select = e.target().unwrap().unchecked_into::<HtmlSelectElement>();
+            if let Some(touch_controls) = TouchMode::find(&select.value()) {
+                settings.touch_controls = touch_controls;
+            }
+        });
+
+    let sd = settings_dispatch.clone();
+    let handle_remove_macro = move |code: &str| {
+        let code = code.to_string();
+        sd.reduce_mut_callback(move |settings| {
+            settings.macros.remove(&code);
+        })
+    };
+
+    let handle_autoload_change =
+        settings_dispatch.reduce_mut_callback_with(|settings, e: Event| {
+            let input = e.target().unwrap().unchecked_into::<HtmlInputElement>();
+            settings.autoload_rom = input.checked();
+            if !settings.autoload_rom {
+                settings::forget_rom();
+            }
+        });
+
+    let set_key_map = {
+        let (settings_dispatch, key_map_error) = (settings_dispatch.clone(), key_map_error.clone());
+        Callback::from(move |text: String| match KeyMap::parse(&text) {
+            Ok(key_map) => {
+                key_map_error.set(None);
+                settings_dispatch.reduce_mut(|settings| settings.key_map = key_map);
+            }
+            Err(e) => key_map_error.set(Some(e.to_string())),
+        })
+    };
+
+    let set = set_key_map.clone();
+    let handle_key_map_change = Callback::from(move |e: Event| {
+        let text = e.target().unwrap().unchecked_into::<HtmlTextAreaElement>();
+        set.emit(text.value());
+    });
+
+    let on_key_map_upload = Callback::from(move |data: Vec<u8>| {
+        set_key_map.emit(String::from_utf8_lossy(&data).into_owned());
+    });
+
+    let handle_reset_click = Callback::from(move |_| {
+        settings::forget_rom();
+        settings_dispatch.set(Settings::default());
+        dispatch.apply(Msg::Palette(Palette::default()));
+    });
+
+    let on_close = props.on_close.clone();
+    let handle_close_click = Callback::from(move |_| on_close.emit(()));
+
+    html! {
+        <div class="dialog">
+            <div class="dialog__content">
+                <h2>{ "Settings" }</h2>
+                <label class="dialog__field">
+                    { "Palette" }
+                    <select onchange={handle_palette_change}>
+                        { for Palette::PRESETS.iter().map(|(name, palette)| html! {
+                            <option value={*name} selected={settings.palette == *palette}>
+                                { name }
+                            </option>
+                        }) }
+                        if !Palette::PRESETS.iter().any(|(_, palette)| settings.palette == *palette) {
+                            <option selected=true>{ "custom" }</option>
+                        }
+                    </select>
+                    <FileUploadButton on_upload={on_palette_upload} accept=".txt,.pal">{ "Load Palette" }</FileUploadButton>
+                </label>
+                <label class="dialog__field">
+                    { "Scale" }
+                    <select onchange={handle_scale_change}>
+                        { for SCALES.iter().map(|scale| html! {
+                            <option value={scale.to_string()} selected={settings.scale == *scale}>
+                                { format!("{}x", scale) }
+                            </option>
+                        }) }
+                    </select>
+                </label>
+                <label class="dialog__field" title="Used from the next ROM opened">
+                    { "Machine" }
+                    <select onchange={handle_preset_change}>
+                        <option value="" selected={settings.preset.is_none()}>{ "default" }</option>
+                        { for Preset::PRESETS.iter().map(|preset| html! {
+                            <option value={preset.name} title={preset.description}
+                                selected={settings.preset.as_deref() == Some(preset.name)}>
+                                { preset.name }
+                            </option>
+                        }) }
+                    </select>
+                </label>
+                <label class="dialog__field" title="Only the key click is played, the PSG isn't emulated yet">
+                    { format!("Volume {}%", settings.volume) }
+                    <input type="range" min="0" max="100" value={settings.volume.to_string()}
+                        onchange={handle_volume_change} />
+                </label>
+                <label class="dialog__field" title="The 1-bit sound of the PPI, used for the key click and by some programs for all their sound">
+                    { format!("Key click {}%", settings.key_click_volume) }
+                    <input type="range" min="0" max="100" value={settings.key_click_volume.to_string()}
+                        onchange={handle_key_click_volume_change} />
+                </label>
+                { for [("A", 0, handle_autofire_a_change), ("B", 1, handle_autofire_b_change)].into_iter().map(|(name, trigger, onchange)| html! {
+                    <label class="dialog__field" title="Frames the trigger stays pressed, then released, while held">
+                        { format!("Autofire {}", name) }
+                        <select {onchange}>
+                            { for AUTOFIRE_FRAMES.iter().map(|frames| html! {
+                                <option value={frames.to_string()} selected={settings.autofire[trigger] == *frames}>
+                                    { if *frames == 0 { "off".to_string() } else { format!("{} frames", frames) } }
+                                </option>
+                            }) }
+                        </select>
+                    </label>
+                }) }
+                <label class="dialog__field" title="A d-pad and the triggers of joystick 1, and a few keys, over the screen">
+                    { "Touch controls" }
+                    <select onchange={handle_touch_controls_change}>
+                        { for TouchMode::ALL.iter().map(|touch_controls| html! {
+                            <option value={touch_controls.name()} selected={settings.touch_controls == *touch_controls}>
+                                { match touch_controls {
+                                    TouchMode::Auto => "on touch screens",
+                                    TouchMode::Always => "always",
+                                    TouchMode::Never => "never",
+                                } }
+                            </option>
+                        }) }
+                    </select>
+                </label>
+                if !settings.macros.is_empty() {
+                    <div class="dialog__field dialog__field--column">
+                        { "Macros" }
+                        { for settings.macros.iter().map(|(code, recorded)| html! {
+                            <div>
+                                { format!("{}: {} presses over {} frames ", code, recorded.events.len(), recorded.frames()) }
+                                <button onclick={handle_remove_macro(code)}>{ "Remove" }</button>
+                            </div>
+                        }) }
+                    </div>
+                }
+                <label class="dialog__field">
+                    <input type="checkbox" checked={settings.autoload_rom} onchange={handle_autoload_change} />
+                    { "Open the last ROM on start, from the next one opened" }
+                </label>
+                <label class="dialog__field dialog__field--column">
+                    { "Key map, one KeyboardEvent.code or gamepad button (PadA, PadUp...) per line, \
+                       then a matrix row and bit, an MSX key or joy and up, down, left, right, a or b. \
+                       Bindings after [<ROM SHA1>] only apply to that ROM." }
+                    <textarea rows="6" placeholder={"ShiftRight 6 0\nKeyZ joy a\nPadStart Space"}
+                        value={settings.key_map.to_text()} onchange={handle_key_map_change} />
+                    if let Some(sha1) = &*rom_sha1 {
+                        <div>{ format!("SHA1 of the ROM opened: {}", sha1) }</div>
+                    }
+                    if let Some(error) = &*key_map_error {
+                        <div class="dialog__error">{ error }</div>
+                    }
+                    <FileUploadButton on_upload={on_key_map_upload} accept=".txt,.keymap">{ "Load Key Map" }</FileUploadButton>
+                </label>
+                <div class="dialog__buttons">
+                    <button onclick={handle_reset_click}>{ "Reset" }</button>
+                    <button onclick={handle_close_click}>{ "Close" }</button>
+                </div>
+            </div>
+        </div>
+    }
+}
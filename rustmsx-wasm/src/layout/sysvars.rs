@@ -0,0 +1,22 @@
+use msx::{sysvars::SYSTEM_VARIABLES, Z80};
+use yew::prelude::*;
+
+#[derive(Properties, Clone, PartialEq)]
+pub struct Props {
+    pub cpu: Z80,
+}
+
+#[function_component]
+pub fn SystemVariables(props: &Props) -> Html {
+    html! {
+        <div class="sysvars">
+            { for SYSTEM_VARIABLES.iter().map(|var| html! {
+                <div class="sysvar" title={var.description}>
+                    <div class="sysvar__name">{ var.name }</div>
+                    <div class="sysvar__address">{ format!("{:04X}", var.address) }</div>
+                    <div class="sysvar__value">{ var.value(&props.cpu) }</div>
+                </div>
+            }) }
+        </div>
+    }
+}
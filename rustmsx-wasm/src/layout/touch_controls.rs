@@ -0,0 +1,83 @@
+use msx::{keyboard::key_position, keymap::Binding};
+use yew::prelude::*;
+use yewdux::prelude::*;
+
+use crate::store::{ComputerState, Msg};
+
+// the d-pad, by the line of joystick port 1 each button drives
+const PAD: [(&str, u8, &str); 4] = [
+    ("▲", 0, "up"),
+    ("▼", 1, "down"),
+    ("◀", 2, "left"),
+    ("▶", 3, "right"),
+];
+
+const TRIGGERS: [(&str, u8); 2] = [("A", 4), ("B", 5)];
+
+// keys most games need besides the joystick, by KeyboardEvent.code
+const KEYS: [(&str, &str); 5] = [
+    ("Space", "Space"),
+    ("Return", "Enter"),
+    ("Esc", "Escape"),
+    ("F1", "F1"),
+    ("F5", "F5"),
+];
+
+/// A d-pad and the two triggers of joystick 1, and a few keys, laid over
+/// the screen for touch screens. Each button is held while touched, so
+/// that several can be at once.
+#[function_component]
+pub fn TouchControls() -> Html {
+    let dispatch = Dispatch::<ComputerState>::new();
+
+    let button = |label: &str, class: String, binding: Binding| {
+        let press = |pressed: bool| {
+            let d = dispatch.clone();
+            Callback::from(move |e: PointerEvent| {
+                e.prevent_default();
+                d.apply(Msg::Press(binding, pressed));
+            })
+        };
+        // a mouse leaving the button while held releases it, as its
+        // pointerup goes elsewhere; a touch leaves after its pointerup
+        let release = press(false);
+        let onpointerleave = Callback::from(move |e: PointerEvent| {
+            if e.buttons() != 0 {
+                release.emit(e);
+            }
+        });
+        html! {
+            <button class={class}
+                onpointerdown={press(true)} onpointerup={press(false)}
+                onpointercancel={press(false)} {onpointerleave}
+                oncontextmenu={Callback::from(|e: MouseEvent| e.prevent_default())}>
+                { label }
+            </button>
+        }
+    };
+
+    html! {
+        <div class="touch-controls">
+            <div class="touch-controls__keys">
+                { for KEYS.iter().filter_map(|(label, code)| {
+                    let (row, bit) = key_position(code)?;
+                    Some(button(label, "touch-controls__button".to_string(), Binding::Key(row, bit)))
+                }) }
+            </div>
+            <div class="touch-controls__pad">
+                { for PAD.iter().map(|(label, line, direction)| button(
+                    label,
+                    format!("touch-controls__button touch-controls__button--{}", direction),
+                    Binding::Joystick(*line),
+                )) }
+            </div>
+            <div class="touch-controls__triggers">
+                { for TRIGGERS.iter().map(|(label, line)| button(
+                    label,
+                    "touch-controls__button touch-controls__button--trigger".to_string(),
+                    Binding::Joystick(*line),
+                )) }
+            </div>
+        </div>
+    }
+}
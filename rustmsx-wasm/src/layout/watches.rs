@@ -0,0 +1,66 @@
+use rustmsx_debugger::watch::WatchExpr;
+use wasm_bindgen::JsCast;
+use web_sys::HtmlInputElement;
+use yew::prelude::*;
+use yewdux::prelude::*;
+
+use crate::store::{ComputerState, Msg};
+
+#[function_component]
+pub fn Watches() -> Html {
+    let (state, dispatch) = use_store::<ComputerState>();
+    let input = use_state(String::new);
+    let error = use_state(|| None::<String>);
+
+    let handle_input = {
+        let input = input.clone();
+        Callback::from(move |e: InputEvent| {
+            let target = e.target().unwrap();
+            input.set(target.unchecked_into::<HtmlInputElement>().value());
+        })
+    };
+
+    let handle_submit = {
+        let (input, error, d) = (input.clone(), error.clone(), dispatch.clone());
+        Callback::from(move |e: SubmitEvent| {
+            e.prevent_default();
+            match input.parse::<WatchExpr>() {
+                Ok(expression) => {
+                    d.apply(Msg::AddWatch(expression));
+                    input.set(String::new());
+                    error.set(None);
+                }
+                Err(e) => error.set(Some(e.to_string())),
+            }
+        })
+    };
+
+    html! {
+        <div class="watches">
+            <form class="watches__form" onsubmit={handle_submit}>
+                <input type="text" placeholder="hl, c000 or c000-c00f" value={(*input).clone()} oninput={handle_input} />
+                <button type="submit">{ "Watch" }</button>
+            </form>
+            if let Some(error) = &*error {
+                <div class="dialog__error">{ error }</div>
+            }
+            { for state.debugger.borrow().watches.watches().iter().map(|watch| {
+                let expression = watch.expression;
+                let d = dispatch.clone();
+                let handle_remove = Callback::from(move |_| d.apply(Msg::RemoveWatch(expression)));
+                html! {
+                    <div class="watch">
+                        <div class="watch__expression">{ expression.to_string() }</div>
+                        <div class="watch__values">
+                            { for watch.formatted().into_iter().zip(&watch.changed).map(|(value, changed)| {
+                                let class = classes!("watch__value", changed.then_some("watch__value--changed"));
+                                html! { <span class={class}>{ value }</span> }
+                            }) }
+                        </div>
+                        <button onclick={handle_remove}>{ "Remove" }</button>
+                    </div>
+                }
+            }) }
+        </div>
+    }
+}
@@ -0,0 +1,7 @@
+//! What the debugger app and the `<rust-msx>` player have in common, so that
+//! the player can be built without the app around it.
+
+pub mod fetch;
+
+/// Steps run on every tick of the 60Hz interval.
+pub const STEPS_PER_TICK: u32 = 50000;
@@ -0,0 +1,51 @@
+//! Links to the emulator that boot something: `?rom=<url>` fetches a ROM
+//! (see [`rustmsx_wasm::fetch`]) and runs it, `#state=<data>` restores a save state, deflated and encoded
+//! as URL-safe base64 so that a debugging session can be shared.
+
+use std::io::{Read, Write};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+use gloo::utils::window;
+use web_sys::UrlSearchParams;
+
+const STATE_PREFIX: &str = "#state=";
+
+/// The URL of the `rom` query parameter of the page, if any.
+pub fn rom_url() -> Option<String> {
+    let search = window().location().search().ok()?;
+    UrlSearchParams::new_with_str(&search).ok()?.get("rom")
+}
+
+/// The save state in the fragment of the page, if any.
+pub fn state_fragment() -> Option<Result<Vec<u8>, String>> {
+    let hash = window().location().hash().ok()?;
+    let encoded = hash.strip_prefix(STATE_PREFIX)?;
+    Some(decode_state(encoded))
+}
+
+/// A link to the current page that restores a save state.
+pub fn share_link(state: &[u8]) -> Result<String, String> {
+    let location = window().location();
+    let href = location.href().map_err(|e| format!("{:?}", e))?;
+    let page = href.split('#').next().unwrap_or_default();
+    Ok(format!("{}{}{}", page, STATE_PREFIX, encode_state(state)?))
+}
+
+fn encode_state(state: &[u8]) -> Result<String, String> {
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(state).map_err(|e| e.to_string())?;
+    let compressed = encoder.finish().map_err(|e| e.to_string())?;
+    Ok(URL_SAFE_NO_PAD.encode(compressed))
+}
+
+fn decode_state(encoded: &str) -> Result<Vec<u8>, String> {
+    let compressed = URL_SAFE_NO_PAD
+        .decode(encoded)
+        .map_err(|e| format!("Invalid state in the link: {}", e))?;
+    let mut state = Vec::new();
+    DeflateDecoder::new(compressed.as_slice())
+        .read_to_end(&mut state)
+        .map_err(|e| format!("Invalid state in the link: {}", e))?;
+    Ok(state)
+}
@@ -7,8 +7,15 @@ use app::App;
 use tracing_wasm::WASMLayerConfigBuilder;
 
 mod app;
+mod audio;
 mod components;
+mod gamepad;
 mod layout;
+mod link;
+mod netplay;
+mod perf;
+mod rom_folder;
+mod settings;
 mod store;
 
 fn main() {
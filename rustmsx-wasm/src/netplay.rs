@@ -0,0 +1,168 @@
+use std::{cell::RefCell, collections::VecDeque, fmt, rc::Rc};
+
+use gloo::timers::future::TimeoutFuture;
+use js_sys::{Array, Object, Reflect};
+use msx::netplay::NetplayRole;
+use wasm_bindgen::{prelude::*, JsCast};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+    MessageEvent, RtcConfiguration, RtcDataChannel, RtcDataChannelEvent, RtcIceGatheringState,
+    RtcPeerConnection, RtcSdpType, RtcSessionDescriptionInit,
+};
+use yew::Callback;
+
+const STUN_SERVER: &str = "stun:stun.l.google.com:19302";
+
+struct PeerInner {
+    connection: RtcPeerConnection,
+    channel: RefCell<Option<RtcDataChannel>>,
+    inbox: RefCell<VecDeque<String>>,
+}
+
+/// WebRTC data channel to the other player. There is no signaling server:
+/// the offer and the answer are copied and pasted between the two browsers.
+#[derive(Clone)]
+pub struct Peer {
+    pub role: NetplayRole,
+    inner: Rc<PeerInner>,
+}
+
+impl fmt::Debug for Peer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Peer({:?})", self.role)
+    }
+}
+
+impl PartialEq for Peer {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.inner, &other.inner)
+    }
+}
+
+impl Eq for Peer {}
+
+impl Peer {
+    /// Creates the connection; `on_open` is called once the data channel is
+    /// ready to carry netplay messages.
+    pub fn new(role: NetplayRole, on_open: Callback<Peer>) -> Result<Self, JsValue> {
+        let server = Object::new();
+        Reflect::set(&server, &"urls".into(), &STUN_SERVER.into())?;
+        let config = RtcConfiguration::new();
+        config.set_ice_servers(&Array::of1(&server));
+
+        let peer = Self {
+            role,
+            inner: Rc::new(PeerInner {
+                connection: RtcPeerConnection::new_with_configuration(&config)?,
+                channel: RefCell::new(None),
+                inbox: RefCell::new(VecDeque::new()),
+            }),
+        };
+
+        match role {
+            NetplayRole::Host => {
+                let channel = peer.inner.connection.create_data_channel("netplay");
+                peer.attach_channel(channel, on_open);
+            }
+            NetplayRole::Guest => {
+                let p = peer.clone();
+                let on_data_channel = Closure::wrap(Box::new(move |event: RtcDataChannelEvent| {
+                    p.attach_channel(event.channel(), on_open.clone());
+                }) as Box<dyn FnMut(_)>);
+                peer.inner
+                    .connection
+                    .set_ondatachannel(Some(on_data_channel.as_ref().unchecked_ref()));
+                on_data_channel.forget();
+            }
+        }
+
+        Ok(peer)
+    }
+
+    fn attach_channel(&self, channel: RtcDataChannel, on_open: Callback<Peer>) {
+        let p = self.clone();
+        let on_message = Closure::wrap(Box::new(move |event: MessageEvent| {
+            if let Some(data) = event.data().as_string() {
+                p.inner.inbox.borrow_mut().push_back(data);
+            }
+        }) as Box<dyn FnMut(_)>);
+        channel.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+        on_message.forget();
+
+        let p = self.clone();
+        let on_channel_open = Closure::wrap(Box::new(move |_: JsValue| {
+            tracing::info!("[NETPLAY] Data channel open");
+            on_open.emit(p.clone());
+        }) as Box<dyn FnMut(_)>);
+        channel.set_onopen(Some(on_channel_open.as_ref().unchecked_ref()));
+        on_channel_open.forget();
+
+        *self.inner.channel.borrow_mut() = Some(channel);
+    }
+
+    /// Host side: creates the offer to be pasted on the guest.
+    pub async fn create_offer(&self) -> Result<String, JsValue> {
+        let offer = JsFuture::from(self.inner.connection.create_offer()).await?;
+        let offer = offer.unchecked_into::<RtcSessionDescriptionInit>();
+        JsFuture::from(self.inner.connection.set_local_description(&offer)).await?;
+        self.local_description().await
+    }
+
+    /// Guest side: takes the host offer and creates the answer to be pasted
+    /// back on the host.
+    pub async fn accept_offer(&self, offer: &str) -> Result<String, JsValue> {
+        let description = RtcSessionDescriptionInit::new(RtcSdpType::Offer);
+        description.set_sdp(offer);
+        JsFuture::from(self.inner.connection.set_remote_description(&description)).await?;
+
+        let answer = JsFuture::from(self.inner.connection.create_answer()).await?;
+        let answer = answer.unchecked_into::<RtcSessionDescriptionInit>();
+        JsFuture::from(self.inner.connection.set_local_description(&answer)).await?;
+        self.local_description().await
+    }
+
+    /// Host side: completes the connection with the guest answer.
+    pub async fn accept_answer(&self, answer: &str) -> Result<(), JsValue> {
+        let description = RtcSessionDescriptionInit::new(RtcSdpType::Answer);
+        description.set_sdp(answer);
+        JsFuture::from(self.inner.connection.set_remote_description(&description)).await?;
+        Ok(())
+    }
+
+    // With manual signaling there is no way to trickle candidates, so the
+    // description is only handed out once all of them were gathered.
+    async fn local_description(&self) -> Result<String, JsValue> {
+        while self.inner.connection.ice_gathering_state() != RtcIceGatheringState::Complete {
+            TimeoutFuture::new(100).await;
+        }
+
+        self.inner
+            .connection
+            .local_description()
+            .map(|d| d.sdp())
+            .ok_or_else(|| JsValue::from_str("missing local description"))
+    }
+
+    pub fn send(&self, data: &[u8]) {
+        let Ok(data) = std::str::from_utf8(data) else {
+            return;
+        };
+
+        if let Some(channel) = self.inner.channel.borrow().as_ref() {
+            if let Err(e) = channel.send_with_str(data) {
+                tracing::error!("[NETPLAY] Error sending message: {:?}", e);
+            }
+        }
+    }
+
+    pub fn take_received(&self) -> Vec<String> {
+        self.inner.inbox.borrow_mut().drain(..).collect()
+    }
+
+    pub fn close(&self) {
+        if let Some(channel) = self.inner.channel.borrow().as_ref() {
+            channel.close();
+        }
+        self.inner.connection.close();
+    }
+}
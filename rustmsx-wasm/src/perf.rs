@@ -0,0 +1,103 @@
+use rustmsx_wasm::STEPS_PER_TICK;
+
+// the ticks are run by a 60Hz interval
+const TICK_MS: f64 = 1000.0 / 60.0;
+
+// how often the averages shown are updated
+const WINDOW_MS: f64 = 1000.0;
+
+/// Milliseconds since the page loaded, with sub-millisecond precision when
+/// the browser allows it.
+pub fn now() -> f64 {
+    web_sys::window()
+        .and_then(|window| window.performance())
+        .map(|performance| performance.now())
+        .unwrap_or_else(js_sys::Date::now)
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct Window {
+    start: f64,
+    ticks: u32,
+    steps: u64,
+    cpu_ms: f64,
+    render_ms: f64,
+    ui_ms: f64,
+    uis: u32,
+}
+
+/// Timings of the emulation, fed by the tick loop and the UI, shown by the
+/// performance HUD. The averages cover the last second.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PerfStats {
+    /// emulated speed, 100 being the speed of a real machine
+    pub speed: f64,
+    /// average time per tick running the CPU, rendering the frames and
+    /// updating the page
+    pub cpu_ms: f64,
+    pub render_ms: f64,
+    pub ui_ms: f64,
+    /// ticks the browser didn't run in time
+    pub dropped_frames: u64,
+    /// times the audio queue ran dry, none until the audio starts
+    pub audio_underruns: Option<u64>,
+    last_tick: Option<f64>,
+    window: Window,
+}
+
+impl PerfStats {
+    /// Called when a tick starts, counting the ticks missed since the last.
+    pub fn tick(&mut self, now: f64) {
+        if let Some(last_tick) = self.last_tick.replace(now) {
+            let missed = ((now - last_tick) / TICK_MS).round() - 1.0;
+            if missed > 0.0 {
+                self.dropped_frames += missed as u64;
+            }
+        }
+        if self.window.start == 0.0 {
+            self.window.start = now;
+        }
+    }
+
+    /// Called when a tick ends with the steps run and where its time went.
+    pub fn record_tick(&mut self, now: f64, steps: u64, cpu_ms: f64, render_ms: f64) {
+        self.window.ticks += 1;
+        self.window.steps += steps;
+        self.window.cpu_ms += cpu_ms;
+        self.window.render_ms += render_ms;
+
+        let elapsed = now - self.window.start;
+        if elapsed < WINDOW_MS {
+            return;
+        }
+        let window = std::mem::replace(
+            &mut self.window,
+            Window {
+                start: now,
+                ..Window::default()
+            },
+        );
+        let ticks = window.ticks as f64;
+        let expected_steps = STEPS_PER_TICK as f64 * elapsed / TICK_MS;
+        self.speed = 100.0 * window.steps as f64 / expected_steps;
+        self.cpu_ms = window.cpu_ms / ticks;
+        self.render_ms = window.render_ms / ticks;
+        self.ui_ms = match window.uis {
+            0 => 0.0,
+            uis => window.ui_ms / uis as f64,
+        };
+    }
+
+    /// Called after the page was updated, with how long it took.
+    pub fn record_ui(&mut self, ui_ms: f64) {
+        self.window.uis += 1;
+        self.window.ui_ms += ui_ms;
+    }
+
+    /// Forgets the last tick, so that a pause isn't counted as dropped
+    /// frames.
+    pub fn pause(&mut self) {
+        self.last_tick = None;
+        self.window = Window::default();
+    }
+}
@@ -0,0 +1,68 @@
+//! A local ROM folder remembered with the File System Access API, so ROMs
+//! can be opened without uploading them every time. Only some browsers
+//! support it, see [`supported`].
+
+use js_sys::{Array, Promise, Uint8Array};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::JsFuture;
+
+#[wasm_bindgen(module = "/js/rom_folder.js")]
+extern "C" {
+    #[wasm_bindgen(js_name = supported)]
+    fn js_supported() -> bool;
+    #[wasm_bindgen(js_name = savedFolderName)]
+    fn js_saved_folder_name() -> Promise;
+    #[wasm_bindgen(js_name = pickFolder)]
+    fn js_pick_folder() -> Promise;
+    #[wasm_bindgen(js_name = listSavedFolder)]
+    fn js_list_saved_folder() -> Promise;
+    #[wasm_bindgen(js_name = readRom)]
+    fn js_read_rom(name: &str) -> Promise;
+    #[wasm_bindgen(js_name = forgetFolder)]
+    fn js_forget_folder() -> Promise;
+}
+
+/// Whether the browser can remember a ROM folder.
+pub fn supported() -> bool {
+    js_supported()
+}
+
+async fn call(promise: Promise) -> Result<JsValue, String> {
+    JsFuture::from(promise).await.map_err(|e| {
+        e.dyn_ref::<js_sys::Error>()
+            .map(|e| String::from(e.message()))
+            .unwrap_or_else(|| format!("{:?}", e))
+    })
+}
+
+fn names(value: JsValue) -> Vec<String> {
+    Array::from(&value)
+        .iter()
+        .filter_map(|name| name.as_string())
+        .collect()
+}
+
+/// Name of the remembered folder, if any.
+pub async fn saved_folder_name() -> Option<String> {
+    call(js_saved_folder_name()).await.ok()?.as_string()
+}
+
+/// Asks for a folder to remember, returning the ROMs in it.
+pub async fn pick_folder() -> Result<Vec<String>, String> {
+    call(js_pick_folder()).await.map(names)
+}
+
+/// The ROMs in the remembered folder. The browser may ask to allow the
+/// access again, so it must be called on a click.
+pub async fn list_saved_folder() -> Result<Vec<String>, String> {
+    call(js_list_saved_folder()).await.map(names)
+}
+
+pub async fn read_rom(name: &str) -> Result<Vec<u8>, String> {
+    let data = call(js_read_rom(name)).await?;
+    Ok(Uint8Array::new(&data).to_vec())
+}
+
+pub async fn forget_folder() -> Result<(), String> {
+    call(js_forget_folder()).await.map(|_| ())
+}
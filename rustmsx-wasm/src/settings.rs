@@ -0,0 +1,114 @@
+use std::collections::BTreeMap;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use gloo::storage::{LocalStorage, Storage};
+use msx::{input::Macro, keyboard::InputProfile, keymap::KeyMap, palette::Palette};
+use serde::{Deserialize, Serialize};
+use yewdux::prelude::*;
+
+// the last ROM opened, kept apart from the settings as it's large
+const ROM_KEY: &str = "rustmsx.rom";
+
+pub const SCALES: [u32; 4] = [1, 2, 3, 4];
+
+/// User preferences, saved to the local storage of the browser whenever
+/// they change.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Store)]
+#[store(storage = "local")]
+#[serde(default)]
+pub struct Settings {
+    /// host keys and gamepad buttons bound to other inputs than the
+    /// default ones, see [`KeyMap`]
+    pub key_map: KeyMap,
+    /// what the keys at the cursor keys and space bar positions drive
+    pub input_profile: InputProfile,
+    /// frames the joystick triggers A and B stay pressed and released
+    /// while held, 0 without autofire
+    pub autofire: [u32; 2],
+    /// macros played by host keys or gamepad buttons, by their name in the
+    /// key map
+    pub macros: BTreeMap<String, Macro>,
+    pub palette: Palette,
+    /// size of the screen pixels
+    pub scale: u32,
+    /// master volume, 0 to 100
+    pub volume: u8,
+    /// volume of the key click, 0 to 100, before the master volume
+    pub key_click_volume: u8,
+    /// opens the last ROM opened when the page loads
+    pub autoload_rom: bool,
+    /// name of the machine preset ROMs are opened with, if any
+    pub preset: Option<String>,
+    /// when the d-pad, triggers and keys are shown over the screen
+    pub touch_controls: TouchMode,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            key_map: KeyMap::default(),
+            input_profile: InputProfile::default(),
+            autofire: [0; 2],
+            macros: BTreeMap::new(),
+            palette: Palette::default(),
+            scale: 3,
+            volume: 100,
+            key_click_volume: 100,
+            autoload_rom: false,
+            preset: None,
+            touch_controls: TouchMode::default(),
+        }
+    }
+}
+
+/// When the touch controls are shown over the screen.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TouchMode {
+    /// on touch screens
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl TouchMode {
+    pub const ALL: [TouchMode; 3] = [Self::Auto, Self::Always, Self::Never];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Auto => "auto",
+            Self::Always => "always",
+            Self::Never => "never",
+        }
+    }
+
+    pub fn find(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|touch| touch.name() == name)
+    }
+
+    pub fn shown(self) -> bool {
+        match self {
+            Self::Auto => {
+                web_sys::window().is_some_and(|window| window.navigator().max_touch_points() > 0)
+            }
+            Self::Always => true,
+            Self::Never => false,
+        }
+    }
+}
+
+/// Keeps a ROM to open on the next visit.
+pub fn save_rom(data: &[u8]) {
+    if let Err(e) = LocalStorage::set(ROM_KEY, STANDARD.encode(data)) {
+        tracing::error!("Error saving the ROM: {}", e);
+    }
+}
+
+pub fn load_rom() -> Option<Vec<u8>> {
+    let data: String = LocalStorage::get(ROM_KEY).ok()?;
+    STANDARD.decode(data).ok()
+}
+
+pub fn forget_rom() {
+    LocalStorage::delete(ROM_KEY);
+}
@@ -1,16 +1,101 @@
-use std::rc::Rc;
+use std::{collections::BTreeSet, rc::Rc};
 
-use msx::Msx;
+use msx::{
+    archive,
+    frame::FrameBuffer,
+    input::{Controls, Macro},
+    keyboard::NO_KEYS,
+    keymap::Binding,
+    machine::{ResetKind, STEPS_PER_FRAME},
+    netplay::{Input, NetplayMessage, NetplayRole, NetplaySession},
+    palette::Palette,
+    preset::Preset,
+    romdb::{self, RomDatabase, RomInfo},
+    screen_reader::{self, ScreenReader},
+    source_map::SourceMap,
+    Msx,
+};
+use rustmsx_debugger::{
+    command::{Command, CommandLine},
+    stepping::{Pending, StepStop},
+    watch::WatchExpr,
+    Debugger,
+};
+use rustmsx_wasm::STEPS_PER_TICK;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::{spawn_local, JsFuture};
 use yewdux::{mrc::Mrc, prelude::*};
 
-use crate::layout::Renderer;
+use crate::{
+    audio::AudioOutput,
+    gamepad, link,
+    netplay::Peer,
+    perf::{self, PerfStats},
+    settings::{self, Settings},
+};
+
+// netplay frames (one per tick) that local input is delayed by
+pub const NETPLAY_INPUT_DELAY: u64 = 4;
+
+// netplay frames between checksum exchanges
+const NETPLAY_CHECKSUM_INTERVAL: u64 = 60;
+
+// lines the console keeps, dropping the oldest
+const CONSOLE_LINES: usize = 500;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Msg {
     LoadRom(Vec<u8>),
+    SaveState,
+    LoadState(Vec<u8>),
+    ShareState,
+    /// puts the text of the screen, read from the name table, on the
+    /// clipboard
+    CopyScreenText,
+    Error(String),
+    Palette(Palette),
+    LoadPalette(Vec<u8>),
     Toggle,
+    ToggleHud,
+    Reset(ResetKind),
     Step,
+    Frame,
+    Slow(u32),
     Tick,
+    /// a host key or gamepad button by its name in the key map
+    HostKey(String, bool),
+    /// an MSX key or joystick line pressed on the touch controls
+    Press(Binding, bool),
+    /// starts recording a macro, or stops and waits for its key
+    RecordMacro,
+    AddWatch(WatchExpr),
+    RemoveWatch(WatchExpr),
+    /// a line typed on the console, in the syntax of the CLI prompt
+    Command(String),
+    NetplayStart(Peer),
+    NetplayStop,
+}
+
+#[derive(Debug)]
+pub struct Netplay {
+    pub session: NetplaySession,
+    pub peer: Peer,
+    pub local_input: Input,
+}
+
+impl Netplay {
+    fn new(peer: Peer) -> Self {
+        let mut session =
+            NetplaySession::new(peer.role, NETPLAY_INPUT_DELAY, NETPLAY_CHECKSUM_INTERVAL);
+        // one netplay frame per tick, input is sampled at 60Hz
+        session.frames_per_input = STEPS_PER_TICK / STEPS_PER_FRAME as u32;
+
+        Self {
+            session,
+            peer,
+            local_input: NO_KEYS,
+        }
+    }
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Eq)]
@@ -24,9 +109,366 @@ pub enum ExecutionState {
 #[derive(Default, Debug, Clone, PartialEq, Store)]
 pub struct ComputerState {
     pub msx: Mrc<Msx>,
-    pub screen_buffer: Vec<u8>,
+    pub frame: FrameBuffer,
     pub state: ExecutionState,
     pub error: Option<String>,
+    pub netplay: Mrc<Option<Netplay>>,
+    pub rom_info: Option<RomInfo>,
+    /// SHA1 of the ROM opened, which picks the overrides of the key map
+    pub rom_sha1: Option<String>,
+    /// gamepad buttons held down at the last tick
+    pub gamepad_buttons: BTreeSet<String>,
+    pub input: Mrc<Controls>,
+    /// macro recorded last, bound to the next key pressed
+    pub unbound_macro: Option<Macro>,
+    /// slow motion factor, the machine runs at 1/slow of the normal speed
+    pub slow: u32,
+    pub perf: Mrc<PerfStats>,
+    /// started on the first user gesture, as browsers require
+    pub audio: Mrc<Option<AudioOutput>>,
+    /// whether the performance HUD is shown over the screen
+    pub hud: bool,
+    /// link restoring the state shared last
+    pub share_link: Option<String>,
+    pub screen_reader: Mrc<ScreenReader>,
+    /// ticks since the screen reader was updated
+    pub screen_reader_ticks: u32,
+    /// text lines that changed last, for the ARIA live region
+    pub screen_text: String,
+    pub debugger: Mrc<Debugger>,
+    /// the commands typed on the console and what they printed
+    pub console: Vec<String>,
+}
+
+impl ComputerState {
+    fn render(&mut self) {
+        // renders from the machine's VDP, which keeps the decoded patterns
+        // between frames
+        self.msx.borrow().render(&mut self.frame);
+    }
+
+    // presses what a host key or gamepad button is bound to; netplay only
+    // exchanges the keyboard matrix, so it ignores the input profile and
+    // the joystick
+    fn press(&mut self, binding: Binding, pressed: bool) {
+        if let Some(netplay) = self.netplay.borrow_mut().as_mut() {
+            if let Binding::Key(row, bit) = binding {
+                let value = &mut netplay.local_input[row];
+                if pressed {
+                    *value &= !(1 << bit);
+                } else {
+                    *value |= 1 << bit;
+                }
+            }
+            return;
+        }
+
+        let profile = Dispatch::<Settings>::new().get().input_profile;
+        let binding = match binding {
+            Binding::Key(row, bit) => profile
+                .joystick_line((row, bit))
+                .map_or(binding, Binding::Joystick),
+            binding => binding,
+        };
+        let mut msx = self.msx.borrow_mut();
+        self.input.borrow_mut().press(&mut msx, binding, pressed);
+    }
+
+    // a host key or gamepad button goes to the macro waiting for a key,
+    // plays its macro or presses what it's bound to
+    fn host_key(&mut self, code: &str, pressed: bool) {
+        if pressed {
+            if let Some(recorded) = self.unbound_macro.take() {
+                Dispatch::<Settings>::new().reduce_mut(|settings| {
+                    settings.macros.insert(code.to_string(), recorded);
+                });
+                return;
+            }
+        }
+
+        let settings = Dispatch::<Settings>::new().get();
+        if let Some(recorded) = settings.macros.get(code) {
+            if pressed && self.netplay.borrow().is_none() {
+                self.input.borrow_mut().play(recorded);
+            }
+            return;
+        }
+        let rom_sha1 = self.rom_sha1.clone();
+        if let Some(binding) = settings.key_map.binding(code, rom_sha1.as_deref()) {
+            self.press(binding, pressed);
+        }
+    }
+
+    // advances the autofire and the macros, once per tick or frame stepped
+    fn input_frame(&mut self) {
+        let mut input = self.input.borrow_mut();
+        input.autofire = Dispatch::<Settings>::new().get().autofire;
+        input.frame(&mut self.msx.borrow_mut());
+    }
+
+    // stops running and the sound, the performance counters not counting
+    // the pause
+    fn pause(&mut self) {
+        self.state = ExecutionState::Paused;
+        self.perf.borrow_mut().pause();
+        if let Some(audio) = self.audio.borrow_mut().as_mut() {
+            audio.pause();
+        }
+    }
+
+    fn console_print(&mut self, text: &str) {
+        self.console
+            .extend(text.trim_end().lines().map(String::from));
+        let excess = self.console.len().saturating_sub(CONSOLE_LINES);
+        self.console.drain(..excess);
+    }
+
+    // pauses at a breakpoint of the debugger or where its step is over,
+    // after the instruction `pending` was told about ran, telling on the
+    // console
+    fn check_stops(&mut self, pending: Pending) -> bool {
+        let text = {
+            let mut debugger = self.debugger.borrow_mut();
+            let msx = self.msx.borrow();
+            let hit = match debugger.breakpoints.is_empty() {
+                true => None,
+                false => debugger.breakpoints.check(&msx.cpu),
+            };
+            let step_stop = match debugger.stepping.is_active() {
+                true => debugger
+                    .stepping
+                    .after(&msx, &SourceMap::default(), pending),
+                false => None,
+            };
+            match (hit, step_stop) {
+                (Some(bp), _) => format!("Breakpoint #{} hit at {:#06X}", bp.id, msx.pc()),
+                (None, Some(StepStop::Reached(address))) => format!("Reached {:#06X}", address),
+                (None, Some(StepStop::Returned(address))) => {
+                    format!("Returned to {:#06X}", address)
+                }
+                (None, Some(StepStop::Line)) | (None, None) => return false,
+            }
+        };
+        self.console_print(&text);
+        self.debugger.borrow_mut().stepping.clear();
+        self.pause();
+        true
+    }
+
+    // runs a console command with the debugger, or here when it needs the
+    // page, e.g. to run or to watch
+    fn run_command(&mut self, line: &str) {
+        self.console_print(&format!("> {}", line));
+        let command = match CommandLine::parse(line) {
+            Ok(line) => line.command,
+            Err(e) => {
+                self.console_print(&e.to_string());
+                return;
+            }
+        };
+        if matches!(command, Command::Step(_) | Command::Frame(_))
+            && self.state == ExecutionState::Running
+        {
+            self.pause();
+        }
+
+        let res = {
+            let mut msx = self.msx.borrow_mut();
+            self.debugger.borrow_mut().execute(&mut msx, command)
+        };
+        let output = match res {
+            Ok(output) => output,
+            Err(Command::Continue) => {
+                self.state = ExecutionState::Running;
+                self.start_audio();
+                String::new()
+            }
+            Err(Command::Until(address)) => {
+                self.debugger.borrow_mut().stepping.run_until(address);
+                self.state = ExecutionState::Running;
+                self.start_audio();
+                String::new()
+            }
+            Err(Command::Reset(kind)) => {
+                self.msx.borrow_mut().reset_with(kind);
+                String::new()
+            }
+            Err(Command::Watch(None)) => {
+                let debugger = self.debugger.borrow();
+                let watches = debugger.watches.watches().iter();
+                watches
+                    .map(|watch| {
+                        let values = watch.formatted().join(" ");
+                        format!("{:<10} {}\n", watch.expression, values)
+                    })
+                    .collect()
+            }
+            Err(Command::Screen(None)) => self.msx.borrow().screen_text().join("\n"),
+            Err(_) => format!(
+                "{} isn't available in the browser",
+                line.split_whitespace().next().unwrap_or_default()
+            ),
+        };
+        self.console_print(&output);
+        self.render();
+        self.update_watches();
+    }
+
+    // runs the commands of the timers the machine reached, as if typed in
+    // the console
+    fn run_timers(&mut self) {
+        let due = {
+            let msx = self.msx.borrow();
+            self.debugger.borrow_mut().timers.due(&msx)
+        };
+        for timer in due {
+            self.run_command(&timer.command);
+        }
+    }
+
+    // reads the watched values, marking the ones the last tick or step
+    // changed
+    fn update_watches(&mut self) {
+        let msx = self.msx.borrow();
+        self.debugger.borrow_mut().watches.update(&msx.cpu);
+    }
+
+    // announces the lines of the text screen that changed, a few times a
+    // second
+    fn read_screen(&mut self) {
+        self.screen_reader_ticks += 1;
+        if self.screen_reader_ticks < screen_reader::UPDATE_FRAMES {
+            return;
+        }
+        self.screen_reader_ticks = 0;
+        let screen = self.msx.borrow().screen_text();
+        let lines = self.screen_reader.borrow_mut().update(screen);
+        if !lines.is_empty() {
+            self.screen_text = lines.join("\n");
+        }
+    }
+
+    // presses and releases the gamepad buttons changed since the last tick
+    fn poll_gamepads(&mut self) {
+        let buttons = gamepad::pressed_buttons();
+        if buttons == self.gamepad_buttons {
+            return;
+        }
+        let previous = std::mem::replace(&mut self.gamepad_buttons, buttons.clone());
+        let released = previous.difference(&buttons).map(|button| (button, false));
+        let pressed = buttons.difference(&previous).map(|button| (button, true));
+        for (button, pressed) in released.chain(pressed) {
+            self.host_key(button, pressed);
+        }
+    }
+
+    // starts the audio output, or resumes it if the browser suspended it
+    fn start_audio(&mut self) {
+        let mut audio = self.audio.borrow_mut();
+        match audio.as_mut() {
+            Some(audio) => audio.resume(),
+            None => match AudioOutput::new() {
+                Ok(output) => *audio = Some(output),
+                Err(e) => tracing::error!("[AUDIO] Error starting: {:?}", e),
+            },
+        }
+    }
+
+    // plays the sound since the last call at the volume of the settings
+    fn play_audio(&mut self) {
+        let mut audio = self.audio.borrow_mut();
+        let Some(audio) = audio.as_mut() else {
+            return;
+        };
+        let settings = Dispatch::<Settings>::new().get();
+        let mut msx = self.msx.borrow_mut();
+        msx.set_volume(
+            settings.volume as f32 / 100.0,
+            settings.key_click_volume as f32 / 100.0,
+        );
+        let samples = msx.audio_samples(audio.sample_rate());
+        if let Err(e) = audio.play(&samples) {
+            tracing::error!("[AUDIO] Error playing: {:?}", e);
+        }
+        self.perf.borrow_mut().audio_underruns = Some(audio.underruns);
+    }
+
+    fn netplay_tick(&mut self) {
+        let advanced = {
+            let mut netplay = self.netplay.borrow_mut();
+            let Some(netplay) = netplay.as_mut() else {
+                return;
+            };
+            let mut msx = self.msx.borrow_mut();
+
+            for data in netplay.peer.take_received() {
+                let res = NetplayMessage::from_bytes(data.as_bytes())
+                    .and_then(|message| netplay.session.handle_message(message, &mut msx));
+                if let Err(e) = res {
+                    tracing::error!("[NETPLAY] Invalid message: {}", e);
+                }
+            }
+
+            netplay.session.push_local_input(netplay.local_input);
+            // runs at most two frames per tick to catch up with the peer
+            let mut advanced = false;
+            for _ in 0..2 {
+                if !netplay.session.advance(&mut msx) {
+                    break;
+                }
+                advanced = true;
+            }
+
+            for message in netplay.session.take_outgoing() {
+                match message.to_bytes() {
+                    Ok(data) => netplay.peer.send(&data),
+                    Err(e) => tracing::error!("[NETPLAY] Error encoding message: {}", e),
+                }
+            }
+
+            advanced
+        };
+
+        if advanced {
+            self.render();
+        }
+    }
+}
+
+// offers the data as a file download
+fn download(data: &[u8], name: &str) -> Result<(), wasm_bindgen::JsValue> {
+    let blob = gloo::file::Blob::new_with_options(data, Some("application/json"));
+    let url = gloo::file::ObjectUrl::from(blob);
+    let document = web_sys::window().unwrap().document().unwrap();
+    let link = document
+        .create_element("a")?
+        .dyn_into::<web_sys::HtmlAnchorElement>()?;
+    link.set_href(&url);
+    link.set_download(name);
+    link.click();
+    Ok(())
+}
+
+// puts the text on the clipboard, which the browser may refuse, e.g.
+// without the focus
+fn copy_to_clipboard(text: &str) {
+    let promise = web_sys::window()
+        .unwrap()
+        .navigator()
+        .clipboard()
+        .write_text(text);
+    spawn_local(async move {
+        if let Err(e) = JsFuture::from(promise).await {
+            let error = e
+                .dyn_ref::<js_sys::Error>()
+                .map(|e| String::from(e.message()))
+                .unwrap_or_else(|| format!("{:?}", e));
+            Dispatch::<ComputerState>::new().apply(Msg::Error(format!(
+                "Error copying the screen text: {}",
+                error
+            )));
+        }
+    });
 }
 
 impl Reducer<ComputerState> for Msg {
@@ -36,46 +478,237 @@ impl Reducer<ComputerState> for Msg {
         // tracing::info!("[{:?}] Received message: {:?}", state.state, self);
 
         match self {
-            Msg::Toggle => {
-                state.state = match state.state {
-                    ExecutionState::Off => ExecutionState::Running,
-                    ExecutionState::Running => ExecutionState::Paused,
-                    ExecutionState::Paused => ExecutionState::Running,
-                };
+            Msg::Toggle => match state.state {
+                ExecutionState::Running => state.pause(),
+                ExecutionState::Off | ExecutionState::Paused => {
+                    state.state = ExecutionState::Running;
+                    state.start_audio();
+                }
+            },
+            Msg::ToggleHud => {
+                state.hud = !state.hud;
             }
             Msg::Tick => {
                 if state.state != ExecutionState::Running {
                     return store;
                 }
 
-                for _ in 0..50000 {
+                state.poll_gamepads();
+                if state.netplay.borrow().is_some() {
+                    state.netplay_tick();
+                    state.read_screen();
+                    state.update_watches();
+                    return store;
+                }
+
+                let start = perf::now();
+                state.perf.borrow_mut().tick(start);
+                let mut steps = 0;
+                let mut render_ms = 0.0;
+                for _ in 0..STEPS_PER_TICK / state.slow.max(1) {
+                    let pending = state.debugger.borrow().stepping.before(&state.msx.borrow());
                     state.msx.borrow_mut().step();
+                    steps += 1;
 
                     if state.msx.borrow().current_scanline == 0 {
-                        let msx = state.msx.borrow();
-                        let vdp = msx.get_vdp();
-                        let mut renderer = Renderer::new(&vdp);
-                        renderer.draw(0, 0, 256, 192);
-                        state.screen_buffer = renderer.screen_buffer.to_vec();
+                        let render_start = perf::now();
+                        state.render();
+                        render_ms += perf::now() - render_start;
                     }
 
-                    if state.state != ExecutionState::Running {
+                    if !state.debugger.borrow().timers.is_empty() {
+                        state.run_timers();
+                    }
+
+                    if state.check_stops(pending) || state.state != ExecutionState::Running {
                         break;
                     }
                 }
+                state.input_frame();
+                state.read_screen();
+                state.update_watches();
+                state.play_audio();
+                let end = perf::now();
+                state
+                    .perf
+                    .borrow_mut()
+                    .record_tick(end, steps, end - start - render_ms, render_ms);
+            }
+            Msg::Reset(kind) => {
+                state.msx.borrow_mut().reset_with(kind);
             }
             Msg::Step => {
                 state.msx.borrow_mut().step();
+                state.update_watches();
+            }
+            Msg::Frame => {
+                if state.state == ExecutionState::Running {
+                    state.state = ExecutionState::Paused;
+                }
+                state.input_frame();
+                // through the rest of the frame the VDP draws, unless it
+                // stops at a breakpoint
+                let frame = state.msx.borrow().frames();
+                loop {
+                    let pending = state.debugger.borrow().stepping.before(&state.msx.borrow());
+                    state.msx.borrow_mut().step();
+                    if state.check_stops(pending) {
+                        break;
+                    }
+                    let msx = state.msx.borrow();
+                    if msx.frames() != frame || msx.halted() {
+                        break;
+                    }
+                }
+                state.render();
+                state.update_watches();
+            }
+            Msg::Palette(palette) => {
+                state.frame.set_palette(palette);
+                Dispatch::<Settings>::new().reduce_mut(|settings| settings.palette = palette);
+            }
+            Msg::LoadPalette(data) => {
+                let res = std::str::from_utf8(&data)
+                    .map_err(anyhow::Error::from)
+                    .and_then(Palette::parse);
+                match res {
+                    Ok(palette) => {
+                        state.error = None;
+                        state.frame.set_palette(palette);
+                        Dispatch::<Settings>::new()
+                            .reduce_mut(|settings| settings.palette = palette);
+                    }
+                    Err(e) => state.error = Some(e.to_string()),
+                }
+            }
+            Msg::Slow(factor) => {
+                state.slow = factor;
             }
             // Msg::Render(new_buffer) => {
             //     state.screen_buffer = new_buffer;
             // }
+            Msg::HostKey(code, pressed) => {
+                state.host_key(&code, pressed);
+            }
+            Msg::Press(binding, pressed) => {
+                state.press(binding, pressed);
+            }
+            Msg::RecordMacro => {
+                let mut input = state.input.borrow_mut();
+                if input.recording() {
+                    state.unbound_macro = input.stop_recording();
+                } else {
+                    state.unbound_macro = None;
+                    input.start_recording();
+                }
+            }
+            Msg::AddWatch(expression) => {
+                let msx = state.msx.borrow();
+                state
+                    .debugger
+                    .borrow_mut()
+                    .watches
+                    .add(expression, &msx.cpu);
+            }
+            Msg::RemoveWatch(expression) => {
+                state.debugger.borrow_mut().watches.remove(&expression);
+            }
+            Msg::Command(line) => state.run_command(&line),
+            Msg::NetplayStart(peer) => {
+                tracing::info!("[NETPLAY] Starting session as {:?}", peer.role);
+                // the host machine is the reference, the guest starts from its state
+                if peer.role == NetplayRole::Host {
+                    match state.msx.borrow().save_state() {
+                        Ok(data) => peer.send(
+                            &NetplayMessage::State { frame: 0, data }
+                                .to_bytes()
+                                .unwrap_or_default(),
+                        ),
+                        Err(e) => tracing::error!("[NETPLAY] Error saving state: {}", e),
+                    }
+                }
+                *state.netplay.borrow_mut() = Some(Netplay::new(peer));
+                state.state = ExecutionState::Running;
+            }
+            Msg::NetplayStop => {
+                if let Some(netplay) = state.netplay.borrow_mut().take() {
+                    netplay.peer.close();
+                }
+            }
             Msg::LoadRom(data) => {
+                let data = match archive::extract(&data, None) {
+                    Ok(data) => data,
+                    Err(e) => {
+                        state.error = Some(e.to_string());
+                        return store;
+                    }
+                };
+                state.error = None;
+                state.rom_info = RomDatabase::embedded().lookup(&data).cloned();
+                state.rom_sha1 = Some(romdb::sha1_hex(&data));
+                if Dispatch::<Settings>::new().get().autoload_rom {
+                    settings::save_rom(&data);
+                }
+
+                let preset = Dispatch::<Settings>::new().get().preset.clone();
                 let mut msx = state.msx.borrow_mut();
-                msx.load_rom(0, &data);
-                msx.load_empty(1);
-                msx.load_empty(2);
-                msx.load_ram(3);
+                msx.load_machine(&data, preset.as_deref().and_then(Preset::find));
+                msx.enable_io_log();
+                msx.enable_audio();
+            }
+            Msg::SaveState => {
+                // the same format as the CLI, so states can be debugged there
+                let res = state
+                    .msx
+                    .borrow()
+                    .save_state()
+                    .map_err(|e| e.to_string())
+                    .and_then(|data| {
+                        download(&data, "rustmsx.state").map_err(|e| format!("{:?}", e))
+                    });
+                state.error = res.err();
+            }
+            Msg::ShareState => {
+                let res = state
+                    .msx
+                    .borrow()
+                    .save_state()
+                    .map_err(|e| e.to_string())
+                    .and_then(|data| link::share_link(&data));
+                match res {
+                    Ok(link) => {
+                        state.error = None;
+                        state.share_link = Some(link);
+                    }
+                    Err(e) => state.error = Some(e),
+                }
+            }
+            Msg::CopyScreenText => {
+                let mut lines = state.msx.borrow().screen_text();
+                if lines.is_empty() {
+                    state.error = Some("The screen isn't in a text mode".to_string());
+                } else {
+                    while lines.last().is_some_and(|line| line.is_empty()) {
+                        lines.pop();
+                    }
+                    state.error = None;
+                    copy_to_clipboard(&lines.join("\n"));
+                }
+            }
+            Msg::Error(error) => {
+                state.error = Some(error);
+            }
+            Msg::LoadState(data) => {
+                let res = state.msx.borrow_mut().load_state(&data);
+                match res {
+                    Ok(()) => {
+                        state.error = None;
+                        state.rom_info = None;
+                        state.rom_sha1 = None;
+                        state.render();
+                    }
+                    Err(e) => state.error = Some(e.to_string()),
+                }
             }
         };
 
@@ -0,0 +1,50 @@
+// Caches the emulator as it's fetched so that the installed app also works
+// offline. Answers from the cache first and refreshes it in the background,
+// so a new build is picked up on the next visit.
+
+const CACHE = "rustmsx-v1";
+
+self.addEventListener("install", () => self.skipWaiting());
+
+self.addEventListener("activate", (event) => {
+  event.waitUntil(
+    caches
+      .keys()
+      .then((keys) =>
+        Promise.all(keys.filter((key) => key !== CACHE).map((key) => caches.delete(key)))
+      )
+      .then(() => self.clients.claim())
+  );
+});
+
+self.addEventListener("fetch", (event) => {
+  const request = event.request;
+  // ROMs from ?rom= links and other sites are left to the network
+  if (request.method !== "GET" || new URL(request.url).origin !== self.location.origin) {
+    return;
+  }
+
+  event.respondWith(
+    caches.open(CACHE).then(async (cache) => {
+      const cached = await cache.match(request, { ignoreSearch: request.mode === "navigate" });
+      const fetched = fetch(request)
+        .then((response) => {
+          if (response.ok) {
+            cache.put(request, response.clone());
+          }
+          return response;
+        })
+        .catch((error) => {
+          if (cached) {
+            return cached;
+          }
+          throw error;
+        });
+      if (cached) {
+        event.waitUntil(fetched.catch(() => {}));
+        return cached;
+      }
+      return fetched;
+    })
+  );
+});
@@ -0,0 +1,139 @@
+use std::{
+    collections::VecDeque,
+    fmt::Write,
+    fs,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use msx::{frame_hash::FrameHash, Msx};
+
+// autosaves kept, the oldest being deleted
+const KEEP: usize = 3;
+
+/// The rolling saves of `--autosave-every`, for the long unattended runs:
+/// every interval of wall time, the state of the machine is written to
+/// autosave-<frame>.state in a directory, along with the hashes of the
+/// frames run since the previous save in autosave-<frame>.hashes, in the
+/// format of `--frame-hashes`. Only the last few saves are kept, so that
+/// whatever goes wrong overnight has a state from shortly before it to
+/// resume from with `--state`.
+pub struct Autosave {
+    dir: PathBuf,
+    interval: Duration,
+    last: Instant,
+    // last frame hashed, counted from 1
+    frame: u64,
+    hashes: String,
+    // paths of the saves kept, oldest first, without the extensions
+    saved: VecDeque<PathBuf>,
+}
+
+impl Autosave {
+    /// Saves to a directory, which is created on the first save.
+    pub fn new(dir: PathBuf, interval: Duration) -> Self {
+        Self {
+            dir,
+            interval,
+            last: Instant::now(),
+            frame: 0,
+            hashes: String::new(),
+            saved: VecDeque::new(),
+        }
+    }
+
+    /// Keeps the hash of a frame for the next save.
+    pub fn frame(&mut self, hash: &FrameHash) {
+        self.frame = hash.frame;
+        writeln!(self.hashes, "{}", hash).unwrap();
+    }
+
+    /// Saves the state when the interval has passed since the last save,
+    /// returning the path of the state.
+    pub fn save_if_due(&mut self, msx: &Msx) -> anyhow::Result<Option<PathBuf>> {
+        if self.last.elapsed() < self.interval {
+            return Ok(None);
+        }
+        // a failing save is retried after another interval, rather than
+        // on every frame
+        self.last = Instant::now();
+
+        fs::create_dir_all(&self.dir)?;
+        let base = self.dir.join(format!("autosave-{}", self.frame));
+        let state = base.with_extension("state");
+        fs::write(&state, msx.save_state()?)?;
+        fs::write(base.with_extension("hashes"), &self.hashes)?;
+        self.hashes.clear();
+
+        // the same frame when nothing ran since, e.g. stopped at the prompt
+        if self.saved.back() != Some(&base) {
+            self.saved.push_back(base);
+        }
+        if self.saved.len() > KEEP {
+            if let Some(oldest) = self.saved.pop_front() {
+                for extension in ["state", "hashes"] {
+                    fs::remove_file(oldest.with_extension(extension))?;
+                }
+            }
+        }
+        Ok(Some(state))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use msx::slot::{RamSlot, SlotType};
+
+    use super::*;
+
+    #[test]
+    fn test_save_if_due() {
+        let msx = Msx::new(&[
+            SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Empty,
+        ]);
+        let dir = tempfile::tempdir().unwrap();
+        let mut autosave = Autosave::new(dir.path().join("saves"), Duration::ZERO);
+
+        // two frames between saves
+        for save in 1..=KEEP as u64 + 1 {
+            for frame in save * 2 - 1..=save * 2 {
+                autosave.frame(&FrameHash {
+                    frame,
+                    crc32: frame as u32,
+                    expected: None,
+                });
+            }
+            let state = autosave.save_if_due(&msx).unwrap();
+            let expected = dir
+                .path()
+                .join(format!("saves/autosave-{}.state", save * 2));
+            assert_eq!(state, Some(expected));
+        }
+
+        let mut files: Vec<String> = fs::read_dir(dir.path().join("saves"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        files.sort();
+        // the pair of the first save is the one deleted
+        assert_eq!(
+            files,
+            [
+                "autosave-4.hashes",
+                "autosave-4.state",
+                "autosave-6.hashes",
+                "autosave-6.state",
+                "autosave-8.hashes",
+                "autosave-8.state",
+            ]
+        );
+        let hashes = |frame: u64| {
+            fs::read_to_string(dir.path().join(format!("saves/autosave-{}.hashes", frame))).unwrap()
+        };
+        assert_eq!(hashes(4), "3 00000003\n4 00000004\n");
+        assert_eq!(hashes(8), "7 00000007\n8 00000008\n");
+    }
+}
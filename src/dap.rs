@@ -0,0 +1,269 @@
+//! Debug Adapter Protocol server, to debug from VS Code and the other
+//! editors that speak it. The editor connects to the `--dap` address, e.g.
+//! with `"debugServer": 4711` in the launch configuration, and the runner
+//! maps its requests onto breakpoints, stepping, registers and memory.
+//!
+//! Messages are JSON with a `Content-Length` header:
+//!
+//! ```text
+//! Content-Length: 85\r\n
+//! \r\n
+//! {"seq": 1, "type": "request", "command": "initialize", "arguments": {"adapterID": "z80"}}
+//! ```
+
+use std::{
+    collections::HashMap,
+    io::{ErrorKind, Read, Write},
+    net::{TcpListener, TcpStream},
+};
+
+use anyhow::{anyhow, bail};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::info;
+
+/// The only thread there is, the Z80.
+pub const THREAD_ID: u64 = 1;
+
+// references of the variable scopes
+pub const REGISTERS: u64 = 1;
+pub const FLAGS: u64 = 2;
+pub const WATCHES: u64 = 3;
+
+/// What the runner answers to `initialize`.
+pub fn capabilities() -> Value {
+    json!({
+        "supportsConfigurationDoneRequest": true,
+        "supportsFunctionBreakpoints": true,
+        "supportsInstructionBreakpoints": true,
+        "supportsConditionalBreakpoints": true,
+        "supportsHitConditionalBreakpoints": true,
+        "supportsEvaluateForHovers": true,
+        "supportsReadMemoryRequest": true,
+        "supportsDisassembleRequest": true,
+        "supportsTerminateRequest": true,
+        "supportsSteppingGranularity": true,
+    })
+}
+
+/// An address as a memory or instruction reference.
+pub fn reference(address: u16) -> String {
+    format!("0x{:04X}", address)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Request {
+    pub seq: u64,
+    pub command: String,
+    #[serde(default)]
+    pub arguments: Value,
+}
+
+impl Request {
+    /// An argument, or its default when missing or of another type.
+    pub fn argument<T: for<'de> Deserialize<'de> + Default>(&self, name: &str) -> T {
+        self.arguments
+            .get(name)
+            .and_then(|value| T::deserialize(value).ok())
+            .unwrap_or_default()
+    }
+}
+
+struct Client {
+    stream: TcpStream,
+    // bytes read past the last complete message
+    buffer: Vec<u8>,
+}
+
+impl Client {
+    /// The next message, or `None` when there is none yet. Errors when the
+    /// client went away.
+    fn read(&mut self, block: bool) -> anyhow::Result<Option<Vec<u8>>> {
+        loop {
+            if let Some(message) = self.take_message()? {
+                return Ok(Some(message));
+            }
+
+            self.stream.set_nonblocking(!block)?;
+            let mut chunk = [0; 4096];
+            match self.stream.read(&mut chunk) {
+                Ok(0) => bail!("Connection closed"),
+                Ok(n) => self.buffer.extend_from_slice(&chunk[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(None),
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    fn take_message(&mut self) -> anyhow::Result<Option<Vec<u8>>> {
+        let Some(header_end) = self.buffer.windows(4).position(|w| w == b"\r\n\r\n") else {
+            return Ok(None);
+        };
+
+        let header = String::from_utf8_lossy(&self.buffer[..header_end]);
+        let length: usize = header
+            .lines()
+            .find_map(|line| {
+                let (name, value) = line.split_once(':')?;
+                name.trim()
+                    .eq_ignore_ascii_case("Content-Length")
+                    .then(|| value.trim().parse().ok())?
+            })
+            .ok_or_else(|| anyhow!("Missing Content-Length in {:?}", header))?;
+
+        let start = header_end + 4;
+        if self.buffer.len() < start + length {
+            return Ok(None);
+        }
+        let message = self.buffer[start..start + length].to_vec();
+        self.buffer.drain(..start + length);
+        Ok(Some(message))
+    }
+
+    fn write(&mut self, message: &Value) -> anyhow::Result<()> {
+        let body = serde_json::to_string(message)?;
+        self.stream.set_nonblocking(false)?;
+        write!(
+            self.stream,
+            "Content-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        )?;
+        Ok(())
+    }
+}
+
+/// Serves one debugger at a time on the `--dap` address.
+pub struct DapServer {
+    listener: TcpListener,
+    client: Option<Client>,
+    seq: u64,
+    /// ids of the breakpoints set by `setFunctionBreakpoints`, replaced as a
+    /// whole by each request
+    pub function_breakpoints: Vec<usize>,
+    /// same for `setInstructionBreakpoints`
+    pub instruction_breakpoints: Vec<usize>,
+    /// same for `setBreakpoints`, by source path
+    pub source_breakpoints: HashMap<String, Vec<usize>>,
+    /// whether the debugger launched the emulator rather than attached to
+    /// it, so disconnecting quits
+    pub launched: bool,
+    pub stop_on_entry: bool,
+}
+
+impl DapServer {
+    pub fn bind(addr: &str) -> anyhow::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        println!("Waiting for a debugger (DAP) on {}", addr);
+        Ok(Self {
+            listener,
+            client: None,
+            seq: 0,
+            function_breakpoints: Vec::new(),
+            instruction_breakpoints: Vec::new(),
+            source_breakpoints: HashMap::new(),
+            launched: false,
+            stop_on_entry: false,
+        })
+    }
+
+    fn accept(&mut self, block: bool) -> anyhow::Result<()> {
+        if self.client.is_some() {
+            return Ok(());
+        }
+
+        self.listener.set_nonblocking(!block)?;
+        match self.listener.accept() {
+            Ok((stream, peer)) => {
+                info!("[DAP] Connection from {}", peer);
+                stream.set_nodelay(true)?;
+                self.client = Some(Client {
+                    stream,
+                    buffer: Vec::new(),
+                });
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+            Err(e) => return Err(e.into()),
+        }
+        Ok(())
+    }
+
+    /// The next request of the debugger. Blocking waits for one, accepting
+    /// a new debugger if there's none.
+    pub fn next_request(&mut self, block: bool) -> anyhow::Result<Option<Request>> {
+        loop {
+            self.accept(block)?;
+            let Some(client) = &mut self.client else {
+                return Ok(None);
+            };
+
+            match client.read(block) {
+                Ok(Some(message)) => match serde_json::from_slice(&message) {
+                    Ok(request) => return Ok(Some(request)),
+                    // without a seq there's nothing to answer to
+                    Err(e) => info!("[DAP] Invalid request: {}", e),
+                },
+                Ok(None) => return Ok(None),
+                Err(e) => {
+                    info!("[DAP] Debugger disconnected: {}", e);
+                    self.client = None;
+                    if !block {
+                        return Ok(None);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Answers a request with its body, or with the error as the message.
+    pub fn respond(&mut self, request: &Request, body: &anyhow::Result<Value>) {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request.seq,
+            "command": request.command,
+            "success": body.is_ok(),
+        });
+        match body {
+            Ok(body) => response["body"] = body.clone(),
+            Err(e) => response["message"] = json!(e.to_string()),
+        }
+        self.write(response);
+    }
+
+    /// Sends an event, e.g. `stopped` with `{"reason": "breakpoint"}`.
+    pub fn event(&mut self, event: &str, body: Value) {
+        self.write(json!({
+            "type": "event",
+            "event": event,
+            "body": body,
+        }));
+    }
+
+    /// Sends the `stopped` event of the Z80 thread.
+    pub fn stopped(&mut self, reason: &str) {
+        self.event(
+            "stopped",
+            json!({
+                "reason": reason,
+                "threadId": THREAD_ID,
+                "allThreadsStopped": true,
+            }),
+        );
+    }
+
+    /// Drops the debugger, to wait for the next one.
+    pub fn close(&mut self) {
+        self.client = None;
+    }
+
+    fn write(&mut self, mut message: Value) {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        if let Some(client) = &mut self.client {
+            if let Err(e) = client.write(&message) {
+                info!("[DAP] Debugger disconnected: {}", e);
+                self.client = None;
+            }
+        }
+    }
+}
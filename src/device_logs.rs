@@ -0,0 +1,202 @@
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use msx::{
+    io_log::{IoDirection, IoEvent},
+    Msx, ProgramEntry,
+};
+use serde::Serialize;
+
+// an instruction run, in cpu.jsonl
+#[derive(Serialize)]
+struct CpuEntry<'a> {
+    cycle: u64,
+    clock: u64,
+    pc: u16,
+    instruction: &'a str,
+    af: u16,
+    bc: u16,
+    de: u16,
+    hl: u16,
+    sp: u16,
+    ix: u16,
+    iy: u16,
+}
+
+// a port access, in the file of the device at the port; the frames of the
+// I/O log count steps, so they are left out
+#[derive(Serialize)]
+struct IoEntry {
+    cycle: u64,
+    clock: u64,
+    pc: u16,
+    port: u8,
+    direction: IoDirection,
+    value: u8,
+}
+
+impl IoEntry {
+    fn new(cycle: u64, clock: u64, event: &IoEvent) -> Self {
+        Self {
+            cycle,
+            clock,
+            pc: event.pc,
+            port: event.port,
+            direction: event.direction,
+            value: event.value,
+        }
+    }
+}
+
+/// The logs of `--log-dir`, a JSON object per line in a file per device:
+/// the instructions in cpu.jsonl, the port accesses of the VDP, PSG and PPI
+/// in vdp.jsonl, psg.jsonl and ppi.jsonl, and the other ports in io.jsonl.
+///
+/// Every line has the count of the instruction it belongs to, `cycle`, and
+/// the T-state the instruction started at, `clock`, for tools to line up
+/// what the CPU ran with what the devices were sent.
+pub struct DeviceLogs {
+    cpu: BufWriter<File>,
+    vdp: BufWriter<File>,
+    psg: BufWriter<File>,
+    ppi: BufWriter<File>,
+    io: BufWriter<File>,
+    // instruction being executed, with the T-state it started at
+    pending: (u64, u64),
+}
+
+impl DeviceLogs {
+    /// Creates the files in a directory, which is created if needed.
+    pub fn create(dir: &Path) -> anyhow::Result<Self> {
+        fs::create_dir_all(dir)?;
+        let file = |name: &str| -> anyhow::Result<BufWriter<File>> {
+            Ok(BufWriter::new(File::create(dir.join(name))?))
+        };
+        Ok(Self {
+            cpu: file("cpu.jsonl")?,
+            vdp: file("vdp.jsonl")?,
+            psg: file("psg.jsonl")?,
+            ppi: file("ppi.jsonl")?,
+            io: file("io.jsonl")?,
+            pending: (0, 0),
+        })
+    }
+
+    /// Logs the instruction about to be executed.
+    pub fn before_step(&mut self, msx: &Msx, instruction: &ProgramEntry) -> anyhow::Result<()> {
+        let cpu = &msx.cpu;
+        self.pending = (cpu.cycles, msx.clock());
+        let entry = CpuEntry {
+            cycle: self.pending.0,
+            clock: self.pending.1,
+            pc: instruction.address,
+            instruction: &instruction.instruction,
+            af: cpu.get_af(),
+            bc: cpu.get_bc(),
+            de: cpu.get_de(),
+            hl: cpu.get_hl(),
+            sp: cpu.sp,
+            ix: cpu.ix,
+            iy: cpu.iy,
+        };
+        serde_json::to_writer(&mut self.cpu, &entry)?;
+        writeln!(self.cpu)?;
+        Ok(())
+    }
+
+    /// Logs the port accesses of the instruction just executed, taking them
+    /// from the I/O log of the machine.
+    pub fn after_step(&mut self, msx: &mut Msx) -> anyhow::Result<()> {
+        let (cycle, clock) = self.pending;
+        for event in msx.take_io_events() {
+            let output = match event.port {
+                0x98..=0x9B => &mut self.vdp,
+                0xA0..=0xA2 => &mut self.psg,
+                0xA8..=0xAB => &mut self.ppi,
+                _ => &mut self.io,
+            };
+            serde_json::to_writer(&mut *output, &IoEntry::new(cycle, clock, &event))?;
+            writeln!(output)?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> anyhow::Result<()> {
+        for output in [
+            &mut self.cpu,
+            &mut self.vdp,
+            &mut self.psg,
+            &mut self.ppi,
+            &mut self.io,
+        ] {
+            output.flush()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use msx::slot::{RamSlot, RomSlot, SlotType};
+
+    use super::*;
+
+    #[test]
+    fn test_device_logs() {
+        let program = [
+            0x3E, 0xC0, // LD A,0xC0
+            0xD3, 0xA8, // OUT (0xA8),A
+            0x3E, 0x12, // LD A,0x12
+            0xD3, 0x99, // OUT (0x99),A
+            0xD3, 0x7C, // OUT (0x7C),A
+            0x76, // HALT
+        ];
+        let mut msx = Msx::new(&[
+            SlotType::Rom(RomSlot::new(&program, 0x0000, 0x8000)),
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+        ]);
+        msx.enable_io_log();
+        let dir = tempfile::tempdir().unwrap();
+        let mut logs = DeviceLogs::create(dir.path()).unwrap();
+        while !msx.halted() {
+            let instruction = msx.instruction();
+            logs.before_step(&msx, &instruction).unwrap();
+            msx.step();
+            logs.after_step(&mut msx).unwrap();
+        }
+        logs.flush().unwrap();
+
+        let lines = |name: &str| -> Vec<String> {
+            fs::read_to_string(dir.path().join(name))
+                .unwrap()
+                .lines()
+                .map(String::from)
+                .collect()
+        };
+        let cpu = lines("cpu.jsonl");
+        assert_eq!(cpu.len(), 6);
+        assert_eq!(
+            cpu[3],
+            r#"{"cycle":3,"clock":28,"pc":6,"instruction":"OUT #99, A","af":4863,"bc":65535,"de":65535,"hl":65535,"sp":65535,"ix":0,"iy":0}"#
+        );
+        // each access under the instruction that made it
+        assert_eq!(
+            lines("ppi.jsonl"),
+            [r#"{"cycle":1,"clock":8,"pc":2,"port":168,"direction":"write","value":192}"#]
+        );
+        assert_eq!(
+            lines("vdp.jsonl"),
+            [r#"{"cycle":3,"clock":28,"pc":6,"port":153,"direction":"write","value":18}"#]
+        );
+        assert_eq!(
+            lines("io.jsonl"),
+            [r#"{"cycle":4,"clock":40,"pc":8,"port":124,"direction":"write","value":18}"#]
+        );
+        assert!(lines("psg.jsonl").is_empty());
+    }
+}
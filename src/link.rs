@@ -0,0 +1,69 @@
+use std::{
+    io::{ErrorKind, Read, Write},
+    net::{TcpListener, TcpStream},
+};
+
+use anyhow::bail;
+use msx::Msx;
+use tracing::info;
+
+/// Which side of the link cable this instance is.
+#[derive(Debug, Clone)]
+pub enum LinkMode {
+    Listen(String),
+    Connect(String),
+}
+
+/// Virtual link cable: carries the bytes written to the emulated serial port
+/// to another rustmsx process over TCP, and feeds the bytes it receives back.
+pub struct Link {
+    stream: TcpStream,
+}
+
+impl Link {
+    pub fn open(mode: &LinkMode) -> anyhow::Result<Self> {
+        let stream = match mode {
+            LinkMode::Listen(addr) => {
+                let listener = TcpListener::bind(addr)?;
+                println!("Waiting for link connection on {}...", addr);
+                let (stream, peer) = listener.accept()?;
+                info!("[LINK] Connection from {}", peer);
+                stream
+            }
+            LinkMode::Connect(addr) => {
+                let stream = TcpStream::connect(addr)?;
+                info!("[LINK] Connected to {}", addr);
+                stream
+            }
+        };
+
+        stream.set_nodelay(true)?;
+        stream.set_nonblocking(true)?;
+
+        Ok(Self { stream })
+    }
+
+    /// Exchanges pending bytes between the machine's serial port and the
+    /// socket, without blocking.
+    pub fn pump(&mut self, msx: &mut Msx) -> anyhow::Result<()> {
+        let outgoing = msx.link_transmit();
+        if !outgoing.is_empty() {
+            self.stream.set_nonblocking(false)?;
+            let res = self.stream.write_all(&outgoing);
+            self.stream.set_nonblocking(true)?;
+            res?;
+        }
+
+        let mut buf = [0u8; 256];
+        loop {
+            match self.stream.read(&mut buf) {
+                Ok(0) => bail!("Link connection closed"),
+                Ok(n) => msx.link_receive(&buf[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,140 @@
+use std::collections::BTreeMap;
+
+use anyhow::anyhow;
+use tracing_subscriber::{reload, EnvFilter, Registry};
+
+/// The modules of the emulator logging each device, as named by the `log`
+/// command.
+const DEVICE_TARGETS: &[(&str, &[&str])] = &[
+    ("cpu", &["msx::cpu", "msx::instruction"]),
+    ("vdp", &["msx::vdp", "msx::renderer"]),
+    ("ppi", &["msx::ppi"]),
+    ("psg", &["msx::sound"]),
+    ("bus", &["msx::bus"]),
+    ("slot", &["msx::slot"]),
+    ("memory", &["msx::memory"]),
+    ("serial", &["msx::serial"]),
+    ("netplay", &["msx::netplay"]),
+];
+
+/// The tracing filter of the CLI, whose levels per device the `log`
+/// command changes while the machine runs, on top of those given at
+/// startup by the flags or `RUST_LOG`.
+#[derive(Clone)]
+pub struct LogFilter {
+    handle: reload::Handle<EnvFilter, Registry>,
+    // directives given at startup
+    base: String,
+    // levels set at runtime, by device
+    levels: BTreeMap<String, String>,
+}
+
+impl LogFilter {
+    /// Makes the filter of the subscriber from directives, returning the
+    /// layer to install along with it.
+    pub fn new(directives: &str) -> anyhow::Result<(reload::Layer<EnvFilter, Registry>, Self)> {
+        let (layer, handle) = reload::Layer::new(EnvFilter::try_new(directives)?);
+        let filter = Self {
+            handle,
+            base: directives.to_string(),
+            levels: BTreeMap::new(),
+        };
+        Ok((layer, filter))
+    }
+
+    /// The level a device logs at, as set at runtime or by the directives
+    /// given at startup for its first module.
+    pub fn level(&self, device: &str) -> anyhow::Result<String> {
+        let targets = targets(device).ok_or_else(|| anyhow!("Unknown device {}", device))?;
+        if let Some(level) = self.levels.get(device) {
+            return Ok(level.clone());
+        }
+        let level = self
+            .base
+            .split(',')
+            .filter_map(|directive| directive.split_once('='))
+            .find(|(module, _)| Some(module) == targets.first())
+            .map_or("the default level", |(_, level)| level);
+        Ok(level.to_string())
+    }
+
+    /// Sets the level of a device, replacing its directives at startup.
+    pub fn set_level(&mut self, device: &str, level: &str) -> anyhow::Result<()> {
+        targets(device).ok_or_else(|| anyhow!("Unknown device {}", device))?;
+        let previous = self.levels.insert(device.to_string(), level.to_string());
+        let reloaded = EnvFilter::try_new(self.directives())
+            .map_err(|e| anyhow!("Invalid level {}: {}", level, e))
+            .and_then(|filter| {
+                self.handle
+                    .reload(filter)
+                    .map_err(|e| anyhow!("Couldn't change the logging: {}", e))
+            });
+        // an invalid level leaves the device as it was
+        if reloaded.is_err() {
+            match previous {
+                Some(previous) => self.levels.insert(device.to_string(), previous),
+                None => self.levels.remove(device),
+            };
+        }
+        reloaded
+    }
+
+    // the directives at startup, but those of the devices set at runtime
+    fn directives(&self) -> String {
+        let overridden: Vec<&str> = self
+            .levels
+            .keys()
+            .filter_map(|device| targets(device))
+            .flatten()
+            .copied()
+            .collect();
+        let base = self.base.split(',').filter(|directive| {
+            let module = directive.split('=').next().unwrap_or_default();
+            !overridden.contains(&module)
+        });
+        let levels = self.levels.iter().flat_map(|(device, level)| {
+            targets(device)
+                .unwrap_or_default()
+                .iter()
+                .map(move |target| format!("{}={}", target, level))
+        });
+        base.map(str::to_string)
+            .chain(levels)
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+fn targets(device: &str) -> Option<&'static [&'static str]> {
+    DEVICE_TARGETS
+        .iter()
+        .find(|(name, _)| *name == device)
+        .map(|(_, targets)| *targets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_levels() {
+        let (_layer, mut filter) = LogFilter::new("warn,msx::vdp=debug,msx::cpu=trace").unwrap();
+        assert_eq!(filter.level("vdp").unwrap(), "debug");
+        assert_eq!(filter.level("psg").unwrap(), "the default level");
+        assert_eq!(
+            filter.level("fdc").unwrap_err().to_string(),
+            "Unknown device fdc"
+        );
+
+        filter.set_level("cpu", "info").unwrap();
+        assert_eq!(filter.level("cpu").unwrap(), "info");
+        assert_eq!(
+            filter.directives(),
+            "warn,msx::vdp=debug,msx::cpu=info,msx::instruction=info"
+        );
+
+        assert!(filter.set_level("cpu", "loud").is_err());
+        assert_eq!(filter.level("cpu").unwrap(), "info");
+        assert!(filter.set_level("fdc", "info").is_err());
+    }
+}
@@ -1,17 +1,42 @@
+mod autosave;
+mod dap;
+mod device_logs;
+mod link;
+mod log_filter;
 mod mru;
 mod open_msx;
+mod remote;
+mod repl;
+mod report;
 mod runner;
+mod style;
+mod tui;
+mod verify;
 
 use std::path::PathBuf;
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use link::LinkMode;
+use log_filter::LogFilter;
 use runner::RunnerBuilder;
-use tracing_subscriber::{EnvFilter, FmtSubscriber};
+use tracing_subscriber::{fmt, prelude::*, EnvFilter};
 
 #[derive(Parser, Debug)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 pub struct Cli {
-    /// Path to the complete ROM file
-    rom_path: PathBuf,
+    #[clap(subcommand)]
+    command: Option<Command>,
+
+    /// Path to the complete ROM file, or to the cartridge with --cbios
+    #[clap(
+        required_unless_present_any = ["ab_compare", "cbios"],
+        conflicts_with = "ab_compare"
+    )]
+    rom_path: Option<PathBuf>,
+
+    /// Runs two ROMs side by side in lockstep and breaks when their state diverges
+    #[clap(long, num_args = 2, value_names = ["ROM_A", "ROM_B"])]
+    ab_compare: Vec<PathBuf>,
 
     /// Maximum number of cycles to run before breaking
     #[clap(short = 'c', long)]
@@ -29,6 +54,11 @@ pub struct Cli {
     #[clap(short, long)]
     open_msx: bool,
 
+    /// Steps openMSX until it reaches the emulator's PC, when one of them runs an
+    /// instruction in more steps than the other
+    #[clap(long, requires = "open_msx")]
+    resync: bool,
+
     /// Break on CPU registers and flags mismatch between openMSX and emulator
     #[clap(short = 'm', long)]
     break_on_mismatch: bool,
@@ -49,10 +79,238 @@ pub struct Cli {
     #[clap(short, long)]
     report_every: Option<u64>,
 
+    /// Print status, dump, memdump and the --report-every log as JSON
+    #[clap(long)]
+    json: bool,
+
+    /// Takes the prompt commands from remote clients on the given address,
+    /// as JSON over WebSocket or lines of JSON over TCP
+    #[clap(long, value_name = "ADDR", conflicts_with = "tui")]
+    listen: Option<String>,
+
+    /// Serves the Debug Adapter Protocol on the given address, for VS Code and
+    /// other editors, waiting for the debugger before running
+    #[clap(long, value_name = "ADDR", conflicts_with_all = ["tui", "listen"])]
+    dap: Option<String>,
+
+    /// Symbol file of the assembler (sjasmplus --sym and alike), for labels in
+    /// the debugger
+    #[clap(long, value_name = "FILE")]
+    symbols: Option<PathBuf>,
+
+    /// Listing of the assembler (sjasmplus --lst, z88dk -l) or sjasmplus --sld
+    /// file, for source lines in list and the debugger (can be repeated)
+    #[clap(long, value_name = "FILE")]
+    listing: Vec<PathBuf>,
+
+    /// Named memory regions, one "<start> <end> <name>" per line, for the hexdumps,
+    /// the watch list and status
+    #[clap(long, value_name = "FILE")]
+    regions: Option<PathBuf>,
+
+    /// Debug in a full screen terminal UI instead of the line prompt
+    #[clap(long, conflicts_with = "json")]
+    tui: bool,
+
+    /// Print without colors, also the case with NO_COLOR set
+    #[clap(long)]
+    no_color: bool,
+
+    /// Break when a return address on the stack is overwritten before its RET
+    #[clap(long)]
+    stack_guard: bool,
+
+    /// Break when the interrupt vector at 0x0038, H.KEYI or H.TIMI is written, or the interrupts
+    /// enabled, leaving them malformed while the interrupts are enabled
+    #[clap(long)]
+    guard_hooks: bool,
+
+    /// Report calls to the BIOS hooks (H.TIMI, H.KEYI...) and where they were patched to
+    #[clap(long)]
+    trace_hooks: bool,
+
+    /// Print the text output through the BIOS CHPUT routine, e.g. by PRINT or a test ROM
+    #[clap(long)]
+    console: bool,
+
+    /// Break when the text is printed through the BIOS CHPUT routine, e.g. "Ok" once BASIC is
+    /// up; can be repeated
+    #[clap(long, value_name = "TEXT")]
+    break_on_text: Vec<String>,
+
+    /// Print the lines of the text screen (SCREEN 0 and 1) as they change, to follow text mode
+    /// software without looking at the screen
+    #[clap(long)]
+    follow_screen: bool,
+
+    /// Report VRAM accesses closer than the 29 T-states the TMS9918 needs while drawing the screen
+    #[clap(long)]
+    vdp_timing: bool,
+
+    /// Report the reads of programs from pages whose selected slot is empty, e.g. after a wrong
+    /// ENASLT or CALSLT
+    #[clap(long)]
+    slot_check: bool,
+
+    /// Print every write to the primary slot register with the slots of the pages before and
+    /// after it
+    #[clap(long)]
+    trace_slots: bool,
+
+    /// Trace the slot register and break when a write switches the page the program runs from
+    #[clap(long)]
+    break_on_slot_switch: bool,
+
+    /// Write structured logs to the directory, a JSON object per line with the instruction count
+    /// and T-state: cpu.jsonl for the instructions, vdp.jsonl, psg.jsonl, ppi.jsonl and io.jsonl
+    /// for the port accesses
+    #[clap(long, value_name = "DIR")]
+    log_dir: Option<PathBuf>,
+
+    /// Print what programs write to the debug ports 0x2E/0x2F, like the openMSX debugdevice, and
+    /// let them read T-state and frame counters from them
+    #[clap(long)]
+    debug_device: bool,
+
     /// Break on PPI write operations
     #[clap(short = 'p', long)]
     break_on_ppi_write: bool,
 
+    /// IPS or BPS patch applied to the ROM when it is loaded (can be repeated)
+    #[clap(long, value_name = "FILE")]
+    patch: Vec<PathBuf>,
+
+    /// BLOAD (.BIN) file copied to memory before running
+    #[clap(long, value_name = "FILE")]
+    bin: Option<PathBuf>,
+
+    /// Start executing the --bin file at its execution address
+    #[clap(long, requires = "bin")]
+    bin_run: bool,
+
+    /// Number of cycles to run before loading the --bin file, e.g. to let the BIOS boot
+    #[clap(long, value_name = "CYCLES", default_value_t = 0, requires = "bin")]
+    bin_at: u64,
+
+    /// BASIC program (tokenized or ASCII listing) loaded and run once BASIC is up
+    #[clap(long, value_name = "FILE")]
+    bas: Option<PathBuf>,
+
+    /// Number of cycles to run before loading the --bas file, to let BASIC boot
+    #[clap(
+        long,
+        value_name = "CYCLES",
+        default_value_t = 2_000_000,
+        requires = "bas"
+    )]
+    bas_at: u64,
+
+    /// Types the text on the keyboard, with \n for ENTER, {WAIT n} to wait n frames and
+    /// special keys like {ESC}, {F1} or {CTRL+STOP}
+    #[clap(long, value_name = "TEXT")]
+    autotype: Option<String>,
+
+    /// Command typed at the MSX-DOS prompt once DOS boots, e.g. "BASIC MYPROG.BAS"; what it
+    /// prints is written to stdout and the emulator exits when the prompt is back
+    #[clap(long, value_name = "COMMAND")]
+    dos_command: Option<String>,
+
+    /// Writes the CRC32 of each rendered frame to the file, a "<frame> <CRC32>" line per frame
+    #[clap(long, value_name = "FILE")]
+    frame_hashes: Option<PathBuf>,
+
+    /// Compares the CRC32 of each frame with a file written by --frame-hashes, breaking at the
+    /// first frame that differs and failing the run if any did
+    #[clap(long, value_name = "FILE")]
+    frame_hashes_reference: Option<PathBuf>,
+
+    /// Every N minutes, writes a save state and the hashes of the frames run since the previous
+    /// one to --autosave-dir, keeping the last three, for long unattended runs to have a state
+    /// from shortly before anything went wrong
+    #[clap(long, value_name = "MINUTES")]
+    autosave_every: Option<f64>,
+
+    /// Directory of the --autosave-every states, autosave by default
+    #[clap(long, value_name = "DIR", requires = "autosave_every")]
+    autosave_dir: Option<PathBuf>,
+
+    /// Save state to start from, saved by the CLI or the web version
+    #[clap(long, value_name = "FILE")]
+    state: Option<PathBuf>,
+
+    /// Screen colors: classic, bright, grayscale, green, amber or a file with 16 #RRGGBB lines
+    #[clap(long, value_name = "PALETTE")]
+    palette: Option<String>,
+
+    /// Machine model: msx1-jp, msx1-int (50 Hz) or msx1-16k, setting the
+    /// region, the VDP frequency and the RAM
+    #[clap(long, value_name = "PRESET")]
+    preset: Option<String>,
+
+    /// Boots the bundled C-BIOS, a free BIOS without BASIC that only starts cartridges, with the
+    /// ROM as the cartridge in slot 1
+    #[clap(long, conflicts_with_all = ["ab_compare", "cart1"])]
+    cbios: bool,
+
+    /// RAM cartridge in slot 1: ram[:KB], 16 to 64K, megaram[:KB], 64 to 2048K, or a memory
+    /// mapper, mapper[:KB], 64 to 4096K
+    #[clap(long, value_name = "CARTRIDGE")]
+    cart1: Option<String>,
+
+    /// RAM cartridge in slot 2, like --cart1
+    #[clap(long, value_name = "CARTRIDGE")]
+    cart2: Option<String>,
+
+    /// Sunrise IDE interface in slot 1 with this ROM in its flash, usually Nextor, which needs a
+    /// memory mapper, e.g. --cart2 mapper
+    #[clap(long, value_name = "ROM", conflicts_with_all = ["cbios", "cart1"])]
+    ide: Option<PathBuf>,
+
+    /// Hard disk image on the --ide interface, whose changes are lost when the run ends
+    #[clap(long, value_name = "IMAGE", requires = "ide")]
+    disk: Option<PathBuf>,
+
+    /// Cassette recording (.wav) or CAS image in the tape recorder, read through the cassette
+    /// input
+    #[clap(long, value_name = "FILE")]
+    tape: Option<PathBuf>,
+
+    /// Baud rate the tape is played at, detected from a recording or 1200 for a CAS image by
+    /// default
+    #[clap(long, value_name = "BAUD", requires = "tape")]
+    tape_baud: Option<u32>,
+
+    /// Read the tape at once when the BIOS tape routines are called, instead of at its baud
+    /// rate
+    #[clap(long)]
+    turbo_io: bool,
+
+    /// Record what is saved to tape (CSAVE, BSAVE "CAS:") into a CAS image, written when the run
+    /// ends
+    #[clap(long, value_name = "FILE")]
+    record_tape: Option<PathBuf>,
+
+    /// Count T-states as a stock Z80, without the wait state MSX machines add
+    /// to each opcode fetch
+    #[clap(long)]
+    stock_timing: bool,
+
+    /// Number of executed instructions kept for the history command
+    #[clap(long, value_name = "N", default_value_t = msx::history::DEFAULT_HISTORY_SIZE)]
+    history_size: usize,
+
+    /// Additional ROM database (JSON) used to identify the loaded ROMs
+    #[clap(long, value_name = "PATH")]
+    romdb: Option<PathBuf>,
+
+    /// Waits for another instance to connect the link cable (serial port) on the given address
+    #[clap(long, value_name = "ADDR", conflicts_with = "link_connect")]
+    link_listen: Option<String>,
+
+    /// Connects the link cable (serial port) to another instance listening on the given address
+    #[clap(long, value_name = "ADDR")]
+    link_connect: Option<String>,
+
     /// Enable debug logging
     #[clap(short, long)]
     debug: bool,
@@ -66,6 +324,13 @@ pub struct Cli {
     debug_ppi: bool,
 }
 
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Runs the accuracy suite, ZEXDOC, the JSON single-step tests, the ROM pack and a BIOS
+    /// boot, and prints a scorecard; run it before submitting CPU and VDP changes
+    Verify(verify::VerifyArgs),
+}
+
 pub fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
 
@@ -75,20 +340,81 @@ pub fn main() -> anyhow::Result<()> {
         if cli.debug_vdp { "trace" } else { "error" },
         if cli.debug_ppi { "trace" } else { "error" },
     );
-    let subscriber = FmtSubscriber::builder()
-        .with_env_filter(
-            EnvFilter::try_from_default_env().or_else(|_| EnvFilter::try_new(log_level))?,
-        )
-        .finish();
-    tracing::subscriber::set_global_default(subscriber).expect("setting default subscriber failed");
+    // RUST_LOG wins over the flags, if valid
+    let directives = std::env::var(EnvFilter::DEFAULT_ENV)
+        .ok()
+        .filter(|directives| EnvFilter::try_new(directives).is_ok())
+        .unwrap_or(log_level);
+    // the log command changes the filter while running
+    let (filter_layer, log_filter) = LogFilter::new(&directives)?;
+    tracing_subscriber::registry()
+        .with(filter_layer)
+        .with(fmt::layer())
+        .try_init()
+        .expect("setting default subscriber failed");
+
+    if let Some(Command::Verify(args)) = &cli.command {
+        return verify::run(args);
+    }
+
+    let (rom_path, compare_rom_path) = match cli.rom_path {
+        Some(rom_path) => (Some(rom_path), None),
+        None if cli.cbios => (None, None),
+        None => (
+            Some(cli.ab_compare[0].clone()),
+            Some(cli.ab_compare[1].clone()),
+        ),
+    };
+
+    let link_mode = match (cli.link_listen, cli.link_connect) {
+        (Some(addr), _) => Some(LinkMode::Listen(addr)),
+        (_, Some(addr)) => Some(LinkMode::Connect(addr)),
+        _ => None,
+    };
+
+    let mut builder = RunnerBuilder::new();
+    builder
+        .log_filter(log_filter)
+        .rom_database(cli.romdb)?
+        .autotype(cli.autotype)?
+        .dos_command(cli.dos_command)
+        .palette(cli.palette)?
+        .frame_hashes(cli.frame_hashes, cli.frame_hashes_reference)?
+        .autosave(cli.autosave_every, cli.autosave_dir)?
+        .preset(cli.preset)?
+        .tape(cli.tape, cli.tape_baud)?
+        .record_tape(cli.record_tape)
+        .turbo_io(cli.turbo_io)
+        .symbols(cli.symbols)?
+        .listings(&cli.listing)?
+        .regions(cli.regions)?;
+    if let Some(compare_rom_path) = compare_rom_path {
+        builder.compare_rom_from_file(compare_rom_path, 0x0000, 0x10000)?;
+    }
+
+    match (rom_path, cli.cbios) {
+        (Some(rom_path), true) => {
+            builder
+                .cbios_slot()
+                .cartridge_from_file(rom_path, &cli.patch)?;
+        }
+        (None, _) => {
+            builder.cbios_slot().empty_slot();
+        }
+        (Some(rom_path), false) => {
+            builder.rom_slot_from_file(rom_path, &cli.patch, 0x0000, 0x10000)?;
+            match cli.ide {
+                Some(ide) => builder.ide_slot(&ide, cli.disk.as_deref())?,
+                None => builder.cartridge_slot(cli.cart1)?,
+            };
+        }
+    }
 
-    let mut runner = RunnerBuilder::new()
-        .rom_slot_from_file(cli.rom_path, 0x0000, 0x10000)?
+    let mut runner = builder
         // .ram_slot(0x0000, 0xFFFF)
         // .ram_slot(0x0000, 0xFFFF)
-        .empty_slot()
-        .empty_slot()
-        .ram_slot(0x0000, 0x10000)
+        .cartridge_slot(cli.cart2)?
+        .main_ram_slot()
         .max_cycles(cli.max_cycles)
         .track_flags(cli.track_flags)
         .breakpoints(
@@ -98,13 +424,39 @@ pub fn main() -> anyhow::Result<()> {
                 .collect(),
         )
         .open_msx(cli.open_msx)
+        .resync(cli.resync)
+        .json(cli.json)
+        .color(!cli.no_color)
+        .tui(cli.tui)
+        .listen(cli.listen)
+        .dap(cli.dap)
         .break_on_mismatch(cli.break_on_mismatch)
         .log_on_mismatch(cli.log_on_mismatch)
         .break_on_mem_mismatch(cli.break_on_mem_mismatch)
         .break_on_ppi_write(cli.break_on_ppi_write)
         .break_on_halt(cli.break_on_halt)
+        .stack_guard(cli.stack_guard)
+        .hook_guard(cli.guard_hooks)
+        .trace_hooks(cli.trace_hooks)
+        .console(cli.console)
+        .break_on_text(&cli.break_on_text)
+        .follow_screen(cli.follow_screen)
+        .vdp_timing(cli.vdp_timing)
+        .slot_check(cli.slot_check)
+        .trace_slots(cli.trace_slots)
+        .break_on_slot_switch(cli.break_on_slot_switch)
+        .log_dir(cli.log_dir)
+        .stock_timing(cli.stock_timing)
+        .debug_device(cli.debug_device)
         .report_every(cli.report_every)
+        .history_size(cli.history_size)
+        .link(link_mode)
+        .bin_file(cli.bin, cli.bin_run, cli.bin_at)
+        .bas_file(cli.bas, cli.bas_at)
         .build();
+    if let Some(state) = cli.state {
+        runner.load_state(&state)?;
+    }
     runner.run()?;
 
     Ok(())
@@ -31,7 +31,7 @@ impl<T: PartialEq> MRUList<T> {
         &self.items
     }
 
-    pub fn iter(&self) -> Iter<T> {
+    pub fn iter(&self) -> Iter<'_, T> {
         Iter {
             inner: self.items.iter(),
         }
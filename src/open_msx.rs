@@ -24,6 +24,7 @@ pub enum Response {
 }
 
 pub struct Client {
+    #[allow(unused)]
     pub socket: UnixStream,
     pub reader: EventReader<UnixStream>,
     pub writer: BufWriter<UnixStream>,
@@ -143,6 +144,14 @@ impl Client {
                     "base": format!("0x{:04X}", slot.base),
                     "size": format!("0x{:05X}", slot.size),
                 })),
+                SlotType::MegaRam(_)
+                | SlotType::MemoryMapper(_)
+                | SlotType::Expanded(_)
+                | SlotType::SunriseIde(_)
+                | SlotType::Device(_) => {
+                    let name = slot.as_slot().map_or("", |slot| slot.name());
+                    anyhow::bail!("{} can't be emulated by openMSX", name)
+                }
             })
             .collect();
 
@@ -230,6 +239,22 @@ impl Client {
         Ok(())
     }
 
+    pub fn pc(&mut self) -> Result<u16> {
+        Ok(self.send("reg pc")?.parse()?)
+    }
+
+    /// Steps until openMSX reaches `pc`, for up to `max_steps` steps. Returns
+    /// whether it got there.
+    pub fn step_to(&mut self, pc: u16, max_steps: usize) -> Result<bool> {
+        for _ in 0..max_steps {
+            if self.pc()? == pc {
+                return Ok(true);
+            }
+            self.step()?;
+        }
+        Ok(self.pc()? == pc)
+    }
+
     pub fn send(&mut self, command: &str) -> anyhow::Result<String> {
         match self.request(command) {
             Ok(Response::Ok(data)) => Ok(data),
@@ -0,0 +1,251 @@
+//! Remote control of the runner, for editors and scripts. Clients send the
+//! prompt commands as JSON and get back their output, over WebSocket or as
+//! lines of JSON over plain TCP:
+//!
+//! ```text
+//! > {"id": 1, "command": "bp 4010"}
+//! < {"id": 1, "ok": true, "output": ""}
+//! > {"id": 2, "command": "cont"}
+//! < {"id": 2, "ok": true, "output": ""}
+//! < {"event": "running"}
+//! < {"event": "stopped", "pc": 16400, "cycles": 52081}
+//! ```
+//!
+//! The command `pause` stops a running emulator.
+
+use std::{
+    io::{BufRead, BufReader, ErrorKind, Write},
+    net::{TcpListener, TcpStream},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::info;
+use tungstenite::{Message, WebSocket};
+
+// how long a client has to start a WebSocket handshake
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Deserialize)]
+pub struct Request {
+    /// echoed back in the response, to match them up
+    #[serde(default)]
+    pub id: Value,
+    pub command: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Response {
+    pub id: Value,
+    pub ok: bool,
+    /// what the command printed
+    pub output: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Response {
+    /// Answers a request that printed nothing.
+    pub fn ok(id: Value) -> Self {
+        Self {
+            id,
+            ok: true,
+            output: String::new(),
+            error: None,
+        }
+    }
+}
+
+/// Sent when the state of the emulation changes, without a request.
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    Stopped { pc: u16, cycles: u64 },
+    Running,
+    Exited,
+}
+
+enum Connection {
+    WebSocket(WebSocket<TcpStream>),
+    Lines {
+        reader: BufReader<TcpStream>,
+        // a line read in part before the socket ran out of data
+        pending: Vec<u8>,
+    },
+}
+
+impl Connection {
+    fn open(stream: TcpStream) -> anyhow::Result<Self> {
+        stream.set_nonblocking(false)?;
+        stream.set_nodelay(true)?;
+
+        // WebSocket clients start with an HTTP upgrade request right away,
+        // line clients may wait to hear the state first
+        let mut start = [0; 4];
+        stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+        let websocket = matches!(stream.peek(&mut start), Ok(4)) && &start == b"GET ";
+        stream.set_read_timeout(None)?;
+        if websocket {
+            let socket = tungstenite::accept(stream).map_err(|e| anyhow::anyhow!("{}", e))?;
+            Ok(Connection::WebSocket(socket))
+        } else {
+            Ok(Connection::Lines {
+                reader: BufReader::new(stream),
+                pending: Vec::new(),
+            })
+        }
+    }
+
+    fn stream(&self) -> &TcpStream {
+        match self {
+            Connection::WebSocket(socket) => socket.get_ref(),
+            Connection::Lines { reader, .. } => reader.get_ref(),
+        }
+    }
+
+    /// The next message, or `None` when there is none yet. Errors when the
+    /// client went away.
+    fn read(&mut self, block: bool) -> anyhow::Result<Option<String>> {
+        self.stream().set_nonblocking(!block)?;
+
+        loop {
+            let text = match self {
+                Connection::WebSocket(socket) => match socket.read_message() {
+                    Ok(Message::Text(text)) => text,
+                    Ok(Message::Close(_)) => anyhow::bail!("Connection closed"),
+                    Ok(_) => continue,
+                    Err(tungstenite::Error::Io(e)) if e.kind() == ErrorKind::WouldBlock => {
+                        return Ok(None)
+                    }
+                    Err(e) => return Err(e.into()),
+                },
+                Connection::Lines { reader, pending } => match reader.read_until(b'\n', pending) {
+                    Ok(0) => anyhow::bail!("Connection closed"),
+                    Ok(_) if pending.ends_with(b"\n") => {
+                        String::from_utf8_lossy(&std::mem::take(pending)).to_string()
+                    }
+                    Ok(_) => continue,
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(None),
+                    Err(e) => return Err(e.into()),
+                },
+            };
+
+            if text.trim().is_empty() {
+                continue;
+            }
+            return Ok(Some(text));
+        }
+    }
+
+    fn write(&mut self, text: String) -> anyhow::Result<()> {
+        self.stream().set_nonblocking(false)?;
+        match self {
+            Connection::WebSocket(socket) => socket.write_message(Message::Text(text))?,
+            Connection::Lines { reader, .. } => {
+                let mut stream = reader.get_ref();
+                stream.write_all(text.as_bytes())?;
+                stream.write_all(b"\n")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Serves one client at a time on the `--listen` address.
+pub struct RemoteServer {
+    listener: TcpListener,
+    client: Option<Connection>,
+    // the last event, sent again to clients that connect later
+    state: Option<String>,
+}
+
+impl RemoteServer {
+    pub fn bind(addr: &str) -> anyhow::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        println!("Listening for debugger clients on {}", addr);
+        Ok(Self {
+            listener,
+            client: None,
+            state: None,
+        })
+    }
+
+    fn accept(&mut self, block: bool) -> anyhow::Result<()> {
+        if self.client.is_some() {
+            return Ok(());
+        }
+
+        self.listener.set_nonblocking(!block)?;
+        match self.listener.accept() {
+            Ok((stream, peer)) => match Connection::open(stream) {
+                Ok(mut client) => {
+                    info!("[REMOTE] Connection from {}", peer);
+                    if let Some(state) = &self.state {
+                        client.write(state.clone())?;
+                    }
+                    self.client = Some(client);
+                }
+                Err(e) => info!("[REMOTE] Rejected {}: {}", peer, e),
+            },
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+            Err(e) => return Err(e.into()),
+        }
+        Ok(())
+    }
+
+    /// The next request of the client. Blocking waits for one, accepting a
+    /// new client if there's none.
+    pub fn next_request(&mut self, block: bool) -> anyhow::Result<Option<Request>> {
+        loop {
+            self.accept(block)?;
+            let Some(client) = &mut self.client else {
+                return Ok(None);
+            };
+
+            match client.read(block) {
+                Ok(Some(text)) => match serde_json::from_str(&text) {
+                    Ok(request) => return Ok(Some(request)),
+                    Err(e) => self.send(&Response {
+                        id: Value::Null,
+                        ok: false,
+                        output: String::new(),
+                        error: Some(format!("Invalid request: {}", e)),
+                    })?,
+                },
+                Ok(None) => return Ok(None),
+                Err(e) => {
+                    info!("[REMOTE] Client disconnected: {}", e);
+                    self.client = None;
+                    if !block {
+                        return Ok(None);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Sends a response to the client, if there's one.
+    pub fn send(&mut self, response: &Response) -> anyhow::Result<()> {
+        self.write(serde_json::to_string(response)?);
+        Ok(())
+    }
+
+    /// Tells the client, and the ones connecting later, that the emulation
+    /// stopped, runs or ended.
+    pub fn event(&mut self, event: &Event) -> anyhow::Result<()> {
+        let text = serde_json::to_string(event)?;
+        self.state = Some(text.clone());
+        self.write(text);
+        Ok(())
+    }
+
+    fn write(&mut self, text: String) {
+        if let Some(client) = &mut self.client {
+            if let Err(e) = client.write(text) {
+                info!("[REMOTE] Client disconnected: {}", e);
+                self.client = None;
+            }
+        }
+    }
+}
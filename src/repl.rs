@@ -0,0 +1,230 @@
+//! Tab completion of the interactive prompt.
+
+use std::collections::BTreeSet;
+
+use msx::{machine::ResetKind, palette::Palette, sysvars::SYSTEM_VARIABLES, vdp::DisplayMode};
+use rustmsx_debugger::command::{find, COMMANDS};
+
+use rustyline::{
+    completion::{Completer, FilenameCompleter, Pair},
+    highlight::Highlighter,
+    hint::Hinter,
+    validate::Validator,
+    Context, Helper,
+};
+
+// registers `set` can change
+const SET_TARGETS: &[&str] = &["a", "b", "c", "hl", "(hl)"];
+// registers breakpoint conditions can test
+const REGISTERS: &[&str] = &[
+    "a", "f", "b", "c", "d", "e", "h", "l", "bc", "de", "hl", "sp", "ix", "iy", "(hl)",
+];
+const DUMP_TARGETS: &[&str] = &["msx", "openmsx", "b", "diff", "--json"];
+const BREAK_SUBCOMMANDS: &[&str] = &["list", "enable", "disable", "ignore", "cond", "delete"];
+
+/// Completes command names, their fixed arguments, register names, palette
+/// presets, file names and the addresses the machine is at.
+#[derive(Default)]
+pub struct ReplHelper {
+    files: FilenameCompleter,
+    /// addresses offered for address arguments, in hex, e.g. PC, SP and
+    /// the breakpoints
+    pub addresses: BTreeSet<u16>,
+}
+
+impl ReplHelper {
+    fn words(&self, command: &str, arg: usize, words: &[&str]) -> Option<Vec<String>> {
+        let address = || {
+            Some(
+                self.addresses
+                    .iter()
+                    .map(|a| format!("{:04X}", a))
+                    .collect(),
+            )
+        };
+        let fixed = |values: &[&str]| Some(values.iter().map(|v| v.to_string()).collect());
+
+        let command = find(command)?.name;
+        match (command, arg) {
+            ("help", 1) => Some(COMMANDS.iter().map(|c| c.name.to_string()).collect()),
+            ("set", 1) => fixed(SET_TARGETS),
+            ("reset", 1) => Some(
+                ResetKind::ALL
+                    .iter()
+                    .map(|kind| kind.name().to_string())
+                    .collect(),
+            ),
+            ("list", 1) => fixed(&["asm"]),
+            ("slow", 1) => fixed(&["off"]),
+            ("input", 1) => fixed(&["keyboard", "joystick"]),
+            ("screen", 1) => fixed(&["--follow"]),
+            ("screen", 2) => fixed(&["off"]),
+            ("vdp", 1) => fixed(&["capture-frame", "mode"]),
+            ("vdp", 2) if words.get(1) == Some(&"mode") => {
+                let mut modes: Vec<String> = DisplayMode::ALL
+                    .iter()
+                    .map(|mode| mode.name().to_string())
+                    .collect();
+                modes.push("auto".to_string());
+                Some(modes)
+            }
+            ("stackguard" | "hooks" | "console" | "vdptiming", 1) => fixed(&["on", "off"]),
+            ("dump" | "status", 1) => fixed(&["--json"]),
+            ("memdump" | "vramdump", 1 | 2) => fixed(DUMP_TARGETS),
+            ("disasm", 1) => {
+                let mut words = vec!["export".to_string()];
+                words.extend(address()?);
+                Some(words)
+            }
+            ("disasm", 2 | 3) if words.get(1) == Some(&"export") => address(),
+            // the count of instructions
+            ("disasm", 2) => Some(Vec::new()),
+            ("until" | "tbreak" | "removebreak" | "mem" | "watch" | "unwatch" | "view", 1) => {
+                address()
+            }
+            ("palette", 1) => Some(
+                Palette::PRESETS
+                    .iter()
+                    .map(|(name, _)| name.to_string())
+                    .collect(),
+            ),
+            ("loadbin", 2) => fixed(&["run"]),
+            ("sysvars", 1) => Some(
+                SYSTEM_VARIABLES
+                    .iter()
+                    .map(|var| var.name.to_string())
+                    .collect(),
+            ),
+            ("region", 1) => fixed(&["add", "remove", "load", "clear"]),
+            ("region", 2 | 3) if matches!(words.get(1), Some(&"add")) => address(),
+            ("region", 2) if words.get(1) == Some(&"remove") => address(),
+            ("break", 1) => {
+                let mut words: Vec<String> =
+                    BREAK_SUBCOMMANDS.iter().map(|s| s.to_string()).collect();
+                words.extend(address()?);
+                Some(words)
+            }
+            ("break", 2)
+                if words
+                    .get(1)
+                    .is_some_and(|w| u16::from_str_radix(w, 16).is_ok()) =>
+            {
+                fixed(&["if"])
+            }
+            ("break", 3) if words.get(2) == Some(&"if") => fixed(REGISTERS),
+            ("break", 3) if words.get(1) == Some(&"cond") => fixed(REGISTERS),
+            _ => None,
+        }
+    }
+}
+
+impl Completer for ReplHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let line = &line[..pos];
+        let start = line.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+        let prefix = &line[start..];
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let arg = match prefix.is_empty() {
+            true => words.len(),
+            false => words.len() - 1,
+        };
+
+        let candidates: Vec<String> = if arg == 0 {
+            COMMANDS
+                .iter()
+                .flat_map(|c| c.names())
+                .map(String::from)
+                .collect()
+        } else if let Some(words) = self.words(words[0], arg, &words) {
+            words
+        } else {
+            // everything else that takes an argument is a file
+            return self.files.complete(line, pos, ctx);
+        };
+
+        let matches: Vec<Pair> = candidates
+            .into_iter()
+            .filter(|c| c.to_lowercase().starts_with(&prefix.to_lowercase()))
+            .map(|c| Pair {
+                display: c.clone(),
+                replacement: format!("{} ", c),
+            })
+            .collect();
+
+        Ok((start, matches))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {}
+
+impl Validator for ReplHelper {}
+
+impl Helper for ReplHelper {}
+
+#[cfg(test)]
+mod tests {
+    use rustyline::history::DefaultHistory;
+
+    use super::*;
+
+    fn complete(helper: &ReplHelper, line: &str) -> (usize, Vec<String>) {
+        let history = DefaultHistory::new();
+        let (start, pairs) = helper
+            .complete(line, line.len(), &Context::new(&history))
+            .unwrap();
+        (
+            start,
+            pairs.into_iter().map(|pair| pair.replacement).collect(),
+        )
+    }
+
+    #[test]
+    fn test_complete() {
+        let helper = ReplHelper {
+            addresses: [0x4000, 0xC000].into(),
+            ..Default::default()
+        };
+
+        assert_eq!(complete(&helper, "unti"), (0, vec!["until ".to_string()]));
+        assert_eq!(complete(&helper, "set H"), (4, vec!["hl ".to_string()]));
+        assert_eq!(
+            complete(&helper, "reset "),
+            (
+                6,
+                vec![
+                    "soft ".to_string(),
+                    "hard ".to_string(),
+                    "power ".to_string()
+                ]
+            )
+        );
+        assert_eq!(
+            complete(&helper, "break "),
+            (
+                6,
+                ["list", "enable", "disable", "ignore", "cond", "delete", "4000", "C000"]
+                    .map(|word| format!("{} ", word))
+                    .to_vec()
+            )
+        );
+        assert_eq!(
+            complete(&helper, "break C000 "),
+            (11, vec!["if ".to_string()])
+        );
+        assert_eq!(
+            complete(&helper, "break cond 1 i"),
+            (13, vec!["ix ".to_string(), "iy ".to_string()])
+        );
+    }
+}
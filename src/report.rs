@@ -0,0 +1,181 @@
+//! Output of the diagnostic commands as JSON, for scripts. Printed instead of
+//! the text output with `--json`, one object per line.
+
+use msx::{
+    bus::MemorySegment, interrupt_stats::InterruptStats, regions::MemoryRegion, romdb::RomDatabase,
+    slot::SlotType, InternalState,
+};
+use rustmsx_debugger::breakpoint::Breakpoint;
+use serde::Serialize;
+
+/// CPU state of each emulator, printed by `dump` and `--report-every`.
+#[derive(Debug, Serialize)]
+pub struct DumpReport {
+    pub cycles: u64,
+    pub msx: InternalState,
+    /// the B machine of an A/B comparison
+    pub compare: Option<InternalState>,
+    pub open_msx: Option<InternalState>,
+}
+
+/// Printed by `status`.
+#[derive(Debug, Serialize)]
+pub struct StatusReport {
+    pub cycles: u64,
+    /// frames the VDP draws a second, 50 or 60
+    pub frequency: u32,
+    pub interrupts: InterruptStatus,
+    pub breakpoints: Vec<BreakpointStatus>,
+    pub link: Option<LinkStatus>,
+    pub primary_slot_config: u8,
+    pub slots: Vec<SlotStatus>,
+    pub compare_slots: Option<Vec<SlotStatus>>,
+    /// whether the A/B machines are in sync
+    pub in_sync: Option<bool>,
+    pub segments: Vec<MemorySegment>,
+    pub regions: Vec<MemoryRegion>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BreakpointStatus {
+    pub id: usize,
+    pub address: u16,
+    pub enabled: bool,
+    pub condition: Option<String>,
+    pub hits: u64,
+    pub ignore_count: u64,
+    pub temporary: bool,
+}
+
+impl From<&Breakpoint> for BreakpointStatus {
+    fn from(bp: &Breakpoint) -> Self {
+        Self {
+            id: bp.id,
+            address: bp.address,
+            enabled: bp.enabled,
+            condition: bp.condition.as_ref().map(|c| c.to_string()),
+            hits: bp.hits,
+            ignore_count: bp.ignore_count,
+            temporary: bp.temporary,
+        }
+    }
+}
+
+/// How the VDP interrupts went, see [`InterruptStats`].
+#[derive(Debug, Serialize)]
+pub struct InterruptStatus {
+    pub fired: u64,
+    pub delivered: u64,
+    pub missed: u64,
+    /// in T-states, none until one was delivered
+    pub average_latency: Option<f64>,
+}
+
+impl From<InterruptStats> for InterruptStatus {
+    fn from(stats: InterruptStats) -> Self {
+        Self {
+            fired: stats.fired,
+            delivered: stats.delivered,
+            missed: stats.missed,
+            average_latency: stats.average_latency(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct LinkStatus {
+    pub mode: String,
+    pub connected: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SlotStatus {
+    pub slot: usize,
+    pub description: String,
+    pub crc32: Option<u32>,
+    /// title of the ROM in the ROM database
+    pub title: Option<String>,
+}
+
+impl SlotStatus {
+    pub fn new(slot: usize, slot_type: &SlotType, rom_db: &RomDatabase) -> Self {
+        let crc32 = match slot_type {
+            SlotType::Rom(rom) => Some(rom.crc32),
+            _ => None,
+        };
+        Self {
+            slot,
+            description: slot_type.to_string(),
+            crc32,
+            title: crc32
+                .and_then(|crc32| rom_db.find_by_crc32(crc32))
+                .map(|info| info.title.clone()),
+        }
+    }
+}
+
+/// Printed by `memdump`, with the memory of the emulators the target
+/// selects as hex strings.
+#[derive(Debug, Default, Serialize)]
+pub struct MemoryReport {
+    pub start: u16,
+    pub end: u16,
+    pub msx: Option<String>,
+    pub compare: Option<String>,
+    pub open_msx: Option<String>,
+}
+
+pub fn hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+/// Prints a report as a single line of JSON.
+pub fn print<T: Serialize>(report: &T) -> anyhow::Result<()> {
+    println!("{}", serde_json::to_string(report)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use msx::{
+        romdb::RomInfo,
+        slot::{RamSlot, RomSlot},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_slot_status() {
+        let rom = RomSlot::new(&[0x18, 0xFE], 0x4000, 0x4000);
+        let mut rom_db = RomDatabase::default();
+        rom_db.add(RomInfo {
+            crc32: format!("{:08x}", rom.crc32),
+            sha1: None,
+            title: "Test".to_string(),
+            mapper: None,
+            machine: None,
+        });
+
+        let status = SlotStatus::new(1, &SlotType::Rom(rom.clone()), &rom_db);
+        assert_eq!(
+            (status.crc32, status.title.as_deref()),
+            (Some(rom.crc32), Some("Test"))
+        );
+        let status = SlotStatus::new(3, &SlotType::Ram(RamSlot::new(0, 0x10000)), &rom_db);
+        assert_eq!((status.crc32, status.title), (None, None));
+    }
+
+    #[test]
+    fn test_memory_report() {
+        let report = MemoryReport {
+            start: 0xC000,
+            end: 0xC003,
+            msx: Some(hex(&[0x00, 0x1F, 0xA0, 0xFF])),
+            ..Default::default()
+        };
+        assert_eq!(
+            serde_json::to_string(&report).unwrap(),
+            r#"{"start":49152,"end":49155,"msx":"001FA0FF","compare":null,"open_msx":null}"#
+        );
+    }
+}
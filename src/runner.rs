@@ -1,20 +1,103 @@
-use std::{num::ParseIntError, path::PathBuf};
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{self, BufWriter, Read, Write},
+    num::ParseIntError,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
 
 use anyhow::{anyhow, bail};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use gag::BufferRedirect;
 use msx::{
-    compare_slices,
+    archive,
+    autotype::Autotyper,
+    basic::BasicProgram,
+    bload::BinFile,
+    cbios, compare_slices,
+    console::Console,
+    disasm::Disassembly,
+    dos::{DosCommand, DosStep},
+    flag_string,
+    frame_capture::FrameCapture,
+    frame_hash::{FrameHasher, FrameHashes},
+    history::{History, DEFAULT_HISTORY_SIZE},
+    hook_guard::HookGuard,
+    hooks::{self, HookTracer},
+    instruction::Instruction,
+    keyboard::InputProfile,
+    machine::STEPS_PER_FRAME,
+    palette::Palette,
+    preset::Preset,
+    ram_cartridge::RamCartridge,
+    regions::Regions,
+    romdb::RomDatabase,
+    rominfo::RomReport,
+    screen_reader::{self, ScreenReader},
     slot::{RamSlot, RomSlot, SlotType},
-    Msx, ProgramEntry, ReportState,
+    slot_check::SlotChecker,
+    slot_trace::SlotTracer,
+    source_map::{SourceLine, SourceMap},
+    stack_guard::StackGuard,
+    sunrise_ide::SunriseIde,
+    symbols::Symbols,
+    sysvars,
+    tape::Tape,
+    vdp_timing::{self, VdpTimingChecker},
+    InternalState, Msx, ProgramEntry, ReportState,
+};
+use rustmsx_debugger::{
+    breakpoint::{Breakpoints, Operand},
+    command::{
+        Command, CommandLine, DumpTarget, InputLogCommand, RegionCommand, SetTarget, TapeCommand,
+    },
+    stepping::StepStop,
+    watch::WatchExpr,
+    Debugger,
 };
-use rustyline::DefaultEditor;
+use rustyline::{history::DefaultHistory, Editor};
+use serde_json::{json, Value};
 use similar::{ChangeTag, TextDiff};
 
-use crate::{mru::MRUList, open_msx::Client};
+use crate::{
+    autosave::Autosave,
+    dap::{self, DapServer},
+    device_logs::DeviceLogs,
+    link::{Link, LinkMode},
+    log_filter::LogFilter,
+    mru::MRUList,
+    open_msx::Client,
+    remote::{Event, RemoteServer, Request, Response},
+    repl::ReplHelper,
+    report::{
+        self, BreakpointStatus, DumpReport, LinkStatus, MemoryReport, SlotStatus, StatusReport,
+    },
+    style::Style,
+    tui::{DebugView, Register, Tui},
+};
+
+// how many instructions run between exchanges on the link cable
+const LINK_PUMP_INTERVAL: u64 = 192;
+
+// how many instructions run between checks for remote debugger requests
+const REMOTE_POLL_INTERVAL: u64 = 10_000;
+
+// how many instructions run between refills of the BIOS keyboard buffer
+const KEY_BUFFER_INTERVAL: u64 = 1000;
+
+// extra openMSX steps allowed to catch up with an instruction the emulator
+// runs as a single step, e.g. one with redundant prefixes
+const MAX_RESYNC_STEPS: usize = 4;
 
 pub struct Runner {
-    pub breakpoints: Vec<u16>,
+    pub debugger: Debugger,
     pub max_cycles: Option<u64>,
     pub open_msx: bool,
+    /// steps openMSX until its PC matches after each step
+    pub resync: bool,
+    /// prints the diagnostic commands as JSON
+    pub json: bool,
     pub break_on_mismatch: bool,
     pub break_on_mem_mismatch: bool,
     pub break_on_ppi_write: bool,
@@ -22,190 +105,202 @@ pub struct Runner {
     pub log_on_mismatch: bool,
     pub track_flags: bool,
     pub report_every: Option<u64>,
+    pub link_mode: Option<LinkMode>,
+    /// address the remote debugger clients connect to
+    pub listen: Option<String>,
+    /// address the DAP debuggers connect to
+    pub dap: Option<String>,
+    pub bin_file: Option<PathBuf>,
+    pub bin_run: bool,
+    pub bin_at: u64,
+    pub bas_file: Option<PathBuf>,
+    pub bas_at: u64,
 
     slots: Vec<SlotType>,
+    rom_db: RomDatabase,
+    /// colors of the screen, selected with --palette or the palette command
+    palette: Palette,
+    style: Style,
+    // full screen debugger used instead of the prompt, with --tui
+    tui: Option<Tui>,
+    // start of the memory shown by the TUI
+    memory_view: u16,
     running: bool,
     cycles: u64,
     client: Option<Client>,
+    link: Option<Link>,
+    remote: Option<RemoteServer>,
+    dap_server: Option<DapServer>,
+    // labels of the program, from --symbols
+    symbols: Symbols,
+    // source lines of the program, from --listing
+    source_map: SourceMap,
+    // named memory regions, from --regions or the region command
+    regions: Regions,
+    key_buffer_queue: VecDeque<u8>,
+    autotyper: Autotyper,
+    // command typed at the MSX-DOS prompt, ending the run when it's done
+    dos_command: Option<DosCommand>,
+    slow: Option<f64>,
+    last_frame: Instant,
+    // the frame counted by the VDP when the run was last throttled
+    throttled_frame: u64,
+    // the frame counted by the VDP when the screen was last hashed
+    hashed_frame: u64,
     instructions: MRUList<ProgramEntry>,
+    // registers when the prompt was last shown, to highlight what changed
+    last_stop: Option<InternalState>,
+    history: History,
+    stack_guard: Option<StackGuard>,
+    // breaks on malformed interrupt entries, with --guard-hooks
+    hook_guard: Option<HookGuard>,
+    // reports the calls to patched BIOS hooks, with --trace-hooks
+    hook_tracer: Option<HookTracer>,
+    // the text printed through CHPUT, when it's shown or breaks
+    console: Option<Console>,
+    // prints the CHPUT text to stdout, with --console
+    echo_console: bool,
+    // texts that break when printed, with --break-on-text
+    text_breaks: Vec<String>,
+    // prints the text screen lines as they change, with --follow-screen
+    screen_reader: Option<ScreenReader>,
+    // reports the VRAM accesses too close for a real VDP, with --vdp-timing
+    vdp_timing: Option<VdpTimingChecker>,
+    // reports the reads of programs from empty slots, with --slot-check
+    slot_check: Option<SlotChecker>,
+    // prints the writes to the slot registers, with --trace-slots
+    slot_trace: Option<SlotTracer>,
+    // prints what the program writes to ports 0x2E/0x2F, with --debug-device
+    debug_device: bool,
+    // hashes each frame, with --frame-hashes or --frame-hashes-reference
+    frame_hasher: Option<FrameHasher>,
+    // file of the --frame-hashes, created when the run starts
+    frame_hashes: Option<PathBuf>,
+    frame_hash_output: Option<BufWriter<File>>,
+    // rolling save states and frame hashes, with --autosave-every
+    autosave: Option<Autosave>,
+    // CAS image the cassette output is recorded to, with --record-tape,
+    // written when the run ends
+    record_tape: Option<PathBuf>,
+    // directory of the structured logs, with --log-dir, created when the
+    // run starts
+    log_dir: Option<PathBuf>,
+    device_logs: Option<DeviceLogs>,
+    // the tracing levels the log command changes, if the CLI set them up
+    log_filter: Option<LogFilter>,
     msx: Msx,
-}
 
-enum SetTarget {
-    A,
-    B,
-    C,
-    HL,
-    HLAddress,
+    // second machine stepped in lockstep for A/B comparisons
+    compare_slots: Option<Vec<SlotType>>,
+    compare_msx: Option<Msx>,
+    in_sync: bool,
 }
 
-enum DumpTarget {
-    Msx,
-    OpenMsx,
-    Diff,
+/// What the emulation does after a debugger request.
+enum DapFlow {
+    Stay,
+    /// stays stopped, telling the debugger again, e.g. after a step
+    Stopped(&'static str),
+    Resume,
 }
 
-enum Command {
-    /// quits the emulator
-    Quit,
-
-    /// resets the emulator at initial state after loading the ROM
-    Reset,
-
-    /// steps one instruction on all emulators
-    Step(u32),
-
-    /// continues execution on all emulators
-    Continue,
-
-    /// dumps the current state of all emulators
-    Dump,
-
-    /// lists the current loaded program around the current program counter
-    List,
-
-    /// lists the execution log
-    Log,
-
-    /// Status
-    Status,
-
-    /// adds a breakpoint address
-    AddBreakpoint(u16),
+impl Runner {
+    pub fn run(&mut self) -> anyhow::Result<()> {
+        self.client = if self.open_msx {
+            Client::start()?;
+            let mut client = Client::new(&self.slots)?;
+            client.init()?;
 
-    /// removes a breakpoint address
-    RemoveBreakpoint(u16),
+            Some(client)
+        } else {
+            None
+        };
 
-    /// gets the value of a memory address
-    MemGet(u16),
+        for slot in &self.slots {
+            if let SlotType::Rom(rom) = slot {
+                if let Some(info) = self.rom_db.find_by_crc32(rom.crc32) {
+                    println!("Loaded: {}", info);
+                }
+            }
+        }
 
-    /// sets the value of a memory address
-    MemSet(u16, u8),
+        if let Some(link_mode) = &self.link_mode {
+            self.msx.enable_serial()?;
+            self.link = Some(Link::open(link_mode)?);
+            self.msx.link_connected(true);
+        }
 
-    /// dumps vram contents
-    VramDump(DumpTarget),
+        if let Some(addr) = &self.listen {
+            self.remote = Some(RemoteServer::bind(addr)?);
+        }
 
-    /// dumps the contents of the memory
-    MemDump(DumpTarget),
+        if let Some(addr) = &self.dap {
+            self.dap_server = Some(DapServer::bind(addr)?);
+        }
 
-    /// sets the value of a register
-    Set(SetTarget),
+        if let Some(path) = &self.frame_hashes {
+            self.frame_hash_output = Some(BufWriter::new(File::create(path)?));
+        }
 
-    /// sends a command to openMSX
-    Send(Vec<String>),
-}
+        if let Some(dir) = &self.log_dir {
+            self.device_logs = Some(DeviceLogs::create(dir)?);
+            // the port accesses are taken from the I/O log after each step
+            self.msx.enable_io_log();
+        }
 
-struct CommandLine {
-    command: Command,
-    args: Vec<String>,
-}
+        self.msx.cpu.track_flags = self.track_flags;
+        self.running = true;
 
-impl CommandLine {
-    fn parse_target(target: Option<&str>) -> anyhow::Result<DumpTarget> {
-        match target {
-            Some("msx") => Ok(DumpTarget::Msx),
-            Some("openmsx") => Ok(DumpTarget::OpenMsx),
-            None | Some("diff") => Ok(DumpTarget::Diff),
-            _ => bail!("Invalid target. Use openmsx, msx or diff."),
+        if self.dap_server.is_some() {
+            self.start_dap()?;
         }
-    }
 
-    fn parse(line: &str) -> anyhow::Result<Self> {
-        let mut parts = line.split_whitespace();
-
-        let command = match parts.next() {
-            Some("quit") | Some("q") => Command::Quit,
-            Some("step") | Some("n") => {
-                let n = match parts.next() {
-                    Some(n) => n.parse()?,
-                    None => 1,
-                };
-                Command::Step(n)
-            }
-            Some("cont") | Some("c") => Command::Continue,
-            Some("reset") => Command::Reset,
-            Some("list") | Some("l") => Command::List,
-            Some("status") | Some("st") => Command::Status,
-            Some("set") | Some("s") => {
-                let target = match parts.next() {
-                    Some("a") => SetTarget::A,
-                    Some("b") => SetTarget::B,
-                    Some("c") => SetTarget::C,
-                    Some("hl") => SetTarget::HL,
-                    Some("(hl)") => SetTarget::HLAddress,
-                    _ => panic!("Invalid set target"),
-                };
+        // without anything to check between the instructions, the machine
+        // runs until it halts in a loop that checks nothing else
+        if !self.debugging() {
+            self.play()?;
+            return self.finish_outputs();
+        }
 
-                Command::Set(target)
-            }
-            Some("dump") | Some("d") => Command::Dump,
-            Some("mem") | Some("m") => {
-                let addr = u16::from_str_radix(parts.next().unwrap(), 16)?;
+        let mut stop_next = false;
 
-                match parts.next() {
-                    Some(p) => {
-                        let value = u8::from_str_radix(p, 16)?;
-                        Command::MemSet(addr, value)
-                    }
-                    None => Command::MemGet(addr),
+        loop {
+            // the BIOS clears the RAM while booting, so the binary may need to
+            // be loaded a while after the start
+            if self.cycles >= self.bin_at {
+                if let Some(bin_file) = self.bin_file.take() {
+                    self.load_bin(&bin_file, self.bin_run)?;
                 }
             }
-            Some("break") | Some("bp") => {
-                let addr = u16::from_str_radix(parts.next().unwrap(), 16)?;
-                Command::AddBreakpoint(addr)
-            }
-            Some("removebreak") | Some("rbp") => {
-                let addr = u16::from_str_radix(parts.next().unwrap(), 16)?;
-                Command::RemoveBreakpoint(addr)
-            }
-            Some("send") => {
-                let mut args = Vec::new();
 
-                for arg in parts.by_ref() {
-                    args.push(arg.to_string());
+            // same for BASIC programs, that need the interpreter to be ready
+            if self.cycles >= self.bas_at && self.msx.key_buffer_ready() {
+                if let Some(bas_file) = self.bas_file.take() {
+                    self.load_basic(&bas_file)?;
                 }
-
-                Command::Send(args)
-            }
-            Some("memdump") | Some("md") => {
-                Command::MemDump(CommandLine::parse_target(parts.next())?)
             }
-            Some("vramdump") | Some("vdpdump") | Some("vd") => {
-                Command::VramDump(CommandLine::parse_target(parts.next())?)
-            }
-            Some("log") => Command::Log,
-            _ => bail!("Invalid command: {}", line),
-        };
-
-        let args = parts.map(|s| s.to_string()).collect();
-
-        Ok(Self { command, args })
-    }
-}
-
-impl Runner {
-    pub fn run(&mut self) -> anyhow::Result<()> {
-        self.client = if self.open_msx {
-            Client::start()?;
-            let mut client = Client::new(&self.slots)?;
-            client.init()?;
-
-            Some(client)
-        } else {
-            None
-        };
-
-        self.running = true;
-
-        let mut stop_next = false;
 
-        loop {
+            let pending = self.debugger.stepping.before(&self.msx);
             let mut stop = self.step()?;
+            // why the debugger is told the emulation stopped
+            let mut reason = "exception";
 
             if let Some(report_every) = self.report_every {
                 if self.cycles % report_every == 0 {
-                    println!("\rCycles: {} PC: {:04X}", self.cycles, self.msx.pc());
-                    self.dump()?;
+                    if !self.json {
+                        println!("\rCycles: {} PC: {:04X}", self.cycles, self.msx.pc());
+                    }
+                    self.dump(self.json)?;
+                    self.last_stop = Some(self.msx.report_state()?);
+                }
+            }
+
+            if self.cycles % REMOTE_POLL_INTERVAL == 0 {
+                let pause = self.poll_remote()?;
+                if self.poll_dap()? || pause {
+                    println!("Paused at {:#06X}", self.msx.pc());
+                    stop = true;
+                    reason = "pause";
                 }
             }
 
@@ -247,6 +342,10 @@ impl Runner {
                 }
             }
 
+            if self.compare_ab()? {
+                stop = true;
+            }
+
             if self.break_on_halt && self.msx.halted() {
                 println!("Halted at {:#06X}", self.msx.pc());
                 stop = true;
@@ -257,23 +356,46 @@ impl Runner {
                 stop = true;
             }
 
-            if self.at_breakpoint() {
-                println!("Breakpoint hit at {:#06X}", self.msx.pc());
+            if let Some(id) = self.at_breakpoint() {
+                println!("Breakpoint #{} hit at {:#06X}", id, self.msx.pc());
                 stop = true;
+                reason = "breakpoint";
+            }
+
+            let step_stop = self
+                .debugger
+                .stepping
+                .after(&self.msx, &self.source_map, pending);
+            if let Some(step_stop) = step_stop {
+                match step_stop {
+                    StepStop::Reached(address) => println!("Reached {:#06X}", address),
+                    StepStop::Returned(address) => println!("Returned to {:#06X}", address),
+                    StepStop::Line => {}
+                }
+                stop = true;
+                reason = "step";
             }
 
             if self.at_cycles_limit() {
                 println!("Breaking at cycle #{}", self.cycles);
                 stop = true;
+                reason = "pause";
             }
 
             if stop || stop_next {
                 if stop_next {
                     println!("Stepped to {:#06X}", self.msx.pc());
+                    reason = "step";
                 }
                 stop_next = false;
 
-                self.start_prompt()?;
+                if self.dap_server.is_some() {
+                    self.serve_dap(reason)?;
+                } else if self.remote.is_some() {
+                    self.serve_remote()?;
+                } else {
+                    self.start_prompt()?;
+                }
             }
 
             if self.msx.halted() || !self.running {
@@ -285,16 +407,236 @@ impl Runner {
             client.shutdown()?;
         }
 
+        if let Some(remote) = &mut self.remote {
+            remote.event(&Event::Exited)?;
+        }
+
+        if let Some(dap) = &mut self.dap_server {
+            dap.event("terminated", json!({}));
+            dap.event("exited", json!({ "exitCode": 0 }));
+        }
+
+        self.finish_outputs()
+    }
+
+    // compares the machines of --ab-compare after a step, their registers
+    // and the hashes of their memory and chips, telling whether they just
+    // diverged
+    fn compare_ab(&mut self) -> anyhow::Result<bool> {
+        let Some(compare_msx) = &mut self.compare_msx else {
+            return Ok(false);
+        };
+        let state_a = format!("{}", self.msx.report_state()?);
+        let state_b = format!("{}", compare_msx.report_state()?);
+        let in_sync =
+            state_a == state_b && self.msx.update_memory_hash() == compare_msx.update_memory_hash();
+
+        let diverged = self.in_sync && !in_sync;
+        if diverged {
+            println!("A/B divergence at cycle #{}", self.cycles);
+            println!("A: {}", state_a);
+            println!("B: {}", state_b);
+            if let Some(difference) = self.msx.first_difference(compare_msx) {
+                println!("A/B differ first in the {}", difference);
+            }
+            println!();
+        } else if !self.in_sync && in_sync {
+            println!("A/B back in sync at cycle #{}", self.cycles);
+        }
+
+        self.in_sync = in_sync;
+        Ok(diverged)
+    }
+
+    /// Whether anything has to be checked or recorded after each instruction,
+    /// e.g. breakpoints, a comparison or a debugger that may connect.
+    fn debugging(&self) -> bool {
+        !self.debugger.breakpoints.is_empty()
+            || self.max_cycles.is_some()
+            || self.debugger.stepping.is_active()
+            || self.report_every.is_some()
+            || self.break_on_halt
+            || self.break_on_ppi_write
+            || self.debug_device
+            || self
+                .frame_hasher
+                .as_ref()
+                .is_some_and(FrameHasher::has_reference)
+            || self.stack_guard.is_some()
+            || self.hook_guard.is_some()
+            || self.hook_tracer.is_some()
+            || self.console.is_some()
+            || self.vdp_timing.is_some()
+            || self.slot_check.is_some()
+            || self.slot_trace.is_some()
+            || !self.debugger.timers.is_empty()
+            || self.device_logs.is_some()
+            || self.client.is_some()
+            || self.compare_msx.is_some()
+            || self.remote.is_some()
+            || self.dap_server.is_some()
+    }
+
+    /// Runs until the machine halts without any of the debugging checks of
+    /// [`Runner::step`], a frame of instructions at a time.
+    fn play(&mut self) -> anyhow::Result<()> {
+        tracing::debug!("Nothing to debug, running without checks");
+        while !self.msx.halted() && self.running {
+            if self.bin_file.is_some() && self.cycles >= self.bin_at {
+                if let Some(bin_file) = self.bin_file.take() {
+                    self.load_bin(&bin_file, self.bin_run)?;
+                }
+            }
+            if self.bas_file.is_some() && self.cycles >= self.bas_at && self.msx.key_buffer_ready()
+            {
+                if let Some(bas_file) = self.bas_file.take() {
+                    self.load_basic(&bas_file)?;
+                }
+            }
+
+            let mut steps = 0;
+            while steps < STEPS_PER_FRAME as u64 {
+                self.msx.step();
+                steps += 1;
+                if self.msx.halted() {
+                    break;
+                }
+            }
+            self.cycles += steps;
+
+            self.frame_done()?;
+            self.autotyper.frame(&mut self.msx);
+            self.dos_command_frame()?;
+            self.follow_screen();
+            while let Some(ch) = self.key_buffer_queue.front() {
+                if !self.msx.push_key_buffer(*ch) {
+                    break;
+                }
+                self.key_buffer_queue.pop_front();
+            }
+            if let Some(link) = &mut self.link {
+                if let Err(e) = link.pump(&mut self.msx) {
+                    println!("Link disconnected: {}", e);
+                    self.link = None;
+                    self.msx.link_connected(false);
+                }
+            }
+            self.throttle();
+        }
         Ok(())
     }
 
     pub fn step(&mut self) -> anyhow::Result<bool> {
-        self.instructions.push(self.msx.instruction());
+        let instruction = self.msx.instruction();
+        if let Some(device_logs) = &mut self.device_logs {
+            device_logs.before_step(&self.msx, &instruction)?;
+        }
+        self.instructions.push(instruction);
+        self.history.record(&self.msx.cpu);
+        if let Some(stack_guard) = &mut self.stack_guard {
+            stack_guard.before_step(&self.msx.cpu);
+        }
+        if let Some(hook_guard) = &mut self.hook_guard {
+            hook_guard.before_step(&self.msx.cpu);
+        }
+        if let Some(hook_tracer) = &mut self.hook_tracer {
+            if let Some(call) = hook_tracer.before_step(&self.msx.cpu) {
+                println!("{}", call);
+            }
+        }
+        // the text break printed by this step, if any
+        let mut printed = None;
+        if let Some(console) = &mut self.console {
+            if let Some(ch) = console.before_step(&self.msx.cpu) {
+                if self.echo_console {
+                    print!("{}", ch);
+                    let _ = io::stdout().flush();
+                }
+                printed = self
+                    .text_breaks
+                    .iter()
+                    .find(|text| console.printed(text))
+                    .cloned();
+            }
+        }
+        if let Some(vdp_timing) = &mut self.vdp_timing {
+            vdp_timing.before_step(&self.msx.cpu);
+        }
+        if let Some(slot_check) = &mut self.slot_check {
+            // forget the reads of the checks above
+            self.msx.empty_slot_read();
+            slot_check.before_step(&self.msx.cpu, self.msx.primary_slot_config());
+        }
+        if let Some(slot_trace) = &mut self.slot_trace {
+            self.msx.wrote_slot_register();
+            slot_trace.before_step(&self.msx.cpu, self.msx.primary_slot_config());
+        }
         self.msx.step();
 
+        if let Some(device_logs) = &mut self.device_logs {
+            device_logs.after_step(&mut self.msx)?;
+        }
+
+        if let Some(slot_check) = &mut self.slot_check {
+            let empty_read = self.msx.empty_slot_read();
+            if let Some(conflict) =
+                slot_check.after_step(self.msx.primary_slot_config(), empty_read)
+            {
+                println!("{}", conflict);
+            }
+        }
+
+        if let Some(vdp_timing) = &mut self.vdp_timing {
+            let violation = vdp_timing.after_step(&mut self.msx.bus.write().unwrap());
+            if let Some(violation) = violation {
+                println!("{}", violation);
+            }
+        }
+
+        let mut stop = false;
+        if let Some(stack_guard) = &mut self.stack_guard {
+            if let Some(violation) = stack_guard.after_step(&self.msx.cpu) {
+                println!("{}", violation);
+                stop = true;
+            }
+        }
+        if let Some(hook_guard) = &mut self.hook_guard {
+            if let Some(violation) = hook_guard.after_step(&self.msx.cpu) {
+                println!("{}", violation);
+                stop = true;
+            }
+        }
+        if let Some(slot_trace) = &mut self.slot_trace {
+            let wrote = self.msx.wrote_slot_register();
+            if let Some(write) =
+                slot_trace.after_step(self.msx.primary_slot_config(), wrote, &self.slots)
+            {
+                println!("{}", write);
+                if slot_trace.breaks_on_pc_page() && write.switches_pc_page() {
+                    stop = true;
+                }
+            }
+        }
+
+        if self.debug_device && self.debug_device_step() {
+            stop = true;
+        }
+
+        if let Some(text) = printed {
+            println!("Printed {:?} at {:#06X}", text, self.msx.pc());
+            stop = true;
+        }
+
+        if let Some(compare_msx) = &mut self.compare_msx {
+            compare_msx.step();
+        }
+
         if let Some(client) = &mut self.client {
             // let opcode = self.msx.cpu.read_byte(self.msx.pc());
             client.step()?;
+            if self.resync && !client.step_to(self.msx.pc(), MAX_RESYNC_STEPS)? {
+                tracing::debug!("openMSX couldn't catch up with {:#06X}", self.msx.pc());
+            }
             // if self.msx.cpu.read_byte(0xFFFF) == 0x00 {
             //     println!(
             //         "OpenMSX halted at {:#06X} with 0xFFFF = 0x00",
@@ -306,111 +648,1550 @@ impl Runner {
 
         self.cycles += 1;
 
-        Ok(false)
+        // run as if typed on the prompt, whose staying there they can't ask
+        // for while running
+        for timer in self.debugger.timers.due(&self.msx) {
+            println!("Timer #{}: {}", timer.id, timer.command);
+            self.handle_command(&timer.command)?;
+        }
+
+        if self.cycles % STEPS_PER_FRAME as u64 == 0 {
+            self.autotyper.frame(&mut self.msx);
+            self.dos_command_frame()?;
+            self.follow_screen();
+        }
+
+        self.throttle();
+
+        if self.frame_done()? {
+            stop = true;
+        }
+
+        if self.cycles % KEY_BUFFER_INTERVAL == 0 {
+            while let Some(ch) = self.key_buffer_queue.front() {
+                if !self.msx.push_key_buffer(*ch) {
+                    break;
+                }
+                self.key_buffer_queue.pop_front();
+            }
+        }
+
+        if self.cycles % LINK_PUMP_INTERVAL == 0 {
+            if let Some(link) = &mut self.link {
+                if let Err(e) = link.pump(&mut self.msx) {
+                    println!("Link disconnected: {}", e);
+                    self.link = None;
+                    self.msx.link_connected(false);
+                }
+            }
+        }
+
+        Ok(stop)
     }
 
-    pub fn at_ppi_write(&mut self) -> bool {
-        self.msx.wrote_to_ppi()
+    // prints the output of the debug device, also to the DAP debugger, and
+    // tells whether the program asked to break
+    fn debug_device_step(&mut self) -> bool {
+        let (output, stop) = self.msx.debug_device_output();
+        if !output.is_empty() {
+            print!("{}", output);
+            let _ = io::stdout().flush();
+            if let Some(dap) = &mut self.dap_server {
+                dap.event(
+                    "output",
+                    json!({
+                        "category": "stdout",
+                        "output": output,
+                    }),
+                );
+            }
+        }
+        if stop {
+            println!("Debug device break at {:#06X}", self.msx.pc());
+        }
+        stop
     }
 
-    pub fn at_breakpoint(&mut self) -> bool {
-        self.breakpoints.contains(&self.msx.pc())
+    // watches CHPUT while its text is shown or can break, keeping what was
+    // printed so far otherwise
+    fn update_console(&mut self) {
+        if !self.echo_console && self.text_breaks.is_empty() {
+            self.console = None;
+        } else if self.console.is_none() {
+            self.console = Some(Console::new());
+        }
     }
 
-    pub fn at_cycles_limit(&mut self) -> bool {
-        let is_at = self
-            .max_cycles
-            .map(|limit| self.cycles >= limit)
-            .unwrap_or(false);
-        if is_at {
-            self.max_cycles = None;
+    // prints the lines of the text screen that changed, when following it
+    fn follow_screen(&mut self) {
+        let frame = self.cycles / STEPS_PER_FRAME as u64;
+        if frame % screen_reader::UPDATE_FRAMES as u64 != 0 {
+            return;
+        }
+        if let Some(screen_reader) = &mut self.screen_reader {
+            for line in screen_reader.update(self.msx.screen_text()) {
+                println!("{}", line);
+            }
         }
-        is_at
     }
 
-    pub fn dump(&mut self) -> anyhow::Result<()> {
-        let state = &self.msx.report_state()?;
-        println!("{}", state);
+    /// Types the --dos-command once the DOS prompt shows, then prints what
+    /// it printed and ends the run when the prompt is back.
+    fn dos_command_frame(&mut self) -> anyhow::Result<()> {
+        let Some(dos_command) = &mut self.dos_command else {
+            return Ok(());
+        };
 
-        if let Some(client) = &mut self.client {
-            let state = client.report_state()?;
-            println!("{}", state);
+        match dos_command.step(&self.msx.screen_text(), self.msx.cursor()) {
+            DosStep::Wait => {}
+            DosStep::Type(keys) => {
+                println!("Running {} at the DOS prompt", dos_command.command());
+                self.autotyper.queue(&keys)?;
+            }
+            DosStep::Finished(output) => {
+                for line in output {
+                    println!("{}", line);
+                }
+                self.dos_command = None;
+                self.running = false;
+            }
         }
-
-        println!();
         Ok(())
     }
 
-    pub fn list(&mut self) -> anyhow::Result<()> {
-        let program = self.msx.program_slice(10, 20);
-        for line in program {
-            let flag = if self.msx.pc() == line.address {
-                ">"
-            } else {
-                " "
-            };
-            println!("{} {}", flag, line);
+    /// Steps through the rest of the video frame, until the VDP counts the
+    /// next one, the CPU halts or a breakpoint or a check stops it.
+    pub fn step_frame(&mut self) -> anyhow::Result<()> {
+        let frame = self.msx.frames();
+        loop {
+            let stop = self.step()?;
+            if let Some(id) = self.at_breakpoint() {
+                println!("Breakpoint #{} hit at {:#06X}", id, self.msx.pc());
+                return Ok(());
+            }
+            if stop || self.msx.frames() != frame || self.msx.halted() {
+                return Ok(());
+            }
         }
-
-        println!();
-        Ok(())
     }
 
-    pub fn log(&mut self) -> anyhow::Result<()> {
-        let instructions = self.instructions.iter().collect::<Vec<_>>();
-        for instruction in instructions.iter().rev() {
-            println!("{}", instruction);
+    // hashes the screen and autosaves once for each frame the VDP drew,
+    // telling whether the frame is the first to differ from the reference
+    fn frame_done(&mut self) -> anyhow::Result<bool> {
+        if self.frame_hasher.is_none() && self.autosave.is_none() {
+            return Ok(false);
         }
+        let frame = self.msx.frames();
+        if frame == self.hashed_frame {
+            return Ok(false);
+        }
+        self.hashed_frame = frame;
 
-        println!();
-        Ok(())
+        let first_difference = self.hash_frame()?;
+        self.autosave()?;
+        Ok(first_difference)
     }
 
-    pub fn start_prompt(&mut self) -> anyhow::Result<()> {
-        let history_file = PathBuf::new()
-            .join(dirs::home_dir().unwrap())
-            .join(".rustmsx_history");
+    // hashes the frame that just ended, writing the hash out and telling
+    // whether it is the first to differ from the reference
+    fn hash_frame(&mut self) -> anyhow::Result<bool> {
+        let Some(frame_hasher) = &mut self.frame_hasher else {
+            return Ok(false);
+        };
 
-        let mut rl = DefaultEditor::new()?;
-        if rl.load_history(&history_file).is_err() {
-            println!("No previous history.");
+        let hash = frame_hasher.hash(&self.msx);
+        if let Some(output) = &mut self.frame_hash_output {
+            writeln!(output, "{}", hash)?;
         }
+        if let Some(autosave) = &mut self.autosave {
+            autosave.frame(&hash);
+        }
+        let first_difference = hash.differs() && frame_hasher.differing() == 1;
+        if let (true, Some(expected)) = (first_difference, hash.expected) {
+            println!(
+                "Frame {} differs from the reference: {:08X}, expected {:08X}",
+                hash.frame, hash.crc32, expected
+            );
+        }
+        Ok(first_difference)
+    }
 
-        loop {
-            let readline = rl.readline(format!("#{:04X}> ", self.msx.pc()).as_str());
-
-            if let Ok(command) = readline {
-                rl.add_history_entry(command.as_str())?;
-                if !self.handle_command(command.as_str())? {
-                    break;
+    // saves the state with --autosave-every when it's time, flushing the
+    // --frame-hashes too; a failing save doesn't end the run it's there for
+    fn autosave(&mut self) -> anyhow::Result<()> {
+        let Some(autosave) = &mut self.autosave else {
+            return Ok(());
+        };
+        match autosave.save_if_due(&self.msx) {
+            Ok(Some(path)) => {
+                println!("Autosaved to {}", path.display());
+                if let Some(output) = &mut self.frame_hash_output {
+                    output.flush()?;
                 }
             }
+            Ok(None) => {}
+            Err(e) => println!("Autosave failed: {}", e),
         }
+        Ok(())
+    }
 
-        rl.append_history(&history_file)?;
+    // flushes the --frame-hashes and --log-dir and writes the --record-tape,
+    // failing the run when frames differed from the reference
+    fn finish_outputs(&mut self) -> anyhow::Result<()> {
+        if let Some(output) = &mut self.frame_hash_output {
+            output.flush()?;
+        }
+        if let Some(device_logs) = &mut self.device_logs {
+            device_logs.flush()?;
+        }
+        if let Some(path) = &self.record_tape {
+            if let Some(cas) = self.msx.tape_recording() {
+                cas.save(path)?;
+            }
+        }
+        match &self.frame_hasher {
+            Some(frame_hasher) if frame_hasher.differing() > 0 => bail!(
+                "{} frames differ from the reference",
+                frame_hasher.differing()
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    // when slowed down, waits for each frame the VDP drew to take its share
+    // of wall time, at the frame rate of the 50 or 60 Hz mode
+    fn throttle(&mut self) {
+        let Some(factor) = self.slow else {
+            return;
+        };
+        let frame = self.msx.frames();
+        if frame == self.throttled_frame {
+            return;
+        }
+        self.throttled_frame = frame;
+
+        let frame_duration = frame_duration(self.msx.pal()).mul_f64(factor);
+        let elapsed = self.last_frame.elapsed();
+        if elapsed < frame_duration {
+            std::thread::sleep(frame_duration - elapsed);
+        }
+        self.last_frame = Instant::now();
+    }
 
+    pub fn load_bin(&mut self, path: &PathBuf, run: bool) -> anyhow::Result<()> {
+        let bin = BinFile::parse(&std::fs::read(path)?)?;
+        self.msx.load_bin(&bin, run);
+        println!(
+            "Loaded {} at {:#06X}-{:#06X}, exec {:#06X}",
+            path.display(),
+            bin.start,
+            bin.end,
+            bin.exec
+        );
         Ok(())
     }
 
-    pub fn handle_command(&mut self, command: &str) -> anyhow::Result<bool> {
-        let line = match CommandLine::parse(command) {
-            Ok(line) => line,
-            Err(e) => {
-                println!("{}\n", e);
-                return Ok(true);
-            }
+    /// Disassembles the memory currently mapped from `start` to `end` into an
+    /// assembler source file.
+    pub fn export_disassembly(&self, start: u16, end: u16, path: &PathBuf) -> anyhow::Result<()> {
+        let disassembly = Disassembly::new(&self.msx.memory(), start, end, &[]);
+        std::fs::write(path, disassembly.to_source())?;
+        println!(
+            "Exported {:#06X}-{:#06X} to {} ({} bytes of code)",
+            start,
+            end,
+            path.display(),
+            disassembly.code_len()
+        );
+        Ok(())
+    }
+
+    /// Prints the header, mapper and checksums of the ROM in a slot (the
+    /// first ROM slot by default) or in a file, with the start of its INIT
+    /// routine.
+    pub fn rom_info(&self, target: Option<&str>) -> anyhow::Result<()> {
+        let slot = match target {
+            None => Some(
+                self.slots
+                    .iter()
+                    .position(|slot| matches!(slot, SlotType::Rom(_)))
+                    .ok_or_else(|| anyhow!("No ROM inserted"))?,
+            ),
+            Some(target) => target.parse::<usize>().ok(),
         };
 
-        match line.command {
-            Command::Quit => {
-                self.running = false;
-                Ok(false)
-            }
-            Command::Step(n) => {
+        let (rom, address) = match (slot, target) {
+            (Some(n), _) => match self.slots.get(n) {
+                Some(SlotType::Rom(rom)) => (rom.data.clone(), Some(rom.base)),
+                _ => bail!("No ROM in slot {}", n),
+            },
+            (None, file) => (
+                archive::read_file(&PathBuf::from(file.unwrap_or_default()))?,
+                None,
+            ),
+        };
+
+        let report = RomReport::new(&rom, address);
+        println!("{}", report);
+        if let Some(info) = self.rom_db.find_by_crc32(report.crc32) {
+            println!("Database: {}", info);
+        }
+        if cbios::is_cbios(&rom) {
+            println!("{}", cbios::LIMITATIONS);
+        } else if report.header.is_some_and(|header| header.text != 0) && self.runs_cbios() {
+            println!("Runs a BASIC program, which C-BIOS can't");
+        }
+
+        let preview = report.init_preview(&rom, 16);
+        if !preview.is_empty() {
+            println!("INIT:");
+            for line in preview {
+                println!("  {}", line);
+            }
+        }
+        Ok(())
+    }
+
+    // whether the BIOS in slot 0 is C-BIOS
+    fn runs_cbios(&self) -> bool {
+        matches!(self.slots.first(), Some(SlotType::Rom(rom)) if cbios::is_cbios(&rom.data))
+    }
+
+    /// Loads a tokenized BASIC program into the program area, or types in an
+    /// ASCII listing, and types `RUN` afterwards.
+    pub fn load_basic(&mut self, path: &PathBuf) -> anyhow::Result<()> {
+        match BasicProgram::parse(&std::fs::read(path)?)? {
+            BasicProgram::Tokenized(program) => {
+                self.msx.load_basic(&program);
+                println!("Loaded {} ({} bytes)", path.display(), program.len());
+            }
+            BasicProgram::Ascii(listing) => {
+                println!("Typing {} ({} bytes)", path.display(), listing.len());
+                self.key_buffer_queue.extend(listing);
+            }
+        }
+
+        self.key_buffer_queue.extend(b"RUN\r");
+        Ok(())
+    }
+
+    /// Writes the logged input events to a JSON file, with their frames and
+    /// cycles.
+    pub fn save_input_log(&self, path: &PathBuf) -> anyhow::Result<()> {
+        if !self.msx.input_log_enabled() {
+            bail!("The input log is off, turn it on with input log on");
+        }
+        let events = self.msx.input_events();
+        std::fs::write(path, serde_json::to_string_pretty(&events)?)?;
+        println!("Saved {} input events to {}", events.len(), path.display());
+        Ok(())
+    }
+
+    /// Writes the state of the machine, which can be loaded by the CLI and by
+    /// the web version.
+    pub fn save_state(&self, path: &PathBuf) -> anyhow::Result<()> {
+        std::fs::write(path, self.msx.save_state()?)?;
+        println!("Saved state to {}", path.display());
+        Ok(())
+    }
+
+    /// Restores a state saved by the CLI or the web version, including the
+    /// contents of the slots.
+    pub fn load_state(&mut self, path: &PathBuf) -> anyhow::Result<()> {
+        self.msx.load_state(&std::fs::read(path)?)?;
+        self.slots = self.msx.slots();
+        self.history.clear();
+        if let Some(stack_guard) = &mut self.stack_guard {
+            stack_guard.clear();
+        }
+        println!("Loaded state from {}", path.display());
+        Ok(())
+    }
+
+    /// Swaps the contents of a slot on all the emulated machines.
+    pub fn insert_cart(&mut self, slot: u8, cart: SlotType) -> anyhow::Result<()> {
+        let previous = self.msx.insert_cart(slot, cart.clone())?;
+        if let Some(compare_msx) = &mut self.compare_msx {
+            compare_msx.insert_cart(slot, cart.clone())?;
+        }
+        if let Some(compare_slots) = &mut self.compare_slots {
+            compare_slots[slot as usize] = cart.clone();
+        }
+        println!("Slot #{}: {} (was {})", slot, cart, previous);
+        self.slots[slot as usize] = cart;
+        Ok(())
+    }
+
+    pub fn at_ppi_write(&mut self) -> bool {
+        self.msx.wrote_to_ppi()
+    }
+
+    /// Returns the id of the breakpoint that stops at the current PC.
+    pub fn at_breakpoint(&mut self) -> Option<usize> {
+        self.debugger
+            .breakpoints
+            .check(&self.msx.cpu)
+            .map(|bp| bp.id)
+    }
+
+    fn list_breakpoints(&self) {
+        print!("{}", self.debugger.list_breakpoints());
+    }
+
+    // the hooks that were patched or called, with the calls when tracing
+    fn list_hooks(&self) {
+        let mut found = false;
+        let extended_valid = hooks::extended_hooks_valid(&self.msx.cpu);
+        for address in hooks::hook_addresses() {
+            // garbage until the BIOS or an extension sets them up
+            if hooks::is_extended(address) && !extended_valid {
+                continue;
+            }
+            let contents = hooks::hook_contents(&self.msx.cpu, address);
+            let calls = self
+                .hook_tracer
+                .as_ref()
+                .map_or(0, |tracer| tracer.calls(address));
+            if !hooks::is_patched(&contents) && calls == 0 {
+                continue;
+            }
+
+            found = true;
+            let name = hooks::hook_name(address).unwrap_or_default();
+            let description = hooks::describe_hook(&contents);
+            match &self.hook_tracer {
+                Some(_) => println!(
+                    "{:<7} {:#06X}  {:<24} {} calls",
+                    name, address, description, calls
+                ),
+                None => println!("{:<7} {:#06X}  {}", name, address, description),
+            }
+        }
+        if !found {
+            println!("No patched hooks.");
+        }
+        if self.hook_tracer.is_none() {
+            println!("Hook tracing is off, turn it on with: hooks on");
+        }
+        println!();
+    }
+
+    fn list_register_changes(&self, capture: &FrameCapture) {
+        let registers: Vec<String> = (capture.first().iter().enumerate())
+            .map(|(register, value)| format!("R#{}={:02X}", register, value))
+            .collect();
+        println!(
+            "{} lines, 192 and up in the VBlank, starting with: {}",
+            capture.lines.len(),
+            registers.join(" ")
+        );
+
+        let changes = capture.changes();
+        if changes.is_empty() {
+            println!("No register changed during the frame");
+        }
+        for change in changes {
+            println!(
+                "Line {:>3}  R#{}  {:02X} -> {:02X}",
+                change.line, change.register, change.from, change.to
+            );
+        }
+        println!();
+    }
+
+    fn list_vdp_timing_violations(&self) {
+        let Some(vdp_timing) = &self.vdp_timing else {
+            println!("VDP timing check is off, turn it on with: vdptiming on");
+            println!();
+            return;
+        };
+
+        let mut found = false;
+        for (pc, count) in vdp_timing.violations() {
+            found = true;
+            println!(
+                "{:#06X}  {}  {} times",
+                pc,
+                Instruction::parse_at(&self.msx.cpu, pc).name(),
+                count
+            );
+        }
+        if !found {
+            println!(
+                "No VRAM accesses too close in {} T-states.",
+                vdp_timing.clock()
+            );
+        }
+        println!();
+    }
+
+    fn list_slot_conflicts(&self) {
+        let Some(slot_check) = &self.slot_check else {
+            println!("Slot check is off, turn it on with: slotcheck on");
+            println!();
+            return;
+        };
+
+        let mut found = false;
+        for (conflict, count) in slot_check.conflicts() {
+            found = true;
+            println!("{}  {} times", conflict, count);
+        }
+        if !found {
+            println!(
+                "No reads from empty slots in {} instructions.",
+                slot_check.steps()
+            );
+        }
+        println!();
+    }
+
+    fn list_regions(&self) {
+        if self.regions.is_empty() {
+            println!("No regions.");
+        }
+        for region in self.regions.iter() {
+            println!(
+                "{:#06X} - {:#06X}  {}",
+                region.start, region.end, region.name
+            );
+        }
+    }
+
+    // the slot and the regions of each page of the memory, as `status` shows
+    fn print_memory_layout(&self) {
+        println!("Memory layout:");
+        let config = self.msx.primary_slot_config();
+        for page in 0..4u16 {
+            let start = page * 0x4000;
+            let end = start + 0x3FFF;
+            let slot = ((config >> (page * 2)) & 0x03) as usize;
+            let Some(slot_type) = self.slots.get(slot) else {
+                continue;
+            };
+
+            let rom = match slot_type {
+                SlotType::Rom(rom) => self.rom_db.find_by_crc32(rom.crc32),
+                _ => None,
+            };
+            let description = match rom {
+                Some(info) => format!(
+                    "{} - mapper: {}",
+                    info.title,
+                    info.mapper.as_deref().unwrap_or("none")
+                ),
+                None => slot_type.to_string(),
+            };
+            println!(
+                "  Page {} ({:#06X} - {:#06X}): slot {}, {}",
+                page, start, end, slot, description
+            );
+            for region in self.regions.overlapping(start, end) {
+                println!(
+                    "    {:#06X} - {:#06X}  {}",
+                    region.start, region.end, region.name
+                );
+            }
+        }
+    }
+
+    pub fn at_cycles_limit(&mut self) -> bool {
+        let is_at = self
+            .max_cycles
+            .map(|limit| self.cycles >= limit)
+            .unwrap_or(false);
+        if is_at {
+            self.max_cycles = None;
+        }
+        is_at
+    }
+
+    pub fn dump(&mut self, json: bool) -> anyhow::Result<()> {
+        if json {
+            let report = DumpReport {
+                cycles: self.cycles,
+                msx: self.msx.report_state()?,
+                compare: match &mut self.compare_msx {
+                    Some(compare_msx) => Some(compare_msx.report_state()?),
+                    None => None,
+                },
+                open_msx: match &mut self.client {
+                    Some(client) => Some(client.report_state()?),
+                    None => None,
+                },
+            };
+            return report::print(&report);
+        }
+
+        // the other emulators are highlighted where they differ from this one
+        let state = self.msx.report_state()?;
+        println!("{}", self.style.registers(&state, self.last_stop.as_ref()));
+
+        if let Some(compare_msx) = &mut self.compare_msx {
+            let compare_state = compare_msx.report_state()?;
+            println!("{}", self.style.registers(&compare_state, Some(&state)));
+        }
+
+        if let Some(client) = &mut self.client {
+            let open_msx_state = client.report_state()?;
+            println!("{}", self.style.registers(&open_msx_state, Some(&state)));
+        }
+
+        println!();
+        Ok(())
+    }
+
+    fn status_report(&self) -> StatusReport {
+        StatusReport {
+            cycles: self.cycles,
+            frequency: vdp_timing::frequency(self.msx.pal()),
+            interrupts: self.msx.interrupt_stats().into(),
+            breakpoints: self
+                .debugger
+                .breakpoints
+                .iter()
+                .map(BreakpointStatus::from)
+                .collect(),
+            link: self.link_mode.as_ref().map(|mode| LinkStatus {
+                mode: format!("{:?}", mode),
+                connected: self.link.is_some(),
+            }),
+            primary_slot_config: self.msx.primary_slot_config(),
+            slots: self
+                .slots
+                .iter()
+                .enumerate()
+                .map(|(n, slot)| SlotStatus::new(n, slot, &self.rom_db))
+                .collect(),
+            compare_slots: self.compare_slots.as_ref().map(|slots| {
+                slots
+                    .iter()
+                    .enumerate()
+                    .map(|(n, slot)| SlotStatus::new(n, slot, &self.rom_db))
+                    .collect()
+            }),
+            in_sync: self.compare_slots.as_ref().map(|_| self.in_sync),
+            segments: self.msx.memory_segments(),
+            regions: self.regions.iter().cloned().collect(),
+        }
+    }
+
+    fn memory_report(&mut self, target: DumpTarget) -> anyhow::Result<MemoryReport> {
+        let mut report = MemoryReport {
+            start: 0,
+            end: (self.msx.mem_size() - 1) as u16,
+            ..Default::default()
+        };
+        let msx = Some(report::hex(&self.msx.memory()));
+
+        // the same targets as the text output
+        if let Some(compare_msx) = &self.compare_msx {
+            let compare = Some(report::hex(&compare_msx.memory()));
+            match target {
+                DumpTarget::Compare => report.compare = compare,
+                DumpTarget::Diff => (report.msx, report.compare) = (msx, compare),
+                _ => report.msx = msx,
+            }
+            return Ok(report);
+        }
+
+        match (&mut self.client, target) {
+            (Some(client), DumpTarget::OpenMsx | DumpTarget::Compare) => {
+                report.open_msx = Some(report::hex(&client.memory(report.start, report.end)?));
+            }
+            (Some(client), DumpTarget::Diff) => {
+                report.open_msx = Some(report::hex(&client.memory(report.start, report.end)?));
+                report.msx = msx;
+            }
+            _ => report.msx = msx,
+        }
+        Ok(report)
+    }
+
+    pub fn list(&mut self, asm: bool) -> anyhow::Result<()> {
+        match self.source_map.location(self.msx.pc()) {
+            Some(at) if !asm => self.list_source(at),
+            _ => self.list_asm(),
+        }
+        println!();
+        Ok(())
+    }
+
+    fn list_source(&self, at: SourceLine) {
+        println!("{}:{}", self.source_map.path(at.file), at.line);
+        let start = at.line.saturating_sub(10).max(1);
+        for line in start..start + 21 {
+            let here = SourceLine {
+                file: at.file,
+                line,
+            };
+            if let Some(text) = self.source_map.text(here) {
+                let address = self.source_map.address(here);
+                println!(
+                    "{}",
+                    self.style.source_line(line, text, address, line == at.line)
+                );
+            }
+        }
+    }
+
+    fn list_asm(&self) {
+        let program = self.msx.program_slice(10, 20);
+        for line in program {
+            let current = self.msx.pc() == line.address;
+            println!("{}", self.style.program_line(&line, current));
+        }
+    }
+
+    pub fn log(&mut self) -> anyhow::Result<()> {
+        let instructions = self.instructions.iter().collect::<Vec<_>>();
+        for instruction in instructions.iter().rev() {
+            println!("{}", self.style.program_line(instruction, false));
+        }
+
+        println!();
+        Ok(())
+    }
+
+    pub fn start_prompt(&mut self) -> anyhow::Result<()> {
+        if let Some(mut tui) = self.tui.take() {
+            let result = self.start_tui(&mut tui);
+            tui.leave()?;
+            self.tui = Some(tui);
+            return result;
+        }
+
+        let history_file = PathBuf::new()
+            .join(dirs::home_dir().unwrap())
+            .join(".rustmsx_history");
+
+        let mut rl = Editor::<ReplHelper, DefaultHistory>::new()?;
+        rl.set_helper(Some(ReplHelper::default()));
+        if rl.load_history(&history_file).is_err() {
+            println!("No previous history.");
+        }
+
+        loop {
+            self.last_stop = Some(self.msx.report_state()?);
+            if let Some(helper) = rl.helper_mut() {
+                helper.addresses = self
+                    .debugger
+                    .breakpoints
+                    .iter()
+                    .map(|bp| bp.address)
+                    .collect();
+                helper.addresses.insert(self.msx.pc());
+                helper.addresses.insert(self.msx.cpu.sp);
+                helper.addresses.insert(self.msx.cpu.get_hl());
+            }
+
+            let readline = rl.readline(format!("#{:04X}> ", self.msx.pc()).as_str());
+
+            if let Ok(command) = readline {
+                rl.add_history_entry(command.as_str())?;
+                if !self.handle_command(command.as_str())? {
+                    break;
+                }
+            }
+        }
+
+        rl.append_history(&history_file)?;
+
+        Ok(())
+    }
+
+    fn start_tui(&mut self, tui: &mut Tui) -> anyhow::Result<()> {
+        loop {
+            self.last_stop = Some(self.msx.report_state()?);
+            self.debugger.watches.update(&self.msx.cpu);
+            let command = tui.read_command(&self.debug_view())?;
+
+            let (result, text) = self.capture_command(&command)?;
+            tui.print(&command, &text);
+            if !result? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Runs a command with what it prints to stdout captured, for the
+    /// TUI console and remote clients.
+    fn capture_command(&mut self, command: &str) -> anyhow::Result<(anyhow::Result<bool>, String)> {
+        let mut output = BufferRedirect::stdout()?;
+        let result = self.handle_command(command);
+        io::stdout().flush()?;
+        let mut text = String::new();
+        output.read_to_string(&mut text)?;
+        Ok((result, text))
+    }
+
+    /// Runs a remote request and answers it. Returns whether the emulation
+    /// stays stopped, like `handle_command`.
+    fn remote_command(&mut self, request: Request) -> anyhow::Result<bool> {
+        let (result, output) = match CommandLine::parse(&request.command) {
+            Ok(_) => self.capture_command(&request.command)?,
+            Err(e) => (Err(e), String::new()),
+        };
+        let response = Response {
+            id: request.id,
+            ok: result.is_ok(),
+            output,
+            error: result.as_ref().err().map(|e| e.to_string()),
+        };
+        if let Some(remote) = &mut self.remote {
+            remote.send(&response)?;
+        }
+        Ok(result.unwrap_or(true))
+    }
+
+    /// Handles the requests that arrived while running. Returns whether a
+    /// client asked to pause.
+    fn poll_remote(&mut self) -> anyhow::Result<bool> {
+        let mut pause = false;
+        while let Some(remote) = &mut self.remote {
+            let Some(request) = remote.next_request(false)? else {
+                break;
+            };
+            if request.command.trim() == "pause" {
+                remote.send(&Response::ok(request.id))?;
+                pause = true;
+            } else if !self.remote_command(request)? {
+                // continuing while running changes nothing, quitting stops
+                pause = pause || !self.running;
+            }
+        }
+        Ok(pause)
+    }
+
+    /// Takes commands from the remote clients while stopped, instead of the
+    /// prompt.
+    fn serve_remote(&mut self) -> anyhow::Result<()> {
+        let stopped = Event::Stopped {
+            pc: self.msx.pc(),
+            cycles: self.cycles,
+        };
+        if let Some(remote) = &mut self.remote {
+            remote.event(&stopped)?;
+        }
+
+        loop {
+            self.last_stop = Some(self.msx.report_state()?);
+            let Some(remote) = &mut self.remote else {
+                return Ok(());
+            };
+            let Some(request) = remote.next_request(true)? else {
+                continue;
+            };
+            if request.command.trim() == "pause" {
+                remote.send(&Response::ok(request.id))?;
+                continue;
+            }
+
+            if !self.remote_command(request)? {
+                if self.running {
+                    if let Some(remote) = &mut self.remote {
+                        remote.event(&Event::Running)?;
+                    }
+                }
+                return Ok(());
+            }
+        }
+    }
+
+    /// Waits for the debugger to connect and set its breakpoints before
+    /// running.
+    fn start_dap(&mut self) -> anyhow::Result<()> {
+        loop {
+            let Some(dap) = &mut self.dap_server else {
+                return Ok(());
+            };
+            let Some(request) = dap.next_request(true)? else {
+                continue;
+            };
+            let configured = request.command == "configurationDone";
+            self.dap_command(request)?;
+            if configured || !self.running {
+                break;
+            }
+        }
+
+        if self.running
+            && self
+                .dap_server
+                .as_ref()
+                .is_some_and(|dap| dap.stop_on_entry)
+        {
+            self.serve_dap("entry")?;
+        }
+        Ok(())
+    }
+
+    /// Runs a debugger request and answers it.
+    fn dap_command(&mut self, request: dap::Request) -> anyhow::Result<DapFlow> {
+        let (body, flow) = match self.dap_request(&request) {
+            Ok((body, flow)) => (Ok(body), flow),
+            Err(e) => (Err(e), DapFlow::Stay),
+        };
+
+        if let Some(dap) = &mut self.dap_server {
+            dap.respond(&request, &body);
+            match request.command.as_str() {
+                "initialize" => dap.event("initialized", json!({})),
+                "disconnect" => dap.close(),
+                _ => {}
+            }
+        }
+        Ok(flow)
+    }
+
+    fn dap_request(&mut self, request: &dap::Request) -> anyhow::Result<(Value, DapFlow)> {
+        let none = json!({});
+        // steps go by source line when the PC is at one
+        let line = match request.argument::<String>("granularity").as_str() {
+            "instruction" => None,
+            _ => self.source_map.location(self.msx.pc()),
+        };
+        let result = match request.command.as_str() {
+            "initialize" => (dap::capabilities(), DapFlow::Stay),
+            "launch" | "attach" => {
+                if let Some(dap) = &mut self.dap_server {
+                    dap.launched = request.command == "launch";
+                    dap.stop_on_entry = request.argument("stopOnEntry");
+                }
+                (none, DapFlow::Stay)
+            }
+            // a debugger connecting while stopped learns it here
+            "configurationDone" => (none, DapFlow::Stopped("pause")),
+            "threads" => (
+                json!({ "threads": [{ "id": dap::THREAD_ID, "name": "Z80" }] }),
+                DapFlow::Stay,
+            ),
+            "setBreakpoints" => {
+                let source: Value = request.argument("source");
+                let path = source
+                    .get("path")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default();
+                let requested: Vec<Value> = request.argument("breakpoints");
+                let file = self.source_map.find_file(path);
+                let lines: Vec<anyhow::Result<(SourceLine, u16)>> = requested
+                    .iter()
+                    .map(|bp| {
+                        let file = file.ok_or_else(|| anyhow!("No listing has this file"))?;
+                        let line = bp.get("line").and_then(Value::as_u64).unwrap_or_default();
+                        let at = SourceLine {
+                            file,
+                            line: line as usize,
+                        };
+                        self.source_map
+                            .code_at_or_after(at)
+                            .ok_or_else(|| anyhow!("No code at or after this line"))
+                    })
+                    .collect();
+
+                let addresses = lines
+                    .iter()
+                    .map(|line| match line {
+                        Ok((_, address)) => Ok(*address),
+                        Err(e) => Err(anyhow!("{}", e)),
+                    })
+                    .collect();
+                let previous = match &mut self.dap_server {
+                    Some(dap) => dap.source_breakpoints.remove(path).unwrap_or_default(),
+                    None => Vec::new(),
+                };
+                let (ids, mut breakpoints) =
+                    self.set_dap_breakpoints(previous, &requested, addresses);
+                // where the breakpoints went, when not on the lines asked for
+                for (bp, line) in breakpoints.iter_mut().zip(&lines) {
+                    if let Ok((at, _)) = line {
+                        bp["line"] = json!(at.line);
+                    }
+                }
+                if let Some(dap) = &mut self.dap_server {
+                    dap.source_breakpoints.insert(path.to_string(), ids);
+                }
+                (json!({ "breakpoints": breakpoints }), DapFlow::Stay)
+            }
+            "setFunctionBreakpoints" => {
+                let requested: Vec<Value> = request.argument("breakpoints");
+                let addresses = requested
+                    .iter()
+                    .map(|bp| {
+                        let name = bp.get("name").and_then(Value::as_str).unwrap_or_default();
+                        self.symbols
+                            .resolve(name.trim())
+                            .ok_or_else(|| anyhow!("Unknown label {}", name))
+                    })
+                    .collect();
+                let previous = match &mut self.dap_server {
+                    Some(dap) => std::mem::take(&mut dap.function_breakpoints),
+                    None => Vec::new(),
+                };
+                let (ids, breakpoints) = self.set_dap_breakpoints(previous, &requested, addresses);
+                if let Some(dap) = &mut self.dap_server {
+                    dap.function_breakpoints = ids;
+                }
+                (json!({ "breakpoints": breakpoints }), DapFlow::Stay)
+            }
+            "setInstructionBreakpoints" => {
+                let requested: Vec<Value> = request.argument("breakpoints");
+                let addresses = requested
+                    .iter()
+                    .map(|bp| {
+                        let reference = bp
+                            .get("instructionReference")
+                            .and_then(Value::as_str)
+                            .unwrap_or_default();
+                        let offset = bp.get("offset").and_then(Value::as_i64).unwrap_or(0);
+                        let address = self.dap_address(reference)?;
+                        Ok(address.wrapping_add(offset as u16))
+                    })
+                    .collect();
+                let previous = match &mut self.dap_server {
+                    Some(dap) => std::mem::take(&mut dap.instruction_breakpoints),
+                    None => Vec::new(),
+                };
+                let (ids, breakpoints) = self.set_dap_breakpoints(previous, &requested, addresses);
+                if let Some(dap) = &mut self.dap_server {
+                    dap.instruction_breakpoints = ids;
+                }
+                (json!({ "breakpoints": breakpoints }), DapFlow::Stay)
+            }
+            "continue" => (json!({ "allThreadsContinued": true }), DapFlow::Resume),
+            "pause" => (none, DapFlow::Stopped("pause")),
+            "next" | "stepIn" if line.is_some() => {
+                if let Some(from) = line {
+                    let into = request.command == "stepIn";
+                    self.debugger.stepping.step_line(&self.msx, from, into);
+                }
+                (none, DapFlow::Resume)
+            }
+            "next" if self.debugger.stepping.step_over(&self.msx) => (none, DapFlow::Resume),
+            "next" | "stepIn" => {
+                self.step()?;
+                (none, DapFlow::Stopped("step"))
+            }
+            "stepOut" => {
+                self.debugger.stepping.step_out(&self.msx);
+                (none, DapFlow::Resume)
+            }
+            "stackTrace" => {
+                let pc = self.msx.pc();
+                let mut frame = json!({
+                    "id": 0,
+                    "name": self.symbols.describe(pc),
+                    "line": 0,
+                    "column": 0,
+                    "instructionPointerReference": dap::reference(pc),
+                });
+                if let Some(at) = self.source_map.location(pc) {
+                    frame["source"] = self.dap_source(at.file);
+                    frame["line"] = json!(at.line);
+                    frame["column"] = json!(1);
+                }
+                (
+                    json!({ "stackFrames": [frame], "totalFrames": 1 }),
+                    DapFlow::Stay,
+                )
+            }
+            "scopes" => {
+                let scope = |name, reference| json!({ "name": name, "variablesReference": reference, "expensive": false });
+                let scopes = [
+                    scope("Registers", dap::REGISTERS),
+                    scope("Flags", dap::FLAGS),
+                    scope("Watch", dap::WATCHES),
+                ];
+                (json!({ "scopes": scopes }), DapFlow::Stay)
+            }
+            "variables" => {
+                let variables = self.dap_variables(request.argument("variablesReference"));
+                (json!({ "variables": variables }), DapFlow::Stay)
+            }
+            "evaluate" => {
+                let expression: String = request.argument("expression");
+                let context: String = request.argument("context");
+                if context == "repl" {
+                    // the debug console takes the prompt commands
+                    let (result, output) = self.capture_command(&expression)?;
+                    let flow = match result? {
+                        true => DapFlow::Stay,
+                        false => DapFlow::Resume,
+                    };
+                    let body = json!({ "result": output.trim_end(), "variablesReference": 0 });
+                    (body, flow)
+                } else {
+                    (self.dap_evaluate(&expression)?, DapFlow::Stay)
+                }
+            }
+            "readMemory" => {
+                let reference: String = request.argument("memoryReference");
+                let offset: i64 = request.argument("offset");
+                let address = self.dap_address(&reference)?.wrapping_add(offset as u16);
+                let count = request.argument::<usize>("count").min(0x10000);
+                let data: Vec<u8> = (0..count)
+                    .map(|n| self.msx.cpu.read_byte(address.wrapping_add(n as u16)))
+                    .collect();
+                let body =
+                    json!({ "address": dap::reference(address), "data": BASE64.encode(data) });
+                (body, DapFlow::Stay)
+            }
+            "disassemble" => {
+                let reference: String = request.argument("memoryReference");
+                let offset: i64 = request.argument("offset");
+                let address = self.dap_address(&reference)?.wrapping_add(offset as u16);
+                let instructions = self.dap_disassemble(
+                    address,
+                    request.argument("instructionOffset"),
+                    request.argument("instructionCount"),
+                );
+                (json!({ "instructions": instructions }), DapFlow::Stay)
+            }
+            "disconnect" => {
+                let dap = self
+                    .dap_server
+                    .as_mut()
+                    .expect("DAP request without server");
+                let terminate = request
+                    .argument::<Option<bool>>("terminateDebuggee")
+                    .unwrap_or(dap.launched);
+                let set: Vec<usize> = std::mem::take(&mut dap.function_breakpoints)
+                    .into_iter()
+                    .chain(std::mem::take(&mut dap.instruction_breakpoints))
+                    .chain(
+                        std::mem::take(&mut dap.source_breakpoints)
+                            .into_values()
+                            .flatten(),
+                    )
+                    .collect();
+                for id in set {
+                    // may have been deleted at the prompt already
+                    let _ = self.debugger.breakpoints.remove(id);
+                }
+                if terminate {
+                    self.running = false;
+                }
+                (none, DapFlow::Resume)
+            }
+            "terminate" => {
+                self.running = false;
+                (none, DapFlow::Resume)
+            }
+            command => bail!("Unsupported request {}", command),
+        };
+        Ok(result)
+    }
+
+    /// Replaces the breakpoints a debugger set, returning the ids of the new
+    /// ones and how each request went.
+    fn set_dap_breakpoints(
+        &mut self,
+        previous: Vec<usize>,
+        requested: &[Value],
+        addresses: Vec<anyhow::Result<u16>>,
+    ) -> (Vec<usize>, Vec<Value>) {
+        for id in previous {
+            let _ = self.debugger.breakpoints.remove(id);
+        }
+
+        let mut ids = Vec::new();
+        let mut breakpoints = Vec::new();
+        for (bp, address) in requested.iter().zip(addresses) {
+            match self.add_dap_breakpoint(bp, address) {
+                Ok((id, address)) => {
+                    ids.push(id);
+                    breakpoints.push(json!({
+                        "id": id,
+                        "verified": true,
+                        "instructionReference": dap::reference(address),
+                    }));
+                }
+                Err(e) => breakpoints.push(json!({ "verified": false, "message": e.to_string() })),
+            }
+        }
+        (ids, breakpoints)
+    }
+
+    fn add_dap_breakpoint(
+        &mut self,
+        bp: &Value,
+        address: anyhow::Result<u16>,
+    ) -> anyhow::Result<(usize, u16)> {
+        let address = address?;
+        let text = |name| {
+            bp.get(name)
+                .and_then(Value::as_str)
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+        };
+        let condition = text("condition").map(str::parse).transpose()?;
+        // breaking on the nth hit ignores the ones before
+        let ignore_count = match text("hitCondition") {
+            Some(count) => count
+                .parse::<u64>()
+                .map_err(|_| anyhow!("Invalid hit count {}", count))?
+                .saturating_sub(1),
+            None => 0,
+        };
+
+        let id = self.debugger.breakpoints.add(address, condition);
+        self.debugger.breakpoints.get_mut(id)?.ignore_count = ignore_count;
+        Ok((id, address))
+    }
+
+    fn dap_variables(&self, reference: u64) -> Vec<Value> {
+        let cpu = &self.msx.cpu;
+        match reference {
+            dap::REGISTERS => self
+                .registers()
+                .into_iter()
+                .map(|register| match register.wide {
+                    true => json!({
+                        "name": register.name,
+                        "value": format!("#{:04X}", register.value),
+                        "variablesReference": 0,
+                        "memoryReference": dap::reference(register.value),
+                    }),
+                    false => json!({
+                        "name": register.name,
+                        "value": format!("#{:02X}", register.value),
+                        "variablesReference": 0,
+                    }),
+                })
+                .collect(),
+            dap::FLAGS => ["S", "Z", "5", "H", "3", "P/V", "N", "C"]
+                .iter()
+                .zip(flag_string(cpu.f).chars())
+                .map(|(name, flag)| {
+                    json!({
+                        "name": name,
+                        "value": if flag == '-' { "0" } else { "1" },
+                        "variablesReference": 0,
+                    })
+                })
+                .collect(),
+            dap::WATCHES => self
+                .debugger
+                .watches
+                .watches()
+                .iter()
+                .map(|watch| {
+                    let expression = watch.expression;
+                    let name = match expression {
+                        WatchExpr::Address(address) => self.symbols.describe(address),
+                        _ => expression.to_string(),
+                    };
+                    let mut variable = json!({
+                        "name": name,
+                        "value": expression.format(&expression.read(cpu)).join(" "),
+                        "variablesReference": 0,
+                    });
+                    if let Some(address) = expression.address() {
+                        variable["memoryReference"] = dap::reference(address).into();
+                    }
+                    variable
+                })
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    // the value of a register, or of the memory at a label or address, for
+    // watch expressions and hovers
+    fn dap_evaluate(&self, expression: &str) -> anyhow::Result<Value> {
+        let expression = expression.trim();
+        let cpu = &self.msx.cpu;
+        if let Ok(operand) = expression.to_lowercase().parse::<Operand>() {
+            let value = operand.value(cpu);
+            let result = match operand.is_word() {
+                true => format!("#{:04X}", value),
+                false => format!("#{:02X}", value),
+            };
+            return Ok(json!({ "result": result, "variablesReference": 0 }));
+        }
+
+        let address = self.dap_address(expression.trim_start_matches('(').trim_end_matches(')'))?;
+        Ok(json!({
+            "result": format!(
+                "#{:02X}, word #{:04X}",
+                cpu.read_byte(address),
+                cpu.read_word(address)
+            ),
+            "variablesReference": 0,
+            "memoryReference": dap::reference(address),
+        }))
+    }
+
+    fn dap_source(&self, file: usize) -> Value {
+        let path = self.source_map.path(file);
+        let name = Path::new(path).file_name().unwrap_or_default();
+        json!({ "name": name.to_string_lossy(), "path": path })
+    }
+
+    fn dap_address(&self, reference: &str) -> anyhow::Result<u16> {
+        self.symbols
+            .resolve(reference)
+            .ok_or_else(|| anyhow!("Unknown label or address {}", reference))
+    }
+
+    // `count` instructions starting `offset` instructions away from the one
+    // at `address`
+    fn dap_disassemble(&self, address: u16, offset: i64, count: usize) -> Vec<Value> {
+        // instructions are 4 bytes at most, so this many bytes back hold at
+        // least as many instructions as asked for before the address
+        let before = (-offset).max(0) as usize;
+        let start = address.wrapping_sub((before * 4) as u16);
+        let entries = self
+            .msx
+            .disassemble(start, before * 4 + offset.max(0) as usize + count);
+        let at = entries
+            .iter()
+            .position(|entry| entry.address.wrapping_sub(start) >= address.wrapping_sub(start))
+            .unwrap_or_default();
+
+        entries
+            .iter()
+            .skip((at as i64 + offset).max(0) as usize)
+            .take(count)
+            .map(|entry| {
+                let mut instruction = json!({
+                    "address": dap::reference(entry.address),
+                    "instructionBytes": entry.data.trim_end(),
+                    "instruction": entry.instruction,
+                });
+                if let Some(label) = self.symbols.label(entry.address) {
+                    instruction["symbol"] = json!(label);
+                }
+                if let Some(at) = self.source_map.location(entry.address) {
+                    instruction["location"] = self.dap_source(at.file);
+                    instruction["line"] = json!(at.line);
+                }
+                instruction
+            })
+            .collect()
+    }
+
+    /// Handles the debugger requests that arrived while running. Returns
+    /// whether the debugger asked to pause.
+    fn poll_dap(&mut self) -> anyhow::Result<bool> {
+        let mut pause = false;
+        while let Some(dap) = &mut self.dap_server {
+            let Some(request) = dap.next_request(false)? else {
+                break;
+            };
+            if request.command == "pause" {
+                dap.respond(&request, &Ok(json!({})));
+                pause = true;
+            } else {
+                self.dap_command(request)?;
+                pause = pause || !self.running;
+            }
+        }
+        Ok(pause)
+    }
+
+    /// Takes requests from the debugger while stopped, instead of the prompt.
+    fn serve_dap(&mut self, reason: &'static str) -> anyhow::Result<()> {
+        // a stop of any kind ends the step in progress
+        self.debugger.stepping.clear();
+        if let Some(dap) = &mut self.dap_server {
+            dap.stopped(reason);
+        }
+
+        loop {
+            self.last_stop = Some(self.msx.report_state()?);
+            let Some(dap) = &mut self.dap_server else {
+                return Ok(());
+            };
+            let Some(request) = dap.next_request(true)? else {
+                continue;
+            };
+
+            match self.dap_command(request)? {
+                DapFlow::Stay => {}
+                DapFlow::Stopped(reason) => {
+                    if let Some(dap) = &mut self.dap_server {
+                        dap.stopped(reason);
+                    }
+                }
+                DapFlow::Resume => {
+                    if self.running {
+                        if let Some(dap) = &mut self.dap_server {
+                            dap.event(
+                                "continued",
+                                json!({ "threadId": dap::THREAD_ID, "allThreadsContinued": true }),
+                            );
+                        }
+                    }
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    fn registers(&self) -> Vec<Register> {
+        let cpu = &self.msx.cpu;
+        let register = |name, value: u16, wide| Register { name, value, wide };
+
+        vec![
+            register("A", cpu.a as u16, false),
+            register("F", cpu.f as u16, false),
+            register("B", cpu.b as u16, false),
+            register("C", cpu.c as u16, false),
+            register("D", cpu.d as u16, false),
+            register("E", cpu.e as u16, false),
+            register("H", cpu.h as u16, false),
+            register("L", cpu.l as u16, false),
+            register("IX", cpu.ix, true),
+            register("IY", cpu.iy, true),
+            register("SP", cpu.sp, true),
+            register("PC", cpu.pc, true),
+        ]
+    }
+
+    fn debug_view(&self) -> DebugView {
+        let cpu = &self.msx.cpu;
+
+        DebugView {
+            cycles: self.cycles,
+            pc: cpu.pc,
+            registers: self.registers(),
+            f: cpu.f,
+            program: self.msx.program_slice(32, 96),
+            breakpoints: self
+                .debugger
+                .breakpoints
+                .iter()
+                .map(|bp| bp.address)
+                .collect(),
+            stack: (0..16u16)
+                .map(|n| {
+                    let address = cpu.sp.wrapping_add(n * 2);
+                    (address, cpu.read_word(address))
+                })
+                .collect(),
+            memory_address: self.memory_view,
+            memory: (0..128u16)
+                .map(|n| cpu.read_byte(self.memory_view.wrapping_add(n)))
+                .collect(),
+            watches: self.debugger.watches.watches().to_vec(),
+        }
+    }
+
+    pub fn handle_command(&mut self, command: &str) -> anyhow::Result<bool> {
+        let line = match CommandLine::parse(command) {
+            Ok(line) => line,
+            Err(e) => {
+                println!("{}\n", e);
+                return Ok(true);
+            }
+        };
+
+        let command = match line.command {
+            // stepping here also runs the hooks, the console and the
+            // comparisons
+            command @ (Command::Step(_) | Command::Frame(_)) => command,
+            command => match self.debugger.execute(&mut self.msx, command) {
+                Ok(output) => {
+                    print!("{}", output);
+                    return Ok(true);
+                }
+                Err(command) => command,
+            },
+        };
+
+        match command {
+            Command::Watch(None) => {
+                self.debugger.watches.update(&self.msx.cpu);
+                for watch in self.debugger.watches.watches() {
+                    let region = watch
+                        .expression
+                        .address()
+                        .and_then(|address| self.regions.find(address));
+                    println!(
+                        "{}",
+                        self.style
+                            .watch(watch, region.map(|region| region.name.as_str()))
+                    );
+                }
+                println!();
+                Ok(true)
+            }
+            Command::View(address) => {
+                self.memory_view = address;
+                let end = address.saturating_add(0x7F);
+                println!(
+                    "{}",
+                    self.style
+                        .memory_hexdump(&self.msx.memory_dump(address, end), &self.regions)
+                );
+                Ok(true)
+            }
+            Command::SysVars(filter) => {
+                let mut found = false;
+                for var in sysvars::matching(filter.as_deref()) {
+                    let value = var.value(&self.msx.cpu);
+                    println!("{}", self.style.system_variable(var, &value));
+                    found = true;
+                }
+                if !found {
+                    println!("No system variable matches {}", filter.unwrap_or_default());
+                }
+                println!();
+                Ok(true)
+            }
+            Command::Region(command) => {
+                let res = match command {
+                    RegionCommand::List => {
+                        self.list_regions();
+                        Ok(())
+                    }
+                    RegionCommand::Add(start, end, name) => self.regions.add(start, end, &name),
+                    RegionCommand::Remove(start) => match self.regions.remove(start).is_empty() {
+                        true => Err(anyhow!("No region starts at {:#06X}", start)),
+                        false => Ok(()),
+                    },
+                    RegionCommand::Load(path) => self
+                        .regions
+                        .load(&path)
+                        .map(|_| println!("{} regions", self.regions.len())),
+                    RegionCommand::Clear => {
+                        self.regions.clear();
+                        Ok(())
+                    }
+                };
+                if let Err(e) = res {
+                    println!("Error: {}", e);
+                }
+                println!();
+                Ok(true)
+            }
+            Command::Quit => {
+                self.running = false;
+                Ok(false)
+            }
+            Command::Step(n) => {
                 for _ in 0..n {
                     self.step()?;
                 }
-                self.dump()?;
+                self.dump(self.json)?;
+                Ok(true)
+            }
+            Command::Frame(n) => {
+                for _ in 0..n {
+                    self.step_frame()?;
+                }
+                self.dump(self.json)?;
+                Ok(true)
+            }
+            Command::Slow(factor) => {
+                self.slow = factor;
+                self.last_frame = Instant::now();
+                match factor {
+                    Some(factor) => println!("Running at 1/{} speed", factor),
+                    None => println!("Running at full speed"),
+                }
+                println!();
                 Ok(true)
             }
             Command::Continue => {
@@ -418,102 +2199,446 @@ impl Runner {
                 self.running = true;
                 Ok(false)
             }
-            Command::Reset => {
-                self.msx.reset();
+            Command::Reset(kind) => {
+                self.msx.reset_with(kind);
+                if let Some(compare_msx) = &mut self.compare_msx {
+                    compare_msx.reset_with(kind);
+                }
+                if let Some(stack_guard) = &mut self.stack_guard {
+                    stack_guard.clear();
+                }
+                self.in_sync = true;
+                Ok(true)
+            }
+            Command::Nmi => {
+                self.msx.nmi();
+                if let Some(compare_msx) = &mut self.compare_msx {
+                    compare_msx.nmi();
+                }
+                Ok(true)
+            }
+            Command::Insert(slot, path) => {
+                match RomSlot::load(path, 0x0000, 0x10000) {
+                    Ok(rom) => {
+                        if let Some(info) = self.rom_db.find_by_crc32(rom.crc32) {
+                            println!("Loaded: {}", info);
+                        }
+                        if let Err(e) = self.insert_cart(slot, SlotType::Rom(rom)) {
+                            println!("Error: {}", e);
+                        }
+                    }
+                    Err(e) => println!("Error: {}", e),
+                }
+                println!();
+                Ok(true)
+            }
+            Command::Tape(TapeCommand::Insert(path)) => {
+                match Tape::load(&path, None) {
+                    Ok(tape) => {
+                        self.msx.insert_tape(tape);
+                        println!("{}", self.debugger.tape_status(&self.msx).trim_end());
+                    }
+                    Err(e) => println!("Error: {}", e),
+                }
+                println!();
+                Ok(true)
+            }
+            Command::Tape(TapeCommand::Save(path)) => {
+                match self.msx.tape_recording() {
+                    Some(cas) => match cas.save(&path) {
+                        Ok(()) => println!("Saved {} to {}", cas, path.display()),
+                        Err(e) => println!("Error: {}", e),
+                    },
+                    None => println!("Not recording, start with tape record"),
+                }
+                println!();
+                Ok(true)
+            }
+            Command::Eject(slot) => {
+                if let Err(e) = self.insert_cart(slot, SlotType::Empty) {
+                    println!("Error: {}", e);
+                }
+                println!();
                 Ok(true)
             }
-            Command::Dump => {
-                self.dump()?;
+            Command::Dump(json) => {
+                self.dump(json || self.json)?;
                 Ok(true)
             }
-            Command::List => {
-                self.list()?;
+            Command::List(asm) => {
+                self.list(asm)?;
                 Ok(true)
             }
             Command::Log => {
                 self.log()?;
                 Ok(true)
             }
-            Command::Status => {
+            Command::LogLevel(device, level) => {
+                let Some(log_filter) = &mut self.log_filter else {
+                    println!("The logging can't be changed here\n");
+                    return Ok(true);
+                };
+                let res = match &level {
+                    Some(level) => log_filter.set_level(&device, level),
+                    None => Ok(()),
+                };
+                match res.and_then(|_| log_filter.level(&device)) {
+                    Ok(level) => println!("{} logs at {}\n", device, level),
+                    Err(e) => println!("Error: {}\n", e),
+                }
+                Ok(true)
+            }
+            Command::StackGuard(enabled) => {
+                // return addresses pushed while disabled aren't known
+                self.stack_guard = enabled.then(StackGuard::new);
+                println!("Stack guard {}", if enabled { "on" } else { "off" });
+                println!();
+                Ok(true)
+            }
+            Command::HookGuard(enabled) => {
+                self.hook_guard = enabled.then(HookGuard::new);
+                println!("Hook guard {}", if enabled { "on" } else { "off" });
+                println!();
+                Ok(true)
+            }
+            Command::Hooks(Some(enabled)) => {
+                self.hook_tracer = enabled.then(HookTracer::new);
+                println!("Hook tracing {}", if enabled { "on" } else { "off" });
+                println!();
+                Ok(true)
+            }
+            Command::Hooks(None) => {
+                self.list_hooks();
+                Ok(true)
+            }
+            Command::Console(enabled) => {
+                self.echo_console = enabled;
+                self.update_console();
+                println!("Console output {}", if enabled { "on" } else { "off" });
+                println!();
+                Ok(true)
+            }
+            Command::BreakText(text) => {
+                match text.as_str() {
+                    "" if self.text_breaks.is_empty() => println!("No text breaks"),
+                    "" => {
+                        for text in &self.text_breaks {
+                            println!("{:?}", text);
+                        }
+                    }
+                    "clear" => {
+                        self.text_breaks.clear();
+                        println!("Text breaks removed");
+                    }
+                    _ => {
+                        println!("Breaking when {:?} is printed", text);
+                        self.text_breaks.push(text);
+                    }
+                }
+                self.update_console();
+                println!();
+                Ok(true)
+            }
+            Command::VdpTiming(Some(enabled)) => {
+                // forget the accesses made while it was off
+                self.msx.accessed_vram();
+                self.vdp_timing = enabled.then(VdpTimingChecker::new);
+                println!("VDP timing check {}", if enabled { "on" } else { "off" });
+                println!();
+                Ok(true)
+            }
+            Command::VdpTiming(None) => {
+                self.list_vdp_timing_violations();
+                Ok(true)
+            }
+            Command::SlotCheck(Some(enabled)) => {
+                self.slot_check = enabled.then(SlotChecker::new);
+                println!("Slot check {}", if enabled { "on" } else { "off" });
+                println!();
+                Ok(true)
+            }
+            Command::SlotTrace(enabled, break_on_pc_page) => {
+                self.slot_trace = enabled.then(|| SlotTracer::new(break_on_pc_page));
+                let state = match (enabled, break_on_pc_page) {
+                    (false, _) => "off",
+                    (true, false) => "on",
+                    (true, true) => "on, breaking on switches of the page of the PC",
+                };
+                println!("Slot tracing {}", state);
+                println!();
+                Ok(true)
+            }
+            Command::SlotCheck(None) => {
+                self.list_slot_conflicts();
+                Ok(true)
+            }
+            Command::VdpCaptureFrame => {
+                let mut capture = FrameCapture::new(&self.msx);
+                while !capture.record(&self.msx) {
+                    self.step()?;
+                }
+                self.list_register_changes(&capture);
+                Ok(true)
+            }
+            Command::VdpMode(mode) => {
+                let mut bus = self.msx.bus.write().unwrap();
+                if let Some(mode) = mode {
+                    bus.vdp.force_mode(mode);
+                }
+                let shown = bus.vdp.display_mode.name();
+                match bus.vdp.forced_mode() {
+                    Some(_) => println!(
+                        "Display mode {}, forced while the registers select {}",
+                        shown,
+                        bus.vdp.register_mode().name()
+                    ),
+                    None => println!("Display mode {}, from the registers", shown),
+                }
+                println!();
+                Ok(true)
+            }
+            Command::Screen(None) => {
+                let screen = self.msx.screen_text();
+                if screen.is_empty() {
+                    println!("No text, the screen is in a graphic mode");
+                }
+                for line in screen {
+                    println!("{}", line);
+                }
+                println!();
+                Ok(true)
+            }
+            Command::Screen(Some(follow)) => {
+                println!("Screen following {}", if follow { "on" } else { "off" });
+                // starts over, printing the whole screen
+                self.screen_reader = follow.then(ScreenReader::new);
+                if let Some(screen_reader) = &mut self.screen_reader {
+                    for line in screen_reader.update(self.msx.screen_text()) {
+                        println!("{}", line);
+                    }
+                }
+                println!();
+                Ok(true)
+            }
+            Command::History(n) => {
+                for entry in self.history.last(n) {
+                    println!("{}", entry);
+                }
+                println!();
+                Ok(true)
+            }
+            Command::Status(json) => {
+                if json || self.json {
+                    report::print(&self.status_report())?;
+                    return Ok(true);
+                }
+
                 println!("Cycles: {}", self.cycles);
-                println!("Breakpoints: {:?}", self.breakpoints);
+                println!(
+                    "VDP: {} Hz, interrupts {}",
+                    vdp_timing::frequency(self.msx.pal()),
+                    self.msx.interrupt_stats()
+                );
+                self.list_breakpoints();
+                if let Some(link_mode) = &self.link_mode {
+                    println!(
+                        "Link: {:?} ({})",
+                        link_mode,
+                        if self.link.is_some() {
+                            "connected"
+                        } else {
+                            "disconnected"
+                        }
+                    );
+                }
                 println!(
                     "Primary Slot Config: {:08b}",
                     self.msx.primary_slot_config()
                 );
                 for (n, slot) in self.slots.iter().enumerate() {
                     println!("Slot #{}: {}", n, slot);
+                    if let SlotType::Rom(rom) = slot {
+                        if let Some(info) = self.rom_db.find_by_crc32(rom.crc32) {
+                            println!(
+                                "         {} - machine: {} mapper: {}",
+                                info.title,
+                                info.machine.as_deref().unwrap_or("any"),
+                                info.mapper.as_deref().unwrap_or("none")
+                            );
+                        }
+                    }
+                }
+                if let Some(compare_slots) = &self.compare_slots {
+                    println!("A/B in sync: {}", self.in_sync);
+                    for (n, slot) in compare_slots.iter().enumerate() {
+                        println!("B Slot #{}: {}", n, slot);
+                    }
+                }
+                self.msx
+                    .memory_segments()
+                    .iter()
+                    .enumerate()
+                    .for_each(|(n, segment)| {
+                        println!("Segment {}: {}", n, segment);
+                    });
+                self.print_memory_layout();
+                println!();
+                Ok(true)
+            }
+            Command::Set(target) => {
+                let value = line
+                    .args
+                    .get(0)
+                    .ok_or_else(|| anyhow!("Missing set value"))?;
+
+                match target {
+                    SetTarget::A => self.msx.set_a(parse_as_u8(value)?),
+                    SetTarget::B => self.msx.set_b(parse_as_u8(value)?),
+                    SetTarget::C => self.msx.set_c(parse_as_u8(value)?),
+                    SetTarget::HL => self.msx.set_hl(parse_as_u16(value)?),
+                    SetTarget::HLAddress => self.msx.set_hl_address(parse_as_u16(value)?),
+                }
+
+                Ok(true)
+            }
+            Command::Until(addr) => {
+                self.debugger.stepping.run_until(addr);
+                self.max_cycles = None;
+                self.running = true;
+                Ok(false)
+            }
+            Command::Send(args) => {
+                if let Some(client) = &mut self.client {
+                    match client.send(&args.join(" ")) {
+                        Ok(_) => {}
+                        Err(e) => println!("Error: {}", e),
+                    }
+                }
+
+                Ok(true)
+            }
+            Command::LoadBin(path, run) => {
+                if let Err(e) = self.load_bin(&path, run) {
+                    println!("Error: {}", e);
+                }
+                println!();
+                Ok(true)
+            }
+            Command::Type(text) => {
+                if text.is_empty() {
+                    self.autotyper.clear(&mut self.msx);
+                } else if let Err(e) = self.autotyper.queue(&text) {
+                    println!("Error: {}", e);
+                }
+                println!("Keys pending: {}", self.autotyper.pending());
+                println!();
+                Ok(true)
+            }
+            Command::Input(profile) => {
+                if let Some(profile) = profile {
+                    // the keys held and pending were meant for the other profile
+                    self.autotyper.clear(&mut self.msx);
+                    self.autotyper.profile = profile;
                 }
-                self.msx
-                    .memory_segments()
-                    .iter()
-                    .enumerate()
-                    .for_each(|(n, segment)| {
-                        println!("Segment {}: {}", n, segment);
-                    });
-                self.msx.print_memory_page_info();
+                println!(
+                    "Cursor keys and space drive the {}",
+                    match self.autotyper.profile {
+                        InputProfile::Keyboard => "keyboard",
+                        InputProfile::Joystick => "joystick in port 1",
+                    }
+                );
                 println!();
                 Ok(true)
             }
-            Command::MemSet(addr, value) => {
-                self.msx.set_memory(addr, value);
+            Command::InputLog(InputLogCommand::Save(path)) => {
+                if let Err(e) = self.save_input_log(&path) {
+                    println!("Error: {}", e);
+                }
+                println!();
                 Ok(true)
             }
-            Command::MemGet(addr) => {
-                let value = self.msx.get_memory(addr);
-                println!("{:#06X}: {:#04X}", addr, value);
+            Command::DisasmExport(start, end, path) => {
+                if let Err(e) = self.export_disassembly(start, end, &path) {
+                    println!("Error: {}", e);
+                }
+                println!();
                 Ok(true)
             }
-            Command::Set(target) => {
-                let value = line
-                    .args
-                    .get(0)
-                    .ok_or_else(|| anyhow!("Missing set value"))?;
-
-                match target {
-                    SetTarget::A => self.msx.set_a(parse_as_u8(value)?),
-                    SetTarget::B => self.msx.set_b(parse_as_u8(value)?),
-                    SetTarget::C => self.msx.set_c(parse_as_u8(value)?),
-                    SetTarget::HL => self.msx.set_hl(parse_as_u16(value)?),
-                    SetTarget::HLAddress => self.msx.set_hl_address(parse_as_u16(value)?),
+            Command::SaveState(path) => {
+                if let Err(e) = self.save_state(&path) {
+                    println!("Error: {}", e);
                 }
-
+                println!();
                 Ok(true)
             }
-            Command::AddBreakpoint(addr) => {
-                self.breakpoints.push(addr);
+            Command::LoadState(path) => {
+                if let Err(e) = self.load_state(&path) {
+                    println!("Error: {}", e);
+                }
+                println!();
                 Ok(true)
             }
-            Command::RemoveBreakpoint(addr) => {
-                self.breakpoints.retain(|&a| a != addr);
+            Command::RomInfo(target) => {
+                if let Err(e) = self.rom_info(target.as_deref()) {
+                    println!("Error: {}", e);
+                }
+                println!();
                 Ok(true)
             }
-            Command::Send(args) => {
-                if let Some(client) = &mut self.client {
-                    match client.send(&args.join(" ")) {
-                        Ok(_) => {}
+            Command::Palette(palette) => {
+                if let Some(palette) = palette {
+                    match palette_from(&palette) {
+                        Ok(palette) => self.palette = palette,
                         Err(e) => println!("Error: {}", e),
                     }
                 }
-
+                print!("{}", self.palette);
+                println!();
+                Ok(true)
+            }
+            Command::LoadBasic(path) => {
+                if let Err(e) = self.load_basic(&path) {
+                    println!("Error: {}", e);
+                }
+                println!();
                 Ok(true)
             }
             Command::VramDump(target) => {
+                if let Some(compare_msx) = &self.compare_msx {
+                    match target {
+                        DumpTarget::Compare => {
+                            println!("VRAM dump (B)");
+                            println!("{}", self.style.hexdump(&compare_msx.vram_dump()));
+                        }
+                        DumpTarget::Diff => {
+                            let diff = self.diff(self.msx.vram_dump(), compare_msx.vram_dump());
+                            println!("VRAM diff (A/B)");
+                            println!("{}", diff);
+                        }
+                        _ => {
+                            println!("VRAM dump");
+                            println!("{}", self.style.hexdump(&self.msx.vram_dump()));
+                        }
+                    }
+                    println!();
+                    return Ok(true);
+                }
+
                 if self.client.is_none() {
                     println!("VRAM dump");
-                    println!("{}", self.msx.vram_dump());
+                    println!("{}", self.style.hexdump(&self.msx.vram_dump()));
                     return Ok(true);
                 }
 
                 match target {
                     DumpTarget::Msx => {
                         println!("VRAM dump");
-                        println!("{}", self.msx.vram_dump());
+                        println!("{}", self.style.hexdump(&self.msx.vram_dump()));
                     }
-                    DumpTarget::OpenMsx => {
+                    DumpTarget::OpenMsx | DumpTarget::Compare => {
                         if let Some(client) = &mut self.client {
                             println!("VRAM dump");
-                            println!("{}", client.vram_dump()?);
+                            println!("{}", self.style.hexdump(&client.vram_dump()?));
                         }
                     }
                     DumpTarget::Diff => {
@@ -531,25 +2656,79 @@ impl Runner {
                 println!();
                 Ok(true)
             }
-            Command::MemDump(target) => {
+            Command::MemDump(target, json) => {
+                if json || self.json {
+                    let report = self.memory_report(target)?;
+                    report::print(&report)?;
+                    return Ok(true);
+                }
+
                 let start = 0u16;
                 let end = (self.msx.mem_size() - 1) as u16;
 
+                if let Some(compare_msx) = &mut self.compare_msx {
+                    match target {
+                        DumpTarget::Compare => {
+                            println!("Memory dump (B) from {:#06X} to {:#06X}", start, end);
+                            println!(
+                                "{}",
+                                self.style.memory_hexdump(
+                                    &compare_msx.memory_dump(start, end),
+                                    &self.regions
+                                )
+                            );
+                        }
+                        DumpTarget::Diff => {
+                            let msx_dump = self.msx.memory_dump(start, end);
+                            let compare_dump = compare_msx.memory_dump(start, end);
+
+                            println!("Memory diff (A/B) from {:#06X} to {:#06X}", start, end);
+                            println!("{}", self.diff(msx_dump, compare_dump));
+                        }
+                        _ => {
+                            println!("Memory dump from {:#06X} to {:#06X}", start, end);
+                            println!(
+                                "{}",
+                                self.style.memory_hexdump(
+                                    &self.msx.memory_dump(start, end),
+                                    &self.regions
+                                )
+                            );
+                        }
+                    }
+                    println!();
+                    return Ok(true);
+                }
+
                 if self.client.is_none() {
                     println!("Memory dump from {:#06X} to {:#06X}", start, end);
-                    println!("{}", self.msx.memory_dump(start, end));
+                    println!(
+                        "{}",
+                        self.style
+                            .memory_hexdump(&self.msx.memory_dump(start, end), &self.regions)
+                    );
                     return Ok(true);
                 }
 
                 match target {
                     DumpTarget::Msx => {
                         println!("Memory dump from {:#06X} to {:#06X}", start, end);
-                        println!("{}", self.msx.memory_dump(start, end));
+                        println!(
+                            "{}",
+                            self.style
+                                .memory_hexdump(&self.msx.memory_dump(start, end), &self.regions)
+                        );
                     }
-                    DumpTarget::OpenMsx => {
+                    DumpTarget::OpenMsx | DumpTarget::Compare => {
                         if let Some(client) = &mut self.client {
                             println!("Memory dump from {:#06X} to {:#06X}", start, end);
-                            println!("{}", client.memory_dump(start, end)?);
+                            println!(
+                                "{}",
+                                self.style.memory_hexdump(
+                                    &client.memory_dump(start, end)?,
+                                    &self.regions
+                                )
+                            );
                         }
                     }
                     DumpTarget::Diff => {
@@ -568,6 +2747,8 @@ impl Runner {
 
                 Ok(true)
             }
+            // the debugger ran the rest
+            _ => Ok(true),
         }
     }
 
@@ -591,8 +2772,29 @@ impl Runner {
             res.push_str(&format!("{}{}", sign, change));
         }
 
-        res
+        self.style.diff(&res)
+    }
+}
+
+// wall clock time of a frame at full speed, a 50th or a 60th of a second
+fn frame_duration(pal: bool) -> Duration {
+    Duration::from_secs(1) / vdp_timing::frequency(pal)
+}
+
+/// A palette preset by name, or a palette file.
+fn palette_from(name: &str) -> anyhow::Result<Palette> {
+    if let Some(palette) = Palette::preset(name) {
+        return Ok(palette);
     }
+    if !Path::new(name).exists() {
+        let presets: Vec<&str> = Palette::PRESETS.iter().map(|(name, _)| *name).collect();
+        bail!(
+            "Unknown palette {}, use one of {} or a palette file",
+            name,
+            presets.join(", ")
+        );
+    }
+    Palette::load(name)
 }
 
 fn parse_as_u8(s: &str) -> Result<u8, ParseIntError> {
@@ -620,6 +2822,10 @@ pub struct RunnerBuilder {
     breakpoints: Vec<u16>,
     max_cycles: Option<u64>,
     open_msx: bool,
+    resync: bool,
+    json: bool,
+    color: bool,
+    tui: bool,
     break_on_mismatch: bool,
     break_on_mem_mismatch: bool,
     break_on_ppi_write: bool,
@@ -627,6 +2833,45 @@ pub struct RunnerBuilder {
     log_on_mismatch: bool,
     track_flags: bool,
     report_every: Option<u64>,
+    compare_rom: Option<RomSlot>,
+    link_mode: Option<LinkMode>,
+    listen: Option<String>,
+    dap: Option<String>,
+    symbols: Symbols,
+    source_map: SourceMap,
+    regions: Regions,
+    rom_db: RomDatabase,
+    bin_file: Option<PathBuf>,
+    bin_run: bool,
+    bin_at: u64,
+    bas_file: Option<PathBuf>,
+    bas_at: u64,
+    autotyper: Autotyper,
+    dos_command: Option<DosCommand>,
+    history_size: usize,
+    stack_guard: bool,
+    hook_guard: bool,
+    trace_hooks: bool,
+    console: bool,
+    text_breaks: Vec<String>,
+    follow_screen: bool,
+    vdp_timing: bool,
+    slot_check: bool,
+    trace_slots: bool,
+    break_on_slot_switch: bool,
+    debug_device: bool,
+    palette: Palette,
+    preset: Option<&'static Preset>,
+    tape: Option<Tape>,
+    record_tape: Option<PathBuf>,
+    turbo_io: bool,
+    stock_timing: bool,
+    frame_hashes: Option<PathBuf>,
+    frame_hash_reference: Option<FrameHashes>,
+    // directory and interval of the autosaves
+    autosave: Option<(PathBuf, Duration)>,
+    log_dir: Option<PathBuf>,
+    log_filter: Option<LogFilter>,
 }
 
 impl RunnerBuilder {
@@ -636,6 +2881,15 @@ impl RunnerBuilder {
             breakpoints: Vec::new(),
             max_cycles: None,
             open_msx: false,
+            resync: false,
+            json: false,
+            color: true,
+            tui: false,
+            listen: None,
+            dap: None,
+            symbols: Symbols::default(),
+            source_map: SourceMap::default(),
+            regions: Regions::default(),
             break_on_mismatch: false,
             break_on_mem_mismatch: false,
             break_on_ppi_write: false,
@@ -643,6 +2897,39 @@ impl RunnerBuilder {
             log_on_mismatch: false,
             track_flags: false,
             report_every: None,
+            compare_rom: None,
+            link_mode: None,
+            rom_db: RomDatabase::embedded(),
+            palette: Palette::default(),
+            bin_file: None,
+            bin_run: false,
+            bin_at: 0,
+            bas_file: None,
+            bas_at: 0,
+            autotyper: Autotyper::new(),
+            dos_command: None,
+            history_size: DEFAULT_HISTORY_SIZE,
+            stack_guard: false,
+            hook_guard: false,
+            trace_hooks: false,
+            console: false,
+            text_breaks: Vec::new(),
+            follow_screen: false,
+            vdp_timing: false,
+            slot_check: false,
+            trace_slots: false,
+            break_on_slot_switch: false,
+            debug_device: false,
+            preset: None,
+            tape: None,
+            record_tape: None,
+            turbo_io: false,
+            stock_timing: false,
+            frame_hashes: None,
+            frame_hash_reference: None,
+            autosave: None,
+            log_dir: None,
+            log_filter: None,
         }
     }
 
@@ -661,6 +2948,46 @@ impl RunnerBuilder {
         self
     }
 
+    /// Keeps openMSX at the same PC as the emulator when their steps don't
+    /// line up, so that only real differences are reported as mismatches.
+    pub fn resync(&mut self, resync: bool) -> &mut Self {
+        self.resync = resync;
+        self
+    }
+
+    /// Prints the output of `status`, `dump`, `memdump` and `--report-every`
+    /// as JSON.
+    pub fn json(&mut self, json: bool) -> &mut Self {
+        self.json = json;
+        self
+    }
+
+    /// Colors the prompt output, unless it isn't going to a terminal.
+    pub fn color(&mut self, color: bool) -> &mut Self {
+        self.color = color;
+        self
+    }
+
+    /// Serves the prompt commands to remote debugger clients on an address,
+    /// in place of the prompt.
+    pub fn listen(&mut self, listen: Option<String>) -> &mut Self {
+        self.listen = listen;
+        self
+    }
+
+    /// Serves the Debug Adapter Protocol on an address, waiting for a
+    /// debugger to connect before running.
+    pub fn dap(&mut self, dap: Option<String>) -> &mut Self {
+        self.dap = dap;
+        self
+    }
+
+    /// Stops into the full screen debugger instead of the line prompt.
+    pub fn tui(&mut self, tui: bool) -> &mut Self {
+        self.tui = tui;
+        self
+    }
+
     pub fn break_on_mismatch(&mut self, break_on_mismatch: bool) -> &mut Self {
         self.break_on_mismatch = break_on_mismatch;
         self
@@ -696,19 +3023,84 @@ impl RunnerBuilder {
         self
     }
 
+    /// Puts a RAM cartridge, like `megaram:512`, in the next slot, or leaves
+    /// it empty.
+    pub fn cartridge_slot(&mut self, cartridge: Option<String>) -> anyhow::Result<&mut Self> {
+        match cartridge {
+            Some(cartridge) => self.slots.push(cartridge.parse::<RamCartridge>()?.slot()),
+            None => {
+                self.empty_slot();
+            }
+        }
+        Ok(self)
+    }
+
+    /// Puts a Sunrise IDE interface in the next slot, with the ROM in its
+    /// flash and the disk image as its master device.
+    pub fn ide_slot(
+        &mut self,
+        rom_path: &Path,
+        disk_path: Option<&Path>,
+    ) -> anyhow::Result<&mut Self> {
+        self.slots
+            .push(SlotType::SunriseIde(SunriseIde::load(rom_path, disk_path)?));
+        Ok(self)
+    }
+
     pub fn ram_slot(&mut self, base: u16, size: u32) -> &mut Self {
         self.slots.push(SlotType::Ram(RamSlot::new(base, size)));
         self
     }
 
+    /// Puts the RAM of the preset, or 64K without one, in the next slot.
+    pub fn main_ram_slot(&mut self) -> &mut Self {
+        match self.preset {
+            Some(preset) => self.ram_slot(preset.ram_base(), preset.ram_size),
+            None => self.ram_slot(0x0000, 0x10000),
+        }
+    }
+
+    /// Loads a ROM into the next slot, applying the given IPS/BPS patches.
     pub fn rom_slot_from_file(
         &mut self,
         rom_path: PathBuf,
+        patches: &[PathBuf],
         base: u16,
         size: u32,
+    ) -> anyhow::Result<&mut Self> {
+        self.slots.push(SlotType::Rom(RomSlot::load_patched(
+            rom_path, patches, base, size,
+        )?));
+        Ok(self)
+    }
+
+    /// Puts the bundled C-BIOS in the next slot, for the BIOS.
+    pub fn cbios_slot(&mut self) -> &mut Self {
+        self.slots.push(cbios::slot());
+        self
+    }
+
+    /// Loads a cartridge into the next slot, at the address its header asks
+    /// for, applying the given IPS/BPS patches.
+    pub fn cartridge_from_file(
+        &mut self,
+        rom_path: PathBuf,
+        patches: &[PathBuf],
     ) -> anyhow::Result<&mut Self> {
         self.slots
-            .push(SlotType::Rom(RomSlot::load(rom_path, base, size)?));
+            .push(SlotType::Rom(RomSlot::load_cartridge(rom_path, patches)?));
+        Ok(self)
+    }
+
+    /// Runs a second machine in lockstep, with the same slot layout but with
+    /// the first ROM slot replaced by the given ROM.
+    pub fn compare_rom_from_file(
+        &mut self,
+        rom_path: PathBuf,
+        base: u16,
+        size: u32,
+    ) -> anyhow::Result<&mut Self> {
+        self.compare_rom = Some(RomSlot::load(rom_path, base, size)?);
         Ok(self)
     }
 
@@ -717,12 +3109,316 @@ impl RunnerBuilder {
         self
     }
 
+    /// Adds the entries of a ROM database file to the embedded one.
+    pub fn rom_database(&mut self, path: Option<PathBuf>) -> anyhow::Result<&mut Self> {
+        if let Some(path) = path {
+            self.rom_db.load(path)?;
+        }
+        Ok(self)
+    }
+
+    /// Labels of the program for the debugger, from an assembler symbol file.
+    pub fn symbols(&mut self, path: Option<PathBuf>) -> anyhow::Result<&mut Self> {
+        if let Some(path) = path {
+            self.symbols.load(path)?;
+        }
+        Ok(self)
+    }
+
+    /// Source lines of the program for `list` and the debugger, from
+    /// assembler listings or SLD files.
+    pub fn listings(&mut self, paths: &[PathBuf]) -> anyhow::Result<&mut Self> {
+        for path in paths {
+            self.source_map.load(path)?;
+        }
+        Ok(self)
+    }
+
+    /// Named memory regions for the hexdumps, the watch list and `status`,
+    /// from a region file.
+    pub fn regions(&mut self, path: Option<PathBuf>) -> anyhow::Result<&mut Self> {
+        if let Some(path) = path {
+            self.regions.load(path)?;
+        }
+        Ok(self)
+    }
+
+    /// Writes the instructions and the port accesses to a file per device
+    /// in a directory, see [`DeviceLogs`].
+    pub fn log_dir(&mut self, log_dir: Option<PathBuf>) -> &mut Self {
+        self.log_dir = log_dir;
+        self
+    }
+
+    /// Lets the log command change the levels of the tracing subscriber.
+    pub fn log_filter(&mut self, log_filter: LogFilter) -> &mut Self {
+        self.log_filter = Some(log_filter);
+        self
+    }
+
+    /// Writes the CRC32 of each frame to `output`, comparing them with the
+    /// ones of a `reference` written the same way.
+    pub fn frame_hashes(
+        &mut self,
+        output: Option<PathBuf>,
+        reference: Option<PathBuf>,
+    ) -> anyhow::Result<&mut Self> {
+        self.frame_hashes = output;
+        if let Some(reference) = reference {
+            self.frame_hash_reference = Some(FrameHashes::load(reference)?);
+        }
+        Ok(self)
+    }
+
+    /// Saves the state and the frame hashes to a directory, `autosave` by
+    /// default, every `minutes` of wall time, see [`Autosave`].
+    pub fn autosave(
+        &mut self,
+        minutes: Option<f64>,
+        dir: Option<PathBuf>,
+    ) -> anyhow::Result<&mut Self> {
+        if let Some(minutes) = minutes {
+            // negative, NaN, too large to be a duration or rounding to zero
+            let interval = Duration::try_from_secs_f64(minutes * 60.0)
+                .ok()
+                .filter(|interval| !interval.is_zero())
+                .ok_or_else(|| {
+                    anyhow!("The autosave interval must be a positive number of minutes")
+                })?;
+            let dir = dir.unwrap_or_else(|| PathBuf::from("autosave"));
+            self.autosave = Some((dir, interval));
+        }
+        Ok(self)
+    }
+
+    /// Cassette recording or CAS image in the tape recorder, played at a
+    /// baud rate or its own.
+    pub fn tape(&mut self, path: Option<PathBuf>, baud: Option<u32>) -> anyhow::Result<&mut Self> {
+        if let Some(path) = path {
+            if baud == Some(0) {
+                bail!("Invalid baud rate 0");
+            }
+            self.tape = Some(Tape::load(&path, baud)?);
+        }
+        Ok(self)
+    }
+
+    /// Records what is saved to tape into a CAS image, written when the run
+    /// ends.
+    pub fn record_tape(&mut self, path: Option<PathBuf>) -> &mut Self {
+        self.record_tape = path;
+        self
+    }
+
+    /// Runs the BIOS tape routines at once from the tape in the recorder.
+    pub fn turbo_io(&mut self, turbo_io: bool) -> &mut Self {
+        self.turbo_io = turbo_io;
+        self
+    }
+
+    /// Colors of the screen, a preset name or a palette file.
+    pub fn palette(&mut self, palette: Option<String>) -> anyhow::Result<&mut Self> {
+        if let Some(palette) = palette {
+            self.palette = palette_from(&palette)?;
+        }
+        Ok(self)
+    }
+
+    /// Makes the machine one of the [`Preset::PRESETS`], which must be set
+    /// before the RAM slot.
+    pub fn preset(&mut self, preset: Option<String>) -> anyhow::Result<&mut Self> {
+        if let Some(name) = preset {
+            let Some(preset) = Preset::find(&name) else {
+                let names: Vec<&str> = Preset::PRESETS.iter().map(|preset| preset.name).collect();
+                bail!("Unknown preset {}, use one of {}", name, names.join(", "));
+            };
+            self.preset = Some(preset);
+        }
+        Ok(self)
+    }
+
+    /// Loads a BLOAD file once `at` cycles were executed.
+    pub fn bin_file(&mut self, bin_file: Option<PathBuf>, run: bool, at: u64) -> &mut Self {
+        self.bin_file = bin_file;
+        self.bin_run = run;
+        self.bin_at = at;
+        self
+    }
+
+    /// Loads a BASIC program and runs it once `at` cycles were executed and
+    /// the BIOS keyboard buffer is set up.
+    pub fn bas_file(&mut self, bas_file: Option<PathBuf>, at: u64) -> &mut Self {
+        self.bas_file = bas_file;
+        self.bas_at = at;
+        self
+    }
+
+    /// Types the text once the machine starts, see [`Autotyper`] for the
+    /// special keys and delays.
+    pub fn autotype(&mut self, text: Option<String>) -> anyhow::Result<&mut Self> {
+        if let Some(text) = text {
+            self.autotyper.queue(&text)?;
+        }
+        Ok(self)
+    }
+
+    /// Types the command once MSX-DOS shows its prompt, printing the lines
+    /// it outputs and stopping when the prompt is back, for headless runs
+    /// of DOS tools.
+    pub fn dos_command(&mut self, command: Option<String>) -> &mut Self {
+        self.dos_command = command.as_deref().map(DosCommand::new);
+        self
+    }
+
+    /// Number of executed instructions kept for the `history` command.
+    pub fn history_size(&mut self, history_size: usize) -> &mut Self {
+        self.history_size = history_size;
+        self
+    }
+
+    /// Breaks when a return address pushed by a CALL is overwritten before
+    /// the matching RET.
+    pub fn stack_guard(&mut self, stack_guard: bool) -> &mut Self {
+        self.stack_guard = stack_guard;
+        self
+    }
+
+    /// Breaks when the interrupt vector, H.KEYI or H.TIMI is left malformed
+    /// while the interrupts are enabled.
+    pub fn hook_guard(&mut self, hook_guard: bool) -> &mut Self {
+        self.hook_guard = hook_guard;
+        self
+    }
+
+    /// Reports the calls to the BIOS hooks, the first time and whenever a
+    /// hook was patched since.
+    pub fn trace_hooks(&mut self, trace_hooks: bool) -> &mut Self {
+        self.trace_hooks = trace_hooks;
+        self
+    }
+
+    /// Prints the characters sent to the BIOS CHPUT routine, so the output
+    /// of a program can be read without looking at the screen.
+    pub fn console(&mut self, console: bool) -> &mut Self {
+        self.console = console;
+        self
+    }
+
+    /// Breaks whenever one of the texts is printed through CHPUT, e.g. when
+    /// BASIC says `Ok` or a test prints its result.
+    pub fn break_on_text(&mut self, texts: &[String]) -> &mut Self {
+        self.text_breaks = texts.to_vec();
+        self
+    }
+
+    /// Prints the lines of the text screen as they change, to follow text
+    /// mode software without looking at the screen, e.g. with a screen
+    /// reader.
+    pub fn follow_screen(&mut self, follow_screen: bool) -> &mut Self {
+        self.follow_screen = follow_screen;
+        self
+    }
+
+    /// Reports the VRAM accesses closer than the TMS9918 allows while it
+    /// draws the screen.
+    pub fn vdp_timing(&mut self, vdp_timing: bool) -> &mut Self {
+        self.vdp_timing = vdp_timing;
+        self
+    }
+
+    /// Reports the reads of programs from pages whose selected slot is
+    /// empty, see [`SlotChecker`].
+    pub fn slot_check(&mut self, slot_check: bool) -> &mut Self {
+        self.slot_check = slot_check;
+        self
+    }
+
+    /// Prints every write to the slot registers with the slots of the
+    /// pages before and after it, see [`SlotTracer`].
+    pub fn trace_slots(&mut self, trace_slots: bool) -> &mut Self {
+        self.trace_slots = trace_slots;
+        self
+    }
+
+    /// Traces the slot registers and breaks on the writes switching the
+    /// page the program runs from.
+    pub fn break_on_slot_switch(&mut self, break_on_slot_switch: bool) -> &mut Self {
+        self.break_on_slot_switch = break_on_slot_switch;
+        self
+    }
+
+    /// Counts the T-states of the Z80 datasheet, overriding the M1 wait
+    /// states of the preset.
+    pub fn stock_timing(&mut self, stock_timing: bool) -> &mut Self {
+        self.stock_timing = stock_timing;
+        self
+    }
+
+    /// Enables the debug device on ports 0x2E and 0x2F, printing what the
+    /// program writes to it and breaking when it asks to.
+    pub fn debug_device(&mut self, debug_device: bool) -> &mut Self {
+        self.debug_device = debug_device;
+        self
+    }
+
+    pub fn link(&mut self, link_mode: Option<LinkMode>) -> &mut Self {
+        self.link_mode = link_mode;
+        self
+    }
+
     pub fn build(&self) -> Runner {
+        let compare_slots = self.compare_rom.as_ref().map(|rom| {
+            let mut slots = self.slots.clone();
+            if let Some(slot) = slots.iter_mut().find(|s| matches!(s, SlotType::Rom(_))) {
+                *slot = SlotType::Rom(rom.clone());
+            }
+            slots
+        });
+
+        let new_msx = |slots: &[SlotType]| {
+            let mut msx = Msx::new(slots);
+            if let Some(preset) = self.preset {
+                preset.apply(&mut msx);
+            }
+            if self.stock_timing {
+                msx.cpu.stock_timing = true;
+            }
+            msx
+        };
+        let mut msx = new_msx(&self.slots);
+        if self.debug_device {
+            msx.enable_debug_device();
+        }
+        if let Some(tape) = &self.tape {
+            msx.insert_tape(tape.clone());
+        }
+        if self.record_tape.is_some() {
+            msx.start_tape_recording();
+        }
+        msx.turbo_io = self.turbo_io;
+
         Runner {
             slots: self.slots.clone(),
-            breakpoints: self.breakpoints.clone(),
+            rom_db: self.rom_db.clone(),
+            palette: self.palette,
+            debugger: Debugger {
+                breakpoints: {
+                    let mut breakpoints = Breakpoints::new();
+                    for address in &self.breakpoints {
+                        breakpoints.add(*address, None);
+                    }
+                    breakpoints
+                },
+                ..Debugger::new()
+            },
             max_cycles: self.max_cycles,
             open_msx: self.open_msx,
+            resync: self.resync,
+            json: self.json,
+            // the TUI shows the command output as plain text
+            style: Style::new(self.color && !self.tui),
+            tui: self.tui.then(Tui::default),
+            memory_view: 0,
             break_on_mismatch: self.break_on_mismatch,
             break_on_mem_mismatch: self.break_on_mem_mismatch,
             break_on_ppi_write: self.break_on_ppi_write,
@@ -730,11 +3426,207 @@ impl RunnerBuilder {
             log_on_mismatch: self.log_on_mismatch,
             track_flags: self.track_flags,
             report_every: self.report_every,
+            link_mode: self.link_mode.clone(),
+            bin_file: self.bin_file.clone(),
+            bin_run: self.bin_run,
+            bin_at: self.bin_at,
+            bas_file: self.bas_file.clone(),
+            bas_at: self.bas_at,
             running: false,
             client: None,
-            msx: Msx::new(&self.slots),
+            link: None,
+            remote: None,
+            listen: self.listen.clone(),
+            dap_server: None,
+            dap: self.dap.clone(),
+            symbols: self.symbols.clone(),
+            source_map: self.source_map.clone(),
+            regions: self.regions.clone(),
+            key_buffer_queue: VecDeque::new(),
+            autotyper: self.autotyper.clone(),
+            dos_command: self.dos_command.clone(),
+            slow: None,
+            last_frame: Instant::now(),
+            throttled_frame: 0,
+            hashed_frame: 0,
+            msx,
+            compare_msx: compare_slots.as_ref().map(|slots| new_msx(slots)),
+            compare_slots,
+            in_sync: true,
             cycles: 0,
             instructions: MRUList::new(100),
+            last_stop: None,
+            history: History::new(self.history_size),
+            stack_guard: self.stack_guard.then(StackGuard::new),
+            hook_guard: self.hook_guard.then(HookGuard::new),
+            hook_tracer: self.trace_hooks.then(HookTracer::new),
+            console: (self.console || !self.text_breaks.is_empty()).then(Console::new),
+            echo_console: self.console,
+            text_breaks: self.text_breaks.clone(),
+            screen_reader: self.follow_screen.then(ScreenReader::new),
+            vdp_timing: self.vdp_timing.then(VdpTimingChecker::new),
+            slot_check: self.slot_check.then(SlotChecker::new),
+            slot_trace: (self.trace_slots || self.break_on_slot_switch)
+                .then(|| SlotTracer::new(self.break_on_slot_switch)),
+            debug_device: self.debug_device,
+            frame_hasher: (self.frame_hashes.is_some()
+                || self.frame_hash_reference.is_some()
+                || self.autosave.is_some())
+            .then(|| FrameHasher::new(self.palette, self.frame_hash_reference.clone())),
+            frame_hashes: self.frame_hashes.clone(),
+            frame_hash_output: None,
+            autosave: self
+                .autosave
+                .as_ref()
+                .map(|(dir, interval)| Autosave::new(dir.clone(), *interval)),
+            record_tape: self.record_tape.clone(),
+            log_dir: self.log_dir.clone(),
+            device_logs: None,
+            log_filter: self.log_filter.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use msx::vdp_timing::{DISPLAY_T_STATES, FRAME_T_STATES};
+
+    use super::*;
+
+    // a ROM putting the RAM in page 3 and storing `value` at 0xC000, which
+    // doesn't change any register
+    fn rom(value: u8) -> RomSlot {
+        let program = [
+            0x3E, 0xC0, // LD A,0xC0
+            0xD3, 0xA8, // OUT (0xA8),A
+            0x21, 0x00, 0xC0, // LD HL,0xC000
+            0x36, value, // LD (HL),value
+            0x00,  // NOP
+        ];
+        RomSlot::new(&program, 0x0000, 0x8000)
+    }
+
+    #[test]
+    fn test_ab_compare_memory() {
+        let mut builder = RunnerBuilder::new();
+        builder.slots = vec![
+            SlotType::Rom(rom(1)),
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Ram(RamSlot::new(0x0000, 0x10000)),
+        ];
+        builder.compare_rom = Some(rom(2));
+        let mut runner = builder.build();
+
+        let mut diverged_at = None;
+        for _ in 0..5 {
+            runner.step().unwrap();
+            if runner.compare_ab().unwrap() {
+                diverged_at = Some(runner.msx.pc());
+                break;
+            }
+        }
+        // right after the store
+        assert_eq!(diverged_at, Some(0x0009));
+        assert_eq!(
+            runner
+                .msx
+                .first_difference(runner.compare_msx.as_ref().unwrap()),
+            Some("memory at 0xC000: 0x01, 0x02 on the other".to_string())
+        );
+    }
+
+    #[test]
+    fn test_autosave_interval() {
+        let mut builder = RunnerBuilder::new();
+        for minutes in [0.0, -1.0, f64::NAN, f64::INFINITY, 1e300, 1e-12] {
+            assert_eq!(
+                builder
+                    .autosave(Some(minutes), None)
+                    .err()
+                    .map(|e| e.to_string()),
+                Some("The autosave interval must be a positive number of minutes".to_string()),
+                "{}",
+                minutes
+            );
+        }
+        builder.autosave(Some(0.5), None).unwrap();
+        assert_eq!(
+            builder.autosave,
+            Some((PathBuf::from("autosave"), Duration::from_secs(30)))
+        );
+    }
+
+    #[test]
+    fn test_frame_and_slow() {
+        let mut builder = RunnerBuilder::new();
+        builder.slots = vec![
+            // JR $
+            SlotType::Rom(RomSlot::new(&[0x18, 0xFE], 0x0000, 0x8000)),
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Empty,
+        ];
+        let mut runner = builder.build();
+
+        // up to the VBlank of the first frame, then through two whole ones
+        runner.step_frame().unwrap();
+        assert_eq!(runner.msx.frames(), 1);
+        let clock = runner.msx.clock();
+        assert!((DISPLAY_T_STATES..DISPLAY_T_STATES + 20).contains(&clock));
+        runner.handle_command("frame 2").unwrap();
+        assert_eq!(runner.msx.frames(), 3);
+        let t_states = runner.msx.clock() - clock;
+        assert!((2 * FRAME_T_STATES - 20..2 * FRAME_T_STATES + 20).contains(&t_states));
+
+        // rejected, leaving the speed as it was
+        runner.handle_command("slow 0").unwrap();
+        assert_eq!(runner.slow, None);
+        // each frame takes three times its duration, at least
+        let start = Instant::now();
+        runner.handle_command("slow 3").unwrap();
+        assert_eq!(runner.slow, Some(3.0));
+        runner.step_frame().unwrap();
+        assert!(start.elapsed() >= Duration::from_secs(3) / 60);
+        // a 50 Hz frame lasts longer
+        runner.msx.set_pal(true);
+        let start = Instant::now();
+        runner.handle_command("slow 3").unwrap();
+        runner.step_frame().unwrap();
+        assert!(start.elapsed() >= Duration::from_secs(3) / 50);
+        runner.handle_command("slow off").unwrap();
+        assert_eq!(runner.slow, None);
+    }
+
+    #[test]
+    fn test_frame_hashes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("frames.txt");
+        let mut builder = RunnerBuilder::new();
+        builder.slots = vec![
+            // JR $
+            SlotType::Rom(RomSlot::new(&[0x18, 0xFE], 0x0000, 0x8000)),
+            SlotType::Empty,
+            SlotType::Empty,
+            SlotType::Empty,
+        ];
+        builder.frame_hashes(Some(path.clone()), None).unwrap();
+        let mut runner = builder.build();
+        runner.frame_hash_output = Some(BufWriter::new(File::create(&path).unwrap()));
+
+        // one hash for each frame the VDP drew, however many steps it took
+        for _ in 0..3 {
+            runner.step_frame().unwrap();
+        }
+        for _ in 0..100 {
+            runner.step().unwrap();
         }
+        runner.finish_outputs().unwrap();
+        let hashes = std::fs::read_to_string(&path).unwrap();
+        let frames: Vec<_> = hashes
+            .lines()
+            .map(|line| line.split_once(' ').unwrap().0)
+            .collect();
+        assert_eq!(frames, ["1", "2", "3"]);
     }
 }
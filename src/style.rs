@@ -0,0 +1,215 @@
+//! Colors of the prompt output. They are off with `--no-color`, when
+//! `NO_COLOR` is set or when the output isn't a terminal.
+
+use std::{
+    fmt::Display,
+    io::{self, IsTerminal},
+};
+
+use msx::{regions::Regions, sysvars::SystemVariable, InternalState, ProgramEntry};
+use rustmsx_debugger::watch::Watch;
+
+const RESET: &str = "\x1b[0m";
+const CHANGED: &str = "\x1b[1;33m";
+const ADDRESS: &str = "\x1b[36m";
+const DIM: &str = "\x1b[2m";
+const CURRENT: &str = "\x1b[1;7m";
+const REMOVED: &str = "\x1b[31m";
+const ADDED: &str = "\x1b[32m";
+
+#[derive(Debug, Clone, Copy)]
+pub struct Style {
+    color: bool,
+}
+
+impl Style {
+    pub fn new(color: bool) -> Self {
+        Self {
+            color: color && std::env::var_os("NO_COLOR").is_none() && io::stdout().is_terminal(),
+        }
+    }
+
+    fn paint(&self, code: &str, text: impl Display) -> String {
+        match self.color {
+            true => format!("{}{}{}", code, text, RESET),
+            false => text.to_string(),
+        }
+    }
+
+    /// The registers in aligned columns, with the ones that differ from
+    /// `previous` highlighted.
+    pub fn registers(&self, state: &InternalState, previous: Option<&InternalState>) -> String {
+        let changed = |f: fn(&InternalState) -> u16| previous.is_some_and(|p| f(p) != f(state));
+        let byte = |name: &str, f: fn(&InternalState) -> u16| {
+            let value = format!("{}: #{:02X}", name, f(state));
+            match changed(f) {
+                true => self.paint(CHANGED, value),
+                false => value,
+            }
+        };
+        let word = |name: &str, f: fn(&InternalState) -> u16| {
+            let value = format!("{}: #{:04X}", name, f(state));
+            match changed(f) {
+                true => self.paint(CHANGED, value),
+                false => value,
+            }
+        };
+
+        let flags: String = match previous {
+            Some(previous) => state
+                .flags()
+                .chars()
+                .zip(previous.flags().chars())
+                .map(|(flag, before)| match flag == before {
+                    true => flag.to_string(),
+                    false => self.paint(CHANGED, flag),
+                })
+                .collect(),
+            None => state.flags(),
+        };
+
+        format!(
+            "{} #{:02X}  {} {} {} {} {} {} {}  {}({}) {} {}  F: {}",
+            self.paint(ADDRESS, format!("#{:04X}", state.pc)),
+            state.opcode,
+            byte("A", |s| s.a as u16),
+            byte("B", |s| s.b as u16),
+            byte("C", |s| s.c as u16),
+            byte("D", |s| s.d as u16),
+            byte("E", |s| s.e as u16),
+            byte("H", |s| s.h as u16),
+            byte("L", |s| s.l as u16),
+            word("HL", |s| s.hl),
+            match changed(|s| s.hl_contents as u16) {
+                true => self.paint(CHANGED, format!("#{:02X}", state.hl_contents)),
+                false => format!("#{:02X}", state.hl_contents),
+            },
+            word("SP", |s| s.sp),
+            word("BC", |s| s.bc),
+            flags,
+        )
+    }
+
+    /// A line of `list` or `log`, highlighted when it's at the PC.
+    pub fn program_line(&self, entry: &ProgramEntry, current: bool) -> String {
+        if current {
+            return self.paint(CURRENT, format!("> {}", entry));
+        }
+
+        let line = format!(
+            "  {}  {}  {:<20}",
+            self.paint(ADDRESS, format!("{:04X}", entry.address)),
+            self.paint(DIM, format!("{:<12}", entry.data)),
+            entry.instruction,
+        );
+        match &entry.dump {
+            Some(dump) => format!("{} {}", line, self.paint(DIM, dump)),
+            None => line.trim_end().to_string(),
+        }
+    }
+
+    /// A line of a source file, with the address of its code, highlighted
+    /// when it's at the PC.
+    pub fn source_line(
+        &self,
+        number: usize,
+        text: &str,
+        address: Option<u16>,
+        current: bool,
+    ) -> String {
+        let address = match address {
+            Some(address) => format!("{:04X}", address),
+            None => " ".repeat(4),
+        };
+        if current {
+            return self.paint(CURRENT, format!("> {}  {:>5}  {}", address, number, text));
+        }
+
+        let line = format!(
+            "  {}  {}  {}",
+            self.paint(ADDRESS, address),
+            self.paint(DIM, format!("{:>5}", number)),
+            text,
+        );
+        line.trim_end().to_string()
+    }
+
+    /// A hexdump with its address column highlighted.
+    pub fn hexdump(&self, dump: &str) -> String {
+        dump.lines()
+            .map(|line| match line.split_once(':') {
+                Some((address, rest)) => format!("{}:{}\n", self.paint(ADDRESS, address), rest),
+                None => format!("{}\n", line),
+            })
+            .collect()
+    }
+
+    /// A hexdump of the memory, naming the regions of the lines where they
+    /// change.
+    pub fn memory_hexdump(&self, dump: &str, regions: &Regions) -> String {
+        let mut output = String::new();
+        let mut previous = Vec::new();
+        for line in dump.lines() {
+            let labels = line
+                .split_once(':')
+                .and_then(|(address, _)| u16::from_str_radix(address.trim(), 16).ok())
+                .map(|address| regions.labels(address, address.saturating_add(15)))
+                .unwrap_or_default();
+
+            let line = self.hexdump(line);
+            match !labels.is_empty() && labels != previous {
+                true => output.push_str(&format!(
+                    "{}  {}\n",
+                    line.trim_end_matches('\n'),
+                    self.paint(DIM, labels.join(", "))
+                )),
+                false => output.push_str(&line),
+            }
+            previous = labels;
+        }
+        output
+    }
+
+    /// A watch expression with its values, the ones that changed since it
+    /// was last shown highlighted, and the region it's in.
+    pub fn watch(&self, watch: &Watch, region: Option<&str>) -> String {
+        let values: Vec<String> = watch
+            .formatted()
+            .into_iter()
+            .zip(&watch.changed)
+            .map(|(value, changed)| match changed {
+                true => self.paint(CHANGED, value),
+                false => value,
+            })
+            .collect();
+        let region = region.map_or(String::new(), |name| format!("  {}", self.paint(DIM, name)));
+        format!(
+            "{}: {}{}",
+            self.paint(ADDRESS, watch.expression),
+            values.join(" "),
+            region
+        )
+    }
+
+    /// A system variable with its current value, in aligned columns.
+    pub fn system_variable(&self, var: &SystemVariable, value: &str) -> String {
+        format!(
+            "{:<7} {}  {:<24} {}",
+            var.name,
+            self.paint(ADDRESS, format!("#{:04X}", var.address)),
+            value,
+            self.paint(DIM, var.description),
+        )
+    }
+
+    /// A diff from `Runner::diff`, with the removed and added lines colored.
+    pub fn diff(&self, diff: &str) -> String {
+        diff.lines()
+            .map(|line| match line.chars().next() {
+                Some('-') => format!("{}\n", self.paint(REMOVED, line)),
+                Some('+') => format!("{}\n", self.paint(ADDED, line)),
+                _ => format!("{}\n", line),
+            })
+            .collect()
+    }
+}
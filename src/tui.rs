@@ -0,0 +1,415 @@
+//! Full screen debugger shown with `--tui` instead of the line prompt. It
+//! takes the same commands, their output goes to the console pane.
+
+use std::io::{self, Stderr};
+
+use crossterm::{
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use msx::{flag_string, ProgramEntry};
+use ratatui::{
+    backend::CrosstermBackend,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Span, Spans},
+    widgets::{Block, Borders, Paragraph},
+    Frame, Terminal,
+};
+use rustmsx_debugger::watch::Watch;
+
+// lines kept in the console pane
+const CONSOLE_LINES: usize = 1000;
+
+type Backend = CrosstermBackend<Stderr>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Register {
+    pub name: &'static str,
+    pub value: u16,
+    /// shown with 4 digits
+    pub wide: bool,
+}
+
+/// What the panes show, collected by the runner at each stop.
+#[derive(Debug, Clone)]
+pub struct DebugView {
+    pub cycles: u64,
+    pub pc: u16,
+    /// laid out in two columns
+    pub registers: Vec<Register>,
+    pub f: u8,
+    pub program: Vec<ProgramEntry>,
+    pub breakpoints: Vec<u16>,
+    /// addresses from SP up with the words stored there
+    pub stack: Vec<(u16, u16)>,
+    pub memory_address: u16,
+    pub memory: Vec<u8>,
+    /// watched expressions, with the values changed since the last view
+    pub watches: Vec<Watch>,
+}
+
+#[derive(Default)]
+pub struct Tui {
+    terminal: Option<Terminal<Backend>>,
+    console: Vec<String>,
+    input: String,
+    history: Vec<String>,
+    // position while browsing the history with the arrows
+    history_pos: Option<usize>,
+    // lines the console is scrolled up from its end
+    scroll: usize,
+    // registers at the previous stop, to highlight the changed ones
+    previous: Vec<Register>,
+    previous_f: u8,
+}
+
+impl Tui {
+    fn enter(&mut self) -> anyhow::Result<()> {
+        if self.terminal.is_none() {
+            enable_raw_mode()?;
+            let mut stderr = io::stderr();
+            execute!(stderr, EnterAlternateScreen)?;
+            self.terminal = Some(Terminal::new(CrosstermBackend::new(stderr))?);
+        }
+        Ok(())
+    }
+
+    /// Goes back to the normal screen, e.g. while the emulation runs.
+    pub fn leave(&mut self) -> anyhow::Result<()> {
+        if let Some(mut terminal) = self.terminal.take() {
+            disable_raw_mode()?;
+            execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+            terminal.show_cursor()?;
+        }
+        Ok(())
+    }
+
+    /// Adds the output of a command to the console.
+    pub fn print(&mut self, command: &str, output: &str) {
+        self.console.push(format!("> {}", command));
+        self.console.extend(output.lines().map(String::from));
+        if self.console.len() > CONSOLE_LINES {
+            self.console.drain(..self.console.len() - CONSOLE_LINES);
+        }
+        self.scroll = 0;
+    }
+
+    /// Shows the panes until a command is entered. An empty line repeats the
+    /// last command, F5 continues, F10 steps and Ctrl-C quits.
+    pub fn read_command(&mut self, view: &DebugView) -> anyhow::Result<String> {
+        let previous = std::mem::replace(&mut self.previous, view.registers.clone());
+        let previous_f = std::mem::replace(&mut self.previous_f, view.f);
+
+        self.enter()?;
+        loop {
+            let panes = Panes {
+                view,
+                previous: &previous,
+                previous_f,
+                console: &self.console,
+                scroll: self.scroll,
+                input: &self.input,
+            };
+            if let Some(terminal) = &mut self.terminal {
+                terminal.draw(|f| panes.draw(f))?;
+            }
+
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if let Some(command) = self.key(key) {
+                return Ok(command);
+            }
+        }
+    }
+
+    fn key(&mut self, key: KeyEvent) -> Option<String> {
+        if key.kind == KeyEventKind::Release {
+            return None;
+        }
+
+        match key.code {
+            KeyCode::Char('c') | KeyCode::Char('d')
+                if key.modifiers.contains(KeyModifiers::CONTROL) =>
+            {
+                return Some("quit".to_string());
+            }
+            KeyCode::F(5) => return Some("cont".to_string()),
+            KeyCode::F(10) => return Some("step".to_string()),
+            KeyCode::Char(c) => self.input.push(c),
+            KeyCode::Backspace => {
+                self.input.pop();
+            }
+            KeyCode::Esc => self.input.clear(),
+            KeyCode::Up if !self.history.is_empty() => {
+                let pos = match self.history_pos {
+                    Some(pos) => pos.saturating_sub(1),
+                    None => self.history.len() - 1,
+                };
+                self.history_pos = Some(pos);
+                self.input = self.history[pos].clone();
+            }
+            KeyCode::Down => {
+                if let Some(pos) = self.history_pos {
+                    self.history_pos = (pos + 1 < self.history.len()).then_some(pos + 1);
+                    self.input = match self.history_pos {
+                        Some(pos) => self.history[pos].clone(),
+                        None => String::new(),
+                    };
+                }
+            }
+            KeyCode::PageUp => {
+                self.scroll = (self.scroll + 10).min(self.console.len().saturating_sub(1))
+            }
+            KeyCode::PageDown => self.scroll = self.scroll.saturating_sub(10),
+            KeyCode::Enter => {
+                self.history_pos = None;
+                let command = std::mem::take(&mut self.input).trim().to_string();
+                if command.is_empty() {
+                    return self.history.last().cloned();
+                }
+                if self.history.last() != Some(&command) {
+                    self.history.push(command.clone());
+                }
+                return Some(command);
+            }
+            _ => {}
+        }
+        None
+    }
+}
+
+impl Drop for Tui {
+    fn drop(&mut self) {
+        let _ = self.leave();
+    }
+}
+
+struct Panes<'a> {
+    view: &'a DebugView,
+    previous: &'a [Register],
+    previous_f: u8,
+    console: &'a [String],
+    scroll: usize,
+    input: &'a str,
+}
+
+fn pane(title: &str) -> Block<'_> {
+    Block::default().borders(Borders::ALL).title(title)
+}
+
+fn address(address: u16) -> Span<'static> {
+    Span::styled(format!("{:04X}", address), Style::default().fg(Color::Cyan))
+}
+
+fn changed_style(changed: bool) -> Style {
+    match changed {
+        true => Style::default()
+            .fg(Color::Yellow)
+            .add_modifier(Modifier::BOLD),
+        false => Style::default(),
+    }
+}
+
+impl Panes<'_> {
+    fn draw(&self, f: &mut Frame<Backend>) {
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Percentage(50),
+                Constraint::Length(10),
+                Constraint::Min(5),
+            ])
+            .split(f.size());
+        let top = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Min(40), Constraint::Length(28)])
+            .split(rows[0]);
+        let side = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(10), Constraint::Min(3)])
+            .split(top[1]);
+        let middle = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Min(40), Constraint::Length(28)])
+            .split(rows[1]);
+
+        self.disassembly(f, top[0]);
+        self.registers(f, side[0]);
+        self.stack(f, side[1]);
+        self.memory(f, middle[0]);
+        self.watches(f, middle[1]);
+        self.console(f, rows[2]);
+    }
+
+    fn disassembly(&self, f: &mut Frame<Backend>, area: Rect) {
+        // keeps the PC in the middle of the pane
+        let height = area.height.saturating_sub(2) as usize;
+        let current = self
+            .view
+            .program
+            .iter()
+            .position(|entry| entry.address == self.view.pc)
+            .unwrap_or(0);
+        let first = current.saturating_sub(height / 2);
+
+        let lines: Vec<Spans> = self.view.program[first..]
+            .iter()
+            .take(height)
+            .map(|entry| {
+                let marker = match self.view.breakpoints.contains(&entry.address) {
+                    true => Span::styled("*", Style::default().fg(Color::Red)),
+                    false => Span::raw(" "),
+                };
+                let line = format!("  {:<12}  {}", entry.data, entry.instruction);
+                if entry.address == self.view.pc {
+                    let style = Style::default().add_modifier(Modifier::REVERSED | Modifier::BOLD);
+                    return Spans::from(vec![
+                        marker,
+                        Span::styled(format!(">{:04X}{}", entry.address, line), style),
+                    ]);
+                }
+                Spans::from(vec![
+                    marker,
+                    Span::raw(" "),
+                    address(entry.address),
+                    Span::raw(line),
+                ])
+            })
+            .collect();
+
+        f.render_widget(Paragraph::new(lines).block(pane("Disassembly")), area);
+    }
+
+    fn registers(&self, f: &mut Frame<Backend>, area: Rect) {
+        let mut lines: Vec<Spans> = self
+            .view
+            .registers
+            .chunks(2)
+            .map(|pair| {
+                let spans = pair.iter().flat_map(|register| {
+                    let changed = self
+                        .previous
+                        .iter()
+                        .any(|p| p.name == register.name && p.value != register.value);
+                    let value = match register.wide {
+                        true => format!("#{:04X}", register.value),
+                        false => format!("#{:02X}  ", register.value),
+                    };
+                    [
+                        Span::raw(format!("{:<3}", register.name)),
+                        Span::styled(value, changed_style(changed)),
+                        Span::raw("  "),
+                    ]
+                });
+                Spans::from(spans.collect::<Vec<_>>())
+            })
+            .collect();
+
+        let flags = flag_string(self.view.f);
+        let previous_flags = flag_string(self.previous_f);
+        let mut spans = vec![Span::raw("F  ")];
+        spans.extend(
+            flags
+                .chars()
+                .zip(previous_flags.chars())
+                .map(|(flag, before)| {
+                    let changed = !self.previous.is_empty() && flag != before;
+                    Span::styled(flag.to_string(), changed_style(changed))
+                }),
+        );
+        lines.push(Spans::from(spans));
+        lines.push(Spans::from(format!("Cycles {}", self.view.cycles)));
+
+        f.render_widget(Paragraph::new(lines).block(pane("Registers")), area);
+    }
+
+    fn stack(&self, f: &mut Frame<Backend>, area: Rect) {
+        let lines: Vec<Spans> = self
+            .view
+            .stack
+            .iter()
+            .map(|(sp, value)| {
+                Spans::from(vec![address(*sp), Span::raw(format!("  #{:04X}", value))])
+            })
+            .collect();
+        f.render_widget(Paragraph::new(lines).block(pane("Stack")), area);
+    }
+
+    fn memory(&self, f: &mut Frame<Backend>, area: Rect) {
+        let lines: Vec<Spans> = self
+            .view
+            .memory
+            .chunks(16)
+            .enumerate()
+            .map(|(n, row)| {
+                let start = self.view.memory_address.wrapping_add(n as u16 * 16);
+                let bytes: Vec<String> = row.iter().map(|b| format!("{:02X}", b)).collect();
+                let chars: String = row
+                    .iter()
+                    .map(|b| match b.is_ascii_graphic() || *b == b' ' {
+                        true => *b as char,
+                        false => '.',
+                    })
+                    .collect();
+                Spans::from(vec![
+                    address(start),
+                    Span::raw(format!(
+                        "  {}  {}  {}",
+                        bytes[..8].join(" "),
+                        bytes[8..].join(" "),
+                        chars
+                    )),
+                ])
+            })
+            .collect();
+
+        let title = format!("Memory #{:04X}", self.view.memory_address);
+        f.render_widget(Paragraph::new(lines).block(pane(&title)), area);
+    }
+
+    fn watches(&self, f: &mut Frame<Backend>, area: Rect) {
+        let lines: Vec<Spans> = self
+            .view
+            .watches
+            .iter()
+            .map(|watch| {
+                let mut spans = vec![Span::styled(
+                    watch.expression.to_string(),
+                    Style::default().fg(Color::Cyan),
+                )];
+                for (value, changed) in watch.formatted().into_iter().zip(&watch.changed) {
+                    spans.push(Span::raw(" "));
+                    spans.push(Span::styled(value, changed_style(*changed)));
+                }
+                Spans::from(spans)
+            })
+            .collect();
+        f.render_widget(Paragraph::new(lines).block(pane("Watch")), area);
+    }
+
+    fn console(&self, f: &mut Frame<Backend>, area: Rect) {
+        let height = area.height.saturating_sub(3) as usize;
+        let end = self.console.len().saturating_sub(self.scroll);
+        let start = end.saturating_sub(height);
+
+        let mut lines: Vec<Spans> = self.console[start..end]
+            .iter()
+            .map(|line| Spans::from(line.as_str()))
+            .collect();
+        lines.resize(height, Spans::default());
+        lines.push(Spans::from(format!(
+            "#{:04X}> {}",
+            self.view.pc, self.input
+        )));
+
+        let title = "Console (F5 cont, F10 step, PgUp/PgDn scroll, Ctrl-C quit)";
+        f.render_widget(Paragraph::new(lines).block(pane(title)), area);
+        f.set_cursor(
+            area.x + 1 + 7 + self.input.chars().count() as u16,
+            area.y + 1 + height as u16,
+        );
+    }
+}
@@ -0,0 +1,138 @@
+//! `rustmsx verify`, the accuracy suite of [`msx::verify`] in one command,
+//! ending with a scorecard. ZEXDOC runs for the longest, build with
+//! `--release` or skip it with `--skip zexdoc`.
+
+use std::{
+    fs,
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use anyhow::bail;
+use clap::{Args, ValueEnum};
+use msx::{
+    cbios,
+    verify::{self, RomPack, Score, BIOS_BOOT_FRAMES},
+};
+
+/// Failures listed in the scorecard for each suite, the rest counted.
+const MAX_FAILURES: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Suite {
+    Zexdoc,
+    SingleStep,
+    RomPack,
+    BiosBoot,
+}
+
+impl Suite {
+    const ALL: [Suite; 4] = [
+        Suite::Zexdoc,
+        Suite::SingleStep,
+        Suite::RomPack,
+        Suite::BiosBoot,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Suite::Zexdoc => "ZEXDOC",
+            Suite::SingleStep => "single-step",
+            Suite::RomPack => "ROM pack",
+            Suite::BiosBoot => "BIOS boot",
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct VerifyArgs {
+    /// Directory of the JSON single-step tests of SingleStepTests/z80, a file per opcode;
+    /// the suite is skipped without it
+    #[clap(long, value_name = "DIR", env = "RUSTMSX_SINGLE_STEP_TESTS")]
+    single_step: Option<PathBuf>,
+
+    /// Directory of the ROMs of the ROM pack, the cases without their ROM are skipped
+    #[clap(long, value_name = "DIR", env = "RUSTMSX_TEST_ROMS")]
+    roms: Option<PathBuf>,
+
+    /// BIOS to boot instead of the bundled C-BIOS, which should reach the "Ok" of BASIC
+    #[clap(long, value_name = "FILE")]
+    bios: Option<PathBuf>,
+
+    /// Leaves a suite out, e.g. zexdoc, which takes the longest (can be repeated)
+    #[clap(long, value_enum, value_name = "SUITE")]
+    skip: Vec<Suite>,
+}
+
+/// Runs the suites in order, printing their progress and then the
+/// scorecard, and fails when any of them did.
+pub fn run(args: &VerifyArgs) -> anyhow::Result<()> {
+    let mut scores = Vec::new();
+    for suite in Suite::ALL {
+        let score = if args.skip.contains(&suite) {
+            Score::skipped("--skip")
+        } else {
+            println!("== {}", suite.name());
+            run_suite(suite, args)?
+        };
+        scores.push((suite, score));
+    }
+
+    println!();
+    println!("Scorecard");
+    for (suite, score) in &scores {
+        print_score(suite.name(), score);
+    }
+
+    let failed = scores.iter().filter(|(_, score)| !score.is_ok()).count();
+    if failed > 0 {
+        bail!("{} of {} suites failed", failed, scores.len());
+    }
+    Ok(())
+}
+
+fn run_suite(suite: Suite, args: &VerifyArgs) -> anyhow::Result<Score> {
+    let score = match suite {
+        Suite::Zexdoc => verify::zexdoc(|text| {
+            print!("{}", text);
+            let _ = io::stdout().flush();
+        }),
+        Suite::SingleStep => match &args.single_step {
+            Some(dir) => verify::single_step(dir)?,
+            None => Score::skipped("no --single-step directory"),
+        },
+        Suite::RomPack => RomPack::bundled()?.run(args.roms.as_deref()),
+        Suite::BiosBoot => {
+            let (bios, text) = match &args.bios {
+                Some(path) => (fs::read(path)?, "Ok"),
+                None => (cbios::ROM.to_vec(), "C-BIOS"),
+            };
+            verify::bios_boot(&bios, text, BIOS_BOOT_FRAMES).into()
+        }
+    };
+    Ok(score)
+}
+
+// "<suite> PASS 67/67", with what failed and what was skipped below
+fn print_score(name: &str, score: &Score) {
+    let status = match (score.is_ok(), score.total) {
+        (false, _) => "FAIL",
+        (true, 0) => "SKIP",
+        (true, _) => "PASS",
+    };
+    println!(
+        "  {:<12} {} {:>7}",
+        name,
+        status,
+        format!("{}/{}", score.passed, score.total)
+    );
+    for failure in score.failures.iter().take(MAX_FAILURES) {
+        println!("      {}", failure);
+    }
+    if score.failures.len() > MAX_FAILURES {
+        println!("      and {} more", score.failures.len() - MAX_FAILURES);
+    }
+    if let Some(skipped) = &score.skipped {
+        println!("      skipped: {}", skipped);
+    }
+}